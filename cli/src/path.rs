@@ -37,6 +37,10 @@ pub fn get_path(context: &mut Context) -> AtomicResult<()> {
                 let atoms: Vec<Atom> = vec![*atom];
                 serialize::atoms_to_ntriples(atoms, store)?
             }
+            Format::Turtle => {
+                let atoms: Vec<Atom> = vec![*atom];
+                serialize::atoms_to_turtle(atoms, store)?
+            }
         },
     };
     println!("{}", out);
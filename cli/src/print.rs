@@ -21,8 +21,8 @@ pub fn get_serialization(argmatches: &ArgMatches) -> AtomicResult<Format> {
             "jsonld" => Format::JsonLd,
             "jsonad" => Format::JsonAd,
             "nt" => Format::NTriples,
-            "turtle" => Format::NTriples,
-            "n3" => Format::NTriples,
+            "turtle" => Format::Turtle,
+            "n3" => Format::Turtle,
             format => {
                 return Err(
                     format!("As {} not supported. Try {:?}", format, SERIALIZE_OPTIONS).into(),
@@ -65,6 +65,7 @@ pub fn print_resource(
         Format::JsonLd => resource.to_json_ld(&context.store)?,
         Format::JsonAd => resource.to_json_ad()?,
         Format::NTriples => serialize::atoms_to_ntriples(resource.to_atoms(), &context.store)?,
+        Format::Turtle => resource.to_turtle(&context.store)?,
         Format::Pretty => pretty_print_resource(resource, &context.store)?,
     };
     println!("{}", out);
@@ -86,5 +86,37 @@ fn criterion_benchmark(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, criterion_benchmark);
+/// Compares loading a Store from JSON-AD with loading it from a binary snapshot.
+fn cold_start_benchmark(c: &mut Criterion) {
+    let store = Store::init().unwrap();
+    store.populate().unwrap();
+    for _ in 0..10_000 {
+        store
+            .add_resource(&random_resource(&random_atom()))
+            .unwrap();
+    }
+    let dir = std::path::Path::new(".temp/bench_cold_start");
+    std::fs::create_dir_all(dir).unwrap();
+    let json_ad_path = dir.join("store.json");
+    let snapshot_path = dir.join("store.snapshot");
+    let json = store.export(true).unwrap();
+    std::fs::write(&json_ad_path, &json).unwrap();
+    store.write_snapshot(&snapshot_path).unwrap();
+
+    c.bench_function("cold start from JSON-AD", |b| {
+        b.iter(|| {
+            let json = std::fs::read_to_string(&json_ad_path).unwrap();
+            let store = Store::init().unwrap();
+            store.import(&json, &parse::ParseOpts::default()).unwrap();
+        })
+    });
+
+    c.bench_function("cold start from snapshot", |b| {
+        b.iter(|| {
+            Store::read_snapshot(&snapshot_path).unwrap();
+        })
+    });
+}
+
+criterion_group!(benches, criterion_benchmark, cold_start_benchmark);
 criterion_main!(benches);
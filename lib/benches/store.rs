@@ -0,0 +1,180 @@
+//! Benchmarks of the Store on a synthetic corpus, made with [atomic_lib::fixtures] at several sizes.
+//! The corpus and the lookups use fixed seeds, so runs can be compared.
+//! Run `cargo bench --all-features --bench store -- --save-baseline before`, make your changes, run it again with `--save-baseline after`,
+//! and paste the output of `cargo run --all-features --example bench_summary -- before after` in your PR.
+
+use atomic_lib::{
+    agents::{Agent, ForAgent},
+    commit::{CommitBuilder, CommitOpts},
+    fixtures::{generate_instances, import_example_ontology},
+    storelike::Query,
+    *,
+};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+
+/// Amounts of Tasks in the corpus. There is one Project for every ten Tasks.
+const SIZES: &[usize] = &[1_000, 10_000];
+const SEED: u64 = 465;
+/// Atoms per `add_atoms` call
+const ATOMS_PER_BATCH: usize = 100;
+
+struct Corpus {
+    db: Db,
+    /// The same Resources, in the in-memory Store, which has no indexes
+    store: Store,
+    task_class: String,
+    tasks: Vec<String>,
+}
+
+fn corpus(size: usize) -> Corpus {
+    let db = Db::init_temp(&format!("bench_store_{}", size)).unwrap();
+    let classes = import_example_ontology(&db).unwrap();
+    let store = Store::init().unwrap();
+    store.populate().unwrap();
+    let mut tasks = Vec::with_capacity(size);
+    for (class, count) in [(&classes.project, size / 10), (&classes.task, size)] {
+        for mut resource in generate_instances(&db, class, count, SEED).unwrap() {
+            resource.set_propval_unsafe(
+                urls::PARENT.into(),
+                Value::AtomicUrl(db.get_server_url().into()),
+            );
+            db.add_resource_opts(&resource, false, true, true).unwrap();
+            store
+                .add_resource_opts(&resource, false, true, true)
+                .unwrap();
+            if class == &classes.task {
+                tasks.push(resource.get_subject().clone());
+            }
+        }
+    }
+    Corpus {
+        db,
+        store,
+        task_class: classes.task,
+        tasks,
+    }
+}
+
+fn atoms(batch: usize) -> Vec<Atom> {
+    (0..ATOMS_PER_BATCH)
+        .map(|i| {
+            Atom::new(
+                format!("https://localhost/bench-atoms/{}/{}", batch, i),
+                urls::DESCRIPTION.into(),
+                Value::Markdown(format!("Atom {} of batch {}", i, batch)),
+            )
+        })
+        .collect()
+}
+
+fn store_benchmarks(c: &mut Criterion) {
+    for &size in SIZES {
+        let corpus = corpus(size);
+        let db = &corpus.db;
+        let mut rng = StdRng::seed_from_u64(SEED);
+
+        let mut group = c.benchmark_group("add_atoms");
+        group.throughput(Throughput::Elements(ATOMS_PER_BATCH as u64));
+        let mut batch = 0;
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter_batched(
+                || {
+                    batch += 1;
+                    atoms(batch)
+                },
+                |atoms| db.add_atoms(atoms).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+        group.finish();
+
+        // Serving a Resource means fetching and serializing it
+        let mut group = c.benchmark_group("get_resource_string");
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| {
+                let subject = corpus.tasks.choose(&mut rng).unwrap();
+                db.get_resource_extended(subject, false, &ForAgent::Sudo)
+                    .unwrap()
+                    .to_json_ad()
+                    .unwrap()
+            })
+        });
+        group.finish();
+
+        let query = Query::new_class(&corpus.task_class);
+        let mut group = c.benchmark_group("tpf");
+        group.sample_size(10);
+        group.bench_function(BenchmarkId::new("indexed", size), |b| {
+            b.iter(|| db.query(&query).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("not indexed", size), |b| {
+            b.iter(|| corpus.store.query(&query).unwrap())
+        });
+        group.finish();
+
+        // `Store::all_resources` clones the whole HashMap before iterating, `Db` streams from disk.
+        let mut group = c.benchmark_group("all_resources");
+        group.sample_size(10);
+        group.bench_function(BenchmarkId::new("Db", size), |b| {
+            b.iter(|| db.all_resources(false).count())
+        });
+        group.bench_function(BenchmarkId::new("Store", size), |b| {
+            b.iter(|| corpus.store.all_resources(false).count())
+        });
+        group.finish();
+
+        let mut group = c.benchmark_group("serialize_store");
+        group.sample_size(10);
+        group.bench_function(BenchmarkId::new("JSON-AD", size), |b| {
+            b.iter(|| db.export(false).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("N-Quads", size), |b| {
+            b.iter(|| db.export_nquads(false).unwrap())
+        });
+        group.finish();
+
+        let mut group = c.benchmark_group("validate_store");
+        group.sample_size(10);
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| validate::validate_store(db, false))
+        });
+        group.finish();
+
+        let agent = db.get_default_agent().unwrap();
+        let mut group = c.benchmark_group("apply_commit");
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter_batched(
+                || signed_commit(db, &agent, corpus.tasks.choose(&mut rng).unwrap()),
+                |commit| commit.apply_opts(db, &commit_opts()).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+        group.finish();
+    }
+}
+
+/// A Commit that renames the Task, signed outside of the measurement.
+fn signed_commit(db: &Db, agent: &Agent, subject: &str) -> Commit {
+    let resource = db.get_resource(subject).unwrap();
+    let mut builder = CommitBuilder::new(subject.into());
+    builder.set(urls::NAME.into(), Value::String(utils::random_string(10)));
+    builder.sign(agent, db, &resource).unwrap()
+}
+
+/// Everything is checked, including the signature.
+fn commit_opts() -> CommitOpts {
+    CommitOpts {
+        validate_schema: true,
+        validate_signature: true,
+        validate_timestamp: true,
+        validate_rights: true,
+        validate_previous_commit: false,
+        validate_for_agent: None,
+        update_index: true,
+        validate_relative_urls: false,
+    }
+}
+
+criterion_group!(benches, store_benchmarks);
+criterion_main!(benches);
@@ -0,0 +1,193 @@
+//! Prints the results of the criterion benchmarks as a markdown table, to paste in a PR.
+//!
+//! ```sh
+//! cargo bench --all-features --bench store -- --save-baseline before
+//! # make your changes
+//! cargo bench --all-features --bench store -- --save-baseline after
+//! cargo run --all-features --example bench_summary -- before after
+//! ```
+//!
+//! Without arguments, the latest run (`new`) is shown.
+//! Benchmarks in the same group and size (like `tpf/indexed/1000` and `tpf/not indexed/1000`) are compared with each other, too.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+struct Estimate {
+    group: String,
+    function: Option<String>,
+    parameter: Option<String>,
+    /// Mean duration of an iteration in nanoseconds
+    mean: f64,
+}
+
+fn main() {
+    let baselines: Vec<String> = std::env::args().skip(1).collect();
+    let baselines = if baselines.is_empty() {
+        vec!["new".to_string()]
+    } else {
+        baselines
+    };
+    let dir = criterion_dir();
+    let results: Vec<BTreeMap<String, Estimate>> = baselines
+        .iter()
+        .map(|baseline| {
+            let mut found = BTreeMap::new();
+            collect(&dir, baseline, &mut found);
+            found
+        })
+        .collect();
+    if results.iter().all(|found| found.is_empty()) {
+        eprintln!(
+            "No benchmark results for {:?} in {:?}. Run `cargo bench --all-features` first.",
+            baselines, dir
+        );
+        std::process::exit(1);
+    }
+
+    let mut ids: Vec<&String> = results.iter().flat_map(|found| found.keys()).collect();
+    ids.sort();
+    ids.dedup();
+
+    println!("| Benchmark | {} |", header(&baselines));
+    println!("|---|{}", "---|".repeat(columns(&baselines)));
+    for id in &ids {
+        let means: Vec<Option<f64>> = results
+            .iter()
+            .map(|found| found.get(*id).map(|e| e.mean))
+            .collect();
+        let mut row: Vec<String> = means
+            .iter()
+            .map(|mean| mean.map(format_duration).unwrap_or_else(|| "-".into()))
+            .collect();
+        if baselines.len() == 2 {
+            row.push(match (means[0], means[1]) {
+                (Some(before), Some(after)) => format!("{:+.1}%", (after / before - 1.0) * 100.0),
+                _ => "-".into(),
+            });
+        }
+        println!("| `{}` | {} |", id, row.join(" | "));
+    }
+
+    // Compare the functions within a group, e.g. indexed and not indexed lookups
+    let latest = results.last().unwrap();
+    let mut groups: BTreeMap<(String, String), Vec<&Estimate>> = BTreeMap::new();
+    for estimate in latest.values() {
+        if let (Some(_), Some(parameter)) = (&estimate.function, &estimate.parameter) {
+            groups
+                .entry((estimate.group.clone(), parameter.clone()))
+                .or_default()
+                .push(estimate);
+        }
+    }
+    let comparisons: Vec<String> = groups
+        .into_iter()
+        .filter(|(_, estimates)| estimates.len() > 1)
+        .map(|((group, parameter), mut estimates)| {
+            estimates.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+            let fastest = estimates[0];
+            let others: Vec<String> = estimates[1..]
+                .iter()
+                .map(|e| {
+                    format!(
+                        "`{}` is {:.1}x slower",
+                        e.function.as_deref().unwrap_or_default(),
+                        e.mean / fastest.mean
+                    )
+                })
+                .collect();
+            format!(
+                "- `{}` ({}): `{}` is fastest, {}",
+                group,
+                parameter,
+                fastest.function.as_deref().unwrap_or_default(),
+                others.join(", ")
+            )
+        })
+        .collect();
+    if !comparisons.is_empty() {
+        println!("\n{}", comparisons.join("\n"));
+    }
+}
+
+fn header(baselines: &[String]) -> String {
+    let mut columns = baselines.to_vec();
+    if baselines.len() == 2 {
+        columns.push("change".into());
+    }
+    columns.join(" | ")
+}
+
+fn columns(baselines: &[String]) -> usize {
+    baselines.len() + usize::from(baselines.len() == 2)
+}
+
+/// Criterion writes to the `criterion` folder in the target directory of the workspace.
+fn criterion_dir() -> PathBuf {
+    if let Ok(target) = std::env::var("CARGO_TARGET_DIR") {
+        return Path::new(&target).join("criterion");
+    }
+    let workspace_target = Path::new(env!("CARGO_MANIFEST_DIR")).join("../target/criterion");
+    if workspace_target.exists() {
+        return workspace_target;
+    }
+    PathBuf::from("target/criterion")
+}
+
+/// Finds the `estimates.json` of the baseline in every benchmark folder.
+fn collect(dir: &Path, baseline: &str, found: &mut BTreeMap<String, Estimate>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        if path.file_name().and_then(|name| name.to_str()) == Some(baseline) {
+            if let Some(estimate) = read_estimate(&path) {
+                found.insert(full_id(&path), estimate);
+            }
+        } else if path.file_name().and_then(|name| name.to_str()) != Some("report") {
+            collect(&path, baseline, found);
+        }
+    }
+}
+
+fn read_json(path: &Path) -> Option<Value> {
+    serde_json::from_str(&std::fs::read_to_string(path).ok()?).ok()
+}
+
+fn read_estimate(baseline_dir: &Path) -> Option<Estimate> {
+    let estimates = read_json(&baseline_dir.join("estimates.json"))?;
+    let benchmark = read_json(&baseline_dir.join("benchmark.json"))?;
+    let text = |key: &str| benchmark[key].as_str().map(String::from);
+    Some(Estimate {
+        group: text("group_id")?,
+        function: text("function_id"),
+        parameter: text("value_str"),
+        mean: estimates["mean"]["point_estimate"].as_f64()?,
+    })
+}
+
+fn full_id(baseline_dir: &Path) -> String {
+    read_json(&baseline_dir.join("benchmark.json"))
+        .and_then(|benchmark| benchmark["full_id"].as_str().map(String::from))
+        .unwrap_or_else(|| baseline_dir.parent().unwrap().display().to_string())
+}
+
+fn format_duration(nanos: f64) -> String {
+    if nanos >= 1e9 {
+        format!("{:.2} s", nanos / 1e9)
+    } else if nanos >= 1e6 {
+        format!("{:.2} ms", nanos / 1e6)
+    } else if nanos >= 1e3 {
+        format!("{:.2} µs", nanos / 1e3)
+    } else {
+        format!("{:.0} ns", nanos)
+    }
+}
@@ -0,0 +1,78 @@
+//! Creates, updates and deletes a Resource with Commits that are checked against the schema while they are built.
+//! Runs against the in-memory Store, but any Storelike works, such as a store that caches Resources from a server.
+//!
+//! ```sh
+//! cargo run -p atomic_lib --example commit_builder
+//! ```
+
+use atomic_lib::{
+    commit::{CommitBuilder, CommitOpts},
+    urls, Storelike, Value,
+};
+
+fn main() {
+    let store = atomic_lib::Store::init().unwrap();
+    store.populate().unwrap();
+    let agent = store.create_agent(Some("author")).unwrap();
+    let opts = CommitOpts {
+        validate_schema: true,
+        validate_signature: true,
+        validate_timestamp: true,
+        validate_rights: false,
+        validate_previous_commit: true,
+        validate_for_agent: None,
+        update_index: true,
+        validate_relative_urls: false,
+    };
+    let subject = format!("{}/classes/Article", store.get_server_url());
+
+    // Create: a Class requires a shortname and a description
+    let mut create = CommitBuilder::checked(subject.clone(), &store).unwrap();
+    create
+        .set(urls::IS_A, Value::ResourceArray(vec![urls::CLASS.into()]))
+        .unwrap()
+        .set_by_shortname("shortname", "article")
+        .unwrap();
+    // Values are checked right away, so mistakes show up where they are made
+    let wrong_datatype = create.set(urls::SHORTNAME, Value::Integer(1)).unwrap_err();
+    println!("Rejected: {}", wrong_datatype);
+    create
+        .set_by_shortname("description", "A written text")
+        .unwrap();
+    create
+        .sign(&agent)
+        .unwrap()
+        .apply_opts(&store, &opts)
+        .unwrap();
+
+    // Update: the builder reads the current version, and uses its `lastCommit` as the `previousCommit`
+    let mut update = CommitBuilder::checked(subject.clone(), &store).unwrap();
+    update
+        .set_by_shortname("description", "A text with a title and a body")
+        .unwrap()
+        .push(urls::RECOMMENDS, urls::NAME.into())
+        .unwrap();
+    update
+        .sign(&agent)
+        .unwrap()
+        .apply_opts(&store, &opts)
+        .unwrap();
+    let class = store.get_class(&subject).unwrap();
+    assert_eq!(class.recommends, vec![urls::NAME.to_string()]);
+
+    // Required Properties and contradicting calls are reported when signing
+    let mut invalid = CommitBuilder::checked(subject.clone(), &store).unwrap();
+    invalid.remove(urls::DESCRIPTION).unwrap();
+    println!("Rejected: {}", invalid.sign(&agent).unwrap_err());
+
+    // Delete
+    let mut delete = CommitBuilder::checked(subject.clone(), &store).unwrap();
+    delete.destroy().unwrap();
+    delete
+        .sign(&agent)
+        .unwrap()
+        .apply_opts(&store, &opts)
+        .unwrap();
+    assert!(store.get_resource(&subject).is_err());
+    println!("Created, updated and deleted {}", subject);
+}
@@ -0,0 +1,54 @@
+//! Uses only the parts of atomic_lib that are always available: the in-memory Store, Values, Commits and JSON-AD.
+//! Checks that the library builds without any features, also for the browser:
+//!
+//! ```sh
+//! cargo run -p atomic_lib --no-default-features --example minimal
+//! cargo build -p atomic_lib --no-default-features --example minimal --target wasm32-unknown-unknown
+//! ```
+
+use atomic_lib::{parse::ParseOpts, urls, Storelike, Value};
+
+fn main() {
+    let store = atomic_lib::Store::init().unwrap();
+    store.populate().unwrap();
+    let agent = store.create_agent(Some("minimal")).unwrap();
+    store.set_default_agent(agent);
+
+    // Changes are signed Commits, also without a database
+    let mut resource = atomic_lib::Resource::new_generate_subject(&store);
+    resource
+        .set_propval(
+            urls::DESCRIPTION.into(),
+            Value::Markdown("Built without features".into()),
+            &store,
+        )
+        .unwrap();
+    resource.save_locally(&store).unwrap();
+
+    // JSON-AD round trip
+    let json_ad = store
+        .get_resource(resource.get_subject())
+        .unwrap()
+        .to_json_ad()
+        .unwrap();
+    let parsed =
+        atomic_lib::parse::parse_json_ad_resource(&json_ad, &store, &ParseOpts::default()).unwrap();
+    assert_eq!(
+        parsed.get(urls::DESCRIPTION).unwrap().to_string(),
+        "Built without features"
+    );
+
+    // Formats behind a feature return an error that names it
+    let json_ld = r#"{ "@context": "https://schema.org", "name": "Ada" }"#;
+    assert!(atomic_lib::parse::is_json_ld(json_ld));
+    let result = atomic_lib::parse::parse_json_ld_string(
+        json_ld,
+        &store,
+        &ParseOpts::default(),
+        &Default::default(),
+    );
+    if cfg!(not(feature = "json-ld")) {
+        assert!(result.unwrap_err().message.contains("`json-ld`"));
+    }
+    println!("{}", json_ad);
+}
@@ -177,6 +177,23 @@ pub fn verify_public_key(public_key: &str) -> AtomicResult<()> {
     Ok(())
 }
 
+/// Errors if the Agent is suspended, see [urls::SUSPENDED].
+/// Suspended Agents can't sign requests or Commits, until an admin lifts the suspension.
+pub fn check_not_suspended(agent: &Resource) -> AtomicResult<()> {
+    if let Ok(Value::Boolean(true)) = agent.get(urls::SUSPENDED) {
+        let reason = agent
+            .get(urls::SUSPENSION_REASON)
+            .map(|reason| format!(" {}", reason))
+            .unwrap_or_default();
+        return Err(crate::AtomicError::unauthorized(format!(
+            "Agent {} is suspended.{}",
+            agent.get_subject(),
+            reason
+        )));
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test {
     #[cfg(test)]
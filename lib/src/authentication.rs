@@ -1,7 +1,7 @@
 //! Check signatures in authentication headers, find the correct agent. Authorization is done in Hierarchies
 
 use crate::{
-    agents::{decode_base64, ForAgent},
+    agents::{check_not_suspended, decode_base64, ForAgent},
     commit::check_timestamp,
     errors::AtomicResult,
     urls, Storelike,
@@ -9,7 +9,7 @@ use crate::{
 
 /// Set of values extracted from the request.
 /// Most are coming from headers.
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, serde::Serialize)]
 pub struct AuthValues {
     // x-atomic-public-key
     #[serde(rename = "https://atomicdata.dev/properties/auth/publicKey")]
@@ -62,7 +62,8 @@ pub fn get_agent_from_auth_values_and_check(
         // check if the timestamp is valid
         check_timestamp(auth_vals.timestamp)?;
         // check if the public key belongs to the agent
-        let found_public_key = store.get_value(&auth_vals.agent_subject, urls::PUBLIC_KEY)?;
+        let agent = store.get_resource(&auth_vals.agent_subject)?;
+        let found_public_key = agent.get(urls::PUBLIC_KEY)?;
         if found_public_key.to_string() != auth_vals.public_key {
             Err(
                 "The public key in the auth headers does not match the public key in the agent"
@@ -70,6 +71,7 @@ pub fn get_agent_from_auth_values_and_check(
                     .into(),
             )
         } else {
+            check_not_suspended(&agent)?;
             Ok(ForAgent::AgentSubject(auth_vals.agent_subject))
         }
     } else {
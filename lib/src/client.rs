@@ -1,8 +1,18 @@
-//! Functions for interacting with an Atomic Server
+//! Functions for interacting with an Atomic Server.
+//! Enable the `client` feature for [AtomicClient], a typed client that also handles Commits, Queries, uploads and subscriptions.
+//! Without it, the functions that send HTTP requests return an error that names the feature.
+
+#[cfg(feature = "client")]
+mod remote;
+
+#[cfg(feature = "client")]
+pub use remote::{AtomicClient, Subscription};
+
 use crate::{
     agents::Agent,
     commit::sign_message,
     errors::AtomicResult,
+    outbound::OutboundFeature,
     parse::{parse_json_ad_resource, ParseOpts},
     Resource, Storelike,
 };
@@ -17,7 +27,13 @@ pub fn fetch_resource(
     store: &impl Storelike,
     for_agent: Option<Agent>,
 ) -> AtomicResult<Resource> {
-    let body = fetch_body(subject, crate::parse::JSON_AD_MIME, for_agent)?;
+    let body = fetch_body_for(
+        store,
+        OutboundFeature::Resolve,
+        subject,
+        crate::parse::JSON_AD_MIME,
+        for_agent,
+    )?;
     let resource = parse_json_ad_resource(&body, store, &ParseOpts::default())
         .map_err(|e| format!("Error parsing body of {}. {}", subject, e))?;
     Ok(resource)
@@ -54,6 +70,19 @@ pub fn fetch_body(url: &str, content_type: &str, for_agent: Option<Agent>) -> At
         get_authentication_headers(url, &agent)?;
     }
 
+    let (status, body) = http_get(url, content_type)?;
+    if status != 200 {
+        return Err(format!(
+            "Could not fetch url '{}'. Status: {}. Body: {}",
+            url, status, body
+        )
+        .into());
+    };
+    Ok(body)
+}
+
+#[cfg(feature = "client")]
+fn http_get(url: &str, content_type: &str) -> AtomicResult<(u16, String)> {
     let agent = ureq::builder()
         .timeout(std::time::Duration::from_secs(2))
         .build();
@@ -66,13 +95,62 @@ pub fn fetch_body(url: &str, content_type: &str, for_agent: Option<Agent>) -> At
     let body = resp
         .into_string()
         .map_err(|e| format!("Could not parse HTTP response for {}: {}", url, e))?;
+    Ok((status, body))
+}
+
+#[cfg(not(feature = "client"))]
+fn http_get(url: &str, _content_type: &str) -> AtomicResult<(u16, String)> {
+    Err(crate::AtomicError::missing_feature(
+        "client",
+        &format!("Fetching {}", url),
+    ))
+}
+
+/// Fetches a URL for a feature of a server, through the [crate::outbound::OutboundHttp] of the store, which refuses internal and denied destinations.
+/// Stores without one, such as the in-memory store of a client, fetch the URL directly using [fetch_body].
+pub fn fetch_body_for(
+    store: &impl Storelike,
+    feature: OutboundFeature,
+    url: &str,
+    content_type: &str,
+    for_agent: Option<Agent>,
+) -> AtomicResult<String> {
+    #[cfg(not(feature = "client"))]
+    let _ = (store, feature);
+    #[cfg(feature = "client")]
+    if let Some(outbound) = store.get_outbound() {
+        return fetch_body_outbound(&outbound, feature, url, content_type, for_agent);
+    }
+    fetch_body(url, content_type, for_agent)
+}
+
+#[cfg(feature = "client")]
+fn fetch_body_outbound(
+    outbound: &crate::outbound::OutboundHttp,
+    feature: OutboundFeature,
+    url: &str,
+    content_type: &str,
+    for_agent: Option<Agent>,
+) -> AtomicResult<String> {
+    let headers = match &for_agent {
+        Some(agent) => get_authentication_headers(url, agent)?,
+        None => Vec::new(),
+    };
+    let (status, body) = outbound.get_string(feature, url, content_type, &headers)?;
+    // Lets callers tell a Resource that does not exist yet apart from a failing server
+    if status == 404 {
+        return Err(crate::AtomicError::not_found(format!(
+            "Could not fetch url '{}'. Status: 404. Body: {}",
+            url, body
+        )));
+    }
     if status != 200 {
         return Err(format!(
             "Could not fetch url '{}'. Status: {}. Body: {}",
             url, status, body
         )
         .into());
-    };
+    }
     Ok(body)
 }
 
@@ -92,7 +170,11 @@ pub fn post_commit_custom_endpoint(
     store: &impl Storelike,
 ) -> AtomicResult<()> {
     let json = commit.into_resource(store)?.to_json_ad()?;
+    http_post(endpoint, &json)
+}
 
+#[cfg(feature = "client")]
+fn http_post(endpoint: &str, json: &str) -> AtomicResult<()> {
     let agent = ureq::builder()
         .timeout(std::time::Duration::from_secs(2))
         .build();
@@ -100,7 +182,7 @@ pub fn post_commit_custom_endpoint(
     let resp = agent
         .post(endpoint)
         .set("Content-Type", "application/json")
-        .send_string(&json)
+        .send_string(json)
         .map_err(|e| format!("Error when posting commit to {} : {}", endpoint, e))?;
 
     if resp.status() != 200 {
@@ -116,6 +198,14 @@ pub fn post_commit_custom_endpoint(
     }
 }
 
+#[cfg(not(feature = "client"))]
+fn http_post(endpoint: &str, _json: &str) -> AtomicResult<()> {
+    Err(crate::AtomicError::missing_feature(
+        "client",
+        &format!("Posting a Commit to {}", endpoint),
+    ))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -129,6 +219,14 @@ mod test {
         assert!(shortname.to_string() == "shortname");
     }
 
+    #[test]
+    #[cfg(not(feature = "client"))]
+    fn requests_without_client_name_the_feature() {
+        let err =
+            fetch_body("https://atomicdata.dev", crate::parse::JSON_AD_MIME, None).unwrap_err();
+        assert!(err.message.contains("`client`"), "{}", err);
+    }
+
     #[test]
     #[ignore]
     fn post_commit_basic() {
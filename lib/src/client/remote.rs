@@ -0,0 +1,375 @@
+//! A typed client for a remote Atomic Server, enabled by the `client` feature.
+//! Uses the same [Resource], [crate::Value] and [Commit] types as the stores, so Resources can be moved between a local store and a server without conversion.
+
+use std::{net::TcpStream, path::Path, time::Duration};
+
+use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
+
+use crate::{
+    agents::Agent,
+    authentication::AuthValues,
+    commit::{sign_message, CommitBuilder, CommitResponse},
+    errors::{AtomicError, AtomicErrorType, AtomicResult},
+    parse::{parse_json_ad_resource, parse_json_ad_string, ParseOpts, SaveOpts, JSON_AD_MIME},
+    storelike::{Query, QueryResult},
+    urls,
+    values::SubResource,
+    Commit, Resource, Store, Storelike, Value,
+};
+
+use super::get_authentication_headers;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_RETRIES: u32 = 3;
+/// Doubled after every retry
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+const DEFAULT_PAGE_SIZE: usize = 30;
+
+/// Fetches Resources, runs Queries, sends Commits and uploads Files to a remote Atomic Server.
+/// Every request is signed by the Agent.
+/// Requests that fail because of a connection error or a `429`, `502`, `503` or `504` response are retried.
+/// Error responses are converted to an [AtomicError] with the matching [AtomicErrorType].
+pub struct AtomicClient {
+    base_url: String,
+    agent: Agent,
+    /// Holds the Properties needed for parsing responses
+    store: Store,
+    http: ureq::Agent,
+    retries: u32,
+}
+
+impl AtomicClient {
+    /// `base_url` is the URL of the server, e.g. `https://atomicdata.dev`.
+    pub fn new(base_url: &str, agent: Agent) -> AtomicResult<Self> {
+        let store = Store::init()?;
+        store.populate()?;
+        store.set_default_agent(agent.clone());
+        Ok(AtomicClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            agent,
+            store,
+            http: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+            retries: DEFAULT_RETRIES,
+        })
+    }
+
+    /// How often a request is retried after a transient failure. Defaults to 3.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// The in-memory store that is used for parsing responses.
+    /// Properties of the server are fetched into this store when they are first needed.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// Fetches a Resource as JSON-AD.
+    pub fn get_resource(&self, subject: &str) -> AtomicResult<Resource> {
+        let body = self.request("GET", subject, None, &[])?;
+        self.parse_resource(&body)
+    }
+
+    /// Runs the Query using the `/query` endpoint of the server.
+    /// The `offset` is rounded down to a multiple of the `limit`, as the server returns pages.
+    pub fn query(&self, query: &Query) -> AtomicResult<QueryResult> {
+        let mut url = url::Url::parse(&format!("{}{}", self.base_url, urls::PATH_QUERY))?;
+        let page_size = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).max(1);
+        {
+            let mut params = url.query_pairs_mut();
+            if let Some(property) = &query.property {
+                params.append_pair("property", property);
+            }
+            if let Some(value) = &query.value {
+                params.append_pair("value", &value.to_string());
+            }
+            if let Some(sort_by) = &query.sort_by {
+                params.append_pair("sort_by", sort_by);
+                params.append_pair("sort_desc", &query.sort_desc.to_string());
+            }
+            params.append_pair("page_size", &page_size.to_string());
+            params.append_pair("current_page", &(query.offset / page_size).to_string());
+            params.append_pair("include_nested", &query.include_nested.to_string());
+            params.append_pair("include_external", &query.include_external.to_string());
+        }
+        let collection = self.get_resource(url.as_str())?;
+        let count = match collection.get(urls::COLLECTION_MEMBER_COUNT) {
+            Ok(Value::Integer(count)) => *count as usize,
+            _ => 0,
+        };
+        let mut result = QueryResult {
+            subjects: Vec::new(),
+            resources: Vec::new(),
+            count,
+        };
+        if let Ok(Value::ResourceArray(members)) = collection.get(urls::COLLECTION_MEMBERS) {
+            for member in members {
+                match member {
+                    SubResource::Resource(resource) => {
+                        result.subjects.push(resource.get_subject().clone());
+                        result.resources.push(*resource.clone());
+                    }
+                    SubResource::Subject(subject) => result.subjects.push(subject.clone()),
+                    SubResource::Nested(_) => {}
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Signs the Commit and sends it to the `/commit` endpoint.
+    /// The current Resource is fetched first, so the Commit refers to its last Commit.
+    /// `resource_new` is fetched after the Commit has been applied, so it contains the values as they are stored on the server.
+    pub fn commit(&self, builder: CommitBuilder) -> AtomicResult<CommitResponse> {
+        let subject = builder.get_subject().to_string();
+        let resource_old = match self.get_resource(&subject) {
+            Ok(resource) => Some(resource),
+            Err(e) if matches!(e.error_type, AtomicErrorType::NotFoundError) => None,
+            Err(e) => return Err(e),
+        };
+        let current = resource_old
+            .clone()
+            .unwrap_or_else(|| Resource::new(subject.clone()));
+        let commit = builder.sign(&self.agent, &self.store, &current)?;
+        let mut commit_resource = commit.into_resource(&self.store)?;
+        if let Some(client_id) = &commit.client_id {
+            commit_resource
+                .set_propval_unsafe(urls::CLIENT_ID.into(), Value::String(client_id.clone()));
+        }
+        let json = commit_resource.to_json_ad()?;
+        let endpoint = format!("{}/commit", self.base_url);
+        let body = self.request("POST", &endpoint, Some("application/json"), json.as_bytes())?;
+        let commit_resource = self.parse_resource(&body)?;
+        let resource_new = if commit.destroy.unwrap_or(false) {
+            None
+        } else {
+            Some(self.get_resource(&subject)?)
+        };
+        Ok(CommitResponse {
+            commit_resource,
+            resource_new,
+            resource_old,
+            commit_struct: commit,
+        })
+    }
+
+    /// Uploads a file to the `/upload` endpoint, and returns the created File Resources.
+    /// The File is attached to the `parent`, which requires write rights.
+    pub fn upload_file(&self, parent: &str, path: &Path) -> AtomicResult<Vec<Resource>> {
+        let filename = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| format!("No file name in {:?}", path))?;
+        let contents = std::fs::read(path)?;
+        let boundary = format!("atomic{}", crate::utils::random_string(16));
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+            boundary,
+            filename.replace('"', "")
+        )
+        .into_bytes();
+        body.extend_from_slice(&contents);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        let url = format!(
+            "{}/upload?parent={}",
+            self.base_url,
+            urlencoding::encode(parent)
+        );
+        let content_type = format!("multipart/form-data; boundary={}", boundary);
+        let response = self.request("POST", &url, Some(&content_type), &body)?;
+        parse_json_ad_string(&response, &self.store, &self.parse_opts())
+    }
+
+    /// Opens a WebSocket to the server, and subscribes to Commits for the `subject`.
+    pub fn subscribe(&self, subject: &str) -> AtomicResult<Subscription> {
+        let endpoint = format!("{}/ws", self.base_url);
+        let ws_url = endpoint
+            .replacen("https://", "wss://", 1)
+            .replacen("http://", "ws://", 1);
+        let (socket, _response) = tungstenite::connect(ws_url.as_str())
+            .map_err(|e| format!("Could not open WebSocket to {}: {}", ws_url, e))?;
+        let mut subscription = Subscription {
+            socket,
+            store: self.store.clone(),
+        };
+        let timestamp = crate::utils::now();
+        let private_key = self
+            .agent
+            .private_key
+            .as_ref()
+            .ok_or("No private key in agent")?;
+        let auth = AuthValues {
+            public_key: self.agent.public_key.clone(),
+            timestamp,
+            signature: sign_message(
+                &format!("{} {}", endpoint, timestamp),
+                private_key,
+                &self.agent.public_key,
+            )?,
+            requested_subject: endpoint,
+            agent_subject: self.agent.subject.clone(),
+        };
+        subscription.send(format!("AUTHENTICATE {}", serde_json::to_string(&auth)?))?;
+        subscription.subscribe(subject)?;
+        Ok(subscription)
+    }
+
+    fn parse_opts(&self) -> ParseOpts {
+        ParseOpts {
+            save: SaveOpts::DontSave,
+            ..Default::default()
+        }
+    }
+
+    fn parse_resource(&self, body: &str) -> AtomicResult<Resource> {
+        parse_json_ad_resource(body, &self.store, &self.parse_opts())
+    }
+
+    /// Sends a signed request, retries transient failures and converts error responses.
+    fn request(
+        &self,
+        method: &str,
+        url: &str,
+        content_type: Option<&str>,
+        body: &[u8],
+    ) -> AtomicResult<String> {
+        let mut attempt = 0;
+        loop {
+            let mut request = self.http.request(method, url).set("Accept", JSON_AD_MIME);
+            for (header, value) in get_authentication_headers(url, &self.agent)? {
+                request = request.set(&header, &value);
+            }
+            if let Some(content_type) = content_type {
+                request = request.set("Content-Type", content_type);
+            }
+            let result = if method == "GET" {
+                request.call()
+            } else {
+                request.send_bytes(body)
+            };
+            let (error, transient) = match result {
+                Ok(response) => return Ok(response.into_string()?),
+                Err(ureq::Error::Status(status, response)) => {
+                    let body = response.into_string().unwrap_or_default();
+                    (
+                        error_from_response(url, status, &body),
+                        matches!(status, 429 | 502 | 503 | 504),
+                    )
+                }
+                Err(ureq::Error::Transport(e)) => (
+                    AtomicError::other_error(format!("Request to {} failed: {}", url, e)),
+                    true,
+                ),
+            };
+            if !transient || attempt >= self.retries {
+                return Err(error);
+            }
+            std::thread::sleep(RETRY_DELAY * 2u32.pow(attempt));
+            attempt += 1;
+        }
+    }
+}
+
+/// Commits for the subscribed subjects, received over a WebSocket.
+/// Iterating blocks until the next Commit arrives, and ends when the server closes the connection.
+pub struct Subscription {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    store: Store,
+}
+
+impl Subscription {
+    /// Also receive Commits for this `subject`.
+    pub fn subscribe(&mut self, subject: &str) -> AtomicResult<()> {
+        self.send(format!("SUBSCRIBE {}", subject))
+    }
+
+    pub fn unsubscribe(&mut self, subject: &str) -> AtomicResult<()> {
+        self.send(format!("UNSUBSCRIBE {}", subject))
+    }
+
+    fn send(&mut self, message: String) -> AtomicResult<()> {
+        self.socket
+            .send(Message::Text(message))
+            .map_err(|e| format!("Could not send WebSocket message: {}", e).into())
+    }
+
+    fn parse_commit(&self, json: &str) -> AtomicResult<Commit> {
+        let opts = ParseOpts {
+            save: SaveOpts::DontSave,
+            ..Default::default()
+        };
+        Commit::from_resource(parse_json_ad_resource(json, &self.store, &opts)?)
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = AtomicResult<Commit>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let text = match self.socket.read() {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(_)) => return None,
+                Ok(_) => continue,
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                    return None
+                }
+                Err(e) => return Some(Err(format!("WebSocket error: {}", e).into())),
+            };
+            if let Some(json) = text.strip_prefix("COMMIT ") {
+                return Some(self.parse_commit(json));
+            }
+            if let Some(error) = text.strip_prefix("ERROR ") {
+                return Some(Err(AtomicError::other_error(error.to_string())));
+            }
+        }
+    }
+}
+
+/// Converts an error response of the server, which is a JSON-AD Error Resource.
+fn error_from_response(url: &str, status: u16, body: &str) -> AtomicError {
+    let description = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|json| json.get(urls::DESCRIPTION)?.as_str().map(String::from))
+        .unwrap_or_else(|| body.to_string());
+    let error_type = match status {
+        401 | 403 => AtomicErrorType::UnauthorizedError,
+        404 => AtomicErrorType::NotFoundError,
+        405 => AtomicErrorType::MethodNotAllowed,
+        410 => AtomicErrorType::Gone,
+        423 => AtomicErrorType::Locked,
+        _ => AtomicErrorType::OtherError,
+    };
+    AtomicError {
+        message: format!("{} responded with {}. {}", url, status, description),
+        error_type,
+        subject: None,
+        property: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn error_responses() {
+        let body = serde_json::json!({
+            urls::IS_A: [urls::ERROR],
+            urls::DESCRIPTION: "Unauthorized. No read right",
+        })
+        .to_string();
+        let error = error_from_response("https://example.com/a", 401, &body);
+        assert!(matches!(
+            error.error_type,
+            AtomicErrorType::UnauthorizedError
+        ));
+        assert!(error.message.ends_with("Unauthorized. No read right"));
+
+        let error = error_from_response("https://example.com/a", 404, "not json");
+        assert!(matches!(error.error_type, AtomicErrorType::NotFoundError));
+        assert!(error.message.ends_with("not json"));
+    }
+}
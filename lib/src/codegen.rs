@@ -0,0 +1,541 @@
+//! Generates Rust structs for [Class]es, so apps can use `file.filesize` instead of `resource.get(urls::FILESIZE)`.
+//! Every Class becomes a struct with a field for each required (`T`) and recommended (`Option<T>`) Property,
+//! a `from_resource` that checks the datatypes and the required Properties, and an `apply_to` that writes the fields to a [Resource].
+//! The generated code uses the helpers in this module, and a `properties` module with the URLs of the Properties.
+//!
+//! The schema is read from a JSON-AD export, so builds don't need a server.
+//! The output only depends on the schema: regenerating it for the same schema gives identical code.
+//! For example, in a `build.rs`:
+//!
+//! ```ignore
+//! let schema = std::fs::read_to_string("schema.json").unwrap();
+//! let code = atomic_lib::codegen::generate_from_json_ad(&schema, "atomic_lib").unwrap();
+//! let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("schema.rs");
+//! std::fs::write(out, code).unwrap();
+//! ```
+//!
+//! The structs for the default Classes are in [crate::typed], behind the `typed` feature.
+
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{
+    datatype::DataType,
+    errors::AtomicResult,
+    parse::ParseOpts,
+    schema::{Class, Property},
+    urls,
+    values::UnsupportedValue,
+    Resource, Store, Storelike, Value,
+};
+
+/// Generates a struct for every Class in the JSON-AD, see [generate].
+/// Properties and Classes that the JSON-AD refers to should be in the JSON-AD too, or in the base models (like `description` and `parent`).
+pub fn generate_from_json_ad(json_ad: &str, lib_path: &str) -> AtomicResult<String> {
+    let store = Store::init()?;
+    store.import(json_ad, &ParseOpts::default())?;
+    let parsed: serde_json::Value = serde_json::from_str(json_ad)?;
+    let classes: Vec<String> = parsed
+        .as_array()
+        .ok_or("The JSON-AD should be an array of Resources")?
+        .iter()
+        .filter(|resource| {
+            resource[urls::IS_A]
+                .as_array()
+                .map(|classes| classes.iter().any(|class| class == urls::CLASS))
+                .unwrap_or(false)
+        })
+        .filter_map(|resource| resource["@id"].as_str().map(String::from))
+        .collect();
+    generate(&store, &classes, lib_path)
+}
+
+/// Generates Rust source code with a struct for each of the `classes`.
+/// `lib_path` is how the generated code refers to this library: `atomic_lib`, or `crate` inside of it.
+pub fn generate(
+    store: &impl Storelike,
+    classes: &[String],
+    lib_path: &str,
+) -> AtomicResult<String> {
+    let mut classes: Vec<Class> = classes
+        .iter()
+        .map(|subject| store.get_class(subject))
+        .collect::<AtomicResult<_>>()?;
+    classes.sort_by(|a, b| a.subject.cmp(&b.subject));
+    let mut properties: BTreeMap<String, Property> = BTreeMap::new();
+    for class in &classes {
+        for subject in class.requires.iter().chain(&class.recommends) {
+            if !properties.contains_key(subject) {
+                let property = store.get_property(subject).map_err(|e| {
+                    format!("Class {} uses an unknown Property. {}", class.subject, e)
+                })?;
+                properties.insert(subject.clone(), property);
+            }
+        }
+    }
+    Ok(render(&classes, &properties, lib_path))
+}
+
+fn render(classes: &[Class], properties: &BTreeMap<String, Property>, lib_path: &str) -> String {
+    let mut taken = HashSet::new();
+    let constants: BTreeMap<&String, String> = properties
+        .values()
+        .map(|property| {
+            let name = unique(&mut taken, identifier(&property.shortname).to_uppercase());
+            (&property.subject, name)
+        })
+        .collect();
+
+    let mut out = String::new();
+    out.push_str(&format!(
+        "//! Structs for Classes, generated by [{}::codegen]. Do not edit this file, regenerate it instead.\n",
+        lib_path
+    ));
+    if !classes.is_empty() {
+        out.push_str(&format!(
+            "\nuse {}::{{codegen, errors::AtomicResult, Resource}};\n",
+            lib_path
+        ));
+        if !properties.is_empty() {
+            out.push_str(&format!("use {}::datatype::DataType;\n", lib_path));
+        }
+    }
+
+    out.push_str(
+        "\n/// The URLs of the Properties of the generated Classes.\npub mod properties {\n",
+    );
+    for property in properties.values() {
+        doc(&mut out, "    ", &property.description);
+        out.push_str(&format!(
+            "    pub const {}: &str = {:?};\n",
+            constants[&property.subject], property.subject
+        ));
+    }
+    out.push_str("}\n");
+
+    let mut taken = HashSet::new();
+    for class in classes {
+        let name = unique(&mut taken, type_name(&class.shortname));
+        let mut fields_taken: HashSet<String> = ["subject".to_string()].into();
+        let mut fields: Vec<(String, &Property, bool)> = Vec::new();
+        for (subject, required) in class
+            .requires
+            .iter()
+            .map(|s| (s, true))
+            .chain(class.recommends.iter().map(|s| (s, false)))
+        {
+            if fields.iter().any(|(_, p, _)| &p.subject == subject) {
+                continue;
+            }
+            let property = &properties[subject];
+            let field = unique(&mut fields_taken, field_name(&property.shortname));
+            fields.push((field, property, required));
+        }
+
+        out.push('\n');
+        doc(&mut out, "", &class.description);
+        if first_line(&class.description).is_some() {
+            out.push_str("///\n");
+        }
+        out.push_str(&format!("/// <{}>\n", class.subject));
+        out.push_str("#[derive(Debug, Clone, PartialEq)]\n");
+        out.push_str(&format!(
+            "pub struct {} {{\n    pub subject: String,\n",
+            name
+        ));
+        for (field, property, required) in &fields {
+            doc(&mut out, "    ", &property.description);
+            let rust_type = rust_type(&property.data_type);
+            if *required {
+                out.push_str(&format!("    pub {}: {},\n", field, rust_type));
+            } else {
+                out.push_str(&format!("    pub {}: Option<{}>,\n", field, rust_type));
+            }
+        }
+        out.push_str("}\n\n");
+
+        out.push_str(&format!("impl {} {{\n", name));
+        out.push_str(&format!(
+            "    pub const CLASS: &'static str = {:?};\n\n",
+            class.subject
+        ));
+        out.push_str("    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.\n");
+        out.push_str("    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {\n");
+        out.push_str(&format!(
+            "        Ok({} {{\n            subject: resource.get_subject().clone(),\n",
+            name
+        ));
+        for (field, property, required) in &fields {
+            out.push_str(&format!(
+                "            {}: codegen::{}(resource, properties::{}, {})?,\n",
+                field,
+                if *required { "required" } else { "optional" },
+                constants[&property.subject],
+                datatype_expr(&property.data_type)
+            ));
+        }
+        out.push_str("        })\n    }\n\n");
+        out.push_str("    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.\n");
+        out.push_str("    pub fn apply_to(&self, resource: &mut Resource) {\n");
+        out.push_str("        codegen::add_class(resource, Self::CLASS);\n");
+        for (field, property, required) in &fields {
+            out.push_str(&format!(
+                "        codegen::{}(resource, properties::{}, &self.{}, {});\n",
+                if *required { "set" } else { "set_optional" },
+                constants[&property.subject],
+                field,
+                datatype_expr(&property.data_type)
+            ));
+        }
+        out.push_str("    }\n}\n");
+    }
+    out
+}
+
+/// Writes the first line of a description as a doc comment.
+fn doc(out: &mut String, indent: &str, description: &str) {
+    if let Some(line) = first_line(description) {
+        out.push_str(&format!("{}/// {}\n", indent, line));
+    }
+}
+
+fn first_line(description: &str) -> Option<&str> {
+    description
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+}
+
+/// Replaces everything but ASCII letters and digits with underscores.
+fn identifier(shortname: &str) -> String {
+    let name: String = shortname
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => format!("_{}", name),
+    }
+}
+
+/// `activity-summary` becomes `ActivitySummary`.
+fn type_name(shortname: &str) -> String {
+    let name: String = identifier(shortname)
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+fn field_name(shortname: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+        "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+        "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub",
+        "ref", "return", "self", "static", "struct", "super", "trait", "true", "try", "type",
+        "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+    ];
+    let name = identifier(shortname).to_lowercase();
+    if KEYWORDS.contains(&name.as_str()) {
+        format!("{}_", name)
+    } else {
+        name
+    }
+}
+
+/// Appends underscores until the name is not taken yet.
+fn unique(taken: &mut HashSet<String>, mut name: String) -> String {
+    while taken.contains(&name) {
+        name.push('_');
+    }
+    taken.insert(name.clone());
+    name
+}
+
+fn rust_type(datatype: &DataType) -> &'static str {
+    match datatype {
+        DataType::Integer | DataType::Timestamp => "i64",
+        DataType::Float => "f64",
+        DataType::Boolean => "bool",
+        DataType::ResourceArray => "Vec<String>",
+        DataType::AtomicUrl
+        | DataType::Date
+        | DataType::Markdown
+        | DataType::Slug
+        | DataType::String
+        | DataType::Unsupported(_) => "String",
+    }
+}
+
+fn datatype_expr(datatype: &DataType) -> String {
+    match datatype {
+        DataType::AtomicUrl => "DataType::AtomicUrl".into(),
+        DataType::Boolean => "DataType::Boolean".into(),
+        DataType::Date => "DataType::Date".into(),
+        DataType::Integer => "DataType::Integer".into(),
+        DataType::Float => "DataType::Float".into(),
+        DataType::Markdown => "DataType::Markdown".into(),
+        DataType::ResourceArray => "DataType::ResourceArray".into(),
+        DataType::Slug => "DataType::Slug".into(),
+        DataType::String => "DataType::String".into(),
+        DataType::Timestamp => "DataType::Timestamp".into(),
+        DataType::Unsupported(url) => format!("DataType::Unsupported({:?}.into())", url),
+    }
+}
+
+/// A Rust type that generated structs use for Values of some [DataType]s.
+pub trait TypedValue: Sized {
+    fn from_value(value: &Value) -> Option<Self>;
+    fn to_value(&self, datatype: &DataType) -> Value;
+}
+
+impl TypedValue for String {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::AtomicUrl(s)
+            | Value::Date(s)
+            | Value::Markdown(s)
+            | Value::Slug(s)
+            | Value::String(s) => Some(s.clone()),
+            Value::Resource(resource) => Some(resource.get_subject().clone()),
+            Value::Unsupported(unsupported) => Some(unsupported.value.clone()),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self, datatype: &DataType) -> Value {
+        match datatype {
+            DataType::AtomicUrl => Value::AtomicUrl(self.clone()),
+            DataType::Date => Value::Date(self.clone()),
+            DataType::Markdown => Value::Markdown(self.clone()),
+            DataType::Slug => Value::Slug(self.clone()),
+            DataType::Unsupported(url) => Value::Unsupported(UnsupportedValue {
+                value: self.clone(),
+                datatype: url.clone(),
+            }),
+            _ => Value::String(self.clone()),
+        }
+    }
+}
+
+impl TypedValue for i64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Integer(i) | Value::Timestamp(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self, datatype: &DataType) -> Value {
+        match datatype {
+            DataType::Timestamp => Value::Timestamp(*self),
+            _ => Value::Integer(*self),
+        }
+    }
+}
+
+impl TypedValue for f64 {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self, _datatype: &DataType) -> Value {
+        Value::Float(*self)
+    }
+}
+
+impl TypedValue for bool {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self, _datatype: &DataType) -> Value {
+        Value::Boolean(*self)
+    }
+}
+
+impl TypedValue for Vec<String> {
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::ResourceArray(_) => value.to_subjects(None).ok(),
+            _ => None,
+        }
+    }
+
+    fn to_value(&self, _datatype: &DataType) -> Value {
+        self.clone().into()
+    }
+}
+
+/// Reads a required Property, for generated code.
+pub fn required<T: TypedValue>(
+    resource: &Resource,
+    property: &str,
+    datatype: DataType,
+) -> AtomicResult<T> {
+    optional(resource, property, datatype)?.ok_or_else(|| {
+        format!(
+            "Resource {} is missing the required Property {}",
+            resource.get_subject(),
+            property
+        )
+        .into()
+    })
+}
+
+/// Reads a recommended Property, for generated code.
+pub fn optional<T: TypedValue>(
+    resource: &Resource,
+    property: &str,
+    datatype: DataType,
+) -> AtomicResult<Option<T>> {
+    let Ok(value) = resource.get(property) else {
+        return Ok(None);
+    };
+    match T::from_value(value) {
+        Some(typed) if value.datatype() == datatype => Ok(Some(typed)),
+        _ => Err(format!(
+            "Property {} of {} should be a {}, but is a {}",
+            property,
+            resource.get_subject(),
+            datatype.shortname(),
+            value.datatype().shortname()
+        )
+        .into()),
+    }
+}
+
+/// Sets a required Property, for generated code.
+pub fn set<T: TypedValue>(resource: &mut Resource, property: &str, value: &T, datatype: DataType) {
+    resource.set_propval_unsafe(property.into(), value.to_value(&datatype));
+}
+
+/// Sets a recommended Property, or removes it if it is `None`, for generated code.
+pub fn set_optional<T: TypedValue>(
+    resource: &mut Resource,
+    property: &str,
+    value: &Option<T>,
+    datatype: DataType,
+) {
+    match value {
+        Some(value) => set(resource, property, value, datatype),
+        None => {
+            if resource.get(property).is_ok() {
+                resource.remove_propval(property);
+            }
+        }
+    }
+}
+
+/// Adds a Class to the `is-a` of a Resource, if it's not there yet.
+pub fn add_class(resource: &mut Resource, class: &str) {
+    let mut classes = resource
+        .get(urls::IS_A)
+        .and_then(|value| value.to_subjects(None))
+        .unwrap_or_default();
+    if !classes.iter().any(|c| c == class) {
+        classes.push(class.into());
+        resource.set_propval_unsafe(urls::IS_A.into(), classes.into());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_classes_are_up_to_date() {
+        let generated =
+            generate_from_json_ad(include_str!("../defaults/default_store.json"), "crate").unwrap();
+        if std::env::var("UPDATE_TYPED").is_ok() {
+            std::fs::write(
+                concat!(env!("CARGO_MANIFEST_DIR"), "/src/typed.rs"),
+                &generated,
+            )
+            .unwrap();
+        }
+        assert!(
+            generated == include_str!("typed.rs"),
+            "src/typed.rs is outdated. Regenerate it with `UPDATE_TYPED=1 cargo test -p atomic_lib codegen`."
+        );
+    }
+
+    #[test]
+    fn names_are_valid_and_unique() {
+        assert_eq!(type_name("activity-summary"), "ActivitySummary");
+        assert_eq!(type_name("3d-model"), "_3dModel");
+        assert_eq!(field_name("download-url"), "download_url");
+        assert_eq!(field_name("type"), "type_");
+        let mut taken = HashSet::new();
+        assert_eq!(unique(&mut taken, "name".into()), "name");
+        assert_eq!(unique(&mut taken, "name".into()), "name_");
+    }
+
+    #[test]
+    fn helpers_check_datatypes() {
+        let mut resource = Resource::new("https://example.com/file".into());
+        resource.set_propval_unsafe(urls::FILESIZE.into(), Value::Integer(12));
+        resource.set_propval_unsafe(urls::FILENAME.into(), Value::Integer(3));
+        let size: i64 = required(&resource, urls::FILESIZE, DataType::Integer).unwrap();
+        assert_eq!(size, 12);
+        required::<String>(&resource, urls::FILENAME, DataType::String).unwrap_err();
+        required::<String>(&resource, urls::MIMETYPE, DataType::String).unwrap_err();
+        let mimetype: Option<String> =
+            optional(&resource, urls::MIMETYPE, DataType::String).unwrap();
+        assert!(mimetype.is_none());
+
+        set_optional::<String>(&mut resource, urls::FILENAME, &None, DataType::String);
+        assert!(resource.get(urls::FILENAME).is_err());
+        set(&mut resource, urls::CREATED_AT, &5, DataType::Timestamp);
+        assert!(matches!(
+            resource.get(urls::CREATED_AT),
+            Ok(Value::Timestamp(5))
+        ));
+        add_class(&mut resource, urls::FILE);
+        add_class(&mut resource, urls::FILE);
+        assert_eq!(
+            resource.get(urls::IS_A).unwrap().to_subjects(None).unwrap(),
+            vec![urls::FILE.to_string()]
+        );
+    }
+
+    #[cfg(feature = "typed")]
+    #[test]
+    fn typed_file_round_trips() {
+        use crate::typed::File;
+
+        let mut resource = Resource::new("https://example.com/file".into());
+        resource.set_class(urls::FILE);
+        resource.set_propval_unsafe(urls::FILESIZE.into(), Value::Integer(12));
+        resource.set_propval_unsafe(urls::MIMETYPE.into(), Value::String("text/plain".into()));
+        resource.set_propval_unsafe(
+            urls::DOWNLOAD_URL.into(),
+            Value::String("https://example.com/download/file".into()),
+        );
+        let mut file = File::from_resource(&resource).unwrap();
+        assert_eq!(file.filesize, Some(12));
+        assert_eq!(file.mimetype.as_deref(), Some("text/plain"));
+
+        file.filesize = Some(13);
+        file.mimetype = None;
+        let mut written = Resource::new(file.subject.clone());
+        file.apply_to(&mut written);
+        assert_eq!(File::from_resource(&written).unwrap(), file);
+        assert!(written.get(urls::MIMETYPE).is_err());
+    }
+}
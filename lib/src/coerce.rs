@@ -0,0 +1,288 @@
+//! Values that don't match the datatype of their Property anymore, e.g. because the Property was changed with `force`,
+//! or because a newer version of the default ontology changed it.
+//! Reading these Resources keeps working: the stored Value is served as it is, and the server lists the Properties in a `Warning` header, see [crate::Resource::datatype_mismatches].
+//! Writing stays strict, so new values always match the Property.
+//! Values that can be parsed as the new datatype (like the string `"42"` for an Integer) can be rewritten using [coerce_store].
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{
+    commit::{CommitBuilder, CommitOpts},
+    datatype::DataType,
+    errors::AtomicResult,
+    overlay::Overlay,
+    schema::Property,
+    urls, Resource, Storelike, Value,
+};
+
+/// A Value that is stored with another datatype than its Property requires.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DatatypeMismatch {
+    pub subject: String,
+    pub property: String,
+    pub value: String,
+    /// The datatype the Value was stored as
+    pub stored: String,
+    /// The current datatype of the Property
+    pub expected: String,
+    /// The datatype the Property had before its last datatype change, if its Commits are in the store
+    pub previous: Option<String>,
+    /// Whether the Value can be parsed as the current datatype, see [coerce_store]
+    pub convertible: bool,
+}
+
+/// Whether the Value was stored with another datatype than the Property requires.
+/// Nested Resources count as `AtomicUrl`, and Properties with an unsupported datatype accept anything.
+pub fn is_mismatch(value: &Value, property: &Property) -> bool {
+    match (value, &property.data_type) {
+        (_, DataType::Unsupported(_)) => false,
+        (Value::NestedResource(_) | Value::Resource(_), DataType::AtomicUrl) => false,
+        _ => value.datatype() != property.data_type,
+    }
+}
+
+/// Parses the stored Value as the datatype of the Property.
+/// Returns `None` if it can't be parsed, or if it doesn't pass the other checks of the Property.
+pub fn coerce_value(value: &Value, property: &Property) -> Option<Value> {
+    let coerced = Value::new(&value.to_string(), &property.data_type).ok()?;
+    property.check_value(&coerced).ok()?;
+    Some(coerced)
+}
+
+/// The datatype of the Property before it was changed to the current one, derived from the Commits of the Property.
+/// Always `None` without the `db` feature, since the Commits can't be looked up then.
+pub fn previous_datatype(store: &impl Storelike, property: &str) -> Option<String> {
+    #[cfg(feature = "db")]
+    let commits = crate::plugins::versioning::get_commits_for_resource(property, store).ok()?;
+    #[cfg(not(feature = "db"))]
+    let commits: Vec<crate::Commit> = {
+        let _ = (store, property);
+        Vec::new()
+    };
+    let mut datatypes: Vec<String> = commits
+        .iter()
+        .filter_map(|commit| commit.set.as_ref()?.get(urls::DATATYPE_PROP))
+        .map(|datatype| datatype.to_string())
+        .collect();
+    datatypes.dedup();
+    datatypes.pop()?;
+    datatypes.pop()
+}
+
+/// Finds the Values in the Resource that don't match their Property.
+/// `previous` caches [previous_datatype] per Property, so a store can be checked without reading the same Commits again.
+pub fn find_mismatches(
+    store: &impl Storelike,
+    resource: &Resource,
+    previous: &mut HashMap<String, Option<String>>,
+) -> Vec<DatatypeMismatch> {
+    let mut found = Vec::new();
+    for (prop, value) in resource.get_propvals() {
+        let Ok(property) = store.get_property(prop) else {
+            continue;
+        };
+        if !is_mismatch(value, &property) {
+            continue;
+        }
+        let previous = previous
+            .entry(prop.clone())
+            .or_insert_with(|| previous_datatype(store, prop))
+            .clone();
+        found.push(DatatypeMismatch {
+            subject: resource.get_subject().clone(),
+            property: prop.clone(),
+            value: value.to_string(),
+            stored: value.datatype().to_string(),
+            expected: property.data_type.to_string(),
+            previous,
+            convertible: coerce_value(value, &property).is_some(),
+        });
+    }
+    found
+}
+
+/// The result of [coerce_store].
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoercionReport {
+    /// Values that were rewritten as the datatype of their Property
+    pub coerced: Vec<DatatypeMismatch>,
+    /// Values that can't be converted, these need to be fixed by hand
+    pub remaining: Vec<DatatypeMismatch>,
+}
+
+/// Rewrites all Values that don't match their Property, but can be parsed as its datatype, using Commits signed by the default Agent.
+/// The other mismatches are reported in [CoercionReport::remaining].
+/// If `dry_run` is true, the Commits are applied to an [Overlay], which leaves the store as it is.
+/// Commits are skipped, since they can't be edited.
+pub fn coerce_store(store: &impl Storelike, dry_run: bool) -> AtomicResult<CoercionReport> {
+    if dry_run {
+        coerce(&Overlay::new(store))
+    } else {
+        coerce(store)
+    }
+}
+
+fn coerce(store: &impl Storelike) -> AtomicResult<CoercionReport> {
+    let opts = CommitOpts {
+        validate_schema: false,
+        validate_signature: false,
+        validate_timestamp: false,
+        validate_rights: false,
+        validate_previous_commit: false,
+        validate_for_agent: None,
+        update_index: true,
+        validate_relative_urls: false,
+    };
+    let agent = store.get_default_agent()?;
+    let mut previous = HashMap::new();
+    let mut report = CoercionReport::default();
+    for resource in store.all_resources(false) {
+        if resource
+            .get_main_class()
+            .map(|class| class == urls::COMMIT)
+            .unwrap_or(false)
+        {
+            continue;
+        }
+        let mismatches = find_mismatches(store, &resource, &mut previous);
+        if mismatches.is_empty() {
+            continue;
+        }
+        let mut commitbuilder = CommitBuilder::new(resource.get_subject().clone());
+        let mut changed = false;
+        for mismatch in mismatches {
+            let coerced = store
+                .get_property(&mismatch.property)
+                .ok()
+                .zip(resource.get(&mismatch.property).ok())
+                .and_then(|(property, value)| coerce_value(value, &property));
+            match coerced {
+                Some(value) => {
+                    commitbuilder.set(mismatch.property.clone(), value);
+                    changed = true;
+                    report.coerced.push(mismatch);
+                }
+                None => report.remaining.push(mismatch),
+            }
+        }
+        if changed {
+            commitbuilder
+                .sign(&agent, store, &resource)?
+                .apply_opts(store, &opts)?;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(all(test, feature = "db"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mismatches_are_served_reported_and_coerced() {
+        let store = crate::Db::init_temp("mismatches_are_served_reported_and_coerced").unwrap();
+        let mut property = Resource::new_generate_subject(&store);
+        property.set_class(urls::PROPERTY);
+        for (prop, val) in [
+            (urls::SHORTNAME, Value::Slug("age".into())),
+            (urls::DESCRIPTION, Value::Markdown("Age in years".into())),
+            (urls::DATATYPE_PROP, Value::AtomicUrl(urls::STRING.into())),
+            (
+                urls::PARENT,
+                Value::AtomicUrl(store.get_server_url().into()),
+            ),
+        ] {
+            property.set_propval(prop.into(), val, &store).unwrap();
+        }
+        property.save_locally(&store).unwrap();
+        let property = property.get_subject().clone();
+
+        let mut people = Vec::new();
+        for age in ["42", "old"] {
+            let mut person = Resource::new_generate_subject(&store);
+            person
+                .set_propval(property.clone(), Value::String(age.into()), &store)
+                .unwrap();
+            person.save_locally(&store).unwrap();
+            people.push(person.get_subject().clone());
+        }
+
+        let mut change = CommitBuilder::new(property.clone());
+        change.set(
+            urls::DATATYPE_PROP.into(),
+            Value::AtomicUrl(urls::INTEGER.into()),
+        );
+        change.force(true);
+        let agent = store.get_default_agent().unwrap();
+        let resource = store.get_resource(&property).unwrap();
+        change
+            .sign(&agent, &store, &resource)
+            .unwrap()
+            .apply_opts(
+                &store,
+                &CommitOpts {
+                    validate_schema: true,
+                    validate_signature: true,
+                    validate_timestamp: true,
+                    validate_rights: false,
+                    validate_previous_commit: false,
+                    validate_for_agent: None,
+                    update_index: true,
+                    validate_relative_urls: false,
+                },
+            )
+            .unwrap();
+
+        // Reading still works, and the mismatch is reported
+        let person = store.get_resource(&people[0]).unwrap();
+        assert_eq!(person.datatype_mismatches(&store), vec![property.clone()]);
+        assert!(person.to_json_ad().unwrap().contains("\"42\""));
+        person.to_n_triples(&store).unwrap();
+
+        // The defaults can have mismatches of their own, so only the Property of this test is checked
+        let ours = |mismatches: &[DatatypeMismatch]| -> Vec<DatatypeMismatch> {
+            mismatches
+                .iter()
+                .filter(|m| m.property == property)
+                .cloned()
+                .collect()
+        };
+        let mismatches = ours(&store.validate().datatype_mismatches);
+        assert_eq!(mismatches.len(), 2);
+        for mismatch in &mismatches {
+            assert_eq!(mismatch.stored, urls::STRING);
+            assert_eq!(mismatch.expected, urls::INTEGER);
+            assert_eq!(mismatch.previous.as_deref(), Some(urls::STRING));
+            assert_eq!(mismatch.convertible, mismatch.subject == people[0]);
+        }
+
+        let dry_run = coerce_store(&store, true).unwrap();
+        assert_eq!(ours(&dry_run.coerced).len(), 1);
+        assert_eq!(ours(&dry_run.remaining).len(), 1);
+        assert!(matches!(
+            store
+                .get_resource(&people[0])
+                .unwrap()
+                .get(&property)
+                .unwrap(),
+            Value::String(_)
+        ));
+
+        let report = coerce_store(&store, false).unwrap();
+        assert_eq!(ours(&report.coerced)[0].subject, people[0]);
+        assert_eq!(ours(&report.remaining)[0].subject, people[1]);
+        assert!(matches!(
+            store
+                .get_resource(&people[0])
+                .unwrap()
+                .get(&property)
+                .unwrap(),
+            Value::Integer(42)
+        ));
+        assert_eq!(ours(&store.validate().datatype_mismatches).len(), 1);
+    }
+}
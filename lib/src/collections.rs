@@ -2,8 +2,10 @@
 //! They are constructed using a [Query]
 use crate::{
     agents::ForAgent,
+    cursors::Cursor,
     errors::AtomicResult,
-    storelike::{Query, ResourceCollection},
+    schema_version,
+    storelike::{Query, QueryResult, ResourceCollection},
     urls, Resource, Storelike, Value,
 };
 
@@ -33,6 +35,12 @@ pub struct CollectionBuilder {
     pub include_nested: bool,
     /// Whether to include resources from other servers
     pub include_external: bool,
+    /// Only include Resources that were last written under an older version of the default ontology, or an unknown one.
+    /// See [crate::schema_version].
+    pub schema_version_below: Option<i64>,
+    /// Continue after the last member of a previous page, see [crate::cursors].
+    /// An empty cursor starts at the first page. Ignores `current_page`.
+    pub cursor: Option<String>,
 }
 
 impl CollectionBuilder {
@@ -102,9 +110,26 @@ impl CollectionBuilder {
             name: Some(format!("{} collection", path)),
             include_nested: true,
             include_external: false,
+            schema_version_below: None,
+            cursor: None,
         }
     }
 
+    /// Identifies the filters and sorting of the Collection, but not its page, for [Cursor]s.
+    fn cursor_query(&self) -> String {
+        let path = self.subject.split('?').next().unwrap_or_default();
+        format!(
+            "collection {} {:?} {:?} {:?} {} {} {:?}",
+            path,
+            self.property,
+            self.value,
+            self.sort_by,
+            self.sort_desc,
+            self.include_external,
+            self.schema_version_below
+        )
+    }
+
     /// Converts the CollectionBuilder into a collection, with Members
     pub fn into_collection(
         self,
@@ -148,6 +173,8 @@ pub struct Collection {
     pub include_nested: bool,
     /// Include resources from other servers
     pub include_external: bool,
+    /// Pass this as `cursor` to get the next page. Only set when the Collection was requested with a `cursor`, and more members follow.
+    pub next_cursor: Option<String>,
 }
 
 /// Sorts a vector or resources by some property.
@@ -214,9 +241,37 @@ impl Collection {
             for_agent: for_agent.clone(),
         };
 
-        let query_result = store.query(&q)?;
+        let mut next_cursor = None;
+        let query_result = match (
+            &collection_builder.cursor,
+            collection_builder.schema_version_below,
+        ) {
+            (Some(cursor), below) => {
+                let query = collection_builder.cursor_query();
+                let after = match cursor.as_str() {
+                    "" => None,
+                    cursor => {
+                        let after = Cursor::decode(store, &query, cursor)?;
+                        after.check_boundary(store, q.sort_by.as_deref())?;
+                        Some(after)
+                    }
+                };
+                let (result, next) = query_after_cursor(store, &q, below, after.as_ref())?;
+                next_cursor = next
+                    .map(|(value, subject)| Cursor::new(&query, value, subject).encode(store))
+                    .transpose()?;
+                result
+            }
+            (None, Some(below)) => query_schema_version_below(store, &q, below)?,
+            (None, None) => store.query(&q)?,
+        };
         let members = query_result.subjects;
-        let members_nested = Some(query_result.resources);
+        // Pages of a cursor only contain the Resources of their members if they are nested
+        let members_nested = if collection_builder.cursor.is_some() && !q.include_nested {
+            None
+        } else {
+            Some(query_result.resources)
+        };
         let total_items = query_result.count;
         let pages_fraction = total_items as f64 / collection_builder.page_size as f64;
         let total_pages = pages_fraction.ceil() as usize;
@@ -243,6 +298,7 @@ impl Collection {
             name: collection_builder.name,
             include_nested: collection_builder.include_nested,
             include_external: collection_builder.include_external,
+            next_cursor,
         };
         Ok(collection)
     }
@@ -313,6 +369,13 @@ impl Collection {
             self.page_size.into(),
             store,
         )?;
+        if let Some(cursor) = &self.next_cursor {
+            resource.set_propval_string(
+                crate::urls::COLLECTION_NEXT_CURSOR.into(),
+                cursor,
+                store,
+            )?;
+        }
 
         Ok(resource.to_owned())
     }
@@ -337,6 +400,8 @@ pub fn construct_collection_from_params(
     let mut name = None;
     let mut include_nested = false;
     let mut include_external = false;
+    let mut schema_version_below = None;
+    let mut cursor = None;
 
     if let Ok(val) = resource.get(urls::COLLECTION_PROPERTY) {
         property = Some(val.to_string());
@@ -360,12 +425,16 @@ pub fn construct_collection_from_params(
         match k.as_ref() {
             "property" => property = Some(v.to_string()),
             "value" => value = Some(v.to_string()),
-            "sort_by" => sort_by = Some(v.to_string()),
-            "sort_desc" => sort_desc = v.parse::<bool>()?,
-            "current_page" => current_page = v.parse::<usize>()?,
-            "page_size" => page_size = v.parse::<usize>()?,
-            "include_nested" => include_nested = v.parse::<bool>()?,
-            "include_external" => include_external = v.parse::<bool>()?,
+            "sort_by" | "sort-by" => sort_by = Some(v.to_string()),
+            "sort_desc" | "sort-desc" => sort_desc = v.parse::<bool>()?,
+            "current_page" | "current-page" => current_page = v.parse::<usize>()?,
+            "page_size" | "page-size" => page_size = v.parse::<usize>()?,
+            "include_nested" | "include-nested" => include_nested = v.parse::<bool>()?,
+            "include_external" | "include-external" => include_external = v.parse::<bool>()?,
+            "schema_version_below" | "schema-version-below" => {
+                schema_version_below = Some(v.parse::<i64>()?)
+            }
+            "cursor" => cursor = Some(v.to_string()),
             e => {
                 return Err(format!("Invalid query param: {}", e).into());
             }
@@ -382,11 +451,134 @@ pub fn construct_collection_from_params(
         name,
         include_nested,
         include_external,
+        schema_version_below,
+        cursor,
     };
     let collection = Collection::collect_members(store, collection_builder, for_agent)?;
     collection.add_to_resource(resource, store)
 }
 
+/// Runs the Query without paging, keeps the Resources that were last written under a schema version older than `below`, and pages those.
+fn query_schema_version_below(
+    store: &impl Storelike,
+    q: &Query,
+    below: i64,
+) -> AtomicResult<QueryResult> {
+    let mut all = q.clone();
+    all.limit = None;
+    all.offset = 0;
+    all.include_nested = true;
+    let matching: Vec<Resource> = store
+        .query(&all)?
+        .resources
+        .into_iter()
+        .filter(|resource| {
+            schema_version::is_older(schema_version::last_written_under(store, resource), below)
+        })
+        .collect();
+    let count = matching.len();
+    let page: Vec<Resource> = matching
+        .into_iter()
+        .skip(q.offset)
+        .take(q.limit.unwrap_or(usize::MAX))
+        .collect();
+    Ok(QueryResult {
+        count,
+        subjects: page
+            .iter()
+            .map(|resource| resource.get_subject().clone())
+            .collect(),
+        resources: if q.include_nested { page } else { Vec::new() },
+    })
+}
+
+/// A member of a Collection with its sortable value of `sort_by`, if it has one.
+type SortedMember = (Option<String>, String);
+
+/// Runs the Query without paging, orders the matches by their value of `sort_by` and then by subject,
+/// and pages the ones that come strictly after the `after` cursor.
+/// Also returns the sort value and subject of the last member, if more members follow.
+fn query_after_cursor(
+    store: &impl Storelike,
+    q: &Query,
+    schema_version_below: Option<i64>,
+    after: Option<&Cursor>,
+) -> AtomicResult<(QueryResult, Option<SortedMember>)> {
+    let mut all = q.clone();
+    all.limit = None;
+    all.offset = 0;
+    all.include_nested = false;
+    all.for_agent = ForAgent::Sudo;
+    let subjects = match schema_version_below {
+        Some(below) => query_schema_version_below(store, &all, below)?.subjects,
+        None => store.query(&all)?.subjects,
+    };
+    let count = subjects.len();
+
+    // Members without a value are sorted first, like in the query index
+    let key = |value: &Option<String>, subject: &str| {
+        (value.clone().unwrap_or_default(), subject.to_string())
+    };
+    let mut members: Vec<SortedMember> = subjects
+        .into_iter()
+        .map(|subject| {
+            let value = q.sort_by.as_ref().and_then(|sort_by| {
+                store
+                    .get_resource(&subject)
+                    .and_then(|r| r.get(sort_by).map(|v| v.to_sortable_string()))
+                    .ok()
+            });
+            (value, subject)
+        })
+        .collect();
+    members.sort_by(|(va, sa), (vb, sb)| {
+        let ordering = key(va, sa).cmp(&key(vb, sb));
+        if q.sort_desc {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+    if let Some(after) = after {
+        let boundary = key(&after.value, &after.subject);
+        members.retain(|(value, subject)| {
+            let position = key(value, subject);
+            if q.sort_desc {
+                position < boundary
+            } else {
+                position > boundary
+            }
+        });
+    }
+
+    let limit = q.limit.unwrap_or(usize::MAX);
+    let mut page = QueryResult {
+        subjects: Vec::new(),
+        resources: Vec::new(),
+        count,
+    };
+    let mut last = None;
+    let mut remaining = members.into_iter();
+    for (value, subject) in remaining.by_ref() {
+        if let Ok(resource) = store.get_resource_extended(&subject, true, &q.for_agent) {
+            page.subjects.push(subject.clone());
+            if q.include_nested {
+                page.resources.push(resource);
+            }
+            last = Some((value, subject));
+            if page.subjects.len() >= limit {
+                break;
+            }
+        }
+    }
+    let next = if remaining.next().is_some() {
+        last
+    } else {
+        None
+    };
+    Ok((page, next))
+}
+
 /// Creates a Collection resource in the Store for a Class, for example `/documents`.
 /// Does not save it, though.
 pub fn create_collection_resource_for_class(
@@ -458,6 +650,8 @@ mod test {
             name: Some("Test collection".into()),
             include_nested: false,
             include_external: false,
+            schema_version_below: None,
+            cursor: None,
         };
         let collection =
             Collection::collect_members(&store, collection_builder, &ForAgent::Sudo).unwrap();
@@ -479,6 +673,8 @@ mod test {
             name: None,
             include_nested: false,
             include_external: false,
+            schema_version_below: None,
+            cursor: None,
         };
         let collection =
             Collection::collect_members(&store, collection_builder, &ForAgent::Sudo).unwrap();
@@ -506,11 +702,13 @@ mod test {
             // The important bit here
             include_nested: true,
             include_external: false,
+            schema_version_below: None,
+            cursor: None,
         };
         let collection =
             Collection::collect_members(&store, collection_builder, &ForAgent::Sudo).unwrap();
         let first_resource = &collection.members_nested.clone().unwrap()[0];
-        assert!(first_resource.get_subject().contains("Agent"));
+        assert!(first_resource.get_subject().contains("ActivitySummary"));
 
         let resource_collection = &collection.to_resource(&store).unwrap();
         let val = resource_collection
@@ -1,14 +1,21 @@
 //! Describe changes / mutations to data
 
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::{Arc, Condvar, Mutex},
+    thread::ThreadId,
+};
 use urls::{SET, SIGNER};
 
 use crate::{
     agents::{decode_base64, encode_base64},
     datatype::DataType,
-    errors::AtomicResult,
+    errors::{AtomicErrorType, AtomicResult},
     hierarchy,
+    metrics::Operation,
+    patch::Patch,
     resources::PropVals,
     urls,
     values::SubResource,
@@ -26,6 +33,35 @@ pub struct CommitResponse {
     pub commit_struct: Commit,
 }
 
+impl CommitResponse {
+    /// The Commit resource as it is sent to clients: with the `client-id` that the client sent along, which is not stored.
+    pub fn echoed_commit_resource(&self) -> Resource {
+        let mut resource = self.commit_resource.clone();
+        if let Some(client_id) = &self.commit_struct.client_id {
+            resource.set_propval_unsafe(urls::CLIENT_ID.into(), Value::String(client_id.clone()));
+        }
+        resource
+    }
+}
+
+/// The longest `client-id` a Commit can have. Long enough for a UUID or a ULID with a prefix.
+pub const MAX_CLIENT_ID_LENGTH: usize = 128;
+
+/// Client ids are echoed in notifications, so only printable ASCII without whitespace is allowed.
+fn check_client_id(client_id: &str) -> AtomicResult<String> {
+    if client_id.is_empty()
+        || client_id.len() > MAX_CLIENT_ID_LENGTH
+        || !client_id.chars().all(|c| c.is_ascii_graphic())
+    {
+        return Err(format!(
+            "The client-id of a Commit must be 1 to {} printable ASCII characters without spaces",
+            MAX_CLIENT_ID_LENGTH
+        )
+        .into());
+    }
+    Ok(client_id.to_string())
+}
+
 #[derive(Clone, Debug)]
 /// Describes options for applying a Commit.
 /// Skip the checks you don't need to get better performance, or if you want to break the rules a little.
@@ -45,6 +81,9 @@ pub struct CommitOpts {
     pub update_index: bool,
     /// For who the right checks will be perormed. If empty, the signer of the Commit will be used.
     pub validate_for_agent: Option<String>,
+    /// Rejects relative references (like `./attachment`) in values that resolve to another origin than the subject.
+    /// Relative references are always resolved against the subject, see [Commit::resolve_references].
+    pub validate_relative_urls: bool,
 }
 
 /// A Commit is a set of changes to a Resource.
@@ -70,17 +109,39 @@ pub struct Commit {
     /// If set to true, deletes the entire resource
     #[serde(rename = "https://atomicdata.dev/properties/destroy")]
     pub destroy: Option<bool>,
+    /// If set to true, `destroy` permanently removes the resource instead of moving it to the trash
+    #[serde(rename = "https://atomicdata.dev/properties/purge")]
+    pub purge: Option<bool>,
+    /// If set to true, changes to Properties are applied even if they invalidate existing values
+    #[serde(rename = "https://atomicdata.dev/properties/force")]
+    pub force: Option<bool>,
     /// Base64 encoded signature of the JSON serialized Commit
     #[serde(rename = "https://atomicdata.dev/properties/signature")]
     pub signature: Option<String>,
     /// List of Properties and Arrays to be appended to them
     #[serde(rename = "https://atomicdata.dev/properties/push")]
     pub push: Option<std::collections::HashMap<String, Value>>,
+    /// List of Properties and Arrays of which the elements are to be removed from them
+    #[serde(rename = "https://atomicdata.dev/properties/pull")]
+    pub pull: Option<std::collections::HashMap<String, Value>>,
+    /// List of String or Markdown Properties and [Patch]es to be applied to their current values
+    #[serde(rename = "https://atomicdata.dev/properties/patch")]
+    pub patch: Option<std::collections::HashMap<String, Value>>,
+    /// List of Integer or Float Properties and the numbers to be added to their current values
+    #[serde(rename = "https://atomicdata.dev/properties/increment")]
+    pub increment: Option<std::collections::HashMap<String, Value>>,
+    /// If set to true, `push` skips the elements that are already present in the array
+    #[serde(rename = "https://atomicdata.dev/properties/pushUnique")]
+    pub push_unique: Option<bool>,
     /// The previously applied commit to this Resource.
     #[serde(rename = "https://atomicdata.dev/properties/previousCommit")]
     pub previous_commit: Option<String>,
     /// The URL of the Commit
     pub url: Option<String>,
+    /// Set by the client, so it can recognize its own Commit in the notifications it receives, see [CommitResponse::echoed_commit_resource].
+    /// Not signed and not stored.
+    #[serde(skip)]
+    pub client_id: Option<String>,
 }
 
 impl Commit {
@@ -88,11 +149,26 @@ impl Commit {
     /// Creates, edits or destroys a resource.
     /// Allows for control over which validations should be performed.
     /// Returns the generated Commit, the old Resource and the new Resource.
-    #[tracing::instrument(skip(store))]
+    #[track_caller]
     pub fn apply_opts(
         &self,
         store: &impl Storelike,
         opts: &CommitOpts,
+    ) -> AtomicResult<CommitResponse> {
+        let location = std::panic::Location::caller();
+        let start = std::time::Instant::now();
+        let result = self.apply_opts_untimed(store, opts);
+        if let Some(metrics) = store.get_metrics() {
+            metrics.record(Operation::Commit, start, location, || self.subject.clone());
+        }
+        result
+    }
+
+    #[tracing::instrument(skip(store))]
+    fn apply_opts_untimed(
+        &self,
+        store: &impl Storelike,
+        opts: &CommitOpts,
     ) -> AtomicResult<CommitResponse> {
         let subject_url = url::Url::parse(&self.subject)
             .map_err(|e| format!("Subject '{}' is not a URL. {}", &self.subject, e))?;
@@ -106,10 +182,9 @@ impl Commit {
                 Some(sig) => sig,
                 None => return Err("No signature set".into()),
             };
-            let pubkey_b64 = store
-                .get_resource(&self.signer)?
-                .get(urls::PUBLIC_KEY)?
-                .to_string();
+            let signer = store.get_resource(&self.signer)?;
+            crate::agents::check_not_suspended(&signer)?;
+            let pubkey_b64 = signer.get(urls::PUBLIC_KEY)?.to_string();
             let agent_pubkey = decode_base64(&pubkey_b64)?;
             let stringified_commit = self.serialize_deterministically_json_ad(store)?;
             let peer_public_key =
@@ -128,7 +203,27 @@ impl Commit {
         if opts.validate_timestamp {
             check_timestamp(self.created_at)?;
         }
-        let commit_resource: Resource = self.into_resource(store)?;
+        let mut commit_resource: Resource = self.into_resource(store)?;
+        crate::schema_version::stamp(&mut commit_resource);
+        // Prevents concurrent Commits to the same Resource from overwriting each other's changes.
+        let waiting = std::time::Instant::now();
+        let _guard = store
+            .get_subject_locks()
+            .map(|locks| locks.acquire(&self.subject));
+        if let Some(metrics) = store.get_metrics() {
+            let location = std::panic::Location::caller();
+            metrics.record(Operation::LockWait, waiting, location, || {
+                self.subject.clone()
+            });
+        }
+
+        // Locks are advisory, so only Commits that are checked for rights respect them.
+        if opts.validate_rights {
+            if let Some(locks) = store.get_locks() {
+                let validate_for = opts.validate_for_agent.as_ref().unwrap_or(&self.signer);
+                locks.check_and_refresh(&self.subject, validate_for)?;
+            }
+        }
         let mut is_new = false;
         // Create a new resource if it doens't exist yet
         let mut resource_old = match store.get_resource(&self.subject) {
@@ -140,7 +235,8 @@ impl Commit {
         };
 
         // Make sure the one creating the commit had the same idea of what the current state is.
-        if !is_new && opts.validate_previous_commit {
+        // `push`, `pull` and `increment` are applied to the current value, so they don't depend on the state the client has seen.
+        if !is_new && opts.validate_previous_commit && !self.only_relative_changes() {
             if let Ok(last_commit_val) = resource_old.get(urls::LAST_COMMIT) {
                 let last_commit = last_commit_val.to_string();
 
@@ -162,12 +258,23 @@ impl Commit {
             }
         };
 
-        let mut resource_new = self
+        // Relative references are resolved after checking the signature, which covers the Commit as it was sent
+        let resolved = self.resolve_references(opts.validate_relative_urls)?;
+        let mut resource_new = resolved
             .apply_changes(resource_old.clone(), store, false)
             .map_err(|e| format!("Error applying changes to Resource {}. {}", self.subject, e))?;
 
+        // A Resource can't become its own ancestor
+        if let Some(new_parent) = self.set.as_ref().and_then(|set| set.get(urls::PARENT)) {
+            hierarchy::check_new_parent(store, &self.subject, &new_parent.to_string())?;
+        }
+
         if opts.validate_rights {
             let validate_for = opts.validate_for_agent.as_ref().unwrap_or(&self.signer);
+            #[cfg(feature = "db")]
+            crate::plugins::pins::check_commit(self, validate_for)?;
+            #[cfg(feature = "db")]
+            crate::plugins::notifications::check_commit(self, &resource_old, validate_for)?;
             if is_new {
                 hierarchy::check_append(store, &resource_new, &validate_for.into())?;
             } else {
@@ -184,8 +291,46 @@ impl Commit {
                 }
                 // This should use the _old_ resource, no the new one, as the new one might maliciously give itself write rights.
                 hierarchy::check_write(store, &resource_old, &validate_for.into())?;
+                #[cfg(feature = "db")]
+                crate::plugins::property_rights::check_commit(
+                    store,
+                    self,
+                    &resource_old,
+                    validate_for,
+                )?;
+                // Moving a Resource changes the contents of both the old and the new parent
+                if let Ok(new_parent) = resource_new.get(urls::PARENT) {
+                    let new_parent = new_parent.to_string();
+                    // A Resource without a parent is moved when it gets one
+                    let old_parent = resource_old.get(urls::PARENT).ok().map(|v| v.to_string());
+                    if old_parent != Some(new_parent.clone()) {
+                        hierarchy::check_move(
+                            store,
+                            &resource_old,
+                            &new_parent,
+                            &validate_for.into(),
+                        )?;
+                    }
+                }
             }
         };
+        // New Resources get the default rights of their Drive, after the rights of the signer have been checked.
+        #[cfg(feature = "db")]
+        let mut commit_resource = commit_resource;
+        #[cfg(feature = "db")]
+        let with_default_rights = match is_new {
+            true => crate::plugins::default_rights::for_new_resource(store, self, &resource_new)?,
+            false => None,
+        }
+        .map(|rights| {
+            rights.add_to_resource(&mut resource_new);
+            rights.record(&mut commit_resource);
+            rights.add_to_commit(&resolved)
+        });
+        #[cfg(not(feature = "db"))]
+        let with_default_rights: Option<Commit> = None;
+        let applied = with_default_rights.as_ref().unwrap_or(&resolved);
+
         // Check if all required props are there
         if opts.validate_schema {
             resource_new.check_required_props(store)?;
@@ -202,12 +347,37 @@ impl Commit {
 
         // BEFORE APPLY COMMIT HANDLERS
         #[cfg(feature = "db")]
+        crate::plugins::deprecation::before_apply_commit(
+            store,
+            self,
+            &resource_old,
+            &resource_new,
+            is_new,
+        )?;
+        #[cfg(feature = "db")]
         for class in &_resource_new_classes {
             match class.subject.as_str() {
                 urls::COMMIT => return Err("Commits can not be edited or created directly.".into()),
                 urls::INVITE => {
                     crate::plugins::invite::before_apply_commit(store, self, &resource_new)?
                 }
+                urls::DYNAMIC_COLLECTION => {
+                    crate::plugins::dynamic_collection::before_apply_commit(
+                        store,
+                        self,
+                        &resource_new,
+                    )?
+                }
+                urls::IMPORT_PROFILE => {
+                    crate::plugins::import_profile::before_apply_commit(store, self, &resource_new)?
+                }
+                urls::PROPERTY => crate::plugins::property::before_apply_commit(
+                    store,
+                    self,
+                    &resource_old,
+                    &resource_new,
+                    is_new,
+                )?,
                 _other => {}
             };
         }
@@ -216,20 +386,49 @@ impl Commit {
         // TODO: Should we remove the existing commits too? Probably.
         if let Some(destroy) = self.destroy {
             if destroy {
+                // Unless purged, the resource is moved to the trash of its Drive
+                #[cfg(feature = "db")]
+                if !is_new && !self.purge.unwrap_or(false) {
+                    if let Some(trash) = crate::plugins::trash::find_trash(store, &resource_old)? {
+                        let resource_new = crate::plugins::trash::move_to_trash(
+                            store,
+                            &resource_old,
+                            &trash,
+                            commit_resource.get_subject(),
+                            opts.update_index,
+                        )?;
+                        store.add_resource_opts(
+                            &commit_resource,
+                            false,
+                            opts.update_index,
+                            false,
+                        )?;
+                        let commit_response = CommitResponse {
+                            resource_new: Some(resource_new),
+                            resource_old: Some(resource_old),
+                            commit_resource,
+                            commit_struct: self.clone(),
+                        };
+                        store.handle_commit(&commit_response);
+                        return Ok(commit_response);
+                    }
+                }
                 // Note: the value index is updated before this action, in resource.apply_changes()
                 store.remove_resource(&self.subject)?;
                 store.add_resource_opts(&commit_resource, false, opts.update_index, false)?;
-                return Ok(CommitResponse {
+                let commit_response = CommitResponse {
                     resource_new: None,
                     resource_old: Some(resource_old),
                     commit_resource,
                     commit_struct: self.clone(),
-                });
+                };
+                store.handle_commit(&commit_response);
+                return Ok(commit_response);
             }
         }
 
         // We apply the changes again, but this time also update the index
-        self.apply_changes(resource_old.clone(), store, opts.update_index)?;
+        applied.apply_changes(resource_old.clone(), store, opts.update_index)?;
 
         // Save the Commit to the Store. We can skip the required props checking, but we need to make sure the commit hasn't been applied before.
         store.add_resource_opts(&commit_resource, false, opts.update_index, false)?;
@@ -309,8 +508,12 @@ impl Commit {
                     })?;
 
                 if update_index {
-                    let new_atom =
-                        Atom::new(resource.get_subject().clone(), prop.into(), new_val.clone());
+                    // The stored value has been normalized, see [crate::normalize]
+                    let new_atom = Atom::new(
+                        resource.get_subject().clone(),
+                        prop.into(),
+                        resource.get(prop)?.clone(),
+                    );
                     if let Ok(old_val) = resource_unedited.get(prop) {
                         let old_atom =
                             Atom::new(resource.get_subject().clone(), prop.into(), old_val.clone());
@@ -320,7 +523,73 @@ impl Commit {
                 }
             }
         }
+        if let Some(patch) = self.patch.clone() {
+            for (prop, patch_val) in patch.iter() {
+                let property = store.get_property(prop)?;
+                if !matches!(property.data_type, DataType::String | DataType::Markdown) {
+                    return Err(format!(
+                        "Property '{}' can not be patched, only String and Markdown values can.",
+                        prop
+                    )
+                    .into());
+                }
+                let current = match resource.get(prop) {
+                    Ok(val) => val.to_string(),
+                    Err(_) => String::new(),
+                };
+                let patched = Patch::from_str(&patch_val.to_string())
+                    .and_then(|patch| patch.apply(&current))
+                    .map_err(|e| format!("Failed to patch property '{}' in Commit. {}", prop, e))?;
+                let new_val = Value::new(&patched, &property.data_type)?;
+                resource.set_propval(prop.into(), new_val, store)?;
+
+                if update_index {
+                    if let Ok(old_val) = resource_unedited.get(prop) {
+                        let old_atom =
+                            Atom::new(resource.get_subject().clone(), prop.into(), old_val.clone());
+                        remove_atoms.push(old_atom);
+                    }
+                    add_atoms.push(Atom::new(
+                        resource.get_subject().clone(),
+                        prop.into(),
+                        resource.get(prop)?.clone(),
+                    ));
+                }
+            }
+        }
+        if let Some(increment) = self.increment.clone() {
+            for (prop, delta) in increment.iter() {
+                let property = store.get_property(prop)?;
+                let zero = match property.data_type {
+                    DataType::Integer => Value::Integer(0),
+                    DataType::Float => Value::Float(0.0),
+                    _ => {
+                        let msg = "can not be incremented, only Integer and Float values can.";
+                        return Err(format!("Property '{}' {}", prop, msg).into());
+                    }
+                };
+                let current = resource.get(prop).cloned().unwrap_or(zero);
+                let new_val = current.add_number(delta).map_err(|e| {
+                    format!("Failed to increment property '{}' in Commit. {}", prop, e)
+                })?;
+                resource.set_propval(prop.into(), new_val, store)?;
+
+                if update_index {
+                    if let Ok(old_val) = resource_unedited.get(prop) {
+                        let old_atom =
+                            Atom::new(resource.get_subject().clone(), prop.into(), old_val.clone());
+                        remove_atoms.push(old_atom);
+                    }
+                    add_atoms.push(Atom::new(
+                        resource.get_subject().clone(),
+                        prop.into(),
+                        resource.get(prop)?.clone(),
+                    ));
+                }
+            }
+        }
         if let Some(push) = self.push.clone() {
+            let unique = self.push_unique.unwrap_or(false);
             for (prop, vec) in push.iter() {
                 let mut old_vec = match resource.get(prop) {
                     Ok(val) => match val {
@@ -329,10 +598,15 @@ impl Commit {
                     },
                     Err(_) => Vec::new(),
                 };
-                let new_vec = match vec {
+                let mut new_vec = match vec {
                     Value::ResourceArray(res_arr) => res_arr.clone(),
                     _other => return Err("Wrong datatype when pushing to array".into()),
                 };
+                if unique {
+                    let mut existing: HashSet<String> =
+                        old_vec.iter().map(|v| v.to_string()).collect();
+                    new_vec.retain(|v| existing.insert(v.to_string()));
+                }
                 old_vec.append(&mut new_vec.clone());
                 resource.set_propval_unsafe(prop.into(), old_vec.into());
                 if update_index {
@@ -347,6 +621,36 @@ impl Commit {
                 }
             }
         }
+        if let Some(pull) = self.pull.clone() {
+            for (prop, vec) in pull.iter() {
+                let old_vec = match resource.get(prop) {
+                    Ok(Value::ResourceArray(res_arr)) => res_arr.clone(),
+                    Ok(_other) => return Err("Wrong datatype when pulling from array".into()),
+                    // Nothing to remove
+                    Err(_) => continue,
+                };
+                let pulled: HashSet<String> = match vec {
+                    Value::ResourceArray(res_arr) => {
+                        res_arr.iter().map(|v| v.to_string()).collect()
+                    }
+                    _other => return Err("Wrong datatype when pulling from array".into()),
+                };
+                let (removed, kept): (Vec<SubResource>, Vec<SubResource>) = old_vec
+                    .into_iter()
+                    .partition(|v| pulled.contains(&v.to_string()));
+                resource.set_propval_unsafe(prop.into(), kept.into());
+                if update_index {
+                    for removed_resource in removed {
+                        let atom = Atom::new(
+                            resource.get_subject().clone(),
+                            prop.into(),
+                            removed_resource.into(),
+                        );
+                        remove_atoms.push(atom);
+                    }
+                }
+            }
+        }
         // Remove all atoms from index if destroy
         if let Some(destroy) = self.destroy {
             if destroy {
@@ -382,6 +686,7 @@ impl Commit {
             validate_previous_commit: false,
             validate_for_agent: None,
             update_index: false,
+            validate_relative_urls: false,
         };
         self.apply_opts(store, &opts)
     }
@@ -400,6 +705,22 @@ impl Commit {
             Ok(found) => Some(found.to_nested()?.to_owned()),
             Err(_) => None,
         };
+        let pull = match resource.get(urls::PULL) {
+            Ok(found) => Some(found.to_nested()?.to_owned()),
+            Err(_) => None,
+        };
+        let patch = match resource.get(urls::PATCH) {
+            Ok(found) => Some(found.to_nested()?.to_owned()),
+            Err(_) => None,
+        };
+        let increment = match resource.get(urls::INCREMENT) {
+            Ok(found) => Some(found.to_nested()?.to_owned()),
+            Err(_) => None,
+        };
+        let push_unique = match resource.get(urls::PUSH_UNIQUE) {
+            Ok(found) => Some(found.to_bool()?),
+            Err(_) => None,
+        };
         let remove = match resource.get(urls::REMOVE) {
             Ok(found) => Some(found.to_subjects(None)?),
             Err(_) => None,
@@ -408,12 +729,24 @@ impl Commit {
             Ok(found) => Some(found.to_bool()?),
             Err(_) => None,
         };
+        let purge = match resource.get(urls::PURGE) {
+            Ok(found) => Some(found.to_bool()?),
+            Err(_) => None,
+        };
+        let force = match resource.get(urls::FORCE) {
+            Ok(found) => Some(found.to_bool()?),
+            Err(_) => None,
+        };
         let previous_commit = match resource.get(urls::PREVIOUS_COMMIT) {
             Ok(found) => Some(found.to_string()),
             Err(_) => None,
         };
         let signature = resource.get(urls::SIGNATURE)?.to_string();
         let url = Some(resource.get_subject().into());
+        let client_id = match resource.get(urls::CLIENT_ID) {
+            Ok(found) => Some(check_client_id(&found.to_string())?),
+            Err(_) => None,
+        };
 
         Ok(Commit {
             subject,
@@ -421,11 +754,18 @@ impl Commit {
             signer,
             set,
             push,
+            pull,
+            patch,
+            increment,
+            push_unique,
             remove,
             destroy,
+            purge,
+            force,
             previous_commit,
             signature: Some(signature),
             url,
+            client_id,
         })
     }
 
@@ -474,6 +814,16 @@ impl Commit {
                 resource.set_propval_unsafe(urls::DESTROY.into(), true.into());
             }
         }
+        if let Some(purge) = self.purge {
+            if purge {
+                resource.set_propval_unsafe(urls::PURGE.into(), true.into());
+            }
+        }
+        if let Some(force) = self.force {
+            if force {
+                resource.set_propval_unsafe(urls::FORCE.into(), true.into());
+            }
+        }
         if let Some(previous_commit) = &self.previous_commit {
             resource.set_propval_unsafe(
                 urls::PREVIOUS_COMMIT.into(),
@@ -492,6 +842,26 @@ impl Commit {
                 resource.set_propval_unsafe(urls::PUSH.into(), push.clone().into());
             }
         }
+        if let Some(pull) = &self.pull {
+            if !pull.is_empty() {
+                resource.set_propval_unsafe(urls::PULL.into(), pull.clone().into());
+            }
+        }
+        if let Some(patch) = &self.patch {
+            if !patch.is_empty() {
+                resource.set_propval_unsafe(urls::PATCH.into(), patch.clone().into());
+            }
+        }
+        if let Some(increment) = &self.increment {
+            if !increment.is_empty() {
+                resource.set_propval_unsafe(urls::INCREMENT.into(), increment.clone().into());
+            }
+        }
+        if let Some(push_unique) = self.push_unique {
+            if push_unique {
+                resource.set_propval_unsafe(urls::PUSH_UNIQUE.into(), true.into());
+            }
+        }
         Ok(resource)
     }
 
@@ -499,6 +869,45 @@ impl Commit {
         &self.subject
     }
 
+    /// Returns a copy of the Commit, in which relative references (like `./attachment` or `#section`) in `set`, `push` and `pull`
+    /// are resolved against its subject. See [crate::utils::resolve_reference].
+    pub fn resolve_references(&self, strict: bool) -> AtomicResult<Commit> {
+        let resolve = |changes: &Option<HashMap<String, Value>>| -> AtomicResult<_> {
+            changes
+                .as_ref()
+                .map(|changes| {
+                    changes
+                        .iter()
+                        .map(|(prop, val)| {
+                            let resolved = val
+                                .resolve_references(&self.subject, strict)
+                                .map_err(|e| format!("Invalid value for {}. {}", prop, e))?;
+                            Ok((prop.clone(), resolved))
+                        })
+                        .collect::<AtomicResult<HashMap<String, Value>>>()
+                })
+                .transpose()
+        };
+        let mut resolved = self.clone();
+        resolved.set = resolve(&self.set)?;
+        resolved.push = resolve(&self.push)?;
+        resolved.pull = resolve(&self.pull)?;
+        Ok(resolved)
+    }
+
+    /// Returns true if the Commit only contains `push`, `pull` and `increment` changes.
+    /// These are applied to the current values, so concurrent Commits like these don't conflict.
+    pub fn only_relative_changes(&self) -> bool {
+        let has_relative = self.push.as_ref().is_some_and(|p| !p.is_empty())
+            || self.pull.as_ref().is_some_and(|p| !p.is_empty())
+            || self.increment.as_ref().is_some_and(|i| !i.is_empty());
+        has_relative
+            && self.set.as_ref().is_none_or(|s| s.is_empty())
+            && self.patch.as_ref().is_none_or(|p| p.is_empty())
+            && self.remove.as_ref().is_none_or(|r| r.is_empty())
+            && !self.destroy.unwrap_or(false)
+    }
+
     /// Generates a deterministic serialized JSON-AD representation of the Commit.
     /// Removes the signature from the object before serializing, since this function is used to check if the signature is correct.
     #[tracing::instrument(skip(store))]
@@ -528,16 +937,41 @@ pub struct CommitBuilder {
     set: std::collections::HashMap<String, Value>,
     /// The set of PropVals that need to be appended to resource arrays.
     push: std::collections::HashMap<String, Value>,
+    /// The set of PropVals that need to be removed from resource arrays.
+    #[serde(default)]
+    pull: std::collections::HashMap<String, Value>,
+    /// Patches to the current String or Markdown values, see [crate::patch].
+    /// https://atomicdata.dev/properties/patch
+    #[serde(default)]
+    patch: std::collections::HashMap<String, Value>,
+    /// Numbers to add to the current Integer or Float values.
+    /// https://atomicdata.dev/properties/increment
+    #[serde(default)]
+    increment: std::collections::HashMap<String, Value>,
+    /// Skip pushed values that are already present in the array.
+    #[serde(default)]
+    push_unique: bool,
     /// The set of property URLs that need to be removed
     /// https://atomicdata.dev/properties/remove
     remove: HashSet<String>,
     /// If set to true, deletes the entire resource
     /// https://atomicdata.dev/properties/destroy
     destroy: bool,
+    /// Destroy permanently, instead of moving the resource to the trash.
+    /// https://atomicdata.dev/properties/purge
+    #[serde(default)]
+    purge: bool,
+    /// Apply changes to Properties, even if existing values become invalid.
+    /// https://atomicdata.dev/properties/force
+    #[serde(default)]
+    force: bool,
     // pub signature: String,
     /// The previous Commit that was applied to the target resource (the subject) of this Commit. You should be able to follow these from Commit to Commit to establish an audit trail.
     /// https://atomicdata.dev/properties/previousCommit
     previous_commit: Option<String>,
+    /// Copied to [Commit::client_id], not signed.
+    #[serde(skip)]
+    client_id: Option<String>,
 }
 
 impl CommitBuilder {
@@ -545,11 +979,18 @@ impl CommitBuilder {
     pub fn new(subject: String) -> Self {
         CommitBuilder {
             push: HashMap::new(),
+            pull: HashMap::new(),
+            patch: HashMap::new(),
+            increment: HashMap::new(),
+            push_unique: false,
             subject,
             set: HashMap::new(),
             remove: HashSet::new(),
             destroy: false,
+            purge: false,
+            force: false,
             previous_commit: None,
+            client_id: None,
         }
     }
 
@@ -571,6 +1012,54 @@ impl CommitBuilder {
         Ok(())
     }
 
+    /// Removes a URL or (nested anonymous) Resource from a ResourceArray.
+    /// Every occurrence of the value is removed, and the rest of the array is left untouched.
+    pub fn pull_propval(&mut self, property: &str, value: SubResource) -> AtomicResult<()> {
+        let mut vec = match self.pull.get(property) {
+            Some(val) => match val {
+                Value::ResourceArray(resources) => resources.to_owned(),
+                other => {
+                    return Err(
+                        format!("Expected ResourceArray in pull_propval, got {}", other).into(),
+                    )
+                }
+            },
+            None => Vec::new(),
+        };
+        vec.push(value);
+        self.pull.insert(property.into(), Value::ResourceArray(vec));
+        Ok(())
+    }
+
+    /// Changes a String or Markdown value from `old` to `new`, by only sending the part that changed.
+    /// `old` must be the current value of the Property, otherwise the Commit is refused.
+    /// Useful for long texts, where sending the entire value with [CommitBuilder::set] would be wasteful.
+    pub fn patch_string(&mut self, property: &str, old: &str, new: &str) {
+        self.set.remove(property);
+        self.patch.insert(
+            property.into(),
+            Value::String(Patch::diff(old, new).to_string()),
+        );
+    }
+
+    /// Adds `delta` to the current value of an Integer or Float Property, when the Commit is applied.
+    /// Unlike [CommitBuilder::set], concurrent increments don't overwrite each other, which makes this useful for counters.
+    /// Use a negative `delta` to decrement.
+    pub fn increment(&mut self, property: &str, delta: Value) -> AtomicResult<()> {
+        let total = match self.increment.get(property) {
+            Some(previous) => previous.add_number(&delta)?,
+            None => Value::Integer(0).add_number(&delta)?,
+        };
+        self.set.remove(property);
+        self.increment.insert(property.into(), total);
+        Ok(())
+    }
+
+    /// When applying the Commit, skip pushed values that the array already contains.
+    pub fn push_unique(&mut self, unique: bool) {
+        self.push_unique = unique;
+    }
+
     /// Creates the Commit and signs it using a signature.
     /// Does not send it - see [atomic_lib::client::post_commit].
     /// Private key is the base64 encoded pkcs8 for the signer.
@@ -591,9 +1080,16 @@ impl CommitBuilder {
 
     /// Set Property / Value combinations that will either be created or overwritten.
     pub fn set(&mut self, prop: String, val: Value) {
+        self.patch.remove(&prop);
+        self.increment.remove(&prop);
         self.set.insert(prop, val);
     }
 
+    /// The subject of the Resource that is changed by this Commit
+    pub fn get_subject(&self) -> &str {
+        &self.subject
+    }
+
     /// Set a new subject for this Commit
     pub fn set_subject(&mut self, subject: String) {
         self.subject = subject;
@@ -608,6 +1104,263 @@ impl CommitBuilder {
     pub fn destroy(&mut self, destroy: bool) {
         self.destroy = destroy
     }
+
+    /// Whether a destroyed resource skips the trash and is removed permanently
+    pub fn purge(&mut self, purge: bool) {
+        self.purge = purge
+    }
+
+    /// Whether changes to a Property are applied even if they make existing values invalid
+    pub fn force(&mut self, force: bool) {
+        self.force = force
+    }
+
+    /// An id picked by the client, which the server echoes in its response and in the notifications for this Commit.
+    pub fn client_id(&mut self, client_id: String) {
+        self.client_id = Some(client_id)
+    }
+
+    /// Start constructing a Commit that is checked against the schema in `store` while it is built, see [CheckedCommitBuilder].
+    /// The current version of the Resource is read from `store`. If it does not exist, the Commit creates it.
+    pub fn checked<S: Storelike>(
+        subject: String,
+        store: &S,
+    ) -> AtomicResult<CheckedCommitBuilder<'_, S>> {
+        let (resource, exists) = match store.get_resource(&subject) {
+            Ok(resource) => (resource, true),
+            Err(e) if matches!(e.error_type, AtomicErrorType::NotFoundError) => {
+                (Resource::new(subject.clone()), false)
+            }
+            Err(e) => return Err(e),
+        };
+        let mut builder = CommitBuilder::new(subject);
+        if let Ok(last) = resource.get(urls::LAST_COMMIT) {
+            builder.previous_commit = Some(last.to_string());
+        }
+        Ok(CheckedCommitBuilder {
+            store,
+            resource,
+            exists,
+            builder,
+            changed: HashSet::new(),
+            misuse: Vec::new(),
+        })
+    }
+}
+
+/// Builds a Commit like [CommitBuilder], but checks every change against the Properties and Classes in a store, which can be a cached client store.
+/// Values with the wrong datatype, values that the Property does not allow and unknown shortnames fail right away.
+/// Calls that contradict each other, such as a `set` after a `destroy`, fail when the Commit is signed.
+/// Missing `required` Properties are also reported when signing, so they can be set in any order.
+pub struct CheckedCommitBuilder<'a, S: Storelike> {
+    store: &'a S,
+    /// The Resource as it will be after the Commit
+    resource: Resource,
+    exists: bool,
+    builder: CommitBuilder,
+    /// The Properties that are set, pushed or removed
+    changed: HashSet<String>,
+    /// Mistakes in the order of calls, returned by [CheckedCommitBuilder::sign]
+    misuse: Vec<String>,
+}
+
+impl<S: Storelike> std::fmt::Debug for CheckedCommitBuilder<'_, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CheckedCommitBuilder")
+            .field("resource", &self.resource)
+            .field("exists", &self.exists)
+            .field("builder", &self.builder)
+            .field("changed", &self.changed)
+            .field("misuse", &self.misuse)
+            .finish()
+    }
+}
+
+impl<S: Storelike> CheckedCommitBuilder<'_, S> {
+    fn check_not_destroyed(&mut self, action: &str, property: &str) {
+        if self.builder.destroy {
+            self.misuse.push(format!(
+                "Can't {} {} in a Commit that destroys the Resource",
+                action, property
+            ));
+        }
+    }
+
+    /// Sets the Value of a Property, after checking its datatype and the values it allows.
+    pub fn set(&mut self, property: &str, value: Value) -> AtomicResult<&mut Self> {
+        self.check_not_destroyed("set", property);
+        if self.builder.remove.contains(property) {
+            self.misuse
+                .push(format!("{} is both set and removed", property));
+        }
+        self.resource
+            .set_propval(property.into(), value, self.store)?;
+        // `set_propval` normalizes the value, so the Commit contains what the server would store
+        let value = self.resource.get(property)?.clone();
+        self.builder.set(property.into(), value);
+        self.changed.insert(property.into());
+        Ok(self)
+    }
+
+    /// Parses `value` using the datatype of the Property with this shortname, which must belong to one of the Classes of the Resource.
+    pub fn set_by_shortname(&mut self, shortname: &str, value: &str) -> AtomicResult<&mut Self> {
+        let property = self
+            .resource
+            .resolve_shortname_to_property(shortname, self.store)?;
+        let value = Value::new(value, &property.data_type).map_err(|e| {
+            e.set_subject(self.resource.get_subject())
+                .set_property(&property)
+        })?;
+        self.set(&property.subject, value)
+    }
+
+    /// Removes a Property from the Resource.
+    pub fn remove(&mut self, property: &str) -> AtomicResult<&mut Self> {
+        self.check_not_destroyed("remove", property);
+        if self.builder.set.contains_key(property) || self.builder.push.contains_key(property) {
+            self.misuse
+                .push(format!("{} is both set and removed", property));
+        }
+        // Fails for Properties that don't exist
+        self.store.get_property(property)?;
+        self.resource.remove_propval(property);
+        self.builder.remove(property.into());
+        self.changed.insert(property.into());
+        Ok(self)
+    }
+
+    /// Appends a value to a ResourceArray, after checking the values the Property allows.
+    pub fn push(&mut self, property: &str, value: SubResource) -> AtomicResult<&mut Self> {
+        self.check_not_destroyed("push to", property);
+        if self.builder.remove.contains(property) {
+            self.misuse
+                .push(format!("{} is both pushed to and removed", property));
+        }
+        let full_prop = self.store.get_property(property)?;
+        if full_prop.data_type != DataType::ResourceArray {
+            return Err(format!(
+                "Can't push to {}, its datatype is {} instead of a ResourceArray",
+                property, full_prop.data_type
+            )
+            .into());
+        }
+        let mut items = match self.resource.get(property) {
+            Ok(Value::ResourceArray(items)) => items.clone(),
+            _ => Vec::new(),
+        };
+        items.push(value.clone());
+        self.resource
+            .set_propval(property.into(), Value::ResourceArray(items), self.store)?;
+        self.builder.push_propval(property, value)?;
+        self.changed.insert(property.into());
+        Ok(self)
+    }
+
+    /// Destroys the Resource. Can't be combined with other changes.
+    pub fn destroy(&mut self) -> AtomicResult<&mut Self> {
+        if !self.exists {
+            return Err(format!(
+                "Can't destroy {}, it does not exist",
+                self.resource.get_subject()
+            )
+            .into());
+        }
+        let mut changed: Vec<&String> = self.changed.iter().collect();
+        changed.sort();
+        for property in changed {
+            self.misuse.push(format!(
+                "{} is changed in a Commit that destroys the Resource",
+                property
+            ));
+        }
+        self.builder.destroy(true);
+        Ok(self)
+    }
+
+    /// The Resource as it will be after the Commit is applied.
+    pub fn resource(&self) -> &Resource {
+        &self.resource
+    }
+
+    /// Checks the required Properties and signs the Commit, with the `lastCommit` of the Resource as its `previousCommit`.
+    /// Does not apply or send it.
+    pub fn sign(self, agent: &crate::agents::Agent) -> AtomicResult<Commit> {
+        if !self.misuse.is_empty() {
+            return Err(format!(
+                "Invalid Commit for {}: {}",
+                self.resource.get_subject(),
+                self.misuse.join(". ")
+            )
+            .into());
+        }
+        if !self.builder.destroy {
+            if self.changed.is_empty() {
+                return Err(format!(
+                    "The Commit for {} changes nothing",
+                    self.resource.get_subject()
+                )
+                .into());
+            }
+            self.resource.check_required_props(self.store)?;
+        }
+        sign_at(self.builder, agent, crate::utils::now(), self.store)
+    }
+}
+
+/// The subjects that Commits are currently being applied to in a store, and the thread applying them.
+/// Makes sure Commits to the same subject are applied one at a time, see [Storelike::get_subject_locks].
+/// Cheap to clone, clones share the same locks.
+#[derive(Clone, Debug, Default)]
+pub struct SubjectLocks {
+    in_progress: Arc<(Mutex<HashMap<String, ThreadId>>, Condvar)>,
+}
+
+impl SubjectLocks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Waits until no other thread applies a Commit to `subject`, and holds it until the guard is dropped.
+    /// Re-entrant, so Commit handlers can save the same Resource on the same thread.
+    fn acquire(&self, subject: &str) -> SubjectGuard<'_> {
+        let (lock, released) = &*self.in_progress;
+        let this_thread = std::thread::current().id();
+        let mut in_progress = lock.lock().unwrap();
+        loop {
+            match in_progress.get(subject) {
+                None => break,
+                Some(thread) if *thread == this_thread => {
+                    return SubjectGuard {
+                        locks: self,
+                        subject: None,
+                    };
+                }
+                Some(_) => in_progress = released.wait(in_progress).unwrap(),
+            }
+        }
+        in_progress.insert(subject.to_string(), this_thread);
+        SubjectGuard {
+            locks: self,
+            subject: Some(subject.to_string()),
+        }
+    }
+}
+
+/// Releases a subject of [SubjectLocks] when dropped.
+struct SubjectGuard<'a> {
+    locks: &'a SubjectLocks,
+    /// None if the thread already held the subject
+    subject: Option<String>,
+}
+
+impl Drop for SubjectGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(subject) = &self.subject {
+            let (lock, released) = &*self.locks.in_progress;
+            lock.lock().unwrap().remove(subject);
+            released.notify_all();
+        }
+    }
 }
 
 /// Signs a CommitBuilder at a specific unix timestamp.
@@ -624,11 +1377,18 @@ fn sign_at(
         set: Some(commitbuilder.set),
         remove: Some(commitbuilder.remove.into_iter().collect()),
         destroy: Some(commitbuilder.destroy),
+        purge: Some(commitbuilder.purge),
+        force: Some(commitbuilder.force),
         created_at: sign_date,
         previous_commit: commitbuilder.previous_commit,
         signature: None,
         push: Some(commitbuilder.push),
+        pull: Some(commitbuilder.pull),
+        patch: Some(commitbuilder.patch),
+        increment: Some(commitbuilder.increment),
+        push_unique: Some(commitbuilder.push_unique),
         url: None,
+        client_id: commitbuilder.client_id,
     };
     let stringified = commit
         .serialize_deterministically_json_ad(store)
@@ -690,12 +1450,42 @@ mod test {
             validate_rights: false,
             validate_for_agent: None,
             update_index: true,
+            validate_relative_urls: false,
         };
     }
 
     use super::*;
     use crate::{agents::Agent, Storelike};
 
+    #[test]
+    fn subject_locks_are_per_store() {
+        use std::time::Duration;
+
+        let subject = "https://localhost/locked";
+        let first = SubjectLocks::new();
+        let second = SubjectLocks::new();
+        let held = first.acquire(subject);
+        // Re-entrant on the same thread
+        drop(first.acquire(subject));
+        std::thread::scope(|scope| {
+            // Another store does not wait
+            scope
+                .spawn(|| drop(second.acquire(subject)))
+                .join()
+                .unwrap();
+            // The same store waits until the subject is released
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let first = &first;
+            scope.spawn(move || {
+                let _guard = first.acquire(subject);
+                sender.send(()).unwrap();
+            });
+            assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+            drop(held);
+            receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        });
+    }
+
     #[test]
     fn agent_and_commit() {
         let store = crate::Store::init().unwrap();
@@ -728,6 +1518,105 @@ mod test {
         );
     }
 
+    #[test]
+    fn resolve_relative_references() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let agent = store.create_agent(None).unwrap();
+        let subject = "https://localhost/docs/guide";
+        let mut commitbuilder = CommitBuilder::new(subject.into());
+        commitbuilder.set(
+            urls::DESTINATION.into(),
+            Value::AtomicUrl("./attachment-1".into()),
+        );
+        commitbuilder.set(
+            urls::SUBRESOURCES.into(),
+            Value::ResourceArray(vec![
+                SubResource::Subject("#section-2".into()),
+                SubResource::Subject("https://example.com/other".into()),
+            ]),
+        );
+        let commit = commitbuilder
+            .sign(&agent, &store, &Resource::new(subject.into()))
+            .unwrap();
+        commit.apply_opts(&store, &OPTS).unwrap();
+
+        let resource = store.get_resource(subject).unwrap();
+        assert_eq!(
+            resource.get(urls::DESTINATION).unwrap().to_string(),
+            "https://localhost/docs/attachment-1"
+        );
+        assert_eq!(
+            resource
+                .get(urls::SUBRESOURCES)
+                .unwrap()
+                .to_subjects(None)
+                .unwrap(),
+            vec![
+                "https://localhost/docs/guide#section-2",
+                "https://example.com/other"
+            ]
+        );
+
+        // The export only contains absolute URLs, and can be imported again
+        let exported: Vec<serde_json::Value> =
+            serde_json::from_str(&store.export(false).unwrap()).unwrap();
+        let exported = exported
+            .into_iter()
+            .find(|item| item["@id"] == subject)
+            .unwrap();
+        assert_eq!(
+            exported[urls::DESTINATION],
+            "https://localhost/docs/attachment-1"
+        );
+        assert_eq!(
+            exported[urls::SUBRESOURCES][0],
+            "https://localhost/docs/guide#section-2"
+        );
+        let imported = crate::Store::init().unwrap();
+        imported.populate().unwrap();
+        imported
+            .import(&exported.to_string(), &crate::parse::ParseOpts::default())
+            .unwrap();
+        assert_eq!(
+            imported
+                .get_resource(subject)
+                .unwrap()
+                .get(urls::DESTINATION)
+                .unwrap()
+                .to_string(),
+            "https://localhost/docs/attachment-1"
+        );
+
+        // Network-path references can point to other servers, which strict validation refuses
+        let mut commitbuilder = CommitBuilder::new(subject.into());
+        commitbuilder.set(
+            urls::DESTINATION.into(),
+            Value::AtomicUrl("//example.com/elsewhere".into()),
+        );
+        let commit = commitbuilder.sign(&agent, &store, &resource).unwrap();
+        let strict = CommitOpts {
+            validate_relative_urls: true,
+            validate_previous_commit: false,
+            ..OPTS.clone()
+        };
+        commit.apply_opts(&store, &strict).unwrap_err();
+        let lenient = CommitOpts {
+            validate_relative_urls: false,
+            ..strict
+        };
+        commit.apply_opts(&store, &lenient).unwrap();
+        assert_eq!(
+            store
+                .get_resource(subject)
+                .unwrap()
+                .get(urls::DESTINATION)
+                .unwrap()
+                .to_string(),
+            "https://example.com/elsewhere"
+        );
+    }
+
     #[test]
     fn serialize_commit() {
         let store = crate::Store::init().unwrap();
@@ -745,11 +1634,18 @@ mod test {
             signer: String::from("https://localhost/author"),
             set: Some(set),
             push: None,
+            pull: None,
+            patch: None,
+            increment: None,
+            push_unique: None,
             remove: Some(remove),
             previous_commit: None,
             destroy: Some(destroy),
+            purge: None,
+            force: None,
             signature: None,
             url: None,
+            client_id: Some("not-signed".into()),
         };
         let serialized = commit.serialize_deterministically_json_ad(&store).unwrap();
         let should_be = "{\"https://atomicdata.dev/properties/createdAt\":1603638837,\"https://atomicdata.dev/properties/isA\":[\"https://atomicdata.dev/classes/Commit\"],\"https://atomicdata.dev/properties/remove\":[\"https://atomicdata.dev/properties/isA\"],\"https://atomicdata.dev/properties/set\":{\"https://atomicdata.dev/properties/description\":\"Some description\",\"https://atomicdata.dev/properties/shortname\":\"shortname\"},\"https://atomicdata.dev/properties/signer\":\"https://localhost/author\",\"https://atomicdata.dev/properties/subject\":\"https://localhost/test\"}";
@@ -819,4 +1715,357 @@ mod test {
             commit.apply_opts(&store, &OPTS).unwrap();
         }
     }
+
+    #[test]
+    fn client_id_is_not_signed_and_limited() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let agent = store.create_agent(Some("test_actor")).unwrap();
+        let subject = "https://localhost/client_id";
+        let mut commitbuilder = crate::commit::CommitBuilder::new(subject.into());
+        commitbuilder.client_id("ignored".into());
+        let commit = commitbuilder
+            .sign(&agent, &store, &Resource::new(subject.into()))
+            .unwrap();
+        assert_eq!(commit.client_id.as_deref(), Some("ignored"));
+        let mut resource = commit.into_resource(&store).unwrap();
+        assert!(resource.get(urls::CLIENT_ID).is_err());
+
+        // Set after signing, the signature stays valid
+        resource.set_propval_unsafe(urls::CLIENT_ID.into(), Value::String("tmp-1".into()));
+        let parsed = Commit::from_resource(resource.clone()).unwrap();
+        assert_eq!(parsed.client_id.as_deref(), Some("tmp-1"));
+        let response = parsed.apply_opts(&store, &OPTS).unwrap();
+        assert!(response.commit_resource.get(urls::CLIENT_ID).is_err());
+        assert_eq!(
+            response
+                .echoed_commit_resource()
+                .get(urls::CLIENT_ID)
+                .unwrap()
+                .to_string(),
+            "tmp-1"
+        );
+
+        for invalid in ["", "has space", &"x".repeat(MAX_CLIENT_ID_LENGTH + 1)] {
+            resource.set_propval_unsafe(urls::CLIENT_ID.into(), Value::String(invalid.into()));
+            Commit::from_resource(resource.clone()).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn push_unique_and_pull() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let agent = store.create_agent(Some("test_actor")).unwrap();
+        store.set_default_agent(agent.clone());
+        let subject = "https://localhost/arrays";
+        let mut resource = Resource::new(subject.into());
+        resource
+            .set_propval(
+                urls::CHILDREN.into(),
+                vec!["https://localhost/a", "https://localhost/b"].into(),
+                &store,
+            )
+            .unwrap();
+        resource.save_locally(&store).unwrap();
+
+        let mut commitbuilder = CommitBuilder::new(subject.into());
+        commitbuilder
+            .push_propval(urls::CHILDREN, "https://localhost/a".into())
+            .unwrap();
+        commitbuilder
+            .push_propval(urls::CHILDREN, "https://localhost/c".into())
+            .unwrap();
+        commitbuilder
+            .pull_propval(urls::CHILDREN, "https://localhost/b".into())
+            .unwrap();
+        commitbuilder.push_unique(true);
+        let resource = store.get_resource(subject).unwrap();
+        let commit = commitbuilder.sign(&agent, &store, &resource).unwrap();
+        assert!(commit.only_relative_changes());
+        commit.apply_opts(&store, &OPTS).unwrap();
+
+        let children = store
+            .get_resource(subject)
+            .unwrap()
+            .get(urls::CHILDREN)
+            .unwrap()
+            .to_subjects(None)
+            .unwrap();
+        assert_eq!(children, vec!["https://localhost/a", "https://localhost/c"]);
+    }
+
+    #[test]
+    fn concurrent_pushes_both_survive() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let agent = store.create_agent(Some("test_actor")).unwrap();
+        store.set_default_agent(agent.clone());
+        let subject = "https://localhost/concurrent_pushes";
+        let mut resource = Resource::new(subject.into());
+        resource
+            .set_propval_string(urls::DESCRIPTION.into(), "Pushed to", &store)
+            .unwrap();
+        resource.save_locally(&store).unwrap();
+
+        let count = 8;
+        let handles: Vec<_> = (0..count)
+            .map(|i| {
+                let store = store.clone();
+                let agent = agent.clone();
+                std::thread::spawn(move || {
+                    // Every thread creates its commit based on the same lastCommit
+                    let resource = store.get_resource(subject).unwrap();
+                    let mut commitbuilder = CommitBuilder::new(subject.into());
+                    commitbuilder
+                        .push_propval(urls::CHILDREN, format!("https://localhost/{i}").into())
+                        .unwrap();
+                    let commit = commitbuilder.sign(&agent, &store, &resource).unwrap();
+                    commit.apply_opts(&store, &OPTS).unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let children = store
+            .get_resource(subject)
+            .unwrap()
+            .get(urls::CHILDREN)
+            .unwrap()
+            .to_subjects(None)
+            .unwrap();
+        assert_eq!(children.len(), count, "no push should be lost");
+    }
+
+    #[test]
+    fn concurrent_increments_sum_exactly() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let agent = store.create_agent(Some("test_actor")).unwrap();
+        store.set_default_agent(agent.clone());
+        let counter = "https://localhost/counter";
+        let property = crate::schema::Property {
+            class_type: None,
+            data_type: DataType::Integer,
+            shortname: "counter".into(),
+            subject: counter.into(),
+            description: "A counter".into(),
+            allows_only: None,
+            keep_whitespace: false,
+            minimum: Some(0.0),
+            maximum: Some(1000.0),
+        };
+        store.add_resource(&property.to_resource()).unwrap();
+        let subject = "https://localhost/concurrent_increments";
+        let mut resource = Resource::new(subject.into());
+        resource
+            .set_propval(counter.into(), Value::Integer(10), &store)
+            .unwrap();
+        resource.save_locally(&store).unwrap();
+        let increment = |prop: &str, delta: Value| {
+            let resource = store.get_resource(subject).unwrap();
+            let mut commitbuilder = CommitBuilder::new(subject.into());
+            commitbuilder.increment(prop, delta)?;
+            commitbuilder
+                .sign(&agent, &store, &resource)?
+                .apply_opts(&store, &OPTS)
+        };
+
+        let count = 8;
+        let per_thread = 5;
+        let handles: Vec<_> = (0..count)
+            .map(|i| {
+                let store = store.clone();
+                let agent = agent.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..per_thread {
+                        // Every thread creates its commits based on a possibly outdated lastCommit
+                        let resource = store.get_resource(subject).unwrap();
+                        let mut commitbuilder = CommitBuilder::new(subject.into());
+                        commitbuilder
+                            .increment(counter, Value::Integer(i + 1))
+                            .unwrap();
+                        let commit = commitbuilder.sign(&agent, &store, &resource).unwrap();
+                        commit.apply_opts(&store, &OPTS).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let value = || {
+            store
+                .get_resource(subject)
+                .unwrap()
+                .get(counter)
+                .unwrap()
+                .to_int()
+                .unwrap()
+        };
+        // 1 + 2 + ... + count, for every round
+        let expected = 10 + per_thread * count * (count + 1) / 2;
+        assert_eq!(value(), expected, "no increment should be lost");
+
+        // The Commit records the delta, not the resulting value
+        let response = increment(counter, Value::Integer(-expected)).unwrap();
+        assert_eq!(value(), 0);
+        let recorded = response
+            .commit_resource
+            .get(urls::INCREMENT)
+            .unwrap()
+            .to_nested()
+            .unwrap()
+            .get(counter)
+            .unwrap()
+            .to_int()
+            .unwrap();
+        assert_eq!(recorded, -expected);
+
+        assert!(increment(counter, Value::Integer(-1)).is_err());
+        assert!(increment(counter, Value::Integer(1001)).is_err());
+        assert!(increment(counter, Value::Float(0.5)).is_err());
+        assert!(increment(urls::DESCRIPTION, Value::Integer(1)).is_err());
+        assert_eq!(value(), 0);
+    }
+
+    #[test]
+    #[cfg(feature = "db")]
+    fn move_requires_write_on_both_parents() {
+        let store = crate::Db::init_temp("move_requires_write_on_both_parents").unwrap();
+        let agent = store.create_agent(Some("mover")).unwrap();
+        let create = |parent: &str, writers: Vec<String>| {
+            let mut resource = Resource::new_generate_subject(&store);
+            resource
+                .set_propval(urls::PARENT.into(), Value::AtomicUrl(parent.into()), &store)
+                .unwrap();
+            if !writers.is_empty() {
+                resource
+                    .set_propval(urls::WRITE.into(), writers.into(), &store)
+                    .unwrap();
+            }
+            resource.save_locally(&store).unwrap();
+            resource.get_subject().clone()
+        };
+        let move_to = |subject: &str, parent: &str| {
+            let resource = store.get_resource(subject).unwrap();
+            let mut commitbuilder = CommitBuilder::new(subject.into());
+            commitbuilder.set(urls::PARENT.into(), Value::AtomicUrl(parent.into()));
+            let opts = CommitOpts {
+                validate_schema: false,
+                validate_signature: true,
+                validate_timestamp: true,
+                validate_rights: true,
+                validate_previous_commit: false,
+                validate_for_agent: None,
+                update_index: true,
+                validate_relative_urls: false,
+            };
+            commitbuilder
+                .sign(&agent, &store, &resource)
+                .unwrap()
+                .apply_opts(&store, &opts)
+        };
+        let drive = store.get_server_url().to_string();
+        let own = create(&drive, vec![agent.subject.clone()]);
+        let other_own = create(&drive, vec![agent.subject.clone()]);
+        let foreign = create(&drive, vec![]);
+        let item = create(&own, vec![]);
+        let shared = create(&foreign, vec![agent.subject.clone()]);
+
+        let err = move_to(&item, &foreign).unwrap_err();
+        assert!(err.message.contains("into"), "{}", err);
+        let err = move_to(&shared, &own).unwrap_err();
+        assert!(err.message.contains("out of"), "{}", err);
+        let err = move_to(&own, &item).unwrap_err();
+        assert!(err.message.contains("own parent"), "{}", err);
+
+        move_to(&item, &other_own).unwrap();
+        assert_eq!(
+            store
+                .get_resource(&item)
+                .unwrap()
+                .get(urls::PARENT)
+                .unwrap()
+                .to_string(),
+            other_own
+        );
+    }
+
+    #[test]
+    fn checked_builder() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let agent = store.create_agent(None).unwrap();
+        let subject = &format!("{}/classes/Article", store.get_server_url());
+
+        let mut create = CommitBuilder::checked(subject.into(), &store).unwrap();
+        create
+            .set(urls::IS_A, Value::ResourceArray(vec![urls::CLASS.into()]))
+            .unwrap();
+        // Wrong datatypes and unknown shortnames fail right away
+        create.set(urls::SHORTNAME, Value::Integer(1)).unwrap_err();
+        create.set_by_shortname("colour", "red").unwrap_err();
+        create.set_by_shortname("shortname", "article").unwrap();
+        // The description is required
+        let err = create.sign(&agent).unwrap_err();
+        assert!(err.message.contains(urls::DESCRIPTION), "{}", err);
+
+        let mut create = CommitBuilder::checked(subject.into(), &store).unwrap();
+        create
+            .set(urls::IS_A, Value::ResourceArray(vec![urls::CLASS.into()]))
+            .unwrap()
+            .set_by_shortname("shortname", "article")
+            .unwrap()
+            .set_by_shortname("description", "A written text")
+            .unwrap();
+        create
+            .sign(&agent)
+            .unwrap()
+            .apply_opts(&store, &OPTS)
+            .unwrap();
+
+        let mut update = CommitBuilder::checked(subject.into(), &store).unwrap();
+        update.push(urls::RECOMMENDS, urls::NAME.into()).unwrap();
+        update.push(urls::SHORTNAME, urls::NAME.into()).unwrap_err();
+        update
+            .sign(&agent)
+            .unwrap()
+            .apply_opts(&store, &OPTS)
+            .unwrap();
+        let class = store.get_class(subject).unwrap();
+        assert_eq!(class.recommends, vec![urls::NAME.to_string()]);
+
+        // Contradicting calls fail when signing
+        let mut conflict = CommitBuilder::checked(subject.into(), &store).unwrap();
+        conflict.remove(urls::RECOMMENDS).unwrap();
+        conflict
+            .set(urls::RECOMMENDS, Value::ResourceArray(vec![]))
+            .unwrap();
+        let err = conflict.sign(&agent).unwrap_err();
+        assert!(err.message.contains("both set and removed"), "{}", err);
+        let mut conflict = CommitBuilder::checked(subject.into(), &store).unwrap();
+        conflict.destroy().unwrap();
+        conflict.set_by_shortname("shortname", "post").unwrap();
+        conflict.sign(&agent).unwrap_err();
+        // Removing a required Property leaves an invalid Class
+        let mut invalid = CommitBuilder::checked(subject.into(), &store).unwrap();
+        invalid.remove(urls::SHORTNAME).unwrap();
+        invalid.sign(&agent).unwrap_err();
+
+        let mut delete = CommitBuilder::checked(subject.into(), &store).unwrap();
+        delete.destroy().unwrap();
+        delete
+            .sign(&agent)
+            .unwrap()
+            .apply_opts(&store, &OPTS)
+            .unwrap();
+        store.get_resource(subject).unwrap_err();
+        CommitBuilder::checked(subject.into(), &store)
+            .unwrap()
+            .destroy()
+            .unwrap_err();
+    }
 }
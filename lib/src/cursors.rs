@@ -0,0 +1,133 @@
+//! Cursors for paging through Collections and query results, see [crate::collections] and [crate::db::StructuredQuery].
+//! A cursor holds the sort value and the subject of the last member of a page, and the next page starts strictly after that position.
+//! Unlike offsets, this does not skip or repeat members when Commits add or remove members between two requests.
+//! Cursors are opaque to clients: they are signed using the private key of the default Agent, and only work for the query that created them.
+
+use base64::{engine::general_purpose, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::AtomicResult, AtomicError, Storelike};
+
+/// The last member of a page, as encoded in a cursor.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cursor {
+    /// Hash of the query that created the cursor
+    #[serde(rename = "q")]
+    query: String,
+    /// The [crate::Value::to_sortable_string] of the sorted Property, if the member had one
+    #[serde(rename = "v")]
+    pub value: Option<String>,
+    #[serde(rename = "s")]
+    pub subject: String,
+}
+
+impl Cursor {
+    /// `query` identifies the query, including its filters and sorting, but not its page size.
+    pub fn new(query: &str, value: Option<String>, subject: String) -> Self {
+        Cursor {
+            query: crate::patch::checksum(query),
+            value,
+            subject,
+        }
+    }
+
+    /// Serializes and signs the cursor.
+    pub fn encode(&self, store: &impl Storelike) -> AtomicResult<String> {
+        let payload = general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(self)?);
+        let tag = ring::hmac::sign(&signing_key(store)?, payload.as_bytes());
+        Ok(format!(
+            "{}.{}",
+            payload,
+            general_purpose::URL_SAFE_NO_PAD.encode(tag.as_ref())
+        ))
+    }
+
+    /// Checks the signature of a cursor, and whether it was created by the same `query`.
+    pub fn decode(store: &impl Storelike, query: &str, cursor: &str) -> AtomicResult<Cursor> {
+        let invalid = || {
+            AtomicError::from("Invalid cursor. Use the cursor of the previous page of the same query, or start again from the first page.")
+        };
+        let (payload, tag) = cursor.split_once('.').ok_or_else(invalid)?;
+        let tag = general_purpose::URL_SAFE_NO_PAD
+            .decode(tag)
+            .map_err(|_| invalid())?;
+        ring::hmac::verify(&signing_key(store)?, payload.as_bytes(), &tag)
+            .map_err(|_| invalid())?;
+        let bytes = general_purpose::URL_SAFE_NO_PAD
+            .decode(payload)
+            .map_err(|_| invalid())?;
+        let cursor: Cursor = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+        if cursor.query != crate::patch::checksum(query) {
+            return Err(invalid());
+        }
+        Ok(cursor)
+    }
+
+    /// Fails with `Gone` if the last member still exists, but its value of `sort_by` changed.
+    /// Its old position is then no longer a meaningful place to continue from.
+    /// Members that were deleted in the meantime are fine.
+    pub fn check_boundary(
+        &self,
+        store: &impl Storelike,
+        sort_by: Option<&str>,
+    ) -> AtomicResult<()> {
+        let (Some(sort_by), Ok(resource)) = (sort_by, store.get_resource(&self.subject)) else {
+            return Ok(());
+        };
+        let current = resource.get(sort_by).ok().map(|v| v.to_sortable_string());
+        if current != self.value {
+            return Err(AtomicError::gone(format!(
+                "The cursor has expired, because {} of {} changed. Start again from the first page.",
+                sort_by, self.subject
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn signing_key(store: &impl Storelike) -> AtomicResult<ring::hmac::Key> {
+    let private_key = store
+        .get_default_agent()?
+        .private_key
+        .ok_or("Cursors are signed by the default Agent, which has no private key")?;
+    let key = format!("cursor:{}", private_key);
+    Ok(ring::hmac::Key::new(
+        ring::hmac::HMAC_SHA256,
+        key.as_bytes(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Resource, Value};
+
+    #[test]
+    fn cursors_are_signed_and_bound_to_their_query() {
+        let store = crate::test_utils::init_store();
+
+        let cursor = Cursor::new("query-a", Some("b".into()), "https://example.com/b".into());
+        let encoded = cursor.encode(&store).unwrap();
+        assert_eq!(Cursor::decode(&store, "query-a", &encoded).unwrap(), cursor);
+        assert!(Cursor::decode(&store, "query-b", &encoded).is_err());
+
+        let (payload, tag) = encoded.split_once('.').unwrap();
+        let forged = Cursor::new("query-a", Some("z".into()), "https://example.com/z".into());
+        let forged_payload =
+            general_purpose::URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap());
+        assert_ne!(payload, forged_payload);
+        assert!(Cursor::decode(&store, "query-a", &format!("{}.{}", forged_payload, tag)).is_err());
+
+        // The boundary may be deleted, but not moved
+        assert!(cursor
+            .check_boundary(&store, Some(crate::urls::NAME))
+            .is_ok());
+        let mut resource = Resource::new(cursor.subject.clone());
+        resource.set_propval_unsafe(crate::urls::NAME.into(), Value::String("c".into()));
+        store.add_resource(&resource).unwrap();
+        let err = cursor
+            .check_boundary(&store, Some(crate::urls::NAME))
+            .unwrap_err();
+        assert!(matches!(err.error_type, crate::AtomicErrorType::Gone));
+    }
+}
@@ -56,6 +56,44 @@ impl std::str::FromStr for DataType {
     }
 }
 
+impl DataType {
+    /// The last path segment of the Datatype URL, e.g. `integer`.
+    pub fn shortname(&self) -> &str {
+        match self {
+            DataType::AtomicUrl => "atomicURL",
+            DataType::Boolean => "boolean",
+            DataType::Date => "date",
+            DataType::Integer => "integer",
+            DataType::Float => "float",
+            DataType::Markdown => "markdown",
+            DataType::ResourceArray => "resourceArray",
+            DataType::Slug => "slug",
+            DataType::String => "string",
+            DataType::Timestamp => "timestamp",
+            DataType::Unsupported(url) => url.rsplit('/').next().unwrap_or(url),
+        }
+    }
+
+    /// A valid value for this Datatype, shown in error messages.
+    pub fn example_value(&self) -> &'static str {
+        match self {
+            DataType::AtomicUrl => "https://atomicdata.dev/classes/Agent",
+            DataType::Boolean => "true",
+            DataType::Date => "2024-01-31",
+            DataType::Integer => "42",
+            DataType::Float => "3.14",
+            DataType::Markdown => "Some **bold** text",
+            DataType::ResourceArray => {
+                r#"["https://atomicdata.dev/classes/Agent", "https://atomicdata.dev/classes/Class"]"#
+            }
+            DataType::Slug => "my-slug",
+            DataType::String => "Some text",
+            DataType::Timestamp => "1706659200000",
+            DataType::Unsupported(_) => "",
+        }
+    }
+}
+
 impl fmt::Display for DataType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -1,16 +1,23 @@
 //! Persistent, ACID compliant, threadsafe to-disk store.
 //! Powered by Sled - an embedded database.
 
+mod commit_log;
 mod migrations;
 mod prop_val_sub_index;
 mod query_index;
+mod schema_usage;
+mod snapshot;
+mod structured_query;
 #[cfg(test)]
 pub mod test;
 mod val_prop_sub_index;
 
 use std::{
-    collections::{HashMap, HashSet},
-    sync::{Arc, Mutex},
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
     vec,
 };
 
@@ -19,20 +26,25 @@ use tracing::{info, instrument};
 use crate::{
     agents::ForAgent,
     atoms::IndexAtom,
-    commit::CommitResponse,
+    commit::{CommitResponse, SubjectLocks},
     db::{
         query_index::{requires_query_index, NO_VALUE},
         val_prop_sub_index::find_in_val_prop_sub_index,
     },
     endpoints::{default_endpoints, Endpoint, HandleGetContext},
     errors::{AtomicError, AtomicResult},
+    locks::LockRegistry,
+    metrics::{MetricsReport, Operation, StoreMetrics},
+    plugins::activity::ActivityCache,
     resources::PropVals,
     storelike::{Query, QueryResult, Storelike},
+    subjects::SubjectReservations,
     values::SortableValue,
     Atom, Resource,
 };
 
 use self::{
+    commit_log::{last_sequence, CommitSequence},
     migrations::migrate_maybe,
     prop_val_sub_index::{
         add_atom_to_prop_val_sub_index, find_in_prop_val_sub_index,
@@ -42,11 +54,20 @@ use self::{
         check_if_atom_matches_watched_query_filters, query_sorted_indexed, should_include_resource,
         update_indexed_member, IndexIterator, QueryFilter,
     },
+    schema_usage::SchemaUsageCache,
+    snapshot::{record_preimage, SnapshotRegistry},
     val_prop_sub_index::{add_atom_to_reference_index, remove_atom_from_reference_index},
 };
 
-// A function called by the Store when a Commit is accepted
-type HandleCommit = Box<dyn Fn(&CommitResponse) + Send + Sync>;
+pub use self::schema_usage::{ClassUsage, PropertyUsage, SchemaUsageReport};
+pub use self::snapshot::DbSnapshot;
+pub use self::structured_query::{Condition, Operator, StructuredQuery, StructuredQueryResult};
+
+/// Values longer than this are not indexed, unless changed using [Db::set_max_indexed_value_size].
+pub const DEFAULT_MAX_INDEXED_VALUE_SIZE: usize = 1024 * 1024;
+
+// A function called by the Store when a Commit is accepted, with its place in the commit log
+type HandleCommit = Box<dyn Fn(&CommitResponse, Option<u64>) + Send + Sync>;
 
 /// Inside the reference_index, each value is mapped to this type.
 /// The String on the left represents a Property URL, and the second one is the set of subjects.
@@ -79,12 +100,38 @@ pub struct Db {
     query_index: sled::Tree,
     /// A list of all the Collections currently being used. Is used to update `query_index`.
     watched_queries: sled::Tree,
+    /// Commit subjects by sequence number, see [commit_log]
+    commit_log: sled::Tree,
+    /// Sequence numbers by Commit subject
+    commit_sequences: sled::Tree,
+    commit_sequence: CommitSequence,
     /// The address where the db will be hosted, e.g. http://localhost/
     server_url: String,
     /// Endpoints are checked whenever a resource is requested. They calculate (some properties of) the resource and return it.
     endpoints: Vec<Endpoint>,
     /// Function called whenever a Commit is applied.
     on_commit: Option<Arc<HandleCommit>>,
+    /// The [DbSnapshot]s that are currently alive. Written Resources store their previous value in these.
+    snapshots: SnapshotRegistry,
+    /// Advisory locks, see [crate::locks].
+    locks: LockRegistry,
+    /// Cached Drive activity summaries, see [crate::plugins::activity].
+    activity_cache: ActivityCache,
+    /// Cached report of how the schema is used, see [Db::schema_usage].
+    schema_usage_cache: SchemaUsageCache,
+    /// Generated subjects that may not be saved yet, see [crate::subjects].
+    subject_reservations: SubjectReservations,
+    /// Subjects that Commits are being applied to, see [SubjectLocks].
+    subject_locks: SubjectLocks,
+    /// Counters and timers of store operations, see [Db::metrics].
+    metrics: StoreMetrics,
+    /// Values that are longer than this (in bytes) are not indexed, see [Db::set_max_indexed_value_size].
+    max_indexed_value_size: Arc<AtomicUsize>,
+    /// Parent chains that are longer than this are treated as broken, see [Db::set_max_hierarchy_depth].
+    max_hierarchy_depth: Arc<AtomicUsize>,
+    /// Checks requests to other servers, see [Db::set_outbound].
+    #[cfg(feature = "client")]
+    outbound: Arc<Mutex<crate::outbound::OutboundHttp>>,
 }
 
 impl Db {
@@ -98,6 +145,9 @@ impl Db {
         let query_index = db.open_tree("members_index")?;
         let prop_val_sub_index = db.open_tree("prop_val_sub_index")?;
         let watched_queries = db.open_tree("watched_queries")?;
+        let commit_log = db.open_tree("commit_log")?;
+        let commit_sequences = db.open_tree("commit_sequences")?;
+        let commit_sequence = Arc::new(Mutex::new(last_sequence(&commit_log)?));
         let store = Db {
             db,
             default_agent: Arc::new(Mutex::new(None)),
@@ -107,8 +157,22 @@ impl Db {
             prop_val_sub_index,
             server_url,
             watched_queries,
+            commit_log,
+            commit_sequences,
+            commit_sequence,
             endpoints: default_endpoints(),
             on_commit: None,
+            snapshots: Arc::new(Mutex::new(Vec::new())),
+            locks: LockRegistry::new(),
+            activity_cache: ActivityCache::default(),
+            schema_usage_cache: SchemaUsageCache::default(),
+            subject_reservations: SubjectReservations::new(),
+            subject_locks: SubjectLocks::new(),
+            metrics: StoreMetrics::new(),
+            max_indexed_value_size: Arc::new(AtomicUsize::new(DEFAULT_MAX_INDEXED_VALUE_SIZE)),
+            max_hierarchy_depth: Arc::new(AtomicUsize::new(crate::hierarchy::DEFAULT_MAX_DEPTH)),
+            #[cfg(feature = "client")]
+            outbound: Arc::new(Mutex::new(crate::outbound::OutboundHttp::default())),
         };
         migrate_maybe(&store).map(|e| format!("Error during migration of database: {:?}", e))?;
         crate::populate::populate_base_models(&store)
@@ -131,13 +195,49 @@ impl Db {
         Ok(store)
     }
 
+    /// Creates a child of `parent` with the `propvals` using a Commit, and returns it. Useful for testing.
+    /// Skips the check for required Properties, but the Classes are still fetched, so use Classes that are in the store.
+    #[cfg(test)]
+    pub(crate) fn create_test_resource(
+        &self,
+        parent: &str,
+        propvals: Vec<(&str, crate::Value)>,
+    ) -> Resource {
+        let resource = Resource::new_generate_subject(self);
+        let mut builder = crate::commit::CommitBuilder::new(resource.get_subject().clone());
+        builder.set(
+            crate::urls::PARENT.into(),
+            crate::Value::AtomicUrl(parent.into()),
+        );
+        for (prop, value) in propvals {
+            builder.set(prop.into(), value);
+        }
+        let agent = self.get_default_agent().unwrap();
+        let commit = builder.sign(&agent, self, &resource).unwrap();
+        let opts = crate::commit::CommitOpts {
+            validate_schema: false,
+            validate_signature: false,
+            validate_timestamp: false,
+            validate_rights: false,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        commit
+            .apply_opts(self, &opts)
+            .unwrap()
+            .resource_new
+            .unwrap()
+    }
+
     #[instrument(skip(self))]
     fn all_index_atoms(&self, include_external: bool) -> IndexIterator {
         Box::new(
             self.all_resources(include_external)
                 .flat_map(|resource| {
                     let index_atoms: Vec<IndexAtom> = resource
-                        .to_atoms()
+                        .to_index_atoms()
                         .iter()
                         .flat_map(|atom| atom.to_indexable_atoms())
                         .collect();
@@ -148,19 +248,200 @@ impl Db {
     }
 
     /// Internal method for fetching Resource data.
+    #[track_caller]
     #[instrument(skip(self))]
     fn set_propvals(&self, subject: &str, propvals: &PropVals) -> AtomicResult<()> {
-        let resource_bin = bincode::serialize(propvals)?;
-        self.resources.insert(subject.as_bytes(), resource_bin)?;
+        self.metrics.time(Operation::Write, subject, || {
+            let resource_bin = bincode::serialize(propvals)?;
+            let mut snapshots = self.snapshots.lock().unwrap();
+            record_preimage(&mut snapshots, &self.resources, subject.as_bytes())?;
+            self.resources.insert(subject.as_bytes(), resource_bin)?;
+            Ok(())
+        })
+    }
+
+    /// Counts how often store operations (reads, writes, index lookups, queries, Commits) happened, and how long they took.
+    /// Also lists the most recent operations that exceeded the threshold set by [Db::set_slow_threshold].
+    pub fn metrics(&self) -> MetricsReport {
+        #[allow(unused_mut)]
+        let mut report = self.metrics.report();
+        #[cfg(feature = "client")]
+        {
+            report.outbound = Some(self.outbound.lock().unwrap().report());
+        }
+        report
+    }
+
+    /// Replaces the guard for requests to other servers, e.g. to apply the allow and deny lists of the server config.
+    /// Counters of refused requests start at zero again.
+    #[cfg(feature = "client")]
+    pub fn set_outbound(&self, outbound: crate::outbound::OutboundHttp) {
+        *self.outbound.lock().unwrap() = outbound;
+    }
+
+    /// Logs every store operation that takes at least `threshold`, with its subject and call site. `None` disables this.
+    pub fn set_slow_threshold(&self, threshold: Option<std::time::Duration>) {
+        self.metrics.set_slow_threshold(threshold);
+    }
+
+    /// Values longer than this many bytes are left out of the indexes, so they don't slow down every index update.
+    /// Queries on their property won't find them. Changing this only affects Resources that are indexed afterwards.
+    pub fn set_max_indexed_value_size(&self, max: usize) {
+        self.max_indexed_value_size.store(max, Ordering::Relaxed);
+    }
+
+    /// Walking up the parents of a Resource fails after this many steps, so a broken hierarchy can't keep requests busy.
+    pub fn set_max_hierarchy_depth(&self, max: usize) {
+        self.max_hierarchy_depth.store(max, Ordering::Relaxed);
+    }
+
+    pub fn max_indexed_value_size(&self) -> usize {
+        self.max_indexed_value_size.load(Ordering::Relaxed)
+    }
+
+    /// Finds the Resources matching the [Query], see [Storelike::query].
+    fn query_untimed(&self, q: &Query) -> AtomicResult<QueryResult> {
+        // Values are stored normalized, so the queried value has to be normalized as well
+        if let (Some(prop), Some(value)) = (&q.property, &q.value) {
+            if let Ok(property) = self.get_property(prop) {
+                if let Some(normalized) = crate::normalize::normalize_value(value, &property) {
+                    let mut q = q.clone();
+                    q.value = Some(normalized);
+                    return self.query_untimed(&q);
+                }
+            }
+        }
+        if requires_query_index(q) {
+            return self.query_complex(q);
+        }
+        // Children are listed in the order that users gave them, see [crate::hierarchy::sort_children]
+        if q.property.as_deref() == Some(crate::urls::PARENT) && q.value.is_some() {
+            return self.query_children(q);
+        }
+
+        self.query_basic(q)
+    }
+
+    fn remove_resource_untimed(&self, subject: &str) -> AtomicResult<()> {
+        if let Ok(found) = self.get_propvals(subject) {
+            let resource = Resource::from_propvals(found, subject.to_string());
+            for (prop, val) in resource.get_propvals() {
+                let remove_atom = crate::Atom::new(subject.into(), prop.clone(), val.clone());
+                self.remove_atom_from_index(&remove_atom, &resource)?;
+            }
+            let mut snapshots = self.snapshots.lock().unwrap();
+            record_preimage(&mut snapshots, &self.resources, subject.as_bytes())?;
+            let _found = self.resources.remove(subject.as_bytes())?;
+        } else {
+            return Err(format!(
+                "Resource {} could not be deleted, because it was not found in the store.",
+                subject
+            )
+            .into());
+        }
         Ok(())
     }
 
+    /// Returns a cheap, read-only view of the Resources as they are right now.
+    /// Use this for long-running reads (exports, validation, backups) so they see a consistent state without blocking Commits.
+    /// Only Resources that are changed while the snapshot is alive are copied. Dropping the snapshot frees them.
+    pub fn snapshot(&self) -> DbSnapshot {
+        DbSnapshot::new(self)
+    }
+
+    #[cfg(feature = "rdf")]
+    /// Like [Storelike::export], but serializes to N-Quads, with the Resources of every Drive in the graph of that Drive.
+    /// See [crate::serialize::resources_to_nquads].
+    pub fn export_nquads(&self, include_external: bool) -> AtomicResult<String> {
+        let snapshot = self.snapshot();
+        let resources: Vec<Resource> = snapshot.all_resources(include_external).collect();
+        crate::serialize::resources_to_nquads(&resources, &snapshot)
+    }
+
     /// Sets a function that is called whenever a [Commit::apply] is called.
     /// This can be used to listen to events.
     pub fn set_handle_commit(&mut self, on_commit: HandleCommit) {
         self.on_commit = Some(Arc::new(on_commit));
     }
 
+    /// Cached activity summaries of Drives, cleared when Commits are applied to them.
+    pub fn get_activity_cache(&self) -> &ActivityCache {
+        &self.activity_cache
+    }
+
+    /// Counts how Properties and Classes are used in this store: how many Resources use every Property,
+    /// how many instances every Class has, which required Properties are missing, and which Properties are used without being declared.
+    /// The report is cached, and cleared when a Class or Property is changed.
+    pub fn schema_usage(&self) -> AtomicResult<SchemaUsageReport> {
+        self.schema_usage_cache.get_or_compute(self)
+    }
+
+    /// The [Endpoint]s that are active in this store.
+    pub fn get_endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+
+    /// Groups the subjects of Resources that have equal values for all `match_props`, optionally only for instances of `class`.
+    /// Strings are compared trimmed and case-folded, and arrays as sets of their members.
+    /// The values are read from the property-value index, so Resources are never fetched.
+    /// Only groups with more than one subject are returned. See [crate::plugins::duplicates] for merging them.
+    pub fn find_duplicates(
+        &self,
+        class: Option<&str>,
+        match_props: &[&str],
+    ) -> AtomicResult<Vec<Vec<String>>> {
+        if match_props.is_empty() {
+            return Err("At least one Property is needed to find duplicates".into());
+        }
+        let instances: Option<HashSet<String>> = match class {
+            Some(class) => Some(
+                find_in_prop_val_sub_index(
+                    self,
+                    crate::urls::IS_A,
+                    Some(&crate::Value::AtomicUrl(class.into())),
+                )
+                .map(|atom| atom.map(|atom| atom.subject))
+                .collect::<AtomicResult<_>>()?,
+            ),
+            None => None,
+        };
+
+        // The normalized values of every subject, in the order of `match_props`
+        let mut values: HashMap<String, Vec<BTreeSet<String>>> = HashMap::new();
+        for (i, prop) in match_props.iter().enumerate() {
+            for atom in find_in_prop_val_sub_index(self, prop, None) {
+                let atom = atom?;
+                if let Some(instances) = &instances {
+                    if !instances.contains(&atom.subject) {
+                        continue;
+                    }
+                }
+                values
+                    .entry(atom.subject)
+                    .or_insert_with(|| vec![BTreeSet::new(); match_props.len()])[i]
+                    .insert(atom.ref_value.trim().to_lowercase());
+            }
+        }
+
+        let mut groups: HashMap<Vec<BTreeSet<String>>, Vec<String>> = HashMap::new();
+        for (subject, values) in values {
+            // Resources without a value for one of the Properties are never duplicates
+            if values.iter().all(|value| !value.is_empty()) {
+                groups.entry(values).or_default().push(subject);
+            }
+        }
+        let mut groups: Vec<Vec<String>> = groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .map(|mut group| {
+                group.sort();
+                group
+            })
+            .collect();
+        groups.sort();
+        Ok(groups)
+    }
+
     /// Finds resource by Subject, return PropVals HashMap
     /// Deals with the binary API of Sled
     #[instrument(skip(self))]
@@ -293,6 +574,50 @@ impl Db {
         })
     }
 
+    /// Lists the children of a parent in the order of [crate::hierarchy::sort_children].
+    /// All children are sorted before the requested page is selected.
+    fn query_children(&self, q: &Query) -> AtomicResult<QueryResult> {
+        let mut all = q.clone();
+        all.limit = None;
+        all.offset = 0;
+        all.include_nested = false;
+        // Rights are checked below, for the requested page only
+        all.for_agent = ForAgent::Sudo;
+        let all_children = self.query_basic(&all)?;
+
+        let mut children: Vec<Resource> = all_children
+            .subjects
+            .iter()
+            .filter_map(|subject| self.get_resource(subject).ok())
+            .collect();
+        crate::hierarchy::sort_children(&mut children);
+
+        let mut subjects: Vec<String> = vec![];
+        let mut resources: Vec<Resource> = vec![];
+        let page = children
+            .into_iter()
+            .skip(q.offset)
+            .take(q.limit.unwrap_or(usize::MAX));
+        for child in page {
+            if !should_include_resource(q) {
+                subjects.push(child.get_subject().clone());
+                continue;
+            }
+            if let Ok(resource) =
+                self.get_resource_extended(child.get_subject(), true, &q.for_agent)
+            {
+                subjects.push(child.get_subject().clone());
+                resources.push(resource);
+            }
+        }
+
+        Ok(QueryResult {
+            subjects,
+            resources,
+            count: all_children.count,
+        })
+    }
+
     fn query_complex(&self, q: &Query) -> AtomicResult<QueryResult> {
         let (mut subjects, mut resources, mut total_count) = query_sorted_indexed(self, q)?;
         let q_filter: QueryFilter = q.into();
@@ -351,6 +676,18 @@ impl Storelike for Db {
 
     #[instrument(skip(self))]
     fn add_atom_to_index(&self, atom: &Atom, resource: &Resource) -> AtomicResult<()> {
+        let size = atom.value.text_len();
+        let max = self.max_indexed_value_size();
+        if size > max {
+            tracing::warn!(
+                "Not indexing {} of {}, since its value is {} bytes. The maximum is {} bytes.",
+                atom.property,
+                atom.subject,
+                size,
+                max
+            );
+            return Ok(());
+        }
         for index_atom in atom.to_indexable_atoms() {
             add_atom_to_reference_index(&index_atom, self)?;
             add_atom_to_prop_val_sub_index(&index_atom, self)?;
@@ -363,6 +700,7 @@ impl Storelike for Db {
         Ok(())
     }
 
+    #[track_caller]
     #[instrument(skip(self, resource), fields(sub = %resource.get_subject()))]
     fn add_resource_opts(
         &self,
@@ -416,6 +754,11 @@ impl Storelike for Db {
         Ok(())
     }
 
+    /// Exports from a [DbSnapshot], so Commits that are applied during the export don't end up in it halfway.
+    fn export(&self, include_external: bool) -> AtomicResult<String> {
+        self.snapshot().export(include_external)
+    }
+
     fn get_server_url(&self) -> &str {
         &self.server_url
     }
@@ -433,9 +776,12 @@ impl Storelike for Db {
         }
     }
 
+    #[track_caller]
     #[instrument(skip(self))]
     fn get_resource(&self, subject: &str) -> AtomicResult<Resource> {
-        let propvals = self.get_propvals(subject);
+        let propvals = self
+            .metrics
+            .time(Operation::Get, subject, || self.get_propvals(subject));
 
         match propvals {
             Ok(propvals) => {
@@ -483,8 +829,13 @@ impl Storelike for Db {
                         store: self,
                         for_agent,
                     };
-                    (handle)(context).map_err(|e| {
-                        format!("Error handling {} Endpoint: {}", endpoint.shortname, e)
+                    // Keeps the type of the error, so an expired cursor still becomes a 410
+                    (handle)(context).map_err(|mut e| {
+                        e.message = format!(
+                            "Error handling {} Endpoint: {}",
+                            endpoint.shortname, e.message
+                        );
+                        e
                     })?
                 } else {
                     endpoint.to_resource(self)?
@@ -501,6 +852,7 @@ impl Storelike for Db {
         let mut resource = self.get_resource(&removed_query_params)?;
 
         let _explanation = crate::hierarchy::check_read(self, &resource, for_agent)?;
+        crate::plugins::expiry::check_expiry(&resource)?;
 
         // Whether the resource has dynamic properties
         let mut has_dynamic = false;
@@ -518,6 +870,18 @@ impl Storelike for Db {
                         )?;
                     }
                 }
+                crate::urls::DYNAMIC_COLLECTION => {
+                    has_dynamic = true;
+                    if !skip_dynamic {
+                        resource =
+                            crate::plugins::dynamic_collection::construct_dynamic_collection(
+                                self,
+                                url.query_pairs(),
+                                &mut resource,
+                                for_agent,
+                            )?;
+                    }
+                }
                 crate::urls::INVITE => {
                     has_dynamic = true;
                     if !skip_dynamic {
@@ -554,6 +918,23 @@ impl Storelike for Db {
         // make sure the actual subject matches the one requested - It should not be changed in the logic above
         resource.set_subject(subject.into());
 
+        // Show who is editing the resource
+        if let Some(lock) = self.locks.get(&removed_query_params) {
+            resource.set_propval_unsafe(
+                crate::urls::LOCKED_BY.into(),
+                crate::Value::AtomicUrl(lock.agent),
+            );
+            resource.set_propval_unsafe(
+                crate::urls::LOCKED_UNTIL.into(),
+                crate::Value::Timestamp(lock.expires_at),
+            );
+        }
+
+        // Show under which version of the default ontology the resource was last written
+        if !skip_dynamic {
+            crate::schema_version::add_to_resource(self, &mut resource);
+        }
+
         // This lets clients know that the resource may have dynamic properties that are currently not included
         if has_dynamic && skip_dynamic {
             resource.set_propval(
@@ -565,22 +946,61 @@ impl Storelike for Db {
         Ok(resource)
     }
 
+    fn get_max_hierarchy_depth(&self) -> usize {
+        self.max_hierarchy_depth.load(Ordering::Relaxed)
+    }
+
+    fn get_locks(&self) -> Option<&LockRegistry> {
+        Some(&self.locks)
+    }
+
+    fn get_metrics(&self) -> Option<&StoreMetrics> {
+        Some(&self.metrics)
+    }
+
+    fn get_subject_reservations(&self) -> Option<&SubjectReservations> {
+        Some(&self.subject_reservations)
+    }
+
+    fn get_subject_locks(&self) -> Option<&SubjectLocks> {
+        Some(&self.subject_locks)
+    }
+
+    #[cfg(feature = "client")]
+    fn get_outbound(&self) -> Option<crate::outbound::OutboundHttp> {
+        Some(self.outbound.lock().unwrap().clone())
+    }
+
     fn handle_commit(&self, commit_response: &CommitResponse) {
+        self.activity_cache.invalidate(self, commit_response);
+        self.schema_usage_cache.invalidate(commit_response);
+        let commit = commit_response.commit_resource.get_subject();
+        let sequence = self
+            .log_commit(commit)
+            .map_err(|e| tracing::error!("Could not add {} to the commit log: {}", commit, e))
+            .ok();
         if let Some(fun) = &self.on_commit {
-            fun(commit_response);
+            fun(commit_response, sequence);
         }
     }
 
     /// Search the Store, returns the matching subjects.
     /// The second returned vector should be filled if query.include_resources is true.
     /// Tries `query_cache`, which you should implement yourself.
+    #[track_caller]
     #[instrument(skip(self))]
     fn query(&self, q: &Query) -> AtomicResult<QueryResult> {
-        if requires_query_index(q) {
-            return self.query_complex(q);
-        }
-
-        self.query_basic(q)
+        let location = std::panic::Location::caller();
+        let start = std::time::Instant::now();
+        let result = self.query_untimed(q);
+        self.metrics.record(Operation::Query, start, location, || {
+            format!(
+                "{} {}",
+                q.property.as_deref().unwrap_or("*"),
+                q.value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+            )
+        });
+        result
     }
 
     #[instrument(skip(self))]
@@ -649,28 +1069,22 @@ impl Storelike for Db {
         crate::populate::populate_all(self)
     }
 
+    #[track_caller]
     #[instrument(skip(self))]
     fn remove_resource(&self, subject: &str) -> AtomicResult<()> {
-        if let Ok(found) = self.get_propvals(subject) {
-            let resource = Resource::from_propvals(found, subject.to_string());
-            for (prop, val) in resource.get_propvals() {
-                let remove_atom = crate::Atom::new(subject.into(), prop.clone(), val.clone());
-                self.remove_atom_from_index(&remove_atom, &resource)?;
-            }
-            let _found = self.resources.remove(subject.as_bytes())?;
-        } else {
-            return Err(format!(
-                "Resource {} could not be deleted, because it was not found in the store.",
-                subject
-            )
-            .into());
-        }
-        Ok(())
+        self.metrics.time(Operation::Remove, subject, || {
+            self.remove_resource_untimed(subject)
+        })
     }
 
     fn set_default_agent(&self, agent: crate::agents::Agent) {
         self.default_agent.lock().unwrap().replace(agent);
     }
+
+    /// Validates a [DbSnapshot], so the report describes a single, consistent state.
+    fn validate(&self) -> crate::validate::ValidationReport {
+        self.snapshot().validate()
+    }
 }
 
 fn corrupt_db_message(subject: &str) -> String {
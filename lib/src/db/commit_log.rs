@@ -0,0 +1,119 @@
+//! The order in which Commits were applied to a [Db].
+//!
+//! Every applied Commit gets the next sequence number, without gaps, in [Db::handle_commit](crate::Storelike::handle_commit).
+//! The numbers are stored, so after a restart, listeners can continue after the last Commit they received.
+//! Commits that were applied before the log existed have no sequence number.
+
+use std::sync::{Arc, Mutex};
+
+use crate::{errors::AtomicResult, Db};
+
+/// The last assigned sequence number. Holding the lock while writing keeps the log in order.
+pub(crate) type CommitSequence = Arc<Mutex<u64>>;
+
+fn key(sequence: u64) -> [u8; 8] {
+    sequence.to_be_bytes()
+}
+
+fn sequence_from(bytes: &[u8]) -> AtomicResult<u64> {
+    let bytes: [u8; 8] = bytes
+        .try_into()
+        .map_err(|_| "Invalid sequence number in the commit log")?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+/// Reads the last sequence number from the log, to continue from there.
+pub(crate) fn last_sequence(log: &sled::Tree) -> AtomicResult<u64> {
+    match log.last()? {
+        Some((key, _commit)) => sequence_from(&key),
+        None => Ok(0),
+    }
+}
+
+impl Db {
+    /// Adds the Commit to the log, and returns its sequence number.
+    pub(crate) fn log_commit(&self, commit: &str) -> AtomicResult<u64> {
+        let mut last = self.commit_sequence.lock().unwrap();
+        let sequence = *last + 1;
+        self.commit_log.insert(key(sequence), commit.as_bytes())?;
+        self.commit_sequences
+            .insert(commit.as_bytes(), &key(sequence))?;
+        *last = sequence;
+        Ok(sequence)
+    }
+
+    /// The sequence number of the last applied Commit, or 0 if none were applied since the log exists.
+    pub fn last_commit_sequence(&self) -> u64 {
+        *self.commit_sequence.lock().unwrap()
+    }
+
+    /// The sequence number of a Commit, if it was applied since the log exists.
+    pub fn commit_sequence_of(&self, commit: &str) -> AtomicResult<Option<u64>> {
+        match self.commit_sequences.get(commit.as_bytes())? {
+            Some(bytes) => Ok(Some(sequence_from(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// At most `limit` (sequence number, Commit subject) pairs that come after `after`, in order.
+    pub fn commits_after(&self, after: u64, limit: usize) -> AtomicResult<Vec<(u64, String)>> {
+        self.commit_log
+            .range(key(after.saturating_add(1))..)
+            .take(limit)
+            .map(|entry| {
+                let (key, commit) = entry?;
+                Ok((
+                    sequence_from(&key)?,
+                    String::from_utf8(commit.to_vec())
+                        .map_err(|e| format!("Invalid Commit in the commit log: {}", e))?,
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Resource, Storelike, Value};
+
+    #[test]
+    fn sequences_continue_after_reopening() {
+        let store = Db::init_temp("commit_log_reopen").unwrap();
+        let first = store.last_commit_sequence();
+        let mut commits = Vec::new();
+        for name in ["one", "two"] {
+            let mut resource = Resource::new(format!("{}/{}", store.get_server_url(), name));
+            resource.set_propval_unsafe(
+                crate::urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+            );
+            resource.set_propval_unsafe(crate::urls::NAME.into(), Value::String(name.into()));
+            let response = resource.save_locally(&store).unwrap();
+            commits.push(response.commit_resource.get_subject().clone());
+        }
+        assert_eq!(
+            store.commit_sequence_of(&commits[1]).unwrap(),
+            Some(first + 2)
+        );
+        drop(store);
+
+        let store = Db::init(
+            std::path::Path::new(".temp/db/commit_log_reopen"),
+            "https://localhost".into(),
+        )
+        .unwrap();
+        assert_eq!(store.last_commit_sequence(), first + 2);
+        let after: Vec<String> = store
+            .commits_after(first, 10)
+            .unwrap()
+            .into_iter()
+            .map(|(_, commit)| commit)
+            .collect();
+        assert_eq!(after, commits);
+        assert_eq!(
+            store.log_commit("https://localhost/commits/next").unwrap(),
+            first + 3
+        );
+    }
+}
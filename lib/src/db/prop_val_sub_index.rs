@@ -2,18 +2,38 @@
 
 use tracing::instrument;
 
-use crate::{atoms::IndexAtom, errors::AtomicResult, Db, Value};
+use crate::{
+    atoms::IndexAtom,
+    errors::AtomicResult,
+    metrics::{Operation, TimedIter},
+    Db, Value,
+};
 
 use super::query_index::{IndexIterator, SEPARATION_BIT};
 
 /// Finds all Atoms for a given {property}-{value} tuple.
+#[track_caller]
 pub fn find_in_prop_val_sub_index(store: &Db, prop: &str, val: Option<&Value>) -> IndexIterator {
     let mut prefix: Vec<u8> = [prop.as_bytes(), &[SEPARATION_BIT]].concat();
     if let Some(value) = val {
         prefix.extend(value.to_sortable_string().as_bytes());
         prefix.extend([SEPARATION_BIT]);
     }
-    Box::new(store.prop_val_sub_index.scan_prefix(prefix).map(|kv| {
+    let atoms = store.prop_val_sub_index.scan_prefix(prefix).map(|kv| {
+        let (key, _value) = kv?;
+        key_to_index_atom(&key)
+    });
+    Box::new(TimedIter::new(
+        atoms,
+        &store.metrics,
+        Operation::IndexLookup,
+        prop.to_string(),
+    ))
+}
+
+/// Iterates over every Atom in the index, grouped by Property and then by value.
+pub fn all_in_prop_val_sub_index(store: &Db) -> IndexIterator {
+    Box::new(store.prop_val_sub_index.iter().map(|kv| {
         let (key, _value) = kv?;
         key_to_index_atom(&key)
     }))
@@ -0,0 +1,351 @@
+//! Counts how Properties and Classes are actually used, see [Db::schema_usage].
+//! Computed from the prop_val_sub_index, so no Resources have to be deserialized.
+//! Walking the index is expensive on large stores, so the report is cached in [SchemaUsageCache].
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{
+    atoms::IndexAtom, commit::CommitResponse, errors::AtomicResult, schema::Class, urls, Db,
+    Resource, Storelike,
+};
+
+use super::prop_val_sub_index::{all_in_prop_val_sub_index, find_in_prop_val_sub_index};
+
+/// Instance counts change with every Commit, so even without Schema changes the report is refreshed after this.
+const CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Properties that any Resource can have, regardless of its Classes.
+/// These are never reported as undeclared.
+const GENERIC_PROPERTIES: &[&str] = &[
+    urls::IS_A,
+    urls::PARENT,
+    urls::LAST_COMMIT,
+    urls::READ,
+    urls::WRITE,
+    urls::APPEND,
+    urls::SORT_POSITION,
+    urls::LOCAL_ID,
+    urls::DEPRECATED,
+    urls::REPLACED_BY,
+    urls::DELETED_AT,
+    urls::TRASHED_FROM,
+    urls::LOCKED_BY,
+    urls::LOCKED_UNTIL,
+];
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchemaUsageReport {
+    /// Every Property that is defined or has values in the store, sorted by subject
+    pub properties: Vec<PropertyUsage>,
+    /// Every Class that is defined or has instances in the store, sorted by subject
+    pub classes: Vec<ClassUsage>,
+    /// Properties that are defined in the store, but have no values
+    pub unused_properties: Vec<String>,
+    /// When the report was computed, in milliseconds since the Unix epoch
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertyUsage {
+    pub subject: String,
+    /// Resources that have a value for this Property. Empty ResourceArrays are not counted.
+    pub resource_count: usize,
+    /// Distinct values of this Property. The members of ResourceArrays are counted separately.
+    pub distinct_values: usize,
+    /// Maps the amount of values (members, for ResourceArrays) to the amount of Resources that have that many.
+    pub cardinality: BTreeMap<usize, usize>,
+    /// Resources that have this Property, but none of their Classes require or recommend it.
+    /// Resources without Classes are not counted.
+    pub undeclared_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassUsage {
+    pub subject: String,
+    pub instance_count: usize,
+    /// Required Properties that some instances don't have, with the amount of instances that miss them
+    pub missing_required: BTreeMap<String, usize>,
+}
+
+/// Walks the index once for the Classes of every Resource, and once for all other values.
+pub(crate) fn compute_schema_usage(store: &Db) -> AtomicResult<SchemaUsageReport> {
+    let mut classes_of: HashMap<String, Vec<String>> = HashMap::new();
+    let mut instances: HashMap<String, HashSet<String>> = HashMap::new();
+    for atom in find_in_prop_val_sub_index(store, urls::IS_A, None) {
+        let atom = atom?;
+        classes_of
+            .entry(atom.subject.clone())
+            .or_default()
+            .push(atom.ref_value.clone());
+        instances
+            .entry(atom.ref_value)
+            .or_default()
+            .insert(atom.subject);
+    }
+
+    let mut class_subjects: HashSet<String> = instances.keys().cloned().collect();
+    class_subjects.extend(instances.get(urls::CLASS).into_iter().flatten().cloned());
+    let mut declared: HashMap<String, HashSet<String>> = HashMap::new();
+    let mut required_by: HashMap<String, Vec<String>> = HashMap::new();
+    for subject in &class_subjects {
+        let Ok(class) = store.get_resource(subject).and_then(Class::from_resource) else {
+            continue;
+        };
+        for prop in &class.requires {
+            required_by
+                .entry(prop.clone())
+                .or_default()
+                .push(subject.clone());
+        }
+        declared.insert(
+            subject.clone(),
+            class.requires.into_iter().chain(class.recommends).collect(),
+        );
+    }
+
+    let scan = UsageScan {
+        classes_of: &classes_of,
+        instances: &instances,
+        declared: &declared,
+        required_by: &required_by,
+    };
+    let mut properties: BTreeMap<String, PropertyUsage> = BTreeMap::new();
+    let mut missing: HashMap<String, BTreeMap<String, usize>> = HashMap::new();
+    let mut current: Option<PropertyValues> = None;
+    for atom in all_in_prop_val_sub_index(store) {
+        let atom = atom?;
+        let values = match current.take() {
+            Some(values) if values.property == atom.property => values,
+            finished => {
+                if let Some(values) = finished {
+                    scan.finish(values, &mut properties, &mut missing);
+                }
+                PropertyValues::new(atom.property.clone())
+            }
+        };
+        current = Some(values.add(atom));
+    }
+    if let Some(values) = current {
+        scan.finish(values, &mut properties, &mut missing);
+    }
+
+    // Required Properties without any values are missing on every instance
+    for (prop, classes) in &required_by {
+        if properties.contains_key(prop) {
+            continue;
+        }
+        for class in classes {
+            let count = instances.get(class).map(HashSet::len).unwrap_or(0);
+            if count > 0 {
+                missing
+                    .entry(class.clone())
+                    .or_default()
+                    .insert(prop.clone(), count);
+            }
+        }
+    }
+
+    let mut unused_properties = Vec::new();
+    for subject in instances.get(urls::PROPERTY).into_iter().flatten() {
+        if !properties.contains_key(subject) {
+            unused_properties.push(subject.clone());
+            properties.insert(subject.clone(), PropertyUsage::unused(subject));
+        }
+    }
+    unused_properties.sort();
+
+    let mut classes: Vec<ClassUsage> = class_subjects
+        .into_iter()
+        .map(|subject| ClassUsage {
+            instance_count: instances.get(&subject).map(HashSet::len).unwrap_or(0),
+            missing_required: missing.remove(&subject).unwrap_or_default(),
+            subject,
+        })
+        .collect();
+    classes.sort_by(|a, b| a.subject.cmp(&b.subject));
+
+    Ok(SchemaUsageReport {
+        properties: properties.into_values().collect(),
+        classes,
+        unused_properties,
+        created_at: crate::utils::now(),
+    })
+}
+
+/// What is known about the Classes, while the values of the Properties are counted.
+struct UsageScan<'a> {
+    classes_of: &'a HashMap<String, Vec<String>>,
+    instances: &'a HashMap<String, HashSet<String>>,
+    declared: &'a HashMap<String, HashSet<String>>,
+    required_by: &'a HashMap<String, Vec<String>>,
+}
+
+impl UsageScan<'_> {
+    fn finish(
+        &self,
+        values: PropertyValues,
+        properties: &mut BTreeMap<String, PropertyUsage>,
+        missing: &mut HashMap<String, BTreeMap<String, usize>>,
+    ) {
+        let mut cardinality: BTreeMap<usize, usize> = BTreeMap::new();
+        for count in values.per_subject.values() {
+            *cardinality.entry(*count).or_default() += 1;
+        }
+
+        let undeclared_count = if GENERIC_PROPERTIES.contains(&values.property.as_str()) {
+            0
+        } else {
+            values
+                .per_subject
+                .keys()
+                .filter(|subject| {
+                    let Some(classes) = self.classes_of.get(*subject) else {
+                        return false;
+                    };
+                    !classes.iter().any(|class| {
+                        self.declared
+                            .get(class)
+                            .map(|props| props.contains(&values.property))
+                            .unwrap_or(false)
+                    })
+                })
+                .count()
+        };
+
+        for class in self.required_by.get(&values.property).into_iter().flatten() {
+            let count = self
+                .instances
+                .get(class)
+                .into_iter()
+                .flatten()
+                .filter(|subject| !values.per_subject.contains_key(*subject))
+                .count();
+            if count > 0 {
+                missing
+                    .entry(class.clone())
+                    .or_default()
+                    .insert(values.property.clone(), count);
+            }
+        }
+
+        properties.insert(
+            values.property.clone(),
+            PropertyUsage {
+                subject: values.property,
+                resource_count: values.per_subject.len(),
+                distinct_values: values.distinct_values,
+                cardinality,
+                undeclared_count,
+            },
+        );
+    }
+}
+
+/// The index atoms of a single Property. The index is sorted by Property and then by value, so these are contiguous.
+struct PropertyValues {
+    property: String,
+    per_subject: HashMap<String, usize>,
+    distinct_values: usize,
+    last_value: Option<String>,
+}
+
+impl PropertyValues {
+    fn new(property: String) -> Self {
+        PropertyValues {
+            property,
+            per_subject: HashMap::new(),
+            distinct_values: 0,
+            last_value: None,
+        }
+    }
+
+    fn add(mut self, atom: IndexAtom) -> Self {
+        if self.last_value.as_ref() != Some(&atom.ref_value) {
+            self.distinct_values += 1;
+            self.last_value = Some(atom.ref_value);
+        }
+        *self.per_subject.entry(atom.subject).or_default() += 1;
+        self
+    }
+}
+
+impl PropertyUsage {
+    fn unused(subject: &str) -> Self {
+        PropertyUsage {
+            subject: subject.into(),
+            resource_count: 0,
+            distinct_values: 0,
+            cardinality: BTreeMap::new(),
+            undeclared_count: 0,
+        }
+    }
+}
+
+struct CachedReport {
+    report: SchemaUsageReport,
+    created: Instant,
+}
+
+/// Caches the [SchemaUsageReport] of a [Db].
+/// Cleared when a Class or Property is changed, and refreshed after [CACHE_TTL].
+#[derive(Clone, Default)]
+pub struct SchemaUsageCache {
+    cached: Arc<Mutex<Option<CachedReport>>>,
+    /// Increased on every invalidation, so reports that were computed in the meantime are not stored.
+    generation: Arc<Mutex<u64>>,
+}
+
+impl SchemaUsageCache {
+    pub(crate) fn get_or_compute(&self, store: &Db) -> AtomicResult<SchemaUsageReport> {
+        if let Some(cached) = self.cached.lock().unwrap().as_ref() {
+            if cached.created.elapsed() <= CACHE_TTL {
+                return Ok(cached.report.clone());
+            }
+        }
+        let generation = *self.generation.lock().unwrap();
+        // Computed without holding the lock, so Commits are not blocked by it
+        let report = compute_schema_usage(store)?;
+        let current_generation = self.generation.lock().unwrap();
+        if *current_generation == generation {
+            *self.cached.lock().unwrap() = Some(CachedReport {
+                report: report.clone(),
+                created: Instant::now(),
+            });
+        }
+        Ok(report)
+    }
+
+    /// Clears the report if the Commit changed a Class or Property.
+    pub fn invalidate(&self, commit_response: &CommitResponse) {
+        let touches_schema = [&commit_response.resource_new, &commit_response.resource_old]
+            .into_iter()
+            .flatten()
+            .any(is_schema_resource);
+        if touches_schema {
+            let mut generation = self.generation.lock().unwrap();
+            *generation += 1;
+            *self.cached.lock().unwrap() = None;
+        }
+    }
+}
+
+fn is_schema_resource(resource: &Resource) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .map(|classes| {
+            classes
+                .iter()
+                .any(|class| class == urls::CLASS || class == urls::PROPERTY)
+        })
+        .unwrap_or(false)
+}
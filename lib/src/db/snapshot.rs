@@ -0,0 +1,359 @@
+//! Read-only, point-in-time views of a [Db].
+//!
+//! Sled has no MVCC snapshots, so we use copy-on-write preimages instead.
+//! Taking a [DbSnapshot] registers an empty preimage map with the [Db].
+//! Whenever a Resource is written or removed while a snapshot is alive, its _previous_ value is stored in that map (once, the first time it changes).
+//! Reads on the snapshot check the preimages first and fall back to the live tree.
+//! This makes snapshots practically free to take, and their memory use grows only with the amount of writes that happen during their lifetime.
+//! Dropping the snapshot frees the preimages; the [Db] prunes dead registrations on the next write.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, Weak},
+};
+
+use crate::{
+    agents::ForAgent,
+    errors::{AtomicError, AtomicResult},
+    hierarchy,
+    resources::PropVals,
+    storelike::{Query, QueryResult},
+    urls, Atom, Db, Resource, Storelike,
+};
+
+/// Maps a subject (as bytes) to the serialized [PropVals] it had when the snapshot was taken.
+/// `None` means the Resource did not exist at that time.
+pub(crate) type Preimages = HashMap<Vec<u8>, Option<sled::IVec>>;
+
+/// Keeps track of the snapshots that are currently alive for a [Db].
+/// Holding this lock while writing makes taking a snapshot atomic with respect to writes.
+pub(crate) type SnapshotRegistry = Arc<Mutex<Vec<Weak<Mutex<Preimages>>>>>;
+
+/// Stores the current value of `subject` in every living snapshot that has not yet seen it change.
+/// Must be called _before_ the actual write, while holding the registry lock.
+pub(crate) fn record_preimage(
+    registry: &mut Vec<Weak<Mutex<Preimages>>>,
+    resources: &sled::Tree,
+    subject: &[u8],
+) -> AtomicResult<()> {
+    registry.retain(|weak| weak.strong_count() > 0);
+    if registry.is_empty() {
+        return Ok(());
+    }
+    let current = resources.get(subject)?;
+    for weak in registry.iter() {
+        if let Some(preimages) = weak.upgrade() {
+            preimages
+                .lock()
+                .unwrap()
+                .entry(subject.to_vec())
+                .or_insert_with(|| current.clone());
+        }
+    }
+    Ok(())
+}
+
+/// A read-only view of a [Db], capturing its Resources at the moment [Db::snapshot] was called.
+/// Commits can continue against the live [Db] while this is being read.
+/// Indexes are not part of the snapshot. [Storelike::query] uses the live indexes, and corrects them using the preimages of the Resources that changed.
+#[derive(Clone)]
+pub struct DbSnapshot {
+    store: Db,
+    preimages: Arc<Mutex<Preimages>>,
+}
+
+impl DbSnapshot {
+    /// Registers the snapshot while holding the registry lock, which writers also hold during their write.
+    pub(crate) fn new(store: &Db) -> DbSnapshot {
+        let preimages = Arc::new(Mutex::new(HashMap::new()));
+        store
+            .snapshots
+            .lock()
+            .unwrap()
+            .push(Arc::downgrade(&preimages));
+        DbSnapshot {
+            store: store.clone(),
+            preimages,
+        }
+    }
+
+    /// Returns the preimage of a subject, if it was changed after the snapshot was taken.
+    fn preimage(&self, subject: &[u8]) -> Option<Option<sled::IVec>> {
+        self.preimages.lock().unwrap().get(subject).cloned()
+    }
+
+    /// Returns the serialized PropVals of a subject as they were when the snapshot was taken.
+    fn get_bin(&self, subject: &[u8]) -> AtomicResult<Option<sled::IVec>> {
+        if let Some(pre) = self.preimage(subject) {
+            return Ok(pre);
+        }
+        let live = self.store.resources.get(subject)?;
+        // A writer might have changed the value in between our two reads.
+        // Writers always record the preimage before writing, so checking again is sufficient.
+        if let Some(pre) = self.preimage(subject) {
+            return Ok(pre);
+        }
+        Ok(live)
+    }
+
+    fn readonly_error(&self) -> AtomicError {
+        AtomicError::method_not_allowed("Snapshots are read-only")
+    }
+}
+
+/// Iterates over the live tree (replacing changed items with their preimages),
+/// followed by Resources that were removed from the live tree after the snapshot was taken.
+struct SnapshotIter {
+    snapshot: DbSnapshot,
+    live: sled::Iter,
+    seen: HashSet<Vec<u8>>,
+    removed: Option<std::vec::IntoIter<(Vec<u8>, sled::IVec)>>,
+    self_url: String,
+    include_external: bool,
+}
+
+fn bin_to_resource(
+    subject: &[u8],
+    bin: &[u8],
+    self_url: &str,
+    include_external: bool,
+) -> Option<Resource> {
+    let subject = String::from_utf8_lossy(subject).to_string();
+    if !include_external && !subject.starts_with(self_url) {
+        return None;
+    }
+    let propvals: PropVals = bincode::deserialize(bin)
+        .unwrap_or_else(|e| panic!("{}. {}", super::corrupt_db_message(&subject), e));
+    Some(Resource::from_propvals(propvals, subject))
+}
+
+impl Iterator for SnapshotIter {
+    type Item = Resource;
+
+    fn next(&mut self) -> Option<Resource> {
+        if self.removed.is_none() {
+            for item in self.live.by_ref() {
+                let (subject, live_bin) = item.expect(super::DB_CORRUPT_MSG);
+                let bin = match self.snapshot.preimage(&subject) {
+                    // Created after the snapshot was taken
+                    Some(None) => continue,
+                    Some(Some(pre)) => pre,
+                    None => live_bin,
+                };
+                self.seen.insert(subject.to_vec());
+                if let Some(resource) =
+                    bin_to_resource(&subject, &bin, &self.self_url, self.include_external)
+                {
+                    return Some(resource);
+                }
+            }
+            let removed: Vec<(Vec<u8>, sled::IVec)> = self
+                .snapshot
+                .preimages
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(subject, _)| !self.seen.contains(*subject))
+                .filter_map(|(subject, pre)| pre.clone().map(|bin| (subject.clone(), bin)))
+                .collect();
+            self.removed = Some(removed.into_iter());
+        }
+        while let Some((subject, bin)) = self.removed.as_mut()?.next() {
+            if let Some(resource) =
+                bin_to_resource(&subject, &bin, &self.self_url, self.include_external)
+            {
+                return Some(resource);
+            }
+        }
+        None
+    }
+}
+
+/// The part of a [Query] that the indexes of the live store answer, applied to a single Resource.
+fn matches_query(q: &Query, r: &Resource) -> bool {
+    match (&q.property, &q.value) {
+        (Some(prop), Some(val)) => r.get(prop).map(|v| v.contains_value(val)).unwrap_or(false),
+        (Some(prop), None) => r.get(prop).is_ok(),
+        (None, Some(val)) => r.get_propvals().values().any(|v| v.contains_value(val)),
+        (None, None) => true,
+    }
+}
+
+impl Storelike for DbSnapshot {
+    fn add_atoms(&self, _atoms: Vec<Atom>) -> AtomicResult<()> {
+        Err(self.readonly_error())
+    }
+
+    fn add_resource_opts(
+        &self,
+        _resource: &Resource,
+        _check_required_props: bool,
+        _update_index: bool,
+        _overwrite_existing: bool,
+    ) -> AtomicResult<()> {
+        Err(self.readonly_error())
+    }
+
+    fn all_resources(&self, include_external: bool) -> Box<dyn Iterator<Item = Resource>> {
+        Box::new(SnapshotIter {
+            snapshot: self.clone(),
+            live: self.store.resources.iter(),
+            seen: HashSet::new(),
+            removed: None,
+            self_url: self.store.get_server_url().into(),
+            include_external,
+        })
+    }
+
+    fn get_server_url(&self) -> &str {
+        self.store.get_server_url()
+    }
+
+    fn get_self_url(&self) -> Option<String> {
+        self.store.get_self_url()
+    }
+
+    fn get_default_agent(&self) -> AtomicResult<crate::agents::Agent> {
+        self.store.get_default_agent()
+    }
+
+    fn get_resource(&self, subject: &str) -> AtomicResult<Resource> {
+        match self.get_bin(subject.as_bytes())? {
+            Some(bin) => {
+                let propvals: PropVals = bincode::deserialize(&bin).map_err(|e| {
+                    format!(
+                        "Deserialize propval error: {} {}",
+                        super::corrupt_db_message(subject),
+                        e
+                    )
+                })?;
+                Ok(Resource::from_propvals(propvals, subject.into()))
+            }
+            None => Err(AtomicError::not_found(format!(
+                "Resource {} not found in snapshot",
+                subject
+            ))),
+        }
+    }
+
+    // Snapshots never fetch or store external resources
+    fn handle_not_found(&self, _subject: &str, error: AtomicError) -> AtomicResult<Resource> {
+        Err(error)
+    }
+
+    /// Builds Collections from the Resources in the snapshot, so their members and counts match the rest of the snapshot.
+    fn get_resource_extended(
+        &self,
+        subject: &str,
+        skip_dynamic: bool,
+        for_agent: &ForAgent,
+    ) -> AtomicResult<Resource> {
+        let url = url::Url::parse(subject)?;
+        let mut without_params = url.clone();
+        without_params.set_query(None);
+        let mut without_params = without_params.to_string();
+        if without_params.ends_with('/') {
+            without_params.pop();
+        }
+        let mut resource = self.get_resource(&without_params)?;
+        hierarchy::check_read(self, &resource, for_agent)?;
+        if !skip_dynamic
+            && resource
+                .get_classes(self)?
+                .iter()
+                .any(|c| c.subject == urls::COLLECTION)
+        {
+            resource = crate::collections::construct_collection_from_params(
+                self,
+                url.query_pairs(),
+                &mut resource,
+                for_agent,
+            )?;
+        }
+        resource.set_subject(subject.into());
+        Ok(resource)
+    }
+
+    /// Uses the indexes of the live store for the Resources that did not change since the snapshot was taken,
+    /// and checks the preimages of the ones that did.
+    fn query(&self, q: &Query) -> AtomicResult<QueryResult> {
+        // Values are stored normalized, the preimages as well
+        if let (Some(prop), Some(value)) = (&q.property, &q.value) {
+            if let Ok(property) = self.get_property(prop) {
+                if let Some(normalized) = crate::normalize::normalize_value(value, &property) {
+                    let mut q = q.clone();
+                    q.value = Some(normalized);
+                    return self.query(&q);
+                }
+            }
+        }
+        let mut live_query = q.clone();
+        live_query.offset = 0;
+        live_query.limit = None;
+        live_query.include_nested = false;
+        // Rights are checked below, against the snapshot
+        live_query.for_agent = ForAgent::Sudo;
+        let live = self.store.query(&live_query)?;
+
+        let changed = self.preimages.lock().unwrap().clone();
+        let self_url = self.store.get_server_url();
+        let mut resources: Vec<Resource> = live
+            .subjects
+            .iter()
+            .filter(|subject| !changed.contains_key(subject.as_bytes()))
+            .filter_map(|subject| self.get_resource(subject).ok())
+            .collect();
+        resources.extend(
+            changed
+                .iter()
+                .filter_map(|(subject, pre)| {
+                    let bin = pre.as_ref()?;
+                    bin_to_resource(subject, bin, self_url, q.include_external)
+                })
+                .filter(|r| matches_query(q, r)),
+        );
+        let mut resources: Vec<Resource> = resources
+            .into_iter()
+            .filter(|r| hierarchy::check_read(self, r, &q.for_agent).is_ok())
+            .collect();
+        let count = resources.len();
+
+        if let Some(sort) = &q.sort_by {
+            resources = crate::collections::sort_resources(resources, sort, q.sort_desc);
+        } else {
+            resources.sort_by(|a, b| a.get_subject().cmp(b.get_subject()));
+        }
+
+        let resources: Vec<Resource> = resources
+            .into_iter()
+            .skip(q.offset)
+            .take(q.limit.unwrap_or(usize::MAX))
+            .collect();
+        let subjects = resources.iter().map(|r| r.get_subject().clone()).collect();
+
+        Ok(QueryResult {
+            subjects,
+            resources: if q.include_nested || q.for_agent != ForAgent::Sudo {
+                resources
+            } else {
+                Vec::new()
+            },
+            count,
+        })
+    }
+
+    fn remove_resource(&self, _subject: &str) -> AtomicResult<()> {
+        Err(self.readonly_error())
+    }
+
+    fn set_default_agent(&self, _agent: crate::agents::Agent) {}
+}
+
+impl std::fmt::Debug for DbSnapshot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DbSnapshot")
+            .field("server_url", &self.store.get_server_url())
+            .field("changed_since", &self.preimages.lock().unwrap().len())
+            .finish()
+    }
+}
@@ -0,0 +1,650 @@
+//! Queries that combine a Class, a parent subtree, property conditions and full-text matches.
+//! Every filter is resolved using an index, starting with the most selective one.
+//! Once the set of candidates is small, the remaining filters are checked on the candidates themselves, instead of scanning another index.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use serde::Deserialize;
+
+use crate::{
+    agents::ForAgent, cursors::Cursor, errors::AtomicResult, hierarchy::check_read,
+    plugins::trash::is_trashed, urls, utils::date_to_millis, values::SubResource, Db, Resource,
+    Storelike, Value,
+};
+
+use super::prop_val_sub_index::find_in_prop_val_sub_index;
+
+/// Below this amount of candidates, filters are checked per Resource instead of by scanning their index.
+const VERIFY_THRESHOLD: usize = 256;
+const DEFAULT_LIMIT: usize = 30;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct StructuredQuery {
+    /// Only include instances of this Class
+    pub class: Option<String>,
+    /// Only include descendants of this Resource
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    /// Full-text search term. Resolved by the caller, which passes the matching subjects to [Db::structured_query].
+    pub text: Option<String>,
+    /// Property URL to sort by. Resources without a value are sorted last.
+    pub sort_by: Option<String>,
+    #[serde(default)]
+    pub sort_desc: bool,
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: usize,
+    /// Include the full Resources instead of only their subjects
+    #[serde(default)]
+    pub include_nested: bool,
+    /// Continue after the last member of a previous page, see [crate::cursors].
+    /// An empty cursor starts at the first page. Ignores `offset`.
+    pub cursor: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Condition {
+    /// Property URL
+    pub property: String,
+    pub operator: Operator,
+    /// A string, number or boolean. Dates (`YYYY-MM-DD`) can be compared with Timestamps.
+    pub value: serde_json::Value,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Operator {
+    Eq,
+    Neq,
+    Lt,
+    Gt,
+    Contains,
+}
+
+#[derive(Debug)]
+pub struct StructuredQueryResult {
+    /// The requested page of Resources, readable by the Agent
+    pub resources: Vec<Resource>,
+    /// Amount of matching Resources that the Agent can read
+    pub count: usize,
+    /// Pass this as `cursor` to get the next page. Only set when the query has a `cursor`, and more Resources follow.
+    pub next_cursor: Option<String>,
+}
+
+impl StructuredQueryResult {
+    /// Converts the result to a Resource with `members` and `totalMembers`.
+    pub fn into_resource(self, subject: String, include_nested: bool) -> Resource {
+        let mut resource = Resource::new(subject);
+        let members: Vec<SubResource> = self
+            .resources
+            .into_iter()
+            .map(|r| {
+                if include_nested {
+                    SubResource::Resource(Box::new(r))
+                } else {
+                    SubResource::Subject(r.get_subject().clone())
+                }
+            })
+            .collect();
+        resource.set_propval_unsafe(urls::COLLECTION_MEMBERS.into(), members.into());
+        resource.set_propval_unsafe(
+            urls::COLLECTION_MEMBER_COUNT.into(),
+            Value::Integer(self.count as i64),
+        );
+        if let Some(cursor) = self.next_cursor {
+            resource.set_propval_unsafe(urls::COLLECTION_NEXT_CURSOR.into(), Value::String(cursor));
+        }
+        resource
+    }
+}
+
+impl StructuredQuery {
+    /// The filters of the query, the most selective first.
+    /// The text term is not a filter, it is resolved by the caller.
+    fn filters(&self) -> Vec<Filter<'_>> {
+        let mut filters: Vec<Filter> = Vec::new();
+        if let Some(class) = &self.class {
+            filters.push(Filter::Equals(urls::IS_A, class.clone()));
+        }
+        for condition in &self.conditions {
+            if condition.operator == Operator::Eq {
+                filters.push(Filter::Equals(
+                    &condition.property,
+                    condition.value_string(),
+                ));
+            } else {
+                filters.push(Filter::Scan(condition));
+            }
+        }
+        if let Some(parent) = &self.parent {
+            filters.push(Filter::Subtree(parent));
+        }
+        filters.sort_by_key(Filter::rank);
+        filters
+    }
+
+    /// Identifies the filters and sorting of the query, but not its page, for [Cursor]s.
+    fn cursor_query(&self) -> String {
+        let mut query = self.clone();
+        query.limit = None;
+        query.offset = 0;
+        query.include_nested = false;
+        query.cursor = None;
+        format!("structured-query {:?}", query)
+    }
+
+    /// The order of the results: by the sortable value of `sort_by`, and then by subject.
+    /// Resources without a value are sorted last, also when sorting descending.
+    fn compare(&self, a: (Option<&str>, &str), b: (Option<&str>, &str)) -> std::cmp::Ordering {
+        let ordering = match (a.0, b.0) {
+            (Some(value_a), Some(value_b)) => sort_compare(value_a, value_b),
+            (Some(_), None) => return std::cmp::Ordering::Less,
+            (None, Some(_)) => return std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        };
+        let ordering = if self.sort_desc {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        ordering.then_with(|| a.1.cmp(b.1))
+    }
+
+    /// Whether the Resource passes the Class, parent and conditions of the query, without running it.
+    /// Used to check if a Commit makes a Resource enter or leave the results.
+    /// The text term, the rights and the trash are not checked.
+    pub fn matches(&self, store: &Db, resource: &Resource) -> bool {
+        self.filters()
+            .iter()
+            .all(|filter| store.resource_matches(filter, resource))
+    }
+}
+
+impl Condition {
+    fn value_string(&self) -> String {
+        match &self.value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+
+    /// Checks the indexed values of one Resource for this Property.
+    /// `neq` only matches if none of the values is equal, so ResourceArrays behave like sets.
+    fn matches(&self, values: &[String]) -> bool {
+        let expected = self.value_string();
+        match self.operator {
+            Operator::Eq => values.iter().any(|v| v == &expected),
+            Operator::Neq => values.iter().all(|v| v != &expected),
+            Operator::Contains => {
+                let expected = expected.to_lowercase();
+                values.iter().any(|v| v.to_lowercase().contains(&expected))
+            }
+            Operator::Lt => values
+                .iter()
+                .any(|v| compare(v, &expected) == Some(std::cmp::Ordering::Less)),
+            Operator::Gt => values
+                .iter()
+                .any(|v| compare(v, &expected) == Some(std::cmp::Ordering::Greater)),
+        }
+    }
+}
+
+/// Compares numbers numerically, and dates with timestamps.
+/// Other strings are compared lexicographically, which works for ISO dates.
+/// Numbers and other strings can't be compared.
+fn compare(value: &str, expected: &str) -> Option<std::cmp::Ordering> {
+    match (value.parse::<f64>(), expected.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b),
+        (Ok(a), Err(_)) => date_to_millis(expected).and_then(|b| a.partial_cmp(&(b as f64))),
+        (Err(_), Ok(_)) => None,
+        (Err(_), Err(_)) => Some(value.cmp(expected)),
+    }
+}
+
+/// A total order for sorting: numbers (numerically) before other strings.
+fn sort_compare(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.total_cmp(&b),
+        (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+        (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+/// A filter of the query.
+#[derive(Debug)]
+enum Filter<'a> {
+    /// Exact lookups in the property-value index, including the Class
+    Equals(&'a str, String),
+    /// Walks the parent index down from the root of the subtree
+    Subtree(&'a str),
+    /// Scans all values of a Property in the property-value index
+    Scan(&'a Condition),
+}
+
+impl Filter<'_> {
+    /// Lower is expected to match fewer Resources, and is resolved first.
+    fn rank(&self) -> u8 {
+        match self {
+            Filter::Equals(..) => 0,
+            Filter::Subtree(_) => 1,
+            Filter::Scan(_) => 2,
+        }
+    }
+}
+
+impl Db {
+    /// Runs a [StructuredQuery].
+    /// `text_matches` are the subjects that match the full-text term, if the query has one.
+    /// Only Resources that `for_agent` can read are returned and counted.
+    pub fn structured_query(
+        &self,
+        query: &StructuredQuery,
+        text_matches: Option<Vec<String>>,
+        for_agent: &ForAgent,
+    ) -> AtomicResult<StructuredQueryResult> {
+        let filters = query.filters();
+        let mut candidates: Option<HashSet<String>> = text_matches.map(HashSet::from_iter);
+        if candidates.is_none() && filters.is_empty() {
+            return Err(
+                "A query needs at least one filter: a class, parent, condition or text".into(),
+            );
+        }
+
+        for filter in &filters {
+            if candidates.as_ref().map(|c| c.is_empty()).unwrap_or(false) {
+                break;
+            }
+            candidates = Some(match candidates {
+                Some(current) if current.len() <= VERIFY_THRESHOLD => current
+                    .into_iter()
+                    .filter(|subject| self.filter_matches(filter, subject))
+                    .collect(),
+                Some(current) => {
+                    let found = self.filter_subjects(filter)?;
+                    current.intersection(&found).cloned().collect()
+                }
+                None => self.filter_subjects(filter)?,
+            });
+        }
+
+        let sort_value = |resource: &Resource| {
+            query
+                .sort_by
+                .as_ref()
+                .and_then(|prop| resource.get(prop).ok())
+                .map(|value| value.to_sortable_string())
+        };
+        let mut members: Vec<(Option<String>, Resource)> = candidates
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|subject| self.get_resource(&subject).ok())
+            .filter(|resource| !is_trashed(resource))
+            .filter(|resource| check_read(self, resource, for_agent).is_ok())
+            .map(|resource| (sort_value(&resource), resource))
+            .collect();
+        members.sort_by(|(value_a, a), (value_b, b)| {
+            query.compare(
+                (value_a.as_deref(), a.get_subject()),
+                (value_b.as_deref(), b.get_subject()),
+            )
+        });
+
+        let count = members.len();
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+        let Some(cursor) = &query.cursor else {
+            let resources = members
+                .into_iter()
+                .skip(query.offset)
+                .take(limit)
+                .map(|(_, resource)| resource)
+                .collect();
+            return Ok(StructuredQueryResult {
+                resources,
+                count,
+                next_cursor: None,
+            });
+        };
+
+        let cursor_query = query.cursor_query();
+        if !cursor.is_empty() {
+            let after = Cursor::decode(self, &cursor_query, cursor)?;
+            after.check_boundary(self, query.sort_by.as_deref())?;
+            members.retain(|(value, resource)| {
+                query.compare(
+                    (value.as_deref(), resource.get_subject()),
+                    (after.value.as_deref(), &after.subject),
+                ) == std::cmp::Ordering::Greater
+            });
+        }
+        let next_cursor = match members.get(limit.saturating_sub(1)) {
+            Some((value, last)) if limit > 0 && members.len() > limit => Some(
+                Cursor::new(&cursor_query, value.clone(), last.get_subject().clone())
+                    .encode(self)?,
+            ),
+            _ => None,
+        };
+        let resources = members
+            .into_iter()
+            .take(limit)
+            .map(|(_, resource)| resource)
+            .collect();
+        Ok(StructuredQueryResult {
+            resources,
+            count,
+            next_cursor,
+        })
+    }
+
+    /// Resolves a filter using its index.
+    fn filter_subjects(&self, filter: &Filter) -> AtomicResult<HashSet<String>> {
+        let mut subjects = HashSet::new();
+        match filter {
+            Filter::Equals(prop, value) => {
+                let value = Value::String(value.clone());
+                for atom in find_in_prop_val_sub_index(self, prop, Some(&value)) {
+                    subjects.insert(atom?.subject);
+                }
+            }
+            Filter::Scan(condition) => {
+                let mut values: HashMap<String, Vec<String>> = HashMap::new();
+                for atom in find_in_prop_val_sub_index(self, &condition.property, None) {
+                    let atom = atom?;
+                    values.entry(atom.subject).or_default().push(atom.ref_value);
+                }
+                subjects.extend(
+                    values
+                        .into_iter()
+                        .filter(|(_, values)| condition.matches(values))
+                        .map(|(subject, _)| subject),
+                );
+            }
+            Filter::Subtree(root) => {
+                let mut queue = VecDeque::from([root.to_string()]);
+                while let Some(parent) = queue.pop_front() {
+                    let parent = Value::AtomicUrl(parent);
+                    for atom in find_in_prop_val_sub_index(self, urls::PARENT, Some(&parent)) {
+                        let child = atom?.subject;
+                        // Guards against cycles in the hierarchy
+                        if subjects.insert(child.clone()) {
+                            queue.push_back(child);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(subjects)
+    }
+
+    /// Checks a filter on a single Resource, used when there are only a few candidates left.
+    fn filter_matches(&self, filter: &Filter, subject: &str) -> bool {
+        match self.get_resource(subject) {
+            Ok(resource) => self.resource_matches(filter, &resource),
+            Err(_) => false,
+        }
+    }
+
+    fn resource_matches(&self, filter: &Filter, resource: &Resource) -> bool {
+        let indexed_values = |prop: &str| -> Vec<String> {
+            resource
+                .get(prop)
+                .ok()
+                .and_then(|v| v.to_reference_index_strings())
+                .unwrap_or_default()
+        };
+        match filter {
+            Filter::Equals(prop, value) => indexed_values(prop).contains(value),
+            Filter::Scan(condition) => {
+                let values = indexed_values(&condition.property);
+                !values.is_empty() && condition.matches(&values)
+            }
+            Filter::Subtree(root) => resource
+                .get_parent_tree(self)
+                .map(|tree| tree.iter().any(|p| p.get_subject() == root))
+                .unwrap_or(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Any Class from the default store, so it doesn't have to be fetched.
+    const TASK: &str = urls::MESSAGE;
+
+    fn condition(property: &str, operator: Operator, value: serde_json::Value) -> Condition {
+        Condition {
+            property: property.into(),
+            operator,
+            value,
+        }
+    }
+
+    fn subjects(store: &Db, query: &StructuredQuery) -> Vec<String> {
+        store
+            .structured_query(query, None, &ForAgent::Sudo)
+            .unwrap()
+            .resources
+            .iter()
+            .map(|r| r.get_subject().clone())
+            .collect()
+    }
+
+    #[test]
+    fn multiple_conditions() {
+        let store = Db::init_temp("structured_query_multiple_conditions").unwrap();
+        let drive = store.get_server_url().to_string();
+        let folder = store
+            .create_test_resource(&drive, vec![])
+            .get_subject()
+            .clone();
+        let other_folder = store
+            .create_test_resource(&drive, vec![])
+            .get_subject()
+            .clone();
+        let task = |parent: &str, status: &str, due: i64| {
+            store
+                .create_test_resource(
+                    parent,
+                    vec![
+                        (urls::IS_A, Value::from(vec![TASK.to_string()])),
+                        (urls::SHORTNAME, Value::Slug(status.into())),
+                        (urls::CREATED_AT, Value::Timestamp(due)),
+                    ],
+                )
+                .get_subject()
+                .clone()
+        };
+        let late = task(&folder, "todo", 300);
+        let early = task(&folder, "todo", 100);
+        let _done = task(&folder, "done", 200);
+        let _elsewhere = task(&other_folder, "todo", 50);
+
+        let mut query = StructuredQuery {
+            class: Some(TASK.into()),
+            parent: Some(folder.clone()),
+            conditions: vec![condition(urls::SHORTNAME, Operator::Eq, "todo".into())],
+            sort_by: Some(urls::CREATED_AT.into()),
+            ..Default::default()
+        };
+        assert_eq!(subjects(&store, &query), vec![early.clone(), late.clone()]);
+
+        query.sort_desc = true;
+        assert_eq!(subjects(&store, &query), vec![late.clone(), early.clone()]);
+
+        query.limit = Some(1);
+        query.offset = 1;
+        let result = store
+            .structured_query(&query, None, &ForAgent::Sudo)
+            .unwrap();
+        assert_eq!(result.count, 2);
+        assert_eq!(result.resources[0].get_subject(), &early);
+
+        // Text matches are intersected with the other filters
+        let result = store
+            .structured_query(
+                &StructuredQuery {
+                    class: Some(TASK.into()),
+                    ..Default::default()
+                },
+                Some(vec![late.clone(), "https://example.com/unknown".into()]),
+                &ForAgent::Sudo,
+            )
+            .unwrap();
+        assert_eq!(result.count, 1);
+
+        // Agents only see the Resources they can read.
+        // This one has a parent that does not exist, so its rights can't be checked.
+        let orphan_parent = format!("{}/missing", drive);
+        let orphan = store
+            .create_test_resource(&orphan_parent, vec![])
+            .get_subject()
+            .clone();
+        let orphans = StructuredQuery {
+            parent: Some(orphan_parent),
+            ..Default::default()
+        };
+        assert_eq!(subjects(&store, &orphans), vec![orphan]);
+        let result = store
+            .structured_query(&orphans, None, &ForAgent::Public)
+            .unwrap();
+        assert_eq!(result.count, 0);
+
+        store
+            .structured_query(&StructuredQuery::default(), None, &ForAgent::Sudo)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn cursors_continue_after_changes() {
+        let store = Db::init_temp("structured_query_cursors").unwrap();
+        let drive = store.get_server_url().to_string();
+        let folder = store
+            .create_test_resource(&drive, vec![])
+            .get_subject()
+            .clone();
+        let at = |millis: i64| {
+            store
+                .create_test_resource(&folder, vec![(urls::CREATED_AT, Value::Timestamp(millis))])
+                .get_subject()
+                .clone()
+        };
+        let ten = at(10);
+        let twenty = at(20);
+        let thirty = at(30);
+        let forty = at(40);
+        let mut query = StructuredQuery {
+            parent: Some(folder.clone()),
+            sort_by: Some(urls::CREATED_AT.into()),
+            limit: Some(2),
+            cursor: Some("".into()),
+            ..Default::default()
+        };
+        let page = |query: &StructuredQuery| {
+            store
+                .structured_query(query, None, &ForAgent::Sudo)
+                .map(|result| {
+                    let subjects: Vec<String> = result
+                        .resources
+                        .iter()
+                        .map(|r| r.get_subject().clone())
+                        .collect();
+                    (subjects, result.next_cursor)
+                })
+        };
+
+        let (first, next) = page(&query).unwrap();
+        assert_eq!(first, vec![ten, twenty.clone()]);
+        // The last member of the page is removed, one member is added before it and one after it
+        store.remove_resource(&twenty).unwrap();
+        let _fifteen = at(15);
+        let thirty_five = at(35);
+        query.cursor = next;
+        let (second, next) = page(&query).unwrap();
+        assert_eq!(second, vec![thirty, thirty_five.clone()]);
+
+        // A cursor only works for the query that created it
+        let mut other = query.clone();
+        other.sort_desc = true;
+        page(&other).unwrap_err();
+
+        // Moving the last member of the page expires the cursor
+        let mut moved = store.get_resource(&thirty_five).unwrap();
+        moved.set_propval_unsafe(urls::CREATED_AT.into(), Value::Timestamp(5));
+        store.add_resource_opts(&moved, false, true, true).unwrap();
+        query.cursor = next.clone();
+        let err = page(&query).unwrap_err();
+        assert!(matches!(err.error_type, crate::AtomicErrorType::Gone));
+
+        moved.set_propval_unsafe(urls::CREATED_AT.into(), Value::Timestamp(35));
+        store.add_resource_opts(&moved, false, true, true).unwrap();
+        let (last, next) = page(&query).unwrap();
+        assert_eq!(last, vec![forty]);
+        assert!(next.is_none());
+    }
+
+    #[test]
+    fn operators_on_integers_and_dates() {
+        let store = Db::init_temp("structured_query_operators").unwrap();
+        let drive = store.get_server_url().to_string();
+        let folder = store
+            .create_test_resource(&drive, vec![])
+            .get_subject()
+            .clone();
+        let at = |millis: i64| {
+            store
+                .create_test_resource(&folder, vec![(urls::CREATED_AT, Value::Timestamp(millis))])
+                .get_subject()
+                .clone()
+        };
+        let minus = at(-5);
+        let nine = at(9);
+        let ten = at(10);
+        let hundred = at(100);
+        // 2024-01-01T00:00:00Z
+        let new_year = at(1_704_067_200_000);
+
+        let run = |operator: Operator, value: serde_json::Value| {
+            let mut found = subjects(
+                &store,
+                &StructuredQuery {
+                    parent: Some(folder.clone()),
+                    conditions: vec![condition(urls::CREATED_AT, operator, value)],
+                    ..Default::default()
+                },
+            );
+            found.sort();
+            found
+        };
+        let sorted = |mut v: Vec<&String>| {
+            v.sort();
+            v.into_iter().cloned().collect::<Vec<String>>()
+        };
+
+        // Numeric, not lexicographic: 9 < 10 < 100
+        assert_eq!(run(Operator::Lt, 10.into()), sorted(vec![&minus, &nine]));
+        assert_eq!(
+            run(Operator::Gt, 10.into()),
+            sorted(vec![&hundred, &new_year])
+        );
+        assert_eq!(run(Operator::Gt, (-10).into()).len(), 5);
+        assert_eq!(run(Operator::Eq, 10.into()), vec![ten.clone()]);
+        assert_eq!(run(Operator::Neq, 10.into()).len(), 4);
+        // Dates are compared to Timestamps
+        assert_eq!(run(Operator::Lt, "2024-01-01".into()).len(), 4);
+        assert_eq!(
+            run(Operator::Gt, "2023-12-31".into()),
+            vec![new_year.clone()]
+        );
+        assert_eq!(
+            run(Operator::Contains, "10".into()),
+            sorted(vec![&ten, &hundred])
+        );
+
+        assert_eq!(date_to_millis("1970-01-01"), Some(0));
+        assert_eq!(date_to_millis("2024-13-01"), None);
+    }
+}
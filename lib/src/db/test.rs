@@ -566,3 +566,457 @@ fn test_collection_update_value(store: &Db, property_url: &str, old_val: Value,
         "Modifying the filtered value did not remove the item from the results"
     );
 }
+
+#[test]
+/// A snapshot should keep returning the state from before it was taken, while the live store is being mutated.
+fn snapshot_export_is_consistent_during_writes() {
+    let store = Db::init_temp("snapshot_export").unwrap();
+    // The export order can differ, since removed resources are appended at the end.
+    fn sorted_export(store: &impl Storelike) -> Vec<serde_json::Value> {
+        let mut items: Vec<serde_json::Value> =
+            serde_json::from_str(&store.export(true).unwrap()).unwrap();
+        items.sort_by_key(|item| item["@id"].to_string());
+        items
+    }
+
+    let before = sorted_export(&store.snapshot());
+    let snapshot = store.snapshot();
+
+    let writer_store = store.clone();
+    let writer = std::thread::spawn(move || {
+        let existing: Vec<Resource> = writer_store.all_resources(false).take(20).collect();
+        for i in 0..200 {
+            let mut resource =
+                Resource::new(format!("{}/snapshot-{}", writer_store.get_server_url(), i));
+            resource.set_propval_unsafe(urls::NAME.into(), Value::String(format!("thing {}", i)));
+            writer_store
+                .add_resource_opts(&resource, false, true, true)
+                .unwrap();
+        }
+        for (i, mut resource) in existing.into_iter().enumerate() {
+            if i.is_multiple_of(2) {
+                writer_store
+                    .remove_resource(resource.get_subject())
+                    .unwrap();
+            } else {
+                resource.set_propval_unsafe(
+                    urls::DESCRIPTION.into(),
+                    Value::Markdown("changed".into()),
+                );
+                writer_store
+                    .add_resource_opts(&resource, false, true, true)
+                    .unwrap();
+            }
+        }
+    });
+
+    let during = sorted_export(&snapshot);
+    writer.join().unwrap();
+    let after = sorted_export(&snapshot);
+
+    assert_eq!(before, during, "Export from snapshot changed during writes");
+    assert_eq!(before, after, "Export from snapshot changed after writes");
+
+    let live = sorted_export(&store);
+    assert_ne!(before, live, "Live store should contain the new writes");
+
+    // Dropping the snapshot should free its preimages on the next write
+    drop(snapshot);
+    let resource = Resource::new(format!("{}/snapshot-last", store.get_server_url()));
+    store
+        .add_resource_opts(&resource, false, true, true)
+        .unwrap();
+    assert!(store.snapshots.lock().unwrap().is_empty());
+}
+
+#[test]
+/// Queries on a snapshot use the live indexes, but return the Resources as they were when the snapshot was taken.
+fn snapshot_query_uses_preimages() {
+    let store = Db::init_temp("snapshot_query").unwrap();
+    let tag = |i: usize| {
+        let mut resource = Resource::new(format!("{}/tagged-{}", store.get_server_url(), i));
+        resource.set_propval_unsafe(urls::NAME.into(), Value::String("tagged".into()));
+        resource
+    };
+    for i in 0..3 {
+        store.add_resource_opts(&tag(i), false, true, true).unwrap();
+    }
+    let q = Query {
+        property: Some(urls::NAME.into()),
+        value: Some(Value::String("tagged".into())),
+        ..Query::new()
+    };
+    let mut collection =
+        crate::collections::CollectionBuilder::class_collection(urls::AGENT, "tagged", &store);
+    collection.property = Some(urls::NAME.into());
+    collection.value = Some("tagged".into());
+    store
+        .add_resource_opts(&collection.to_resource(&store).unwrap(), false, true, true)
+        .unwrap();
+    let snapshot = store.snapshot();
+
+    store
+        .remove_resource(&format!("{}/tagged-0", store.get_server_url()))
+        .unwrap();
+    let mut renamed = tag(1);
+    renamed.set_propval_unsafe(urls::NAME.into(), Value::String("renamed".into()));
+    store
+        .add_resource_opts(&renamed, false, true, true)
+        .unwrap();
+    store.add_resource_opts(&tag(3), false, true, true).unwrap();
+
+    let in_snapshot = snapshot.query(&q).unwrap();
+    let expected: Vec<String> = (0..3)
+        .map(|i| format!("{}/tagged-{}", store.get_server_url(), i))
+        .collect();
+    assert_eq!(in_snapshot.subjects, expected);
+    assert_eq!(in_snapshot.count, 3);
+    assert_eq!(store.query(&q).unwrap().count, 2);
+
+    // Collections are built from the snapshot as well
+    let member_count = |resource: Resource| {
+        resource
+            .get(urls::COLLECTION_MEMBER_COUNT)
+            .unwrap()
+            .to_string()
+    };
+    let in_snapshot = snapshot
+        .get_resource_extended(&collection.subject, false, &ForAgent::Sudo)
+        .unwrap();
+    assert_eq!(member_count(in_snapshot), "3");
+    let live = store
+        .get_resource_extended(&collection.subject, false, &ForAgent::Sudo)
+        .unwrap();
+    assert_eq!(member_count(live), "2");
+}
+
+#[test]
+fn schema_usage_report() {
+    let store = Db::init_temp("schema_usage").unwrap();
+    let server = store.get_server_url().to_string();
+
+    let unused_prop = format!("{}/unusedProp", server);
+    let mut prop = Resource::new(unused_prop.clone());
+    prop.set_propval_unsafe(urls::IS_A.into(), vec![urls::PROPERTY].into());
+    prop.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug("unused-prop".into()));
+    prop.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("Unused".into()));
+    prop.set_propval_unsafe(
+        urls::DATATYPE_PROP.into(),
+        Value::AtomicUrl(urls::STRING.into()),
+    );
+    store.add_resource_opts(&prop, false, true, true).unwrap();
+
+    let extra_prop = format!("{}/extraProp", server);
+    let class_subject = format!("{}/Thing", server);
+    let mut class = Resource::new(class_subject.clone());
+    class.set_propval_unsafe(urls::IS_A.into(), vec![urls::CLASS].into());
+    class.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug("thing".into()));
+    class.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("A thing".into()));
+    class.set_propval_unsafe(urls::REQUIRES.into(), vec![urls::DESCRIPTION].into());
+    class.set_propval_unsafe(urls::RECOMMENDS.into(), vec![urls::NAME].into());
+    store.add_resource_opts(&class, false, true, true).unwrap();
+
+    let add_thing = |i: usize, description: bool| {
+        let mut thing = Resource::new(format!("{}/thing-{}", server, i));
+        thing.set_propval_unsafe(urls::IS_A.into(), vec![class_subject.as_str()].into());
+        thing.set_propval_unsafe(urls::NAME.into(), Value::String(format!("thing {}", i)));
+        if description {
+            thing.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("Same".into()));
+        } else {
+            thing.set_propval_unsafe(extra_prop.clone(), Value::String("extra".into()));
+        }
+        store.add_resource_opts(&thing, false, true, true).unwrap();
+    };
+    add_thing(1, true);
+    add_thing(2, true);
+    add_thing(3, false);
+
+    let report = store.schema_usage().unwrap();
+    let class_usage = report
+        .classes
+        .iter()
+        .find(|c| c.subject == class_subject)
+        .unwrap();
+    assert_eq!(class_usage.instance_count, 3);
+    assert_eq!(
+        class_usage.missing_required.get(urls::DESCRIPTION),
+        Some(&1)
+    );
+    assert!(report.unused_properties.contains(&unused_prop));
+
+    let property = |report: &SchemaUsageReport, subject: &str| {
+        report
+            .properties
+            .iter()
+            .find(|p| p.subject == subject)
+            .unwrap()
+            .clone()
+    };
+    let extra = property(&report, &extra_prop);
+    assert_eq!(extra.resource_count, 1);
+    assert_eq!(extra.undeclared_count, 1, "Thing does not declare it");
+    let names = property(&report, urls::NAME);
+    assert!(names.resource_count >= 3);
+    assert_eq!(names.cardinality.get(&1), Some(&names.resource_count));
+    let is_a = property(&report, urls::IS_A);
+    assert_eq!(is_a.undeclared_count, 0, "isA is allowed on every Resource");
+
+    // Resources that are added without a Commit don't clear the cached report
+    add_thing(4, true);
+    let cached = store.schema_usage().unwrap();
+    assert_eq!(cached.created_at, report.created_at);
+
+    // Changing a Class does
+    let mut class = store.get_resource(&class_subject).unwrap();
+    class.set_propval_unsafe(
+        urls::DESCRIPTION.into(),
+        Value::Markdown("A changed thing".into()),
+    );
+    class.save_locally(&store).unwrap();
+    let refreshed = store.schema_usage().unwrap();
+    let class_usage = refreshed
+        .classes
+        .iter()
+        .find(|c| c.subject == class_subject)
+        .unwrap();
+    assert_eq!(class_usage.instance_count, 4);
+}
+
+#[test]
+#[timeout(60000)]
+fn subject_strategies_are_unique_under_concurrency() {
+    use crate::subjects::{subject_from_local_id, SubjectStrategy};
+    use std::collections::HashSet;
+
+    for strategy in SubjectStrategy::ALL {
+        let store = Db::init_temp(&format!("subject_strategy_{}", strategy)).unwrap();
+        let server = store.get_server_url().to_string();
+        let legacy = Resource::new_instance(urls::PARAGRAPH, &store).unwrap();
+        store.add_resource_opts(&legacy, false, true, true).unwrap();
+
+        let mut drive = store.get_resource(&server).unwrap();
+        drive.set_propval_unsafe(
+            urls::SUBJECT_STRATEGY.into(),
+            Value::String(strategy.to_string()),
+        );
+        store.add_resource_opts(&drive, false, true, true).unwrap();
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    (0..20)
+                        .map(|_| {
+                            let resource = Resource::new_instance(urls::PARAGRAPH, &store).unwrap();
+                            store
+                                .add_resource_opts(&resource, false, true, true)
+                                .unwrap();
+                            resource.get_subject().clone()
+                        })
+                        .collect::<Vec<String>>()
+                })
+            })
+            .collect();
+        let subjects: Vec<String> = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect();
+        let unique: HashSet<&String> = subjects.iter().collect();
+        assert_eq!(unique.len(), 160, "duplicate subjects using {}", strategy);
+        for subject in &subjects {
+            assert!(subject.starts_with(&server));
+            match strategy {
+                SubjectStrategy::Random => assert!(subject.contains("/r/")),
+                SubjectStrategy::Ulid => assert!(subject.contains("/u/")),
+                _ => {}
+            }
+        }
+        // Existing subjects are not affected by the strategy
+        assert!(store.get_resource(legacy.get_subject()).is_ok());
+
+        let importer = format!("{}/importer", server);
+        let mut importer_resource = Resource::new(importer.clone());
+        importer_resource.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(server.clone()));
+        store
+            .add_resource_opts(&importer_resource, false, true, true)
+            .unwrap();
+        assert_eq!(
+            subject_from_local_id(&store, &importer, "My Note"),
+            subject_from_local_id(&store, &importer, "My Note")
+        );
+        assert_ne!(
+            subject_from_local_id(&store, &importer, "My Note"),
+            subject_from_local_id(&store, &importer, "my-note")
+        );
+    }
+}
+
+#[test]
+#[timeout(30000)]
+fn metrics_count_store_operations() {
+    use crate::metrics::Operation;
+
+    let store = Db::init_temp("metrics").unwrap();
+    let metrics = store.get_metrics().unwrap().clone();
+    let count = |op: Operation| metrics.count(op);
+    let server = store.get_server_url().to_string();
+
+    let gets = count(Operation::Get);
+    for _ in 0..3 {
+        store.get_resource(&server).unwrap();
+    }
+    assert_eq!(count(Operation::Get), gets + 3);
+
+    let writes = count(Operation::Write);
+    let commits = count(Operation::Commit);
+    let lock_waits = count(Operation::LockWait);
+    let mut resource = Resource::new_instance(urls::PARAGRAPH, &store).unwrap();
+    resource
+        .set_propval_string(urls::DESCRIPTION.into(), "metrics", &store)
+        .unwrap();
+    resource
+        .set_propval_string(urls::PARENT.into(), &server, &store)
+        .unwrap();
+    resource.save_locally(&store).unwrap();
+    assert_eq!(count(Operation::Commit), commits + 1);
+    assert_eq!(count(Operation::LockWait), lock_waits + 1);
+    assert!(count(Operation::Write) > writes);
+
+    let queries = count(Operation::Query);
+    let lookups = count(Operation::IndexLookup);
+    let q = Query::new_prop_val(urls::DESCRIPTION, "metrics");
+    assert_eq!(store.query(&q).unwrap().subjects.len(), 1);
+    assert_eq!(count(Operation::Query), queries + 1);
+    assert!(count(Operation::IndexLookup) > lookups);
+
+    let removes = count(Operation::Remove);
+    store.remove_resource(resource.get_subject()).unwrap();
+    assert_eq!(count(Operation::Remove), removes + 1);
+
+    assert!(store.metrics().slow_operations.is_empty());
+    store.set_slow_threshold(Some(std::time::Duration::ZERO));
+    store.get_resource(&server).unwrap();
+    let report = store.metrics();
+    let slow = report.slow_operations.last().unwrap();
+    assert_eq!(slow.operation, "get");
+    assert_eq!(slow.subject, server);
+    assert!(slow.location.contains("test.rs"), "{}", slow.location);
+    assert!(report.operations["get"].count >= gets + 4);
+}
+
+#[test]
+fn large_values_are_not_indexed() {
+    let store = Db::init_temp("large_values").unwrap();
+    store.set_max_indexed_value_size(10);
+    let server = store.get_server_url().to_string();
+    let long = "a".repeat(20);
+
+    let mut resource = Resource::new_instance(urls::PARAGRAPH, &store).unwrap();
+    resource
+        .set_propval_string(urls::DESCRIPTION.into(), &long, &store)
+        .unwrap();
+    resource
+        .set_propval_string(urls::PARENT.into(), &server, &store)
+        .unwrap();
+    resource.save_locally(&store).unwrap();
+
+    // The full value is stored, but can't be found using the index
+    let stored = store.get_resource(resource.get_subject()).unwrap();
+    assert_eq!(stored.get(urls::DESCRIPTION).unwrap().to_string(), long);
+    let q = Query::new_prop_val(urls::DESCRIPTION, &long);
+    assert!(store.query(&q).unwrap().subjects.is_empty());
+
+    let mut response = stored.clone();
+    let truncated = response.truncate_values(5);
+    assert_eq!(truncated.get(urls::DESCRIPTION), Some(&20));
+    assert_eq!(
+        response.get(urls::DESCRIPTION).unwrap().to_string(),
+        format!("aaaaa{}20 bytes]", crate::values::TRUNCATED_MARKER)
+    );
+}
+
+#[test]
+fn overlay_leaves_the_base_store_untouched() {
+    use crate::{commit::CommitBuilder, overlay::Overlay};
+
+    let store = Db::init_temp("overlay").unwrap();
+    let server = store.get_server_url().to_string();
+    let agent = store.get_default_agent().unwrap();
+    let mut sorted = Query::new_class(urls::PARAGRAPH);
+    sorted.sort_by = Some(urls::DESCRIPTION.into());
+    let mut subjects = Vec::new();
+    for i in 0..10 {
+        let mut resource = Resource::new_instance(urls::PARAGRAPH, &store).unwrap();
+        resource
+            .set_propval_string(urls::DESCRIPTION.into(), &format!("base {}", i), &store)
+            .unwrap();
+        resource
+            .set_propval_string(urls::PARENT.into(), &server, &store)
+            .unwrap();
+        resource.save_locally(&store).unwrap();
+        subjects.push(resource.get_subject().clone());
+    }
+    // Watching the query changes the index, so that happens before the checksum is taken
+    assert_eq!(store.query(&sorted).unwrap().count, 10);
+    store.db.flush().unwrap();
+    let checksum = store.db.checksum().unwrap();
+    let export = store.export(true).unwrap();
+
+    let overlay = Overlay::new(&store);
+    let opts = crate::commit::CommitOpts {
+        validate_schema: true,
+        validate_signature: true,
+        validate_timestamp: true,
+        validate_rights: false,
+        validate_previous_commit: true,
+        validate_for_agent: None,
+        update_index: true,
+        validate_relative_urls: false,
+    };
+    for (i, subject) in subjects.iter().enumerate() {
+        if i.is_multiple_of(3) {
+            overlay.remove_resource(subject).unwrap();
+            continue;
+        }
+        let resource = overlay.get_resource(subject).unwrap();
+        let mut builder = CommitBuilder::new(subject.clone());
+        builder.set(
+            urls::DESCRIPTION.into(),
+            Value::Markdown(format!("overlay {}", i)),
+        );
+        builder
+            .sign(&agent, &overlay, &resource)
+            .unwrap()
+            .apply_opts(&overlay, &opts)
+            .unwrap();
+    }
+    let mut added = Resource::new_instance(urls::PARAGRAPH, &overlay).unwrap();
+    added
+        .set_propval_string(urls::DESCRIPTION.into(), "added", &overlay)
+        .unwrap();
+    added
+        .set_propval_string(urls::PARENT.into(), &server, &overlay)
+        .unwrap();
+    added.save_locally(&overlay).unwrap();
+
+    // Queries see the pending changes
+    let result = overlay.query(&sorted).unwrap();
+    let descriptions: Vec<String> = result
+        .resources
+        .iter()
+        .map(|r| r.get(urls::DESCRIPTION).unwrap().to_string())
+        .collect();
+    assert!(descriptions.contains(&"added".to_string()));
+    assert!(descriptions.contains(&"overlay 1".to_string()));
+    assert!(!descriptions.iter().any(|d| d.starts_with("base")));
+    let mut expected = descriptions.clone();
+    expected.sort();
+    assert_eq!(descriptions, expected);
+    assert_eq!(result.count, 7);
+    assert!(overlay.get_resource(&subjects[0]).is_err());
+
+    drop(overlay);
+    store.db.flush().unwrap();
+    assert_eq!(store.db.checksum().unwrap(), checksum);
+    assert_eq!(store.export(true).unwrap(), export);
+}
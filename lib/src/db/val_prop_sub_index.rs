@@ -1,5 +1,10 @@
 //! Index sorted by {Value}-{Property}-{Subject}.
-use crate::{atoms::IndexAtom, errors::AtomicResult, Db, Value};
+use crate::{
+    atoms::IndexAtom,
+    errors::AtomicResult,
+    metrics::{Operation, TimedIter},
+    Db, Value,
+};
 use tracing::instrument;
 
 use super::query_index::{IndexIterator, SEPARATION_BIT};
@@ -33,6 +38,7 @@ fn key_from_atom(atom: &IndexAtom) -> Vec<u8> {
 }
 
 /// Finds all Atoms for a given {value}.
+#[track_caller]
 pub fn find_in_val_prop_sub_index(store: &Db, val: &Value, prop: Option<&str>) -> IndexIterator {
     let ref_index = val.to_reference_index_strings();
     let value_key = if let Some(v) = ref_index {
@@ -49,10 +55,16 @@ pub fn find_in_val_prop_sub_index(store: &Db, val: &Value, prop: Option<&str>) -
         prefix.extend(prop.as_bytes());
         prefix.extend([SEPARATION_BIT]);
     }
-    Box::new(store.reference_index.scan_prefix(prefix).map(|kv| {
+    let atoms = store.reference_index.scan_prefix(prefix).map(|kv| {
         let (key, _value) = kv?;
         key_to_index_atom(&key)
-    }))
+    });
+    Box::new(TimedIter::new(
+        atoms,
+        &store.metrics,
+        Operation::IndexLookup,
+        value_key,
+    ))
 }
 
 /// Parses a Value index key string, converts it into an atom.
@@ -85,6 +85,9 @@ pub fn default_endpoints() -> Vec<Endpoint> {
         plugins::bookmark::bookmark_endpoint(),
         plugins::importer::import_endpoint(),
         plugins::query::query_endpoint(),
+        plugins::trash::restore_endpoint(),
+        plugins::reorder::reorder_endpoint(),
+        plugins::activity::activity_endpoint(),
         #[cfg(debug_assertions)]
         plugins::prunetests::prune_tests_endpoint(),
     ]
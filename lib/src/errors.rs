@@ -12,7 +12,7 @@ use std::{
 
 use base64::DecodeError;
 
-use crate::{urls, Resource, Value};
+use crate::{schema::Property, urls, Resource, Value};
 
 /// The default Error type for all Atomic Lib Errors.
 pub type AtomicResult<T> = std::result::Result<T, AtomicError>;
@@ -22,6 +22,45 @@ pub struct AtomicError {
     pub message: String,
     pub error_type: AtomicErrorType,
     pub subject: Option<String>,
+    /// Describes the Property that the error is about, so clients can explain what a valid value looks like.
+    pub property: Option<Box<PropertyHint>>,
+}
+
+/// The Property of a value that failed validation, with an example of a valid value.
+#[derive(Clone, Debug)]
+pub struct PropertyHint {
+    /// URL of the Property
+    pub property: String,
+    pub shortname: String,
+    pub description: String,
+    /// Shortname of the Datatype, e.g. `integer`
+    pub datatype: String,
+    pub example: String,
+}
+
+impl PropertyHint {
+    pub fn from_property(property: &Property) -> Self {
+        PropertyHint {
+            property: property.subject.clone(),
+            shortname: property.shortname.clone(),
+            description: property.description.clone(),
+            datatype: property.data_type.shortname().into(),
+            example: property.data_type.example_value().into(),
+        }
+    }
+
+    /// A sentence that explains what the Property expects.
+    pub fn explain(&self) -> String {
+        let mut explanation = format!("`{}` ({})", self.shortname, self.property);
+        if !self.description.is_empty() {
+            explanation.push_str(&format!(": {}", self.description.trim_end_matches('.')));
+        }
+        explanation.push_str(&format!(
+            ". Expects a {}, for example: {}",
+            self.datatype, self.example
+        ));
+        explanation
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -31,6 +70,14 @@ pub enum AtomicErrorType {
     ParseError,
     OtherError,
     MethodNotAllowed,
+    /// The Resource is locked by another Agent
+    Locked,
+    /// The Resource has expired, see [crate::plugins::expiry]
+    Gone,
+    /// A request to another server was refused, see [crate::outbound]
+    OutboundBlocked,
+    /// The parents of a Resource form a loop, see [crate::hierarchy::ParentWalk]
+    HierarchyCycle,
 }
 
 impl std::error::Error for AtomicError {
@@ -45,6 +92,7 @@ impl AtomicError {
             message: message.into(),
             error_type: AtomicErrorType::MethodNotAllowed,
             subject: None,
+            property: None,
         }
     }
 
@@ -55,6 +103,7 @@ impl AtomicError {
             message: format!("Resource not found. {}", message),
             error_type: AtomicErrorType::NotFoundError,
             subject: None,
+            property: None,
         }
     }
 
@@ -64,15 +113,71 @@ impl AtomicError {
             message: format!("Unauthorized. {}", message),
             error_type: AtomicErrorType::UnauthorizedError,
             subject: None,
+            property: None,
         }
     }
 
+    /// A server will probably return this error as a 423.
+    pub fn locked(message: String) -> AtomicError {
+        AtomicError {
+            message: format!("Locked. {}", message),
+            error_type: AtomicErrorType::Locked,
+            subject: None,
+            property: None,
+        }
+    }
+
+    /// A server will probably return this error as a 410.
+    pub fn gone(message: String) -> AtomicError {
+        AtomicError {
+            message,
+            error_type: AtomicErrorType::Gone,
+            subject: None,
+            property: None,
+        }
+    }
+
+    /// A server will probably return this error as a 403.
+    pub fn outbound_blocked(message: String) -> AtomicError {
+        AtomicError {
+            message,
+            error_type: AtomicErrorType::OutboundBlocked,
+            subject: None,
+            property: None,
+        }
+    }
+
+    /// The parents of the first subject lead back to one of the `subjects`.
+    /// `subjects` lists the loop in order, ending with the subject that was seen twice.
+    /// A server will probably return this error as a 409.
+    pub fn hierarchy_cycle(subjects: &[String]) -> AtomicError {
+        AtomicError {
+            message: format!(
+                "Hierarchy cycle: {}. A Resource can't be its own parent or ancestor, set the parent of one of these Resources to fix it.",
+                subjects.join(" -> ")
+            ),
+            error_type: AtomicErrorType::HierarchyCycle,
+            subject: subjects.first().cloned(),
+            property: None,
+        }
+    }
+
+    /// The operation needs a cargo feature of `atomic_lib` that this build does not enable.
+    /// `operation` says what was attempted, e.g. `"Parsing CSV"`.
+    pub fn missing_feature(feature: &str, operation: &str) -> AtomicError {
+        AtomicError::other_error(format!(
+            "{} requires the `{}` feature of atomic_lib, which is not enabled in this build.",
+            operation, feature
+        ))
+    }
+
     /// A server will probably return a 500.
     pub fn other_error(message: String) -> AtomicError {
         AtomicError {
             message,
             error_type: AtomicErrorType::OtherError,
             subject: None,
+            property: None,
         }
     }
 
@@ -97,6 +202,7 @@ impl AtomicError {
         AtomicError {
             message: msg,
             subject: None,
+            property: None,
             error_type: AtomicErrorType::ParseError,
         }
     }
@@ -106,6 +212,18 @@ impl AtomicError {
         let mut r = Resource::new(subject);
         r.set_class(urls::ERROR);
         r.set_propval_unsafe(urls::DESCRIPTION.into(), Value::String(self.message));
+        if let Some(subject) = self.subject {
+            r.set_propval_unsafe(urls::ERROR_SUBJECT.into(), Value::AtomicUrl(subject));
+        }
+        if let Some(hint) = self.property {
+            r.set_propval_unsafe(urls::ERROR_PROPERTY.into(), Value::AtomicUrl(hint.property));
+            r.set_propval_unsafe(
+                urls::ERROR_PROPERTY_DESCRIPTION.into(),
+                Value::String(hint.description),
+            );
+            r.set_propval_unsafe(urls::ERROR_DATATYPE.into(), Value::String(hint.datatype));
+            r.set_propval_unsafe(urls::ERROR_EXAMPLE.into(), Value::String(hint.example));
+        }
         r
     }
 
@@ -113,6 +231,14 @@ impl AtomicError {
         self.subject = Some(subject.into());
         self
     }
+
+    /// Adds a description of the Property that the error is about, and appends it to the message.
+    pub fn set_property(mut self, property: &Property) -> Self {
+        let hint = PropertyHint::from_property(property);
+        self.message = format!("{} Property {}", self.message.trim_end(), hint.explain());
+        self.property = Some(Box::new(hint));
+        self
+    }
 }
 
 impl std::fmt::Display for AtomicError {
@@ -128,6 +254,7 @@ impl From<&str> for AtomicError {
             message: message.into(),
             error_type: AtomicErrorType::OtherError,
             subject: None,
+            property: None,
         }
     }
 }
@@ -137,6 +264,7 @@ impl From<String> for AtomicError {
         AtomicError {
             message,
             subject: None,
+            property: None,
             error_type: AtomicErrorType::OtherError,
         }
     }
@@ -147,6 +275,7 @@ impl From<std::boxed::Box<dyn std::error::Error>> for AtomicError {
         AtomicError {
             message: error.to_string(),
             subject: None,
+            property: None,
             error_type: AtomicErrorType::OtherError,
         }
     }
@@ -159,6 +288,7 @@ impl<T> From<std::sync::PoisonError<T>> for AtomicError {
             message: error.to_string(),
             error_type: AtomicErrorType::OtherError,
             subject: None,
+            property: None,
         }
     }
 }
@@ -168,6 +298,7 @@ impl From<std::io::Error> for AtomicError {
         AtomicError {
             message: error.to_string(),
             subject: None,
+            property: None,
             error_type: AtomicErrorType::OtherError,
         }
     }
@@ -179,6 +310,7 @@ impl From<url::ParseError> for AtomicError {
             message: error.to_string(),
             error_type: AtomicErrorType::OtherError,
             subject: None,
+            property: None,
         }
     }
 }
@@ -189,6 +321,7 @@ impl From<serde_json::Error> for AtomicError {
             message: error.to_string(),
             error_type: AtomicErrorType::OtherError,
             subject: None,
+            property: None,
         }
     }
 }
@@ -199,6 +332,7 @@ impl From<std::string::FromUtf8Error> for AtomicError {
             message: error.to_string(),
             error_type: AtomicErrorType::OtherError,
             subject: None,
+            property: None,
         }
     }
 }
@@ -209,6 +343,7 @@ impl From<ParseFloatError> for AtomicError {
             message: error.to_string(),
             error_type: AtomicErrorType::OtherError,
             subject: None,
+            property: None,
         }
     }
 }
@@ -218,6 +353,7 @@ impl From<ParseIntError> for AtomicError {
         AtomicError {
             message: error.to_string(),
             subject: None,
+            property: None,
             error_type: AtomicErrorType::OtherError,
         }
     }
@@ -229,6 +365,7 @@ impl From<DecodeError> for AtomicError {
             message: error.to_string(),
             error_type: AtomicErrorType::OtherError,
             subject: None,
+            property: None,
         }
     }
 }
@@ -238,6 +375,7 @@ impl From<ParseBoolError> for AtomicError {
         AtomicError {
             message: error.to_string(),
             subject: None,
+            property: None,
             error_type: AtomicErrorType::OtherError,
         }
     }
@@ -249,6 +387,7 @@ impl From<Infallible> for AtomicError {
             message: error.to_string(),
             error_type: AtomicErrorType::OtherError,
             subject: None,
+            property: None,
         }
     }
 }
@@ -260,6 +399,7 @@ impl From<sled::Error> for AtomicError {
             message: error.to_string(),
             error_type: AtomicErrorType::OtherError,
             subject: None,
+            property: None,
         }
     }
 }
@@ -270,6 +410,7 @@ impl From<Box<bincode::ErrorKind>> for AtomicError {
         AtomicError {
             message: error.to_string(),
             subject: None,
+            property: None,
             error_type: AtomicErrorType::OtherError,
         }
     }
@@ -0,0 +1,428 @@
+//! Generates random, but valid, instances of a Class for benchmarks, UI demos and tests.
+//! The values are based on the required and recommended Properties of the Class, and are the same for the same seed.
+//! Enable the `fixtures` feature to use this module.
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    agents::ForAgent,
+    datatype::DataType,
+    errors::AtomicResult,
+    parse::{ParseOpts, SaveOpts},
+    schema::{Class, Property},
+    storelike::Query,
+    urls, Resource, Storelike, Value,
+};
+
+/// Properties that are set by [save_instances] or by the server, never generated.
+const SKIPPED: &[&str] = &[
+    urls::PARENT,
+    urls::IS_A,
+    urls::READ,
+    urls::WRITE,
+    urls::LAST_COMMIT,
+    urls::LOCAL_ID,
+];
+/// Generated Dates and Timestamps lie between these years.
+const YEARS: std::ops::Range<i64> = 2020..2030;
+/// The range for numbers whose Property has no `minimum` or `maximum`.
+const DEFAULT_SPAN: f64 = 1000.0;
+/// The maximum amount of references in a generated ResourceArray.
+const MAX_REFERENCES: usize = 3;
+
+const LOREM: &[&str] = &[
+    "lorem",
+    "ipsum",
+    "dolor",
+    "sit",
+    "amet",
+    "consectetur",
+    "adipiscing",
+    "elit",
+    "sed",
+    "do",
+    "eiusmod",
+    "tempor",
+    "incididunt",
+    "ut",
+    "labore",
+    "et",
+    "dolore",
+    "magna",
+    "aliqua",
+    "enim",
+    "minim",
+    "veniam",
+    "quis",
+    "nostrud",
+    "exercitation",
+    "ullamco",
+    "laboris",
+    "nisi",
+    "aliquip",
+    "commodo",
+];
+
+/// Creates `count` Resources of the Class, with all its required Properties and about half of its recommended ones.
+/// References are picked from the existing instances of the `classtype` of the Property, or from its `allowsOnly`.
+/// The Resources are not saved, use [save_instances] for that.
+/// Returns an error if a value for a required Property can't be generated, e.g. if no instances of its `classtype` exist.
+pub fn generate_instances(
+    store: &impl Storelike,
+    class_subject: &str,
+    count: usize,
+    seed: u64,
+) -> AtomicResult<Vec<Resource>> {
+    let class = store.get_class(class_subject)?;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let generator = Generator::new(store, &class)?;
+    let mut resources = Vec::with_capacity(count);
+    for _ in 0..count {
+        resources.push(generator.instance(store, &mut rng)?);
+    }
+    Ok(resources)
+}
+
+/// Saves the generated Resources under the parent with Commits signed by the default Agent.
+pub fn save_instances(
+    store: &impl Storelike,
+    resources: Vec<Resource>,
+    parent: &str,
+) -> AtomicResult<usize> {
+    let mut saved = 0;
+    for mut resource in resources {
+        resource.set_propval(urls::PARENT.into(), Value::AtomicUrl(parent.into()), store)?;
+        resource.save_locally(store)?;
+        saved += 1;
+    }
+    Ok(saved)
+}
+
+/// The Classes of [import_example_ontology].
+pub struct ExampleClasses {
+    /// Requires a `name` and `shortname`
+    pub project: String,
+    /// Requires a `name`, a `priority` between 1 and 5 and a `project`, and recommends a `due` date, `watchers` and `createdAt`
+    pub task: String,
+}
+
+/// Adds a small ontology of Projects and Tasks to the store, which covers most datatypes.
+/// Tasks link to Projects, so generate Projects first. Used by the benchmarks and tests.
+pub fn import_example_ontology(store: &impl Storelike) -> AtomicResult<ExampleClasses> {
+    let base = store.get_server_url().to_string();
+    let ontology = serde_json::json!([
+        {
+            "@id": format!("{}/properties/priority", base),
+            "https://atomicdata.dev/properties/isA": [urls::PROPERTY],
+            "https://atomicdata.dev/properties/shortname": "priority",
+            "https://atomicdata.dev/properties/description": "How urgent it is",
+            "https://atomicdata.dev/properties/datatype": urls::INTEGER,
+            "https://atomicdata.dev/properties/minimum": 1.0,
+            "https://atomicdata.dev/properties/maximum": 5.0
+        },
+        {
+            "@id": format!("{}/properties/due", base),
+            "https://atomicdata.dev/properties/isA": [urls::PROPERTY],
+            "https://atomicdata.dev/properties/shortname": "due",
+            "https://atomicdata.dev/properties/description": "When it should be done",
+            "https://atomicdata.dev/properties/datatype": urls::DATE
+        },
+        {
+            "@id": format!("{}/properties/project", base),
+            "https://atomicdata.dev/properties/isA": [urls::PROPERTY],
+            "https://atomicdata.dev/properties/shortname": "project",
+            "https://atomicdata.dev/properties/description": "The project it belongs to",
+            "https://atomicdata.dev/properties/datatype": urls::ATOMIC_URL,
+            "https://atomicdata.dev/properties/classtype": format!("{}/classes/Project", base)
+        },
+        {
+            "@id": format!("{}/properties/watchers", base),
+            "https://atomicdata.dev/properties/isA": [urls::PROPERTY],
+            "https://atomicdata.dev/properties/shortname": "watchers",
+            "https://atomicdata.dev/properties/description": "Who gets updates",
+            "https://atomicdata.dev/properties/datatype": urls::RESOURCE_ARRAY,
+            "https://atomicdata.dev/properties/classtype": urls::AGENT
+        },
+        {
+            "@id": format!("{}/classes/Project", base),
+            "https://atomicdata.dev/properties/isA": [urls::CLASS],
+            "https://atomicdata.dev/properties/shortname": "project",
+            "https://atomicdata.dev/properties/description": "A project",
+            "https://atomicdata.dev/properties/requires": [urls::NAME, urls::SHORTNAME],
+            "https://atomicdata.dev/properties/recommends": [urls::DESCRIPTION]
+        },
+        {
+            "@id": format!("{}/classes/Task", base),
+            "https://atomicdata.dev/properties/isA": [urls::CLASS],
+            "https://atomicdata.dev/properties/shortname": "task",
+            "https://atomicdata.dev/properties/description": "A task",
+            "https://atomicdata.dev/properties/requires": [
+                urls::NAME,
+                format!("{}/properties/priority", base),
+                format!("{}/properties/project", base)
+            ],
+            "https://atomicdata.dev/properties/recommends": [
+                format!("{}/properties/due", base),
+                format!("{}/properties/watchers", base),
+                urls::CREATED_AT
+            ]
+        }
+    ]);
+    let parse_opts = ParseOpts {
+        importer: Some(base.clone()),
+        for_agent: ForAgent::Sudo,
+        overwrite_outside: false,
+        save: SaveOpts::Save,
+        signer: None,
+        base: None,
+    };
+    store.import(&ontology.to_string(), &parse_opts)?;
+    Ok(ExampleClasses {
+        project: format!("{}/classes/Project", base),
+        task: format!("{}/classes/Task", base),
+    })
+}
+
+struct Field {
+    property: Property,
+    required: bool,
+    /// Existing instances of the `classtype` of the Property, sorted so they are picked in the same order.
+    candidates: Vec<String>,
+}
+
+struct Generator<'a> {
+    class: &'a Class,
+    fields: Vec<Field>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(store: &impl Storelike, class: &'a Class) -> AtomicResult<Self> {
+        let required = class.requires.iter().map(|prop| (prop, true));
+        let recommended = class.recommends.iter().map(|prop| (prop, false));
+        let mut fields = Vec::new();
+        for (subject, required) in required.chain(recommended) {
+            if SKIPPED.contains(&subject.as_str()) {
+                continue;
+            }
+            let property = store.get_property(subject)?;
+            let candidates = match (&property.data_type, &property.class_type) {
+                (DataType::AtomicUrl | DataType::ResourceArray, Some(class_type)) => {
+                    let mut query = Query::new_class(class_type);
+                    query.include_nested = false;
+                    let mut subjects = store.query(&query)?.subjects;
+                    subjects.sort();
+                    subjects
+                }
+                _ => Vec::new(),
+            };
+            fields.push(Field {
+                property,
+                required,
+                candidates,
+            });
+        }
+        Ok(Generator { class, fields })
+    }
+
+    fn instance(&self, store: &impl Storelike, rng: &mut StdRng) -> AtomicResult<Resource> {
+        let subject = format!(
+            "{}/{}/{}",
+            store.get_server_url(),
+            self.class.shortname,
+            random_chars(rng, 10)
+        );
+        let mut resource = Resource::new(subject);
+        resource.set_propval(
+            urls::IS_A.into(),
+            vec![self.class.subject.clone()].into(),
+            store,
+        )?;
+        for field in &self.fields {
+            if !field.required && !rng.gen_bool(0.5) {
+                continue;
+            }
+            match field.value(store, rng) {
+                Ok(value) => {
+                    field.property.check_value(&value).map_err(|e| {
+                        format!(
+                            "Generated an invalid value for {}: {}",
+                            field.property.subject, e
+                        )
+                    })?;
+                    resource.set_propval(field.property.subject.clone(), value, store)?;
+                }
+                Err(e) if field.required => {
+                    return Err(format!(
+                        "Can't generate a value for required property {}: {}",
+                        field.property.subject, e
+                    )
+                    .into())
+                }
+                Err(_) => {}
+            }
+        }
+        Ok(resource)
+    }
+}
+
+impl Field {
+    fn value(&self, store: &impl Storelike, rng: &mut StdRng) -> Result<Value, String> {
+        let property = &self.property;
+        if let Some(allowed) = property.allows_only.as_ref().filter(|a| !a.is_empty()) {
+            return match property.data_type {
+                DataType::ResourceArray => Ok(pick_many(rng, allowed).into()),
+                _ => Value::new(allowed.choose(rng).unwrap(), &property.data_type)
+                    .map_err(|e| e.to_string()),
+            };
+        }
+        let value = match &property.data_type {
+            DataType::String => Value::String(capitalize(&words(rng, 2..6))),
+            DataType::Markdown => {
+                let sentences: Vec<String> = (0..rng.gen_range(1..4))
+                    .map(|_| format!("{}.", capitalize(&words(rng, 4..12))))
+                    .collect();
+                Value::Markdown(sentences.join(" "))
+            }
+            DataType::Slug => Value::Slug(words(rng, 1..4).replace(' ', "-")),
+            DataType::Integer => {
+                let (min, max) = self.bounds()?;
+                let (min, max) = (min.ceil() as i64, max.floor() as i64);
+                if min > max {
+                    return Err("no integer lies between its minimum and maximum".into());
+                }
+                Value::Integer(rng.gen_range(min..=max))
+            }
+            DataType::Float => {
+                let (min, max) = self.bounds()?;
+                // Round to cents, but stay within the bounds
+                let float = (rng.gen_range(min..=max) * 100.0).round() / 100.0;
+                Value::Float(float.clamp(min, max))
+            }
+            DataType::Boolean => Value::Boolean(rng.gen_bool(0.5)),
+            DataType::Date => {
+                let (year, month, day) = date(rng);
+                Value::Date(format!("{:04}-{:02}-{:02}", year, month, day))
+            }
+            DataType::Timestamp => {
+                let (year, month, day) = date(rng);
+                let midnight =
+                    crate::utils::date_to_millis(&format!("{:04}-{:02}-{:02}", year, month, day))
+                        .ok_or("invalid date")?;
+                Value::Timestamp(midnight + rng.gen_range(0..24 * 60 * 60 * 1000))
+            }
+            DataType::AtomicUrl => match &property.class_type {
+                Some(class_type) => Value::AtomicUrl(
+                    self.candidates
+                        .choose(rng)
+                        .ok_or(format!("there are no instances of {}", class_type))?
+                        .clone(),
+                ),
+                // Any Resource will do, so link to the Drive
+                None => Value::AtomicUrl(store.get_server_url().into()),
+            },
+            DataType::ResourceArray => pick_many(rng, &self.candidates).into(),
+            DataType::Unsupported(datatype) => {
+                return Err(format!("unsupported datatype {}", datatype))
+            }
+        };
+        Ok(value)
+    }
+
+    /// The `minimum` and `maximum` of the Property, or a default range next to the one that is set.
+    fn bounds(&self) -> Result<(f64, f64), String> {
+        let (min, max) = match (self.property.minimum, self.property.maximum) {
+            (Some(min), Some(max)) => (min, max),
+            (Some(min), None) => (min, min + DEFAULT_SPAN),
+            (None, Some(max)) => (max - DEFAULT_SPAN, max),
+            (None, None) => (0.0, DEFAULT_SPAN),
+        };
+        if min > max {
+            return Err(format!("minimum {} is higher than maximum {}", min, max));
+        }
+        Ok((min, max))
+    }
+}
+
+fn words(rng: &mut StdRng, amount: std::ops::Range<usize>) -> String {
+    let amount = rng.gen_range(amount);
+    (0..amount)
+        .map(|_| *LOREM.choose(rng).unwrap())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Picks up to [MAX_REFERENCES] different subjects.
+fn pick_many(rng: &mut StdRng, subjects: &[String]) -> Vec<String> {
+    let amount = rng.gen_range(0..=MAX_REFERENCES.min(subjects.len()));
+    subjects.choose_multiple(rng, amount).cloned().collect()
+}
+
+/// A date in [YEARS]. Days stop at 28, so every month is valid.
+fn date(rng: &mut StdRng) -> (i64, i64, i64) {
+    (
+        rng.gen_range(YEARS),
+        rng.gen_range(1..=12),
+        rng.gen_range(1..=28),
+    )
+}
+
+/// Like [crate::utils::random_string], but from the seeded generator.
+fn random_chars(rng: &mut StdRng, n: usize) -> String {
+    rng.sample_iter(&rand::distributions::Alphanumeric)
+        .take(n)
+        .map(char::from)
+        .collect::<String>()
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Db;
+
+    #[test]
+    fn generated_instances_are_valid() {
+        let store = Db::init_temp("generated_instances_are_valid").unwrap();
+        let base = store.get_server_url().to_string();
+        let classes = import_example_ontology(&store).unwrap();
+        let issues = |report: &crate::validate::ValidationReport| -> usize {
+            report.issues_by_schema_version.values().sum()
+        };
+        let issues_before = issues(&crate::validate::validate_store(&store, false));
+        let (project, task) = (classes.project, classes.task);
+
+        // Tasks need an existing Project
+        assert!(generate_instances(&store, &task, 1, 1).is_err());
+        let projects = generate_instances(&store, &project, 3, 1).unwrap();
+        save_instances(&store, projects, &base).unwrap();
+
+        let tasks = generate_instances(&store, &task, 50, 42).unwrap();
+        let again = generate_instances(&store, &task, 50, 42).unwrap();
+        assert_eq!(
+            tasks
+                .iter()
+                .map(|r| r.to_json_ad().unwrap())
+                .collect::<Vec<_>>(),
+            again
+                .iter()
+                .map(|r| r.to_json_ad().unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(save_instances(&store, tasks, &base).unwrap(), 50);
+
+        // The generated Resources add no issues to the ones the store already had
+        let report = crate::validate::validate_store(&store, false);
+        assert!(report.is_valid(), "{}", report);
+        assert_eq!(issues(&report), issues_before);
+        let found = store.query(&Query::new_class(&task)).unwrap();
+        assert_eq!(found.count, 50);
+    }
+}
@@ -3,8 +3,55 @@
 //! See
 
 use core::fmt;
+use std::collections::{HashMap, HashSet};
 
-use crate::{agents::ForAgent, errors::AtomicResult, storelike::Query, urls, Resource, Storelike};
+use serde::Serialize;
+
+use crate::{
+    agents::ForAgent,
+    errors::{AtomicError, AtomicErrorType, AtomicResult},
+    storelike::Query,
+    urls, Resource, Storelike,
+};
+
+/// Parent chains longer than this are treated as broken, unless the store sets another maximum, see [Storelike::get_max_hierarchy_depth].
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Keeps track of the Resources that were passed while walking up the parents of a Resource.
+/// Every walk up the hierarchy uses this, so a parent that points to one of its own descendants can't keep a request busy.
+pub struct ParentWalk {
+    passed: Vec<String>,
+    max_depth: usize,
+}
+
+impl ParentWalk {
+    pub fn new(store: &impl Storelike, resource: &Resource) -> Self {
+        ParentWalk {
+            passed: vec![resource.get_subject().clone()],
+            max_depth: store.get_max_hierarchy_depth(),
+        }
+    }
+
+    /// Records the next Resource of the walk.
+    /// Throws a [AtomicErrorType::HierarchyCycle] error that names the Resources in the loop if it was passed before,
+    /// or an error if the walk is longer than the maximum depth.
+    pub fn step(&mut self, subject: &str) -> AtomicResult<()> {
+        if let Some(start) = self.passed.iter().position(|s| s == subject) {
+            let mut cycle = self.passed[start..].to_vec();
+            cycle.push(subject.into());
+            return Err(AtomicError::hierarchy_cycle(&cycle));
+        }
+        if self.passed.len() > self.max_depth {
+            return Err(format!(
+                "The hierarchy of {} is deeper than the maximum of {} levels",
+                self.passed[0], self.max_depth
+            )
+            .into());
+        }
+        self.passed.push(subject.into());
+        Ok(())
+    }
+}
 
 #[derive(Debug)]
 pub enum Right {
@@ -33,12 +80,47 @@ impl fmt::Display for Right {
 /// Looks for children relations, adds to the resource. Performs a Query, might be expensive.
 pub fn add_children(store: &impl Storelike, resource: &mut Resource) -> AtomicResult<Resource> {
     let results = store.query(&Query::new_prop_val(urls::PARENT, resource.get_subject()))?;
-    let mut children = results.subjects;
-    children.sort();
+    let children = results.subjects;
     resource.set_propval(urls::CHILDREN.into(), children.into(), store)?;
     Ok(resource.to_owned())
 }
 
+/// Sorts children in the order in which they are listed in their parent.
+/// Children with a [urls::SORT_POSITION] come first, ordered by that position.
+/// The others follow, sorted by their name (or shortname) and then their subject.
+pub fn sort_children(children: &mut [Resource]) {
+    fn key(resource: &Resource) -> (Option<f64>, Option<String>) {
+        let position = resource
+            .get(urls::SORT_POSITION)
+            .and_then(|v| v.to_float())
+            .ok();
+        let name = resource
+            .get(urls::NAME)
+            .or_else(|_| resource.get(urls::SHORTNAME))
+            .map(|v| v.to_string().to_lowercase())
+            .ok();
+        (position, name)
+    }
+    children.sort_by(|a, b| {
+        let (pos_a, name_a) = key(a);
+        let (pos_b, name_b) = key(b);
+        let by_position = match (pos_a, pos_b) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        };
+        by_position
+            .then_with(|| match (name_a, name_b) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            })
+            .then_with(|| a.get_subject().cmp(b.get_subject()))
+    });
+}
+
 /// Throws if not allowed.
 /// Returns string with explanation if allowed.
 pub fn check_write(
@@ -94,85 +176,426 @@ pub fn check_append(
     }
 }
 
+/// Can the Agent move the Resource from its current parent to `new_parent`?
+/// Requires write rights to both the current and the new parent. Write rights to the Resource itself are checked separately.
+/// If the current parent is not set or can't be found, only the new parent is checked.
+/// Moving to another Drive is only allowed if the subject of the Resource has the same origin as the new Drive.
+/// Throws if not allowed.
+pub fn check_move(
+    store: &impl Storelike,
+    resource: &Resource,
+    new_parent: &str,
+    for_agent: &ForAgent,
+) -> AtomicResult<String> {
+    let subject = resource.get_subject();
+    let old_parent = resource.get_parent(store).ok();
+    let new_parent = store.get_resource(new_parent).map_err(|e| {
+        AtomicError::not_found(format!(
+            "Can't move {} to {}, because the new parent can't be found. {}",
+            subject, new_parent, e
+        ))
+    })?;
+    let new_ancestors = new_parent.get_parent_tree(store)?;
+    if new_parent.get_subject() == subject
+        || new_ancestors.iter().any(|a| a.get_subject() == subject)
+    {
+        return Err(format!(
+            "Can't move {} to {}, because a Resource can't be its own parent.",
+            subject,
+            new_parent.get_subject()
+        )
+        .into());
+    }
+
+    if let Some(old_parent) = &old_parent {
+        check_rights(store, old_parent, for_agent, Right::Write).map_err(|e| {
+            AtomicError::unauthorized(format!(
+                "Moving {} out of {} requires write rights to {}. {}",
+                subject,
+                old_parent.get_subject(),
+                old_parent.get_subject(),
+                e.message.trim_start_matches("Unauthorized. ")
+            ))
+        })?;
+    }
+    check_rights(store, &new_parent, for_agent, Right::Write).map_err(|e| {
+        AtomicError::unauthorized(format!(
+            "Moving {} into {} requires write rights to {}. {}",
+            subject,
+            new_parent.get_subject(),
+            new_parent.get_subject(),
+            e.message.trim_start_matches("Unauthorized. ")
+        ))
+    })?;
+
+    let old_drive = match &old_parent {
+        Some(old_parent) => drive_of(store, old_parent)?,
+        None => None,
+    };
+    let new_drive = std::iter::once(&new_parent)
+        .chain(new_ancestors.iter())
+        .find(|r| is_drive(r))
+        .map(|drive| drive.get_subject().clone());
+    if let Some(new_drive) = new_drive.filter(|new_drive| Some(new_drive) != old_drive.as_ref()) {
+        let origin = |s: &str| -> AtomicResult<String> {
+            Ok(url::Url::parse(s)?.origin().ascii_serialization())
+        };
+        if origin(subject)? != origin(&new_drive)? {
+            return Err(format!(
+                "Can't move {} to Drive {}, because its subject is not on the same domain as the Drive.",
+                subject, new_drive
+            )
+            .into());
+        }
+    }
+    Ok(match old_parent {
+        Some(old_parent) => format!(
+            "Write rights to both {} and {}",
+            old_parent.get_subject(),
+            new_parent.get_subject()
+        ),
+        None => format!("Write rights to {}", new_parent.get_subject()),
+    })
+}
+
+/// Refuses to make `new_parent` the parent of `subject` if it is the Resource itself or one of its descendants, since that creates a cycle.
+/// Parents that don't exist yet are accepted here.
+pub fn check_new_parent(
+    store: &impl Storelike,
+    subject: &str,
+    new_parent: &str,
+) -> AtomicResult<()> {
+    let mut chain = vec![subject.to_string(), new_parent.to_string()];
+    if new_parent == subject {
+        return Err(AtomicError::hierarchy_cycle(&chain));
+    }
+    let Ok(parent) = store.get_resource(new_parent) else {
+        return Ok(());
+    };
+    for ancestor in parent.get_parent_tree(store)? {
+        chain.push(ancestor.get_subject().clone());
+        if ancestor.get_subject() == subject {
+            return Err(AtomicError::hierarchy_cycle(&chain));
+        }
+    }
+    Ok(())
+}
+
+/// Finds the loops in the hierarchy of the store, so they can be fixed by hand.
+/// Reads the parent of every Resource once, and only follows parents that are in the store.
+/// Every loop is listed once, starting with the first Resource of the loop that was found.
+pub fn find_cycles(store: &impl Storelike) -> Vec<Vec<String>> {
+    let parents: HashMap<String, String> = store
+        .all_resources(false)
+        .filter_map(|resource| {
+            let parent = resource.get(urls::PARENT).ok()?.to_string();
+            Some((resource.get_subject().clone(), parent))
+        })
+        .collect();
+    let mut checked: HashSet<&String> = HashSet::new();
+    let mut cycles = Vec::new();
+    let mut subjects: Vec<&String> = parents.keys().collect();
+    subjects.sort();
+    for subject in subjects {
+        let mut path: Vec<&String> = Vec::new();
+        let mut current = Some(subject);
+        while let Some(subject) = current {
+            if checked.contains(subject) {
+                break;
+            }
+            if let Some(start) = path.iter().position(|s| *s == subject) {
+                cycles.push(path[start..].iter().map(|s| s.to_string()).collect());
+                break;
+            }
+            path.push(subject);
+            current = parents.get(subject);
+        }
+        checked.extend(path);
+    }
+    cycles
+}
+
+/// Whether the Resource has the Drive class.
+pub(crate) fn is_drive(resource: &Resource) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .map(|classes| classes.iter().any(|c| c == urls::DRIVE))
+        .unwrap_or(false)
+}
+
+/// The Resource itself if it is a Drive, or else its closest Drive ancestor.
+fn drive_of(store: &impl Storelike, resource: &Resource) -> AtomicResult<Option<String>> {
+    if is_drive(resource) {
+        return Ok(Some(resource.get_subject().clone()));
+    }
+    Ok(resource
+        .get_parent_tree(store)?
+        .into_iter()
+        .find(is_drive)
+        .map(|drive| drive.get_subject().clone()))
+}
+
 /// Recursively checks a Resource and its Parents for rights.
 /// Throws if not allowed.
 /// Returns string with explanation if allowed.
+/// Use [trace_rights] to see every step of the check.
 #[tracing::instrument(skip(store, resource))]
 pub fn check_rights(
     store: &impl Storelike,
     resource: &Resource,
-    for_agent_enum: &ForAgent,
+    for_agent: &ForAgent,
     right: Right,
 ) -> AtomicResult<String> {
-    if for_agent_enum == &ForAgent::Sudo {
-        return Ok("Sudo has root access, and can edit anything.".into());
+    trace_rights(store, resource, for_agent, right)?.into_result()
+}
+
+/// Why an Agent got a right, see [TraceStep].
+/// There are no groups of Agents: an Agent is either listed itself, or through the PublicAgent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Grant {
+    /// Sudo and the default Agent of the server can do anything
+    Root,
+    /// Agents can always edit themselves and their children
+    Itself,
+    /// Notifications, Inboxes and Subscriptions are only for their recipient
+    Recipient,
+    /// The Agent is listed in the rights of the Resource
+    Agent,
+    /// The PublicAgent is listed in the rights of the Resource, so everyone has the right
+    Public,
+}
+
+/// A Resource that was checked while walking up the hierarchy.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceStep {
+    pub subject: String,
+    /// The Agents listed in the rights array (e.g. `write`) of this Resource.
+    /// Left out by [RightsTrace::redacted].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rights: Option<Vec<String>>,
+    /// How the right was granted here, if it was
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grant: Option<Grant>,
+    /// Rules that apply to this Resource, such as the ones for Commits and recipients
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+/// The outcome of a rights check, and every Resource that was checked to get there.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RightsTrace {
+    pub subject: String,
+    pub agent: String,
+    pub right: String,
+    pub allowed: bool,
+    /// The same message that [check_rights] returns or throws
+    pub explanation: String,
+    /// The Resource where the right was found, or the last one that was checked before it was denied
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decided_at: Option<String>,
+    /// From the Resource itself up to its Drive
+    pub steps: Vec<TraceStep>,
+    /// Most denials are authorization errors, but Commits can't be edited by anyone.
+    #[serde(skip)]
+    unauthorized: bool,
+}
+
+impl RightsTrace {
+    /// Returns the explanation if the right was granted, or else the error of [check_rights].
+    pub fn into_result(self) -> AtomicResult<String> {
+        match (self.allowed, self.unauthorized) {
+            (true, _) => Ok(self.explanation),
+            (false, true) => Err(AtomicError::unauthorized(self.explanation)),
+            (false, false) => Err(self.explanation.into()),
+        }
+    }
+
+    /// Removes the rights arrays, so the trace can be shown to the Agent that was denied without telling who is allowed.
+    /// What remains is which Resources were checked, and where the check ended.
+    pub fn redacted(mut self) -> Self {
+        for step in &mut self.steps {
+            step.rights = None;
+        }
+        self
+    }
+
+    fn decide(&mut self, allowed: bool, decided_at: Option<&str>, explanation: String) {
+        self.allowed = allowed;
+        self.decided_at = decided_at.map(String::from);
+        self.explanation = explanation;
     }
+}
+
+/// Checks a Resource and its Parents for rights, like [check_rights], and records every step.
+/// Only fails if the store can't be read or the parents form a loop, a denied right is not an error here.
+pub fn trace_rights(
+    store: &impl Storelike,
+    resource: &Resource,
+    for_agent_enum: &ForAgent,
+    right: Right,
+) -> AtomicResult<RightsTrace> {
     let for_agent = for_agent_enum.to_string();
-    if resource.get_subject() == &for_agent {
-        return Ok("Agents can always edit themselves or their children.".into());
+    let mut trace = RightsTrace {
+        subject: resource.get_subject().clone(),
+        agent: for_agent.clone(),
+        right: right.to_string(),
+        allowed: false,
+        explanation: String::new(),
+        decided_at: None,
+        steps: Vec::new(),
+        unauthorized: true,
+    };
+    if for_agent_enum == &ForAgent::Sudo {
+        trace.decide(
+            true,
+            None,
+            "Sudo has root access, and can edit anything.".into(),
+        );
+        return Ok(trace);
     }
-    if let Ok(server_agent) = store.get_default_agent() {
-        if server_agent.subject == for_agent {
-            return Ok("Server agent has root access, and can edit anything.".into());
+    let is_server_agent = store
+        .get_default_agent()
+        .map(|server_agent| server_agent.subject == for_agent)
+        .unwrap_or(false);
+
+    let mut walk = ParentWalk::new(store, resource);
+    let mut resource = resource.clone();
+    loop {
+        let subject = resource.get_subject().clone();
+        let rights = match resource.get(&right.to_string()) {
+            Ok(arr_val) => arr_val.to_subjects(None)?,
+            Err(_) => Vec::new(),
+        };
+        let mut step = TraceStep {
+            subject: subject.clone(),
+            rights: Some(rights.clone()),
+            grant: None,
+            note: None,
+        };
+
+        if subject == for_agent {
+            step.grant = Some(Grant::Itself);
+            trace.steps.push(step);
+            trace.decide(
+                true,
+                Some(&subject),
+                "Agents can always edit themselves or their children.".into(),
+            );
+            return Ok(trace);
+        }
+        if is_server_agent {
+            step.grant = Some(Grant::Root);
+            trace.steps.push(step);
+            trace.decide(
+                true,
+                None,
+                "Server agent has root access, and can edit anything.".into(),
+            );
+            return Ok(trace);
         }
-    }
 
-    // Handle Commits.
-    if let Ok(commit_subject) = resource.get(urls::SUBJECT) {
-        return match right {
-            Right::Read => {
-                // Commits can be read when their subject / target is readable.
-                let target = store.get_resource(&commit_subject.to_string())?;
-                check_rights(store, &target, for_agent_enum, right)
+        // Notifications, Inboxes and Subscriptions are only for their recipient, see [crate::plugins::notifications].
+        if let Ok(recipient) = resource.get(urls::RECIPIENT) {
+            let allowed = recipient.to_string() == for_agent;
+            step.note = Some("Only the recipient can access this Resource".into());
+            step.grant = allowed.then_some(Grant::Recipient);
+            trace.steps.push(step);
+            if allowed {
+                trace.decide(
+                    true,
+                    Some(&subject),
+                    format!("{} is the recipient", for_agent),
+                );
+            } else {
+                trace.decide(
+                    false,
+                    Some(&subject),
+                    format!("Only the recipient can access {}", subject),
+                );
             }
-            Right::Write => Err("Commits cannot be edited.".into()),
-            Right::Append => Err("Commits cannot have children, you cannot Append to them.".into()),
-        };
-    }
+            return Ok(trace);
+        }
 
-    // Check if the resource's rights explicitly refers to the agent or the public agent
-    if let Ok(arr_val) = resource.get(&right.to_string()) {
-        for s in arr_val.to_subjects(None)? {
-            match s.as_str() {
-                urls::PUBLIC_AGENT => {
-                    return Ok(format!(
-                        "PublicAgent has been granted rights in {}",
-                        resource.get_subject()
-                    ))
-                }
-                agent => {
-                    if agent == for_agent {
-                        return Ok(format!(
-                            "Right has been explicitly set in {}",
-                            resource.get_subject()
-                        ));
-                    }
-                }
+        // Handle Commits.
+        if let Ok(commit_subject) = resource.get(urls::SUBJECT) {
+            let denied = match right {
+                Right::Read => None,
+                Right::Write => Some("Commits cannot be edited."),
+                Right::Append => Some("Commits cannot have children, you cannot Append to them."),
             };
+            step.note = Some(format!(
+                "Commits can be read when {} can be read",
+                commit_subject
+            ));
+            trace.steps.push(step);
+            if let Some(reason) = denied {
+                trace.unauthorized = false;
+                trace.decide(false, Some(&subject), reason.into());
+                return Ok(trace);
+            }
+            walk.step(&commit_subject.to_string())?;
+            resource = store.get_resource(&commit_subject.to_string())?;
+            continue;
         }
-    }
 
-    // Try the parents recursively
-    if let Ok(parent) = resource.get_parent(store) {
-        check_rights(store, &parent, for_agent_enum, right)
-    } else {
-        if for_agent_enum == &ForAgent::Public {
-            // resource has no parent and agent is not in rights array - check fails
-            let action = match right {
-                Right::Read => "readable",
-                Right::Write => "editable",
-                Right::Append => "appendable",
-            };
-            return Err(crate::errors::AtomicError::unauthorized(format!(
-                "This resource is not publicly {}. Try signing in",
-                action,
-            )));
+        // Check if the resource's rights explicitly refers to the agent or the public agent
+        let grant = rights.iter().find_map(|agent| match agent.as_str() {
+            urls::PUBLIC_AGENT => Some(Grant::Public),
+            agent if agent == for_agent => Some(Grant::Agent),
+            _ => None,
+        });
+        step.grant = grant;
+        trace.steps.push(step);
+        match grant {
+            Some(Grant::Public) => {
+                trace.decide(
+                    true,
+                    Some(&subject),
+                    format!("PublicAgent has been granted rights in {}", subject),
+                );
+                return Ok(trace);
+            }
+            Some(_) => {
+                trace.decide(
+                    true,
+                    Some(&subject),
+                    format!("Right has been explicitly set in {}", subject),
+                );
+                return Ok(trace);
+            }
+            None => {}
+        }
+
+        // Try the parents
+        match resource.get_parent(store) {
+            Ok(parent) => {
+                walk.step(parent.get_subject())?;
+                resource = parent
+            }
+            Err(e) if matches!(e.error_type, AtomicErrorType::HierarchyCycle) => return Err(e),
+            Err(_) => {
+                // resource has no parent and agent is not in rights array - check fails
+                let explanation = if for_agent_enum == &ForAgent::Public {
+                    let action = match right {
+                        Right::Read => "readable",
+                        Right::Write => "editable",
+                        Right::Append => "appendable",
+                    };
+                    format!("This resource is not publicly {}. Try signing in", action)
+                } else {
+                    format!(
+                        "No {} right has been found for {} in this resource or its parents",
+                        right, for_agent
+                    )
+                };
+                trace.decide(false, Some(&subject), explanation);
+                return Ok(trace);
+            }
         }
-        // resource has no parent and agent is not in rights array - check fails
-        Err(crate::errors::AtomicError::unauthorized(format!(
-            "No {} right has been found for {} in this resource or its parents",
-            right, for_agent
-        )))
     }
 }
 
@@ -207,6 +630,103 @@ mod test {
         // assert!(resource.get(property).unwrap().to_string() == value.to_string());
     }
 
+    #[test]
+    fn trace_shows_where_rights_were_found_or_denied() {
+        use super::*;
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let writer = store.create_agent(Some("writer")).unwrap().subject;
+        let mut drive = Resource::new("https://localhost/drive".into());
+        drive.set_propval_unsafe(urls::READ.into(), vec![urls::PUBLIC_AGENT].into());
+        drive.set_propval_unsafe(urls::WRITE.into(), vec![writer.clone()].into());
+        store.add_resource(&drive).unwrap();
+        let mut child = Resource::new("https://localhost/drive/child".into());
+        child.set_propval_unsafe(
+            urls::PARENT.into(),
+            Value::AtomicUrl(drive.get_subject().clone()),
+        );
+        store.add_resource(&child).unwrap();
+        let outsider: ForAgent = "https://localhost/agents/outsider".into();
+
+        let read = trace_rights(&store, &child, &outsider, Right::Read).unwrap();
+        assert!(read.allowed);
+        assert_eq!(read.steps.len(), 2);
+        assert_eq!(read.steps[0].grant, None);
+        assert_eq!(read.steps[1].grant, Some(Grant::Public));
+        assert_eq!(read.decided_at.as_deref(), Some("https://localhost/drive"));
+
+        let write = trace_rights(&store, &child, &outsider, Right::Write).unwrap();
+        assert!(!write.allowed);
+        assert_eq!(write.decided_at.as_deref(), Some("https://localhost/drive"));
+        assert_eq!(write.steps[1].rights, Some(vec![writer.clone()]));
+        let err = check_rights(&store, &child, &outsider, Right::Write).unwrap_err();
+        assert!(matches!(
+            err.error_type,
+            crate::AtomicErrorType::UnauthorizedError
+        ));
+        assert!(err.message.ends_with(&write.explanation));
+        assert!(write
+            .redacted()
+            .steps
+            .iter()
+            .all(|step| step.rights.is_none()));
+
+        let own = trace_rights(&store, &child, &writer.into(), Right::Write).unwrap();
+        assert!(own.allowed);
+        assert_eq!(own.steps[1].grant, Some(Grant::Agent));
+    }
+
+    #[test]
+    fn cycles_are_detected_and_refused() {
+        use super::*;
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let subjects: Vec<String> = ["a", "b", "c"]
+            .iter()
+            .map(|name| format!("https://localhost/{}", name))
+            .collect();
+        // a -> b -> c -> a
+        for (i, subject) in subjects.iter().enumerate() {
+            let mut resource = Resource::new(subject.clone());
+            let parent = subjects[(i + 1) % subjects.len()].clone();
+            resource.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(parent));
+            store.add_resource(&resource).unwrap();
+        }
+        let a = store.get_resource(&subjects[0]).unwrap();
+        let err = a.get_parent_tree(&store).unwrap_err();
+        assert!(matches!(err.error_type, AtomicErrorType::HierarchyCycle));
+        assert!(err.message.contains(&subjects.join(" -> ")), "{}", err);
+        let outsider: ForAgent = "https://localhost/agents/outsider".into();
+        let err = check_read(&store, &a, &outsider).unwrap_err();
+        assert!(matches!(err.error_type, AtomicErrorType::HierarchyCycle));
+        assert_eq!(find_cycles(&store), vec![subjects.clone()]);
+
+        // d -> e, so e can't become the parent of d
+        let d = Resource::new("https://localhost/d".into());
+        store.add_resource(&d).unwrap();
+        let mut e = Resource::new("https://localhost/d/e".into());
+        e.set_propval_unsafe(
+            urls::PARENT.into(),
+            Value::AtomicUrl(d.get_subject().clone()),
+        );
+        store.add_resource(&e).unwrap();
+        check_new_parent(&store, d.get_subject(), e.get_subject()).unwrap_err();
+        check_new_parent(&store, d.get_subject(), d.get_subject()).unwrap_err();
+        check_new_parent(&store, e.get_subject(), d.get_subject()).unwrap();
+
+        // A chain that is too deep
+        let mut parent = d.get_subject().clone();
+        for i in 0..DEFAULT_MAX_DEPTH + 2 {
+            let mut resource = Resource::new(format!("https://localhost/deep/{}", i));
+            resource.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(parent));
+            store.add_resource(&resource).unwrap();
+            parent = resource.get_subject().clone();
+        }
+        let deepest = store.get_resource(&parent).unwrap();
+        let err = deepest.get_parent_tree(&store).unwrap_err();
+        assert!(err.message.contains("maximum"), "{}", err);
+    }
+
     #[test]
     fn display_right() {
         let read = super::Right::Read;
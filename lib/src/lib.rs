@@ -20,6 +20,8 @@ See the [Atomic Data Docs](https://docs.atomicdata.dev) for more information.
 - [hierarchy] for authorization
 - [crate::endpoints::Endpoint] for custom API endpoints
 - [config::Config] files.
+- [client::AtomicClient] for talking to a remote Atomic Server (requires the `client` feature).
+- [codegen] for generating typed Rust structs from Classes (the default ones are in `typed`, behind the `typed` feature).
 
 ## Getting started
 
@@ -62,29 +64,45 @@ pub mod agents;
 pub mod atoms;
 pub mod authentication;
 pub mod client;
+pub mod codegen;
+pub mod coerce;
 pub mod collections;
 pub mod commit;
 #[cfg(feature = "config")]
 pub mod config;
+pub mod cursors;
 pub mod datatype;
 #[cfg(feature = "db")]
 pub mod db;
 #[cfg(feature = "db")]
 pub mod endpoints;
 pub mod errors;
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
 pub mod hierarchy;
+pub mod locks;
 pub mod mapping;
+pub mod metrics;
+pub mod normalize;
+pub mod outbound;
+pub mod overlay;
 pub mod parse;
+pub mod patch;
 #[cfg(feature = "db")]
 pub mod plugins;
 pub mod populate;
 pub mod resources;
 pub mod schema;
+pub mod schema_version;
 pub mod serialize;
 pub mod store;
 pub mod storelike;
+pub mod subjects;
 #[cfg(test)]
 mod test_utils;
+#[cfg(feature = "typed")]
+#[rustfmt::skip]
+pub mod typed;
 pub mod urls;
 pub mod utils;
 pub mod validate;
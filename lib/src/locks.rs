@@ -0,0 +1,150 @@
+//! Advisory locks, which reserve a Resource for editing by a single Agent.
+//! While a Resource is locked, Commits signed by other Agents are refused.
+//! Locks are kept in memory and expire lazily: expired locks are removed whenever the [LockRegistry] is used, so no background thread is needed.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{errors::AtomicResult, utils::now, AtomicError};
+
+/// The default duration of a lock, in milliseconds.
+pub const DEFAULT_LOCK_DURATION: i64 = 5 * 60 * 1000;
+/// The longest a lock can be held without being refreshed, in milliseconds.
+pub const MAX_LOCK_DURATION: i64 = 60 * 60 * 1000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResourceLock {
+    pub subject: String,
+    /// The Agent holding the lock
+    pub agent: String,
+    /// Unix timestamp in milliseconds
+    pub expires_at: i64,
+    /// Duration in milliseconds. Used when the lock is refreshed by a Commit of the holder.
+    pub duration: i64,
+}
+
+/// Keeps track of the locked Resources of a store.
+/// Cheap to clone, clones share the same locks.
+#[derive(Clone, Debug, Default)]
+pub struct LockRegistry {
+    locks: Arc<Mutex<HashMap<String, ResourceLock>>>,
+}
+
+impl LockRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the active lock of a Resource, if any.
+    pub fn get(&self, subject: &str) -> Option<ResourceLock> {
+        let mut locks = self.locks.lock().unwrap();
+        prune_expired(&mut locks);
+        locks.get(subject).cloned()
+    }
+
+    /// Locks the Resource for the Agent, for `duration` milliseconds (capped at [MAX_LOCK_DURATION]).
+    /// Renews the lock if the Agent already holds it.
+    /// Fails if the Resource is locked by another Agent.
+    pub fn acquire(&self, subject: &str, agent: &str, duration: i64) -> AtomicResult<ResourceLock> {
+        if duration <= 0 {
+            return Err("Lock duration must be positive".into());
+        }
+        let duration = duration.min(MAX_LOCK_DURATION);
+        let mut locks = self.locks.lock().unwrap();
+        prune_expired(&mut locks);
+        if let Some(existing) = locks.get(subject) {
+            if existing.agent != agent {
+                return Err(locked_error(existing));
+            }
+        }
+        let lock = ResourceLock {
+            subject: subject.into(),
+            agent: agent.into(),
+            expires_at: now() + duration,
+            duration,
+        };
+        locks.insert(subject.into(), lock.clone());
+        Ok(lock)
+    }
+
+    /// Fails if the Resource is locked by another Agent.
+    /// If the Agent holds the lock, its expiry is extended.
+    pub fn check_and_refresh(&self, subject: &str, agent: &str) -> AtomicResult<()> {
+        let mut locks = self.locks.lock().unwrap();
+        prune_expired(&mut locks);
+        match locks.get_mut(subject) {
+            Some(lock) if lock.agent != agent => Err(locked_error(lock)),
+            Some(lock) => {
+                lock.expires_at = now() + lock.duration;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Removes the lock of a Resource. Returns the removed lock, if there was one.
+    /// Callers are responsible for checking whether the Agent may release it.
+    pub fn release(&self, subject: &str) -> Option<ResourceLock> {
+        let mut locks = self.locks.lock().unwrap();
+        prune_expired(&mut locks);
+        locks.remove(subject)
+    }
+}
+
+fn prune_expired(locks: &mut HashMap<String, ResourceLock>) {
+    let now = now();
+    locks.retain(|_, lock| lock.expires_at > now);
+}
+
+fn locked_error(lock: &ResourceLock) -> AtomicError {
+    AtomicError::locked(format!(
+        "Resource {} is locked by {} until {}.",
+        lock.subject, lock.agent, lock.expires_at
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lock_blocks_others() {
+        let locks = LockRegistry::new();
+        let subject = "https://localhost/doc";
+        locks
+            .acquire(subject, "https://localhost/alice", 1000)
+            .unwrap();
+        locks
+            .check_and_refresh(subject, "https://localhost/alice")
+            .unwrap();
+        let err = locks
+            .check_and_refresh(subject, "https://localhost/bob")
+            .unwrap_err();
+        assert!(matches!(err.error_type, crate::AtomicErrorType::Locked));
+        assert!(err.message.contains("alice"));
+        locks
+            .acquire(subject, "https://localhost/bob", 1000)
+            .unwrap_err();
+
+        locks.release(subject).unwrap();
+        locks
+            .check_and_refresh(subject, "https://localhost/bob")
+            .unwrap();
+    }
+
+    #[test]
+    fn locks_expire() {
+        let locks = LockRegistry::new();
+        let subject = "https://localhost/doc";
+        locks
+            .acquire(subject, "https://localhost/alice", 1)
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert!(locks.get(subject).is_none());
+        locks
+            .acquire(subject, "https://localhost/bob", 1000)
+            .unwrap();
+    }
+}
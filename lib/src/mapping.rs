@@ -4,7 +4,9 @@
 
 use crate::errors::AtomicResult;
 use std::collections::hash_map::IntoIter;
-use std::{collections::HashMap, fs, path::Path};
+use std::collections::HashMap;
+#[cfg(feature = "fs")]
+use std::{fs, path::Path};
 /// Maps shortanmes (bookmarks) to URLs
 #[derive(Clone)]
 pub struct Mapping {
@@ -45,8 +47,9 @@ impl Mapping {
     }
 
     /// Reads an .amp (atomic mapping) file from your disk.
+    #[cfg(feature = "fs")]
     pub fn read_mapping_from_file(&mut self, path: &Path) -> AtomicResult<()> {
-        let mapping_string = std::fs::read_to_string(path)?;
+        let mapping_string = fs::read_to_string(path)?;
         self.parse_mapping(&mapping_string)?;
         Ok(())
     }
@@ -82,6 +85,7 @@ impl Mapping {
     }
 
     /// Serializes the mapping and stores it to the path
+    #[cfg(feature = "fs")]
     pub fn write_mapping_to_disk(&self, path: &Path) {
         let mut file_string: String = String::new();
         for (key, url) in self.hashmap.clone().iter() {
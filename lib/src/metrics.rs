@@ -0,0 +1,348 @@
+//! Counters and timers for store operations, see [StoreMetrics].
+//! Recording only updates atomic counters, so the metrics are always on.
+//! Operations that take longer than the slow threshold are also kept in a small log, with their subject and call site.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    panic::Location,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// Durations are counted in buckets of powers of two microseconds, which is enough to estimate percentiles.
+const BUCKETS: usize = 32;
+/// How many slow operations are kept. Older ones are dropped.
+const SLOW_LOG_SIZE: usize = 100;
+const SLOW_LOG_DISABLED: u64 = u64::MAX;
+
+const OPERATIONS: usize = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Reading a single Resource
+    Get,
+    /// Writing a single Resource
+    Write,
+    /// Removing a single Resource
+    Remove,
+    /// Iterating over one of the indexes, e.g. to find the Resources that refer to a value
+    IndexLookup,
+    /// Running a [crate::storelike::Query]
+    Query,
+    /// Applying a Commit, including checking rights and updating the indexes
+    Commit,
+    /// Waiting for other Commits to the same Resource to finish
+    LockWait,
+}
+
+impl Operation {
+    pub const ALL: [Operation; OPERATIONS] = [
+        Operation::Get,
+        Operation::Write,
+        Operation::Remove,
+        Operation::IndexLookup,
+        Operation::Query,
+        Operation::Commit,
+        Operation::LockWait,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Get => "get",
+            Operation::Write => "write",
+            Operation::Remove => "remove",
+            Operation::IndexLookup => "indexLookup",
+            Operation::Query => "query",
+            Operation::Commit => "commit",
+            Operation::LockWait => "lockWait",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+#[derive(Default)]
+struct Timer {
+    count: AtomicU64,
+    total_micros: AtomicU64,
+    buckets: [AtomicU64; BUCKETS],
+}
+
+impl Timer {
+    fn record(&self, duration: Duration) {
+        let micros = duration.as_micros().min(u64::MAX as u128) as u64;
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.total_micros.fetch_add(micros, Ordering::Relaxed);
+        self.buckets[bucket_of(micros)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn report(&self) -> OperationMetrics {
+        let buckets: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .collect();
+        let count: u64 = buckets.iter().sum();
+        OperationMetrics {
+            count: self.count.load(Ordering::Relaxed),
+            total_ms: self.total_micros.load(Ordering::Relaxed) as f64 / 1000.0,
+            p50_ms: percentile(&buckets, count, 0.5),
+            p99_ms: percentile(&buckets, count, 0.99),
+        }
+    }
+}
+
+/// Bucket `i` contains durations shorter than `2^i` microseconds.
+fn bucket_of(micros: u64) -> usize {
+    let bits = (u64::BITS - micros.leading_zeros()) as usize;
+    bits.min(BUCKETS - 1)
+}
+
+/// Estimates the percentile as the upper bound of the bucket that contains it, so it is never lower than the actual value.
+fn percentile(buckets: &[u64], count: u64, quantile: f64) -> f64 {
+    if count == 0 {
+        return 0.0;
+    }
+    let rank = ((count as f64) * quantile).ceil().max(1.0) as u64;
+    let mut seen = 0;
+    for (i, amount) in buckets.iter().enumerate() {
+        seen += amount;
+        if seen >= rank {
+            return (1u64 << i) as f64 / 1000.0;
+        }
+    }
+    (1u64 << (BUCKETS - 1)) as f64 / 1000.0
+}
+
+/// A single operation that took longer than the slow threshold.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlowOperation {
+    pub operation: &'static str,
+    /// The subject, or for index lookups and queries, what was looked up
+    pub subject: String,
+    /// Where in the source the operation was started
+    pub location: String,
+    pub duration_ms: f64,
+    /// When the operation finished, in milliseconds since the Unix epoch
+    pub at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OperationMetrics {
+    pub count: u64,
+    pub total_ms: f64,
+    /// Estimated from power of two buckets, so these are rounded up
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricsReport {
+    pub operations: BTreeMap<&'static str, OperationMetrics>,
+    /// Operations are added to `slow_operations` when they take at least this long. Disabled when `None`.
+    pub slow_threshold_ms: Option<f64>,
+    /// The most recent slow operations, oldest first
+    pub slow_operations: Vec<SlowOperation>,
+    /// Refused requests to other servers, see [crate::outbound]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outbound: Option<crate::outbound::OutboundReport>,
+}
+
+/// Counts store operations and how long they take.
+/// Cheap to clone, clones share the same counters.
+#[derive(Clone)]
+pub struct StoreMetrics {
+    timers: Arc<[Timer; OPERATIONS]>,
+    /// In nanoseconds, [SLOW_LOG_DISABLED] means disabled
+    slow_threshold: Arc<AtomicU64>,
+    slow_log: Arc<Mutex<VecDeque<SlowOperation>>>,
+}
+
+impl std::fmt::Debug for StoreMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StoreMetrics").finish_non_exhaustive()
+    }
+}
+
+impl Default for StoreMetrics {
+    fn default() -> Self {
+        StoreMetrics {
+            timers: Arc::default(),
+            slow_threshold: Arc::new(AtomicU64::new(SLOW_LOG_DISABLED)),
+            slow_log: Arc::default(),
+        }
+    }
+}
+
+impl StoreMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Operations that take at least this long are logged and kept in [MetricsReport::slow_operations].
+    pub fn set_slow_threshold(&self, threshold: Option<Duration>) {
+        let nanos = threshold
+            .map(|t| t.as_nanos().min(SLOW_LOG_DISABLED as u128 - 1) as u64)
+            .unwrap_or(SLOW_LOG_DISABLED);
+        self.slow_threshold.store(nanos, Ordering::Relaxed);
+    }
+
+    /// Records an operation that started at `start`.
+    /// `subject` is only called when the operation was slow, so it can be expensive.
+    pub fn record(
+        &self,
+        operation: Operation,
+        start: Instant,
+        location: &'static Location<'static>,
+        subject: impl FnOnce() -> String,
+    ) {
+        let duration = start.elapsed();
+        self.timers[operation.index()].record(duration);
+        let threshold = self.slow_threshold.load(Ordering::Relaxed);
+        if threshold == SLOW_LOG_DISABLED || (duration.as_nanos() as u64) < threshold {
+            return;
+        }
+        let slow = SlowOperation {
+            operation: operation.as_str(),
+            subject: subject(),
+            location: location.to_string(),
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            at: crate::utils::now(),
+        };
+        tracing::warn!(
+            "Slow {} of {} took {:.1}ms at {}",
+            slow.operation,
+            slow.subject,
+            slow.duration_ms,
+            slow.location
+        );
+        let mut log = self.slow_log.lock().unwrap();
+        if log.len() >= SLOW_LOG_SIZE {
+            log.pop_front();
+        }
+        log.push_back(slow);
+    }
+
+    /// Runs `f` and records how long it took.
+    #[track_caller]
+    pub fn time<T>(&self, operation: Operation, subject: &str, f: impl FnOnce() -> T) -> T {
+        let location = Location::caller();
+        let start = Instant::now();
+        let result = f();
+        self.record(operation, start, location, || subject.to_string());
+        result
+    }
+
+    /// The amount of times the operation was recorded.
+    pub fn count(&self, operation: Operation) -> u64 {
+        self.timers[operation.index()].count.load(Ordering::Relaxed)
+    }
+
+    pub fn report(&self) -> MetricsReport {
+        let threshold = self.slow_threshold.load(Ordering::Relaxed);
+        MetricsReport {
+            operations: Operation::ALL
+                .iter()
+                .map(|op| (op.as_str(), self.timers[op.index()].report()))
+                .collect(),
+            slow_threshold_ms: (threshold != SLOW_LOG_DISABLED)
+                .then_some(threshold as f64 / 1_000_000.0),
+            slow_operations: self.slow_log.lock().unwrap().iter().cloned().collect(),
+            outbound: None,
+        }
+    }
+}
+
+/// Wraps an iterator, and records the time spent in `next` as a single operation when it is dropped.
+/// Used for index lookups, which do their work while being iterated.
+pub struct TimedIter<I> {
+    inner: I,
+    metrics: StoreMetrics,
+    operation: Operation,
+    subject: String,
+    location: &'static Location<'static>,
+    spent: Duration,
+}
+
+impl<I> TimedIter<I> {
+    #[track_caller]
+    pub fn new(inner: I, metrics: &StoreMetrics, operation: Operation, subject: String) -> Self {
+        TimedIter {
+            inner,
+            metrics: metrics.clone(),
+            operation,
+            subject,
+            location: Location::caller(),
+            spent: Duration::ZERO,
+        }
+    }
+}
+
+impl<I: Iterator> Iterator for TimedIter<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = Instant::now();
+        let next = self.inner.next();
+        self.spent += start.elapsed();
+        next
+    }
+}
+
+impl<I> Drop for TimedIter<I> {
+    fn drop(&mut self) {
+        let subject = std::mem::take(&mut self.subject);
+        // `record` measures from `start`, so we move it back by the time spent iterating
+        let start = Instant::now()
+            .checked_sub(self.spent)
+            .unwrap_or_else(Instant::now);
+        self.metrics
+            .record(self.operation, start, self.location, || subject);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn percentiles_round_up_to_buckets() {
+        let metrics = StoreMetrics::new();
+        for _ in 0..99 {
+            metrics.timers[Operation::Get.index()].record(Duration::from_micros(3));
+        }
+        metrics.timers[Operation::Get.index()].record(Duration::from_millis(5));
+        let report = metrics.report();
+        let get = &report.operations["get"];
+        assert_eq!(get.count, 100);
+        assert_eq!(get.p50_ms, 0.004);
+        assert_eq!(get.p99_ms, 0.004);
+        assert_eq!(report.operations["write"].count, 0);
+    }
+
+    #[test]
+    fn logs_slow_operations() {
+        let metrics = StoreMetrics::new();
+        metrics.time(Operation::Get, "fast", || ());
+        assert!(metrics.report().slow_operations.is_empty());
+
+        metrics.set_slow_threshold(Some(Duration::from_millis(1)));
+        metrics.time(Operation::Query, "slow", || {
+            std::thread::sleep(Duration::from_millis(2))
+        });
+        let report = metrics.report();
+        assert_eq!(report.slow_operations.len(), 1);
+        assert_eq!(report.slow_operations[0].subject, "slow");
+        assert!(report.slow_operations[0].location.contains("metrics.rs"));
+    }
+}
@@ -0,0 +1,283 @@
+//! Normalization of Values, so equal values are stored (and indexed) in the same form.
+//! Values are normalized when they are set on a Resource, see [crate::Resource::set_propval].
+//! Existing values can be rewritten using [normalize_store].
+//!
+//! The rules, per [DataType]:
+//! - All text is normalized to Unicode NFC.
+//! - `String` and `Markdown` values are trimmed, unless the Property has `keepWhitespace` set to `true`.
+//! - `Slug`, `Date`, `Integer`, `Float` and `Timestamp` text is trimmed.
+//! - `Boolean` text is `true` or `false`. `True`, ` TRUE ` and `1` are read as `true`, and `0` as `false`.
+//! - URLs (`AtomicUrl` and the members of a `ResourceArray`) have a lowercase scheme and host, no default port and no trailing slash.
+
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::{
+    commit::{CommitBuilder, CommitOpts},
+    datatype::DataType,
+    errors::AtomicResult,
+    overlay::Overlay,
+    schema::Property,
+    urls,
+    values::SubResource,
+    Storelike, Value,
+};
+
+/// Reads `true`, `false`, `1` and `0`, ignoring case and surrounding whitespace.
+pub fn parse_bool(text: &str) -> Option<bool> {
+    match text.trim().to_lowercase().as_str() {
+        "true" | "1" => Some(true),
+        "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Lowercases the scheme and host, removes the default port and removes the trailing slash.
+/// Text that is not a URL is only trimmed.
+pub fn normalize_url(url: &str) -> String {
+    let trimmed = url.trim();
+    let Ok(parsed) = url::Url::parse(trimmed) else {
+        return trimmed.to_string();
+    };
+    let mut normalized = parsed.to_string();
+    if parsed.has_host()
+        && parsed.query().is_none()
+        && parsed.fragment().is_none()
+        && normalized.ends_with('/')
+    {
+        normalized.pop();
+    }
+    normalized
+}
+
+/// Normalizes text that is parsed as a Value of `datatype`.
+pub fn normalize_text(text: &str, datatype: &DataType, keep_whitespace: bool) -> String {
+    let text: String = text.nfc().collect();
+    match datatype {
+        DataType::String | DataType::Markdown if keep_whitespace => text,
+        DataType::AtomicUrl | DataType::ResourceArray => normalize_url(&text),
+        DataType::Boolean => match parse_bool(&text) {
+            Some(bool) => bool.to_string(),
+            None => text.trim().to_string(),
+        },
+        DataType::Unsupported(_) => text,
+        _ => text.trim().to_string(),
+    }
+}
+
+/// Returns the normalized Value, or `None` if the Value is already normalized.
+pub fn normalize_value(value: &Value, property: &Property) -> Option<Value> {
+    let text = |text: &String| {
+        let normalized = normalize_text(text, &property.data_type, property.keep_whitespace);
+        (&normalized != text).then_some(normalized)
+    };
+    match value {
+        Value::String(s) => text(s).map(Value::String),
+        Value::Markdown(s) => text(s).map(Value::Markdown),
+        Value::Slug(s) => text(s).map(Value::Slug),
+        Value::Date(s) => text(s).map(Value::Date),
+        Value::AtomicUrl(s) => text(s).map(Value::AtomicUrl),
+        Value::ResourceArray(items) => {
+            let mut changed = false;
+            let normalized = items
+                .iter()
+                .map(|item| match item {
+                    SubResource::Subject(s) => {
+                        let url = normalize_url(s);
+                        changed |= &url != s;
+                        SubResource::Subject(url)
+                    }
+                    other => other.clone(),
+                })
+                .collect::<Vec<_>>();
+            changed.then_some(Value::ResourceArray(normalized))
+        }
+        _ => None,
+    }
+}
+
+/// A Value that is not normalized, found by [normalize_store].
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalizedValue {
+    pub subject: String,
+    pub property: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// Rewrites all Values in the store that are not normalized, using Commits signed by the default Agent.
+/// If `dry_run` is true, the Commits are applied to an [Overlay], which leaves the store as it is.
+/// Commits are skipped, since they can't be edited.
+pub fn normalize_store(
+    store: &impl Storelike,
+    dry_run: bool,
+) -> AtomicResult<Vec<NormalizedValue>> {
+    if dry_run {
+        normalize(&Overlay::new(store))
+    } else {
+        normalize(store)
+    }
+}
+
+fn normalize(store: &impl Storelike) -> AtomicResult<Vec<NormalizedValue>> {
+    let opts = CommitOpts {
+        validate_schema: false,
+        validate_signature: false,
+        validate_timestamp: false,
+        validate_rights: false,
+        validate_previous_commit: false,
+        validate_for_agent: None,
+        update_index: true,
+        validate_relative_urls: false,
+    };
+    let agent = store.get_default_agent()?;
+    let mut changes = Vec::new();
+    for resource in store.all_resources(false) {
+        if resource
+            .get_main_class()
+            .map(|class| class == urls::COMMIT)
+            .unwrap_or(false)
+        {
+            continue;
+        }
+        let mut commitbuilder = CommitBuilder::new(resource.get_subject().clone());
+        let mut changed = false;
+        for (prop, value) in resource.get_propvals() {
+            let Ok(property) = store.get_property(prop) else {
+                continue;
+            };
+            if let Some(normalized) = normalize_value(value, &property) {
+                changes.push(NormalizedValue {
+                    subject: resource.get_subject().clone(),
+                    property: prop.clone(),
+                    from: value.to_string(),
+                    to: normalized.to_string(),
+                });
+                commitbuilder.set(prop.clone(), normalized);
+                changed = true;
+            }
+        }
+        if changed {
+            commitbuilder
+                .sign(&agent, store, &resource)?
+                .apply_opts(store, &opts)?;
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn property(data_type: DataType, keep_whitespace: bool) -> Property {
+        Property {
+            class_type: None,
+            data_type,
+            shortname: "test".into(),
+            subject: "https://example.com/test".into(),
+            description: "".into(),
+            allows_only: None,
+            keep_whitespace,
+            minimum: None,
+            maximum: None,
+        }
+    }
+
+    fn normalized(value: Value, data_type: DataType) -> Option<String> {
+        normalize_value(&value, &property(data_type, false)).map(|v| v.to_string())
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(
+            normalized(Value::String("  hi ".into()), DataType::String),
+            Some("hi".into())
+        );
+        assert_eq!(
+            normalized(Value::String("hi".into()), DataType::String),
+            None
+        );
+        assert_eq!(
+            normalize_value(
+                &Value::String("  hi ".into()),
+                &property(DataType::String, true)
+            )
+            .map(|v| v.to_string()),
+            None
+        );
+        // `e` followed by a combining acute accent becomes a single `é`
+        assert_eq!(
+            normalized(Value::Markdown("cafe\u{301}\n".into()), DataType::Markdown),
+            Some("caf\u{e9}".into())
+        );
+        assert_eq!(
+            normalized(Value::Slug(" my-slug".into()), DataType::Slug),
+            Some("my-slug".into())
+        );
+        assert_eq!(
+            normalized(Value::Date("2023-01-01 ".into()), DataType::Date),
+            Some("2023-01-01".into())
+        );
+    }
+
+    #[test]
+    fn booleans() {
+        for (text, expected) in [
+            ("True", "true"),
+            ("true ", "true"),
+            ("1", "true"),
+            (" FALSE", "false"),
+            ("0", "false"),
+        ] {
+            assert_eq!(normalize_text(text, &DataType::Boolean, false), expected);
+        }
+        assert_eq!(parse_bool("yes"), None);
+        assert!(matches!(
+            Value::new("True", &DataType::Boolean).unwrap(),
+            Value::Boolean(true)
+        ));
+    }
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            normalize_url(" HTTPS://Example.com:443/Path/ "),
+            "https://example.com/Path"
+        );
+        assert_eq!(
+            normalize_url("http://localhost:9883/"),
+            "http://localhost:9883"
+        );
+        assert_eq!(
+            normalize_url("https://example.com/?q=a/"),
+            "https://example.com/?q=a/"
+        );
+        assert_eq!(normalize_url("not a url "), "not a url");
+        assert_eq!(
+            normalized(
+                Value::AtomicUrl("https://example.com/a/".into()),
+                DataType::AtomicUrl
+            ),
+            Some("https://example.com/a".into())
+        );
+        assert_eq!(
+            normalize_value(
+                &Value::from(vec!["https://example.com/a/", "https://example.com/b"]),
+                &property(DataType::ResourceArray, false)
+            )
+            .unwrap()
+            .to_subjects(None)
+            .unwrap(),
+            vec!["https://example.com/a", "https://example.com/b"]
+        );
+    }
+
+    #[test]
+    fn other_datatypes_are_unchanged() {
+        assert!(normalized(Value::Integer(1), DataType::Integer).is_none());
+        assert!(normalized(Value::Boolean(true), DataType::Boolean).is_none());
+        assert!(normalized(Value::Timestamp(1), DataType::Timestamp).is_none());
+    }
+}
@@ -0,0 +1,265 @@
+/*!
+Requests that a server sends to other servers, such as fetching remote Resources, importing from a URL or checking links.
+These URLs often come from users, so without a check they could be used to reach services that are only meant to be reached from inside the network (server-side request forgery).
+
+[OutboundHttp] checks every destination before connecting:
+
+- Hosts and networks on the allow list are always permitted.
+- Hosts and networks on the deny list, and loopback, private (RFC 1918), link-local and other internal addresses ([DEFAULT_DENY]) are refused.
+- Host names are resolved first, and every address they resolve to is checked. The connection uses the checked addresses, so a second DNS answer can't point it elsewhere.
+- Redirects are followed one hop at a time, and every hop is checked again.
+
+Refused requests fail with [crate::AtomicErrorType::OutboundBlocked], and are counted per [OutboundFeature], see [OutboundHttp::report].
+
+The policy types are always available, so a config can be parsed without an HTTP client. [OutboundHttp] needs the `client` feature.
+*/
+
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{errors::AtomicResult, AtomicError};
+
+/// Networks that are refused unless they are allowed explicitly.
+pub const DEFAULT_DENY: &[&str] = &[
+    // "This" network, loopback, private, shared (carrier-grade NAT) and link-local IPv4
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    // Unspecified, loopback, unique local and link-local IPv6
+    "::/128",
+    "::1/128",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+const FEATURES: usize = 9;
+
+/// The parts of the server that send requests to other servers. Each can be disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboundFeature {
+    /// Fetching Resources from other servers that are not in the store yet
+    Resolve,
+    /// Importing a document from a URL
+    Import,
+    /// Creating a preview of a bookmarked web page
+    Bookmark,
+    /// Fetching remote JSON-LD `@context`s
+    JsonLdContext,
+    /// Checking whether links to other servers still work
+    LinkCheck,
+    /// Following the primary of a read replica
+    Replication,
+    /// Purging changed URLs from a CDN
+    CdnPurge,
+    /// Fetching the title, description and image of a web page for `/preview`
+    LinkPreview,
+    /// Sending Commits to the URLs of Webhooks
+    Webhook,
+}
+
+impl OutboundFeature {
+    pub const ALL: [OutboundFeature; FEATURES] = [
+        OutboundFeature::Resolve,
+        OutboundFeature::Import,
+        OutboundFeature::Bookmark,
+        OutboundFeature::JsonLdContext,
+        OutboundFeature::LinkCheck,
+        OutboundFeature::Replication,
+        OutboundFeature::CdnPurge,
+        OutboundFeature::LinkPreview,
+        OutboundFeature::Webhook,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutboundFeature::Resolve => "resolve",
+            OutboundFeature::Import => "import",
+            OutboundFeature::Bookmark => "bookmark",
+            OutboundFeature::JsonLdContext => "json-ld-context",
+            OutboundFeature::LinkCheck => "link-check",
+            OutboundFeature::Replication => "replication",
+            OutboundFeature::CdnPurge => "cdn-purge",
+            OutboundFeature::LinkPreview => "link-preview",
+            OutboundFeature::Webhook => "webhook",
+        }
+    }
+}
+
+impl FromStr for OutboundFeature {
+    type Err = AtomicError;
+
+    fn from_str(s: &str) -> AtomicResult<Self> {
+        OutboundFeature::ALL
+            .into_iter()
+            .find(|feature| feature.as_str() == s)
+            .ok_or_else(|| {
+                let names: Vec<&str> = OutboundFeature::ALL.iter().map(|f| f.as_str()).collect();
+                format!(
+                    "Unknown outbound feature '{}', use one of {}",
+                    s,
+                    names.join(", ")
+                )
+                .into()
+            })
+    }
+}
+
+/// A host name (`example.com`), all subdomains of a domain (`*.example.com`), or a network (`10.1.0.0/16`, or a single address).
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostRule {
+    Host(String),
+    /// Matches subdomains, not the domain itself
+    Subdomains(String),
+    Network {
+        address: IpAddr,
+        prefix: u8,
+    },
+}
+
+impl FromStr for HostRule {
+    type Err = AtomicError;
+
+    fn from_str(s: &str) -> AtomicResult<Self> {
+        let s = s.trim();
+        let (address, prefix) = match s.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (s, None),
+        };
+        if let Ok(address) = address.trim_matches(['[', ']']).parse::<IpAddr>() {
+            let max = if address.is_ipv4() { 32 } else { 128 };
+            let prefix = match prefix {
+                Some(prefix) => prefix
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|prefix| *prefix <= max)
+                    .ok_or(format!("Invalid network prefix in '{}'", s))?,
+                None => max,
+            };
+            return Ok(HostRule::Network { address, prefix });
+        }
+        if prefix.is_some() || s.is_empty() {
+            return Err(format!("'{}' is not a host name or network", s).into());
+        }
+        match s.strip_prefix("*.") {
+            Some(domain) => Ok(HostRule::Subdomains(domain.to_lowercase())),
+            None => Ok(HostRule::Host(s.to_lowercase())),
+        }
+    }
+}
+
+impl HostRule {
+    /// Matches the host of the URL, e.g. to allow a destination that the operator configured.
+    pub fn from_url(url: &str) -> AtomicResult<HostRule> {
+        let parsed = url::Url::parse(url).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+        parsed
+            .host_str()
+            .ok_or(format!("{} has no host", url))?
+            .parse()
+    }
+
+    /// Whether the rule matches the host name. Networks only match addresses, see [HostRule::matches_ip].
+    pub fn matches_host(&self, host: &str) -> bool {
+        match self {
+            HostRule::Host(name) => name == host,
+            HostRule::Subdomains(domain) => host
+                .strip_suffix(domain.as_str())
+                .map(|rest| rest.ends_with('.'))
+                .unwrap_or(false),
+            HostRule::Network { .. } => false,
+        }
+    }
+
+    /// Whether the address is in the network of the rule.
+    pub fn matches_ip(&self, ip: IpAddr) -> bool {
+        let HostRule::Network { address, prefix } = self else {
+            return false;
+        };
+        match (address, canonical(ip)) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - *prefix as u32).unwrap_or(0);
+                u32::from(*network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - *prefix as u32).unwrap_or(0);
+                u128::from(*network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// IPv4 addresses written as IPv6 (`::ffff:127.0.0.1`) are checked as IPv4.
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(v6)),
+        v4 => v4,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutboundConfig {
+    /// Always permitted, also when they are denied or internal
+    pub allow: Vec<HostRule>,
+    /// Refused in addition to [DEFAULT_DENY]
+    pub deny: Vec<HostRule>,
+    pub timeout: Duration,
+    /// Overrides the `timeout` for some destinations. The first matching rule is used.
+    pub host_timeouts: Vec<(HostRule, Duration)>,
+    /// Responses that are larger than this many bytes are refused
+    pub max_response_size: u64,
+    pub max_redirects: u32,
+    pub disabled: Vec<OutboundFeature>,
+}
+
+impl Default for OutboundConfig {
+    fn default() -> Self {
+        OutboundConfig {
+            allow: Vec::new(),
+            deny: Vec::new(),
+            timeout: Duration::from_secs(10),
+            host_timeouts: Vec::new(),
+            max_response_size: 10 * 1024 * 1024,
+            max_redirects: 5,
+            disabled: Vec::new(),
+        }
+    }
+}
+
+/// Looks up the addresses of a host. Replaced in tests, so host names can resolve to any address.
+pub trait HostResolver: Send + Sync {
+    fn resolve(&self, host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>>;
+}
+
+/// Uses the resolver of the operating system.
+pub struct SystemResolver;
+
+impl HostResolver for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+        Ok((host, port).to_socket_addrs()?.collect())
+    }
+}
+
+/// How many requests were refused, per [OutboundFeature]. Shown at `/metrics`.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboundReport {
+    pub blocked: BTreeMap<&'static str, u64>,
+}
+
+#[cfg(feature = "client")]
+mod http;
+#[cfg(feature = "client")]
+pub use http::OutboundHttp;
@@ -0,0 +1,455 @@
+//! [OutboundHttp], the HTTP client that applies the [OutboundConfig]. Needs the `client` feature.
+
+use std::{
+    io::Read,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use super::*;
+
+/// Added to errors of the resolver, so refused connections can be told apart from failed lookups.
+const BLOCKED_MARKER: &str = "[outbound blocked]";
+
+impl OutboundFeature {
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// Decides which destinations are permitted. Shared with the resolver of the HTTP agent.
+struct Policy {
+    allow: Vec<HostRule>,
+    deny: Vec<HostRule>,
+    resolver: Arc<dyn HostResolver>,
+}
+
+impl Policy {
+    /// Returns the addresses to connect to, or why the destination is refused.
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+        let host = host.trim_matches(['[', ']']).to_lowercase();
+        let lookup = |host: &str| {
+            self.resolver
+                .resolve(host, port)
+                .map_err(|e| format!("Could not resolve {}: {}", host, e))
+        };
+        if self.allow.iter().any(|rule| rule.matches_host(&host)) {
+            return lookup(&host);
+        }
+        if self.deny.iter().any(|rule| rule.matches_host(&host)) {
+            return Err(format!("{} is on the deny list", host));
+        }
+        let addresses = lookup(&host)?;
+        if addresses.is_empty() {
+            return Err(format!("{} has no addresses", host));
+        }
+        for address in &addresses {
+            let ip = address.ip();
+            if self.allow.iter().any(|rule| rule.matches_ip(ip)) {
+                continue;
+            }
+            let internal = DEFAULT_DENY
+                .iter()
+                .filter_map(|network| network.parse::<HostRule>().ok())
+                .any(|rule| rule.matches_ip(ip));
+            if internal || self.deny.iter().any(|rule| rule.matches_ip(ip)) {
+                return Err(format!(
+                    "{} resolves to {}, which is not allowed",
+                    host,
+                    canonical(ip)
+                ));
+            }
+        }
+        Ok(addresses)
+    }
+}
+
+/// Checks the addresses again when the HTTP agent connects, so the connection can't end up at another address than the one that was checked.
+struct GuardedResolver(Arc<Policy>);
+
+impl ureq::Resolver for GuardedResolver {
+    fn resolve(&self, netloc: &str) -> std::io::Result<Vec<SocketAddr>> {
+        let (host, port) = netloc
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse().ok()?)))
+            .ok_or_else(|| std::io::Error::other(format!("Invalid address {}", netloc)))?;
+        self.0.resolve(host, port).map_err(|reason| {
+            std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("{} {}", BLOCKED_MARKER, reason),
+            )
+        })
+    }
+}
+
+/// Sends requests to other servers, and refuses destinations that are not permitted.
+/// Cheap to clone, clones share the same counters.
+#[derive(Clone)]
+pub struct OutboundHttp {
+    config: Arc<OutboundConfig>,
+    policy: Arc<Policy>,
+    agent: ureq::Agent,
+    blocked: Arc<[AtomicU64; FEATURES]>,
+}
+
+impl Default for OutboundHttp {
+    fn default() -> Self {
+        OutboundHttp::new(OutboundConfig::default())
+    }
+}
+
+impl std::fmt::Debug for OutboundHttp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutboundHttp")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OutboundHttp {
+    pub fn new(config: OutboundConfig) -> Self {
+        OutboundHttp::with_resolver(config, Arc::new(SystemResolver))
+    }
+
+    pub fn with_resolver(config: OutboundConfig, resolver: Arc<dyn HostResolver>) -> Self {
+        let policy = Arc::new(Policy {
+            allow: config.allow.clone(),
+            deny: config.deny.clone(),
+            resolver,
+        });
+        let agent = ureq::AgentBuilder::new()
+            .timeout(config.timeout)
+            // Redirects are followed by [OutboundHttp::request], which checks every hop
+            .redirects(0)
+            .resolver(GuardedResolver(policy.clone()))
+            .build();
+        OutboundHttp {
+            config: Arc::new(config),
+            policy,
+            agent,
+            blocked: Default::default(),
+        }
+    }
+
+    pub fn is_enabled(&self, feature: OutboundFeature) -> bool {
+        !self.config.disabled.contains(&feature)
+    }
+
+    /// Returns an error if the feature is disabled, or if the URL points to a destination that is not permitted.
+    pub fn check(&self, feature: OutboundFeature, url: &str) -> AtomicResult<()> {
+        let url = self.parse_url(feature, url)?;
+        let host = url.host_str().unwrap_or_default();
+        let port = url.port_or_known_default().unwrap_or(80);
+        self.policy
+            .resolve(host, port)
+            .map_err(|reason| self.blocked_error(feature, reason))?;
+        Ok(())
+    }
+
+    fn parse_url(&self, feature: OutboundFeature, url: &str) -> AtomicResult<url::Url> {
+        if !self.is_enabled(feature) {
+            return Err(self.blocked_error(
+                feature,
+                format!("requests for {} are disabled", feature.as_str()),
+            ));
+        }
+        let parsed = url::Url::parse(url).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+        if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+            return Err(self.blocked_error(feature, format!("{} is not an http or https URL", url)));
+        }
+        Ok(parsed)
+    }
+
+    /// Sends a request, and follows redirects of GET and HEAD requests after checking them.
+    /// Returns the response for any status, including errors.
+    pub fn request(
+        &self,
+        feature: OutboundFeature,
+        method: &str,
+        url: &str,
+        headers: &[(String, String)],
+        body: Option<&[u8]>,
+    ) -> AtomicResult<ureq::Response> {
+        self.send(&self.agent, true, feature, method, url, headers, body)
+    }
+
+    /// Sends a GET request for a response that is read for a long time, such as an event stream.
+    /// Instead of a timeout for the whole request, reading fails when nothing is received for `read_timeout`.
+    pub fn stream(
+        &self,
+        feature: OutboundFeature,
+        url: &str,
+        headers: &[(String, String)],
+        read_timeout: Duration,
+    ) -> AtomicResult<ureq::Response> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(self.config.timeout)
+            .timeout_read(read_timeout)
+            .redirects(0)
+            .resolver(GuardedResolver(self.policy.clone()))
+            .build();
+        self.send(&agent, false, feature, "GET", url, headers, None)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn send(
+        &self,
+        agent: &ureq::Agent,
+        with_timeout: bool,
+        feature: OutboundFeature,
+        method: &str,
+        url: &str,
+        headers: &[(String, String)],
+        body: Option<&[u8]>,
+    ) -> AtomicResult<ureq::Response> {
+        let mut url = url.to_string();
+        let follows_redirects = matches!(method, "GET" | "HEAD");
+        for _hop in 0..=self.config.max_redirects {
+            self.check(feature, &url)?;
+            let mut request = agent.request(method, &url);
+            if with_timeout {
+                request = request.timeout(self.timeout_for(&url));
+            }
+            for (key, value) in headers {
+                request = request.set(key, value);
+            }
+            let result = match body {
+                Some(body) => request.send_bytes(body),
+                None => request.call(),
+            };
+            let response = match result {
+                Ok(response) => response,
+                Err(ureq::Error::Status(_, response)) => response,
+                Err(ureq::Error::Transport(e)) if e.to_string().contains(BLOCKED_MARKER) => {
+                    return Err(self.blocked_error(feature, e.to_string()));
+                }
+                Err(ureq::Error::Transport(e)) => {
+                    return Err(format!("Request to {} failed: {}", url, e).into())
+                }
+            };
+            let location = response.header("Location");
+            match (response.status(), location) {
+                (301 | 302 | 303 | 307 | 308, Some(location)) if follows_redirects => {
+                    url = url::Url::parse(&url)
+                        .and_then(|base| base.join(location))
+                        .map_err(|e| format!("Invalid redirect from {}: {}", url, e))?
+                        .to_string();
+                }
+                _ => return Ok(response),
+            }
+        }
+        Err(format!(
+            "Too many redirects, stopped after {}",
+            self.config.max_redirects
+        )
+        .into())
+    }
+
+    /// Sends a GET request, and returns the status and the body.
+    pub fn get_string(
+        &self,
+        feature: OutboundFeature,
+        url: &str,
+        accept: &str,
+        headers: &[(String, String)],
+    ) -> AtomicResult<(u16, String)> {
+        let mut headers = headers.to_vec();
+        headers.push(("Accept".into(), accept.into()));
+        let response = self.request(feature, "GET", url, &headers, None)?;
+        let status = response.status();
+        let body = self.read_string(response)?;
+        Ok((status, body))
+    }
+
+    /// Reads the body of a response, up to the maximum response size.
+    pub fn read_string(&self, response: ureq::Response) -> AtomicResult<String> {
+        let url = response.get_url().to_string();
+        let mut body = Vec::new();
+        response
+            .into_reader()
+            .take(self.config.max_response_size + 1)
+            .read_to_end(&mut body)
+            .map_err(|e| format!("Could not read the response of {}: {}", url, e))?;
+        if body.len() as u64 > self.config.max_response_size {
+            return Err(format!(
+                "The response of {} is larger than {} bytes",
+                url, self.config.max_response_size
+            )
+            .into());
+        }
+        String::from_utf8(body)
+            .map_err(|e| format!("The response of {} is not valid UTF-8: {}", url, e).into())
+    }
+
+    pub fn report(&self) -> OutboundReport {
+        OutboundReport {
+            blocked: OutboundFeature::ALL
+                .iter()
+                .map(|feature| {
+                    (
+                        feature.as_str(),
+                        self.blocked[feature.index()].load(Ordering::Relaxed),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn timeout_for(&self, url: &str) -> Duration {
+        let host = url::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(|host| host.to_lowercase()))
+            .unwrap_or_default();
+        let ip = host.trim_matches(['[', ']']).parse::<IpAddr>().ok();
+        self.config
+            .host_timeouts
+            .iter()
+            .find(|(rule, _)| rule.matches_host(&host) || ip.is_some_and(|ip| rule.matches_ip(ip)))
+            .map(|(_, timeout)| *timeout)
+            .unwrap_or(self.config.timeout)
+    }
+
+    fn blocked_error(&self, feature: OutboundFeature, reason: String) -> AtomicError {
+        self.blocked[feature.index()].fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            "Refused outbound request for {}: {}",
+            feature.as_str(),
+            reason
+        );
+        AtomicError::outbound_blocked(format!(
+            "Refused request for {}: {}",
+            feature.as_str(),
+            reason
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::AtomicErrorType;
+
+    /// Resolves the listed names to fixed addresses.
+    struct FakeResolver(HashMap<&'static str, IpAddr>);
+
+    impl HostResolver for FakeResolver {
+        fn resolve(&self, host: &str, port: u16) -> std::io::Result<Vec<SocketAddr>> {
+            match self.0.get(host) {
+                Some(ip) => Ok(vec![SocketAddr::new(*ip, port)]),
+                None => SystemResolver.resolve(host, port),
+            }
+        }
+    }
+
+    fn is_blocked(result: AtomicResult<impl std::fmt::Debug>) -> bool {
+        matches!(
+            result.unwrap_err().error_type,
+            AtomicErrorType::OutboundBlocked
+        )
+    }
+
+    #[test]
+    fn names_resolving_to_internal_addresses_are_refused() {
+        let resolver = FakeResolver(HashMap::from([
+            ("intranet.example.com", "10.1.2.3".parse().unwrap()),
+            ("mapped.example.com", "::ffff:127.0.0.1".parse().unwrap()),
+            ("public.example.com", "93.184.216.34".parse().unwrap()),
+        ]));
+        let config = OutboundConfig {
+            allow: vec!["wiki.example.com".parse().unwrap()],
+            deny: vec!["*.tracker.example".parse().unwrap()],
+            ..Default::default()
+        };
+        let outbound = OutboundHttp::with_resolver(config, Arc::new(resolver));
+        let feature = OutboundFeature::Resolve;
+
+        assert!(is_blocked(
+            outbound.check(feature, "https://intranet.example.com/secret")
+        ));
+        assert!(is_blocked(
+            outbound.check(feature, "http://mapped.example.com/")
+        ));
+        assert!(is_blocked(outbound.check(feature, "http://[::1]:9883/")));
+        assert!(is_blocked(
+            outbound.check(feature, "http://169.254.169.254/latest")
+        ));
+        assert!(is_blocked(
+            outbound.check(feature, "https://a.tracker.example/")
+        ));
+        assert!(is_blocked(outbound.check(feature, "file:///etc/passwd")));
+        outbound
+            .check(feature, "https://public.example.com/resource")
+            .unwrap();
+        assert_eq!(outbound.report().blocked["resolve"], 6);
+    }
+
+    #[test]
+    fn redirects_to_internal_addresses_are_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                BufReader::new(&stream)
+                    .read_line(&mut request_line)
+                    .unwrap();
+                let response = if request_line.contains("/start") {
+                    format!(
+                        "HTTP/1.1 302 Found\r\nLocation: http://127.0.0.1:{}/admin\r\nContent-Length: 0\r\n\r\n",
+                        port
+                    )
+                } else {
+                    "HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecret".to_string()
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        // The name is allowed explicitly, the address it redirects to is not
+        let resolver = FakeResolver(HashMap::from([(
+            "docs.example.com",
+            "127.0.0.1".parse().unwrap(),
+        )]));
+        let config = OutboundConfig {
+            allow: vec!["docs.example.com".parse().unwrap()],
+            ..Default::default()
+        };
+        let outbound = OutboundHttp::with_resolver(config, Arc::new(resolver));
+        let start = format!("http://docs.example.com:{}/start", port);
+        assert!(is_blocked(outbound.get_string(
+            OutboundFeature::Import,
+            &start,
+            "text/html",
+            &[]
+        )));
+        assert_eq!(outbound.report().blocked["import"], 1);
+
+        let admin = format!("http://docs.example.com:{}/admin", port);
+        let (status, body) = outbound
+            .get_string(OutboundFeature::Import, &admin, "text/html", &[])
+            .unwrap();
+        assert_eq!((status, body.as_str()), (200, "secret"));
+    }
+
+    #[test]
+    fn disabled_features_send_nothing() {
+        let config = OutboundConfig {
+            disabled: vec![OutboundFeature::Bookmark],
+            ..Default::default()
+        };
+        let outbound = OutboundHttp::new(config);
+        assert!(is_blocked(
+            outbound.check(OutboundFeature::Bookmark, "https://example.com")
+        ));
+        assert!(outbound.is_enabled(OutboundFeature::Resolve));
+    }
+}
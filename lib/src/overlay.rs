@@ -0,0 +1,312 @@
+/*!
+A [Storelike] that shows another store as it would be after some changes, without changing it.
+Useful for dry runs and previews: apply Commits to an [Overlay], inspect the result, and drop it.
+
+Reads check the pending changes of the overlay first, and then the base store.
+Writes (including applied Commits, and the Commit Resources they create) only go to the overlay.
+Queries combine the results of the base store with the pending changes, so they are correct, but slower than those of the base store.
+Use [Overlay::diff] to see the pending changes.
+*/
+
+use std::{collections::BTreeMap, sync::Mutex};
+
+use crate::{
+    agents::{Agent, ForAgent},
+    atoms::Atom,
+    errors::AtomicResult,
+    hierarchy,
+    storelike::{Query, QueryResult},
+    values::SubResource,
+    AtomicError, Resource, Storelike, Value,
+};
+
+/// A pending change of an [Overlay]: the new version of the Resource, or `None` if it was removed.
+pub type Diff = BTreeMap<String, Option<Resource>>;
+
+pub struct Overlay<'a, S: Storelike> {
+    base: &'a S,
+    changes: Mutex<Diff>,
+    /// Set using [Storelike::set_default_agent], falls back to the Agent of the base store
+    default_agent: Mutex<Option<Agent>>,
+}
+
+impl<'a, S: Storelike> Overlay<'a, S> {
+    pub fn new(base: &'a S) -> Self {
+        Overlay {
+            base,
+            changes: Mutex::new(BTreeMap::new()),
+            default_agent: Mutex::new(None),
+        }
+    }
+
+    /// Returns the Resources that were changed or removed, by subject.
+    pub fn diff(&self) -> Diff {
+        self.changes.lock().unwrap().clone()
+    }
+
+    /// Whether the query filter matches the Resource. Arrays match if one of their items does.
+    fn matches(&self, resource: &Resource, q: &Query) -> bool {
+        if !q.include_external && !resource.get_subject().starts_with(self.get_server_url()) {
+            return false;
+        }
+        let in_range = match &q.sort_by {
+            Some(sort_by) if q.start_val.is_some() || q.end_val.is_some() => {
+                let sortable = resource
+                    .get(sort_by)
+                    .map(|value| value.to_sortable_string())
+                    .unwrap_or_default();
+                q.start_val
+                    .as_ref()
+                    .map(|start| sortable >= start.to_sortable_string())
+                    .unwrap_or(true)
+                    && q.end_val
+                        .as_ref()
+                        .map(|end| sortable <= end.to_sortable_string())
+                        .unwrap_or(true)
+            }
+            _ => true,
+        };
+        if !in_range {
+            return false;
+        }
+        if q.property.is_none() && q.value.is_none() {
+            return true;
+        }
+        resource.get_propvals().iter().any(|(prop, value)| {
+            if q.property.as_ref().map(|p| p != prop).unwrap_or(false) {
+                return false;
+            }
+            match &q.value {
+                None => true,
+                Some(wanted) => value_matches(value, &wanted.to_string()),
+            }
+        })
+    }
+}
+
+fn value_matches(value: &Value, wanted: &str) -> bool {
+    match value {
+        Value::ResourceArray(items) => items.iter().any(|item| match item {
+            SubResource::Subject(subject) => subject == wanted,
+            SubResource::Resource(resource) => resource.get_subject() == wanted,
+            SubResource::Nested(_) => false,
+        }),
+        other => other.to_string() == wanted,
+    }
+}
+
+impl<'a, S: Storelike> Storelike for Overlay<'a, S> {
+    fn add_atoms(&self, atoms: Vec<Atom>) -> AtomicResult<()> {
+        let mut map: BTreeMap<String, Resource> = BTreeMap::new();
+        for atom in atoms {
+            let resource = map
+                .entry(atom.subject.clone())
+                .or_insert_with(|| Resource::new(atom.subject.clone()));
+            resource.set_propval(atom.property, atom.value, self)?;
+        }
+        for resource in map.values() {
+            self.add_resource(resource)?
+        }
+        Ok(())
+    }
+
+    fn add_resource_opts(
+        &self,
+        resource: &Resource,
+        check_required_props: bool,
+        _update_index: bool,
+        overwrite_existing: bool,
+    ) -> AtomicResult<()> {
+        if check_required_props {
+            resource.check_required_props(self)?;
+        }
+        let subject = resource.get_subject();
+        if !overwrite_existing && self.get_resource(subject).is_ok() {
+            return Err(format!("{} already present, will not overwrite.", subject).into());
+        }
+        self.changes
+            .lock()
+            .unwrap()
+            .insert(subject.clone(), Some(resource.clone()));
+        Ok(())
+    }
+
+    fn all_resources(&self, include_external: bool) -> Box<dyn Iterator<Item = Resource>> {
+        let changes = self.diff();
+        let mut resources: Vec<Resource> = self
+            .base
+            .all_resources(include_external)
+            .filter(|resource| !changes.contains_key(resource.get_subject()))
+            .collect();
+        let server_url = self.get_server_url();
+        resources.extend(
+            changes.into_values().flatten().filter(|resource| {
+                include_external || resource.get_subject().starts_with(server_url)
+            }),
+        );
+        Box::new(resources.into_iter())
+    }
+
+    fn get_server_url(&self) -> &str {
+        self.base.get_server_url()
+    }
+
+    fn get_self_url(&self) -> Option<String> {
+        self.base.get_self_url()
+    }
+
+    fn get_default_agent(&self) -> AtomicResult<Agent> {
+        match self.default_agent.lock().unwrap().to_owned() {
+            Some(agent) => Ok(agent),
+            None => self.base.get_default_agent(),
+        }
+    }
+
+    /// Resources that are not in the overlay are read from the base store.
+    /// Note that the base store may fetch and save external Resources that it does not have yet, like any other read.
+    fn get_resource(&self, subject: &str) -> AtomicResult<Resource> {
+        match self.changes.lock().unwrap().get(subject) {
+            Some(Some(resource)) => return Ok(resource.clone()),
+            Some(None) => {
+                return Err(AtomicError::not_found(format!(
+                    "{} is removed in this overlay",
+                    subject
+                )))
+            }
+            None => {}
+        }
+        self.base.get_resource(subject)
+    }
+
+    /// The locks of the base store apply to the overlay too, so a dry run fails like the real thing would.
+    fn get_max_hierarchy_depth(&self) -> usize {
+        self.base.get_max_hierarchy_depth()
+    }
+
+    fn get_locks(&self) -> Option<&crate::locks::LockRegistry> {
+        self.base.get_locks()
+    }
+
+    fn get_subject_locks(&self) -> Option<&crate::commit::SubjectLocks> {
+        self.base.get_subject_locks()
+    }
+
+    #[cfg(feature = "client")]
+    fn get_outbound(&self) -> Option<crate::outbound::OutboundHttp> {
+        self.base.get_outbound()
+    }
+
+    fn remove_resource(&self, subject: &str) -> AtomicResult<()> {
+        self.get_resource(subject).map_err(|_| {
+            format!(
+                "Resource {} could not be deleted, because it is not found",
+                subject
+            )
+        })?;
+        self.changes.lock().unwrap().insert(subject.into(), None);
+        Ok(())
+    }
+
+    fn set_default_agent(&self, agent: Agent) {
+        self.default_agent.lock().unwrap().replace(agent);
+    }
+
+    /// Runs the query without paging on the base store, replaces the changed Resources, and then sorts, checks rights and pages.
+    /// Like any query, a sorted query that the base store has not seen before adds that query to the index of the base store.
+    fn query(&self, q: &Query) -> AtomicResult<QueryResult> {
+        let changes = self.diff();
+        let mut base_query = q.clone();
+        base_query.limit = None;
+        base_query.offset = 0;
+        base_query.include_nested = true;
+        base_query.for_agent = ForAgent::Sudo;
+        let mut resources: Vec<Resource> = self
+            .base
+            .query(&base_query)?
+            .resources
+            .into_iter()
+            .filter(|resource| !changes.contains_key(resource.get_subject()))
+            .collect();
+        resources.extend(
+            changes
+                .into_values()
+                .flatten()
+                .filter(|resource| self.matches(resource, q)),
+        );
+
+        match &q.sort_by {
+            Some(sort_by) => {
+                resources = crate::collections::sort_resources(resources, sort_by, q.sort_desc)
+            }
+            None => resources.sort_by(|a, b| a.get_subject().cmp(b.get_subject())),
+        }
+        let count = resources.len();
+        let resources: Vec<Resource> = resources
+            .into_iter()
+            .filter(|resource| hierarchy::check_read(self, resource, &q.for_agent).is_ok())
+            .skip(q.offset)
+            .take(q.limit.unwrap_or(usize::MAX))
+            .collect();
+        let subjects = resources
+            .iter()
+            .map(|resource| resource.get_subject().clone())
+            .collect();
+        Ok(QueryResult {
+            count,
+            subjects,
+            resources: if q.include_nested {
+                resources
+            } else {
+                Vec::new()
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{urls, Store};
+
+    fn snapshot_of(store: &impl Storelike) -> Vec<String> {
+        let mut resources: Vec<String> = store
+            .all_resources(true)
+            .map(|resource| resource.to_json_ad().unwrap())
+            .collect();
+        resources.sort();
+        resources
+    }
+
+    #[test]
+    fn changes_stay_in_the_overlay() {
+        let store = Store::init().unwrap();
+        store.populate().unwrap();
+        let before = snapshot_of(&store);
+
+        let overlay = Overlay::new(&store);
+        let mut resource = overlay.get_resource(urls::DESCRIPTION).unwrap();
+        resource
+            .set_propval_string(urls::SHORTNAME.into(), "changed", &overlay)
+            .unwrap();
+        overlay.add_resource(&resource).unwrap();
+        overlay.remove_resource(urls::NAME).unwrap();
+
+        assert_eq!(
+            overlay
+                .get_value(urls::DESCRIPTION, urls::SHORTNAME)
+                .unwrap()
+                .to_string(),
+            "changed"
+        );
+        assert!(overlay.get_resource(urls::NAME).is_err());
+        assert!(!overlay
+            .all_resources(true)
+            .any(|resource| resource.get_subject() == urls::NAME));
+        let diff = overlay.diff();
+        assert_eq!(diff.len(), 2);
+        assert!(diff[urls::NAME].is_none());
+
+        drop(overlay);
+        assert_eq!(snapshot_of(&store), before);
+    }
+}
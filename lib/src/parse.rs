@@ -1,11 +1,23 @@
 //! Parsing / deserialization / decoding
 
 use crate::{
-    agents::ForAgent, commit::CommitOpts, datatype::DataType, errors::AtomicResult,
-    resources::PropVals, urls, utils::check_valid_url, values::SubResource, AtomicError, Resource,
-    Storelike, Value,
+    agents::ForAgent,
+    commit::CommitOpts,
+    datatype::DataType,
+    errors::AtomicResult,
+    resources::PropVals,
+    urls,
+    utils::{check_valid_url, is_relative_reference},
+    values::SubResource,
+    AtomicError, Resource, Storelike, Value,
 };
 
+mod json_ld;
+mod stream;
+
+pub use self::json_ld::{is_json_ld, parse_json_ld_string, JsonLdOpts, JSON_LD_MIME};
+pub use self::stream::{import_json_ad_stream, ImportEvent, ImportMode, ImportSummary};
+
 pub const JSON_AD_MIME: &str = "application/ad+json";
 
 pub fn parse_json_array(string: &str) -> AtomicResult<Vec<String>> {
@@ -37,6 +49,9 @@ pub struct ParseOpts {
     /// This can be a dangerous value if true, because it can overwrite _all_ resources where the `for_agen` has write rights.
     /// Only parse items from sources that you trust!
     pub overwrite_outside: bool,
+    /// The declared base URL of the document. Relative references (like `./attachment` or `#section`) in URL values are resolved against it.
+    /// If [None], they are resolved against the `@id` of the Resource they are in, see [crate::utils::resolve_reference].
+    pub base: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -62,6 +77,7 @@ impl std::default::Default for ParseOpts {
             for_agent: ForAgent::Sudo,
             overwrite_outside: true,
             save: SaveOpts::Save,
+            base: None,
         }
     }
 }
@@ -181,10 +197,10 @@ fn parse_json_ad_map_to_resource(
 
     // Converts a string to a URL (subject), check for localid
     let try_to_subject = |s: &str, prop: &str| -> AtomicResult<String> {
-        if check_valid_url(s).is_ok() {
+        if check_valid_url(s).is_ok() || is_relative_reference(s) {
             Ok(s.into())
         } else if let Some(importer) = &parse_opts.importer {
-            Ok(generate_id_from_local_id(importer, s))
+            Ok(generate_id_from_local_id(store, importer, s))
         } else {
             Err(AtomicError::parse_error(
                 &format!("Unable to parse string as URL: {}", s),
@@ -228,7 +244,10 @@ fn parse_json_ad_map_to_resource(
                 let property = store.get_property(&prop)?;
                 // Also converts numbers to strings, not sure what to think about this.
                 // Does not result in invalid atomic data, but does allow for weird inputs
-                Value::new(&num.to_string(), &property.data_type)?
+                Value::new(&num.to_string(), &property.data_type).map_err(|e| {
+                    AtomicError::parse_error(&e.message, subject.as_deref(), Some(&prop))
+                        .set_property(&property)
+                })?
             }
             serde_json::Value::String(str) => {
                 // LocalIDs are mapped to @ids by appending the `localId` to the `importer`'s `parent`.
@@ -239,7 +258,7 @@ fn parse_json_ad_map_to_resource(
                             subject.as_deref(),
                             Some(&prop),
                         ))?;
-                    subject = Some(generate_id_from_local_id(parent, &str));
+                    subject = Some(generate_id_from_local_id(store, parent, &str));
                 }
                 let property = store.get_property(&prop).map_err(|e| {
                     AtomicError::parse_error(
@@ -249,18 +268,23 @@ fn parse_json_ad_map_to_resource(
                     )
                 })?;
 
-                match property.data_type {
+                match &property.data_type {
                     DataType::AtomicUrl => {
                         // If the value is not a valid URL, and we have an importer, we can generate_id_from_local_id
                         let url = try_to_subject(&str, &prop)?;
-                        Value::new(&url, &property.data_type)?
+                        if is_relative_reference(&url) {
+                            Value::AtomicUrl(url)
+                        } else {
+                            Value::new(&url, &property.data_type)?
+                        }
                     }
-                    other => Value::new(&str.to_string(), &other).map_err(|e| {
+                    other => Value::new(&str.to_string(), other).map_err(|e| {
                         AtomicError::parse_error(
                             &format!("Unable to parse value for prop {prop}: {e}. Value: {str}"),
                             subject.as_deref(),
                             Some(&prop),
                         )
+                        .set_property(&property)
                     })?,
                 }
             }
@@ -297,9 +321,17 @@ fn parse_json_ad_map_to_resource(
         // Some of these values are _not correctly matched_ to the datatype.
         propvals.insert(prop, atomic_val);
     }
-    // if there is no parent set, we set it to the Importer
+    // Commits have no `@id`, so their relative references are kept. They are resolved when the Commit is applied.
+    if let Some(base) = parse_opts.base.as_ref().or(subject.as_ref()) {
+        for (prop, val) in propvals.iter_mut() {
+            *val = val.resolve_references(base, false).map_err(|e| {
+                AtomicError::parse_error(&e.message, subject.as_deref(), Some(prop))
+            })?;
+        }
+    }
+    // if there is no parent set, we set it to the Importer, unless the Resource is the Importer itself
     if let Some(importer) = &parse_opts.importer {
-        if !propvals.contains_key(urls::PARENT) {
+        if !propvals.contains_key(urls::PARENT) && subject.as_ref() != Some(importer) {
             propvals.insert(urls::PARENT.into(), Value::AtomicUrl(importer.into()));
         }
     }
@@ -349,6 +381,7 @@ fn parse_json_ad_map_to_resource(
                     validate_previous_commit: false,
                     validate_for_agent: Some(parse_opts.for_agent.to_string()),
                     update_index: true,
+                    validate_relative_urls: false,
                 };
 
                 commit
@@ -364,8 +397,13 @@ fn parse_json_ad_map_to_resource(
     }
 }
 
-fn generate_id_from_local_id(importer_subject: &str, local_id: &str) -> String {
-    format!("{}/{}", importer_subject, local_id)
+/// Uses the [crate::subjects::SubjectStrategy] of the Drive of the importer, if it has one.
+fn generate_id_from_local_id(
+    store: &impl crate::Storelike,
+    importer_subject: &str,
+    local_id: &str,
+) -> String {
+    crate::subjects::subject_from_local_id(store, importer_subject, local_id)
 }
 
 #[cfg(test)]
@@ -545,11 +583,12 @@ mod test {
             for_agent: ForAgent::Sudo,
             overwrite_outside: false,
             importer: Some(importer.clone()),
+            base: None,
         };
 
         store.import(json, &parse_opts).unwrap();
 
-        let imported_subject = generate_id_from_local_id(&importer, local_id);
+        let imported_subject = generate_id_from_local_id(&store, &importer, local_id);
 
         let found = store.get_resource(&imported_subject).unwrap();
         println!("{:?}", found);
@@ -557,6 +596,67 @@ mod test {
         assert_eq!(found.get(urls::LOCAL_ID).unwrap().to_string(), local_id);
     }
 
+    #[test]
+    fn import_relative_references() {
+        let (store, importer) = create_store_and_importer();
+        let json = r##"[{
+            "@id": "https://localhost/docs/guide",
+            "https://atomicdata.dev/properties/destination": "./attachment-1",
+            "https://atomicdata.dev/properties/subresources": ["#intro", "https://example.com/other"]
+          }, {
+            "https://atomicdata.dev/properties/localId": "notes",
+            "https://atomicdata.dev/properties/destination": "../shared/image"
+          }]"##;
+        let parse_opts = ParseOpts {
+            save: SaveOpts::Commit,
+            signer: Some(store.get_default_agent().unwrap()),
+            importer: Some(importer.clone()),
+            base: Some("https://example.com/docs/page".into()),
+            ..Default::default()
+        };
+        store.import(json, &parse_opts).unwrap();
+        let guide = store.get_resource("https://localhost/docs/guide").unwrap();
+        assert_eq!(
+            guide.get(urls::DESTINATION).unwrap().to_string(),
+            "https://example.com/docs/attachment-1"
+        );
+        assert_eq!(
+            guide
+                .get(urls::SUBRESOURCES)
+                .unwrap()
+                .to_subjects(None)
+                .unwrap(),
+            vec![
+                "https://example.com/docs/page#intro",
+                "https://example.com/other"
+            ]
+        );
+        let notes = store
+            .get_resource(&generate_id_from_local_id(&store, &importer, "notes"))
+            .unwrap();
+        assert_eq!(
+            notes.get(urls::DESTINATION).unwrap().to_string(),
+            "https://example.com/shared/image"
+        );
+
+        // Without a declared base, the `@id` is the base.
+        // Resources with only a localId get a subject from the importer, which can't be a base here.
+        let json = r##"[{
+            "@id": "https://localhost/docs/guide",
+            "https://atomicdata.dev/properties/destination": "./attachment-1"
+          }]"##;
+        let parse_opts = ParseOpts {
+            base: None,
+            ..parse_opts
+        };
+        store.import(json, &parse_opts).unwrap();
+        let guide = store.get_resource("https://localhost/docs/guide").unwrap();
+        assert_eq!(
+            guide.get(urls::DESTINATION).unwrap().to_string(),
+            "https://localhost/docs/attachment-1"
+        );
+    }
+
     #[test]
     fn import_resources_localid_references() {
         let (store, importer) = create_store_and_importer();
@@ -567,14 +667,15 @@ mod test {
             signer: Some(store.get_default_agent().unwrap()),
             overwrite_outside: false,
             importer: Some(importer.clone()),
+            base: None,
         };
 
         store
             .import(include_str!("../test_files/local_id.json"), &parse_opts)
             .unwrap();
 
-        let reference_subject = generate_id_from_local_id(&importer, "reference");
-        let my_subject = generate_id_from_local_id(&importer, "my-local-id");
+        let reference_subject = generate_id_from_local_id(&store, &importer, "reference");
+        let my_subject = generate_id_from_local_id(&store, &importer, "my-local-id");
         let found = store.get_resource(&my_subject).unwrap();
         let found_ref = store.get_resource(&reference_subject).unwrap();
 
@@ -625,6 +726,7 @@ mod test {
             for_agent: agent.subject.into(),
             overwrite_outside: false,
             importer: Some(importer),
+            base: None,
         };
 
         // We can't allow this to happen, so we expect an error
@@ -0,0 +1,79 @@
+//! Parses JSON-LD documents (e.g. schema.org data) into Resources.
+//!
+//! Supports a subset of the JSON-LD expansion algorithm: term definitions, prefixes, `@vocab`, `@base`,
+//! typed values, `@list` / `@set` and `@graph`.
+//! The expanded document is converted to JSON-AD, which is then parsed using [super::parse_json_ad_string],
+//! so all [super::ParseOpts] (importer, saving, rights) apply in the same way.
+//!
+//! Detecting JSON-LD is always available. Parsing it requires the `json-ld` feature,
+//! without it [parse_json_ld_string] returns an error that names the feature.
+
+#[cfg(feature = "json-ld")]
+mod expand;
+#[cfg(feature = "json-ld")]
+pub use expand::parse_json_ld_string;
+
+#[cfg(not(feature = "json-ld"))]
+use crate::{errors::AtomicResult, parse::ParseOpts, AtomicError, Resource, Storelike};
+
+pub const JSON_LD_MIME: &str = "application/ld+json";
+
+/// Options that are specific for JSON-LD. See [super::ParseOpts] for the general options.
+#[derive(Debug, Clone, Default)]
+pub struct JsonLdOpts {
+    /// Fetch `@context`s that are referenced by URL.
+    /// Disabled by default, because it makes the import depend on (and send requests to) other servers.
+    /// Bundled contexts, like schema.org, are always available.
+    pub fetch_remote_contexts: bool,
+    /// Create a Property for every IRI that does not exist in the store, with a datatype derived from its value.
+    /// If false, unknown IRIs result in an error.
+    pub create_missing_properties: bool,
+}
+
+/// Whether a JSON document looks like JSON-LD instead of JSON-AD.
+/// JSON-AD documents never contain a `@context` or `@graph`.
+pub fn is_json_ld(string: &str) -> bool {
+    let Ok(parsed) = serde_json::from_str::<serde_json::Value>(string) else {
+        return false;
+    };
+    let first = match &parsed {
+        serde_json::Value::Array(items) => items.first(),
+        other => Some(other),
+    };
+    matches!(first, Some(serde_json::Value::Object(map)) if map.contains_key("@context") || map.contains_key("@graph"))
+}
+
+/// Parses a JSON-LD document. This build does not enable the `json-ld` feature, so this always fails.
+#[cfg(not(feature = "json-ld"))]
+pub fn parse_json_ld_string(
+    _string: &str,
+    _store: &impl Storelike,
+    _parse_opts: &ParseOpts,
+    _ld_opts: &JsonLdOpts,
+) -> AtomicResult<Vec<Resource>> {
+    Err(AtomicError::missing_feature("json-ld", "Parsing JSON-LD"))
+}
+
+#[cfg(all(test, not(feature = "json-ld")))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn json_ld_without_feature_is_detected_and_refused() {
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let document = r#"{ "@context": "https://schema.org", "name": "Ada" }"#;
+        assert!(is_json_ld(document));
+        let err = parse_json_ld_string(
+            document,
+            &store,
+            &ParseOpts::default(),
+            &JsonLdOpts::default(),
+        )
+        .unwrap_err();
+        assert!(err.message.contains("`json-ld`"), "{}", err);
+        let agent = store.get_resource(crate::urls::AGENT).unwrap();
+        assert!(agent.to_json_ld(&store).is_err());
+        assert!(agent.to_json(&store).is_ok());
+    }
+}
@@ -0,0 +1,774 @@
+//! Expands JSON-LD documents and converts them to JSON-AD. Needs the `json-ld` feature.
+
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Map;
+
+use crate::{
+    datatype::DataType,
+    errors::AtomicResult,
+    schema::Property,
+    storelike::Query,
+    urls,
+    utils::{check_valid_url, date_to_millis, datetime_to_millis, random_string},
+    Resource, Storelike, Value,
+};
+
+use super::{JsonLdOpts, JSON_LD_MIME};
+use crate::parse::{parse_json_ad_string, ParseOpts, SaveOpts};
+
+/// Contexts that are often used and are resolved without fetching them.
+const BUNDLED_CONTEXTS: &[(&str, &str)] = &[
+    ("https://schema.org", "https://schema.org/"),
+    ("https://schema.org/", "https://schema.org/"),
+    ("http://schema.org", "https://schema.org/"),
+    ("http://schema.org/", "https://schema.org/"),
+];
+/// Maximum depth of nested (remote) contexts and term definitions.
+const MAX_CONTEXT_DEPTH: usize = 8;
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+
+/// Parses a JSON-LD document, which can be a single node, an array of nodes or a `@graph`.
+/// Properties are matched by their expanded IRI. Nodes without a (non-blank) `@id` become nested Resources,
+/// or get a `localId` when they are at the top level.
+#[tracing::instrument(skip(store, string))]
+pub fn parse_json_ld_string(
+    string: &str,
+    store: &impl Storelike,
+    parse_opts: &ParseOpts,
+    ld_opts: &JsonLdOpts,
+) -> AtomicResult<Vec<Resource>> {
+    if ld_opts.create_missing_properties && parse_opts.save == SaveOpts::DontSave {
+        return Err("Creating missing Properties requires saving the parsed Resources.".into());
+    }
+    let parsed: serde_json::Value =
+        serde_json::from_str(string).map_err(|e| format!("Invalid JSON: {}", e))?;
+    let mut converter = Converter {
+        store,
+        parse_opts,
+        ld_opts,
+        properties: HashMap::new(),
+        local_properties: local_instances(store, urls::PROPERTY)?,
+        local_classes: local_instances(store, urls::CLASS)?,
+    };
+
+    let root = Context::default();
+    let mut nodes = Vec::new();
+    converter.collect_nodes(&root, parsed, &mut nodes)?;
+
+    let mut json_ad = Vec::new();
+    for (context, node) in nodes {
+        let mut map = converter.convert_node(&context, node)?;
+        if !map.contains_key("@id") && parse_opts.importer.is_some() {
+            map.insert(
+                urls::LOCAL_ID.into(),
+                serde_json::Value::String(random_string(10)),
+            );
+        }
+        json_ad.push(serde_json::Value::Object(map));
+    }
+    let json_ad = serde_json::Value::Array(json_ad).to_string();
+    parse_json_ad_string(&json_ad, store, parse_opts)
+}
+
+/// The active context, used to expand terms to IRIs.
+#[derive(Clone, Default, Debug)]
+struct Context {
+    vocab: Option<String>,
+    base: Option<String>,
+    terms: HashMap<String, Term>,
+}
+
+#[derive(Clone, Debug)]
+struct Term {
+    /// Not yet expanded, as it can refer to prefixes that are defined later in the same context
+    id: String,
+    /// The `@type` of values: `@id`, `@vocab` or a datatype IRI
+    type_mapping: Option<String>,
+}
+
+/// A value after expansion, before it is converted to JSON-AD.
+#[derive(Debug)]
+enum LdValue {
+    Literal {
+        value: serde_json::Value,
+        datatype: Option<String>,
+    },
+    Reference(String),
+    /// A node, already converted to a JSON-AD object
+    Node(Map<String, serde_json::Value>),
+    List(Vec<LdValue>),
+}
+
+impl Context {
+    /// Expands a term, compact IRI or relative IRI. Returns `None` if it can't be expanded to an absolute IRI.
+    fn expand_iri(&self, value: &str, vocab: bool) -> Option<String> {
+        self.expand_iri_depth(value, vocab, 0)
+    }
+
+    fn expand_iri_depth(&self, value: &str, vocab: bool, depth: usize) -> Option<String> {
+        if value.starts_with('@') || depth > MAX_CONTEXT_DEPTH {
+            return Some(value.into());
+        }
+        if vocab {
+            // Terms like `{ "sameAs": { "@type": "@id" } }` only set a type, their IRI comes from the vocab
+            if let Some(term) = self.terms.get(value).filter(|term| term.id != value) {
+                return self.expand_iri_depth(&term.id, true, depth + 1);
+            }
+        }
+        if let Some((prefix, suffix)) = value.split_once(':') {
+            if prefix == "_" || suffix.starts_with("//") {
+                return Some(value.into());
+            }
+            if let Some(term) = self.terms.get(prefix) {
+                let prefix = self.expand_iri_depth(&term.id, true, depth + 1)?;
+                return Some(format!("{}{}", prefix, suffix));
+            }
+            return Some(value.into());
+        }
+        if vocab {
+            if let Some(vocab) = &self.vocab {
+                return Some(format!("{}{}", vocab, value));
+            }
+        }
+        let base = url::Url::parse(self.base.as_deref()?).ok()?;
+        base.join(value).ok().map(|url| url.to_string())
+    }
+
+    fn type_mapping(&self, term: &str) -> Option<&str> {
+        self.terms.get(term)?.type_mapping.as_deref()
+    }
+}
+
+struct Converter<'a, S: Storelike> {
+    store: &'a S,
+    parse_opts: &'a ParseOpts,
+    ld_opts: &'a JsonLdOpts,
+    /// Properties by IRI, including the ones that were created during this import
+    properties: HashMap<String, Property>,
+    /// Subjects of the Properties and Classes in the store.
+    /// Other IRIs are not looked up, as that would fetch them from the web.
+    local_properties: HashSet<String>,
+    local_classes: HashSet<String>,
+}
+
+fn local_instances(store: &impl Storelike, class: &str) -> AtomicResult<HashSet<String>> {
+    let mut query = Query::new_class(class);
+    query.include_external = true;
+    query.include_nested = false;
+    Ok(store.query(&query)?.subjects.into_iter().collect())
+}
+
+impl<S: Storelike> Converter<'_, S> {
+    /// Finds the top-level nodes, each with the context that applies to it.
+    fn collect_nodes(
+        &self,
+        active: &Context,
+        value: serde_json::Value,
+        nodes: &mut Vec<(Context, Map<String, serde_json::Value>)>,
+    ) -> AtomicResult<()> {
+        match value {
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    self.collect_nodes(active, item, nodes)?;
+                }
+                Ok(())
+            }
+            serde_json::Value::Object(mut map) => {
+                let context = match map.remove("@context") {
+                    Some(local) => self.process_context(active, &local, 0)?,
+                    None => active.clone(),
+                };
+                match map.remove("@graph") {
+                    Some(graph) => self.collect_nodes(&context, graph, nodes),
+                    None => {
+                        nodes.push((context, map));
+                        Ok(())
+                    }
+                }
+            }
+            other => Err(format!("Expected a JSON-LD node object, got: {}", other).into()),
+        }
+    }
+
+    fn process_context(
+        &self,
+        active: &Context,
+        local: &serde_json::Value,
+        depth: usize,
+    ) -> AtomicResult<Context> {
+        if depth > MAX_CONTEXT_DEPTH {
+            return Err("The @context is nested too deeply.".into());
+        }
+        match local {
+            serde_json::Value::Null => Ok(Context::default()),
+            serde_json::Value::Array(contexts) => {
+                let mut context = active.clone();
+                for local in contexts {
+                    context = self.process_context(&context, local, depth + 1)?;
+                }
+                Ok(context)
+            }
+            serde_json::Value::String(url) => {
+                if let Some((_, vocab)) = BUNDLED_CONTEXTS.iter().find(|(u, _)| u == url) {
+                    let mut context = active.clone();
+                    context.vocab = Some(vocab.to_string());
+                    return Ok(context);
+                }
+                if !self.ld_opts.fetch_remote_contexts {
+                    return Err(format!(
+                        "The remote @context {} is not fetched, because fetching remote contexts is disabled. Enable it, or include the context in the document.",
+                        url
+                    )
+                    .into());
+                }
+                let body = crate::client::fetch_body_for(
+                    self.store,
+                    crate::outbound::OutboundFeature::JsonLdContext,
+                    url,
+                    JSON_LD_MIME,
+                    None,
+                )
+                .map_err(|e| format!("Unable to fetch @context {}: {}", url, e))?;
+                let remote: serde_json::Value = serde_json::from_str(&body)
+                    .map_err(|e| format!("The @context at {} is not valid JSON: {}", url, e))?;
+                let remote = remote
+                    .get("@context")
+                    .ok_or_else(|| format!("The document at {} has no @context", url))?;
+                self.process_context(active, remote, depth + 1)
+            }
+            serde_json::Value::Object(definitions) => {
+                let mut context = active.clone();
+                for (key, definition) in definitions {
+                    match (key.as_str(), definition) {
+                        ("@vocab", serde_json::Value::String(vocab)) => {
+                            context.vocab = Some(vocab.clone())
+                        }
+                        ("@vocab", serde_json::Value::Null) => context.vocab = None,
+                        ("@base", serde_json::Value::String(base)) => {
+                            context.base = Some(base.clone())
+                        }
+                        ("@base", serde_json::Value::Null) => context.base = None,
+                        // Other keywords, such as @language and @version, don't change how we expand terms
+                        (keyword, _) if keyword.starts_with('@') => {}
+                        (term, serde_json::Value::Null) => {
+                            context.terms.remove(term);
+                        }
+                        (term, serde_json::Value::String(id)) => {
+                            context.terms.insert(
+                                term.into(),
+                                Term {
+                                    id: id.clone(),
+                                    type_mapping: None,
+                                },
+                            );
+                        }
+                        (term, serde_json::Value::Object(expanded)) => {
+                            let id = match expanded.get("@id") {
+                                Some(serde_json::Value::String(id)) => id.clone(),
+                                _ => term.to_string(),
+                            };
+                            let type_mapping = match expanded.get("@type") {
+                                Some(serde_json::Value::String(t)) => Some(t.clone()),
+                                _ => None,
+                            };
+                            context.terms.insert(term.into(), Term { id, type_mapping });
+                        }
+                        (term, other) => {
+                            return Err(format!(
+                                "Invalid definition for term {} in @context: {}",
+                                term, other
+                            )
+                            .into())
+                        }
+                    }
+                }
+                // Datatypes in term definitions can use prefixes, so they are expanded after all terms are known.
+                let expanded_types: Vec<(String, String)> = context
+                    .terms
+                    .iter()
+                    .filter_map(|(term, def)| {
+                        let mapping = def.type_mapping.as_deref()?;
+                        if mapping.starts_with('@') {
+                            return None;
+                        }
+                        Some((term.clone(), context.expand_iri(mapping, true)?))
+                    })
+                    .collect();
+                for (term, mapping) in expanded_types {
+                    if let Some(def) = context.terms.get_mut(&term) {
+                        def.type_mapping = Some(mapping);
+                    }
+                }
+                Ok(context)
+            }
+            other => Err(format!("Invalid @context: {}", other).into()),
+        }
+    }
+
+    /// Converts a node object to a JSON-AD object.
+    fn convert_node(
+        &mut self,
+        active: &Context,
+        mut node: Map<String, serde_json::Value>,
+    ) -> AtomicResult<Map<String, serde_json::Value>> {
+        let context = match node.remove("@context") {
+            Some(local) => self.process_context(active, &local, 0)?,
+            None => active.clone(),
+        };
+        let mut json_ad = Map::new();
+        for (key, value) in node {
+            match key.as_str() {
+                "@id" => {
+                    let id = value.as_str().ok_or("@id must be a string")?;
+                    let id = context.expand_iri(id, false).unwrap_or_else(|| id.into());
+                    // Blank nodes and other non-HTTP identifiers become nested Resources
+                    if check_valid_url(&id).is_ok() {
+                        json_ad.insert("@id".into(), serde_json::Value::String(id));
+                    }
+                }
+                "@type" => {
+                    let classes = self.convert_types(&context, &value)?;
+                    if !classes.is_empty() {
+                        json_ad.insert(urls::IS_A.into(), classes.into());
+                    }
+                }
+                // @reverse, @index and other keywords are not supported
+                keyword if keyword.starts_with('@') => {}
+                term => {
+                    // Terms that can't be expanded to an IRI are ignored, as the JSON-LD algorithm does.
+                    let Some(iri) = context.expand_iri(term, true) else {
+                        continue;
+                    };
+                    if !iri.contains(':') {
+                        continue;
+                    }
+                    let type_mapping = context.type_mapping(term).map(|t| t.to_string());
+                    let Some(value) =
+                        self.convert_value(&context, value, type_mapping.as_deref())?
+                    else {
+                        continue;
+                    };
+                    let property = self.get_property(&iri, term, &value)?;
+                    if let Some(json) = to_json_ad(value, &property)? {
+                        json_ad.insert(iri, json);
+                    }
+                }
+            }
+        }
+        Ok(json_ad)
+    }
+
+    /// Maps `@type`s to Classes in the store. Unknown types are an error, unless missing Properties are created.
+    fn convert_types(
+        &self,
+        context: &Context,
+        value: &serde_json::Value,
+    ) -> AtomicResult<Vec<String>> {
+        let types: Vec<&str> = match value {
+            serde_json::Value::String(t) => vec![t.as_str()],
+            serde_json::Value::Array(types) => types.iter().filter_map(|t| t.as_str()).collect(),
+            other => return Err(format!("@type must be a string or array, got {}", other).into()),
+        };
+        let mut classes = Vec::new();
+        for t in types {
+            let iri = context.expand_iri(t, true).unwrap_or_else(|| t.into());
+            if self.local_classes.contains(&iri) {
+                classes.push(iri);
+            } else if !self.ld_opts.create_missing_properties {
+                return Err(format!(
+                    "No Class found for @type {} ({}). Create it first, or enable creating missing Properties to skip unknown types.",
+                    iri, t
+                )
+                .into());
+            }
+        }
+        Ok(classes)
+    }
+
+    fn convert_value(
+        &mut self,
+        context: &Context,
+        value: serde_json::Value,
+        type_mapping: Option<&str>,
+    ) -> AtomicResult<Option<LdValue>> {
+        let converted = match value {
+            serde_json::Value::Null => return Ok(None),
+            serde_json::Value::Array(items) => {
+                let mut list = Vec::new();
+                for item in items {
+                    if let Some(item) = self.convert_value(context, item, type_mapping)? {
+                        list.push(item);
+                    }
+                }
+                LdValue::List(list)
+            }
+            serde_json::Value::String(s) => match type_mapping {
+                Some("@id") => LdValue::Reference(context.expand_iri(&s, false).unwrap_or(s)),
+                Some("@vocab") => LdValue::Reference(context.expand_iri(&s, true).unwrap_or(s)),
+                _ => LdValue::Literal {
+                    value: serde_json::Value::String(s),
+                    datatype: type_mapping.map(|t| t.to_string()),
+                },
+            },
+            serde_json::Value::Object(mut map) => {
+                if let Some(value) = map.remove("@value") {
+                    let datatype = match map.get("@type") {
+                        Some(serde_json::Value::String(t)) => context.expand_iri(t, true),
+                        _ => None,
+                    };
+                    LdValue::Literal { value, datatype }
+                } else if let Some(list) = map.remove("@list").or_else(|| map.remove("@set")) {
+                    return self.convert_value(context, list, type_mapping);
+                } else if map.len() == 1 && map.contains_key("@id") {
+                    let id = map.remove("@id").unwrap();
+                    let id = id.as_str().ok_or("@id must be a string")?;
+                    LdValue::Reference(context.expand_iri(id, false).unwrap_or_else(|| id.into()))
+                } else {
+                    LdValue::Node(self.convert_node(context, map)?)
+                }
+            }
+            literal => LdValue::Literal {
+                value: literal,
+                datatype: type_mapping.map(|t| t.to_string()),
+            },
+        };
+        Ok(Some(converted))
+    }
+
+    /// Finds the Property for an IRI, or creates it if that is enabled.
+    fn get_property(&mut self, iri: &str, term: &str, value: &LdValue) -> AtomicResult<Property> {
+        if let Some(property) = self.properties.get(iri) {
+            return Ok(property.clone());
+        }
+        let property = if self.local_properties.contains(iri) {
+            self.store.get_property(iri)?
+        } else if self.ld_opts.create_missing_properties {
+            self.create_property(iri, value)?
+        } else {
+            return Err(format!(
+                "No Property found for {} ({}). Create it first, or enable creating missing Properties.",
+                iri, term
+            )
+            .into());
+        };
+        self.properties.insert(iri.into(), property.clone());
+        Ok(property)
+    }
+
+    fn create_property(&self, iri: &str, value: &LdValue) -> AtomicResult<Property> {
+        let property = Property {
+            subject: iri.into(),
+            shortname: shortname_from_iri(iri),
+            description: format!("Created while importing JSON-LD, see {}", iri),
+            data_type: infer_datatype(value),
+            class_type: None,
+            allows_only: None,
+            keep_whitespace: false,
+            minimum: None,
+            maximum: None,
+        };
+        let mut resource = property.to_resource();
+        let parent = self
+            .parse_opts
+            .importer
+            .clone()
+            .unwrap_or_else(|| self.store.get_server_url().to_string());
+        resource.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(parent));
+        self.store.add_resource(&resource)?;
+        tracing::info!("Created Property {} while importing JSON-LD", iri);
+        Ok(property)
+    }
+}
+
+/// Derives a datatype for a Property that is created during the import.
+fn infer_datatype(value: &LdValue) -> DataType {
+    match value {
+        LdValue::List(items) if items.len() == 1 => infer_datatype(&items[0]),
+        LdValue::List(_) => DataType::ResourceArray,
+        LdValue::Reference(_) | LdValue::Node(_) => DataType::AtomicUrl,
+        LdValue::Literal {
+            datatype: Some(datatype),
+            ..
+        } => match datatype.strip_prefix(XSD).unwrap_or("") {
+            "integer" | "int" | "long" | "short" | "nonNegativeInteger" | "positiveInteger" => {
+                DataType::Integer
+            }
+            "decimal" | "double" | "float" => DataType::Float,
+            "boolean" => DataType::Boolean,
+            "dateTime" => DataType::Timestamp,
+            "date" => DataType::Date,
+            _ => DataType::String,
+        },
+        LdValue::Literal { value, .. } => match value {
+            serde_json::Value::Bool(_) => DataType::Boolean,
+            serde_json::Value::Number(n) if n.is_i64() => DataType::Integer,
+            serde_json::Value::Number(_) => DataType::Float,
+            _ => DataType::String,
+        },
+    }
+}
+
+/// Converts an expanded value to JSON-AD that matches the datatype of the Property.
+/// Returns `None` for empty lists.
+fn to_json_ad(value: LdValue, property: &Property) -> AtomicResult<Option<serde_json::Value>> {
+    if property.data_type == DataType::ResourceArray {
+        let items = match value {
+            LdValue::List(items) => items,
+            single => vec![single],
+        };
+        let mut array = Vec::new();
+        for item in items {
+            array.push(match item {
+                LdValue::Reference(iri) => serde_json::Value::String(iri),
+                LdValue::Node(map) => serde_json::Value::Object(map),
+                LdValue::Literal {
+                    value: serde_json::Value::String(s),
+                    ..
+                } => serde_json::Value::String(s),
+                other => {
+                    return Err(format!(
+                        "{} only accepts references to Resources, got {:?}",
+                        property.subject, other
+                    )
+                    .into())
+                }
+            });
+        }
+        return Ok(Some(serde_json::Value::Array(array)));
+    }
+
+    let value = match value {
+        LdValue::List(mut items) => match items.len() {
+            0 => return Ok(None),
+            1 => items.remove(0),
+            n => {
+                return Err(format!(
+                    "{} accepts a single value, but {} values were given",
+                    property.subject, n
+                )
+                .into())
+            }
+        },
+        single => single,
+    };
+    let json = match value {
+        LdValue::Reference(iri) => serde_json::Value::String(iri),
+        LdValue::Node(map) => serde_json::Value::Object(map),
+        LdValue::List(_) => {
+            return Err(format!("Nested lists are not supported for {}", property.subject).into())
+        }
+        LdValue::Literal { value, .. } => convert_literal(value, &property.data_type)
+            .map_err(|e| format!("Invalid value for {}: {}", property.subject, e))?,
+    };
+    Ok(Some(json))
+}
+
+fn convert_literal(
+    value: serde_json::Value,
+    datatype: &DataType,
+) -> AtomicResult<serde_json::Value> {
+    use serde_json::Value as Json;
+    let converted = match (datatype, value) {
+        (DataType::Timestamp, Json::String(s)) => datetime_to_millis(&s)
+            .map(Json::from)
+            .ok_or_else(|| format!("'{}' is not a valid dateTime", s))?,
+        (DataType::Date, Json::String(s)) if date_to_millis(&s).is_none() => {
+            // Keep only the date part of a dateTime
+            let date = s.split('T').next().unwrap_or_default().to_string();
+            date_to_millis(&date).ok_or_else(|| format!("'{}' is not a valid date", s))?;
+            Json::String(date)
+        }
+        (DataType::Integer, Json::String(s)) => {
+            Json::from(s.trim().parse::<i64>().map_err(|e| e.to_string())?)
+        }
+        (DataType::Float, Json::String(s)) => {
+            Json::from(s.trim().parse::<f64>().map_err(|e| e.to_string())?)
+        }
+        (DataType::Boolean, Json::String(s)) => {
+            Json::Bool(s.parse::<bool>().map_err(|e| e.to_string())?)
+        }
+        (DataType::Integer | DataType::Float | DataType::Timestamp | DataType::Boolean, other) => {
+            other
+        }
+        (_, Json::String(s)) => Json::String(s),
+        (_, other) => Json::String(other.to_string()),
+    };
+    Ok(converted)
+}
+
+/// Creates a valid shortname from the last part of an IRI, e.g. `https://schema.org/birthDate` becomes `birth-date`.
+fn shortname_from_iri(iri: &str) -> String {
+    let last = iri
+        .trim_end_matches(['/', '#'])
+        .rsplit(['/', '#', ':'])
+        .next()
+        .unwrap_or_default();
+    let mut shortname = String::new();
+    for c in last.chars() {
+        if c.is_ascii_uppercase() && !shortname.is_empty() && !shortname.ends_with('-') {
+            shortname.push('-');
+        }
+        if c.is_ascii_alphanumeric() {
+            shortname.push(c.to_ascii_lowercase());
+        } else if !shortname.ends_with('-') {
+            shortname.push('-');
+        }
+    }
+    let shortname = shortname.trim_matches('-').to_string();
+    if shortname.is_empty() {
+        "property".into()
+    } else {
+        shortname
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{parse::is_json_ld, Store};
+
+    const PERSON_AND_ARTICLE: &str = r#"{
+        "@context": [
+            "https://schema.org",
+            {
+                "xsd": "http://www.w3.org/2001/XMLSchema#",
+                "published": { "@id": "datePublished", "@type": "xsd:dateTime" },
+                "sameAs": { "@type": "@id" }
+            }
+        ],
+        "@graph": [
+            {
+                "@id": "https://example.com/people/ada",
+                "@type": "Person",
+                "name": "Ada Lovelace",
+                "birthDate": { "@value": "1815-12-10", "@type": "xsd:date" },
+                "sameAs": "https://en.wikipedia.org/wiki/Ada_Lovelace",
+                "address": {
+                    "@type": "PostalAddress",
+                    "addressLocality": "London"
+                }
+            },
+            {
+                "@id": "https://example.com/articles/notes",
+                "@type": "Article",
+                "headline": "Notes on the Analytical Engine",
+                "published": "1843-10-01T00:00:00Z",
+                "wordCount": 20000,
+                "author": { "@id": "https://example.com/people/ada" }
+            }
+        ]
+    }"#;
+
+    fn store() -> Store {
+        let store = Store::init().unwrap();
+        store.populate().unwrap();
+        store
+    }
+
+    #[test]
+    fn imports_schema_org_person_and_article() {
+        let store = store();
+        let ld_opts = JsonLdOpts {
+            create_missing_properties: true,
+            ..Default::default()
+        };
+        let resources =
+            parse_json_ld_string(PERSON_AND_ARTICLE, &store, &ParseOpts::default(), &ld_opts)
+                .unwrap();
+        assert_eq!(resources.len(), 2);
+
+        let ada = store
+            .get_resource("https://example.com/people/ada")
+            .unwrap();
+        assert_eq!(
+            ada.get("https://schema.org/name").unwrap().to_string(),
+            "Ada Lovelace"
+        );
+        assert!(matches!(
+            ada.get("https://schema.org/birthDate").unwrap(),
+            Value::Date(_)
+        ));
+        assert!(matches!(
+            ada.get("https://schema.org/address").unwrap(),
+            Value::NestedResource(_)
+        ));
+        let birth_date = store.get_property("https://schema.org/birthDate").unwrap();
+        assert_eq!(birth_date.shortname, "birth-date");
+
+        let article = store
+            .get_resource("https://example.com/articles/notes")
+            .unwrap();
+        let published = datetime_to_millis("1843-10-01T00:00:00Z").unwrap();
+        assert!(matches!(
+            article.get("https://schema.org/datePublished").unwrap(),
+            Value::Timestamp(t) if *t == published
+        ));
+        assert!(matches!(
+            article.get("https://schema.org/wordCount").unwrap(),
+            Value::Integer(20000)
+        ));
+        assert_eq!(
+            article
+                .get("https://schema.org/author")
+                .unwrap()
+                .to_string(),
+            "https://example.com/people/ada"
+        );
+    }
+
+    #[test]
+    fn unknown_properties_fail_in_strict_mode() {
+        let store = store();
+        let err = parse_json_ld_string(
+            PERSON_AND_ARTICLE,
+            &store,
+            &ParseOpts::default(),
+            &JsonLdOpts::default(),
+        )
+        .unwrap_err();
+        assert!(err.message.contains("schema.org/Person"), "{}", err);
+    }
+
+    #[test]
+    fn remote_context_fails_when_fetching_is_disabled() {
+        let store = store();
+        let document = r#"{
+            "@context": "https://example.com/contexts/person.jsonld",
+            "@id": "https://example.com/people/grace",
+            "name": "Grace Hopper"
+        }"#;
+        assert!(is_json_ld(document));
+        let err = parse_json_ld_string(
+            document,
+            &store,
+            &ParseOpts::default(),
+            &JsonLdOpts {
+                fetch_remote_contexts: false,
+                create_missing_properties: true,
+            },
+        )
+        .unwrap_err();
+        assert!(
+            err.message.contains("fetching remote contexts is disabled"),
+            "{}",
+            err
+        );
+        assert!(store
+            .get_resource("https://example.com/people/grace")
+            .is_err());
+    }
+
+    #[test]
+    fn shortnames_from_iris() {
+        assert_eq!(
+            shortname_from_iri("https://schema.org/birthDate"),
+            "birth-date"
+        );
+        assert_eq!(shortname_from_iri("http://xmlns.com/foaf/0.1/name"), "name");
+        assert_eq!(
+            shortname_from_iri("https://example.com/vocab#Zip_Code"),
+            "zip-code"
+        );
+    }
+}
@@ -0,0 +1,484 @@
+//! Imports large JSON-AD documents while they are being read, see [import_json_ad_stream].
+//!
+//! Only one Resource is kept in memory at a time, and progress is reported using [ImportEvent]s.
+//! Accepts a JSON array of objects, a single object, or line-delimited objects (one per line).
+
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader, Read},
+    str::FromStr,
+};
+
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserializer, Serialize,
+};
+use serde_json::Map;
+
+use crate::{errors::AtomicResult, urls, AtomicError, Resource, Storelike};
+
+use super::{generate_id_from_local_id, json_ad_object_to_resource, ParseOpts, SaveOpts};
+
+/// A [ImportEvent::Progress] is sent after this many Resources.
+const PROGRESS_INTERVAL: usize = 100;
+
+/// What happens when a Resource in an import fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ImportMode {
+    /// Stop at the first failure, and undo the changes of the Resources that were already imported.
+    #[default]
+    Abort,
+    /// Skip the Resources that fail, and import the rest.
+    Continue,
+}
+
+impl FromStr for ImportMode {
+    type Err = AtomicError;
+
+    fn from_str(s: &str) -> AtomicResult<Self> {
+        match s {
+            "abort" => Ok(ImportMode::Abort),
+            "continue" => Ok(ImportMode::Continue),
+            other => {
+                Err(format!("Unknown import mode '{}', use `abort` or `continue`", other).into())
+            }
+        }
+    }
+}
+
+/// Reports the progress of [import_json_ad_stream].
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ImportEvent {
+    /// Sent every [PROGRESS_INTERVAL] Resources.
+    Progress {
+        parsed: usize,
+        created: usize,
+        failed: usize,
+    },
+    /// A Resource could not be imported. `index` is its position in the document.
+    Failed {
+        index: usize,
+        subject: Option<String>,
+        error: String,
+    },
+    /// Always the last event, also when the import is aborted.
+    Summary(ImportSummary),
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    /// Resources read from the document.
+    pub parsed: usize,
+    /// Resources that were created or updated.
+    pub created: usize,
+    pub failed: usize,
+    /// Whether the import stopped before the end of the document.
+    pub aborted: bool,
+    /// Resources that were restored to their state from before the import, after aborting.
+    pub rolled_back: usize,
+    /// Why the import was aborted.
+    pub error: Option<String>,
+}
+
+/// Imports a JSON-AD document from `reader`, one Resource at a time.
+/// Calls `on_event` with the progress. If `on_event` returns an error (e.g. because the client is gone), the import is aborted.
+/// When aborted in [ImportMode::Abort], the Resources that were already imported are restored, and the Commits that changed them are removed.
+/// Returns an error when the import is aborted. The [ImportEvent::Summary] is sent in any case.
+#[tracing::instrument(skip(reader, store, on_event))]
+pub fn import_json_ad_stream(
+    reader: impl Read,
+    store: &impl Storelike,
+    parse_opts: &ParseOpts,
+    mode: ImportMode,
+    on_event: &mut dyn FnMut(&ImportEvent) -> AtomicResult<()>,
+) -> AtomicResult<ImportSummary> {
+    let mut importer = StreamImporter {
+        store,
+        parse_opts,
+        mode,
+        on_event,
+        summary: ImportSummary::default(),
+        originals: Vec::new(),
+        seen: HashSet::new(),
+        commits: Vec::new(),
+        stopped: None,
+    };
+    let result = importer.read(reader);
+    let mut summary = importer.summary.clone();
+    match result {
+        Ok(()) => {
+            // Everything has been imported, even if the summary can't be sent anymore
+            let _ = (importer.on_event)(&ImportEvent::Summary(summary.clone()));
+            Ok(summary)
+        }
+        Err(e) => {
+            summary.aborted = true;
+            summary.error = Some(e.to_string());
+            if mode == ImportMode::Abort {
+                summary.rolled_back = importer.rollback()?;
+            }
+            // The client might be gone, in which case nobody receives the summary
+            let _ = (importer.on_event)(&ImportEvent::Summary(summary.clone()));
+            Err(format!(
+                "Import aborted after {} of {} Resources, {} rolled back. {}",
+                summary.created, summary.parsed, summary.rolled_back, e
+            )
+            .into())
+        }
+    }
+}
+
+struct StreamImporter<'a, S: Storelike> {
+    store: &'a S,
+    parse_opts: &'a ParseOpts,
+    mode: ImportMode,
+    on_event: &'a mut dyn FnMut(&ImportEvent) -> AtomicResult<()>,
+    summary: ImportSummary,
+    /// The Resources as they were before the import changed them, [None] if they did not exist yet.
+    /// Only kept in [ImportMode::Abort].
+    originals: Vec<(String, Option<Resource>)>,
+    seen: HashSet<String>,
+    /// Commits created by the import. Only kept in [ImportMode::Abort].
+    commits: Vec<String>,
+    /// Why the import stopped while reading an array.
+    stopped: Option<AtomicError>,
+}
+
+impl<S: Storelike> StreamImporter<'_, S> {
+    fn read(&mut self, reader: impl Read) -> AtomicResult<()> {
+        let mut reader = BufReader::new(reader);
+        match peek_non_whitespace(&mut reader)? {
+            Some(b'[') => {
+                let mut deserializer = serde_json::Deserializer::from_reader(&mut reader);
+                let read = deserializer
+                    .deserialize_seq(Objects(self))
+                    .and_then(|_| deserializer.end());
+                match (read, self.stopped.take()) {
+                    (_, Some(stopped)) => Err(stopped),
+                    (Err(e), None) => Err(self.invalid_json(e)),
+                    (Ok(()), None) => Ok(()),
+                }
+            }
+            Some(b'{') => {
+                let objects =
+                    serde_json::Deserializer::from_reader(&mut reader)
+                        .into_iter::<Map<String, serde_json::Value>>();
+                for object in objects {
+                    let object = object.map_err(|e| self.invalid_json(e))?;
+                    self.import_object(object)?;
+                }
+                Ok(())
+            }
+            Some(_) => Err("Root JSON element must be an object or array.".into()),
+            None => Err("No JSON-AD found in the import".into()),
+        }
+    }
+
+    /// Reports a syntax error, after which the rest of the document can't be read.
+    fn invalid_json(&mut self, error: serde_json::Error) -> AtomicError {
+        let error = AtomicError::parse_error(&format!("Invalid JSON: {}", error), None, None);
+        self.summary.failed += 1;
+        let event = ImportEvent::Failed {
+            index: self.summary.parsed,
+            subject: None,
+            error: error.to_string(),
+        };
+        let _ = (self.on_event)(&event);
+        error
+    }
+
+    fn import_object(&mut self, object: Map<String, serde_json::Value>) -> AtomicResult<()> {
+        let index = self.summary.parsed;
+        self.summary.parsed += 1;
+        let importer = self.parse_opts.importer.as_deref();
+        let subject = object_subject(self.store, &object, importer);
+
+        let mut last_commits = Vec::new();
+        if self.mode == ImportMode::Abort {
+            let mut subjects = Vec::new();
+            subjects_in(self.store, &object, importer, &mut subjects);
+            for subject in subjects {
+                let existing = self.store.get_resource(&subject).ok();
+                let last_commit = existing
+                    .as_ref()
+                    .and_then(|r| r.get(urls::LAST_COMMIT).ok())
+                    .map(|v| v.to_string());
+                if self.seen.insert(subject.clone()) {
+                    self.originals.push((subject.clone(), existing));
+                }
+                last_commits.push((subject, last_commit));
+            }
+        }
+
+        match json_ad_object_to_resource(object, self.store, self.parse_opts) {
+            Ok(resource) => {
+                self.summary.created += 1;
+                // Other save methods don't add the Resource to the index, see [super::parse_json_ad_string]
+                if self.parse_opts.save != SaveOpts::Commit {
+                    for atom in resource.to_atoms() {
+                        self.store.add_atom_to_index(&atom, &resource)?;
+                    }
+                }
+                for (subject, before) in last_commits {
+                    let after = self
+                        .store
+                        .get_resource(&subject)
+                        .ok()
+                        .and_then(|r| r.get(urls::LAST_COMMIT).ok().map(|v| v.to_string()));
+                    if let Some(after) = after.filter(|after| Some(after) != before.as_ref()) {
+                        self.commits.push(after);
+                    }
+                }
+            }
+            Err(e) => {
+                self.summary.failed += 1;
+                (self.on_event)(&ImportEvent::Failed {
+                    index,
+                    subject,
+                    error: e.to_string(),
+                })?;
+                if self.mode == ImportMode::Abort {
+                    return Err(e);
+                }
+            }
+        }
+
+        if self.summary.parsed.is_multiple_of(PROGRESS_INTERVAL) {
+            (self.on_event)(&ImportEvent::Progress {
+                parsed: self.summary.parsed,
+                created: self.summary.created,
+                failed: self.summary.failed,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Restores the Resources that were changed by the import, newest first.
+    /// Returns how many Resources were restored.
+    fn rollback(&mut self) -> AtomicResult<usize> {
+        for commit in self.commits.drain(..).rev() {
+            // Commits are not stored when saving without them
+            let _ = self.store.remove_resource(&commit);
+        }
+        let mut count = 0;
+        for (subject, original) in self.originals.drain(..).rev() {
+            match original {
+                Some(original) => self.store.add_resource_opts(&original, false, true, true)?,
+                None => {
+                    if self.store.get_resource(&subject).is_err() {
+                        continue;
+                    }
+                    self.store.remove_resource(&subject)?
+                }
+            }
+            count += 1;
+        }
+        tracing::info!("Rolled back {} Resources of an aborted import", count);
+        Ok(count)
+    }
+}
+
+/// Imports the objects of a JSON array as soon as they are read.
+struct Objects<'i, 'a, S: Storelike>(&'i mut StreamImporter<'a, S>);
+
+impl<'de, S: Storelike> Visitor<'de> for Objects<'_, '_, S> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an array of JSON-AD objects")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(object) = seq.next_element::<Map<String, serde_json::Value>>()? {
+            if let Err(e) = self.0.import_object(object) {
+                self.0.stopped = Some(e);
+                return Err(de::Error::custom("import stopped"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Skips whitespace, and returns the next byte without consuming it.
+fn peek_non_whitespace(reader: &mut impl BufRead) -> std::io::Result<Option<u8>> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            return Ok(None);
+        }
+        let whitespace = buffer
+            .iter()
+            .take_while(|b| b.is_ascii_whitespace())
+            .count();
+        let next = buffer.get(whitespace).copied();
+        reader.consume(whitespace);
+        if next.is_some() {
+            return Ok(next);
+        }
+    }
+}
+
+/// The subject that the JSON-AD object will get, using its `@id` or `localId`.
+fn object_subject(
+    store: &impl Storelike,
+    object: &Map<String, serde_json::Value>,
+    importer: Option<&str>,
+) -> Option<String> {
+    if let Some(serde_json::Value::String(id)) = object.get("@id") {
+        return Some(id.clone());
+    }
+    match (object.get(urls::LOCAL_ID), importer) {
+        (Some(serde_json::Value::String(local_id)), Some(importer)) => {
+            Some(generate_id_from_local_id(store, importer, local_id))
+        }
+        _ => None,
+    }
+}
+
+/// Collects the subjects of the object and of the nested Resources that will be saved with it.
+fn subjects_in(
+    store: &impl Storelike,
+    object: &Map<String, serde_json::Value>,
+    importer: Option<&str>,
+    subjects: &mut Vec<String>,
+) {
+    if let Some(subject) = object_subject(store, object, importer) {
+        subjects.push(subject);
+    }
+    for value in object.values() {
+        let nested: Box<dyn Iterator<Item = &serde_json::Value>> = match value {
+            serde_json::Value::Array(items) => Box::new(items.iter()),
+            other => Box::new(std::iter::once(other)),
+        };
+        for item in nested {
+            if let serde_json::Value::Object(nested) = item {
+                subjects_in(store, nested, importer, subjects);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{agents::ForAgent, Store};
+
+    fn import(
+        store: &Store,
+        json: &str,
+        mode: ImportMode,
+    ) -> (AtomicResult<ImportSummary>, Vec<ImportEvent>) {
+        let opts = ParseOpts {
+            importer: Some("https://localhost/importer".into()),
+            for_agent: ForAgent::Sudo,
+            signer: Some(store.get_default_agent().unwrap()),
+            save: SaveOpts::Commit,
+            overwrite_outside: true,
+            base: None,
+        };
+        let mut events = Vec::new();
+        let result = import_json_ad_stream(json.as_bytes(), store, &opts, mode, &mut |event| {
+            events.push(event.clone());
+            Ok(())
+        });
+        (result, events)
+    }
+
+    fn resource(i: usize, description: serde_json::Value) -> serde_json::Value {
+        let mut object = Map::new();
+        object.insert(
+            "@id".into(),
+            format!("https://localhost/imported/{}", i).into(),
+        );
+        object.insert(urls::DESCRIPTION.into(), description);
+        object.into()
+    }
+
+    #[test]
+    fn import_stream() {
+        let store = Store::init().unwrap();
+        store.populate().unwrap();
+        store.set_default_agent(store.create_agent(Some("importer")).unwrap());
+        let count = PROGRESS_INTERVAL * 2 + 5;
+        let items: Vec<_> = (0..count)
+            .map(|i| resource(i, format!("Item {}", i).into()))
+            .collect();
+
+        let (result, events) = import(
+            &store,
+            &serde_json::to_string(&items).unwrap(),
+            ImportMode::Abort,
+        );
+        let summary = result.unwrap();
+        assert_eq!(summary.created, count);
+        let progress = events
+            .iter()
+            .filter(|e| matches!(e, ImportEvent::Progress { .. }))
+            .count();
+        assert_eq!(progress, 2);
+        assert!(matches!(events.last(), Some(ImportEvent::Summary(_))));
+
+        // Line-delimited JSON-AD, with a failing Resource in the middle
+        let lines = [
+            resource(1, "Changed".into()),
+            resource(2, serde_json::Value::Null),
+            resource(3, "Changed".into()),
+        ]
+        .map(|r| r.to_string())
+        .join("\n");
+        let description = |i: usize| {
+            store
+                .get_resource(&format!("https://localhost/imported/{}", i))
+                .unwrap()
+                .get(urls::DESCRIPTION)
+                .unwrap()
+                .to_string()
+        };
+        let (result, events) = import(&store, &lines, ImportMode::Abort);
+        assert!(result.is_err());
+        assert!(events.iter().any(|e| matches!(
+            e,
+            ImportEvent::Failed { index: 1, subject: Some(s), .. } if s.ends_with("/imported/2")
+        )));
+        let Some(ImportEvent::Summary(summary)) = events.last() else {
+            panic!("No summary")
+        };
+        assert!(summary.aborted);
+        assert_eq!(summary.rolled_back, 2);
+        assert_eq!(description(1), "Item 1");
+        assert_eq!(description(3), "Item 3");
+
+        let (result, _events) = import(&store, &lines, ImportMode::Continue);
+        let summary = result.unwrap();
+        assert_eq!((summary.created, summary.failed), (2, 1));
+        assert_eq!(description(1), "Changed");
+        assert_eq!(description(3), "Changed");
+
+        // Stopping halfway, e.g. because the client is gone, rolls back the new Resources
+        let new_items: Vec<_> = (0..PROGRESS_INTERVAL + 10)
+            .map(|i| resource(count + i, "New".into()))
+            .collect();
+        let opts = ParseOpts {
+            importer: Some("https://localhost/importer".into()),
+            signer: Some(store.get_default_agent().unwrap()),
+            save: SaveOpts::Commit,
+            ..Default::default()
+        };
+        let result = import_json_ad_stream(
+            serde_json::to_string(&new_items).unwrap().as_bytes(),
+            &store,
+            &opts,
+            ImportMode::Abort,
+            &mut |event| match event {
+                ImportEvent::Progress { .. } => Err("Client is gone".into()),
+                _ => Ok(()),
+            },
+        );
+        assert!(result.is_err());
+        assert!(store
+            .get_resource(&format!("https://localhost/imported/{}", count))
+            .is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Patches for String and Markdown values, so Commits to long texts only have to contain the part that changed.
+//! They are sent in the `patch` field of a Commit, next to `set`, and are applied to the current value of the Property.
+//!
+//! A patch is serialized as `sha256:<checksum> <operations>`.
+//! The checksum is the hex encoded SHA-256 hash of the complete value after the patch has been applied, so a patch applied to the wrong version is refused.
+//! The operations are applied from the start of the current value, and count characters (Unicode scalar values):
+//!
+//! - `=N` keeps the next `N` characters
+//! - `-N` deletes the next `N` characters
+//! - `+N:text` inserts the `N` characters of `text`
+//!
+//! The remainder of the current value is kept. For example, `sha256:… =5-3+4:blue` replaces the characters 6 to 8 with `blue`.
+
+use std::{fmt, str::FromStr};
+
+use crate::{errors::AtomicResult, AtomicError};
+
+const CHECKSUM_PREFIX: &str = "sha256:";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Operation {
+    Keep(usize),
+    Delete(usize),
+    Insert(String),
+}
+
+/// A change to a text, see the [module docs](self) for the format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Patch {
+    /// Checksum of the complete value after applying the patch
+    checksum: String,
+    operations: Vec<Operation>,
+}
+
+impl Patch {
+    /// Creates a patch that changes `old` into `new`.
+    /// Only the common start and end are kept, which is compact for the typical edit of a single part of the text.
+    pub fn diff(old: &str, new: &str) -> Patch {
+        let old_chars: Vec<char> = old.chars().collect();
+        let new_chars: Vec<char> = new.chars().collect();
+        let prefix = old_chars
+            .iter()
+            .zip(&new_chars)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let max_suffix = old_chars.len().min(new_chars.len()) - prefix;
+        let suffix = old_chars
+            .iter()
+            .rev()
+            .zip(new_chars.iter().rev())
+            .take(max_suffix)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let mut operations = Vec::new();
+        if prefix > 0 {
+            operations.push(Operation::Keep(prefix));
+        }
+        let deleted = old_chars.len() - prefix - suffix;
+        if deleted > 0 {
+            operations.push(Operation::Delete(deleted));
+        }
+        let inserted: String = new_chars[prefix..new_chars.len() - suffix].iter().collect();
+        if !inserted.is_empty() {
+            operations.push(Operation::Insert(inserted));
+        }
+        Patch {
+            checksum: checksum(new),
+            operations,
+        }
+    }
+
+    /// Applies the patch to the current value, and checks the checksum of the result.
+    pub fn apply(&self, current: &str) -> AtomicResult<String> {
+        let mut remaining = current.chars();
+        let mut result = String::with_capacity(current.len());
+        for operation in &self.operations {
+            match operation {
+                Operation::Keep(n) => {
+                    let kept: String = remaining.by_ref().take(*n).collect();
+                    if kept.chars().count() < *n {
+                        return Err("Patch keeps more characters than the value has".into());
+                    }
+                    result.push_str(&kept);
+                }
+                Operation::Delete(n) => {
+                    if remaining.by_ref().take(*n).count() < *n {
+                        return Err("Patch deletes more characters than the value has".into());
+                    }
+                }
+                Operation::Insert(text) => result.push_str(text),
+            }
+        }
+        result.extend(remaining);
+        if checksum(&result) != self.checksum {
+            return Err("The checksum of the patched value does not match. The patch was probably created for a different version of the value.".into());
+        }
+        Ok(result)
+    }
+}
+
+impl FromStr for Patch {
+    type Err = AtomicError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (checksum, mut rest) = s
+            .strip_prefix(CHECKSUM_PREFIX)
+            .and_then(|s| s.split_once(' '))
+            .unwrap_or((s, ""));
+        if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!(
+                "Patch must start with '{}' and a SHA-256 checksum",
+                CHECKSUM_PREFIX
+            )
+            .into());
+        }
+        let mut operations = Vec::new();
+        while let Some(kind) = rest.chars().next() {
+            rest = &rest[kind.len_utf8()..];
+            let digits = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            let count: usize = rest[..digits]
+                .parse()
+                .map_err(|_| format!("Expected a number after '{}' in patch", kind))?;
+            rest = &rest[digits..];
+            match kind {
+                '=' => operations.push(Operation::Keep(count)),
+                '-' => operations.push(Operation::Delete(count)),
+                '+' => {
+                    rest = rest
+                        .strip_prefix(':')
+                        .ok_or("Expected ':' after the length of an insert in patch")?;
+                    let end = rest
+                        .char_indices()
+                        .nth(count)
+                        .map(|(i, _)| i)
+                        .unwrap_or(rest.len());
+                    let text = &rest[..end];
+                    if text.chars().count() < count {
+                        return Err("Insert in patch is shorter than its length".into());
+                    }
+                    operations.push(Operation::Insert(text.into()));
+                    rest = &rest[end..];
+                }
+                other => return Err(format!("Unknown patch operation '{}'", other).into()),
+            }
+        }
+        Ok(Patch {
+            checksum: checksum.to_lowercase(),
+            operations,
+        })
+    }
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{} ", CHECKSUM_PREFIX, self.checksum)?;
+        for operation in &self.operations {
+            match operation {
+                Operation::Keep(n) => write!(f, "={}", n)?,
+                Operation::Delete(n) => write!(f, "-{}", n)?,
+                Operation::Insert(text) => write!(f, "+{}:{}", text.chars().count(), text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The hex encoded SHA-256 hash of a text, used to check the result of a [Patch].
+pub fn checksum(text: &str) -> String {
+    checksum_bytes(text.as_bytes())
+}
+
+/// The hex encoded SHA-256 hash of any bytes, such as the contents of an uploaded file.
+pub fn checksum_bytes(bytes: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, bytes)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diff_and_apply() {
+        let old = "I like apples and pears.";
+        let new = "I like bananas and pears.";
+        let patch = Patch::diff(old, new);
+        let serialized = patch.to_string();
+        assert!(serialized.ends_with(" =7-5+6:banana"));
+
+        let parsed: Patch = serialized.parse().unwrap();
+        assert_eq!(parsed, patch);
+        assert_eq!(parsed.apply(old).unwrap(), new);
+
+        // Lengths count characters, not bytes
+        let fruit = Patch::diff("🍏 pie: 🍏", "🍌 pie: 🍏");
+        assert!(fruit.to_string().ends_with(" -1+1:🍌"));
+        let parsed: Patch = fruit.to_string().parse().unwrap();
+        assert_eq!(parsed.apply("🍏 pie: 🍏").unwrap(), "🍌 pie: 🍏");
+
+        assert_eq!(Patch::diff("", "new").apply("").unwrap(), "new");
+        assert_eq!(Patch::diff("old", "").apply("old").unwrap(), "");
+
+        // Applying to another version fails the checksum
+        patch.apply("I like plums and pears.").unwrap_err();
+        assert!("=5-3".parse::<Patch>().is_err());
+    }
+}
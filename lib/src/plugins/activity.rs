@@ -0,0 +1,357 @@
+/*!
+Summarizes what happened in a Drive recently: Commits, the most active Agents, new Resources, storage used and accepted Invites.
+Served by the `/activity?drive={subject}&days=7` endpoint, and only visible to Agents with write rights to the Drive.
+
+Building a summary means reading every Commit in the period, so summaries are cached per Drive.
+The cache of a Drive is cleared when a Commit is applied to one of its Resources, see [ActivityCache::invalidate].
+*/
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{
+    agents::ForAgent,
+    commit::CommitResponse,
+    endpoints::{Endpoint, HandleGetContext},
+    errors::AtomicResult,
+    hierarchy::check_write,
+    resources::PropVals,
+    storelike::Query,
+    urls,
+    utils::now,
+    values::SubResource,
+    Commit, Db, Resource, Storelike, Value,
+};
+
+const DEFAULT_DAYS: u64 = 7;
+const MAX_DAYS: u64 = 366;
+/// How many Agents and new Resources are listed.
+const LIST_SIZE: usize = 10;
+/// Summaries are rebuilt after this time even without new Commits, as the period moves along.
+const CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+
+pub fn activity_endpoint() -> Endpoint {
+    Endpoint {
+        path: urls::PATH_ACTIVITY.into(),
+        params: [urls::ACTIVITY_DRIVE.to_string()].into(),
+        description: "Summarizes the activity in a Drive: the amount of Commits, the most active Agents, recently created Resources, the storage used by Files and accepted Invites. Pass the `drive` subject and optionally the amount of `days` (default 7) as query parameters. Requires write rights to the Drive.".to_string(),
+        shortname: "activity".to_string(),
+        handle: Some(handle_get),
+        handle_post: None,
+    }
+}
+
+fn handle_get(context: HandleGetContext) -> AtomicResult<Resource> {
+    let mut drive = None;
+    let mut days = DEFAULT_DAYS;
+    for (k, v) in context.subject.query_pairs() {
+        match k.as_ref() {
+            "drive" | urls::ACTIVITY_DRIVE => drive = Some(v.to_string()),
+            "days" => days = v.parse().map_err(|e| format!("Invalid `days`: {}", e))?,
+            _ => {}
+        }
+    }
+    let Some(drive) = drive else {
+        return activity_endpoint().to_resource(context.store);
+    };
+    let summary = get_summary(context.store, &drive, days, context.for_agent)?;
+    Ok(summary.into_resource(context.subject.to_string()))
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivitySummary {
+    pub drive: String,
+    pub days: u64,
+    /// Unix timestamp in milliseconds of the start of the period
+    pub since: i64,
+    pub commit_count: usize,
+    /// Agents with their amount of Commits, most active first
+    pub active_agents: Vec<AgentActivity>,
+    /// Subjects of Resources created in the period, newest first
+    pub recently_created: Vec<String>,
+    /// Total size in bytes of the Files in the Drive
+    pub storage_used: i64,
+    pub invite_redemptions: usize,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentActivity {
+    pub agent: String,
+    pub commit_count: usize,
+}
+
+impl ActivitySummary {
+    /// Converts the summary to an ActivitySummary Resource, with the active Agents as nested Resources.
+    pub fn into_resource(self, subject: String) -> Resource {
+        let mut resource = Resource::new(subject);
+        resource.set_class(urls::ACTIVITY_SUMMARY);
+        let active_agents: Vec<SubResource> = self
+            .active_agents
+            .into_iter()
+            .map(|activity| {
+                let mut propvals = PropVals::new();
+                propvals.insert(urls::SIGNER.into(), Value::AtomicUrl(activity.agent));
+                propvals.insert(
+                    urls::COMMIT_COUNT.into(),
+                    Value::Integer(activity.commit_count as i64),
+                );
+                SubResource::Nested(propvals)
+            })
+            .collect();
+        for (prop, value) in [
+            (urls::ACTIVITY_DRIVE, Value::AtomicUrl(self.drive)),
+            (urls::ACTIVITY_SINCE, Value::Timestamp(self.since)),
+            (urls::COMMIT_COUNT, Value::Integer(self.commit_count as i64)),
+            (urls::ACTIVE_AGENTS, Value::ResourceArray(active_agents)),
+            (urls::RECENTLY_CREATED, self.recently_created.into()),
+            (urls::STORAGE_USED, Value::Integer(self.storage_used)),
+            (
+                urls::INVITE_REDEMPTIONS,
+                Value::Integer(self.invite_redemptions as i64),
+            ),
+        ] {
+            resource.set_propval_unsafe(prop.into(), value);
+        }
+        resource
+    }
+}
+
+/// Returns the summary of the last `days` of the Drive, from the cache if possible.
+/// Errors if `for_agent` has no write rights to the Drive.
+pub fn get_summary(
+    store: &Db,
+    drive: &str,
+    days: u64,
+    for_agent: &ForAgent,
+) -> AtomicResult<ActivitySummary> {
+    if days == 0 || days > MAX_DAYS {
+        return Err(format!("`days` must be between 1 and {}", MAX_DAYS).into());
+    }
+    let drive_resource = store.get_resource(drive)?;
+    check_write(store, &drive_resource, for_agent)?;
+
+    let cache = store.get_activity_cache();
+    if let Some(summary) = cache.get(drive, days) {
+        return Ok(summary);
+    }
+    let summary = build_summary(store, drive, days)?;
+    cache.insert(summary.clone());
+    Ok(summary)
+}
+
+/// Reads the Commits of the last `days` and the Files of the Drive. Does not check rights.
+pub fn build_summary(
+    store: &impl Storelike,
+    drive: &str,
+    days: u64,
+) -> AtomicResult<ActivitySummary> {
+    let since = now() - (days as i64) * 24 * 60 * 60 * 1000;
+    let server_agent = store.get_default_agent().ok().map(|a| a.subject);
+    let mut membership = DriveMembership::new(drive);
+
+    let mut query = Query::new_class(urls::COMMIT);
+    query.sort_by = Some(urls::CREATED_AT.into());
+    query.sort_desc = true;
+    query.start_val = Some(Value::Timestamp(since));
+    let commits = store.query(&query)?;
+
+    let mut commit_count = 0;
+    let mut per_agent: HashMap<String, usize> = HashMap::new();
+    let mut recently_created = Vec::new();
+    let mut invite_redemptions = 0;
+    for resource in commits.resources {
+        let Ok(commit) = Commit::from_resource(resource) else {
+            continue;
+        };
+        if !membership.contains(store, &commit.subject) {
+            continue;
+        }
+        commit_count += 1;
+        *per_agent.entry(commit.signer.clone()).or_default() += 1;
+        let is_destroy = commit.destroy.unwrap_or(false);
+        if commit.previous_commit.is_none()
+            && !is_destroy
+            && recently_created.len() < LIST_SIZE
+            && !recently_created.contains(&commit.subject)
+        {
+            recently_created.push(commit.subject.clone());
+        }
+        // Accepted Invites are saved by the server, and push the Agent to the `read` rights of the target.
+        let grants_read = commit
+            .push
+            .as_ref()
+            .map(|push| push.contains_key(urls::READ))
+            .unwrap_or(false);
+        if grants_read && server_agent.as_ref() == Some(&commit.signer) {
+            invite_redemptions += 1;
+        }
+    }
+
+    let mut active_agents: Vec<AgentActivity> = per_agent
+        .into_iter()
+        .map(|(agent, commit_count)| AgentActivity {
+            agent,
+            commit_count,
+        })
+        .collect();
+    active_agents.sort_by(|a, b| {
+        b.commit_count
+            .cmp(&a.commit_count)
+            .then_with(|| a.agent.cmp(&b.agent))
+    });
+    active_agents.truncate(LIST_SIZE);
+
+    let mut storage_used = 0;
+    let files = store.query(&Query::new_class(urls::FILE))?;
+    for file in files.resources {
+        if membership.contains(store, file.get_subject()) {
+            if let Ok(Value::Integer(size)) = file.get(urls::FILESIZE) {
+                storage_used += size;
+            }
+        }
+    }
+
+    Ok(ActivitySummary {
+        drive: drive.into(),
+        days,
+        since,
+        commit_count,
+        active_agents,
+        recently_created,
+        storage_used,
+        invite_redemptions,
+    })
+}
+
+/// Remembers which subjects are part of the Drive, as many Commits share the same ancestors.
+struct DriveMembership<'a> {
+    drive: &'a str,
+    known: HashMap<String, bool>,
+}
+
+impl<'a> DriveMembership<'a> {
+    fn new(drive: &'a str) -> Self {
+        DriveMembership {
+            drive,
+            known: HashMap::new(),
+        }
+    }
+
+    /// Whether the subject is the Drive or one of its descendants. Resources that no longer exist are not counted.
+    fn contains(&mut self, store: &impl Storelike, subject: &str) -> bool {
+        if subject == self.drive {
+            return true;
+        }
+        if let Some(known) = self.known.get(subject) {
+            return *known;
+        }
+        let contains = match store.get_resource(subject) {
+            Ok(resource) => ancestors(store, &resource).contains(self.drive),
+            Err(_) => false,
+        };
+        self.known.insert(subject.into(), contains);
+        contains
+    }
+}
+
+fn ancestors(store: &impl Storelike, resource: &Resource) -> HashSet<String> {
+    resource
+        .get_parent_tree(store)
+        .map(|parents| {
+            parents
+                .iter()
+                .map(|parent| parent.get_subject().clone())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+struct CachedSummary {
+    summary: ActivitySummary,
+    created: Instant,
+}
+
+/// Caches [ActivitySummary]s per Drive and amount of days.
+#[derive(Clone, Default)]
+pub struct ActivityCache {
+    drives: Arc<Mutex<HashMap<String, HashMap<u64, CachedSummary>>>>,
+}
+
+impl ActivityCache {
+    fn get(&self, drive: &str, days: u64) -> Option<ActivitySummary> {
+        let drives = self.drives.lock().unwrap();
+        let cached = drives.get(drive)?.get(&days)?;
+        if cached.created.elapsed() > CACHE_TTL {
+            return None;
+        }
+        Some(cached.summary.clone())
+    }
+
+    fn insert(&self, summary: ActivitySummary) {
+        let mut drives = self.drives.lock().unwrap();
+        drives.entry(summary.drive.clone()).or_default().insert(
+            summary.days,
+            CachedSummary {
+                summary,
+                created: Instant::now(),
+            },
+        );
+    }
+
+    /// Removes the cached summaries of the Drives that contain the Resource of the Commit.
+    pub fn invalidate(&self, store: &impl Storelike, commit_response: &CommitResponse) {
+        let mut drives = self.drives.lock().unwrap();
+        if drives.is_empty() {
+            return;
+        }
+        for resource in [&commit_response.resource_new, &commit_response.resource_old]
+            .into_iter()
+            .flatten()
+        {
+            let mut affected = ancestors(store, resource);
+            affected.insert(resource.get_subject().clone());
+            drives.retain(|drive, _| !affected.contains(drive));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn summarizes_drive_activity() {
+        let store = Db::init_temp("summarizes_drive_activity").unwrap();
+        let drive = store.get_server_url().to_string();
+        let agent = store.get_default_agent().unwrap();
+
+        let first = get_summary(&store, &drive, 7, &agent.subject.clone().into()).unwrap();
+
+        let mut resource = Resource::new_generate_subject(&store);
+        resource
+            .set_propval(urls::PARENT.into(), Value::AtomicUrl(drive.clone()), &store)
+            .unwrap();
+        resource.save_locally(&store).unwrap();
+
+        let second = get_summary(&store, &drive, 7, &agent.subject.clone().into()).unwrap();
+        assert_eq!(second.commit_count, first.commit_count + 1);
+        assert!(second.recently_created.contains(resource.get_subject()));
+        assert!(second
+            .active_agents
+            .iter()
+            .any(|activity| activity.agent == agent.subject));
+
+        let public = get_summary(&store, &drive, 7, &ForAgent::Public);
+        assert!(public.is_err(), "Public Agent should not see the activity");
+
+        let summary = second.into_resource("https://localhost/activity".into());
+        assert!(summary.get(urls::ACTIVE_AGENTS).is_ok());
+    }
+}
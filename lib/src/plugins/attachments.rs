@@ -0,0 +1,475 @@
+/*!
+Checks that the `attachments` of Resources, the File Resources and the uploaded files on disk agree with each other, and repairs them.
+
+The check finds four kinds of issues:
+
+- Dangling attachments: entries in an `attachments` array of which the File no longer exists. Repaired by pulling them from the array.
+- Unlisted files: File Resources that are not in the `attachments` of their parent. Repaired by pushing them to the array.
+- Orphaned blobs: uploaded files that no File Resource refers to. Repaired by moving them to the [ORPHANED_DIR] in the uploads directory, from where they can be moved back.
+- Missing blobs: File Resources of which the uploaded file is gone. Repaired by moving the File to the trash of its Drive.
+
+Repairs are Commits signed by the default Agent, so they show up in the history.
+Resources are checked in batches, and the [AttachmentsCheck] can be stored between batches to resume a check of a large store later.
+*/
+
+use std::{
+    collections::HashSet,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commit::{CommitBuilder, CommitOpts},
+    errors::AtomicResult,
+    plugins::trash::{is_trashed, trash_drive},
+    storelike::Query,
+    urls, Resource, Storelike,
+};
+
+/// The directory in the uploads directory where orphaned blobs are moved to.
+pub const ORPHANED_DIR: &str = "orphaned";
+/// Uploads are written to disk before their File Resource is created, so recent files are never orphaned.
+pub const MIN_ORPHAN_AGE: Duration = Duration::from_secs(60 * 60);
+/// How many examples of every kind of issue are kept in the report.
+const MAX_SAMPLES: usize = 20;
+/// How many Resources or files [check_attachments] checks per batch.
+pub const BATCH_SIZE: usize = 500;
+
+/// The amount of issues of one kind, with some examples.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Issues {
+    pub found: usize,
+    pub repaired: usize,
+    /// The first issues that were found
+    pub samples: Vec<String>,
+}
+
+impl Issues {
+    fn add(&mut self, sample: String) {
+        self.found += 1;
+        if self.samples.len() < MAX_SAMPLES {
+            self.samples.push(sample);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentsReport {
+    /// Nothing is repaired in a dry run
+    pub dry_run: bool,
+    pub checked_resources: usize,
+    pub checked_blobs: usize,
+    /// Samples are `<parent> <attachment>`
+    pub dangling_attachments: Issues,
+    /// Samples are File subjects
+    pub unlisted_files: Issues,
+    /// Samples are file names in the uploads directory
+    pub orphaned_blobs: Issues,
+    /// Samples are File subjects
+    pub missing_blobs: Issues,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    #[default]
+    Resources,
+    Blobs,
+    Done,
+}
+
+/// The progress of a check. Serialize it between batches to resume the check later.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentsCheck {
+    pub phase: Phase,
+    /// The last subject (or file name, when checking blobs) that has been checked
+    pub cursor: Option<String>,
+    pub report: AttachmentsReport,
+}
+
+impl AttachmentsCheck {
+    pub fn new(dry_run: bool) -> Self {
+        AttachmentsCheck {
+            report: AttachmentsReport {
+                dry_run,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.phase == Phase::Done
+    }
+
+    /// Checks (and repairs) the next `batch_size` Resources, or files once all Resources have been checked.
+    pub fn next_batch(
+        &mut self,
+        store: &impl Storelike,
+        uploads_path: &Path,
+        batch_size: usize,
+    ) -> AtomicResult<()> {
+        let checker = Checker::new(store, uploads_path, self.report.dry_run)?;
+        match self.phase {
+            Phase::Resources => {
+                let cursor = self.cursor.clone();
+                let batch: Vec<Resource> = store
+                    .all_resources(false)
+                    .skip_while(|r| {
+                        cursor
+                            .as_deref()
+                            .is_some_and(|c| r.get_subject().as_str() <= c)
+                    })
+                    .take(batch_size)
+                    .collect();
+                for resource in &batch {
+                    checker.check_resource(resource, &mut self.report)?;
+                }
+                self.report.checked_resources += batch.len();
+                self.advance(
+                    batch.last().map(|r| r.get_subject().clone()),
+                    batch_size,
+                    batch.len(),
+                    Phase::Blobs,
+                );
+            }
+            Phase::Blobs => {
+                let batch: Vec<String> = list_blobs(uploads_path)?
+                    .into_iter()
+                    .filter(|name| self.cursor.as_ref().is_none_or(|c| name > c))
+                    .take(batch_size)
+                    .collect();
+                let trashed = trashed_blobs(store)?;
+                for name in &batch {
+                    checker.check_blob(name, &trashed, &mut self.report)?;
+                }
+                self.report.checked_blobs += batch.len();
+                self.advance(batch.last().cloned(), batch_size, batch.len(), Phase::Done);
+            }
+            Phase::Done => {}
+        }
+        Ok(())
+    }
+
+    fn advance(&mut self, last: Option<String>, batch_size: usize, checked: usize, next: Phase) {
+        if checked < batch_size {
+            self.phase = next;
+            self.cursor = None;
+        } else {
+            self.cursor = last;
+        }
+    }
+}
+
+/// Checks all Resources and uploaded files in one go. Repairs the issues, unless `dry_run` is set.
+pub fn check_attachments(
+    store: &impl Storelike,
+    uploads_path: &Path,
+    dry_run: bool,
+) -> AtomicResult<AttachmentsReport> {
+    let mut check = AttachmentsCheck::new(dry_run);
+    while !check.is_done() {
+        check.next_batch(store, uploads_path, BATCH_SIZE)?;
+    }
+    Ok(check.report)
+}
+
+struct Checker<'a, S: Storelike> {
+    store: &'a S,
+    uploads_path: &'a Path,
+    dry_run: bool,
+    agent: crate::agents::Agent,
+}
+
+impl<'a, S: Storelike> Checker<'a, S> {
+    fn new(store: &'a S, uploads_path: &'a Path, dry_run: bool) -> AtomicResult<Self> {
+        Ok(Checker {
+            store,
+            uploads_path,
+            dry_run,
+            agent: store.get_default_agent()?,
+        })
+    }
+
+    fn check_resource(
+        &self,
+        resource: &Resource,
+        report: &mut AttachmentsReport,
+    ) -> AtomicResult<()> {
+        let subject = resource.get_subject();
+        let dangling: Vec<String> = attachments(resource)
+            .into_iter()
+            .filter(|file| self.store.get_resource(file).is_err())
+            .collect();
+        for file in &dangling {
+            report
+                .dangling_attachments
+                .add(format!("{} {}", subject, file));
+        }
+        if !dangling.is_empty() && !self.dry_run {
+            self.commit(resource, |commit| {
+                for file in &dangling {
+                    commit.pull_propval(urls::ATTACHMENTS, file.clone().into())?;
+                }
+                Ok(())
+            })?;
+            report.dangling_attachments.repaired += dangling.len();
+            tracing::info!(
+                "Removed {} dangling attachments from {}",
+                dangling.len(),
+                subject
+            );
+        }
+
+        if !is_file(resource) || is_trashed(resource) {
+            return Ok(());
+        }
+        if let Ok(internal_id) = resource.get(urls::INTERNAL_ID) {
+            if !self.uploads_path.join(internal_id.to_string()).is_file() {
+                report.missing_blobs.add(subject.clone());
+                // Files outside of a Drive have no trash, and are not removed permanently
+                if !self.dry_run && trash_drive(self.store, resource)?.is_some() {
+                    self.commit(resource, |commit| {
+                        commit.destroy(true);
+                        Ok(())
+                    })?;
+                    report.missing_blobs.repaired += 1;
+                    tracing::info!("Moved {} to the trash, its file is missing", subject);
+                }
+                // Moving the File to the trash removes it from the attachments of its parent
+                return Ok(());
+            }
+        }
+        let Ok(parent_subject) = resource.get(urls::PARENT).map(|p| p.to_string()) else {
+            return Ok(());
+        };
+        let Ok(parent) = self.store.get_resource(&parent_subject) else {
+            return Ok(());
+        };
+        if !attachments(&parent).contains(subject) {
+            report.unlisted_files.add(subject.clone());
+            if !self.dry_run {
+                self.commit(&parent, |commit| {
+                    commit.push_propval(urls::ATTACHMENTS, subject.clone().into())
+                })?;
+                report.unlisted_files.repaired += 1;
+                tracing::info!("Added {} to the attachments of {}", subject, parent_subject);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_blob(
+        &self,
+        name: &str,
+        trashed: &HashSet<String>,
+        report: &mut AttachmentsReport,
+    ) -> AtomicResult<()> {
+        let path = self.uploads_path.join(name);
+        let age = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or_default();
+        if age < MIN_ORPHAN_AGE || trashed.contains(name) {
+            return Ok(());
+        }
+        let mut query = Query::new_prop_val(urls::INTERNAL_ID, name);
+        query.limit = Some(1);
+        query.include_nested = false;
+        if !self.store.query(&query)?.subjects.is_empty() {
+            return Ok(());
+        }
+        report.orphaned_blobs.add(name.to_string());
+        if !self.dry_run {
+            let orphaned = self.uploads_path.join(ORPHANED_DIR);
+            std::fs::create_dir_all(&orphaned)?;
+            std::fs::rename(&path, orphaned.join(name))?;
+            report.orphaned_blobs.repaired += 1;
+            tracing::info!("Moved orphaned file {} to {:?}", name, orphaned);
+        }
+        Ok(())
+    }
+
+    fn commit(
+        &self,
+        resource: &Resource,
+        build: impl FnOnce(&mut CommitBuilder) -> AtomicResult<()>,
+    ) -> AtomicResult<()> {
+        let opts = CommitOpts {
+            validate_schema: false,
+            validate_signature: false,
+            validate_timestamp: false,
+            validate_rights: false,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        let mut commitbuilder = CommitBuilder::new(resource.get_subject().clone());
+        build(&mut commitbuilder)?;
+        commitbuilder
+            .sign(&self.agent, self.store, resource)?
+            .apply_opts(self.store, &opts)?;
+        Ok(())
+    }
+}
+
+fn attachments(resource: &Resource) -> Vec<String> {
+    resource
+        .get(urls::ATTACHMENTS)
+        .and_then(|a| a.to_subjects(None))
+        .unwrap_or_default()
+}
+
+/// Whether the Resource is a [urls::FILE].
+pub fn is_file(resource: &Resource) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .map(|classes| classes.iter().any(|c| c == urls::FILE))
+        .unwrap_or(false)
+}
+
+/// The names of the files in the uploads directory, sorted. Directories, such as the [ORPHANED_DIR], are skipped.
+fn list_blobs(uploads_path: &Path) -> AtomicResult<Vec<String>> {
+    let entries = match std::fs::read_dir(uploads_path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            if let Some(name) = entry.file_name().to_str() {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// The internal ids of trashed Files. Trashed Resources are not in the value index, except for their parent.
+fn trashed_blobs(store: &impl Storelike) -> AtomicResult<HashSet<String>> {
+    let mut ids = HashSet::new();
+    for trash in store.query(&Query::new_class(urls::TRASH))?.subjects {
+        for file in store
+            .query(&Query::new_prop_val(urls::PARENT, &trash))?
+            .resources
+        {
+            if let Ok(id) = file.get(urls::INTERNAL_ID) {
+                ids.insert(id.to_string());
+            }
+        }
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Db, Value};
+
+    fn new_file(store: &Db, parent: &str, internal_id: &str) -> String {
+        let mut file = Resource::new_generate_subject(store);
+        file.set_class(urls::FILE);
+        file.set_propval(urls::PARENT.into(), Value::AtomicUrl(parent.into()), store)
+            .unwrap();
+        file.set_propval_string(urls::INTERNAL_ID.into(), internal_id, store)
+            .unwrap();
+        file.set_propval_string(
+            urls::DOWNLOAD_URL.into(),
+            &format!("{}/download/{}", store.get_server_url(), internal_id),
+            store,
+        )
+        .unwrap();
+        file.save_locally(store).unwrap();
+        file.get_subject().clone()
+    }
+
+    fn set_mtime_old(path: &Path) {
+        let old = SystemTime::now() - MIN_ORPHAN_AGE * 2;
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+    }
+
+    #[test]
+    fn finds_and_repairs_attachment_issues() {
+        let store = Db::init_temp("finds_and_repairs_attachment_issues").unwrap();
+        let uploads = std::env::temp_dir().join(format!(
+            "atomic-attachments-{}",
+            crate::utils::random_string(10)
+        ));
+        std::fs::create_dir_all(&uploads).unwrap();
+        let drive = store.get_server_url().to_string();
+
+        // Listed, with a blob: fine
+        std::fs::write(uploads.join("ok"), "ok").unwrap();
+        let ok = new_file(&store, &drive, "ok");
+        // Not listed, with a blob
+        std::fs::write(uploads.join("unlisted"), "unlisted").unwrap();
+        let unlisted = new_file(&store, &drive, "unlisted");
+        // Listed, but the blob is gone
+        let missing = new_file(&store, &drive, "missing");
+        // A blob without a File
+        std::fs::write(uploads.join("orphan"), "orphan").unwrap();
+        // A new upload, of which the File will be created any moment
+        std::fs::write(uploads.join("uploading"), "uploading").unwrap();
+        for name in ["ok", "unlisted", "orphan"] {
+            set_mtime_old(&uploads.join(name));
+        }
+        let gone = format!("{}/files/gone", drive);
+        let mut parent = store.get_resource(&drive).unwrap();
+        for file in [&ok, &missing, &gone] {
+            parent
+                .push_propval(urls::ATTACHMENTS, file.clone().into(), true)
+                .unwrap();
+        }
+        parent.save_locally(&store).unwrap();
+
+        // A tiny batch size checks that the check resumes where it stopped
+        let mut check = AttachmentsCheck::new(true);
+        while !check.is_done() {
+            check.next_batch(&store, &uploads, 2).unwrap();
+            check = serde_json::from_str(&serde_json::to_string(&check).unwrap()).unwrap();
+        }
+        let report = check.report;
+        assert_eq!(
+            report.dangling_attachments.samples,
+            [format!("{} {}", drive, gone)]
+        );
+        assert_eq!(report.unlisted_files.samples, [unlisted.clone()]);
+        assert_eq!(report.missing_blobs.samples, [missing.clone()]);
+        assert_eq!(report.orphaned_blobs.samples, ["orphan"]);
+        assert_eq!(report.dangling_attachments.repaired, 0);
+        assert!(uploads.join("orphan").exists());
+
+        let report = check_attachments(&store, &uploads, false).unwrap();
+        assert_eq!(report.dangling_attachments.repaired, 1);
+        assert_eq!(report.unlisted_files.repaired, 1);
+        assert_eq!(report.missing_blobs.repaired, 1);
+        assert_eq!(report.orphaned_blobs.repaired, 1);
+        assert!(uploads.join(ORPHANED_DIR).join("orphan").exists());
+        assert!(is_trashed(&store.get_resource(&missing).unwrap()));
+        let listed = attachments(&store.get_resource(&drive).unwrap());
+        assert_eq!(listed, [ok, unlisted]);
+
+        let report = check_attachments(&store, &uploads, true).unwrap();
+        assert_eq!(report.dangling_attachments.found, 0);
+        assert_eq!(report.unlisted_files.found, 0);
+        assert_eq!(report.missing_blobs.found, 0);
+        assert_eq!(report.orphaned_blobs.found, 0);
+        std::fs::remove_dir_all(&uploads).unwrap();
+    }
+}
@@ -11,12 +11,13 @@ use url::Url;
 use urlencoding::encode;
 
 use crate::{
-    client::fetch_body,
+    client::fetch_body_for,
     endpoints::{Endpoint, HandleGetContext},
     errors::AtomicResult,
+    outbound::OutboundFeature,
     urls,
     values::Value,
-    AtomicError, Resource,
+    AtomicError, Resource, Storelike,
 };
 
 type Handler<'s, 'h> = Vec<(Cow<'s, Selector>, ElementContentHandlers<'h>)>;
@@ -62,7 +63,7 @@ fn handle_bookmark_request(context: HandleGetContext) -> AtomicResult<Resource>
     resource.set_propval_string(urls::URL.into(), &path, store)?;
 
     // Fetch the data and create a parser from it.
-    let content = fetch_data(&path)?;
+    let content = fetch_data(store, &path)?;
     let mut parser = Parser::from_html(&path, &content)?;
 
     // Extract the title, description and preview image from the HTML
@@ -94,8 +95,14 @@ fn handle_bookmark_request(context: HandleGetContext) -> AtomicResult<Resource>
     Ok(resource)
 }
 
-fn fetch_data(url: &str) -> AtomicResult<String> {
-    fetch_body(url, "text/html", None).map_err(|e| format!("Fetching failed: {}", e).into())
+fn fetch_data(store: &impl Storelike, url: &str) -> AtomicResult<String> {
+    // Keeps the type of the error, so refused requests can be told apart
+    fetch_body_for(store, OutboundFeature::Bookmark, url, "text/html", None).map_err(|e| {
+        AtomicError {
+            message: format!("Fetching failed: {}", e.message),
+            ..e
+        }
+    })
 }
 
 struct Parser {
@@ -107,10 +114,23 @@ struct Parser {
     svg_map: HashMap<String, String>,
 }
 
-struct SiteMeta {
-    title: Option<String>,
-    description: Option<String>,
-    image: Option<String>,
+/// The title, description and image of a web page.
+pub struct SiteMeta {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+}
+
+/// Reads the `<title>`, and the description and image from the `<meta>` tags (including OpenGraph and Twitter) of a web page.
+/// A relative image URL is resolved against the URL of the page.
+pub fn site_meta(url: &str, html: &str) -> AtomicResult<SiteMeta> {
+    let parser = Parser::from_html(url, html)?;
+    let mut meta = parser.get_meta();
+    meta.image = meta
+        .image
+        .filter(|image| !image.is_empty())
+        .map(|image| parser.resolve_url(&image));
+    Ok(meta)
 }
 
 impl Parser {
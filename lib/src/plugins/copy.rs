@@ -0,0 +1,340 @@
+/*!
+Copying Resources, for example to duplicate a folder or to create a new document from a template.
+
+[copy_resource] copies a Resource, and optionally all of its descendants, to new subjects under a new parent.
+References between the copied Resources are changed to refer to the copies, references to other Resources are kept.
+The copies get a new history: they are created by new Commits, signed by the server, for which the rights of the requesting Agent are checked.
+Since children are found using their `parent`, the copy shows up as a child of the new parent.
+The subjects of the copies are generated using the [crate::subjects::SubjectStrategy] of the Drive they are copied to.
+*/
+
+use std::collections::{BTreeMap, HashSet, VecDeque};
+
+use crate::{
+    agents::ForAgent,
+    commit::{CommitBuilder, CommitOpts},
+    errors::AtomicResult,
+    hierarchy::check_read,
+    storelike::Query,
+    subjects::{self, SubjectHint},
+    urls,
+    values::SubResource,
+    Db, Resource, Storelike, Value,
+};
+
+/// Copies the Resource at `subject` to a new subject with `new_parent` as its parent.
+/// If `recursive` is true, all its descendants that `for_agent` can read are copied as well.
+/// Files are only copied if `include_files` is true. `copy_file` then copies the file with the given internal ID, and returns the internal ID of the copy.
+/// If the new parent already has a child with the same name or shortname, the copy gets a ` (copy)` or numeric suffix.
+/// Returns the subjects of the copied Resources, mapped to the subjects of their copies.
+pub fn copy_resource(
+    store: &Db,
+    subject: &str,
+    new_parent: &str,
+    recursive: bool,
+    include_files: bool,
+    for_agent: &ForAgent,
+    copy_file: &dyn Fn(&str) -> AtomicResult<String>,
+) -> AtomicResult<BTreeMap<String, String>> {
+    let root = store.get_resource(subject)?;
+    check_read(store, &root, for_agent)?;
+    if is_file(&root) && !include_files {
+        return Err(format!(
+            "{} is a File, which is only copied when including files",
+            subject
+        )
+        .into());
+    }
+    let new_parent_resource = store.get_resource(new_parent)?;
+    if new_parent_resource
+        .get_parent_tree(store)?
+        .iter()
+        .chain(std::iter::once(&new_parent_resource))
+        .any(|ancestor| ancestor.get_subject() == subject)
+    {
+        return Err(format!("Can't copy {} into itself", subject).into());
+    }
+
+    // Parents are always copied before their children, so the rights of the new parent can be checked
+    let mut originals = vec![root];
+    if recursive {
+        let mut visited: HashSet<String> = HashSet::from([subject.to_string()]);
+        let mut queue: VecDeque<String> = VecDeque::from([subject.to_string()]);
+        while let Some(parent) = queue.pop_front() {
+            let mut query = Query::new_prop_val(urls::PARENT, &parent);
+            query.for_agent = for_agent.clone();
+            for child in store.query(&query)?.resources {
+                if (is_file(&child) && !include_files)
+                    || !visited.insert(child.get_subject().clone())
+                {
+                    continue;
+                }
+                queue.push_back(child.get_subject().clone());
+                originals.push(child);
+            }
+        }
+    }
+
+    let server_url = store.get_server_url();
+    let mut copies: BTreeMap<String, String> = BTreeMap::new();
+    let mut internal_ids: BTreeMap<String, String> = BTreeMap::new();
+    for original in &originals {
+        // Parents are copied first, so the copy of the parent is known
+        let parent = match original.get(urls::PARENT) {
+            Ok(parent) if original.get_subject() != subject => copies
+                .get(&parent.to_string())
+                .cloned()
+                .unwrap_or_else(|| new_parent.to_string()),
+            _ => new_parent.to_string(),
+        };
+        let name = name_hint(original);
+        let hint = SubjectHint {
+            parent: Some(&parent),
+            name: name.as_deref(),
+        };
+        let new_subject = if is_file(original) {
+            let internal_id = original.get(urls::INTERNAL_ID)?.to_string();
+            let new_id = copy_file(&internal_id)?;
+            let new_subject = subjects::new_subject(store, hint, || {
+                format!("{}/files/{}", server_url, urlencoding::encode(&new_id))
+            })?;
+            internal_ids.insert(original.get_subject().clone(), new_id);
+            new_subject
+        } else {
+            subjects::new_subject(store, hint, || {
+                Resource::new_generate_subject(store).get_subject().clone()
+            })?
+        };
+        copies.insert(original.get_subject().clone(), new_subject);
+    }
+
+    let agent = store.get_default_agent()?;
+    let opts = CommitOpts {
+        validate_schema: false,
+        validate_signature: false,
+        validate_timestamp: false,
+        validate_rights: true,
+        validate_previous_commit: false,
+        validate_for_agent: Some(for_agent.to_string()),
+        update_index: true,
+        validate_relative_urls: false,
+    };
+    let (name, shortname) = unique_names(store, &originals[0], new_parent)?;
+
+    for original in &originals {
+        let new_subject = &copies[original.get_subject()];
+        let mut commitbuilder = CommitBuilder::new(new_subject.clone());
+        for (prop, value) in original.get_propvals() {
+            let value = match prop.as_str() {
+                // The copy gets its own history
+                urls::LAST_COMMIT => continue,
+                urls::INTERNAL_ID => Value::String(internal_ids[original.get_subject()].clone()),
+                urls::DOWNLOAD_URL => Value::String(new_subject.replacen(
+                    server_url,
+                    &format!("{}/download", server_url),
+                    1,
+                )),
+                _ => replace_copied(value, &copies).unwrap_or_else(|| value.clone()),
+            };
+            commitbuilder.set(prop.clone(), value);
+        }
+        if original.get_subject() == subject {
+            commitbuilder.set(urls::PARENT.into(), Value::AtomicUrl(new_parent.into()));
+            if let Some(name) = &name {
+                commitbuilder.set(urls::NAME.into(), Value::String(name.clone()));
+            }
+            if let Some(shortname) = &shortname {
+                commitbuilder.set(urls::SHORTNAME.into(), Value::Slug(shortname.clone()));
+            }
+        }
+        commitbuilder
+            .sign(&agent, store, &Resource::new(new_subject.clone()))?
+            .apply_opts(store, &opts)?;
+    }
+    Ok(copies)
+}
+
+fn is_file(resource: &Resource) -> bool {
+    resource.get(urls::INTERNAL_ID).is_ok()
+}
+
+/// What the subject of the copy is based on, if the Drive uses [crate::subjects::SubjectStrategy::Slug].
+fn name_hint(resource: &Resource) -> Option<String> {
+    [urls::NAME, urls::FILENAME, urls::SHORTNAME]
+        .iter()
+        .find_map(|prop| resource.get(prop).ok())
+        .map(|value| value.to_string())
+}
+
+/// Returns a new name and shortname for the copy of `original`, if the ones it has are already used by a child of `new_parent`.
+fn unique_names(
+    store: &Db,
+    original: &Resource,
+    new_parent: &str,
+) -> AtomicResult<(Option<String>, Option<String>)> {
+    let mut query = Query::new_prop_val(urls::PARENT, new_parent);
+    query.include_nested = true;
+    let siblings = store.query(&query)?.resources;
+    let used = |prop: &str| -> HashSet<String> {
+        siblings
+            .iter()
+            .filter_map(|sibling| sibling.get(prop).ok().map(|v| v.to_string()))
+            .collect()
+    };
+
+    let name = original.get(urls::NAME).ok().map(|v| v.to_string());
+    let used_names = used(urls::NAME);
+    let name = name.filter(|name| used_names.contains(name)).map(|name| {
+        std::iter::once(format!("{} (copy)", name))
+            .chain((2..).map(|i| format!("{} (copy {})", name, i)))
+            .find(|candidate| !used_names.contains(candidate))
+            .expect("an unused name")
+    });
+
+    let shortname = original.get(urls::SHORTNAME).ok().map(|v| v.to_string());
+    let used_shortnames = used(urls::SHORTNAME);
+    let shortname = shortname
+        .filter(|shortname| used_shortnames.contains(shortname))
+        .map(|shortname| {
+            (2..)
+                .map(|i| format!("{}-{}", shortname, i))
+                .find(|candidate| !used_shortnames.contains(candidate))
+                .expect("an unused shortname")
+        });
+    Ok((name, shortname))
+}
+
+/// Returns the Value with references to copied Resources replaced by their copies, or `None` if it has no such references.
+fn replace_copied(value: &Value, copies: &BTreeMap<String, String>) -> Option<Value> {
+    match value {
+        Value::AtomicUrl(subject) => copies.get(subject).cloned().map(Value::AtomicUrl),
+        Value::ResourceArray(items) => {
+            let refers = items
+                .iter()
+                .any(|item| matches!(item, SubResource::Subject(s) if copies.contains_key(s)));
+            refers.then(|| {
+                Value::ResourceArray(
+                    items
+                        .iter()
+                        .map(|item| match item {
+                            SubResource::Subject(s) => {
+                                SubResource::Subject(copies.get(s).unwrap_or(s).clone())
+                            }
+                            other => other.clone(),
+                        })
+                        .collect(),
+                )
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn copy_folder() {
+        let store = Db::init_temp("copy_folder").unwrap();
+        let drive = store.get_server_url().to_string();
+        let name = |name: &str| Value::String(name.into());
+        let create = |parent: &str, propvals: Vec<(&str, Value)>| {
+            store
+                .create_test_resource(parent, propvals)
+                .get_subject()
+                .clone()
+        };
+        let folder = create(&drive, vec![(urls::NAME, name("Project"))]);
+        let intro = create(&folder, vec![(urls::NAME, name("Intro"))]);
+        let outro = create(
+            &folder,
+            vec![
+                (urls::NAME, name("Outro")),
+                (urls::DESTINATION, Value::AtomicUrl(intro.clone())),
+                (urls::WRITE, Value::from(vec![urls::PUBLIC_AGENT])),
+            ],
+        );
+        let no_files = |_: &str| -> AtomicResult<String> { panic!("no files to copy") };
+
+        let single = copy_resource(
+            &store,
+            &folder,
+            &drive,
+            false,
+            false,
+            &ForAgent::Sudo,
+            &no_files,
+        )
+        .unwrap();
+        assert_eq!(single.len(), 1);
+        let copy = store.get_resource(&single[&folder]).unwrap();
+        assert_eq!(copy.get(urls::NAME).unwrap().to_string(), "Project (copy)");
+        assert_eq!(copy.get(urls::PARENT).unwrap().to_string(), drive);
+        assert_ne!(
+            copy.get(urls::LAST_COMMIT).unwrap().to_string(),
+            store
+                .get_resource(&folder)
+                .unwrap()
+                .get(urls::LAST_COMMIT)
+                .unwrap()
+                .to_string()
+        );
+
+        let copies = copy_resource(
+            &store,
+            &folder,
+            &drive,
+            true,
+            false,
+            &ForAgent::Sudo,
+            &no_files,
+        )
+        .unwrap();
+        assert_eq!(copies.len(), 3);
+        let folder_copy = store.get_resource(&copies[&folder]).unwrap();
+        assert_eq!(
+            folder_copy.get(urls::NAME).unwrap().to_string(),
+            "Project (copy 2)"
+        );
+        let outro_copy = store.get_resource(&copies[&outro]).unwrap();
+        assert_eq!(
+            outro_copy.get(urls::PARENT).unwrap().to_string(),
+            copies[&folder]
+        );
+        // References within the copied Resources refer to the copies, others are kept
+        assert_eq!(
+            outro_copy.get(urls::DESTINATION).unwrap().to_string(),
+            copies[&intro]
+        );
+        assert_eq!(
+            outro_copy
+                .get(urls::WRITE)
+                .unwrap()
+                .to_subjects(None)
+                .unwrap(),
+            vec![urls::PUBLIC_AGENT]
+        );
+
+        assert!(copy_resource(
+            &store,
+            &folder,
+            &intro,
+            true,
+            false,
+            &ForAgent::Sudo,
+            &no_files
+        )
+        .is_err());
+        assert!(copy_resource(
+            &store,
+            &folder,
+            &drive,
+            true,
+            false,
+            &ForAgent::Public,
+            &no_files
+        )
+        .is_err());
+    }
+}
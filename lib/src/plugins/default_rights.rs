@@ -0,0 +1,209 @@
+/*!
+Drives can give the Resources that are created in them explicit rights, using `defaultRead`, `defaultWrite` and `newResourcesPublic`.
+Without these, new Resources only inherit the rights of their parents.
+
+The defaults are only applied when the Commit that creates the Resource does not set or push any rights itself.
+They are added to the changes of that Commit, and recorded in the Commit resource (`appliedRead` and `appliedWrite`), so it is clear where the rights came from.
+The signed part of the Commit is not changed.
+Changing the defaults of a Drive does not change the rights of existing Resources.
+*/
+
+use crate::{errors::AtomicResult, urls, Commit, Resource, Storelike, Value};
+
+/// Rights that are given to a new Resource by the Drive it is created in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DefaultRights {
+    /// Subject of the Drive the defaults come from
+    pub drive: String,
+    pub read: Vec<String>,
+    pub write: Vec<String>,
+}
+
+impl DefaultRights {
+    /// Returns a copy of the Commit that also sets the rights.
+    pub fn add_to_commit(&self, commit: &Commit) -> Commit {
+        let mut commit = commit.clone();
+        let set = commit.set.get_or_insert_with(Default::default);
+        for (prop, agents) in self.propvals() {
+            set.insert(prop.into(), agents);
+        }
+        commit
+    }
+
+    pub fn add_to_resource(&self, resource: &mut Resource) {
+        for (prop, agents) in self.propvals() {
+            resource.set_propval_unsafe(prop.into(), agents);
+        }
+    }
+
+    /// Stores the applied rights in the Commit resource, next to the signed changes.
+    pub fn record(&self, commit_resource: &mut Resource) {
+        for (prop, agents) in [
+            (urls::APPLIED_READ, &self.read),
+            (urls::APPLIED_WRITE, &self.write),
+        ] {
+            if !agents.is_empty() {
+                commit_resource.set_propval_unsafe(prop.into(), agents.clone().into());
+            }
+        }
+    }
+
+    fn propvals(&self) -> Vec<(&'static str, Value)> {
+        [(urls::READ, &self.read), (urls::WRITE, &self.write)]
+            .into_iter()
+            .filter(|(_, agents)| !agents.is_empty())
+            .map(|(prop, agents)| (prop, agents.clone().into()))
+            .collect()
+    }
+}
+
+/// Returns the default rights of the nearest Drive of a newly created Resource.
+/// Returns `None` if the Commit sets or pushes rights itself, or if the Drive has no defaults.
+pub fn for_new_resource(
+    store: &impl Storelike,
+    commit: &Commit,
+    resource_new: &Resource,
+) -> AtomicResult<Option<DefaultRights>> {
+    let sets_rights = commit
+        .set
+        .iter()
+        .chain(commit.push.iter())
+        .flat_map(|changes| changes.keys())
+        .any(|prop| prop == urls::READ || prop == urls::WRITE);
+    if sets_rights {
+        return Ok(None);
+    }
+    let Some(drive) = resource_new
+        .get_parent_tree(store)?
+        .into_iter()
+        .find(crate::hierarchy::is_drive)
+    else {
+        return Ok(None);
+    };
+
+    let agents = |prop: &str| -> AtomicResult<Vec<String>> {
+        match drive.get(prop) {
+            Ok(value) => value.to_subjects(None),
+            Err(_) => Ok(Vec::new()),
+        }
+    };
+    let mut read = agents(urls::DEFAULT_READ)?;
+    let write = agents(urls::DEFAULT_WRITE)?;
+    let public = drive
+        .get(urls::NEW_RESOURCES_PUBLIC)
+        .and_then(|v| v.to_bool())
+        .unwrap_or(false);
+    if public && !read.iter().any(|agent| agent == urls::PUBLIC_AGENT) {
+        read.push(urls::PUBLIC_AGENT.into());
+    }
+    if read.is_empty() && write.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(DefaultRights {
+        drive: drive.get_subject().clone(),
+        read,
+        write,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        agents::ForAgent,
+        commit::{CommitBuilder, CommitOpts},
+        hierarchy::{check_read, check_write},
+        Db,
+    };
+
+    fn create_in(store: &Db, parent: &str, rights: Option<&str>) -> Resource {
+        let agent = store.get_default_agent().unwrap();
+        let subject = format!(
+            "{}/{}",
+            store.get_server_url(),
+            crate::utils::random_string(8)
+        );
+        let mut builder = CommitBuilder::new(subject.clone());
+        builder.set(urls::PARENT.into(), Value::AtomicUrl(parent.into()));
+        if let Some(reader) = rights {
+            builder.set(urls::READ.into(), vec![reader.to_string()].into());
+        }
+        let commit = builder
+            .sign(&agent, store, &Resource::new(subject))
+            .unwrap();
+        let opts = CommitOpts {
+            validate_schema: true,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_rights: true,
+            validate_previous_commit: true,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        commit
+            .apply_opts(store, &opts)
+            .unwrap()
+            .resource_new
+            .unwrap()
+    }
+
+    /// Drives without a parent, so they don't inherit the public read rights of the root Drive.
+    fn create_drive(store: &Db, propvals: Vec<(&str, Value)>) -> String {
+        let mut drive = Resource::new_generate_subject(store);
+        drive.set_class(urls::DRIVE);
+        drive
+            .set_propval(urls::NAME.into(), Value::String("Team".into()), store)
+            .unwrap();
+        for (prop, value) in propvals {
+            drive.set_propval(prop.into(), value, store).unwrap();
+        }
+        drive.save_locally(store).unwrap();
+        drive.get_subject().clone()
+    }
+
+    #[test]
+    fn applies_drive_defaults() {
+        let store = Db::init_temp("applies_drive_defaults").unwrap();
+        let teammate = store.create_agent(Some("teammate")).unwrap().subject;
+        let outsider = store.create_agent(Some("outsider")).unwrap().subject;
+
+        let public_drive = create_drive(&store, vec![(urls::NEW_RESOURCES_PUBLIC, true.into())]);
+        let public = create_in(&store, &public_drive, None);
+        check_read(&store, &public, &ForAgent::Public).unwrap();
+        check_write(&store, &public, &outsider.clone().into()).unwrap_err();
+
+        let team_drive = create_drive(
+            &store,
+            vec![(urls::DEFAULT_WRITE, vec![teammate.clone()].into())],
+        );
+        let team = create_in(&store, &team_drive, None);
+        check_write(&store, &team, &teammate.clone().into()).unwrap();
+        check_read(&store, &team, &ForAgent::Public).unwrap_err();
+        let commit = store
+            .get_resource(&team.get(urls::LAST_COMMIT).unwrap().to_string())
+            .unwrap();
+        assert_eq!(
+            commit
+                .get(urls::APPLIED_WRITE)
+                .unwrap()
+                .to_subjects(None)
+                .unwrap(),
+            vec![teammate.clone()]
+        );
+
+        // Rights in the Commit take precedence over the defaults
+        let explicit = create_in(&store, &team_drive, Some(&outsider));
+        check_write(&store, &explicit, &teammate.clone().into()).unwrap_err();
+        check_read(&store, &explicit, &outsider.clone().into()).unwrap();
+
+        // Changing the defaults does not change existing Resources
+        let mut drive = store.get_resource(&team_drive).unwrap();
+        drive.remove_propval(urls::DEFAULT_WRITE);
+        drive.save_locally(&store).unwrap();
+        let team = store.get_resource(team.get_subject()).unwrap();
+        check_write(&store, &team, &teammate.clone().into()).unwrap();
+        let later = create_in(&store, &team_drive, None);
+        check_write(&store, &later, &teammate.into()).unwrap_err();
+    }
+}
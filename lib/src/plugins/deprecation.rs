@@ -0,0 +1,168 @@
+/*!
+Deprecated Resources have been superseded, for example by a new version of a document.
+They are marked with `deprecated`, and can point to their successor with `replaced-by`.
+
+A deprecated Resource can still be read and destroyed, but Commits that edit it are rejected.
+Commits that un-deprecate it, or that only change `deprecated` and `replaced-by`, are allowed.
+Others can be applied anyway by setting `force` on the Commit.
+*/
+
+use std::collections::HashMap;
+
+use crate::{
+    errors::AtomicResult, urls, validate::replacement_warning, Commit, Resource, Storelike, Value,
+};
+
+/// Whether the Resource is marked as `deprecated`.
+pub fn is_deprecated(resource: &Resource) -> bool {
+    resource
+        .get(urls::DEPRECATED)
+        .and_then(|v| v.to_bool())
+        .unwrap_or(false)
+}
+
+/// The subject of the Resource that replaces this deprecated Resource, if any.
+pub fn replaced_by(resource: &Resource) -> Option<String> {
+    if !is_deprecated(resource) {
+        return None;
+    }
+    resource.get(urls::REPLACED_BY).ok().map(|v| v.to_string())
+}
+
+/// The Properties that the Commit changes.
+pub(crate) fn changed_properties(commit: &Commit) -> impl Iterator<Item = &String> {
+    fn keys(map: &Option<HashMap<String, Value>>) -> impl Iterator<Item = &String> {
+        map.iter().flat_map(|map| map.keys())
+    }
+    keys(&commit.set)
+        .chain(commit.remove.iter().flatten())
+        .chain(keys(&commit.push))
+        .chain(keys(&commit.pull))
+        .chain(keys(&commit.patch))
+        .chain(keys(&commit.increment))
+}
+
+/// Called before any Commit is applied, see [crate::Commit::apply_opts].
+pub fn before_apply_commit(
+    store: &impl Storelike,
+    commit: &Commit,
+    resource_old: &Resource,
+    resource_new: &Resource,
+    is_new: bool,
+) -> AtomicResult<()> {
+    if is_deprecated(resource_new) {
+        if let Some(warning) = replacement_warning(store, resource_new) {
+            tracing::warn!(
+                "Deprecated Resource {}: {}",
+                resource_new.get_subject(),
+                warning
+            );
+        }
+    }
+    if is_new
+        || !is_deprecated(resource_old)
+        || !is_deprecated(resource_new)
+        || commit.force.unwrap_or(false)
+        || commit.destroy.unwrap_or(false)
+    {
+        return Ok(());
+    }
+    let only_deprecation = changed_properties(commit)
+        .all(|prop| prop == urls::DEPRECATED || prop == urls::REPLACED_BY);
+    if only_deprecation {
+        return Ok(());
+    }
+    let replacement = replaced_by(resource_old)
+        .map(|r| format!(", it has been replaced by {}", r))
+        .unwrap_or_default();
+    Err(format!(
+        "Resource {} is deprecated{}. Un-deprecate it first, or set `force` on the Commit to edit it anyway.",
+        resource_old.get_subject(),
+        replacement
+    )
+    .into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        commit::{CommitBuilder, CommitOpts},
+        Db,
+    };
+
+    fn apply(store: &Db, subject: &str, build: impl Fn(&mut CommitBuilder)) -> AtomicResult<()> {
+        let agent = store.get_default_agent().unwrap();
+        let resource = store.get_resource(subject).unwrap();
+        let mut commitbuilder = CommitBuilder::new(subject.into());
+        build(&mut commitbuilder);
+        let opts = CommitOpts {
+            validate_schema: true,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_rights: true,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        commitbuilder
+            .sign(&agent, store, &resource)?
+            .apply_opts(store, &opts)
+            .map(|_| ())
+    }
+
+    #[test]
+    fn edit_deprecated() {
+        let store = Db::init_temp("edit_deprecated").unwrap();
+        let mut resource = Resource::new_generate_subject(&store);
+        resource
+            .set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                &store,
+            )
+            .unwrap();
+        resource.save_locally(&store).unwrap();
+        let subject = resource.get_subject().clone();
+        let name = |name: &str| Value::String(name.into());
+
+        apply(&store, &subject, |c| {
+            c.set(urls::DEPRECATED.into(), Value::Boolean(true));
+            c.set(
+                urls::REPLACED_BY.into(),
+                Value::AtomicUrl(format!("{}/missing", store.get_server_url())),
+            );
+        })
+        .unwrap();
+        let deprecated = store.get_resource(&subject).unwrap();
+        assert!(replaced_by(&deprecated).is_some());
+        assert!(replacement_warning(&store, &deprecated).is_some());
+
+        assert!(apply(&store, &subject, |c| c
+            .set(urls::NAME.into(), name("edited")))
+        .is_err());
+        // Changing where it points to is allowed
+        apply(&store, &subject, |c| {
+            c.set(
+                urls::REPLACED_BY.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+            )
+        })
+        .unwrap();
+        apply(&store, &subject, |c| {
+            c.set(urls::NAME.into(), name("forced"));
+            c.force(true);
+        })
+        .unwrap();
+        apply(&store, &subject, |c| {
+            c.set(urls::DEPRECATED.into(), Value::Boolean(false));
+            c.set(urls::NAME.into(), name("current"));
+        })
+        .unwrap();
+        apply(&store, &subject, |c| {
+            c.set(urls::NAME.into(), name("edited"))
+        })
+        .unwrap();
+    }
+}
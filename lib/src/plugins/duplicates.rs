@@ -0,0 +1,268 @@
+/*!
+Merging duplicate Resources, for example after importing the same data twice.
+Duplicates are found using [crate::Db::find_duplicates].
+
+[merge_resources] keeps one of the duplicates, and changes every reference to the others (including the `parent` of their children) into a reference to the kept Resource.
+After that, the other duplicates are destroyed, which moves them to the trash of their Drive.
+All changes are Commits signed by the server, so they show up in the history and can be reverted.
+*/
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+use crate::{
+    commit::{CommitBuilder, CommitOpts},
+    errors::AtomicResult,
+    overlay::Overlay,
+    storelike::Query,
+    urls,
+    values::SubResource,
+    Db, Storelike, Value,
+};
+
+/// The changes made by [merge_resources], or that would be made in a dry run.
+#[derive(Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeReport {
+    /// Resources that referred to a removed duplicate, with the Properties that now refer to the kept Resource
+    pub updated: Vec<UpdatedReferrer>,
+    /// The duplicates that are destroyed
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct UpdatedReferrer {
+    pub subject: String,
+    pub properties: Vec<String>,
+}
+
+/// Replaces all references to the `remove` subjects with `keep`, and destroys the `remove` Resources.
+/// If `dry_run` is true, the changes are made in an [Overlay], so they fail like the real merge would, but the store is left as it is.
+pub fn merge_resources(
+    store: &Db,
+    keep: &str,
+    remove: &[String],
+    dry_run: bool,
+) -> AtomicResult<MergeReport> {
+    if dry_run {
+        merge(&Overlay::new(store), keep, remove)
+    } else {
+        merge(store, keep, remove)
+    }
+}
+
+fn merge(store: &impl Storelike, keep: &str, remove: &[String]) -> AtomicResult<MergeReport> {
+    if remove.iter().any(|subject| subject == keep) {
+        return Err(format!("{} can't be both kept and removed", keep).into());
+    }
+    store.get_resource(keep)?;
+    let remove: BTreeSet<&str> = remove.iter().map(|s| s.as_str()).collect();
+
+    let mut referrers: BTreeSet<String> = BTreeSet::new();
+    for subject in &remove {
+        store.get_resource(subject)?;
+        let mut query = Query::new();
+        query.value = Some(Value::AtomicUrl(subject.to_string()));
+        query.include_external = true;
+        query.include_nested = false;
+        referrers.extend(
+            store
+                .query(&query)?
+                .subjects
+                .into_iter()
+                .filter(|referrer| !remove.contains(referrer.as_str())),
+        );
+    }
+
+    let agent = store.get_default_agent()?;
+    let opts = CommitOpts {
+        validate_schema: false,
+        validate_signature: false,
+        validate_timestamp: false,
+        validate_rights: false,
+        validate_previous_commit: false,
+        validate_for_agent: None,
+        update_index: true,
+        validate_relative_urls: false,
+    };
+    let mut report = MergeReport::default();
+
+    for subject in referrers {
+        let resource = store.get_resource(&subject)?;
+        // Commits are the history of a Resource, so they keep referring to the removed duplicates
+        if resource
+            .get_main_class()
+            .map(|class| class == urls::COMMIT)
+            .unwrap_or(false)
+        {
+            continue;
+        }
+        let mut commitbuilder = CommitBuilder::new(subject.clone());
+        let mut properties = Vec::new();
+        for (prop, value) in resource.get_propvals() {
+            let Some(replaced) = replace_references(value, keep, &remove) else {
+                continue;
+            };
+            if subject == keep && prop == urls::PARENT {
+                return Err(format!(
+                    "{} can't be kept, because its parent is one of the removed duplicates",
+                    keep
+                )
+                .into());
+            }
+            commitbuilder.set(prop.clone(), replaced);
+            properties.push(prop.clone());
+        }
+        if properties.is_empty() {
+            continue;
+        }
+        properties.sort();
+        commitbuilder
+            .sign(&agent, store, &resource)?
+            .apply_opts(store, &opts)?;
+        report.updated.push(UpdatedReferrer {
+            subject,
+            properties,
+        });
+    }
+
+    for subject in remove {
+        let resource = store.get_resource(subject)?;
+        let mut commitbuilder = CommitBuilder::new(subject.into());
+        commitbuilder.destroy(true);
+        commitbuilder
+            .sign(&agent, store, &resource)?
+            .apply_opts(store, &opts)?;
+        report.removed.push(subject.into());
+    }
+    Ok(report)
+}
+
+/// Returns the Value with the references to `remove` replaced by `keep`, or `None` if it has no such references.
+/// Arrays keep their order, but only contain `keep` once.
+fn replace_references(value: &Value, keep: &str, remove: &BTreeSet<&str>) -> Option<Value> {
+    match value {
+        Value::AtomicUrl(subject) if remove.contains(subject.as_str()) => {
+            Some(Value::AtomicUrl(keep.into()))
+        }
+        Value::ResourceArray(items) => {
+            let refers = items
+                .iter()
+                .any(|item| matches!(item, SubResource::Subject(s) if remove.contains(s.as_str())));
+            if !refers {
+                return None;
+            }
+            let mut has_keep = false;
+            let mut replaced = Vec::with_capacity(items.len());
+            for item in items {
+                match item {
+                    SubResource::Subject(s) if s == keep || remove.contains(s.as_str()) => {
+                        if !has_keep {
+                            replaced.push(SubResource::Subject(keep.into()));
+                            has_keep = true;
+                        }
+                    }
+                    other => replaced.push(other.clone()),
+                }
+            }
+            Some(Value::ResourceArray(replaced))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_and_merge_duplicates() {
+        let store = Db::init_temp("find_and_merge_duplicates").unwrap();
+        let create = |propvals: Vec<(&str, Value)>| {
+            store
+                .create_test_resource(store.get_server_url(), propvals)
+                .get_subject()
+                .clone()
+        };
+        let tags = |tags: &[&str]| Value::from(tags.to_vec());
+        let a = create(vec![
+            (urls::NAME, Value::String("Apple".into())),
+            (urls::WRITE, tags(&[urls::PUBLIC_AGENT, urls::SUDO_AGENT])),
+        ]);
+        // Case, whitespace and the order of array members don't matter
+        let b = create(vec![
+            (urls::NAME, Value::String(" apple ".into())),
+            (urls::WRITE, tags(&[urls::SUDO_AGENT, urls::PUBLIC_AGENT])),
+        ]);
+        let other_array = create(vec![
+            (urls::NAME, Value::String("apple".into())),
+            (urls::WRITE, tags(&[urls::PUBLIC_AGENT])),
+        ]);
+        let without_array = create(vec![(urls::NAME, Value::String("Apple".into()))]);
+
+        let mut expected = vec![a.clone(), b.clone()];
+        expected.sort();
+        let groups = store
+            .find_duplicates(None, &[urls::NAME, urls::WRITE])
+            .unwrap();
+        assert_eq!(groups, vec![expected]);
+        let by_name = store.find_duplicates(None, &[urls::NAME]).unwrap();
+        let apples = by_name.iter().find(|group| group.contains(&a)).unwrap();
+        assert_eq!(apples.len(), 4);
+        assert!(apples.contains(&other_array) && apples.contains(&without_array));
+        assert!(store
+            .find_duplicates(Some(urls::CLASS), &[urls::NAME])
+            .unwrap()
+            .is_empty());
+
+        let child = create(vec![(urls::NAME, Value::String("Core".into()))]);
+        let mut child_resource = store.get_resource(&child).unwrap();
+        child_resource
+            .set_propval(urls::PARENT.into(), Value::AtomicUrl(b.clone()), &store)
+            .unwrap();
+        child_resource
+            .set_propval(urls::READ.into(), tags(&[a.as_str(), b.as_str()]), &store)
+            .unwrap();
+        child_resource.save_locally(&store).unwrap();
+
+        let dry_run = merge_resources(&store, &a, &[b.clone()], true).unwrap();
+        assert_eq!(
+            dry_run.updated,
+            vec![UpdatedReferrer {
+                subject: child.clone(),
+                properties: vec![urls::PARENT.into(), urls::READ.into()],
+            }]
+        );
+        assert_eq!(dry_run.removed, vec![b.clone()]);
+        assert_eq!(
+            store
+                .get_resource(&child)
+                .unwrap()
+                .get(urls::PARENT)
+                .unwrap()
+                .to_string(),
+            b
+        );
+
+        let report = merge_resources(&store, &a, &[b.clone()], false).unwrap();
+        assert_eq!(report, dry_run);
+        let child_resource = store.get_resource(&child).unwrap();
+        assert_eq!(child_resource.get(urls::PARENT).unwrap().to_string(), a);
+        assert_eq!(
+            child_resource
+                .get(urls::READ)
+                .unwrap()
+                .to_subjects(None)
+                .unwrap(),
+            vec![a.clone()]
+        );
+        assert!(crate::plugins::trash::is_trashed(
+            &store.get_resource(&b).unwrap()
+        ));
+        assert!(store
+            .find_duplicates(None, &[urls::NAME, urls::WRITE])
+            .unwrap()
+            .is_empty());
+    }
+}
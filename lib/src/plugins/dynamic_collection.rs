@@ -0,0 +1,282 @@
+/*!
+# DynamicCollection
+A stored query, like "all open Tasks assigned to me, sorted by due date".
+Fetching it runs a [StructuredQuery] and returns the matching Resources as its members, paginated using `current-page` and `page-size`.
+The collection itself is checked like any other Resource, and only members that the Agent can read are listed and counted.
+WebSocket subscribers are notified when a Commit makes a Resource enter or leave the results, see `collection_watch` in the server.
+*/
+
+use crate::{
+    agents::ForAgent,
+    db::{Condition, StructuredQuery},
+    errors::AtomicResult,
+    hierarchy::check_read,
+    plugins::trash::is_trashed,
+    urls, Db, Resource, Storelike, Value,
+};
+
+/// A condition value that is replaced by the Agent that fetches the collection.
+pub const AGENT_PLACEHOLDER: &str = "$agent";
+
+const DEFAULT_PAGE_SIZE: usize = 30;
+
+#[derive(Debug, Clone)]
+pub struct DynamicCollection {
+    pub subject: String,
+    /// The stored query, with [AGENT_PLACEHOLDER] still in the condition values. Use [DynamicCollection::query_for].
+    pub query: StructuredQuery,
+}
+
+impl DynamicCollection {
+    pub fn from_resource(resource: &Resource) -> AtomicResult<DynamicCollection> {
+        let conditions: Vec<Condition> = match resource.get(urls::DYNAMIC_COLLECTION_CONDITIONS) {
+            Ok(conditions) => serde_json::from_str(&conditions.to_string()).map_err(|e| {
+                format!(
+                    "The conditions must be a JSON array of {{property, operator, value}} objects. {}",
+                    e
+                )
+            })?,
+            Err(_) => Vec::new(),
+        };
+        let query = StructuredQuery {
+            class: resource
+                .get(urls::DYNAMIC_COLLECTION_CLASS)
+                .ok()
+                .map(|class| class.to_string()),
+            parent: resource
+                .get(urls::DYNAMIC_COLLECTION_SCOPE)
+                .ok()
+                .map(|scope| scope.to_string()),
+            conditions,
+            sort_by: resource
+                .get(urls::COLLECTION_SORT_BY)
+                .ok()
+                .map(|prop| prop.to_string()),
+            sort_desc: match resource.get(urls::COLLECTION_SORT_DESC) {
+                Ok(desc) => desc.to_bool()?,
+                Err(_) => false,
+            },
+            limit: match resource.get(urls::COLLECTION_PAGE_SIZE) {
+                Ok(size) => Some(size.to_int()?.try_into().unwrap_or(DEFAULT_PAGE_SIZE)),
+                Err(_) => None,
+            },
+            ..Default::default()
+        };
+        if query.class.is_none() && query.parent.is_none() && query.conditions.is_empty() {
+            return Err(
+                "A DynamicCollection needs at least a class, a condition or a scope".into(),
+            );
+        }
+        Ok(DynamicCollection {
+            subject: resource.get_subject().clone(),
+            query,
+        })
+    }
+
+    /// The query for one Agent, with [AGENT_PLACEHOLDER] replaced by its subject.
+    pub fn query_for(&self, for_agent: &ForAgent) -> StructuredQuery {
+        let mut query = self.query.clone();
+        for condition in &mut query.conditions {
+            if condition.value.as_str() == Some(AGENT_PLACEHOLDER) {
+                condition.value = serde_json::Value::String(for_agent.to_string());
+            }
+        }
+        query
+    }
+
+    /// The Properties that a Commit has to change to make a Resource enter or leave the results.
+    /// Moving a Resource to the trash removes it, so [urls::DELETED_AT] is always included.
+    pub fn properties(&self) -> Vec<String> {
+        let mut properties: Vec<String> = self
+            .query
+            .conditions
+            .iter()
+            .map(|condition| condition.property.clone())
+            .collect();
+        if self.query.class.is_some() {
+            properties.push(urls::IS_A.into());
+        }
+        if self.query.parent.is_some() {
+            properties.push(urls::PARENT.into());
+        }
+        properties.push(urls::DELETED_AT.into());
+        properties
+    }
+
+    /// Whether the Resource is in the results for the Agent: it matches the query, is not in the trash, and the Agent can read it.
+    /// Checks a single Resource without running the query, so it also works for a Resource that was just destroyed.
+    pub fn is_member(&self, store: &Db, resource: &Resource, for_agent: &ForAgent) -> bool {
+        self.query_for(for_agent).matches(store, resource)
+            && !is_trashed(resource)
+            && check_read(store, resource, for_agent).is_ok()
+    }
+
+    /// Checks that the Class and the Properties of the conditions exist.
+    pub fn validate(&self, store: &impl Storelike) -> AtomicResult<()> {
+        if let Some(class) = &self.query.class {
+            store.get_class(class)?;
+        }
+        for condition in &self.query.conditions {
+            store.get_property(&condition.property)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the query of the DynamicCollection and adds the requested page of members.
+#[tracing::instrument(skip(store, query_params))]
+pub fn construct_dynamic_collection(
+    store: &Db,
+    query_params: url::form_urlencoded::Parse,
+    resource: &mut Resource,
+    for_agent: &ForAgent,
+) -> AtomicResult<Resource> {
+    let collection = DynamicCollection::from_resource(resource)?;
+    let mut current_page = 0;
+    let mut page_size = collection.query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let mut include_nested = false;
+    for (k, v) in query_params {
+        match k.as_ref() {
+            "current_page" | "current-page" => current_page = v.parse::<usize>()?,
+            "page_size" | "page-size" => page_size = v.parse::<usize>()?,
+            "include_nested" | "include-nested" => include_nested = v.parse::<bool>()?,
+            e => return Err(format!("Invalid query param: {}", e).into()),
+        }
+    }
+    if page_size == 0 {
+        return Err("page-size must be at least 1".into());
+    }
+    let mut query = collection.query_for(for_agent);
+    query.limit = Some(page_size);
+    query.offset = current_page * page_size;
+    let result = store.structured_query(&query, None, for_agent)?;
+    let total_pages = result.count.div_ceil(page_size);
+    let page = result.into_resource(resource.get_subject().clone(), include_nested);
+    for prop in [urls::COLLECTION_MEMBERS, urls::COLLECTION_MEMBER_COUNT] {
+        resource.set_propval_unsafe(prop.into(), page.get(prop)?.clone());
+    }
+    resource.set_propval_unsafe(
+        urls::COLLECTION_CURRENT_PAGE.into(),
+        Value::Integer(current_page as i64),
+    );
+    resource.set_propval_unsafe(
+        urls::COLLECTION_TOTAL_PAGES.into(),
+        Value::Integer(total_pages as i64),
+    );
+    Ok(resource.to_owned())
+}
+
+/// Rejects DynamicCollections with invalid conditions, or with a Class or Properties that don't exist.
+pub fn before_apply_commit(
+    store: &impl Storelike,
+    commit: &crate::Commit,
+    resource_new: &Resource,
+) -> AtomicResult<()> {
+    if commit.destroy.unwrap_or(false) {
+        return Ok(());
+    }
+    DynamicCollection::from_resource(resource_new)
+        .and_then(|collection| collection.validate(store))
+        .map_err(|e| format!("Invalid DynamicCollection. {}", e).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Any Class from the default store, so it doesn't have to be fetched.
+    const TASK: &str = urls::MESSAGE;
+
+    fn members(resource: &Resource) -> Vec<String> {
+        resource
+            .get(urls::COLLECTION_MEMBERS)
+            .unwrap()
+            .to_subjects(None)
+            .unwrap()
+    }
+
+    #[test]
+    fn members_match_the_stored_query() {
+        let store = Db::init_temp("dynamic_collection_members").unwrap();
+        let agent = store.get_default_agent().unwrap().subject;
+        let task = |status: &str, assignee: &str, due: i64| {
+            store.create_test_resource(
+                store.get_server_url(),
+                vec![
+                    (urls::IS_A, Value::from(vec![TASK.to_string()])),
+                    (urls::SHORTNAME, Value::Slug(status.into())),
+                    (urls::WRITE, Value::from(vec![assignee.to_string()])),
+                    (urls::CREATED_AT, Value::Timestamp(due)),
+                ],
+            )
+        };
+        let late = task("open", &agent, 300);
+        let early = task("open", &agent, 100);
+        let done = task("done", &agent, 200);
+        let _other = task("open", "https://example.com/someone", 50);
+
+        let conditions = serde_json::json!([
+            {"property": urls::SHORTNAME, "operator": "eq", "value": "open"},
+            {"property": urls::WRITE, "operator": "eq", "value": AGENT_PLACEHOLDER},
+        ]);
+        let mut resource = store.create_test_resource(
+            store.get_server_url(),
+            vec![
+                (
+                    urls::IS_A,
+                    Value::from(vec![urls::DYNAMIC_COLLECTION.to_string()]),
+                ),
+                (urls::NAME, Value::String("My open tasks".into())),
+                (
+                    urls::DYNAMIC_COLLECTION_CLASS,
+                    Value::AtomicUrl(TASK.into()),
+                ),
+                (
+                    urls::DYNAMIC_COLLECTION_CONDITIONS,
+                    Value::String(conditions.to_string()),
+                ),
+                (
+                    urls::COLLECTION_SORT_BY,
+                    Value::AtomicUrl(urls::CREATED_AT.into()),
+                ),
+                (urls::COLLECTION_PAGE_SIZE, Value::Integer(1)),
+            ],
+        );
+        let for_agent = ForAgent::AgentSubject(agent.clone());
+        let params = url::Url::parse("https://example.com?current-page=1").unwrap();
+        let page =
+            construct_dynamic_collection(&store, params.query_pairs(), &mut resource, &for_agent)
+                .unwrap();
+        assert_eq!(members(&page), vec![late.get_subject().clone()]);
+        assert_eq!(
+            page.get(urls::COLLECTION_MEMBER_COUNT)
+                .unwrap()
+                .to_int()
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            page.get(urls::COLLECTION_TOTAL_PAGES)
+                .unwrap()
+                .to_int()
+                .unwrap(),
+            2
+        );
+
+        let collection = DynamicCollection::from_resource(&resource).unwrap();
+        assert!(collection.is_member(&store, &early, &for_agent));
+        assert!(!collection.is_member(&store, &done, &for_agent));
+        assert!(collection.properties().contains(&urls::IS_A.to_string()));
+
+        let params = url::Url::parse("https://example.com?unknown=1").unwrap();
+        construct_dynamic_collection(&store, params.query_pairs(), &mut resource, &for_agent)
+            .unwrap_err();
+
+        resource.remove_propval(urls::DYNAMIC_COLLECTION_CLASS);
+        resource.set_propval_unsafe(
+            urls::DYNAMIC_COLLECTION_CONDITIONS.into(),
+            Value::String("{}".into()),
+        );
+        DynamicCollection::from_resource(&resource).unwrap_err();
+    }
+}
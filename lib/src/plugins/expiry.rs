@@ -0,0 +1,135 @@
+/*!
+Resources with an `expiresAt` timestamp disappear once that moment has passed, which is useful for temporary Resources such as share links and Invites.
+
+Expired Resources respond with a `410 Gone` error, which also hides them from Collections and search results.
+They are removed by [remove_expired], which the server runs periodically.
+It finds expired Resources using the query index sorted by `expiresAt`, so it doesn't have to scan the entire store.
+Removing happens with a destroy Commit, so expired Resources in a Drive are moved to its trash, and the history is kept.
+To extend the expiry, change `expiresAt` with a normal Commit before it passes.
+*/
+
+use crate::{
+    commit::{CommitBuilder, CommitOpts},
+    errors::{AtomicError, AtomicResult},
+    storelike::Query,
+    urls,
+    utils::now,
+    Db, Resource, Storelike, Value,
+};
+
+/// Whether the `expiresAt` of the Resource has passed at the `moment` (unix timestamp in milliseconds).
+pub fn is_expired(resource: &Resource, moment: i64) -> bool {
+    resource
+        .get(urls::EXPIRES_AT)
+        .and_then(|v| v.to_int())
+        .map(|expires_at| expires_at <= moment)
+        .unwrap_or(false)
+}
+
+/// Returns a `Gone` error if the Resource has expired.
+pub fn check_expiry(resource: &Resource) -> AtomicResult<()> {
+    if is_expired(resource, now()) {
+        return Err(AtomicError::gone(format!(
+            "Resource {} has expired",
+            resource.get_subject()
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the subjects of at most `limit` Resources that expired before `moment`, oldest first.
+pub fn expired_subjects(store: &Db, moment: i64, limit: usize) -> AtomicResult<Vec<String>> {
+    let mut query = Query::new();
+    query.property = Some(urls::EXPIRES_AT.into());
+    query.sort_by = Some(urls::EXPIRES_AT.into());
+    query.end_val = Some(Value::Timestamp(moment));
+    query.limit = Some(limit);
+    // Only the subjects are needed, expired Resources can't be fetched anyway.
+    query.include_nested = false;
+    Ok(store.query(&query)?.subjects)
+}
+
+/// Destroys at most `limit` expired Resources using Commits signed by the server. Returns the amount of destroyed Resources.
+pub fn remove_expired(store: &Db, limit: usize) -> AtomicResult<usize> {
+    let moment = now();
+    let agent = store.get_default_agent()?;
+    let opts = CommitOpts {
+        validate_schema: false,
+        validate_signature: false,
+        validate_timestamp: false,
+        validate_rights: false,
+        validate_previous_commit: false,
+        validate_for_agent: None,
+        update_index: true,
+        validate_relative_urls: false,
+    };
+    let mut removed = 0;
+    for subject in expired_subjects(store, moment, limit)? {
+        let Ok(resource) = store.get_resource(&subject) else {
+            continue;
+        };
+        // The expiry might have been extended after the index was read
+        if !is_expired(&resource, moment) {
+            continue;
+        }
+        let mut commitbuilder = CommitBuilder::new(subject);
+        commitbuilder.destroy(true);
+        commitbuilder
+            .sign(&agent, store, &resource)?
+            .apply_opts(store, &opts)?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::agents::ForAgent;
+
+    #[test]
+    fn removes_expired_resources() {
+        let store = Db::init_temp("removes_expired_resources").unwrap();
+        let hour = 60 * 60 * 1000;
+        let create = |expires_at: i64| {
+            store.create_test_resource(
+                store.get_server_url(),
+                vec![(urls::EXPIRES_AT, Value::Timestamp(expires_at))],
+            )
+        };
+        let expired = create(now() - hour);
+        let mut extended = create(now() - hour);
+        let valid = create(now() + hour);
+
+        let err = store
+            .get_resource_extended(expired.get_subject(), false, &ForAgent::Sudo)
+            .unwrap_err();
+        assert!(matches!(err.error_type, crate::AtomicErrorType::Gone));
+        store
+            .get_resource_extended(valid.get_subject(), false, &ForAgent::Sudo)
+            .unwrap();
+
+        // Owners can extend the expiry with a normal Commit
+        extended
+            .set_propval(
+                urls::EXPIRES_AT.into(),
+                Value::Timestamp(now() + hour),
+                &store,
+            )
+            .unwrap();
+        extended.save_locally(&store).unwrap();
+
+        let found = expired_subjects(&store, now(), 10).unwrap();
+        assert_eq!(found, vec![expired.get_subject().clone()]);
+
+        assert_eq!(remove_expired(&store, 10).unwrap(), 1);
+        assert!(expired_subjects(&store, now(), 10).unwrap().is_empty());
+        store.get_resource(extended.get_subject()).unwrap();
+        store.get_resource(valid.get_subject()).unwrap();
+
+        let mut query = Query::new_prop_val(urls::PARENT, store.get_server_url());
+        query.for_agent = ForAgent::Public;
+        let children = store.query(&query).unwrap().subjects;
+        assert!(!children.contains(expired.get_subject()));
+    }
+}
@@ -0,0 +1,697 @@
+/*!
+ImportProfiles store how CSV or JSON documents from other tools map to Atomic Data, so the same kind of document can be imported again and again using `/import?profile=`.
+A profile maps columns (or keys) to Properties, sets the Class and parent of the new Resources, and can transform values before they are stored.
+When a `key` Property is set, rows that match an existing Resource update it using Commits, which makes importing the same document twice idempotent.
+Profiles are checked when they are saved, see [before_apply_commit].
+*/
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agents::{Agent, ForAgent},
+    commit::CommitOpts,
+    datatype::DataType,
+    errors::AtomicResult,
+    storelike::Query,
+    subjects::{new_subject, SubjectHint},
+    urls,
+    values::SubResource,
+    Resource, Storelike, Value,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// Comma separated, with a header row that contains the column names
+    Csv,
+    /// An array of objects
+    Json,
+}
+
+impl std::str::FromStr for ImportFormat {
+    type Err = crate::AtomicError;
+
+    fn from_str(s: &str) -> AtomicResult<Self> {
+        match s {
+            "csv" => Ok(ImportFormat::Csv),
+            "json" => Ok(ImportFormat::Json),
+            other => Err(format!("Unknown import format '{}', use `csv` or `json`", other).into()),
+        }
+    }
+}
+
+/// Changes an imported value before it is stored. Applied in the order in which they are listed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Transform {
+    /// Reads a date or datetime, e.g. `%d-%m-%Y %H:%M`. Only for Date and Timestamp Properties.
+    DateFormat(String),
+    /// Splits the value into several subjects. Only for ResourceArray Properties.
+    Split(String),
+    /// Replaces entire values, e.g. `{"yes": "true"}`. Other values are kept.
+    Replace(BTreeMap<String, String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportProfile {
+    pub subject: String,
+    pub format: ImportFormat,
+    /// Column or key in the document -> Property URL
+    pub mapping: BTreeMap<String, String>,
+    pub target_class: String,
+    pub target_parent: String,
+    /// Property that identifies a row
+    pub key: Option<String>,
+    /// Property URL -> Transforms
+    pub transforms: BTreeMap<String, Vec<Transform>>,
+}
+
+impl ImportProfile {
+    pub fn from_resource(resource: &Resource) -> AtomicResult<ImportProfile> {
+        let mapping = resource.get(urls::IMPORT_PROFILE_MAPPING)?.to_string();
+        let mapping = serde_json::from_str(&mapping).map_err(|e| {
+            format!(
+                "The mapping must be a JSON object of columns to Property URLs. {}",
+                e
+            )
+        })?;
+        let transforms = match resource.get(urls::IMPORT_PROFILE_TRANSFORMS) {
+            Ok(transforms) => serde_json::from_str(&transforms.to_string()).map_err(|e| {
+                format!(
+                    "The transforms must be a JSON object of Property URLs to lists of transforms. {}",
+                    e
+                )
+            })?,
+            Err(_) => BTreeMap::new(),
+        };
+        Ok(ImportProfile {
+            subject: resource.get_subject().clone(),
+            format: resource
+                .get(urls::IMPORT_PROFILE_FORMAT)?
+                .to_string()
+                .parse()?,
+            mapping,
+            target_class: resource.get(urls::IMPORT_PROFILE_TARGET_CLASS)?.to_string(),
+            target_parent: resource
+                .get(urls::IMPORT_PROFILE_TARGET_PARENT)?
+                .to_string(),
+            key: resource
+                .get(urls::IMPORT_PROFILE_KEY)
+                .ok()
+                .map(|key| key.to_string()),
+            transforms,
+        })
+    }
+
+    /// Checks that the Properties and the Class exist, and that the transforms fit the datatypes of their Properties.
+    pub fn validate(&self, store: &impl Storelike) -> AtomicResult<()> {
+        store.get_class(&self.target_class)?;
+        for (column, property) in &self.mapping {
+            store.get_property(property).map_err(|e| {
+                format!(
+                    "Column '{}' is mapped to an unknown Property {}. {}",
+                    column, property, e
+                )
+            })?;
+        }
+        if let Some(key) = &self.key {
+            if !self.mapping.values().any(|property| property == key) {
+                return Err(format!("The key {} is not one of the mapped Properties", key).into());
+            }
+        }
+        for (property, transforms) in &self.transforms {
+            if !self.mapping.values().any(|mapped| mapped == property) {
+                return Err(format!(
+                    "There are transforms for {}, which is not one of the mapped Properties",
+                    property
+                )
+                .into());
+            }
+            let datatype = store.get_property(property)?.data_type;
+            for transform in transforms {
+                let fits = match transform {
+                    Transform::DateFormat(format) => {
+                        check_date_format(format)?;
+                        matches!(datatype, DataType::Date | DataType::Timestamp)
+                    }
+                    Transform::Split(_) => datatype == DataType::ResourceArray,
+                    Transform::Replace(_) => true,
+                };
+                if !fits {
+                    return Err(format!(
+                        "The transform {:?} can't be used for {}, which has the {} datatype",
+                        transform, property, datatype
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Rejects ImportProfiles that would fail during an import.
+pub fn before_apply_commit(
+    store: &impl Storelike,
+    commit: &crate::Commit,
+    resource_new: &Resource,
+) -> AtomicResult<()> {
+    if commit.destroy.unwrap_or(false) {
+        return Ok(());
+    }
+    ImportProfile::from_resource(resource_new)?
+        .validate(store)
+        .map_err(|e| format!("Invalid ImportProfile. {}", e).into())
+}
+
+/// The result of [import_with_profile].
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileImportReport {
+    /// The ImportProfile that was used
+    pub profile: String,
+    pub rows: usize,
+    pub created: usize,
+    /// Rows that matched an existing Resource with different values
+    pub updated: usize,
+    /// Rows that matched an existing Resource with the same values
+    pub unchanged: usize,
+    pub failed: Vec<RowError>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RowError {
+    /// Position of the row in the document, starting at 1. The header of a CSV document is not counted.
+    pub row: usize,
+    pub error: String,
+}
+
+/// Imports a document using an ImportProfile. Every row is saved using a Commit signed by the `signer`, and checked against the rights of the `for_agent`.
+/// Rows that fail are skipped and listed in the report.
+pub fn import_with_profile(
+    store: &impl Storelike,
+    profile: &ImportProfile,
+    body: &str,
+    for_agent: &ForAgent,
+    signer: &Agent,
+) -> AtomicResult<ProfileImportReport> {
+    let rows = match profile.format {
+        ImportFormat::Csv => parse_csv(body)?,
+        ImportFormat::Json => parse_json_rows(body)?,
+    };
+    let mut report = ProfileImportReport {
+        profile: profile.subject.clone(),
+        rows: rows.len(),
+        ..Default::default()
+    };
+    for (index, row) in rows.into_iter().enumerate() {
+        match import_row(store, profile, row, for_agent, signer) {
+            Ok(RowOutcome::Created) => report.created += 1,
+            Ok(RowOutcome::Updated) => report.updated += 1,
+            Ok(RowOutcome::Unchanged) => report.unchanged += 1,
+            Err(e) => report.failed.push(RowError {
+                row: index + 1,
+                error: e.to_string(),
+            }),
+        }
+    }
+    Ok(report)
+}
+
+enum RowOutcome {
+    Created,
+    Updated,
+    Unchanged,
+}
+
+/// A value from a document, before it is converted to the datatype of its Property.
+enum Cell {
+    Text(String),
+    List(Vec<String>),
+}
+
+fn import_row(
+    store: &impl Storelike,
+    profile: &ImportProfile,
+    row: BTreeMap<String, serde_json::Value>,
+    for_agent: &ForAgent,
+    signer: &Agent,
+) -> AtomicResult<RowOutcome> {
+    let mut propvals: BTreeMap<String, Value> = BTreeMap::new();
+    for (column, property) in &profile.mapping {
+        let cell = match row.get(column) {
+            None | Some(serde_json::Value::Null) => continue,
+            Some(serde_json::Value::String(text)) if text.trim().is_empty() => continue,
+            Some(serde_json::Value::String(text)) => Cell::Text(text.trim().to_string()),
+            Some(serde_json::Value::Array(items)) => Cell::List(
+                items
+                    .iter()
+                    .map(|item| match item {
+                        serde_json::Value::String(text) => text.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect(),
+            ),
+            Some(other) => Cell::Text(other.to_string()),
+        };
+        let full_property = store.get_property(property)?;
+        let mut cell = cell;
+        for transform in profile.transforms.get(property).into_iter().flatten() {
+            cell = apply_transform(cell, transform, &full_property.data_type)
+                .map_err(|e| format!("Column '{}': {}", column, e))?;
+        }
+        // Values can be replaced by nothing, e.g. a `-` placeholder
+        if matches!(&cell, Cell::Text(text) if text.is_empty()) {
+            continue;
+        }
+        let value = match (cell, &full_property.data_type) {
+            (Cell::List(items), DataType::ResourceArray) => {
+                Value::ResourceArray(items.into_iter().map(SubResource::Subject).collect())
+            }
+            (Cell::List(_), datatype) => {
+                return Err(format!(
+                    "Column '{}' contains a list, but {} has the {} datatype",
+                    column, property, datatype
+                )
+                .into())
+            }
+            (Cell::Text(text), datatype) => {
+                Value::new(&text, datatype).map_err(|e| format!("Column '{}': {}", column, e))?
+            }
+        };
+        propvals.insert(property.clone(), value);
+    }
+
+    let existing = match &profile.key {
+        Some(key) => match propvals.get(key) {
+            Some(key_value) => find_by_key(store, profile, key, key_value)?,
+            None => return Err(format!("The row has no value for the key {}", key).into()),
+        },
+        None => None,
+    };
+    let (mut resource, outcome) = match existing {
+        Some(resource) => {
+            let changed = propvals.iter().any(|(property, value)| {
+                resource
+                    .get(property)
+                    .map(|old| old.to_string() != value.to_string())
+                    .unwrap_or(true)
+            });
+            if !changed {
+                return Ok(RowOutcome::Unchanged);
+            }
+            (resource, RowOutcome::Updated)
+        }
+        None => {
+            let name = profile
+                .key
+                .as_ref()
+                .and_then(|key| propvals.get(key))
+                .map(|value| value.to_string());
+            let subject = new_subject(
+                store,
+                SubjectHint {
+                    parent: Some(profile.target_parent.as_str()),
+                    name: name.as_deref(),
+                },
+                || {
+                    format!(
+                        "{}/{}",
+                        profile.target_parent.trim_end_matches('/'),
+                        crate::utils::random_string(10)
+                    )
+                },
+            )?;
+            let mut resource = Resource::new(subject);
+            resource.set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(profile.target_parent.clone()),
+                store,
+            )?;
+            resource.set_propval(
+                urls::IS_A.into(),
+                Value::ResourceArray(vec![profile.target_class.as_str().into()]),
+                store,
+            )?;
+            (resource, RowOutcome::Created)
+        }
+    };
+    for (property, value) in propvals {
+        resource.set_propval(property, value, store)?;
+    }
+    let commit = resource
+        .get_commit_builder()
+        .clone()
+        .sign(signer, store, &resource)?;
+    let opts = CommitOpts {
+        validate_schema: true,
+        validate_signature: true,
+        validate_timestamp: false,
+        validate_rights: for_agent != &ForAgent::Sudo,
+        validate_previous_commit: false,
+        validate_for_agent: Some(for_agent.to_string()),
+        update_index: true,
+        validate_relative_urls: false,
+    };
+    commit
+        .apply_opts(store, &opts)
+        .map_err(|e| format!("Failed to save {}: {}", resource.get_subject(), e))?;
+    Ok(outcome)
+}
+
+/// Finds the Resource inside the target parent that has the same value for the key Property.
+fn find_by_key(
+    store: &impl Storelike,
+    profile: &ImportProfile,
+    key: &str,
+    value: &Value,
+) -> AtomicResult<Option<Resource>> {
+    let mut query = Query::new();
+    query.property = Some(key.into());
+    query.value = Some(value.clone());
+    Ok(store
+        .query(&query)?
+        .resources
+        .into_iter()
+        .find(|resource| resource.has_parent(store, &profile.target_parent)))
+}
+
+fn apply_transform(cell: Cell, transform: &Transform, datatype: &DataType) -> AtomicResult<Cell> {
+    let text = match cell {
+        Cell::Text(text) if text.is_empty() => return Ok(Cell::Text(text)),
+        Cell::Text(text) => text,
+        Cell::List(items) => {
+            return match transform {
+                Transform::Replace(replacements) => Ok(Cell::List(
+                    items
+                        .into_iter()
+                        .map(|item| replacements.get(&item).cloned().unwrap_or(item))
+                        .collect(),
+                )),
+                _ => Err(format!("{:?} can't be applied to a list", transform).into()),
+            };
+        }
+    };
+    match transform {
+        Transform::DateFormat(format) => {
+            let parts = parse_date(&text, format)?;
+            match datatype {
+                DataType::Timestamp => {
+                    let date = format!("{:04}-{:02}-{:02}", parts.year, parts.month, parts.day);
+                    let day = crate::utils::date_to_millis(&date)
+                        .ok_or(format!("'{}' is not a valid date", text))?;
+                    let time = ((parts.hour * 60 + parts.minute) * 60 + parts.second) * 1000;
+                    Ok(Cell::Text((day + time).to_string()))
+                }
+                _ => Ok(Cell::Text(format!(
+                    "{:04}-{:02}-{:02}",
+                    parts.year, parts.month, parts.day
+                ))),
+            }
+        }
+        Transform::Split(separator) => Ok(Cell::List(
+            text.split(separator.as_str())
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect(),
+        )),
+        Transform::Replace(replacements) => {
+            Ok(Cell::Text(replacements.get(&text).cloned().unwrap_or(text)))
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct DateParts {
+    year: i64,
+    month: i64,
+    day: i64,
+    hour: i64,
+    minute: i64,
+    second: i64,
+}
+
+/// Checks that a date format only uses the supported fields, and contains at least a year, month and day.
+fn check_date_format(format: &str) -> AtomicResult<()> {
+    let mut fields = Vec::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        match chars.next() {
+            Some(field @ ('Y' | 'm' | 'd' | 'H' | 'M' | 'S')) => fields.push(field),
+            Some('%') => {}
+            other => {
+                return Err(format!(
+                    "Unsupported field %{} in date format '{}'. Use %Y, %m, %d, %H, %M and %S.",
+                    other.map(String::from).unwrap_or_default(),
+                    format
+                )
+                .into())
+            }
+        }
+    }
+    if !['Y', 'm', 'd'].iter().all(|field| fields.contains(field)) {
+        return Err(format!(
+            "Date format '{}' needs a year (%Y), month (%m) and day (%d)",
+            format
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Reads a date using a format like `%d-%m-%Y`. Numbers can have fewer digits than usual, such as `1-2-2024`.
+fn parse_date(text: &str, format: &str) -> AtomicResult<DateParts> {
+    let error = || format!("'{}' does not match the date format '{}'", text, format);
+    let mut parts = DateParts::default();
+    let mut rest = text;
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        let literal = match c {
+            '%' => match chars.next() {
+                Some('%') => '%',
+                Some(field) => {
+                    let max_digits = if field == 'Y' { 4 } else { 2 };
+                    let digits = rest
+                        .chars()
+                        .take(max_digits)
+                        .take_while(|c| c.is_ascii_digit())
+                        .count();
+                    let number: i64 = rest[..digits].parse().map_err(|_| error())?;
+                    rest = &rest[digits..];
+                    match field {
+                        'Y' => parts.year = number,
+                        'm' => parts.month = number,
+                        'd' => parts.day = number,
+                        'H' => parts.hour = number,
+                        'M' => parts.minute = number,
+                        'S' => parts.second = number,
+                        _ => return Err(error().into()),
+                    }
+                    continue;
+                }
+                None => return Err(error().into()),
+            },
+            other => other,
+        };
+        rest = rest.strip_prefix(literal).ok_or_else(error)?;
+    }
+    if !rest.is_empty()
+        || !(1..=12).contains(&parts.month)
+        || !(1..=31).contains(&parts.day)
+        || parts.hour > 23
+        || parts.minute > 59
+        || parts.second > 59
+    {
+        return Err(error().into());
+    }
+    Ok(parts)
+}
+
+/// Reads a CSV document with a header row. Supports quoted fields, with `""` for a quote inside them.
+#[cfg(feature = "csv")]
+fn parse_csv(body: &str) -> AtomicResult<Vec<BTreeMap<String, serde_json::Value>>> {
+    let mut records: Vec<Vec<String>> = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = body.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, in_quotes) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            ('"', true) => in_quotes = false,
+            ('"', false) if field.is_empty() => in_quotes = true,
+            (',', false) => record.push(std::mem::take(&mut field)),
+            ('\r', false) if chars.peek() == Some(&'\n') => {}
+            ('\n', false) => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (c, _) => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err("The CSV document ends inside a quoted field".into());
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+
+    let mut records = records
+        .into_iter()
+        .filter(|record| !(record.len() == 1 && record[0].is_empty()));
+    let header = records.next().ok_or("The CSV document has no header row")?;
+    Ok(records
+        .map(|record| {
+            header
+                .iter()
+                .cloned()
+                .zip(record.into_iter().map(serde_json::Value::String))
+                .collect()
+        })
+        .collect())
+}
+
+#[cfg(not(feature = "csv"))]
+fn parse_csv(_body: &str) -> AtomicResult<Vec<BTreeMap<String, serde_json::Value>>> {
+    Err(crate::AtomicError::missing_feature(
+        "csv",
+        "Importing a CSV document",
+    ))
+}
+
+fn parse_json_rows(body: &str) -> AtomicResult<Vec<BTreeMap<String, serde_json::Value>>> {
+    let rows: Vec<BTreeMap<String, serde_json::Value>> = serde_json::from_str(body)
+        .map_err(|e| format!("The document must be a JSON array of objects. {}", e))?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Db;
+
+    /// Only requires a name
+    const TAG: &str = "https://atomicdata.dev/classes/Tag";
+
+    fn save_profile(store: &Db, subject: &str, transforms: &str) -> AtomicResult<()> {
+        let mut profile = Resource::new(subject.into());
+        profile.set_propval(
+            urls::IS_A.into(),
+            Value::ResourceArray(vec![urls::IMPORT_PROFILE.into()]),
+            store,
+        )?;
+        for (property, value) in [
+            (urls::NAME, "Weekly export"),
+            (urls::IMPORT_PROFILE_FORMAT, "csv"),
+            (
+                urls::IMPORT_PROFILE_MAPPING,
+                r#"{"Id": "https://atomicdata.dev/properties/shortname", "Title": "https://atomicdata.dev/properties/name", "Deadline": "https://atomicdata.dev/properties/published-at"}"#,
+            ),
+            (urls::IMPORT_PROFILE_TRANSFORMS, transforms),
+        ] {
+            profile.set_propval_string(property.into(), value, store)?;
+        }
+        profile.set_propval(
+            urls::IMPORT_PROFILE_TARGET_CLASS.into(),
+            Value::AtomicUrl(TAG.into()),
+            store,
+        )?;
+        profile.set_propval(
+            urls::IMPORT_PROFILE_TARGET_PARENT.into(),
+            Value::AtomicUrl(store.get_server_url().into()),
+            store,
+        )?;
+        profile.set_propval(
+            urls::IMPORT_PROFILE_KEY.into(),
+            Value::AtomicUrl(urls::SHORTNAME.into()),
+            store,
+        )?;
+        profile.save_locally(store)?;
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_profiles_are_rejected() {
+        let store = Db::init_temp("invalid_import_profiles").unwrap();
+        let subject = format!("{}/profile", store.get_server_url());
+        // `name` is a String, not a Date
+        let err = save_profile(
+            &store,
+            &subject,
+            r#"{"https://atomicdata.dev/properties/name": [{"dateFormat": "%d-%m-%Y"}]}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("can't be used"), "{}", err);
+        let err = save_profile(
+            &store,
+            &subject,
+            r#"{"https://atomicdata.dev/properties/published-at": [{"dateFormat": "%d-%m"}]}"#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("needs a year"), "{}", err);
+        assert!(store.get_resource(&subject).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "csv")]
+    fn reimporting_updates_instead_of_duplicating() {
+        let store = Db::init_temp("reimport_with_profile").unwrap();
+        let subject = format!("{}/profile", store.get_server_url());
+        save_profile(
+            &store,
+            &subject,
+            r#"{"https://atomicdata.dev/properties/published-at": [{"replace": {"-": ""}}, {"dateFormat": "%d/%m/%Y"}]}"#,
+        )
+        .unwrap();
+        let profile = ImportProfile::from_resource(&store.get_resource(&subject).unwrap()).unwrap();
+        let agent = store.get_default_agent().unwrap();
+        let csv = "Id,Title,Deadline\r\nfirst,\"Invoices, paid\",31/1/2024\r\nsecond,Second,-\r\n";
+
+        let report = import_with_profile(&store, &profile, csv, &ForAgent::Sudo, &agent).unwrap();
+        assert_eq!(report.profile, subject);
+        assert_eq!((report.rows, report.created), (2, 2), "{:?}", report);
+        let found = find_by_key(
+            &store,
+            &profile,
+            urls::SHORTNAME,
+            &Value::Slug("first".into()),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(found.get(urls::NAME).unwrap().to_string(), "Invoices, paid");
+
+        let report = import_with_profile(&store, &profile, csv, &ForAgent::Sudo, &agent).unwrap();
+        assert_eq!((report.created, report.unchanged), (0, 2), "{:?}", report);
+
+        let changed = "Id,Title,Deadline\nfirst,Invoices,31/1/2024\nthird,Third,99/1/2024\n";
+        let report =
+            import_with_profile(&store, &profile, changed, &ForAgent::Sudo, &agent).unwrap();
+        assert_eq!((report.updated, report.created), (1, 0), "{:?}", report);
+        assert_eq!(report.failed[0].row, 2);
+        let found = store.get_resource(found.get_subject()).unwrap();
+        assert_eq!(found.get(urls::NAME).unwrap().to_string(), "Invoices");
+    }
+
+    #[test]
+    #[cfg(not(feature = "csv"))]
+    fn csv_without_feature_names_it() {
+        let err = parse_csv("Id,Title\r\nfirst,First\r\n").unwrap_err();
+        assert!(err.message.contains("`csv`"), "{}", err);
+    }
+
+    #[test]
+    fn dates_are_read_using_the_format() {
+        let parts = parse_date("1-2-2024 13:05", "%d-%m-%Y %H:%M").unwrap();
+        assert_eq!((parts.year, parts.month, parts.day), (2024, 2, 1));
+        assert_eq!((parts.hour, parts.minute), (13, 5));
+        assert!(parse_date("2024-02-01", "%d-%m-%Y").is_err());
+    }
+}
@@ -1,5 +1,6 @@
 /*!
 Importers allow users to (periodically) import JSON-AD files from a remote source.
+JSON-LD documents are accepted too, see [crate::parse::parse_json_ld_string].
 */
 
 use crate::{
@@ -17,7 +18,7 @@ pub fn import_endpoint() -> Endpoint {
             urls::IMPORTER_PARENT.to_string(),
             urls::IMPORTER_URL.to_string(),
         ].into(),
-        description: "Imports one or more Resources to some parent. POST your JSON-AD and add a `parent` query param to the URL. See https://docs.atomicdata.dev/create-json-ad.html".to_string(),
+        description: "Imports one or more Resources to some parent. POST your JSON-AD and add a `parent` query param to the URL. See https://docs.atomicdata.dev/create-json-ad.html . JSON-LD is accepted too (detected by its `@context`, or with `format=json-ld`). Relative references (like `./attachment`) are resolved against the `base` query param, the `url` of the fetched document, or the `@id` of the Resource they are in. For JSON-LD, add `create-properties=true` to create Properties for unknown IRIs, and `fetch-contexts=true` to fetch remote contexts. AtomicServer imports JSON-AD bodies (arrays or line-delimited) while they are uploaded. Add `mode=continue` to skip failing Resources instead of aborting and rolling back the import, and accept `text/event-stream` (or add `progress=true`) to receive progress events.".to_string(),
         shortname: "path".to_string(),
         // Not sure if we need this, or if we should derive it from `None` here.
         handle: Some(handle_get),
@@ -41,7 +42,10 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
     let mut url = None;
     let mut json = None;
     let mut parent_maybe = None;
+    let mut base = None;
     let mut overwrite_outside = false;
+    let mut format = None;
+    let mut ld_opts = crate::parse::JsonLdOpts::default();
     for (k, v) in subject.query_pairs() {
         match k.as_ref() {
             "json" | urls::IMPORTER_URL => return Err("JSON must be POSTed in the body".into()),
@@ -50,6 +54,10 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
             "overwrite-outside" | urls::IMPORTER_OVERWRITE_OUTSIDE => {
                 overwrite_outside = v == "true"
             }
+            "format" => format = Some(v.to_string()),
+            "base" => base = Some(v.to_string()),
+            "create-properties" => ld_opts.create_missing_properties = v == "true",
+            "fetch-contexts" => ld_opts.fetch_remote_contexts = v == "true",
             _ => {}
         }
     }
@@ -63,10 +71,21 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
             })?);
     }
 
+    // Relative references in a fetched document are resolved against its URL, unless another `base` is declared
+    let base = base.or_else(|| url.clone());
     if let Some(fetch_url) = url {
         json = Some(
-            crate::client::fetch_body(&fetch_url, crate::parse::JSON_AD_MIME, None)
-                .map_err(|e| format!("Error while fetching {}: {}", fetch_url, e))?,
+            crate::client::fetch_body_for(
+                store,
+                crate::outbound::OutboundFeature::Import,
+                &fetch_url,
+                crate::parse::JSON_AD_MIME,
+                None,
+            )
+            .map_err(|e| crate::AtomicError {
+                message: format!("Error while fetching {}: {}", fetch_url, e.message),
+                ..e
+            })?,
         );
     }
 
@@ -78,13 +97,24 @@ pub fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
         // not the one performing the import, because we don't have their private key.
         signer: Some(store.get_default_agent()?),
         save: crate::parse::SaveOpts::Commit,
+        base,
     };
 
     if let Some(json_string) = json {
         if for_agent == &ForAgent::Public {
             return Err("No agent specified for importer".to_string().into());
         }
-        store.import(&json_string, &parse_opts)?;
+        let is_json_ld = match format.as_deref() {
+            Some("json-ld") | Some(crate::parse::JSON_LD_MIME) => true,
+            Some("json-ad") | Some(crate::parse::JSON_AD_MIME) => false,
+            Some(other) => return Err(format!("Unknown import format: {}", other).into()),
+            None => crate::parse::is_json_ld(&json_string),
+        };
+        if is_json_ld {
+            crate::parse::parse_json_ld_string(&json_string, store, &parse_opts, &ld_opts)?;
+        } else {
+            store.import(&json_string, &parse_opts)?;
+        }
     } else {
         return Err(
             "No JSON specified for importer. Pass a `url` query param, or post a JSON-AD body."
@@ -120,7 +120,7 @@ pub fn construct_invite_redirect(
 }
 
 /// Adds the requested rights to the target resource.
-/// Overwrites the target resource to include the new rights.
+/// Pushes the Agent to the rights array using `pushUnique`, so concurrent redemptions don't overwrite each other.
 /// Checks if the Agent has a valid URL.
 /// Will not throw an error if the Agent already has the rights.
 #[tracing::instrument(skip(store))]
@@ -35,15 +35,31 @@ They are used for performing custom queries, or calculating dynamic attributes.
 
 // Class Extenders
 pub mod chatroom;
+pub mod copy;
+pub mod default_rights;
+pub mod deprecation;
+pub mod duplicates;
+pub mod dynamic_collection;
+pub mod expiry;
+pub mod import_profile;
 pub mod importer;
 pub mod invite;
+pub mod notifications;
+pub mod pins;
+pub mod property;
+pub mod property_rights;
+pub mod retention;
 
 // Endpoints
+pub mod activity;
+pub mod attachments;
 #[cfg(feature = "html")]
 pub mod bookmark;
 pub mod files;
 pub mod path;
 pub mod prunetests;
 pub mod query;
+pub mod reorder;
 pub mod search;
+pub mod trash;
 pub mod versioning;
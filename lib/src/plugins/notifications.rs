@@ -0,0 +1,418 @@
+/*!
+Notifications tell an Agent that a Commit assigned or mentioned it.
+
+When a Commit sets one of the `notifyOn` Properties (by default `assignedTo` and `mentions`) to an Agent, [notify] creates a Notification in the Inbox of that Agent.
+Agents are only notified when they are added to the value, never for their own Commits, and only for Resources they can read.
+
+Notifications and Inboxes have a `recipient`. Only the recipient can read them, whatever the rights of their parents, see [crate::hierarchy::check_rights].
+The recipient marks a Notification as read with a Commit that sets `isRead`, and can destroy it. Nothing else can be changed, see [check_commit].
+*/
+
+use serde::Serialize;
+
+use crate::{
+    agents::ForAgent,
+    commit::CommitResponse,
+    errors::AtomicResult,
+    hierarchy::check_read,
+    plugins::deprecation::changed_properties,
+    storelike::Query,
+    urls,
+    utils::{now, random_string},
+    AtomicError, Commit, Resource, Storelike, Value,
+};
+
+/// The Properties that notify the Agents they are set to, unless configured otherwise.
+pub const DEFAULT_NOTIFY_ON: &[&str] = &[urls::ASSIGNED_TO, urls::MENTIONS];
+
+/// A Notification, with the name of the Resource it is about.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationSummary {
+    pub subject: String,
+    pub about: String,
+    /// The `name` of the Resource it is about
+    pub name: Option<String>,
+    pub property: Option<String>,
+    pub commit: String,
+    pub created_at: Option<i64>,
+}
+
+/// A page of unread Notifications, newest first.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InboxPage {
+    pub notifications: Vec<NotificationSummary>,
+    /// The amount of unread Notifications
+    pub total: usize,
+}
+
+/// Refuses Commits that create Notifications, or that change anything but `isRead` in one.
+/// Called when rights are validated, see [crate::Commit::apply_opts].
+pub fn check_commit(
+    commit: &Commit,
+    resource_old: &Resource,
+    validate_for: &str,
+) -> AtomicResult<()> {
+    if validate_for == urls::SUDO_AGENT {
+        return Ok(());
+    }
+    if changed_properties(commit).any(|prop| prop == urls::RECIPIENT) {
+        return Err(AtomicError::unauthorized(
+            "Notifications and Inboxes can only be created by the server".into(),
+        ));
+    }
+    if resource_old.get(urls::RECIPIENT).is_err() || commit.destroy == Some(true) {
+        return Ok(());
+    }
+    if let Some(prop) = changed_properties(commit).find(|prop| *prop != urls::IS_READ) {
+        return Err(AtomicError::unauthorized(format!(
+            "Only `isRead` of a Notification can be changed, not {}",
+            prop
+        )));
+    }
+    Ok(())
+}
+
+/// Creates Notifications for the Agents that the Commit added to one of the `notify_on` Properties.
+/// Returns the new Notifications.
+pub fn notify(
+    store: &impl Storelike,
+    commit_response: &CommitResponse,
+    notify_on: &[String],
+) -> AtomicResult<Vec<Resource>> {
+    let Some(resource) = &commit_response.resource_new else {
+        return Ok(Vec::new());
+    };
+    let signer = &commit_response.commit_struct.signer;
+    let mut notifications = Vec::new();
+    for prop in notify_on {
+        let before = commit_response
+            .resource_old
+            .as_ref()
+            .map(|old| agents_in(old, prop))
+            .unwrap_or_default();
+        for agent in agents_in(resource, prop) {
+            if &agent == signer || before.contains(&agent) || !is_local_agent(store, &agent) {
+                continue;
+            }
+            if check_read(store, resource, &ForAgent::AgentSubject(agent.clone())).is_err() {
+                tracing::debug!(
+                    "Not notifying {} about {}, it can't read it",
+                    agent,
+                    resource.get_subject()
+                );
+                continue;
+            }
+            let inbox = inbox_for(store, &agent)?;
+            let mut notification = Resource::new(format!("{}/{}", inbox, random_string(10)));
+            notification.set_class(urls::NOTIFICATION);
+            notification.set_propval(urls::PARENT.into(), Value::AtomicUrl(inbox), store)?;
+            notification.set_propval(
+                urls::RECIPIENT.into(),
+                Value::AtomicUrl(agent.clone()),
+                store,
+            )?;
+            notification.set_propval(
+                urls::NOTIFICATION_ABOUT.into(),
+                Value::AtomicUrl(resource.get_subject().clone()),
+                store,
+            )?;
+            notification.set_propval(
+                urls::NOTIFICATION_COMMIT.into(),
+                Value::AtomicUrl(commit_response.commit_resource.get_subject().clone()),
+                store,
+            )?;
+            notification.set_propval(
+                urls::NOTIFICATION_PROPERTY.into(),
+                Value::AtomicUrl(prop.clone()),
+                store,
+            )?;
+            notification.set_propval(urls::IS_READ.into(), Value::Boolean(false), store)?;
+            notification.set_propval(urls::CREATED_AT.into(), Value::Timestamp(now()), store)?;
+            notification.save_locally(store)?;
+            notifications.push(notification);
+        }
+    }
+    Ok(notifications)
+}
+
+/// Creates a Notification that was not caused by a Commit, such as a warning from the server.
+/// The `message` is its description.
+pub fn notify_agent(
+    store: &impl Storelike,
+    agent: &str,
+    about: &str,
+    message: &str,
+) -> AtomicResult<Resource> {
+    let inbox = inbox_for(store, agent)?;
+    let mut notification = Resource::new(format!("{}/{}", inbox, random_string(10)));
+    notification.set_class(urls::NOTIFICATION);
+    notification.set_propval(urls::PARENT.into(), Value::AtomicUrl(inbox), store)?;
+    notification.set_propval(
+        urls::RECIPIENT.into(),
+        Value::AtomicUrl(agent.into()),
+        store,
+    )?;
+    notification.set_propval(
+        urls::NOTIFICATION_ABOUT.into(),
+        Value::AtomicUrl(about.into()),
+        store,
+    )?;
+    notification.set_propval(
+        urls::DESCRIPTION.into(),
+        Value::Markdown(message.into()),
+        store,
+    )?;
+    notification.set_propval(urls::IS_READ.into(), Value::Boolean(false), store)?;
+    notification.set_propval(urls::CREATED_AT.into(), Value::Timestamp(now()), store)?;
+    notification.save_locally(store)?;
+    Ok(notification)
+}
+
+/// Returns the unread Notifications of the Agent, newest first.
+pub fn unread(
+    store: &impl Storelike,
+    agent: &str,
+    offset: usize,
+    limit: usize,
+) -> AtomicResult<InboxPage> {
+    let mut unread: Vec<Resource> = store
+        .query(&Query::new_prop_val(
+            urls::PARENT,
+            &inbox_subject(store, agent),
+        ))?
+        .resources
+        .into_iter()
+        .filter(|r| {
+            r.get(urls::RECIPIENT)
+                .map(|a| a.to_string() == agent)
+                .unwrap_or(false)
+        })
+        .filter(|r| !matches!(r.get(urls::IS_READ), Ok(Value::Boolean(true))))
+        .collect();
+    let created_at = |r: &Resource| r.get(urls::CREATED_AT).and_then(|v| v.to_int()).ok();
+    unread.sort_by_key(|r| std::cmp::Reverse(created_at(r)));
+    let for_agent = ForAgent::AgentSubject(agent.into());
+    let text = |r: &Resource, prop: &str| r.get(prop).ok().map(|v| v.to_string());
+    let notifications = unread
+        .iter()
+        .skip(offset)
+        .take(limit)
+        .map(|notification| {
+            let about = text(notification, urls::NOTIFICATION_ABOUT).unwrap_or_default();
+            let name = store
+                .get_resource(&about)
+                .ok()
+                .filter(|r| check_read(store, r, &for_agent).is_ok())
+                .and_then(|r| text(&r, urls::NAME));
+            NotificationSummary {
+                subject: notification.get_subject().clone(),
+                name,
+                property: text(notification, urls::NOTIFICATION_PROPERTY),
+                commit: text(notification, urls::NOTIFICATION_COMMIT).unwrap_or_default(),
+                created_at: created_at(notification),
+                about,
+            }
+        })
+        .collect();
+    Ok(InboxPage {
+        notifications,
+        total: unread.len(),
+    })
+}
+
+/// The subject of the Inbox of the Agent. Agent subjects can't be part of a path, so it uses a hash.
+pub fn inbox_subject(store: &impl Storelike, agent: &str) -> String {
+    let hash = ring::digest::digest(&ring::digest::SHA256, agent.as_bytes());
+    let id: String = hash.as_ref()[..16]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}/inboxes/{}", store.get_server_url(), id)
+}
+
+/// Returns the subject of the Inbox of the Agent, creating it if needed.
+fn inbox_for(store: &impl Storelike, agent: &str) -> AtomicResult<String> {
+    let subject = inbox_subject(store, agent);
+    if store.get_resource(&subject).is_ok() {
+        return Ok(subject);
+    }
+    let mut inbox = Resource::new(subject.clone());
+    inbox.set_class(urls::INBOX);
+    inbox.set_propval(
+        urls::PARENT.into(),
+        Value::AtomicUrl(store.get_server_url().into()),
+        store,
+    )?;
+    inbox.set_propval_string(urls::NAME.into(), "Inbox", store)?;
+    inbox.set_propval(
+        urls::RECIPIENT.into(),
+        Value::AtomicUrl(agent.into()),
+        store,
+    )?;
+    inbox.save_locally(store)?;
+    Ok(subject)
+}
+
+/// The subjects in an AtomicUrl or ResourceArray value.
+fn agents_in(resource: &Resource, prop: &str) -> Vec<String> {
+    let mut agents: Vec<String> = Vec::new();
+    for subject in resource
+        .get(prop)
+        .and_then(|value| value.to_subjects(None))
+        .unwrap_or_default()
+    {
+        if !agents.contains(&subject) {
+            agents.push(subject);
+        }
+    }
+    agents
+}
+
+fn is_local_agent(store: &impl Storelike, subject: &str) -> bool {
+    store
+        .get_resource(subject)
+        .and_then(|r| r.get(urls::IS_A)?.to_subjects(None))
+        .map(|classes| classes.iter().any(|c| c == urls::AGENT))
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        agents::Agent,
+        commit::{CommitBuilder, CommitOpts},
+        AtomicErrorType, Db,
+    };
+
+    fn apply(
+        store: &Db,
+        signer: &Agent,
+        subject: &str,
+        build: impl Fn(&mut CommitBuilder),
+    ) -> AtomicResult<CommitResponse> {
+        let resource = store
+            .get_resource(subject)
+            .unwrap_or_else(|_| Resource::new(subject.into()));
+        let mut commitbuilder = CommitBuilder::new(subject.into());
+        build(&mut commitbuilder);
+        let opts = CommitOpts {
+            validate_schema: true,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_rights: true,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        commitbuilder
+            .sign(signer, store, &resource)?
+            .apply_opts(store, &opts)
+    }
+
+    #[test]
+    fn assigning_notifies_only_the_assignee() {
+        let store = Db::init_temp("assigning_notifies_only_the_assignee").unwrap();
+        let notify_on: Vec<String> = DEFAULT_NOTIFY_ON.iter().map(|p| p.to_string()).collect();
+        let alice = Agent::new(Some("alice"), &store).unwrap();
+        alice.to_resource().unwrap().save_locally(&store).unwrap();
+        let bob = Agent::new(Some("bob"), &store).unwrap();
+        bob.to_resource().unwrap().save_locally(&store).unwrap();
+        // Both can read and write everything in the Drive
+        let mut drive = store.get_resource(store.get_server_url()).unwrap();
+        let everyone: Value = vec![alice.subject.clone(), bob.subject.clone()].into();
+        drive
+            .set_propval(urls::READ.into(), everyone.clone(), &store)
+            .unwrap();
+        drive
+            .set_propval(urls::WRITE.into(), everyone, &store)
+            .unwrap();
+        drive.save_locally(&store).unwrap();
+
+        let task = format!("{}/task", store.get_server_url());
+        let response = apply(&store, &alice, &task, |c| {
+            c.set(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+            );
+            c.set(urls::NAME.into(), Value::String("Do it".into()));
+            c.set(
+                urls::ASSIGNED_TO.into(),
+                Value::AtomicUrl(bob.subject.clone()),
+            );
+            c.set(urls::MENTIONS.into(), vec![alice.subject.clone()].into());
+        })
+        .unwrap();
+        // Alice wrote the Commit, so she is not notified of her own mention
+        let created = notify(&store, &response, &notify_on).unwrap();
+        assert_eq!(created.len(), 1);
+        let notification = created[0].get_subject().clone();
+
+        // Setting the same assignee again is not a new assignment
+        let response = apply(&store, &alice, &task, |c| {
+            c.set(
+                urls::ASSIGNED_TO.into(),
+                Value::AtomicUrl(bob.subject.clone()),
+            );
+        })
+        .unwrap();
+        assert!(notify(&store, &response, &notify_on).unwrap().is_empty());
+
+        let page = unread(&store, &bob.subject, 0, 10).unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.notifications[0].about, task);
+        assert_eq!(page.notifications[0].name.as_deref(), Some("Do it"));
+        assert_eq!(unread(&store, &alice.subject, 0, 10).unwrap().total, 0);
+
+        // Alice can write the whole Drive, but not read Bob's notifications
+        let resource = store.get_resource(&notification).unwrap();
+        let err = check_read(
+            &store,
+            &resource,
+            &ForAgent::AgentSubject(alice.subject.clone()),
+        )
+        .unwrap_err();
+        assert!(matches!(err.error_type, AtomicErrorType::UnauthorizedError));
+        check_read(
+            &store,
+            &resource,
+            &ForAgent::AgentSubject(bob.subject.clone()),
+        )
+        .unwrap();
+        assert!(apply(&store, &alice, &notification, |c| {
+            c.set(urls::IS_READ.into(), Value::Boolean(true));
+        })
+        .is_err());
+        // Bob can only mark it as read
+        assert!(apply(&store, &bob, &notification, |c| {
+            c.set(
+                urls::NOTIFICATION_ABOUT.into(),
+                Value::AtomicUrl(bob.subject.clone()),
+            );
+        })
+        .is_err());
+        apply(&store, &bob, &notification, |c| {
+            c.set(urls::IS_READ.into(), Value::Boolean(true));
+        })
+        .unwrap();
+        assert_eq!(unread(&store, &bob.subject, 0, 10).unwrap().total, 0);
+
+        // Notifications can't be forged
+        let forged = format!("{}/forged", store.get_server_url());
+        let err = apply(&store, &alice, &forged, |c| {
+            c.set(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+            );
+            c.set(
+                urls::RECIPIENT.into(),
+                Value::AtomicUrl(bob.subject.clone()),
+            );
+        })
+        .unwrap_err();
+        assert!(matches!(err.error_type, AtomicErrorType::UnauthorizedError));
+    }
+}
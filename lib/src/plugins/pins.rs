@@ -0,0 +1,197 @@
+/*!
+Pinned Resources, which an Agent keeps in the `pins` array of its own Agent Resource, e.g. to show them in a sidebar.
+
+Pins are added and removed with `push` and `pull` in a Commit, and reordered by `set`ting the whole array.
+Only the Agent itself can change its pins, even if others have write rights to the Agent, see [check_commit].
+Like the rest of the Agent Resource, pins can be read by anyone.
+*/
+
+use serde::Serialize;
+
+use crate::{
+    agents::ForAgent, errors::AtomicResult, hierarchy::check_read,
+    plugins::deprecation::changed_properties, urls, AtomicError, Commit, Storelike,
+};
+
+/// A pinned Resource, with just enough to render a link to it.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PinSummary {
+    pub subject: String,
+    /// The `name`, `shortname` or `filename` of the Resource
+    pub name: Option<String>,
+    /// The first class of the Resource
+    pub class: Option<String>,
+    /// The MIME type, for Files
+    pub mimetype: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Pins {
+    /// The pins that the Agent can read, in the order of the `pins` array
+    pub pins: Vec<PinSummary>,
+    /// Pins that no longer exist, or that the Agent can no longer read
+    pub unavailable: Vec<String>,
+}
+
+/// Refuses Commits that change the `pins` of a Resource, unless they are validated for that Resource itself.
+/// Called when rights are validated, see [crate::Commit::apply_opts].
+pub fn check_commit(commit: &Commit, validate_for: &str) -> AtomicResult<()> {
+    if validate_for == commit.subject || validate_for == urls::SUDO_AGENT {
+        return Ok(());
+    }
+    if changed_properties(commit).any(|prop| prop == urls::PINS) {
+        return Err(AtomicError::unauthorized(format!(
+            "Only {} can change its own pins",
+            commit.subject
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the pins of the Agent, as far as the Agent can still read them.
+pub fn pins_for(store: &impl Storelike, agent: &str) -> AtomicResult<Pins> {
+    let for_agent = ForAgent::AgentSubject(agent.into());
+    let subjects = match store.get_resource(agent)?.get(urls::PINS) {
+        Ok(pins) => pins.to_subjects(None)?,
+        Err(_) => Vec::new(),
+    };
+    let mut pins = Pins::default();
+    for subject in subjects {
+        let resource = match store.get_resource(&subject) {
+            Ok(resource) if check_read(store, &resource, &for_agent).is_ok() => resource,
+            _ => {
+                pins.unavailable.push(subject);
+                continue;
+            }
+        };
+        let text = |prop: &str| resource.get(prop).ok().map(|value| value.to_string());
+        pins.pins.push(PinSummary {
+            name: text(urls::NAME)
+                .or_else(|| text(urls::SHORTNAME))
+                .or_else(|| text(urls::FILENAME)),
+            class: resource
+                .get(urls::IS_A)
+                .and_then(|classes| classes.to_subjects(None))
+                .ok()
+                .and_then(|classes| classes.into_iter().next()),
+            mimetype: text(urls::MIMETYPE),
+            subject,
+        });
+    }
+    Ok(pins)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        agents::Agent,
+        commit::{CommitBuilder, CommitOpts},
+        AtomicErrorType, Db, Resource, Value,
+    };
+
+    fn apply(store: &Db, signer: &Agent, build: impl Fn(&mut CommitBuilder)) -> AtomicResult<()> {
+        let resource = store.get_resource(&signer.subject).unwrap();
+        apply_to(store, signer, &resource, build)
+    }
+
+    fn apply_to(
+        store: &Db,
+        signer: &Agent,
+        resource: &Resource,
+        build: impl Fn(&mut CommitBuilder),
+    ) -> AtomicResult<()> {
+        let mut commitbuilder = CommitBuilder::new(resource.get_subject().into());
+        build(&mut commitbuilder);
+        let opts = CommitOpts {
+            validate_schema: true,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_rights: true,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        commitbuilder
+            .sign(signer, store, resource)?
+            .apply_opts(store, &opts)
+            .map(|_| ())
+    }
+
+    fn new_resource(store: &Db, name: &str, read: Option<&str>) -> String {
+        let mut resource = Resource::new_generate_subject(store);
+        resource
+            .set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                store,
+            )
+            .unwrap();
+        resource
+            .set_propval_string(urls::NAME.into(), name, store)
+            .unwrap();
+        if let Some(read) = read {
+            resource
+                .set_propval(urls::READ.into(), vec![read.to_string()].into(), store)
+                .unwrap();
+        }
+        resource.save_locally(store).unwrap();
+        resource.get_subject().clone()
+    }
+
+    #[test]
+    fn only_the_agent_changes_its_pins() {
+        let store = Db::init_temp("only_the_agent_changes_its_pins").unwrap();
+        let server_agent = store.get_default_agent().unwrap();
+        // Only the Agents that are listed can read the Resources in the Drive
+        let mut drive = store.get_resource(store.get_server_url()).unwrap();
+        drive
+            .set_propval(
+                urls::READ.into(),
+                vec![server_agent.subject.clone()].into(),
+                &store,
+            )
+            .unwrap();
+        drive.save_locally(&store).unwrap();
+        let agent = Agent::new(Some("pinner"), &store).unwrap();
+        agent.to_resource().unwrap().save_locally(&store).unwrap();
+        let first = new_resource(&store, "First", Some(&agent.subject));
+        let second = new_resource(&store, "Second", Some(&agent.subject));
+        let secret = new_resource(&store, "Secret", None);
+
+        // The server Agent can write anything, but not the pins of another Agent
+        let agent_resource = store.get_resource(&agent.subject).unwrap();
+        let err = apply_to(&store, &server_agent, &agent_resource, |c| {
+            c.push_propval(urls::PINS, first.clone().into()).unwrap();
+        })
+        .unwrap_err();
+        assert!(matches!(err.error_type, AtomicErrorType::UnauthorizedError));
+
+        for pin in [&second, &secret, &first] {
+            apply(&store, &agent, |c| {
+                c.push_propval(urls::PINS, pin.clone().into()).unwrap();
+            })
+            .unwrap();
+        }
+        let pins = pins_for(&store, &agent.subject).unwrap();
+        let names: Vec<_> = pins.pins.iter().map(|p| p.name.clone().unwrap()).collect();
+        assert_eq!(names, ["Second", "First"]);
+        assert_eq!(pins.unavailable, [secret.clone()]);
+
+        // Reordering sets the whole array
+        apply(&store, &agent, |c| {
+            c.set(
+                urls::PINS.into(),
+                vec![first.clone(), second.clone()].into(),
+            );
+        })
+        .unwrap();
+        let pins = pins_for(&store, &agent.subject).unwrap();
+        let subjects: Vec<_> = pins.pins.iter().map(|p| p.subject.clone()).collect();
+        assert_eq!(subjects, [first, second]);
+        assert!(pins.unavailable.is_empty());
+    }
+}
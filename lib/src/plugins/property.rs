@@ -0,0 +1,215 @@
+/*!
+Guards against breaking changes to Properties that are already in use.
+Changing the `datatype` or `allowsOnly` of a Property is rejected when existing values would become invalid,
+and destroying a Property is rejected while other Resources still use it.
+Both can be overridden by setting `force` on the Commit.
+*/
+
+use crate::{
+    errors::AtomicResult, schema::Property, storelike::Query, urls, Commit, Resource, Storelike,
+    Value,
+};
+
+/// How many Resources are mentioned in the error when a Property can't be changed or destroyed.
+const SAMPLE_SIZE: usize = 5;
+
+/// Called before a Commit to a Property is applied, see [crate::Commit::apply_opts].
+pub fn before_apply_commit(
+    store: &impl Storelike,
+    commit: &Commit,
+    resource_old: &Resource,
+    resource_new: &Resource,
+    is_new: bool,
+) -> AtomicResult<()> {
+    if is_new || commit.force.unwrap_or(false) {
+        return Ok(());
+    }
+    let subject = resource_new.get_subject();
+
+    if commit.destroy.unwrap_or(false) {
+        let referrers = find_referrers(store, subject)?;
+        if !referrers.is_empty() {
+            return Err(format!(
+                "Property {} is still used by {} Resource(s), such as {}. Remove these references first, or set `force` on the Commit.",
+                subject,
+                referrers.len(),
+                sample(&referrers)
+            )
+            .into());
+        }
+        return Ok(());
+    }
+
+    // If the old Property was incomplete, no values have been validated against it
+    let Ok(old) = Property::from_resource(resource_old.clone()) else {
+        return Ok(());
+    };
+    let new = Property::from_resource(resource_new.clone())?;
+    if old.data_type == new.data_type && !tightens(&old.allows_only, &new.allows_only) {
+        return Ok(());
+    }
+
+    let invalid = find_invalid_values(store, &new)?;
+    if !invalid.is_empty() {
+        return Err(format!(
+            "This change to Property {} makes {} existing value(s) invalid, such as in {}. Set `force` on the Commit to apply it anyway, and use the validation report to find the invalid values.",
+            subject,
+            invalid.len(),
+            sample(&invalid)
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Whether the new `allowsOnly` list rejects values that the old one accepted.
+fn tightens(old: &Option<Vec<String>>, new: &Option<Vec<String>>) -> bool {
+    match (old, new) {
+        (_, None) => false,
+        (None, Some(_)) => true,
+        (Some(old), Some(new)) => old.iter().any(|v| !new.contains(v)),
+    }
+}
+
+/// Returns the subjects of Resources with a value for the Property that is not valid for it.
+/// Uses the property index, so only indexed Resources are checked.
+pub fn find_invalid_values(
+    store: &impl Storelike,
+    property: &Property,
+) -> AtomicResult<Vec<String>> {
+    let mut invalid = Vec::new();
+    for subject in users(store, &property.subject)? {
+        let Ok(resource) = store.get_resource(&subject) else {
+            continue;
+        };
+        if let Ok(value) = resource.get(&property.subject) {
+            if property.check_value(value).is_err() {
+                invalid.push(subject);
+            }
+        }
+    }
+    Ok(invalid)
+}
+
+/// Returns the subjects of Resources that use the Property, or refer to it (e.g. Classes that require it).
+/// Commits are not counted, as they refer to every Property they have changed.
+fn find_referrers(store: &impl Storelike, property: &str) -> AtomicResult<Vec<String>> {
+    let mut referrers = users(store, property)?;
+
+    let mut query = Query::new();
+    query.value = Some(Value::AtomicUrl(property.into()));
+    query.include_external = true;
+    query.include_nested = false;
+    for subject in store.query(&query)?.subjects {
+        if subject == property || referrers.contains(&subject) {
+            continue;
+        }
+        let is_commit = store
+            .get_resource(&subject)
+            .and_then(|r| r.get(urls::IS_A)?.to_subjects(None))
+            .map(|classes| classes.iter().any(|c| c == urls::COMMIT))
+            .unwrap_or(false);
+        if !is_commit {
+            referrers.push(subject);
+        }
+    }
+    Ok(referrers)
+}
+
+/// Subjects of Resources that have a value for the Property.
+fn users(store: &impl Storelike, property: &str) -> AtomicResult<Vec<String>> {
+    let mut query = Query::new();
+    query.property = Some(property.into());
+    query.include_external = true;
+    query.include_nested = false;
+    let mut subjects = store.query(&query)?.subjects;
+    subjects.dedup();
+    Ok(subjects)
+}
+
+fn sample(subjects: &[String]) -> String {
+    subjects
+        .iter()
+        .take(SAMPLE_SIZE)
+        .cloned()
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        commit::{CommitBuilder, CommitOpts},
+        Db,
+    };
+
+    fn apply(store: &Db, subject: &str, commitbuilder: CommitBuilder) -> AtomicResult<()> {
+        let agent = store.get_default_agent()?;
+        let resource = store.get_resource(subject)?;
+        let commit = commitbuilder.sign(&agent, store, &resource)?;
+        let opts = CommitOpts {
+            validate_schema: true,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_rights: false,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        commit.apply_opts(store, &opts)?;
+        Ok(())
+    }
+
+    #[test]
+    fn guards_properties_in_use() {
+        let store = Db::init_temp("guards_properties_in_use").unwrap();
+        let mut property = Resource::new_generate_subject(&store);
+        property.set_class(urls::PROPERTY);
+        for (prop, val) in [
+            (urls::SHORTNAME, Value::Slug("age".into())),
+            (urls::DESCRIPTION, Value::Markdown("Age in years".into())),
+            (urls::DATATYPE_PROP, Value::AtomicUrl(urls::STRING.into())),
+            (
+                urls::PARENT,
+                Value::AtomicUrl(store.get_server_url().into()),
+            ),
+        ] {
+            property.set_propval(prop.into(), val, &store).unwrap();
+        }
+        property.save_locally(&store).unwrap();
+        let property = property.get_subject().clone();
+
+        let mut person = Resource::new_generate_subject(&store);
+        person
+            .set_propval(property.clone(), Value::String("old".into()), &store)
+            .unwrap();
+        person.save_locally(&store).unwrap();
+
+        let mut change = CommitBuilder::new(property.clone());
+        change.set(
+            urls::DATATYPE_PROP.into(),
+            Value::AtomicUrl(urls::INTEGER.into()),
+        );
+        let err = apply(&store, &property, change.clone()).unwrap_err();
+        assert!(err.message.contains("1 existing value"), "{}", err);
+
+        let mut destroy = CommitBuilder::new(property.clone());
+        destroy.destroy(true);
+        destroy.purge(true);
+        let err = apply(&store, &property, destroy.clone()).unwrap_err();
+        assert!(err.message.contains(person.get_subject()), "{}", err);
+
+        change.force(true);
+        apply(&store, &property, change).unwrap();
+        let report = store.validate();
+        assert!(report
+            .schema_violations
+            .iter()
+            .any(|(atom, _)| atom.subject == *person.get_subject()));
+
+        destroy.force(true);
+        apply(&store, &property, destroy).unwrap();
+    }
+}
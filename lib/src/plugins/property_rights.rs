@@ -0,0 +1,459 @@
+/*!
+[PropertyRights](urls::PROPERTY_RIGHT) restrict who can change specific properties of a Resource, e.g. so collaborators can edit the body of a document, but not its rights, parent or shortname.
+
+A Resource or a Class lists them in `propertyRights`, usually as nested resources.
+Every rule has a `restrictedProperty` and the Agents in `write` that may change it, which can include the PublicAgent.
+The rules of a Resource and of its Classes add up: an Agent has to be listed in every rule for a property.
+Rules only limit Agents that have write rights through the hierarchy, they never grant rights.
+The rules can be removed by changing `propertyRights` or `isA`, or by destroying the Resource, so these require passing every rule.
+Only existing Resources are checked, whoever creates a Resource sets its first values.
+Sudo and the server Agent are not restricted.
+*/
+
+use serde::Serialize;
+
+use crate::{
+    errors::AtomicResult, plugins::deprecation::changed_properties, urls, values::SubResource,
+    AtomicError, Commit, Resource, Storelike, Value,
+};
+
+/// An entry of `propertyRights`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyRule {
+    pub property: String,
+    /// The Agents that may change the property
+    pub write: Vec<String>,
+    /// The Resource or Class that lists the rule
+    pub source: String,
+}
+
+impl PropertyRule {
+    fn allows(&self, agent: &str) -> bool {
+        self.write
+            .iter()
+            .any(|allowed| allowed == agent || allowed == urls::PUBLIC_AGENT)
+    }
+}
+
+/// Whether an Agent may change a restricted property, as shown by the `/rights` endpoint.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertyDecision {
+    pub property: String,
+    pub allowed: bool,
+    /// The Resources and Classes with rules for the property
+    pub restricted_in: Vec<String>,
+    pub explanation: String,
+}
+
+/// Properties that can remove rules, so changing them requires passing every rule.
+const GUARDED: &[&str] = &[urls::PROPERTY_RIGHTS, urls::IS_A];
+
+fn read_rule(
+    store: &impl Storelike,
+    entry: &SubResource,
+    source: &str,
+) -> AtomicResult<PropertyRule> {
+    let linked;
+    let propvals = match entry {
+        SubResource::Nested(propvals) => propvals,
+        SubResource::Resource(resource) => resource.get_propvals(),
+        SubResource::Subject(subject) => {
+            linked = store.get_resource(subject)?;
+            linked.get_propvals()
+        }
+    };
+    let property = propvals
+        .get(urls::RESTRICTED_PROPERTY)
+        .ok_or_else(|| format!("A PropertyRight in {} has no restrictedProperty", source))?
+        .to_string();
+    let write = match propvals.get(urls::WRITE) {
+        Some(agents) => agents.to_subjects(None)?,
+        None => Vec::new(),
+    };
+    Ok(PropertyRule {
+        property,
+        write,
+        source: source.into(),
+    })
+}
+
+fn rules_of(store: &impl Storelike, resource: &Resource) -> AtomicResult<Vec<PropertyRule>> {
+    match resource.get(urls::PROPERTY_RIGHTS) {
+        Ok(Value::ResourceArray(entries)) => entries
+            .iter()
+            .map(|entry| read_rule(store, entry, resource.get_subject()))
+            .collect(),
+        Ok(other) => Err(format!(
+            "propertyRights of {} is not an array: {}",
+            resource.get_subject(),
+            other
+        )
+        .into()),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+/// The rules of the Resource itself and of its Classes.
+/// Classes that can't be fetched are skipped.
+pub fn rules(store: &impl Storelike, resource: &Resource) -> AtomicResult<Vec<PropertyRule>> {
+    let mut rules = rules_of(store, resource)?;
+    let classes = match resource.get(urls::IS_A) {
+        Ok(classes) => classes.to_subjects(None)?,
+        Err(_) => Vec::new(),
+    };
+    for class in classes {
+        if let Ok(class) = store.get_resource(&class) {
+            rules.extend(rules_of(store, &class)?);
+        }
+    }
+    Ok(rules)
+}
+
+/// Decides whether `agent` may change `property`. Returns `None` if no rule restricts it.
+pub fn decide(rules: &[PropertyRule], property: &str, agent: &str) -> Option<PropertyDecision> {
+    if GUARDED.contains(&property) && !rules.is_empty() {
+        let denied: Vec<&PropertyRule> = rules.iter().filter(|rule| !rule.allows(agent)).collect();
+        return Some(PropertyDecision {
+            property: property.into(),
+            allowed: denied.is_empty(),
+            restricted_in: sources(rules.iter()),
+            explanation: match denied.first() {
+                None => format!("{} passes every PropertyRight, so it can change them", agent),
+                Some(rule) => format!(
+                    "Only Agents that pass every PropertyRight can change {}, and {} may not change {} in {}",
+                    property, agent, rule.property, rule.source
+                ),
+            },
+        });
+    }
+    let applying: Vec<&PropertyRule> = rules
+        .iter()
+        .filter(|rule| rule.property == property)
+        .collect();
+    if applying.is_empty() {
+        return None;
+    }
+    let denied = applying.iter().find(|rule| !rule.allows(agent));
+    Some(PropertyDecision {
+        property: property.into(),
+        allowed: denied.is_none(),
+        restricted_in: sources(applying.iter().copied()),
+        explanation: match denied {
+            None => format!("{} is listed in every rule for {}", agent, property),
+            Some(rule) => format!(
+                "{} may not change {}, which is restricted in {}",
+                agent, property, rule.source
+            ),
+        },
+    })
+}
+
+fn sources<'a>(rules: impl Iterator<Item = &'a PropertyRule>) -> Vec<String> {
+    let mut sources: Vec<String> = rules.map(|rule| rule.source.clone()).collect();
+    sources.dedup();
+    sources
+}
+
+/// The decisions for every restricted property of the Resource, and for the properties that can remove the rules.
+pub fn decisions(
+    store: &impl Storelike,
+    resource: &Resource,
+    agent: &str,
+) -> AtomicResult<Vec<PropertyDecision>> {
+    let rules = rules(store, resource)?;
+    let mut properties: Vec<&str> = rules.iter().map(|rule| rule.property.as_str()).collect();
+    properties.sort_unstable();
+    properties.dedup();
+    for guarded in GUARDED {
+        if !rules.is_empty() && !properties.contains(guarded) {
+            properties.push(*guarded);
+        }
+    }
+    Ok(properties
+        .into_iter()
+        .filter_map(|property| decide(&rules, property, agent))
+        .collect())
+}
+
+/// Refuses Commits that change a restricted property of an existing Resource, naming every property that `validate_for` may not change.
+/// Nothing of the Commit is applied then. Called when rights are validated, see [crate::Commit::apply_opts].
+pub fn check_commit(
+    store: &impl Storelike,
+    commit: &Commit,
+    resource_old: &Resource,
+    validate_for: &str,
+) -> AtomicResult<()> {
+    if validate_for == urls::SUDO_AGENT {
+        return Ok(());
+    }
+    if let Ok(server_agent) = store.get_default_agent() {
+        if server_agent.subject == validate_for {
+            return Ok(());
+        }
+    }
+    let rules = rules(store, resource_old)?;
+    if rules.is_empty() {
+        return Ok(());
+    }
+    let touched: Vec<&str> = if commit.destroy.unwrap_or(false) {
+        GUARDED.to_vec()
+    } else {
+        let mut touched: Vec<&str> = changed_properties(commit).map(String::as_str).collect();
+        touched.sort_unstable();
+        touched.dedup();
+        touched
+    };
+    let denied: Vec<PropertyDecision> = touched
+        .into_iter()
+        .filter_map(|property| decide(&rules, property, validate_for))
+        .filter(|decision| !decision.allowed)
+        .collect();
+    if denied.is_empty() {
+        return Ok(());
+    }
+    Err(AtomicError::unauthorized(format!(
+        "Not allowed to change {} of {}. {}",
+        denied
+            .iter()
+            .map(|decision| decision.property.as_str())
+            .collect::<Vec<_>>()
+            .join(", "),
+        commit.subject,
+        denied
+            .iter()
+            .map(|decision| decision.explanation.as_str())
+            .collect::<Vec<_>>()
+            .join(". ")
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        agents::Agent,
+        commit::{CommitBuilder, CommitOpts},
+        resources::PropVals,
+        AtomicErrorType, Db,
+    };
+
+    fn rule(property: &str, write: &[&str]) -> SubResource {
+        let mut propvals = PropVals::new();
+        propvals.insert(
+            urls::RESTRICTED_PROPERTY.into(),
+            Value::AtomicUrl(property.into()),
+        );
+        propvals.insert(
+            urls::WRITE.into(),
+            write
+                .iter()
+                .map(|agent| agent.to_string())
+                .collect::<Vec<_>>()
+                .into(),
+        );
+        SubResource::Nested(propvals)
+    }
+
+    fn agent(store: &Db, name: &str) -> Agent {
+        let agent = Agent::new(Some(name), store).unwrap();
+        agent.to_resource().unwrap().save_locally(store).unwrap();
+        agent
+    }
+
+    fn apply(
+        store: &Db,
+        signer: &Agent,
+        subject: &str,
+        build: impl Fn(&mut CommitBuilder),
+    ) -> AtomicResult<()> {
+        let resource = store.get_resource(subject).unwrap();
+        let mut commitbuilder = CommitBuilder::new(subject.into());
+        build(&mut commitbuilder);
+        let opts = CommitOpts {
+            validate_schema: false,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_rights: true,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        commitbuilder
+            .sign(signer, store, &resource)?
+            .apply_opts(store, &opts)
+            .map(|_| ())
+    }
+
+    /// A document that `owner` and `editor` can write, but only `owner` can change the rights and the shortname of.
+    fn document(store: &Db, owner: &Agent, editor: &Agent) -> String {
+        let mut resource = Resource::new_generate_subject(store);
+        resource
+            .set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                store,
+            )
+            .unwrap();
+        resource
+            .set_propval(
+                urls::WRITE.into(),
+                vec![owner.subject.clone(), editor.subject.clone()].into(),
+                store,
+            )
+            .unwrap();
+        resource
+            .set_propval_string(urls::DESCRIPTION.into(), "First draft", store)
+            .unwrap();
+        resource.set_propval_unsafe(
+            urls::PROPERTY_RIGHTS.into(),
+            Value::ResourceArray(vec![
+                rule(urls::WRITE, &[&owner.subject]),
+                rule(urls::SHORTNAME, &[&owner.subject]),
+            ]),
+        );
+        resource.save_locally(store).unwrap();
+        resource.get_subject().clone()
+    }
+
+    fn description(store: &Db, subject: &str) -> String {
+        store
+            .get_resource(subject)
+            .unwrap()
+            .get(urls::DESCRIPTION)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn rejects_commits_with_forbidden_properties_atomically() {
+        let store = Db::init_temp("property_rights_atomic").unwrap();
+        let owner = agent(&store, "owner");
+        let editor = agent(&store, "editor");
+        let doc = document(&store, &owner, &editor);
+
+        // Mixing an allowed and a forbidden property rejects the whole Commit
+        let err = apply(&store, &editor, &doc, |c| {
+            c.set(urls::DESCRIPTION.into(), Value::Markdown("Edited".into()));
+            c.set(urls::SHORTNAME.into(), Value::Slug("stolen".into()));
+        })
+        .unwrap_err();
+        assert!(err.message.contains(urls::SHORTNAME), "{}", err);
+        assert!(!err.message.contains(urls::DESCRIPTION), "{}", err);
+        assert_eq!(description(&store, &doc), "First draft");
+        assert!(store
+            .get_resource(&doc)
+            .unwrap()
+            .get(urls::SHORTNAME)
+            .is_err());
+
+        // Unrestricted properties follow the hierarchy
+        apply(&store, &editor, &doc, |c| {
+            c.set(urls::DESCRIPTION.into(), Value::Markdown("Edited".into()));
+        })
+        .unwrap();
+        assert_eq!(description(&store, &doc), "Edited");
+
+        // Rights can be restricted too
+        let err = apply(&store, &editor, &doc, |c| {
+            c.remove(urls::WRITE.into());
+        })
+        .unwrap_err();
+        assert!(err.message.contains(urls::WRITE), "{}", err);
+
+        apply(&store, &owner, &doc, |c| {
+            c.set(urls::SHORTNAME.into(), Value::Slug("mine".into()));
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn rules_can_only_be_removed_by_agents_that_pass_them() {
+        let store = Db::init_temp("property_rights_guarded").unwrap();
+        let owner = agent(&store, "owner");
+        let editor = agent(&store, "editor");
+        let doc = document(&store, &owner, &editor);
+
+        let err = apply(&store, &editor, &doc, |c| {
+            c.remove(urls::PROPERTY_RIGHTS.into());
+        })
+        .unwrap_err();
+        assert!(err.message.contains(urls::PROPERTY_RIGHTS), "{}", err);
+        let err = apply(&store, &editor, &doc, |c| {
+            c.destroy(true);
+        })
+        .unwrap_err();
+        assert!(
+            matches!(err.error_type, AtomicErrorType::UnauthorizedError),
+            "{}",
+            err
+        );
+        assert!(store.get_resource(&doc).is_ok());
+
+        apply(&store, &owner, &doc, |c| {
+            c.remove(urls::PROPERTY_RIGHTS.into());
+        })
+        .unwrap();
+        apply(&store, &editor, &doc, |c| {
+            c.set(urls::SHORTNAME.into(), Value::Slug("free".into()));
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn classes_restrict_their_instances() {
+        let store = Db::init_temp("property_rights_classes").unwrap();
+        let editor = agent(&store, "editor");
+        let mut class = Resource::new_generate_subject(&store);
+        class.set_class(urls::CLASS);
+        class
+            .set_propval_string(urls::SHORTNAME.into(), "article", &store)
+            .unwrap();
+        class
+            .set_propval_string(urls::DESCRIPTION.into(), "An article", &store)
+            .unwrap();
+        class.set_propval_unsafe(
+            urls::PROPERTY_RIGHTS.into(),
+            Value::ResourceArray(vec![rule(urls::PARENT, &[])]),
+        );
+        class.save_locally(&store).unwrap();
+        let mut article = Resource::new_generate_subject(&store);
+        article.set_class(class.get_subject());
+        article
+            .set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                &store,
+            )
+            .unwrap();
+        article
+            .set_propval(
+                urls::WRITE.into(),
+                vec![editor.subject.clone()].into(),
+                &store,
+            )
+            .unwrap();
+        article.save_locally(&store).unwrap();
+
+        let decisions = decisions(&store, &article, &editor.subject).unwrap();
+        let parent = decisions
+            .iter()
+            .find(|decision| decision.property == urls::PARENT)
+            .unwrap();
+        assert!(!parent.allowed);
+        assert_eq!(parent.restricted_in, vec![class.get_subject().clone()]);
+        assert!(decisions
+            .iter()
+            .any(|decision| decision.property == urls::IS_A && !decision.allowed));
+
+        let err = apply(&store, &editor, article.get_subject(), |c| {
+            c.set(
+                urls::PARENT.into(),
+                Value::AtomicUrl(editor.subject.clone()),
+            );
+        })
+        .unwrap_err();
+        assert!(err.message.contains(class.get_subject()), "{}", err);
+    }
+}
@@ -17,9 +17,10 @@ pub fn query_endpoint() -> Endpoint {
             urls::COLLECTION_INCLUDE_NESTED.to_string(),
             urls::COLLECTION_SORT_BY.to_string(),
             urls::COLLECTION_SORT_DESC.to_string(),
+            urls::COLLECTION_CURSOR.to_string(),
         ]
         .into(),
-        description: "Query the server for resources matching the query filter.".to_string(),
+        description: "Query the server for resources matching the query filter. POST a JSON body to combine a class, parent subtree, property conditions, full-text search and sorting.".to_string(),
         shortname: "query".to_string(),
         handle: Some(handle_query_request),
         handle_post: None,
@@ -0,0 +1,233 @@
+/*!
+Manually ordering the children of a Resource, for example to drag tasks in a list.
+
+Children are listed by their [urls::SORT_POSITION], see [crate::hierarchy::sort_children].
+The `/reorder` endpoint moves a child between two of its siblings, by giving it a position in between theirs.
+Only the moved Resource changes, unless its new neighbours have no position, or their positions are too close together.
+In that case all siblings get new, evenly spaced positions.
+Reorders are applied one at a time, so concurrent reorders always start from the current positions.
+*/
+
+use std::sync::Mutex;
+
+use crate::{
+    agents::ForAgent,
+    endpoints::{Endpoint, HandleGetContext, HandlePostContext},
+    errors::AtomicResult,
+    hierarchy::{check_write, sort_children},
+    storelike::Query,
+    urls, Resource, Storelike, Value,
+};
+
+/// The distance between the positions of siblings after rebalancing.
+const SPACING: f64 = 1024.0;
+/// Siblings are rebalanced when there is less room than this between their positions.
+const MIN_GAP: f64 = 1e-6;
+
+static REORDER_LOCK: Mutex<()> = Mutex::new(());
+
+pub fn reorder_endpoint() -> Endpoint {
+    Endpoint {
+        path: urls::PATH_REORDER.into(),
+        params: [urls::SUBJECT.to_string()].into(),
+        description: "Moves a Resource between two of its siblings. POST to this endpoint with the `subject` to move, and the sibling it should come `after` and / or `before`. Without `after` and `before`, it is moved to the end. Requires write rights to the Resource.".to_string(),
+        shortname: "reorder".to_string(),
+        handle: Some(handle_get),
+        handle_post: Some(handle_post),
+    }
+}
+
+fn handle_get(context: HandleGetContext) -> AtomicResult<Resource> {
+    reorder_endpoint().to_resource(context.store)
+}
+
+fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
+    let param = |name: &str| {
+        context
+            .subject
+            .query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.to_string())
+    };
+    let subject = param("subject").ok_or("No `subject` given to reorder")?;
+    reorder(
+        context.store,
+        &subject,
+        param("after").as_deref(),
+        param("before").as_deref(),
+        context.for_agent,
+    )
+}
+
+fn position(resource: &Resource) -> Option<f64> {
+    resource
+        .get(urls::SORT_POSITION)
+        .and_then(|v| v.to_float())
+        .ok()
+}
+
+/// Moves the Resource directly `after` and / or `before` one of its siblings.
+/// If both are given, they must be adjacent. If neither is given, the Resource is moved to the end.
+/// Requires write rights to the Resource.
+#[tracing::instrument(skip(store))]
+pub fn reorder(
+    store: &impl Storelike,
+    subject: &str,
+    after: Option<&str>,
+    before: Option<&str>,
+    for_agent: &ForAgent,
+) -> AtomicResult<Resource> {
+    let _lock = REORDER_LOCK.lock()?;
+    let mut resource = store.get_resource(subject)?;
+    check_write(store, &resource, for_agent)?;
+    let parent = resource.get(urls::PARENT)?.to_string();
+
+    let mut siblings: Vec<Resource> = store
+        .query(&Query::new_prop_val(urls::PARENT, &parent))?
+        .subjects
+        .iter()
+        .filter(|sibling| *sibling != subject)
+        .filter_map(|sibling| store.get_resource(sibling).ok())
+        .collect();
+    sort_children(&mut siblings);
+    let index_of = |sibling: &str| -> AtomicResult<usize> {
+        siblings
+            .iter()
+            .position(|s| s.get_subject() == sibling)
+            .ok_or_else(|| format!("{} is not a sibling of {}", sibling, subject).into())
+    };
+    let index = match (after, before) {
+        (Some(after), Some(before)) => {
+            let index = index_of(after)? + 1;
+            if index != index_of(before)? {
+                return Err(format!("{} does not directly follow {}", before, after).into());
+            }
+            index
+        }
+        (Some(after), None) => index_of(after)? + 1,
+        (None, Some(before)) => index_of(before)?,
+        (None, None) => siblings.len(),
+    };
+
+    // Siblings without a position are listed after those with one
+    let lower = index.checked_sub(1).map(|i| position(&siblings[i]));
+    let upper = siblings.get(index).map(position);
+    let new_position = match (lower, upper) {
+        (None, None | Some(None)) => Some(SPACING),
+        (None, Some(Some(upper))) => Some(upper - SPACING),
+        (Some(Some(lower)), None | Some(None)) => Some(lower + SPACING),
+        (Some(Some(lower)), Some(Some(upper))) => {
+            let middle = lower + (upper - lower) / 2.0;
+            (upper - lower > MIN_GAP && lower < middle && middle < upper).then_some(middle)
+        }
+        (Some(None), _) => None,
+    };
+
+    let new_position = match new_position {
+        Some(new_position) => new_position,
+        None => {
+            tracing::info!("Rebalancing the positions of the children of {}", parent);
+            for (i, sibling) in siblings.iter_mut().enumerate() {
+                // The siblings after the moved Resource shift one place
+                let slot = if i < index { i + 1 } else { i + 2 };
+                let sibling_position = slot as f64 * SPACING;
+                if position(sibling) != Some(sibling_position) {
+                    sibling.set_propval(
+                        urls::SORT_POSITION.into(),
+                        Value::Float(sibling_position),
+                        store,
+                    )?;
+                    sibling.save_locally(store)?;
+                }
+            }
+            (index + 1) as f64 * SPACING
+        }
+    };
+
+    resource.set_propval(
+        urls::SORT_POSITION.into(),
+        Value::Float(new_position),
+        store,
+    )?;
+    resource.save_locally(store)?;
+    Ok(resource)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Db;
+
+    fn children(store: &Db, parent: &str) -> Vec<String> {
+        store
+            .query(&Query::new_prop_val(urls::PARENT, parent))
+            .unwrap()
+            .subjects
+    }
+
+    #[test]
+    fn reorder_children() {
+        let store = Db::init_temp("reorder_children").unwrap();
+        let mut folder = Resource::new_generate_subject(&store);
+        folder
+            .set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                &store,
+            )
+            .unwrap();
+        folder.save_locally(&store).unwrap();
+        let folder = folder.get_subject().clone();
+        let [a, b, c] = ["a", "b", "c"].map(|name| {
+            let mut child = Resource::new_generate_subject(&store);
+            child
+                .set_propval(
+                    urls::PARENT.into(),
+                    Value::AtomicUrl(folder.clone()),
+                    &store,
+                )
+                .unwrap();
+            child
+                .set_propval(urls::NAME.into(), Value::String(name.into()), &store)
+                .unwrap();
+            child.save_locally(&store).unwrap();
+            child.get_subject().clone()
+        });
+        // Without positions, children are sorted by name
+        assert_eq!(
+            children(&store, &folder),
+            vec![a.clone(), b.clone(), c.clone()]
+        );
+
+        reorder(&store, &c, None, Some(&a), &ForAgent::Sudo).unwrap();
+        assert_eq!(
+            children(&store, &folder),
+            vec![c.clone(), a.clone(), b.clone()]
+        );
+        reorder(&store, &c, Some(&a), Some(&b), &ForAgent::Sudo).unwrap();
+        assert_eq!(
+            children(&store, &folder),
+            vec![a.clone(), c.clone(), b.clone()]
+        );
+        reorder(&store, &a, None, None, &ForAgent::Sudo).unwrap();
+        assert_eq!(
+            children(&store, &folder),
+            vec![c.clone(), b.clone(), a.clone()]
+        );
+        assert!(reorder(&store, &a, Some(&c), Some(&a), &ForAgent::Sudo).is_err());
+
+        // Keep moving into the shrinking gap at the start, until the siblings are rebalanced
+        for i in 0..80 {
+            let (moved, first) = if i % 2 == 0 { (&b, &c) } else { (&c, &b) };
+            reorder(&store, moved, Some(first), None, &ForAgent::Sudo).unwrap();
+            let listed = children(&store, &folder);
+            assert_eq!(&listed[0], first);
+            assert_eq!(&listed[1], moved);
+            let positions: Vec<f64> = listed
+                .iter()
+                .map(|s| position(&store.get_resource(s).unwrap()).unwrap())
+                .collect();
+            assert!(positions.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+}
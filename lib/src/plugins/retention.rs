@@ -0,0 +1,554 @@
+/*!
+[RetentionPolicies](urls::RETENTION_POLICY) remove old Commits and inactive Resources, e.g. to purge logs and Notifications after a fixed period while keeping documents forever.
+
+A policy affects the descendants of its `targetSubtree` (or of its parent, if that is not set), optionally only the instances of its `targetClass`.
+Commits and RetentionPolicies themselves are never affected.
+A policy only takes effect if the Agent that last changed it has write rights to its subtree, so nobody can remove data they could not remove themselves.
+Changes made by the server Agent don't count, because the server records every run in `lastRun`.
+
+When several policies affect a Resource, the precedence from the description of the Class applies:
+an `exempt` policy keeps everything, and otherwise the shortest `keepVersionsFor` and `deleteInactiveAfter` win.
+
+- `keepVersionsFor` removes the Commits that are older than that many days, except the last of them. That one becomes the baseline: it gets the complete Resource in [urls::BASELINE], so versions are constructed starting from there.
+- `deleteInactiveAfter` destroys Resources whose last Commit is older than that many days, using Commits signed by the server that skip the trash.
+  A Resource is only destroyed together with all of its descendants. If one of them is not due, e.g. because it is outside the scope of the policy, the Resource is kept and flagged.
+
+[plan] lists what a run at a given moment would do, and [apply] carries it out.
+The moment is a parameter, so a run can be planned for any point in time.
+*/
+
+use std::collections::{HashMap, HashSet};
+
+use serde::Serialize;
+
+use crate::{
+    agents::ForAgent,
+    commit::{CommitBuilder, CommitOpts},
+    errors::AtomicResult,
+    hierarchy::check_write,
+    plugins::versioning::{construct_version, get_commits_for_resource},
+    urls, Db, Resource, Storelike, Value,
+};
+
+const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+/// Parents beyond this depth are ignored, so a broken hierarchy can't keep a run busy.
+const MAX_DEPTH: usize = 256;
+
+/// What a run of the RetentionPolicies at [RetentionPlan::moment] does.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionPlan {
+    /// Unix timestamp in milliseconds
+    pub moment: i64,
+    /// The policies that take effect
+    pub policies: Vec<String>,
+    /// The policies that are ignored, and why
+    pub skipped_policies: Vec<SkippedPolicy>,
+    /// Histories that are shortened
+    pub compact: Vec<Compaction>,
+    /// Resources that are destroyed, descendants before their parents
+    pub delete: Vec<String>,
+    /// Resources that are due for deletion, but have descendants that are not
+    pub flagged: Vec<Flagged>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedPolicy {
+    pub subject: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Compaction {
+    pub subject: String,
+    /// The Commits that are removed
+    pub commits: Vec<String>,
+    /// The Commit that gets the complete Resource. `None` if the Resource is destroyed in the same run.
+    pub baseline: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Flagged {
+    pub subject: String,
+    /// The descendants that would be left without a parent
+    pub kept_descendants: Vec<String>,
+}
+
+/// A RetentionPolicy that takes effect.
+#[derive(Debug)]
+struct Policy {
+    /// Only strict descendants are affected
+    root: String,
+    class: Option<String>,
+    keep_versions_for: Option<i64>,
+    delete_inactive_after: Option<i64>,
+    exempt: bool,
+}
+
+impl Policy {
+    fn affects(&self, resource: &Resource, ancestors: &[String]) -> bool {
+        ancestors.contains(&self.root)
+            && self
+                .class
+                .as_ref()
+                .map(|class| is_a(resource, class))
+                .unwrap_or(true)
+    }
+}
+
+/// Reads a RetentionPolicy, or returns why it does not take effect.
+fn read_policy(store: &Db, resource: &Resource, server_agent: &str) -> Result<Policy, String> {
+    let days = |prop: &str| -> Result<Option<i64>, String> {
+        match resource.get(prop).map(|v| v.to_int()) {
+            Err(_) => Ok(None),
+            Ok(Ok(days)) if days >= 0 => Ok(Some(days)),
+            Ok(_) => Err(format!("{} must be zero or more days", prop)),
+        }
+    };
+    let policy = Policy {
+        root: resource
+            .get(urls::RETENTION_TARGET_SUBTREE)
+            .or_else(|_| resource.get(urls::PARENT))
+            .map(|v| v.to_string())
+            .map_err(|_| "it has no target subtree and no parent".to_string())?,
+        class: resource
+            .get(urls::RETENTION_TARGET_CLASS)
+            .ok()
+            .map(|v| v.to_string()),
+        keep_versions_for: days(urls::KEEP_VERSIONS_FOR)?,
+        delete_inactive_after: days(urls::DELETE_INACTIVE_AFTER)?,
+        exempt: resource
+            .get(urls::RETENTION_EXEMPT)
+            .and_then(|v| v.to_bool())
+            .unwrap_or(false),
+    };
+    let root = store
+        .get_resource(&policy.root)
+        .map_err(|e| format!("its subtree {} can't be read: {}", policy.root, e))?;
+    let commits = get_commits_for_resource(resource.get_subject(), store)
+        .map_err(|e| format!("its Commits can't be read: {}", e))?;
+    // Policies that only the server changed were created by an admin, e.g. using an import
+    if let Some(author) = commits
+        .iter()
+        .rev()
+        .map(|commit| &commit.signer)
+        .find(|signer| *signer != server_agent)
+    {
+        check_write(store, &root, &ForAgent::AgentSubject(author.clone())).map_err(|_| {
+            format!(
+                "{} last changed it, and has no write rights to {}",
+                author, policy.root
+            )
+        })?;
+    }
+    Ok(policy)
+}
+
+fn is_a(resource: &Resource, class: &str) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|v| v.to_subjects(None))
+        .map(|classes| classes.iter().any(|c| c == class))
+        .unwrap_or(false)
+}
+
+/// The parents of `subject`, nearest first.
+fn ancestors(parents: &HashMap<String, String>, subject: &str) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    let mut current = subject;
+    while let Some(parent) = parents.get(current) {
+        if found.len() >= MAX_DEPTH || parent == subject || found.contains(parent) {
+            break;
+        }
+        found.push(parent.clone());
+        current = parent;
+    }
+    found
+}
+
+/// When the last Commit of the Resource was created.
+fn last_activity(store: &Db, resource: &Resource) -> Option<i64> {
+    let commit = store
+        .get_resource(&resource.get(urls::LAST_COMMIT).ok()?.to_string())
+        .ok()?;
+    commit.get(urls::CREATED_AT).ok()?.to_int().ok()
+}
+
+/// Lists what the RetentionPolicies remove when they run at `moment` (a unix timestamp in milliseconds), without changing anything.
+pub fn plan(store: &Db, moment: i64) -> AtomicResult<RetentionPlan> {
+    let mut plan = RetentionPlan {
+        moment,
+        ..Default::default()
+    };
+    let resources: Vec<Resource> = store.all_resources(false).collect();
+    let server_agent = store.get_default_agent()?.subject;
+    let mut policies: Vec<Policy> = Vec::new();
+    for resource in resources.iter().filter(|r| is_a(r, urls::RETENTION_POLICY)) {
+        match read_policy(store, resource, &server_agent) {
+            Ok(policy) => {
+                plan.policies.push(resource.get_subject().clone());
+                policies.push(policy);
+            }
+            Err(reason) => plan.skipped_policies.push(SkippedPolicy {
+                subject: resource.get_subject().clone(),
+                reason,
+            }),
+        }
+    }
+    if policies.is_empty() {
+        return Ok(plan);
+    }
+
+    let mut parents: HashMap<String, String> = HashMap::new();
+    let mut children: HashMap<String, Vec<String>> = HashMap::new();
+    for resource in &resources {
+        if let Ok(parent) = resource.get(urls::PARENT) {
+            parents.insert(resource.get_subject().clone(), parent.to_string());
+            children
+                .entry(parent.to_string())
+                .or_default()
+                .push(resource.get_subject().clone());
+        }
+    }
+
+    // The most restrictive history and deletion limits per affected Resource
+    let mut keep_versions: Vec<(String, i64)> = Vec::new();
+    let mut due: HashSet<String> = HashSet::new();
+    for resource in &resources {
+        if is_a(resource, urls::COMMIT) || is_a(resource, urls::RETENTION_POLICY) {
+            continue;
+        }
+        let ancestors = ancestors(&parents, resource.get_subject());
+        let affecting: Vec<&Policy> = policies
+            .iter()
+            .filter(|policy| policy.affects(resource, &ancestors))
+            .collect();
+        if affecting.is_empty() || affecting.iter().any(|policy| policy.exempt) {
+            continue;
+        }
+        if let Some(days) = affecting.iter().filter_map(|p| p.keep_versions_for).min() {
+            keep_versions.push((resource.get_subject().clone(), days));
+        }
+        if let Some(days) = affecting
+            .iter()
+            .filter_map(|p| p.delete_inactive_after)
+            .min()
+        {
+            let inactive = last_activity(store, resource)
+                .map(|last| last < moment - days * DAY_MS)
+                .unwrap_or(false);
+            if inactive {
+                due.insert(resource.get_subject().clone());
+            }
+        }
+    }
+
+    let mut delete: Vec<(usize, String)> = Vec::new();
+    for subject in &due {
+        let mut descendants: Vec<String> = children.get(subject).cloned().unwrap_or_default();
+        let mut seen: HashSet<String> = descendants.iter().cloned().collect();
+        let mut i = 0;
+        while i < descendants.len() {
+            for child in children.get(&descendants[i]).into_iter().flatten() {
+                if seen.insert(child.clone()) {
+                    descendants.push(child.clone());
+                }
+            }
+            i += 1;
+        }
+        let mut kept_descendants: Vec<String> = descendants
+            .into_iter()
+            .filter(|descendant| !due.contains(descendant))
+            .collect();
+        if kept_descendants.is_empty() {
+            delete.push((ancestors(&parents, subject).len(), subject.clone()));
+        } else {
+            kept_descendants.sort();
+            plan.flagged.push(Flagged {
+                subject: subject.clone(),
+                kept_descendants,
+            });
+        }
+    }
+    // Deepest first, so no Resource loses its parent before it is destroyed itself
+    delete.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    plan.delete = delete
+        .into_iter()
+        .map(|(_depth, subject)| subject)
+        .collect();
+    plan.flagged.sort_by(|a, b| a.subject.cmp(&b.subject));
+
+    keep_versions.sort();
+    for (subject, days) in keep_versions {
+        let cutoff = moment - days * DAY_MS;
+        let old: Vec<String> = get_commits_for_resource(&subject, store)?
+            .into_iter()
+            .filter(|commit| commit.created_at < cutoff)
+            .filter_map(|commit| commit.url)
+            .collect();
+        let compaction = if plan.delete.contains(&subject) {
+            if old.is_empty() {
+                continue;
+            }
+            Compaction {
+                subject,
+                commits: old,
+                baseline: None,
+            }
+        } else {
+            match old.split_last() {
+                Some((baseline, older)) if !older.is_empty() => Compaction {
+                    subject,
+                    commits: older.to_vec(),
+                    baseline: Some(baseline.clone()),
+                },
+                _ => continue,
+            }
+        };
+        plan.compact.push(compaction);
+    }
+    Ok(plan)
+}
+
+/// Carries out a [RetentionPlan]: shortens the histories, destroys the Resources and sets `lastRun` of the policies, using Commits signed by the server.
+pub fn apply(store: &Db, plan: &RetentionPlan) -> AtomicResult<()> {
+    let agent = store.get_default_agent()?;
+    let opts = CommitOpts {
+        validate_schema: false,
+        validate_signature: false,
+        validate_timestamp: false,
+        validate_rights: false,
+        validate_previous_commit: false,
+        validate_for_agent: None,
+        update_index: true,
+        validate_relative_urls: false,
+    };
+    for compaction in &plan.compact {
+        // The baseline is constructed from the Commits that are removed next
+        if let Some(baseline) = &compaction.baseline {
+            let version = construct_version(baseline, store, &ForAgent::Sudo)?;
+            let mut commit_resource = store.get_resource(baseline)?;
+            commit_resource
+                .set_propval_unsafe(urls::BASELINE.into(), version.get_propvals().clone().into());
+            store.add_resource_opts(&commit_resource, false, false, true)?;
+        }
+        for commit in &compaction.commits {
+            if let Err(e) = store.remove_resource(commit) {
+                tracing::warn!("Could not remove Commit {}: {}", commit, e);
+            }
+        }
+    }
+    for subject in &plan.delete {
+        // It may have been removed since the plan was made
+        let Ok(resource) = store.get_resource(subject) else {
+            continue;
+        };
+        let mut commitbuilder = CommitBuilder::new(subject.clone());
+        commitbuilder.destroy(true);
+        commitbuilder.purge(true);
+        commitbuilder
+            .sign(&agent, store, &resource)?
+            .apply_opts(store, &opts)?;
+    }
+    for subject in &plan.policies {
+        let Ok(resource) = store.get_resource(subject) else {
+            continue;
+        };
+        let mut commitbuilder = CommitBuilder::new(subject.clone());
+        commitbuilder.set(
+            urls::RETENTION_LAST_RUN.into(),
+            Value::Timestamp(plan.moment),
+        );
+        commitbuilder
+            .sign(&agent, store, &resource)?
+            .apply_opts(store, &opts)?;
+    }
+    tracing::info!(
+        "Applied retention policies: shortened {} histories, destroyed {} resources, flagged {}",
+        plan.compact.len(),
+        plan.delete.len(),
+        plan.flagged.len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::now;
+
+    const DOCUMENT: &str = "https://atomicdata.dev/classes/Document";
+
+    fn create_policy(store: &Db, parent: &str, propvals: &[(&str, Value)]) -> Resource {
+        let mut propvals = propvals.to_vec();
+        propvals.push((urls::IS_A, Value::from(vec![urls::RETENTION_POLICY])));
+        store.create_test_resource(parent, propvals)
+    }
+
+    #[test]
+    fn deletes_inactive_resources_unless_exempt_or_flagged() {
+        let store = Db::init_temp("retention_deletes").unwrap();
+        let drive = store.get_server_url().to_string();
+        let logs = store.create_test_resource(&drive, vec![]);
+        let logs_subject = logs.get_subject().clone();
+        let policy = create_policy(
+            &store,
+            &logs_subject,
+            &[(urls::DELETE_INACTIVE_AFTER, Value::Integer(30))],
+        );
+        // Documents are kept forever, wherever they are
+        create_policy(
+            &store,
+            &drive,
+            &[
+                (
+                    urls::RETENTION_TARGET_CLASS,
+                    Value::AtomicUrl(DOCUMENT.into()),
+                ),
+                (urls::RETENTION_EXEMPT, Value::Boolean(true)),
+            ],
+        );
+        let old_log = store.create_test_resource(&logs_subject, vec![]);
+        let log_with_doc = store.create_test_resource(&logs_subject, vec![]);
+        let doc = store.create_test_resource(
+            log_with_doc.get_subject(),
+            vec![(urls::IS_A, Value::from(vec![DOCUMENT]))],
+        );
+        let log_with_children = store.create_test_resource(&logs_subject, vec![]);
+        let nested_log = store.create_test_resource(log_with_children.get_subject(), vec![]);
+
+        // Nothing is inactive yet
+        let early = plan(&store, now() + 10 * DAY_MS).unwrap();
+        assert_eq!(early.policies.len(), 2);
+        assert!(early.delete.is_empty());
+
+        let later = plan(&store, now() + 31 * DAY_MS).unwrap();
+        assert_eq!(later.delete.len(), 3);
+        // Children before their parents
+        let position = |subject: &str| later.delete.iter().position(|s| s == subject).unwrap();
+        assert!(position(nested_log.get_subject()) < position(log_with_children.get_subject()));
+        assert!(later.delete.contains(old_log.get_subject()));
+        assert_eq!(
+            later.flagged,
+            vec![Flagged {
+                subject: log_with_doc.get_subject().clone(),
+                kept_descendants: vec![doc.get_subject().clone()],
+            }]
+        );
+
+        apply(&store, &later).unwrap();
+        assert!(store.get_resource(old_log.get_subject()).is_err());
+        assert!(store.get_resource(nested_log.get_subject()).is_err());
+        store.get_resource(log_with_doc.get_subject()).unwrap();
+        store.get_resource(doc.get_subject()).unwrap();
+        // Not moved to the trash
+        assert!(crate::plugins::trash::expired_trash(&store, i64::MAX).is_empty());
+        let policy = store.get_resource(policy.get_subject()).unwrap();
+        assert_eq!(
+            policy
+                .get(urls::RETENTION_LAST_RUN)
+                .unwrap()
+                .to_int()
+                .unwrap(),
+            later.moment
+        );
+        // Recording the run does not change who is responsible for the policy
+        assert_eq!(plan(&store, later.moment).unwrap().policies.len(), 2);
+    }
+
+    #[test]
+    fn compacts_old_versions() {
+        let store = Db::init_temp("retention_compacts").unwrap();
+        let drive = store.get_server_url().to_string();
+        let logs = store.create_test_resource(&drive, vec![]);
+        create_policy(
+            &store,
+            logs.get_subject(),
+            &[(urls::KEEP_VERSIONS_FOR, Value::Integer(7))],
+        );
+        let mut log = store.create_test_resource(logs.get_subject(), vec![]);
+        for name in ["first", "second"] {
+            // Commits are sorted by their timestamp
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            log.set_propval(urls::NAME.into(), Value::String(name.into()), &store)
+                .unwrap();
+            log.save_locally(&store).unwrap();
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let cutoff = now();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        log.set_propval(urls::NAME.into(), Value::String("third".into()), &store)
+            .unwrap();
+        log.save_locally(&store).unwrap();
+        let commits = get_commits_for_resource(log.get_subject(), &store).unwrap();
+        assert_eq!(commits.len(), 4);
+        let urls: Vec<String> = commits.iter().map(|c| c.url.clone().unwrap()).collect();
+
+        let planned = plan(&store, cutoff + 7 * DAY_MS).unwrap();
+        assert_eq!(
+            planned.compact,
+            vec![Compaction {
+                subject: log.get_subject().clone(),
+                commits: urls[..2].to_vec(),
+                baseline: Some(urls[2].clone()),
+            }]
+        );
+        assert!(planned.delete.is_empty());
+
+        apply(&store, &planned).unwrap();
+        let remaining = get_commits_for_resource(log.get_subject(), &store).unwrap();
+        assert_eq!(remaining.len(), 2);
+        let baseline = construct_version(&urls[2], &store, &ForAgent::Sudo).unwrap();
+        assert_eq!(baseline.get(urls::NAME).unwrap().to_string(), "second");
+        assert_eq!(
+            baseline.get(urls::PARENT).unwrap().to_string(),
+            logs.get_subject().clone()
+        );
+        let latest = construct_version(&urls[3], &store, &ForAgent::Sudo).unwrap();
+        assert_eq!(latest.get(urls::NAME).unwrap().to_string(), "third");
+
+        // The baseline is the oldest Commit now, so there is nothing left to compact
+        assert!(plan(&store, cutoff + 7 * DAY_MS)
+            .unwrap()
+            .compact
+            .is_empty());
+    }
+
+    #[test]
+    fn ignores_policies_of_agents_without_write_rights() {
+        let store = Db::init_temp("retention_rights").unwrap();
+        let drive = store.get_server_url().to_string();
+        let outsider = store.create_agent(Some("outsider")).unwrap();
+        let subject = format!("{}/greedy-policy", drive);
+        let mut commitbuilder = CommitBuilder::new(subject.clone());
+        commitbuilder.set(
+            urls::IS_A.into(),
+            Value::ResourceArray(vec![urls::RETENTION_POLICY.into()]),
+        );
+        commitbuilder.set(urls::PARENT.into(), Value::AtomicUrl(drive.clone()));
+        commitbuilder.set(urls::DELETE_INACTIVE_AFTER.into(), Value::Integer(0));
+        let opts = CommitOpts {
+            validate_schema: false,
+            validate_signature: false,
+            validate_timestamp: false,
+            validate_rights: false,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        commitbuilder
+            .sign(&outsider, &store, &Resource::new(subject.clone()))
+            .unwrap()
+            .apply_opts(&store, &opts)
+            .unwrap();
+
+        let planned = plan(&store, now() + DAY_MS).unwrap();
+        assert!(planned.policies.is_empty());
+        assert_eq!(planned.skipped_policies[0].subject, subject);
+        assert!(planned.delete.is_empty());
+    }
+}
@@ -0,0 +1,283 @@
+/*!
+Destroy Commits move Resources to the trash of their Drive, so they can be restored later.
+Resources are only removed permanently when the Commit sets `purge`, or when they are destroyed while already in the trash.
+
+A trashed Resource keeps all of its properties, but only its `parent` (the trash) is kept in the value index.
+This hides it from Collections and child listings, while it can still be fetched by its subject.
+*/
+
+use crate::{
+    agents::ForAgent,
+    endpoints::{Endpoint, HandleGetContext, HandlePostContext},
+    errors::AtomicResult,
+    hierarchy::{check_append, check_write},
+    urls,
+    utils::now,
+    Resource, Storelike, Value,
+};
+
+pub fn restore_endpoint() -> Endpoint {
+    Endpoint {
+        path: urls::PATH_RESTORE.into(),
+        params: [urls::SUBJECT.to_string()].into(),
+        description: "Moves a trashed Resource back to the parent it was deleted from. POST to this endpoint with the `subject` of the trashed Resource as a query parameter. Requires write rights to the Resource and append rights to the original parent.".to_string(),
+        shortname: "restore".to_string(),
+        handle: Some(handle_get),
+        handle_post: Some(handle_post),
+    }
+}
+
+fn handle_get(context: HandleGetContext) -> AtomicResult<Resource> {
+    restore_endpoint().to_resource(context.store)
+}
+
+fn handle_post(context: HandlePostContext) -> AtomicResult<Resource> {
+    let subject = context
+        .subject
+        .query_pairs()
+        .find(|(k, _)| k == "subject" || k == urls::SUBJECT)
+        .map(|(_, v)| v.to_string())
+        .ok_or("No `subject` given to restore")?;
+    restore(context.store, &subject, context.for_agent)
+}
+
+/// Whether the Resource has been moved to the trash.
+pub fn is_trashed(resource: &Resource) -> bool {
+    resource.get(urls::DELETED_AT).is_ok()
+}
+
+fn is_a(resource: &Resource, class: &str) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .map(|classes| classes.iter().any(|c| c == class))
+        .unwrap_or(false)
+}
+
+/// Returns the Drive whose trash the Resource is moved to when it is destroyed.
+/// Returns `None` if the Resource should be destroyed permanently instead:
+/// when it is already trashed, when it is a Drive or a trash itself, or when it is not part of a Drive.
+pub fn trash_drive(store: &impl Storelike, resource: &Resource) -> AtomicResult<Option<String>> {
+    if is_trashed(resource) || is_a(resource, urls::DRIVE) || is_a(resource, urls::TRASH) {
+        return Ok(None);
+    }
+    for ancestor in resource.get_parent_tree(store)? {
+        if is_a(&ancestor, urls::TRASH) {
+            return Ok(None);
+        }
+        if is_a(&ancestor, urls::DRIVE) {
+            return Ok(Some(ancestor.get_subject().clone()));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the subject of the trash in the Drive of the Resource, creating it if needed.
+/// Returns `None` if the Resource should be destroyed permanently, see [trash_drive].
+pub fn find_trash(store: &impl Storelike, resource: &Resource) -> AtomicResult<Option<String>> {
+    match trash_drive(store, resource)? {
+        Some(drive) => get_or_create_trash(store, &drive).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn get_or_create_trash(store: &impl Storelike, drive: &str) -> AtomicResult<String> {
+    let subject = format!("{}/trash", drive.trim_end_matches('/'));
+    if store.get_resource(&subject).is_err() {
+        let mut trash = Resource::new(subject.clone());
+        trash.set_class(urls::TRASH);
+        trash.set_propval(urls::PARENT.into(), Value::AtomicUrl(drive.into()), store)?;
+        trash.set_propval(urls::NAME.into(), Value::String("Trash".into()), store)?;
+        trash.save_locally(store)?;
+    }
+    Ok(subject)
+}
+
+/// Moves the Resource to the trash, and records where it came from.
+/// Also removes it from the `attachments` of its parent, as these are not derived from the hierarchy.
+/// Called while applying a destroy Commit, see [crate::Commit::apply_opts].
+pub fn move_to_trash(
+    store: &impl Storelike,
+    resource_old: &Resource,
+    trash: &str,
+    commit_subject: &str,
+    update_index: bool,
+) -> AtomicResult<Resource> {
+    let subject = resource_old.get_subject().clone();
+    let original_parent = resource_old.get(urls::PARENT)?.to_string();
+
+    let mut resource_new = resource_old.clone();
+    resource_new.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(trash.into()));
+    resource_new.set_propval_unsafe(
+        urls::TRASHED_FROM.into(),
+        Value::AtomicUrl(original_parent.clone()),
+    );
+    resource_new.set_propval_unsafe(urls::DELETED_AT.into(), Value::Timestamp(now()));
+    resource_new.set_propval_unsafe(
+        urls::LAST_COMMIT.into(),
+        Value::AtomicUrl(commit_subject.into()),
+    );
+
+    if update_index {
+        for atom in resource_old.to_index_atoms() {
+            store.remove_atom_from_index(&atom, resource_old)?;
+        }
+        for atom in resource_new.to_index_atoms() {
+            store.add_atom_to_index(&atom, &resource_new)?;
+        }
+    }
+    store.add_resource_opts(&resource_new, false, false, true)?;
+
+    if let Ok(mut parent) = store.get_resource(&original_parent) {
+        let is_attachment = parent
+            .get(urls::ATTACHMENTS)
+            .and_then(|a| a.to_subjects(None))
+            .map(|a| a.contains(&subject))
+            .unwrap_or(false);
+        if is_attachment {
+            parent.pull_propval(urls::ATTACHMENTS, subject.into())?;
+            parent.save_locally(store)?;
+        }
+    }
+
+    Ok(resource_new)
+}
+
+/// Moves a trashed Resource back to the parent it was deleted from.
+/// Requires write rights to the Resource, and append rights to the original parent.
+#[tracing::instrument(skip(store))]
+pub fn restore(
+    store: &impl Storelike,
+    subject: &str,
+    for_agent: &ForAgent,
+) -> AtomicResult<Resource> {
+    let mut resource = store.get_resource(subject)?;
+    if !is_trashed(&resource) {
+        return Err(format!("{} is not in the trash", subject).into());
+    }
+    check_write(store, &resource, for_agent)?;
+    let original_parent = resource.get(urls::TRASHED_FROM)?.to_string();
+
+    resource.set_propval(
+        urls::PARENT.into(),
+        Value::AtomicUrl(original_parent.clone()),
+        store,
+    )?;
+    resource.remove_propval(urls::DELETED_AT);
+    resource.remove_propval(urls::TRASHED_FROM);
+    check_append(store, &resource, for_agent)?;
+    resource.save_locally(store)?;
+
+    // Only the parent was indexed while the Resource was in the trash
+    for atom in resource.to_index_atoms() {
+        store.add_atom_to_index(&atom, &resource)?;
+    }
+
+    if is_a(&resource, urls::FILE) {
+        let mut parent = store.get_resource(&original_parent)?;
+        parent.push_propval(urls::ATTACHMENTS, subject.into(), true)?;
+        parent.save_locally(store)?;
+    }
+
+    Ok(resource)
+}
+
+/// Returns the subjects of trashed Resources that were deleted before `deleted_before` (a unix timestamp in milliseconds).
+pub fn expired_trash(store: &impl Storelike, deleted_before: i64) -> Vec<String> {
+    store
+        .all_resources(false)
+        .filter(|r| {
+            r.get(urls::DELETED_AT)
+                .and_then(|v| v.to_int())
+                .map(|deleted_at| deleted_at < deleted_before)
+                .unwrap_or(false)
+        })
+        .map(|r| r.get_subject().clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{commit::CommitOpts, Db};
+
+    fn destroy(store: &Db, subject: &str, purge: bool) {
+        let agent = store.get_default_agent().unwrap();
+        let resource = store.get_resource(subject).unwrap();
+        let mut commitbuilder = crate::commit::CommitBuilder::new(subject.into());
+        commitbuilder.destroy(true);
+        commitbuilder.purge(purge);
+        let commit = commitbuilder.sign(&agent, store, &resource).unwrap();
+        let opts = CommitOpts {
+            validate_schema: true,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_rights: true,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        commit.apply_opts(store, &opts).unwrap();
+    }
+
+    fn children(store: &Db, parent: &str) -> Vec<String> {
+        store
+            .query(&crate::storelike::Query::new_prop_val(urls::PARENT, parent))
+            .unwrap()
+            .subjects
+    }
+
+    #[test]
+    fn trash_and_restore() {
+        let store = Db::init_temp("trash_and_restore").unwrap();
+        let drive = store.get_server_url().to_string();
+        let mut resource = Resource::new_generate_subject(&store);
+        resource
+            .set_propval(urls::PARENT.into(), Value::AtomicUrl(drive.clone()), &store)
+            .unwrap();
+        resource
+            .set_propval(urls::NAME.into(), Value::String("doc".into()), &store)
+            .unwrap();
+        resource.save_locally(&store).unwrap();
+        let subject = resource.get_subject().clone();
+        assert!(children(&store, &drive).contains(&subject));
+
+        destroy(&store, &subject, false);
+        let trashed = store.get_resource(&subject).unwrap();
+        assert!(is_trashed(&trashed));
+        assert_eq!(trashed.get(urls::TRASHED_FROM).unwrap().to_string(), drive);
+        let trash = trashed.get(urls::PARENT).unwrap().to_string();
+        assert!(!children(&store, &drive).contains(&subject));
+        assert!(children(&store, &trash).contains(&subject));
+        assert!(expired_trash(&store, now() + 1).contains(&subject));
+
+        restore(&store, &subject, &ForAgent::Sudo).unwrap();
+        let restored = store.get_resource(&subject).unwrap();
+        assert!(!is_trashed(&restored));
+        assert!(children(&store, &drive).contains(&subject));
+        assert!(!children(&store, &trash).contains(&subject));
+
+        // Destroying twice removes it permanently
+        destroy(&store, &subject, false);
+        destroy(&store, &subject, false);
+        store.get_resource(&subject).unwrap_err();
+    }
+
+    #[test]
+    fn purge_skips_trash() {
+        let store = Db::init_temp("purge_skips_trash").unwrap();
+        let mut resource = Resource::new_generate_subject(&store);
+        resource
+            .set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                &store,
+            )
+            .unwrap();
+        resource.save_locally(&store).unwrap();
+        let subject = resource.get_subject().clone();
+        destroy(&store, &subject, true);
+        store.get_resource(&subject).unwrap_err();
+    }
+}
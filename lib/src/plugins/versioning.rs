@@ -5,6 +5,7 @@ use crate::{
     collections::CollectionBuilder,
     endpoints::{Endpoint, HandleGetContext},
     errors::AtomicResult,
+    resources::PropVals,
     storelike::Query,
     urls, AtomicError, Commit, Resource, Storelike,
 };
@@ -78,6 +79,8 @@ fn handle_all_versions_request(context: HandleGetContext) -> AtomicResult<Resour
         name: Some(format!("Versions of {}", target)),
         include_nested: false,
         include_external: false,
+        schema_version_below: None,
+        cursor: None,
     };
     let mut collection = collection_builder.into_collection(store, for_agent)?;
     let new_members = collection
@@ -91,14 +94,37 @@ fn handle_all_versions_request(context: HandleGetContext) -> AtomicResult<Resour
 
 /// Searches the local store for all commits with this subject, returns sorted from old to new.
 #[tracing::instrument(skip(store))]
-fn get_commits_for_resource(subject: &str, store: &impl Storelike) -> AtomicResult<Vec<Commit>> {
+pub(crate) fn get_commits_for_resource(
+    subject: &str,
+    store: &impl Storelike,
+) -> AtomicResult<Vec<Commit>> {
     let mut q = Query::new_prop_val(urls::SUBJECT, subject);
     q.sort_by = Some(urls::CREATED_AT.into());
     let result = store.query(&q)?;
     let filtered: Vec<Commit> = result
         .resources
         .iter()
-        .filter_map(|r| crate::Commit::from_resource(r.clone()).ok())
+        .filter_map(|r| {
+            let mut commit = crate::Commit::from_resource(r.clone()).ok()?;
+            // Materialized values don't depend on the previous versions, unlike the patches they came from
+            if let Ok(materialized) = r.get(urls::MATERIALIZED).and_then(|v| v.to_nested()) {
+                commit.patch = None;
+                commit
+                    .set
+                    .get_or_insert_with(Default::default)
+                    .extend(materialized.clone());
+            }
+            // The Commits before a baseline have been removed, so it contains the complete Resource, see [crate::plugins::retention]
+            if let Ok(baseline) = r.get(urls::BASELINE).and_then(|v| v.to_nested()) {
+                commit.set = Some(baseline.clone());
+                commit.remove = None;
+                commit.push = None;
+                commit.pull = None;
+                commit.patch = None;
+                commit.increment = None;
+            }
+            Some(commit)
+        })
         .collect();
 
     Ok(filtered)
@@ -146,6 +172,32 @@ pub fn construct_version(
     Ok(version)
 }
 
+/// Stores the complete values that the patches in the Commits of a Resource resulted in, see [crate::patch].
+/// Versions are then constructed using these values instead of by applying the patches in sequence.
+/// Returns the amount of updated Commits.
+pub fn materialize_patches(subject: &str, store: &impl Storelike) -> AtomicResult<usize> {
+    let mut version = Resource::new(subject.into());
+    let mut updated = 0;
+    for commit in get_commits_for_resource(subject, store)? {
+        version = commit.apply_changes(version, store, false)?;
+        let (Some(patch), Some(commit_url)) = (&commit.patch, &commit.url) else {
+            continue;
+        };
+        if patch.is_empty() {
+            continue;
+        }
+        let mut materialized = PropVals::new();
+        for prop in patch.keys() {
+            materialized.insert(prop.clone(), version.get(prop)?.clone());
+        }
+        let mut commit_resource = store.get_resource(commit_url)?;
+        commit_resource.set_propval_unsafe(urls::MATERIALIZED.into(), materialized.into());
+        store.add_resource_opts(&commit_resource, false, false, true)?;
+        updated += 1;
+    }
+    Ok(updated)
+}
+
 /// Creates the versioning URL for some specific Commit
 fn construct_version_endpoint_url(store: &impl Storelike, commit_url: &str) -> String {
     format!(
@@ -177,7 +229,10 @@ pub fn get_version(
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{Resource, Store};
+    use crate::{
+        commit::{CommitBuilder, CommitOpts},
+        Resource, Store,
+    };
 
     #[test]
     fn constructs_versions() {
@@ -223,4 +278,93 @@ mod test {
             second_val
         );
     }
+
+    #[test]
+    fn constructs_patched_versions() {
+        let store = Store::init().unwrap();
+        store.populate().unwrap();
+        let agent = store.create_agent(None).unwrap();
+        store.set_default_agent(agent.clone());
+        let subject = "http://localhost/patched";
+        let opts = CommitOpts {
+            validate_schema: true,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_rights: false,
+            validate_previous_commit: true,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        let patch = |resource: &Resource, old: &str, new: &str| {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            let mut builder = CommitBuilder::new(subject.into());
+            builder.patch_string(crate::urls::DESCRIPTION, old, new);
+            let response = builder
+                .sign(&agent, &store, resource)
+                .unwrap()
+                .apply_opts(&store, &opts)
+                .unwrap();
+            (
+                store.get_resource(subject).unwrap(),
+                response.commit_resource,
+            )
+        };
+        let description =
+            |resource: &Resource| resource.get(crate::urls::DESCRIPTION).unwrap().to_string();
+
+        let first_val = "# Notes\n\nA long document.";
+        let mut resource = Resource::new(subject.to_string());
+        resource
+            .set_propval_string(crate::urls::DESCRIPTION.into(), first_val, &store)
+            .unwrap();
+        resource.save_locally(&store).unwrap();
+
+        let second_val = "# Notes\n\nA long, long document.";
+        let (resource, second_commit) = patch(&resource, first_val, second_val);
+        assert_eq!(description(&resource), second_val);
+        let patch_val = second_commit
+            .get(crate::urls::PATCH)
+            .unwrap()
+            .to_nested()
+            .unwrap()
+            .get(crate::urls::DESCRIPTION)
+            .unwrap()
+            .to_string();
+        assert!(patch_val.ends_with("+6:, long"));
+
+        // Clients without patches send the full value, which can be patched again
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let mut resource = resource;
+        let third_val = "# Notes\n\nA long, long document. The end.";
+        resource
+            .set_propval_string(crate::urls::DESCRIPTION.into(), third_val, &store)
+            .unwrap();
+        resource.save_locally(&store).unwrap();
+        let fourth_val = "# Notes\n\nA short document. The end.";
+        let (resource, fourth_commit) = patch(&resource, third_val, fourth_val);
+        assert_eq!(description(&resource), fourth_val);
+
+        // A patch that was made for an outdated value is refused
+        let mut builder = CommitBuilder::new(subject.into());
+        builder.patch_string(crate::urls::DESCRIPTION, third_val, "Something else");
+        builder
+            .sign(&agent, &store, &resource)
+            .unwrap()
+            .apply_opts(&store, &opts)
+            .unwrap_err();
+
+        let version = |commit: &Resource| {
+            description(&construct_version(commit.get_subject(), &store, &ForAgent::Sudo).unwrap())
+        };
+        assert_eq!(version(&second_commit), second_val);
+        assert_eq!(version(&fourth_commit), fourth_val);
+
+        assert_eq!(materialize_patches(subject, &store).unwrap(), 2);
+        let materialized = store.get_resource(fourth_commit.get_subject()).unwrap();
+        materialized.get(crate::urls::MATERIALIZED).unwrap();
+        assert_eq!(version(&second_commit), second_val);
+        assert_eq!(version(&fourth_commit), fourth_val);
+        assert_eq!(materialize_patches(subject, &store).unwrap(), 0);
+    }
 }
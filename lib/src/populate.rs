@@ -28,6 +28,9 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "A short name of something. It can only contain letters, numbers and dashes `-`. Use dashes to denote spaces between words. Not case sensitive - lowercase only. Useful in programming contexts where the user should be able to type something short to identify a specific thing.".into(),
             subject: urls::SHORTNAME.into(),
             allows_only: None,
+            keep_whitespace: false,
+            minimum: None,
+            maximum: None,
         },
         Property {
             class_type: None,
@@ -36,6 +39,9 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.".into(),
             subject: urls::DESCRIPTION.into(),
             allows_only: None,
+            keep_whitespace: false,
+            minimum: None,
+            maximum: None,
         },
         Property {
             class_type: Some(urls::CLASS.into()),
@@ -44,6 +50,9 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "A list of Classes of which the thing is an instance of. The Classes of a Resource determine which Properties are recommended and required.".into(),
             subject: urls::IS_A.into(),
             allows_only: None,
+            keep_whitespace: false,
+            minimum: None,
+            maximum: None,
         },
         Property {
             class_type: Some(urls::DATATYPE_CLASS.into()),
@@ -52,6 +61,9 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "The Datatype of a property, such as String or Timestamp.".into(),
             subject: urls::DATATYPE_PROP.into(),
             allows_only: None,
+            keep_whitespace: false,
+            minimum: None,
+            maximum: None,
         },
         Property {
             class_type: Some(urls::CLASS.into()),
@@ -62,6 +74,9 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
                .into(),
             subject: urls::CLASSTYPE_PROP.into(),
             allows_only: None,
+            keep_whitespace: false,
+            minimum: None,
+            maximum: None,
         },
         Property {
             class_type: Some(urls::PROPERTY.into()),
@@ -70,6 +85,9 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "The Properties that are not required, but recommended for this Class.".into(),
             subject: urls::RECOMMENDS.into(),
             allows_only: None,
+            keep_whitespace: false,
+            minimum: None,
+            maximum: None,
         },
         Property {
             class_type: Some(urls::PROPERTY.into()),
@@ -78,6 +96,9 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "The Properties that are required for this Class.".into(),
             subject: urls::REQUIRES.into(),
             allows_only: None,
+            keep_whitespace: false,
+            minimum: None,
+            maximum: None,
         },
         Property {
             class_type: None,
@@ -86,6 +107,9 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "The parent of a Resource sets the hierarchical structure of the Resource, and therefore also the rights / grants. It is used for both navigation, structure and authorization. Parents are the inverse of [children](https://atomicdata.dev/properties/children).".into(),
             subject: urls::PARENT.into(),
             allows_only: None,
+            keep_whitespace: false,
+            minimum: None,
+            maximum: None,
         },
         Property {
             class_type: None,
@@ -94,6 +118,9 @@ pub fn populate_base_models(store: &impl Storelike) -> AtomicResult<()> {
             description: "Restricts this Property to only the values inside this one. This essentially turns the Property into an `enum`.".into(),
             subject: urls::ALLOWS_ONLY.into(),
             allows_only: None,
+            keep_whitespace: false,
+            minimum: None,
+            maximum: None,
         }
     ];
 
@@ -3,10 +3,14 @@
 
 use crate::commit::{CommitOpts, CommitResponse};
 use crate::storelike::Query;
+use crate::subjects::SubjectHint;
 use crate::urls;
 use crate::utils::random_string;
 use crate::values::{SubResource, Value};
-use crate::{commit::CommitBuilder, errors::AtomicResult};
+use crate::{
+    commit::CommitBuilder,
+    errors::{AtomicError, AtomicErrorType, AtomicResult},
+};
 use crate::{
     mapping::is_url,
     schema::{Class, Property},
@@ -30,18 +34,68 @@ pub struct Resource {
 /// Maps Property URLs to their values
 pub type PropVals = HashMap<String, Value>;
 
+/// The ResourceArrays that can grow large, and are paginated by [Resource::paginate_arrays].
+/// Classes and rights are never paginated, since clients need all of them to use the Resource.
+pub const PAGINATED_ARRAYS: &[&str] = &[
+    urls::CHILDREN,
+    urls::SUBRESOURCES,
+    urls::ATTACHMENTS,
+    urls::COLLECTION_MEMBERS,
+    urls::ENDPOINT_RESULTS,
+];
+
 impl Resource {
+    /// Appends multiple subjects to a ResourceArray through the commitbuilder.
+    /// Clones the existing array only once, so prefer this over calling [Resource::push_propval] in a loop for large arrays.
+    /// The resulting Commit only contains the appended subjects, not the entire array.
+    /// With `skip_existing`, the Commit is also applied with `pushUnique`, so values that were added concurrently are skipped too.
+    pub fn append_subjects(
+        &mut self,
+        property: &str,
+        subjects: Vec<String>,
+        skip_existing: bool,
+    ) -> AtomicResult<()> {
+        let mut vec = match self.propvals.get(property) {
+            Some(Value::ResourceArray(vec)) => vec.to_owned(),
+            Some(_other) => return Err("Wrong datatype, expected ResourceArray".into()),
+            None => Vec::new(),
+        };
+        let mut existing: std::collections::HashSet<String> = if skip_existing {
+            vec.iter().map(|v| v.to_string()).collect()
+        } else {
+            Default::default()
+        };
+        if skip_existing {
+            self.commit.push_unique(true);
+        }
+        for subject in subjects {
+            if skip_existing && !existing.insert(subject.clone()) {
+                continue;
+            }
+            let value: SubResource = subject.into();
+            vec.push(value.clone());
+            self.commit.push_propval(property, value)?;
+        }
+        self.propvals.insert(property.into(), vec.into());
+        Ok(())
+    }
+
     /// Fetches all 'required' properties. Returns an error if any are missing in this Resource.
     pub fn check_required_props(&self, store: &impl Storelike) -> AtomicResult<()> {
         let classvec = self.get_classes(store)?;
         for class in classvec.iter() {
             for required_prop in class.requires.clone() {
-                self.get(&required_prop).map_err(|_e| {
-                    format!(
-                        "Property {} missing. Is required in class {} ",
+                if self.get(&required_prop).is_err() {
+                    let error = AtomicError::from(format!(
+                        "Property {} missing. Is required in class {}.",
                         &required_prop, class.subject
-                    )
-                })?;
+                    ))
+                    .set_subject(&self.subject);
+                    return Err(match store.get_property(&required_prop) {
+                        Ok(property) => error.set_property(&property),
+                        Err(_) => error,
+                    });
+                }
             }
         }
         Ok(())
@@ -49,20 +103,35 @@ impl Resource {
 
     /// Removes / deletes the resource from the store by performing a Commit.
     /// Recursively deletes the resource's children.
+    /// Resources in a Drive are moved to its trash, unless the resource is removed permanently (see [crate::plugins::trash]).
+    /// In that case, its children are removed permanently as well.
     #[tracing::instrument(skip(store))]
     pub fn destroy(
         &mut self,
         store: &impl Storelike,
+    ) -> AtomicResult<crate::commit::CommitResponse> {
+        #[cfg(feature = "db")]
+        let purge = crate::plugins::trash::trash_drive(store, self)?.is_none();
+        #[cfg(not(feature = "db"))]
+        let purge = false;
+        self.destroy_recursive(store, purge)
+    }
+
+    fn destroy_recursive(
+        &mut self,
+        store: &impl Storelike,
+        purge: bool,
     ) -> AtomicResult<crate::commit::CommitResponse> {
         let children = self.get_children(store);
 
         if let Ok(children) = children {
             for mut child in children {
-                child.destroy(store)?;
+                child.destroy_recursive(store, purge)?;
             }
         }
 
         self.commit.destroy(true);
+        self.commit.purge(purge);
         self.save(store)
             .map_err(|e| format!("Failed to destroy {} : {}", self.subject, e).into())
     }
@@ -120,18 +189,17 @@ impl Resource {
     }
 
     /// Returns the `Parent` of this Resource.
-    /// Throws in case of recursion
+    /// Throws a [crate::AtomicErrorType::HierarchyCycle] error if the Resource is its own parent.
     pub fn get_parent(&self, store: &impl Storelike) -> AtomicResult<Resource> {
         match self.get(urls::PARENT) {
             Ok(parent_val) => {
                 match store.get_resource(&parent_val.to_string()) {
                     Ok(parent) => {
                         if self.get_subject() == parent.get_subject() {
-                            return Err(format!(
-                                "There is a circular relationship in {} (parent = same resource).",
-                                self.get_subject()
-                            )
-                            .into());
+                            return Err(AtomicError::hierarchy_cycle(&[
+                                self.get_subject().clone(),
+                                parent.get_subject().clone(),
+                            ]));
                         }
                         // Check write right
                         Ok(parent)
@@ -150,11 +218,19 @@ impl Resource {
     }
 
     /// Walks the parent tree upwards until there is no parent, then returns them as a vector.
+    /// Throws a [crate::AtomicErrorType::HierarchyCycle] error if the parents form a loop, or if there are more than [Storelike::get_max_hierarchy_depth].
     pub fn get_parent_tree(&self, store: &impl Storelike) -> AtomicResult<Vec<Resource>> {
         let mut parents: Vec<Resource> = Vec::new();
+        let mut walk = crate::hierarchy::ParentWalk::new(store, self);
         let mut current = self.clone();
 
-        while let Ok(parent) = current.get_parent(store) {
+        loop {
+            let parent = match current.get_parent(store) {
+                Ok(parent) => parent,
+                Err(e) if matches!(e.error_type, AtomicErrorType::HierarchyCycle) => return Err(e),
+                Err(_no_parent) => break,
+            };
+            walk.step(parent.get_subject())?;
             parents.push(parent.clone());
             current = parent;
         }
@@ -180,18 +256,11 @@ impl Resource {
     }
 
     /// checks if a resouce has a specific parent. iterates over all parents.
+    /// Returns `false` if the parents form a loop.
     pub fn has_parent(&self, store: &impl Storelike, parent: &str) -> bool {
-        let mut mut_res = self.to_owned();
-        loop {
-            if let Ok(found_parent) = mut_res.get_parent(store) {
-                if found_parent.get_subject() == parent {
-                    return true;
-                }
-                mut_res = found_parent;
-            } else {
-                return false;
-            }
-        }
+        self.get_parent_tree(store)
+            .map(|parents| parents.iter().any(|p| p.get_subject() == parent))
+            .unwrap_or(false)
     }
 
     /// Returns all PropVals.
@@ -217,17 +286,23 @@ impl Resource {
     }
 
     /// Create a new instance of some Class.
-    /// The subject is generated, but can be changed.
+    /// The subject is generated using the [crate::subjects::SubjectStrategy] of the root Drive, but can be changed.
     /// Does not save the resource to the store.
     pub fn new_instance(class_url: &str, store: &impl Storelike) -> AtomicResult<Resource> {
         let propvals: PropVals = HashMap::new();
         let class = store.get_class(class_url)?;
-        let subject = format!(
-            "{}/{}/{}",
-            store.get_server_url(),
-            &class.shortname,
-            random_string(10)
-        );
+        let hint = SubjectHint {
+            parent: None,
+            name: Some(&class.shortname),
+        };
+        let subject = crate::subjects::new_subject(store, hint, || {
+            format!(
+                "{}/{}/{}",
+                store.get_server_url(),
+                &class.shortname,
+                random_string(10)
+            )
+        })?;
         let mut resource = Resource {
             propvals,
             subject: subject.clone(),
@@ -240,6 +315,7 @@ impl Resource {
 
     /// Appends a Resource to a specific property through the commitbuilder.
     /// Useful if you want to have compact Commits that add things to existing ResourceArrays.
+    /// With `skip_existing`, the Commit is also applied with `pushUnique`, so values that were added concurrently are skipped too.
     pub fn push_propval(
         &mut self,
         property: &str,
@@ -250,6 +326,7 @@ impl Resource {
             Some(some) => match some {
                 Value::ResourceArray(vec) => {
                     if skip_existing {
+                        self.commit.push_unique(true);
                         let str_val = value.to_string();
                         for i in vec {
                             if i.to_string() == str_val {
@@ -270,6 +347,122 @@ impl Resource {
         Ok(())
     }
 
+    /// Truncates the large ResourceArrays of [PAGINATED_ARRAYS] to the items in `offset..offset + limit`.
+    /// Returns the original length of every array that was shortened.
+    /// Only use this for serializing responses - never save a paginated Resource.
+    pub fn paginate_arrays(&mut self, offset: usize, limit: usize) -> HashMap<String, usize> {
+        let mut lengths = HashMap::new();
+        for (prop, val) in self.propvals.iter_mut() {
+            if !PAGINATED_ARRAYS.contains(&prop.as_str()) {
+                continue;
+            }
+            if let Value::ResourceArray(vec) = val {
+                let total = vec.len();
+                if offset == 0 && total <= limit {
+                    continue;
+                }
+                *vec = std::mem::take(vec)
+                    .into_iter()
+                    .skip(offset)
+                    .take(limit)
+                    .collect();
+                lengths.insert(prop.clone(), total);
+            }
+        }
+        lengths
+    }
+
+    /// Shortens every String and Markdown value that is longer than `max` bytes, see [Value::truncate_text].
+    /// Returns the original length of every value that was shortened.
+    /// Only use this for serializing responses - never save a truncated Resource.
+    pub fn truncate_values(&mut self, max: usize) -> HashMap<String, usize> {
+        self.propvals
+            .iter_mut()
+            .filter_map(|(prop, val)| val.truncate_text(max).map(|len| (prop.clone(), len)))
+            .collect()
+    }
+
+    /// Returns the Properties whose value doesn't match their current datatype, see [crate::coerce].
+    /// These values are still served as they are stored, so clients should be warned about them.
+    pub fn datatype_mismatches(&self, store: &impl Storelike) -> Vec<String> {
+        let mut props: Vec<String> = self
+            .propvals
+            .iter()
+            .filter(|(prop, value)| {
+                store
+                    .get_property(prop)
+                    .map(|property| crate::coerce::is_mismatch(value, &property))
+                    .unwrap_or(false)
+            })
+            .map(|(prop, _)| prop.clone())
+            .collect();
+        props.sort();
+        props
+    }
+
+    /// Removes every property that is not selected in `fields`, which contains Property URLs or shortnames.
+    /// Dotted fields such as `members.name` select properties of nested Resources.
+    /// Returns the fields that could not be resolved to a Property.
+    /// Only use this for serializing responses - never save a Resource with selected fields.
+    pub fn select_fields(&mut self, fields: &[String], store: &impl Storelike) -> Vec<String> {
+        let mut unknown = Vec::new();
+        // Property URL => the fields to select in its nested Resources. `None` keeps them intact.
+        let mut selected: HashMap<String, Option<Vec<String>>> = HashMap::new();
+        for field in fields {
+            // Property URLs contain dots themselves, so only shortnames can be dotted
+            let (head, tail) = match field.split_once('.') {
+                Some((head, tail)) if !is_url(field) => (head, Some(tail.to_string())),
+                _ => (field.as_str(), None),
+            };
+            let property = match self.resolve_shortname_to_property(head, store) {
+                Ok(property) => property,
+                Err(_) => {
+                    unknown.push(field.clone());
+                    continue;
+                }
+            };
+            let entry = selected
+                .entry(property.subject)
+                .or_insert_with(|| Some(Vec::new()));
+            match (entry, tail) {
+                (Some(nested), Some(tail)) => nested.push(tail),
+                (entry, None) => *entry = None,
+                (None, Some(_)) => {}
+            }
+        }
+
+        self.propvals.retain(|prop, _| selected.contains_key(prop));
+        for (prop, nested_fields) in selected {
+            if let (Some(value), Some(nested_fields)) =
+                (self.propvals.get_mut(&prop), nested_fields)
+            {
+                unknown.extend(select_nested_fields(value, &nested_fields, store));
+            }
+        }
+        unknown
+    }
+
+    /// Removes every occurrence of a Resource from a ResourceArray through the commitbuilder.
+    /// The Commit only contains the removed value, so it won't overwrite values that were added concurrently.
+    pub fn pull_propval(&mut self, property: &str, value: SubResource) -> AtomicResult<()> {
+        match self.propvals.get(property) {
+            Some(Value::ResourceArray(vec)) => {
+                let str_val = value.to_string();
+                let kept: Vec<SubResource> = vec
+                    .iter()
+                    .filter(|v| v.to_string() != str_val)
+                    .cloned()
+                    .collect();
+                self.propvals.insert(property.into(), kept.into());
+            }
+            Some(_other) => return Err("Wrong datatype, expected ResourceArray".into()),
+            // The array might exist in the store, so we still add it to the Commit
+            None => {}
+        };
+        self.commit.pull_propval(property, value)?;
+        Ok(())
+    }
+
     /// Remove a propval from a resource by property URL.
     pub fn remove_propval(&mut self, property_url: &str) {
         self.propvals.remove_entry(property_url);
@@ -360,6 +553,7 @@ impl Resource {
             // TODO: auto-merge should work before we enable this https://github.com/atomicdata-dev/atomic-server/issues/412
             validate_previous_commit: false,
             update_index: true,
+            validate_relative_urls: false,
         };
         let commit_response = commit.apply_opts(store, &opts)?;
         if let Some(new) = &commit_response.resource_new {
@@ -388,6 +582,7 @@ impl Resource {
             // https://github.com/atomicdata-dev/atomic-server/issues/412
             validate_previous_commit: false,
             update_index: true,
+            validate_relative_urls: false,
         };
         let commit_response = commit.apply_opts(store, &opts)?;
         if let Some(new) = &commit_response.resource_new {
@@ -423,7 +618,8 @@ impl Resource {
                 e
             )
         })?;
-        let val = Value::new(value, &fullprop.data_type)?;
+        let val = Value::new(value, &fullprop.data_type)
+            .map_err(|e| e.set_subject(&self.subject).set_property(&fullprop))?;
         self.set_propval_unsafe(property_url, val);
         Ok(())
     }
@@ -439,12 +635,14 @@ impl Resource {
         store: &impl Storelike,
     ) -> AtomicResult<()> {
         let full_prop = store.get_property(&property)?;
-        if let Some(allowed) = full_prop.allows_only {
-            let error = Err(format!(
+        let value = crate::normalize::normalize_value(&value, &full_prop).unwrap_or(value);
+        if let Some(allowed) = &full_prop.allows_only {
+            let error = Err(AtomicError::from(format!(
                 "Property '{}' does not allow value '{}'. Allowed: {:?}",
                 property, value, allowed
-            )
-            .into());
+            ))
+            .set_subject(&self.subject)
+            .set_property(&full_prop));
 
             match &value {
                 Value::ResourceArray(value_array) => {
@@ -461,17 +659,25 @@ impl Resource {
                 }
             }
         }
+        if let Err(e) = full_prop.check_bounds(&value) {
+            return Err(AtomicError::from(format!(
+                "Property '{}' does not allow value '{}'. {}",
+                property, value, e
+            ))
+            .set_subject(&self.subject)
+            .set_property(&full_prop));
+        }
         if full_prop.data_type == value.datatype() {
             self.set_propval_unsafe(property, value);
             Ok(())
         } else {
-            Err(format!("Datatype for subject '{}', property '{}', value '{}' did not match. Wanted '{}', got '{}'",
+            Err(AtomicError::from(format!("Datatype for subject '{}', property '{}', value '{}' did not match. Wanted '{}', got '{}'",
                 self.get_subject(),
                 property,
                 value,
                 full_prop.data_type,
                 value.datatype()
-            ).into())
+            )).set_subject(&self.subject).set_property(&full_prop))
         }
     }
 
@@ -494,7 +700,8 @@ impl Resource {
         store: &impl Storelike,
     ) -> AtomicResult<()> {
         let fullprop = self.resolve_shortname_to_property(property, store)?;
-        let fullval = Value::new(value, &fullprop.data_type)?;
+        let fullval = Value::new(value, &fullprop.data_type)
+            .map_err(|e| e.set_subject(&self.subject).set_property(&fullprop))?;
         self.set_propval_unsafe(fullprop.subject, fullval);
         Ok(())
     }
@@ -535,8 +742,15 @@ impl Resource {
     }
 
     /// Converts Resource to JSON-LD string, with @context object and RDF compatibility.
+    /// Requires the `json-ld` feature.
     #[instrument(skip_all)]
     pub fn to_json_ld(&self, store: &impl Storelike) -> AtomicResult<String> {
+        if cfg!(not(feature = "json-ld")) {
+            return Err(AtomicError::missing_feature(
+                "json-ld",
+                "Serializing to JSON-LD",
+            ));
+        }
         let obj = crate::serialize::propvals_to_json_ld(
             self.get_propvals(),
             Some(self.get_subject().clone()),
@@ -546,6 +760,19 @@ impl Resource {
         serde_json::to_string_pretty(&obj).map_err(|_| "Could not serialize to JSON-LD".into())
     }
 
+    /// The Atoms that should be added to the value index.
+    /// Trashed Resources only index their `parent`, which hides them from Collections but lists them in the trash.
+    pub fn to_index_atoms(&self) -> Vec<Atom> {
+        if self.propvals.contains_key(urls::DELETED_AT) {
+            return self
+                .to_atoms()
+                .into_iter()
+                .filter(|atom| atom.property == urls::PARENT)
+                .collect();
+        }
+        self.to_atoms()
+    }
+
     #[instrument(skip_all)]
     pub fn to_atoms(&self) -> Vec<Atom> {
         let mut atoms: Vec<Atom> = Vec::new();
@@ -562,6 +789,63 @@ impl Resource {
     pub fn to_n_triples(&self, store: &impl Storelike) -> AtomicResult<String> {
         crate::serialize::atoms_to_ntriples(self.to_atoms(), store)
     }
+
+    #[instrument(skip_all)]
+    #[cfg(feature = "rdf")]
+    /// Serializes the Resource to the RDF N-Quads format, using the graph of its Drive, see [crate::serialize::resources_to_nquads].
+    pub fn to_n_quads(&self, store: &impl Storelike) -> AtomicResult<String> {
+        let graph = crate::serialize::DriveGraphs::default().graph_of(self, store);
+        crate::serialize::atoms_to_nquads(self.to_atoms(), graph.as_deref(), store)
+    }
+
+    #[instrument(skip_all)]
+    #[cfg(feature = "rdf")]
+    /// Serializes the Resource to the RDF Turtle format, using prefixes.
+    pub fn to_turtle(&self, store: &impl Storelike) -> AtomicResult<String> {
+        crate::serialize::resources_to_turtle(
+            std::slice::from_ref(self),
+            store,
+            &crate::serialize::TurtleOpts::default(),
+        )
+    }
+}
+
+/// Applies [Resource::select_fields] to the nested Resources in a Value.
+/// A field is only reported as unknown if none of the nested Resources could resolve it.
+fn select_nested_fields(
+    value: &mut Value,
+    fields: &[String],
+    store: &impl Storelike,
+) -> Vec<String> {
+    let nested: Vec<&mut SubResource> = match value {
+        Value::ResourceArray(vec) => vec.iter_mut().collect(),
+        Value::NestedResource(sub) => vec![sub],
+        Value::Resource(resource) => return resource.select_fields(fields, store),
+        _ => return Vec::new(),
+    };
+    let mut unknown_counts: HashMap<String, usize> = HashMap::new();
+    let mut selected = 0;
+    for sub in nested {
+        let unknown = match sub {
+            SubResource::Resource(resource) => resource.select_fields(fields, store),
+            SubResource::Nested(propvals) => {
+                let mut resource = Resource::from_propvals(std::mem::take(propvals), String::new());
+                let unknown = resource.select_fields(fields, store);
+                *propvals = resource.into_propvals();
+                unknown
+            }
+            SubResource::Subject(_) => continue,
+        };
+        selected += 1;
+        for field in unknown {
+            *unknown_counts.entry(field).or_default() += 1;
+        }
+    }
+    unknown_counts
+        .into_iter()
+        .filter(|(_, count)| *count == selected)
+        .map(|(field, _)| field)
+        .collect()
 }
 
 #[cfg(test)]
@@ -611,6 +895,35 @@ mod test {
         new_resource.check_required_props(&store).unwrap();
     }
 
+    #[test]
+    fn validation_errors_describe_property() {
+        let store = init_store();
+        let mut new_resource = Resource::new_instance(urls::CLASS, &store).unwrap();
+        let err = new_resource.check_required_props(&store).unwrap_err();
+        let hint = err.property.clone().expect("Missing property hint");
+        assert_eq!(
+            err.subject.as_deref(),
+            Some(new_resource.get_subject().as_str())
+        );
+        assert_eq!(hint.datatype, "slug");
+        assert_eq!(hint.example, "my-slug");
+        assert!(err.message.contains(&hint.property));
+
+        let err = new_resource
+            .set_propval_string(urls::IS_DYNAMIC.into(), "maybe", &store)
+            .unwrap_err();
+        assert_eq!(err.property.unwrap().datatype, "boolean");
+
+        let error_resource = new_resource
+            .set_propval_string(urls::IS_DYNAMIC.into(), "maybe", &store)
+            .unwrap_err()
+            .into_resource("https://localhost/error".into());
+        assert_eq!(
+            error_resource.get(urls::ERROR_EXAMPLE).unwrap().to_string(),
+            "true"
+        );
+    }
+
     #[test]
     fn new_instance() {
         let store = init_store();
@@ -698,6 +1011,7 @@ mod test {
                     validate_previous_commit: true,
                     validate_for_agent: None,
                     update_index: true,
+                    validate_relative_urls: false,
                 },
             )
             .unwrap();
@@ -797,6 +1111,115 @@ mod test {
         assert_eq!(new_val.first().unwrap(), append_value);
     }
 
+    #[test]
+    fn append_subjects() {
+        let store = init_store();
+        let property: String = urls::CHILDREN.into();
+        let mut resource = Resource::new_generate_subject(&store);
+        resource
+            .push_propval(&property, "http://localhost/a".into(), false)
+            .unwrap();
+        resource
+            .append_subjects(
+                &property,
+                vec!["http://localhost/a".into(), "http://localhost/b".into()],
+                true,
+            )
+            .unwrap();
+        let vec = resource.get(&property).unwrap().to_subjects(None).unwrap();
+        assert_eq!(vec, vec!["http://localhost/a", "http://localhost/b"]);
+        let resp = resource.save_locally(&store).unwrap();
+        let pushed = resp.commit_struct.push.unwrap();
+        assert_eq!(
+            pushed
+                .get(&property)
+                .unwrap()
+                .to_subjects(None)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[test]
+    fn paginate_arrays() {
+        let store = init_store();
+        let property: String = urls::CHILDREN.into();
+        let mut resource = Resource::new_generate_subject(&store);
+        let subjects: Vec<String> = (0..10).map(|i| format!("http://localhost/{i}")).collect();
+        resource
+            .set_propval(property.clone(), subjects.into(), &store)
+            .unwrap();
+        let lengths = resource.paginate_arrays(4, 3);
+        assert_eq!(lengths.get(&property), Some(&10));
+        let vec = resource.get(&property).unwrap().to_subjects(None).unwrap();
+        assert_eq!(
+            vec,
+            vec![
+                "http://localhost/4",
+                "http://localhost/5",
+                "http://localhost/6"
+            ]
+        );
+
+        // Short arrays are left alone
+        assert!(resource.paginate_arrays(0, 100).is_empty());
+    }
+
+    #[test]
+    fn paginate_arrays_keeps_classes_and_rights() {
+        let store = init_store();
+        let mut resource = Resource::new_generate_subject(&store);
+        let subjects: Vec<String> = (0..10).map(|i| format!("http://localhost/{i}")).collect();
+        resource.set_propval_unsafe(urls::IS_A.into(), subjects.clone().into());
+        resource.set_propval_unsafe(urls::WRITE.into(), subjects.clone().into());
+        resource.set_propval_unsafe(urls::ATTACHMENTS.into(), subjects.into());
+        let lengths = resource.paginate_arrays(4, 3);
+        assert_eq!(lengths.len(), 1);
+        assert_eq!(lengths.get(urls::ATTACHMENTS), Some(&10));
+        for prop in [urls::IS_A, urls::WRITE] {
+            let vec = resource.get(prop).unwrap().to_subjects(None).unwrap();
+            assert_eq!(vec.len(), 10, "{} should not be paginated", prop);
+        }
+    }
+
+    #[test]
+    fn select_fields() {
+        let store = init_store();
+        let mut resource = Resource::new_generate_subject(&store);
+        resource.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug("doc".into()));
+        resource.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("long".into()));
+        let attachments: Vec<SubResource> = (0..2)
+            .map(|i| {
+                let mut file = Resource::new(format!("http://localhost/file{i}"));
+                file.set_propval_unsafe(urls::FILENAME.into(), Value::String(format!("{i}.txt")));
+                file.set_propval_unsafe(urls::FILESIZE.into(), Value::Integer(i));
+                SubResource::Resource(Box::new(file))
+            })
+            .collect();
+        resource.set_propval_unsafe(urls::ATTACHMENTS.into(), Value::ResourceArray(attachments));
+
+        let fields: Vec<String> = ["shortname", "attachments.filename", "nope"]
+            .iter()
+            .map(|f| f.to_string())
+            .collect();
+        let unknown = resource.select_fields(&fields, &store);
+        assert_eq!(unknown, vec!["nope".to_string()]);
+        assert_eq!(resource.get_propvals().len(), 2);
+        resource.get(urls::DESCRIPTION).unwrap_err();
+        if let Value::ResourceArray(files) = resource.get(urls::ATTACHMENTS).unwrap() {
+            for file in files {
+                let SubResource::Resource(file) = file else {
+                    panic!("expected nested resource")
+                };
+                assert_eq!(file.get_propvals().len(), 1);
+                file.get(urls::FILENAME).unwrap();
+            }
+        } else {
+            panic!("expected ResourceArray")
+        }
+    }
+
     #[test]
     fn get_children() {
         let store = init_store();
@@ -18,6 +18,15 @@ pub struct Property {
     /// Restricts values to be only one of these Subjects.
     /// https://atomicdata.dev/properties/allowsOnly
     pub allows_only: Option<Vec<String>>,
+    /// Keeps the surrounding whitespace of String and Markdown values, instead of trimming it. See [crate::normalize].
+    /// https://atomicdata.dev/properties/keepWhitespace
+    pub keep_whitespace: bool,
+    /// The lowest allowed value of an Integer or Float Property.
+    /// https://atomicdata.dev/properties/minimum
+    pub minimum: Option<f64>,
+    /// The highest allowed value of an Integer or Float Property.
+    /// https://atomicdata.dev/properties/maximum
+    pub maximum: Option<f64>,
 }
 
 impl PartialEq for Property {
@@ -40,6 +49,12 @@ impl Property {
             Ok(classtype) => Some(classtype.to_subjects(None)?),
             Err(_) => None,
         };
+        let keep_whitespace = matches!(
+            resource.get(urls::KEEP_WHITESPACE),
+            Ok(Value::Boolean(true))
+        );
+        let minimum = resource.get(urls::MINIMUM).and_then(|v| v.to_float()).ok();
+        let maximum = resource.get(urls::MAXIMUM).and_then(|v| v.to_float()).ok();
 
         Ok(Property {
             class_type,
@@ -47,6 +62,9 @@ impl Property {
             shortname,
             description,
             allows_only,
+            keep_whitespace,
+            minimum,
+            maximum,
             subject: resource.get_subject().into(),
         })
     }
@@ -61,7 +79,7 @@ impl Property {
         resource.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug(self.shortname.clone()));
         resource.set_propval_unsafe(
             urls::DESCRIPTION.into(),
-            Value::String(self.description.clone()),
+            Value::Markdown(self.description.clone()),
         );
         resource.set_propval_unsafe(
             urls::DATATYPE_PROP.into(),
@@ -73,9 +91,62 @@ impl Property {
                 Value::AtomicUrl(classtype.clone()),
             );
         }
+        if self.keep_whitespace {
+            resource.set_propval_unsafe(urls::KEEP_WHITESPACE.into(), Value::Boolean(true));
+        }
+        if let Some(minimum) = self.minimum {
+            resource.set_propval_unsafe(urls::MINIMUM.into(), Value::Float(minimum));
+        }
+        if let Some(maximum) = self.maximum {
+            resource.set_propval_unsafe(urls::MAXIMUM.into(), Value::Float(maximum));
+        }
 
         resource
     }
+
+    /// Checks whether an existing Value is still valid for this Property.
+    /// Values of another datatype are accepted if they can be parsed as the datatype of the Property.
+    /// Used to find the values that a change to the Property would invalidate.
+    pub fn check_value(&self, value: &Value) -> Result<(), String> {
+        if let Some(allowed) = &self.allows_only {
+            let values = match value {
+                Value::ResourceArray(_) => value.to_subjects(None).map_err(|e| e.to_string())?,
+                _ => vec![value.to_string()],
+            };
+            if let Some(not_allowed) = values.iter().find(|v| !allowed.contains(v)) {
+                return Err(format!("'{}' is not one of {:?}", not_allowed, allowed));
+            }
+        }
+        if value.datatype() != self.data_type {
+            Value::new(&value.to_string(), &self.data_type)
+                .map_err(|e| format!("'{}' is not a valid {}. {}", value, self.data_type, e))?;
+        }
+        self.check_bounds(value)
+    }
+
+    /// Checks whether a number lies between the `minimum` and `maximum` of this Property, if it has them.
+    pub fn check_bounds(&self, value: &Value) -> Result<(), String> {
+        if self.minimum.is_none() && self.maximum.is_none() {
+            return Ok(());
+        }
+        let number = match value {
+            Value::Integer(_) | Value::Float(_) => value.to_float().map_err(|e| e.to_string())?,
+            _ => return Ok(()),
+        };
+        if let Some(minimum) = self.minimum.filter(|minimum| number < *minimum) {
+            return Err(format!(
+                "{} is lower than the minimum of {}",
+                value, minimum
+            ));
+        }
+        if let Some(maximum) = self.maximum.filter(|maximum| number > *maximum) {
+            return Err(format!(
+                "{} is higher than the maximum of {}",
+                value, maximum
+            ));
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -127,7 +198,7 @@ impl Class {
         resource.set_propval_unsafe(urls::SHORTNAME.into(), Value::Slug(self.shortname.clone()));
         resource.set_propval_unsafe(
             urls::DESCRIPTION.into(),
-            Value::String(self.description.clone()),
+            Value::Markdown(self.description.clone()),
         );
         if !self.requires.is_empty() {
             resource.set_propval_unsafe(urls::REQUIRES.into(), Value::from(self.requires.clone()));
@@ -0,0 +1,126 @@
+/*!
+Records which version of the default ontology (the Properties and Classes in `defaults/`) was active when a Resource was last written.
+This helps to debug Resources that no longer validate after an upgrade, and to find the ones a migration still has to touch.
+
+The server stamps every Commit it applies with [CURRENT], in `schema-version`.
+The stamp is added to the stored Commit Resource, outside of the signed fields, so signatures stay valid.
+Resources show the version of their `lastCommit` as a computed `schema-version`.
+Resources whose last Commit has no stamp were written by an older server, and have an unknown version.
+*/
+
+use crate::{urls, Resource, Storelike, Value};
+
+/// Version of the default ontology. Increase it whenever the Properties or Classes in `defaults/` change.
+pub const CURRENT: i64 = 1;
+
+/// Shown for Resources whose version is unknown.
+pub const UNKNOWN: &str = "unknown";
+
+/// Adds the current version to a Commit Resource that is about to be stored.
+pub fn stamp(commit_resource: &mut Resource) {
+    commit_resource.set_propval_unsafe(urls::SCHEMA_VERSION.into(), Value::Integer(CURRENT));
+}
+
+/// The version that was active when the Resource was last written, read from its `lastCommit`.
+pub fn last_written_under(store: &impl Storelike, resource: &Resource) -> Option<i64> {
+    let last_commit = resource.get(urls::LAST_COMMIT).ok()?.to_string();
+    store
+        .get_resource(&last_commit)
+        .ok()?
+        .get(urls::SCHEMA_VERSION)
+        .ok()?
+        .to_int()
+        .ok()
+}
+
+/// The version as text, or [UNKNOWN].
+pub fn label(version: Option<i64>) -> String {
+    version
+        .map(|version| version.to_string())
+        .unwrap_or_else(|| UNKNOWN.into())
+}
+
+/// Whether a Resource that was written under `version` predates version `below`. Unknown versions are always older.
+pub fn is_older(version: Option<i64>, below: i64) -> bool {
+    version.map(|version| version < below).unwrap_or(true)
+}
+
+/// Sets the computed `schema-version` of the Resource, or removes it if the version is unknown.
+/// Resources without a `lastCommit`, such as Commits themselves, are left as they are.
+pub fn add_to_resource(store: &impl Storelike, resource: &mut Resource) {
+    if resource.get(urls::LAST_COMMIT).is_err() {
+        return;
+    }
+    match last_written_under(store, resource) {
+        Some(version) => {
+            resource.set_propval_unsafe(urls::SCHEMA_VERSION.into(), Value::Integer(version))
+        }
+        None => resource.remove_propval(urls::SCHEMA_VERSION),
+    }
+}
+
+#[cfg(all(test, feature = "db"))]
+mod test {
+    use super::*;
+    use crate::{agents::ForAgent, collections::CollectionBuilder};
+
+    #[test]
+    fn commits_are_stamped_and_old_resources_are_unknown() {
+        let store = crate::Db::init_temp("commits_are_stamped").unwrap();
+        let mut resource = Resource::new_generate_subject(&store);
+        resource
+            .set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                &store,
+            )
+            .unwrap();
+        resource
+            .set_propval_string(urls::NAME.into(), "Stamped", &store)
+            .unwrap();
+        let response = resource.save_locally(&store).unwrap();
+        assert_eq!(
+            response
+                .commit_resource
+                .get(urls::SCHEMA_VERSION)
+                .unwrap()
+                .to_int()
+                .unwrap(),
+            CURRENT
+        );
+        // The stamp is not part of the signed Commit
+        let commit = crate::Commit::from_resource(response.commit_resource.clone()).unwrap();
+        let signed = commit.serialize_deterministically_json_ad(&store).unwrap();
+        assert!(!signed.contains(urls::SCHEMA_VERSION));
+
+        let extended = store
+            .get_resource_extended(resource.get_subject(), false, &ForAgent::Sudo)
+            .unwrap();
+        assert_eq!(
+            extended
+                .get(urls::SCHEMA_VERSION)
+                .unwrap()
+                .to_int()
+                .unwrap(),
+            CURRENT
+        );
+
+        // Written without a Commit, like by older servers
+        let mut old = Resource::new_generate_subject(&store);
+        old.set_propval_string(urls::NAME.into(), "Old", &store)
+            .unwrap();
+        store.add_resource(&old).unwrap();
+        assert_eq!(label(last_written_under(&store, &old)), UNKNOWN);
+
+        let mut builder = CollectionBuilder::class_collection(urls::CLASS, "classes", &store);
+        builder.property = Some(urls::NAME.into());
+        builder.value = None;
+        builder.schema_version_below = Some(CURRENT);
+        let members = builder
+            .into_collection(&store, &ForAgent::Sudo)
+            .unwrap()
+            .members;
+        assert!(members.contains(old.get_subject()));
+        assert!(!members.contains(resource.get_subject()));
+    }
+}
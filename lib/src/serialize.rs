@@ -1,4 +1,4 @@
-//! Serialization / formatting / encoding (JSON, RDF, N-Triples)
+//! Serialization / formatting / encoding (JSON, RDF, N-Triples, N-Quads, Turtle)
 
 use serde_json::Map;
 use serde_json::Value as SerdeValue;
@@ -8,6 +8,11 @@ use crate::{
     datatype::DataType, errors::AtomicResult, resources::PropVals, Resource, Storelike, Value,
 };
 
+#[cfg(feature = "rdf")]
+mod turtle;
+#[cfg(feature = "rdf")]
+pub use turtle::{resources_to_turtle, turtle_prefixes, TurtleArrays, TurtleOpts};
+
 /// Serializes a vector or Resources to a JSON-AD string
 pub fn resources_to_json_ad(resources: &[Resource]) -> AtomicResult<String> {
     let mut vec: Vec<serde_json::Value> = Vec::new();
@@ -172,81 +177,162 @@ pub fn serialize_json_array(items: &[String]) -> AtomicResult<String> {
     Ok(string)
 }
 
+#[cfg(feature = "rdf")]
+/// Converts an Atom to an RDF Triple, and passes it to `format`.
+/// Shared by the N-Triples and N-Quads serializers, so both escape and annotate literals the same way.
+fn format_atom<T>(
+    atom: &crate::Atom,
+    store: &impl Storelike,
+    format: impl FnOnce(rio_api::model::Triple) -> T,
+) -> AtomicResult<T> {
+    use rio_api::model::{Literal, NamedNode, Term, Triple};
+
+    let subject = NamedNode { iri: &atom.subject }.into();
+    let predicate = NamedNode {
+        iri: &atom.property,
+    };
+    let property = store.get_property(&atom.property)?;
+    // Values stored before the datatype of the Property changed are typed as they are stored, see [crate::coerce]
+    let datatype = if crate::coerce::is_mismatch(&atom.value, &property) {
+        atom.value.datatype()
+    } else {
+        property.data_type
+    };
+    let value = &atom.value.to_string();
+    let datatype_url = datatype.to_string();
+    let object: Term = match &datatype {
+        DataType::AtomicUrl => NamedNode { iri: value }.into(),
+        // Maybe these should be converted to RDF collections / lists?
+        // DataType::ResourceArray => {}
+        DataType::String => Literal::Simple { value }.into(),
+        _dt => Literal::Typed {
+            value,
+            datatype: NamedNode { iri: &datatype_url },
+        }
+        .into(),
+    };
+    Ok(format(Triple {
+        subject,
+        predicate,
+        object,
+    }))
+}
+
 #[cfg(feature = "rdf")]
 /// Serializes Atoms to Ntriples (which is also valid Turtle / Notation3).
 pub fn atoms_to_ntriples(atoms: Vec<crate::Atom>, store: &impl Storelike) -> AtomicResult<String> {
     use rio_api::formatter::TriplesFormatter;
-    use rio_api::model::{Literal, NamedNode, Term, Triple};
     use rio_turtle::NTriplesFormatter;
 
     let mut formatter = NTriplesFormatter::new(Vec::default());
     for atom in atoms {
-        let subject = NamedNode { iri: &atom.subject }.into();
-        let predicate = NamedNode {
-            iri: &atom.property,
-        };
-        let datatype = store.get_property(&atom.property)?.data_type;
-        let value = &atom.value.to_string();
-        let datatype_url = datatype.to_string();
-        let object: Term = match &datatype {
-            DataType::AtomicUrl => NamedNode { iri: value }.into(),
-            // Maybe these should be converted to RDF collections / lists?
-            // DataType::ResourceArray => {}
-            DataType::String => Literal::Simple { value }.into(),
-            _dt => Literal::Typed {
-                value,
-                datatype: NamedNode { iri: &datatype_url },
-            }
-            .into(),
-        };
+        format_atom(&atom, store, |triple| formatter.format(&triple))??;
+    }
+    let out = String::from_utf8(formatter.finish()?)?;
+    Ok(out)
+}
 
-        formatter.format(&Triple {
-            subject,
-            predicate,
-            object,
-        })?
+#[cfg(feature = "rdf")]
+/// Serializes Atoms to N-Quads, with `graph` as the fourth term.
+/// If `graph` is `None`, the Atoms are in the default graph.
+pub fn atoms_to_nquads(
+    atoms: Vec<crate::Atom>,
+    graph: Option<&str>,
+    store: &impl Storelike,
+) -> AtomicResult<String> {
+    use rio_api::formatter::QuadsFormatter;
+    use rio_api::model::{NamedNode, Quad};
+    use rio_turtle::NQuadsFormatter;
+
+    let mut formatter = NQuadsFormatter::new(Vec::default());
+    let graph_name = graph.map(|iri| NamedNode { iri }.into());
+    for atom in atoms {
+        format_atom(&atom, store, |triple| {
+            formatter.format(&Quad {
+                subject: triple.subject,
+                predicate: triple.predicate,
+                object: triple.object,
+                graph_name,
+            })
+        })??;
     }
     let out = String::from_utf8(formatter.finish()?)?;
     Ok(out)
 }
 
 #[cfg(feature = "rdf")]
-/// Serializes Atoms to Ntriples (which is also valid Turtle / Notation3).
-pub fn atoms_to_turtle(atoms: Vec<crate::Atom>, store: &impl Storelike) -> AtomicResult<String> {
-    use rio_api::formatter::TriplesFormatter;
-    use rio_api::model::{Literal, NamedNode, Term, Triple};
-    use rio_turtle::TurtleFormatter;
+/// Serializes Resources to N-Quads, grouped by the Drive they belong to.
+/// The graph of a Drive is its `graphIri`, or its subject if that is not set.
+/// Resources that are not part of a Drive, such as Properties from other servers, are in the default graph.
+pub fn resources_to_nquads(resources: &[Resource], store: &impl Storelike) -> AtomicResult<String> {
+    let mut graphs = DriveGraphs::default();
+    let mut by_graph: std::collections::BTreeMap<Option<String>, Vec<crate::Atom>> =
+        std::collections::BTreeMap::new();
+    for resource in resources {
+        by_graph
+            .entry(graphs.graph_of(resource, store))
+            .or_default()
+            .extend(resource.to_atoms());
+    }
+    let mut out = String::new();
+    for (graph, atoms) in by_graph {
+        out.push_str(&atoms_to_nquads(atoms, graph.as_deref(), store)?);
+    }
+    Ok(out)
+}
 
-    let mut formatter = TurtleFormatter::new(Vec::default());
+/// Finds the graph IRI of the Drive that a Resource belongs to.
+/// Remembers the graph of every Resource it passes, so the parents of many Resources are only fetched once.
+#[derive(Default)]
+pub struct DriveGraphs {
+    graphs: std::collections::HashMap<String, Option<String>>,
+}
 
-    for atom in atoms {
-        let subject = NamedNode { iri: &atom.subject }.into();
-        let predicate = NamedNode {
-            iri: &atom.property,
-        };
-        let datatype = store.get_property(&atom.property)?.data_type;
-        let value = &atom.value.to_string();
-        let datatype_url = datatype.to_string();
-        let object: Term = match &datatype {
-            DataType::AtomicUrl => NamedNode { iri: value }.into(),
-            // Maybe these should be converted to RDF collections / lists?
-            // DataType::ResourceArray => {}
-            DataType::String => Literal::Simple { value }.into(),
-            _dt => Literal::Typed {
-                value,
-                datatype: NamedNode { iri: &datatype_url },
+impl DriveGraphs {
+    pub fn graph_of(&mut self, resource: &Resource, store: &impl Storelike) -> Option<String> {
+        let mut passed: Vec<String> = Vec::new();
+        let mut current = resource.clone();
+        let graph = loop {
+            if let Some(graph) = self.graphs.get(current.get_subject()) {
+                break graph.clone();
+            }
+            passed.push(current.get_subject().clone());
+            let is_drive = current
+                .get(crate::urls::IS_A)
+                .and_then(|classes| classes.to_subjects(None))
+                .map(|classes| classes.iter().any(|c| c == crate::urls::DRIVE))
+                .unwrap_or(false);
+            if is_drive {
+                break Some(match current.get(crate::urls::GRAPH_IRI) {
+                    Ok(iri) => iri.to_string(),
+                    Err(_) => current.get_subject().clone(),
+                });
+            }
+            match current.get_parent(store) {
+                Ok(parent) if !passed.contains(parent.get_subject()) => current = parent,
+                _ => break None,
             }
-            .into(),
         };
+        for subject in passed {
+            self.graphs.insert(subject, graph.clone());
+        }
+        graph
+    }
+}
 
-        formatter.format(&Triple {
-            subject,
-            predicate,
-            object,
-        })?
+#[cfg(feature = "rdf")]
+/// Serializes Atoms to Turtle, grouped by subject. See [resources_to_turtle].
+pub fn atoms_to_turtle(atoms: Vec<crate::Atom>, store: &impl Storelike) -> AtomicResult<String> {
+    let mut resources: Vec<Resource> = Vec::new();
+    let mut index: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+    for atom in atoms {
+        let i = *index.entry(atom.subject.clone()).or_insert_with(|| {
+            resources.push(Resource::new(atom.subject.clone()));
+            resources.len() - 1
+        });
+        resources[i].set_propval_unsafe(atom.property, atom.value);
     }
-    let out = String::from_utf8(formatter.finish()?)?;
-    Ok(out)
+    resources_to_turtle(&resources, store, &TurtleOpts::default())
 }
 
 /// Should list all the supported serialization formats
@@ -255,6 +341,7 @@ pub enum Format {
     JsonAd,
     JsonLd,
     NTriples,
+    Turtle,
     Pretty,
 }
 
@@ -342,6 +429,7 @@ mod test {
     }
 
     #[test]
+    #[cfg(feature = "json-ld")]
     fn serialize_json_ld() {
         let store = crate::Store::init().unwrap();
         store.populate().unwrap();
@@ -413,4 +501,56 @@ mod test {
         // This could fail when the `description` resource changes
         assert!(serialized.lines().count() == 5);
     }
+
+    #[test]
+    #[cfg(feature = "rdf")]
+    fn serialize_nquads() {
+        use crate::{urls, Storelike};
+        let store = crate::Store::init().unwrap();
+        store.populate().unwrap();
+        let mut drive = Resource::new("https://example.com/drive".into());
+        drive.set_class(urls::DRIVE);
+        store.add_resource(&drive).unwrap();
+        let mut child = Resource::new("https://example.com/drive/child".into());
+        child.set_propval_unsafe(
+            urls::PARENT.into(),
+            Value::AtomicUrl(drive.get_subject().clone()),
+        );
+        child.set_propval_unsafe(
+            urls::NAME.into(),
+            Value::String("A \"quoted\"\nname".into()),
+        );
+        child.set_propval_unsafe(urls::DESCRIPTION.into(), Value::Markdown("*text*".into()));
+        store.add_resource(&child).unwrap();
+
+        // Both formats serialize the terms of a triple the same way, N-Quads only adds the graph.
+        let triples = atoms_to_ntriples(child.to_atoms(), &store).unwrap();
+        let quads = child.to_n_quads(&store).unwrap();
+        let mut expected: Vec<String> = triples
+            .lines()
+            .map(|line| {
+                format!(
+                    "{} <https://example.com/drive> .",
+                    line.trim_end_matches(" .")
+                )
+            })
+            .collect();
+        let mut lines: Vec<&str> = quads.lines().collect();
+        expected.sort();
+        lines.sort();
+        assert_eq!(lines, expected);
+        assert!(quads.contains(r#""A \"quoted\"\nname" <https://example.com/drive> ."#));
+        assert!(quads.contains(r#""*text*"^^<https://atomicdata.dev/datatypes/markdown>"#));
+
+        // A graphIri on the Drive replaces its subject, Resources outside Drives are in the default graph.
+        drive.set_propval_unsafe(
+            urls::GRAPH_IRI.into(),
+            Value::AtomicUrl("https://example.com/graphs/tenant".into()),
+        );
+        store.add_resource(&drive).unwrap();
+        let outside = store.get_resource(urls::NAME).unwrap();
+        let all = resources_to_nquads(&[child, outside], &store).unwrap();
+        assert!(all.contains("<https://example.com/drive/child> <https://atomicdata.dev/properties/parent> <https://example.com/drive> <https://example.com/graphs/tenant> ."));
+        assert!(all.contains(r#"<https://atomicdata.dev/properties/name> <https://atomicdata.dev/properties/shortname> "name"^^<https://atomicdata.dev/datatypes/slug> ."#));
+    }
 }
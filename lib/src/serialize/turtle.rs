@@ -0,0 +1,445 @@
+//! Serializes Resources to [RDF Turtle](https://www.w3.org/TR/turtle/).
+//! Unlike N-Triples, this groups predicates per subject and shortens IRIs using `@prefix` declarations.
+
+use std::collections::HashSet;
+
+use crate::{
+    errors::AtomicResult, resources::PropVals, urls, values::SubResource, Resource, Storelike,
+    Value,
+};
+
+const XSD: &str = "http://www.w3.org/2001/XMLSchema#";
+const XSD_INTEGER: &str = "http://www.w3.org/2001/XMLSchema#integer";
+const XSD_DOUBLE: &str = "http://www.w3.org/2001/XMLSchema#double";
+const XSD_BOOLEAN: &str = "http://www.w3.org/2001/XMLSchema#boolean";
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const INDENT: &str = "    ";
+
+/// How ResourceArrays are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TurtleArrays {
+    /// Repeats the predicate for every item: `<s> <p> <a>, <b> .`.
+    /// Compact and easy to query, but the order of the items is lost, and empty arrays are omitted.
+    #[default]
+    RepeatedPredicates,
+    /// Writes an RDF Collection: `<s> <p> ( <a> <b> ) .`. Keeps the order of the items.
+    List,
+}
+
+/// Options for [resources_to_turtle].
+#[derive(Debug, Clone, Default)]
+pub struct TurtleOpts {
+    pub arrays: TurtleArrays,
+}
+
+/// The prefixes that can be used in Turtle documents for this store.
+/// Contains the common RDF vocabularies, the Atomic Data ontology and the Resources hosted by the store.
+pub fn turtle_prefixes(store: &impl Storelike) -> Vec<(String, String)> {
+    vec![
+        ("xsd".into(), XSD.into()),
+        ("rdf".into(), RDF.into()),
+        (
+            "properties".into(),
+            "https://atomicdata.dev/properties/".into(),
+        ),
+        ("classes".into(), "https://atomicdata.dev/classes/".into()),
+        (
+            "datatypes".into(),
+            "https://atomicdata.dev/datatypes/".into(),
+        ),
+        ("local".into(), format!("{}/", store.get_server_url())),
+    ]
+}
+
+/// Serializes Resources to Turtle.
+/// Only the prefixes that are actually used are declared.
+pub fn resources_to_turtle(
+    resources: &[Resource],
+    store: &impl Storelike,
+    opts: &TurtleOpts,
+) -> AtomicResult<String> {
+    let prefixes = turtle_prefixes(store);
+    let mut writer = TurtleWriter {
+        prefixes: &prefixes,
+        used: HashSet::new(),
+        arrays: opts.arrays,
+    };
+    let mut body = String::new();
+    for resource in resources {
+        let predicates = writer.predicates(resource.get_propvals(), 1);
+        // A subject without predicates is invalid, e.g. when all values are empty arrays
+        if predicates.is_empty() {
+            continue;
+        }
+        body.push('\n');
+        body.push_str(&writer.iri(resource.get_subject()));
+        body.push('\n');
+        body.push_str(&predicates);
+        body.push_str(" .\n");
+    }
+
+    let mut out = String::new();
+    for (i, (prefix, iri)) in prefixes.iter().enumerate() {
+        if writer.used.contains(&i) {
+            out.push_str(&format!("@prefix {}: <{}> .\n", prefix, escape_iri(iri)));
+        }
+    }
+    out.push_str(&body);
+    Ok(out)
+}
+
+struct TurtleWriter<'a> {
+    prefixes: &'a [(String, String)],
+    /// Indexes of the prefixes that have been used
+    used: HashSet<usize>,
+    arrays: TurtleArrays,
+}
+
+impl TurtleWriter<'_> {
+    /// Writes a prefixed name if possible, or a full IRI otherwise.
+    fn iri(&mut self, iri: &str) -> String {
+        for (i, (prefix, namespace)) in self.prefixes.iter().enumerate() {
+            if let Some(local) = iri.strip_prefix(namespace.as_str()) {
+                if is_safe_local_name(local) {
+                    self.used.insert(i);
+                    return format!("{}:{}", prefix, local);
+                }
+            }
+        }
+        format!("<{}>", escape_iri(iri))
+    }
+
+    fn literal(&mut self, value: &str, datatype: Option<&str>) -> String {
+        match datatype {
+            Some(datatype) => format!("\"{}\"^^{}", escape_literal(value), self.iri(datatype)),
+            None => format!("\"{}\"", escape_literal(value)),
+        }
+    }
+
+    /// Writes all predicate / object combinations, separated by `;`.
+    fn predicates(&mut self, propvals: &PropVals, depth: usize) -> String {
+        let mut props: Vec<&String> = propvals.keys().collect();
+        props.sort();
+        let mut lines = Vec::new();
+        for prop in props {
+            let objects = self.objects(&propvals[prop], depth);
+            if objects.is_empty() {
+                continue;
+            }
+            lines.push(format!(
+                "{}{} {}",
+                INDENT.repeat(depth),
+                self.iri(prop),
+                objects.join(", ")
+            ));
+        }
+        lines.join(" ;\n")
+    }
+
+    /// Returns the objects for a single Value. Only ResourceArrays can have multiple objects.
+    fn objects(&mut self, value: &Value, depth: usize) -> Vec<String> {
+        let single = match value {
+            Value::AtomicUrl(url) => self.iri(url),
+            Value::String(s) => self.literal(s, None),
+            Value::Markdown(s) => self.literal(s, Some(urls::MARKDOWN)),
+            Value::Slug(s) => self.literal(s, Some(urls::SLUG)),
+            Value::Date(s) => self.literal(s, Some(urls::DATE)),
+            Value::Integer(i) => self.literal(&i.to_string(), Some(XSD_INTEGER)),
+            Value::Float(f) => self.literal(&f.to_string(), Some(XSD_DOUBLE)),
+            Value::Boolean(b) => self.literal(&b.to_string(), Some(XSD_BOOLEAN)),
+            Value::Timestamp(t) => self.literal(&t.to_string(), Some(urls::TIMESTAMP)),
+            Value::Unsupported(u) => self.literal(&u.value, Some(u.datatype.as_str())),
+            Value::NestedResource(sub) => self.sub_resource(sub, depth),
+            Value::Resource(r) => self.iri(r.get_subject()),
+            Value::ResourceArray(items) => {
+                let objects: Vec<String> = items
+                    .iter()
+                    .map(|item| self.sub_resource(item, depth))
+                    .collect();
+                return match self.arrays {
+                    TurtleArrays::RepeatedPredicates => objects,
+                    TurtleArrays::List if objects.is_empty() => vec!["()".into()],
+                    TurtleArrays::List => vec![format!("( {} )", objects.join(" "))],
+                };
+            }
+        };
+        vec![single]
+    }
+
+    fn sub_resource(&mut self, sub: &SubResource, depth: usize) -> String {
+        match sub {
+            SubResource::Subject(s) => self.iri(s),
+            SubResource::Resource(r) => self.iri(r.get_subject()),
+            // Anonymous nested resources become blank nodes
+            SubResource::Nested(propvals) => match self.predicates(propvals, depth + 1) {
+                predicates if predicates.is_empty() => "[]".into(),
+                predicates => format!("[\n{}\n{}]", predicates, INDENT.repeat(depth)),
+            },
+        }
+    }
+}
+
+/// Only allows a conservative subset of Turtle's `PN_LOCAL`, so we never need escapes in prefixed names.
+fn is_safe_local_name(local: &str) -> bool {
+    let mut chars = local.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphanumeric() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Escapes characters that are not allowed in an `IRIREF`.
+fn escape_iri(iri: &str) -> String {
+    let mut out = String::with_capacity(iri.len());
+    for c in iri.chars() {
+        match c {
+            '<' | '>' | '"' | '{' | '}' | '|' | '^' | '`' | '\\' => {
+                out.push_str(&format!("\\u{:04X}", c as u32))
+            }
+            c if (c as u32) <= 0x20 => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escapes a string for use in a double quoted literal.
+fn escape_literal(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Store;
+    use rio_api::model::{Literal, Subject, Term};
+    use rio_api::parser::TriplesParser;
+    use std::collections::HashMap;
+
+    /// A parsed triple: subject, predicate, and the object as (lexical value, is_iri)
+    type ParsedTriple = (String, String, (String, bool));
+
+    fn subject_string(subject: Subject) -> String {
+        match subject {
+            Subject::NamedNode(n) => n.iri.to_string(),
+            Subject::BlankNode(b) => format!("_:{}", b.id),
+            Subject::Triple(_) => panic!("RDF-star is not used"),
+        }
+    }
+
+    fn object_tuple(object: Term) -> (String, bool) {
+        match object {
+            Term::NamedNode(n) => (n.iri.to_string(), true),
+            Term::BlankNode(b) => (format!("_:{}", b.id), false),
+            Term::Literal(Literal::Simple { value }) => (value.to_string(), false),
+            Term::Literal(Literal::Typed { value, .. }) => (value.to_string(), false),
+            Term::Literal(Literal::LanguageTaggedString { value, .. }) => {
+                (value.to_string(), false)
+            }
+            Term::Triple(_) => panic!("RDF-star is not used"),
+        }
+    }
+
+    fn parse_turtle(turtle: &str) -> Vec<ParsedTriple> {
+        let mut triples = Vec::new();
+        rio_turtle::TurtleParser::new(turtle.as_bytes(), None)
+            .parse_all(&mut |t| -> Result<(), rio_turtle::TurtleError> {
+                triples.push((
+                    subject_string(t.subject),
+                    t.predicate.iri.to_string(),
+                    object_tuple(t.object),
+                ));
+                Ok(())
+            })
+            .unwrap_or_else(|e| panic!("Invalid turtle: {}\n{}", e, turtle));
+        triples
+    }
+
+    fn parse_ntriples(ntriples: &str) -> Vec<ParsedTriple> {
+        let mut triples = Vec::new();
+        rio_turtle::NTriplesParser::new(ntriples.as_bytes())
+            .parse_all(&mut |t| -> Result<(), rio_turtle::TurtleError> {
+                triples.push((
+                    subject_string(t.subject),
+                    t.predicate.iri.to_string(),
+                    object_tuple(t.object),
+                ));
+                Ok(())
+            })
+            .unwrap();
+        triples
+    }
+
+    fn test_resource(store: &Store) -> Resource {
+        let mut resource = Resource::new(format!("{}/turtle-test", store.get_server_url()));
+        resource
+            .set_propval_string(
+                urls::DESCRIPTION.into(),
+                "Has \"quotes\", a \\ backslash\nand a newline",
+                store,
+            )
+            .unwrap();
+        resource
+            .set_propval_string(urls::SHORTNAME.into(), "turtle-test", store)
+            .unwrap();
+        resource
+            .set_propval(urls::CREATED_AT.into(), Value::Timestamp(1234), store)
+            .unwrap();
+        resource
+            .set_propval(
+                urls::CHILDREN.into(),
+                vec![
+                    "https://example.com/c",
+                    "https://example.com/a",
+                    "https://example.com/b/nested",
+                ]
+                .into(),
+                store,
+            )
+            .unwrap();
+        resource
+            .set_propval(urls::FILESIZE.into(), Value::Integer(5), store)
+            .unwrap();
+        resource
+            .set_propval(urls::IS_LOCKED.into(), Value::Boolean(true), store)
+            .unwrap();
+        resource
+    }
+
+    #[test]
+    fn uses_prefixes_and_typed_literals() {
+        let store = Store::init().unwrap();
+        store.populate().unwrap();
+        let turtle =
+            resources_to_turtle(&[test_resource(&store)], &store, &TurtleOpts::default()).unwrap();
+        assert!(turtle.starts_with("@prefix"));
+        assert!(turtle.contains("\"5\"^^xsd:integer"));
+        assert!(turtle.contains("\"true\"^^xsd:boolean"));
+        assert!(turtle.contains("properties:description"));
+        assert!(turtle.contains("local:turtle-test"));
+        assert!(
+            !turtle.contains("@prefix classes:"),
+            "unused prefixes should not be declared"
+        );
+    }
+
+    #[test]
+    fn round_trip_matches_ntriples() {
+        let store = Store::init().unwrap();
+        store.populate().unwrap();
+        let resource = test_resource(&store);
+
+        let turtle = resources_to_turtle(
+            &[resource.clone()],
+            &store,
+            &TurtleOpts {
+                arrays: TurtleArrays::RepeatedPredicates,
+            },
+        )
+        .unwrap();
+        let from_turtle: HashSet<ParsedTriple> = parse_turtle(&turtle).into_iter().collect();
+
+        // N-Triples serializes ResourceArrays as JSON literals, so we compare those separately
+        let mut non_array = resource.clone();
+        non_array.remove_propval(urls::CHILDREN);
+        let ntriples = crate::serialize::atoms_to_ntriples(non_array.to_atoms(), &store).unwrap();
+        for triple in parse_ntriples(&ntriples) {
+            assert!(
+                from_turtle.contains(&triple),
+                "Missing {:?} in turtle:\n{}",
+                triple,
+                turtle
+            );
+        }
+
+        let children = resource
+            .get(urls::CHILDREN)
+            .unwrap()
+            .to_subjects(None)
+            .unwrap();
+        for child in &children {
+            assert!(from_turtle.contains(&(
+                resource.get_subject().clone(),
+                urls::CHILDREN.into(),
+                (child.clone(), true)
+            )));
+        }
+        assert_eq!(
+            from_turtle.len(),
+            resource.get_propvals().len() - 1 + children.len()
+        );
+    }
+
+    #[test]
+    fn lists_keep_order() {
+        let store = Store::init().unwrap();
+        store.populate().unwrap();
+        let resource = test_resource(&store);
+        let turtle = resources_to_turtle(
+            &[resource.clone()],
+            &store,
+            &TurtleOpts {
+                arrays: TurtleArrays::List,
+            },
+        )
+        .unwrap();
+        let triples = parse_turtle(&turtle);
+
+        // Walk the RDF Collection
+        let by_subject: HashMap<(String, String), (String, bool)> = triples
+            .iter()
+            .map(|(s, p, o)| ((s.clone(), p.clone()), o.clone()))
+            .collect();
+        let mut node = by_subject[&(resource.get_subject().clone(), urls::CHILDREN.into())]
+            .0
+            .clone();
+        let mut items = Vec::new();
+        while node != format!("{RDF}nil") {
+            items.push(by_subject[&(node.clone(), format!("{RDF}first"))].0.clone());
+            node = by_subject[&(node.clone(), format!("{RDF}rest"))].0.clone();
+        }
+        assert_eq!(
+            items,
+            resource
+                .get(urls::CHILDREN)
+                .unwrap()
+                .to_subjects(None)
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn skips_empty_arrays() {
+        let store = Store::init().unwrap();
+        store.populate().unwrap();
+        let mut empty = Resource::new(format!("{}/only-empty-arrays", store.get_server_url()));
+        empty.set_propval_unsafe(urls::CHILDREN.into(), Vec::<String>::new().into());
+        let mut nested = PropVals::new();
+        nested.insert(urls::ATTACHMENTS.into(), Vec::<String>::new().into());
+        let mut parent = Resource::new(format!("{}/empty-nested", store.get_server_url()));
+        parent.set_propval_unsafe(
+            urls::SUBRESOURCES.into(),
+            Value::ResourceArray(vec![SubResource::Nested(nested)]),
+        );
+
+        let turtle =
+            resources_to_turtle(&[empty, parent.clone()], &store, &TurtleOpts::default()).unwrap();
+        assert!(!turtle.contains("only-empty-arrays"), "{}", turtle);
+        let triples = parse_turtle(&turtle);
+        assert_eq!(triples.len(), 1, "{}", turtle);
+        assert_eq!(&triples[0].0, parent.get_subject());
+    }
+}
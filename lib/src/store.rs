@@ -1,18 +1,39 @@
 //! In-memory store of Atomic data.
 //! This provides many methods for finding, changing, serializing and parsing Atomic Data.
 
+use crate::commit::SubjectLocks;
 use crate::storelike::QueryResult;
 use crate::Value;
 use crate::{atoms::Atom, storelike::Storelike};
 use crate::{errors::AtomicResult, Resource};
 use std::{collections::HashMap, sync::Arc, sync::Mutex};
 
+// Uses bincode, which is part of the `db` feature
+#[cfg(feature = "db")]
+mod binary_snapshot;
+#[cfg(feature = "db")]
+mod wal;
+
+#[cfg(feature = "db")]
+pub use wal::WalSync;
+
+/// The Resources of a [Store]. Both the map and its items are reference counted,
+/// so taking a [Store::snapshot] is cheap and writes only copy the map's pointers.
+type ResourceMap = Arc<HashMap<String, Arc<Resource>>>;
+
 /// The in-memory store of data, containing the Resources, Properties and Classes
 #[derive(Clone)]
 pub struct Store {
     // The store currently holds two stores - that is not ideal
-    hashmap: Arc<Mutex<HashMap<String, Resource>>>,
+    hashmap: Arc<Mutex<ResourceMap>>,
     default_agent: Arc<Mutex<Option<crate::agents::Agent>>>,
+    /// Snapshots can't be written to.
+    read_only: bool,
+    /// Subjects that Commits are being applied to, see [SubjectLocks].
+    subject_locks: SubjectLocks,
+    /// Set for durable Stores, see [Store::open_durable].
+    #[cfg(feature = "db")]
+    wal: Option<Arc<wal::Wal>>,
 }
 
 impl Store {
@@ -20,13 +41,75 @@ impl Store {
     /// Run `.populate()` to get useful standard models loaded into your store.
     pub fn init() -> AtomicResult<Store> {
         let store = Store {
-            hashmap: Arc::new(Mutex::new(HashMap::new())),
+            hashmap: Arc::new(Mutex::new(Arc::new(HashMap::new()))),
             default_agent: Arc::new(Mutex::new(None)),
+            read_only: false,
+            subject_locks: SubjectLocks::new(),
+            #[cfg(feature = "db")]
+            wal: None,
         };
         crate::populate::populate_base_models(&store)?;
         Ok(store)
     }
 
+    /// Returns a read-only view of the Store as it is right now.
+    /// Writes to the original Store are not visible in the snapshot.
+    /// Cheap to take: the Resources are shared until they are changed.
+    pub fn snapshot(&self) -> Store {
+        Store {
+            hashmap: Arc::new(Mutex::new(self.hashmap.lock().unwrap().clone())),
+            default_agent: self.default_agent.clone(),
+            read_only: true,
+            subject_locks: SubjectLocks::new(),
+            #[cfg(feature = "db")]
+            wal: None,
+        }
+    }
+
+    /// Counts the Resources and Atoms in the Store, and estimates how much memory they use.
+    pub fn stats(&self) -> StoreStats {
+        let map = self.hashmap.lock().unwrap();
+        let mut stats = StoreStats {
+            capacity: map.capacity(),
+            ..Default::default()
+        };
+        for (subject, resource) in map.iter() {
+            stats.resources += 1;
+            stats.atoms += resource.get_propvals().len();
+            stats.estimated_bytes += subject.len() + estimate_size(resource);
+        }
+        stats
+    }
+
+    /// Rebuilds the map of Resources from the live data, so memory left behind by removed and overwritten Resources is freed.
+    /// Resources that are shared with a [Store::snapshot] stay alive until the snapshot is dropped.
+    pub fn compact(&self) -> AtomicResult<CompactionStats> {
+        self.check_writable()?;
+        let before = self.stats();
+        {
+            let mut map = self.hashmap.lock().unwrap();
+            let mut compacted: HashMap<String, Arc<Resource>> = HashMap::with_capacity(map.len());
+            for (subject, resource) in map.iter() {
+                let mut propvals = resource.as_ref().clone().into_propvals();
+                propvals.shrink_to_fit();
+                let resource = Resource::from_propvals(propvals, subject.clone());
+                compacted.insert(subject.clone(), Arc::new(resource));
+            }
+            *map = Arc::new(compacted);
+        }
+        let after = self.stats();
+        Ok(CompactionStats { before, after })
+    }
+
+    fn check_writable(&self) -> AtomicResult<()> {
+        if self.read_only {
+            return Err(crate::AtomicError::method_not_allowed(
+                "This Store is a read-only snapshot",
+            ));
+        }
+        Ok(())
+    }
+
     /// Triple Pattern Fragments interface.
     /// Use this for most queries, e.g. finding all items with some property / value combination.
     /// Returns an empty array if nothing is found.
@@ -102,6 +185,33 @@ impl Store {
     }
 }
 
+/// Size of a [Store], see [Store::stats].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StoreStats {
+    pub resources: usize,
+    pub atoms: usize,
+    /// Rough estimate of the memory used by the Resources, excluding unused capacity
+    pub estimated_bytes: usize,
+    /// How many Resources fit in the map before it grows
+    pub capacity: usize,
+}
+
+/// Returned by [Store::compact].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionStats {
+    pub before: StoreStats,
+    pub after: StoreStats,
+}
+
+fn estimate_size(resource: &Resource) -> usize {
+    let propvals = resource.get_propvals();
+    let mut size = std::mem::size_of::<Resource>();
+    for (property, value) in propvals.iter() {
+        size += property.len() + std::mem::size_of::<Value>() + value.to_string().len();
+    }
+    size
+}
+
 impl Storelike for Store {
     fn add_atoms(&self, atoms: Vec<Atom>) -> AtomicResult<()> {
         // Start with a nested HashMap, containing only strings.
@@ -133,6 +243,7 @@ impl Storelike for Store {
         update_index: bool,
         overwrite_existing: bool,
     ) -> AtomicResult<()> {
+        self.check_writable()?;
         if check_required_props {
             resource.check_required_props(self)?;
         }
@@ -144,16 +255,16 @@ impl Storelike for Store {
         }
         let _ = update_index;
         // This store has no index, so we don't need to update it.
-        self.hashmap
-            .lock()
-            .unwrap()
-            .insert(resource.get_subject().into(), resource.clone());
+        let mut map = self.hashmap.lock().unwrap();
+        Arc::make_mut(&mut map).insert(resource.get_subject().into(), Arc::new(resource.clone()));
         Ok(())
     }
 
     // TODO: Fix this for local stores, include external does not make sense here
     fn all_resources(&self, _include_external: bool) -> Box<dyn Iterator<Item = Resource>> {
-        Box::new(self.hashmap.lock().unwrap().clone().into_values())
+        let map = self.hashmap.lock().unwrap().clone();
+        let resources: Vec<Resource> = map.values().map(|r| r.as_ref().clone()).collect();
+        Box::new(resources.into_iter())
     }
 
     fn get_server_url(&self) -> &str {
@@ -173,17 +284,21 @@ impl Storelike for Store {
         }
     }
 
+    fn get_subject_locks(&self) -> Option<&SubjectLocks> {
+        Some(&self.subject_locks)
+    }
+
     fn get_resource(&self, subject: &str) -> AtomicResult<Resource> {
         if let Some(resource) = self.hashmap.lock().unwrap().get(subject) {
-            return Ok(resource.clone());
+            return Ok(resource.as_ref().clone());
         }
         self.handle_not_found(subject, "Not found in HashMap.".into())
     }
 
     fn remove_resource(&self, subject: &str) -> AtomicResult<()> {
-        self.hashmap
-            .lock()
-            .unwrap()
+        self.check_writable()?;
+        let mut map = self.hashmap.lock().unwrap();
+        Arc::make_mut(&mut map)
             .remove_entry(subject)
             .ok_or(format!(
                 "Resource {} could not be deleted, because it is not found",
@@ -228,6 +343,7 @@ impl Storelike for Store {
                 Err(e) => match &e.error_type {
                     crate::AtomicErrorType::NotFoundError => {}
                     crate::AtomicErrorType::UnauthorizedError => {}
+                    crate::AtomicErrorType::Gone => {}
                     _other => {
                         return Err(
                             format!("Error when getting resource in collection: {}", e).into()
@@ -368,6 +484,40 @@ mod test {
         store.get_resource(urls::CLASS).unwrap();
     }
 
+    #[test]
+    fn compact_frees_removed_resources() {
+        let store = init_store();
+        for i in 0..500 {
+            let mut resource = Resource::new(format!("local:store/temp/{}", i));
+            resource.set_propval_unsafe(urls::DESCRIPTION.into(), Value::String("temp".into()));
+            store.add_resource(&resource).unwrap();
+        }
+        for i in 0..500 {
+            if i % 10 != 0 {
+                store
+                    .remove_resource(&format!("local:store/temp/{}", i))
+                    .unwrap();
+            }
+        }
+
+        let fresh = Store::init().unwrap();
+        for resource in store.all_resources(true) {
+            fresh
+                .add_resource_opts(&resource, false, false, true)
+                .unwrap();
+        }
+
+        let stats = store.compact().unwrap();
+        let fresh_stats = fresh.stats();
+        assert_eq!(stats.after.resources, fresh_stats.resources);
+        assert_eq!(stats.after.atoms, fresh_stats.atoms);
+        assert_eq!(stats.after.estimated_bytes, fresh_stats.estimated_bytes);
+        assert!(stats.after.capacity < stats.before.capacity);
+        assert!(stats.after.capacity <= fresh_stats.capacity);
+        store.get_resource("local:store/temp/10").unwrap();
+        assert!(store.snapshot().compact().is_err());
+    }
+
     #[test]
     #[should_panic]
     fn path_fail() {
@@ -0,0 +1,183 @@
+//! Binary snapshots of a [Store], which load much faster than parsing JSON-AD.
+//! A snapshot starts with a header containing a magic string, a format version, a checksum of the payload
+//! and the sequence number of the last [super::wal] entry that it contains.
+//! The payload is a [bincode] serialized list of subjects and their [PropVals].
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    commit::SubjectLocks, errors::AtomicResult, parse::ParseOpts, resources::PropVals, Resource,
+    Storelike,
+};
+
+use super::Store;
+
+const MAGIC: &[u8; 8] = b"ATOMSNAP";
+/// Increase this when the serialization of Resources or Values changes.
+const FORMAT_VERSION: u32 = 2;
+const HEADER_LEN: usize = MAGIC.len() + 4 + 8 + 8;
+
+impl Store {
+    /// Writes all Resources to a binary snapshot at `path`.
+    /// The file is written next to the target first and then moved, so an interrupted write never leaves a broken snapshot.
+    /// For durable Stores, the snapshot remembers which write-ahead log entries it contains, see [Store::checkpoint].
+    pub fn write_snapshot(&self, path: &Path) -> AtomicResult<()> {
+        match &self.wal {
+            // Holding the log prevents Commits between reading the sequence and the Resources
+            Some(wal) => wal.with_state(|state| self.write_snapshot_at(path, state.sequence)),
+            None => self.write_snapshot_at(path, 0),
+        }
+    }
+
+    pub(super) fn write_snapshot_at(&self, path: &Path, sequence: u64) -> AtomicResult<()> {
+        let payload = {
+            let map = self.hashmap.lock().unwrap();
+            let entries: Vec<(&String, &PropVals)> = map
+                .iter()
+                .map(|(subject, resource)| (subject, resource.get_propvals()))
+                .collect();
+            bincode::serialize(&entries)?
+        };
+        let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&checksum(&payload).to_le_bytes());
+        bytes.extend_from_slice(&sequence.to_le_bytes());
+        bytes.extend_from_slice(&payload);
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp_path)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Loads a Store from a snapshot created by [Store::write_snapshot].
+    /// Errors if the file is missing, was written by an incompatible version, or its checksum does not match.
+    pub fn read_snapshot(path: &Path) -> AtomicResult<Store> {
+        Ok(Store::read_snapshot_with_sequence(path)?.0)
+    }
+
+    /// Also returns the sequence number of the last write-ahead log entry in the snapshot.
+    pub(super) fn read_snapshot_with_sequence(path: &Path) -> AtomicResult<(Store, u64)> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| format!("Unable to read snapshot {}: {}", path.display(), e))?;
+        if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(format!("{} is not a Store snapshot", path.display()).into());
+        }
+        let (header, payload) = bytes.split_at(HEADER_LEN);
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(format!(
+                "Snapshot {} has format version {}, expected {}",
+                path.display(),
+                version,
+                FORMAT_VERSION
+            )
+            .into());
+        }
+        let expected = u64::from_le_bytes(header[12..20].try_into().unwrap());
+        let sequence = u64::from_le_bytes(header[20..28].try_into().unwrap());
+        if checksum(payload) != expected {
+            return Err(
+                format!("Snapshot {} is corrupt, checksum mismatch", path.display()).into(),
+            );
+        }
+        let entries: Vec<(String, PropVals)> = bincode::deserialize(payload)?;
+        let map: HashMap<String, Arc<Resource>> = entries
+            .into_iter()
+            .map(|(subject, propvals)| {
+                let resource = Resource::from_propvals(propvals, subject.clone());
+                (subject, Arc::new(resource))
+            })
+            .collect();
+        let store = Store {
+            hashmap: Arc::new(Mutex::new(Arc::new(map))),
+            default_agent: Arc::new(Mutex::new(None)),
+            read_only: false,
+            subject_locks: SubjectLocks::new(),
+            wal: None,
+        };
+        Ok((store, sequence))
+    }
+
+    /// Loads the Store from the snapshot, or from the JSON-AD file if the snapshot can't be used.
+    /// After loading the JSON-AD, a new snapshot is written, so the next start is fast again.
+    pub fn load_with_snapshot(snapshot_path: &Path, json_ad_path: &Path) -> AtomicResult<Store> {
+        match Store::read_snapshot(snapshot_path) {
+            Ok(store) => return Ok(store),
+            Err(e) => tracing::warn!("Loading from {} instead. {}", json_ad_path.display(), e),
+        }
+        let json = std::fs::read_to_string(json_ad_path)
+            .map_err(|e| format!("Unable to read {}: {}", json_ad_path.display(), e))?;
+        let store = Store::init()?;
+        // The export can use Properties before it defines them, so the defaults have to be known already
+        store.populate()?;
+        store.import(&json, &ParseOpts::default())?;
+        if let Err(e) = store.write_snapshot(snapshot_path) {
+            tracing::warn!(
+                "Unable to write snapshot {}: {}",
+                snapshot_path.display(),
+                e
+            );
+        }
+        Ok(store)
+    }
+}
+
+/// FNV-1a, which is fast and good enough to detect truncated or damaged files.
+pub(super) fn checksum(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::urls;
+
+    #[test]
+    fn corrupt_snapshot_falls_back_to_json_ad() {
+        let dir = Path::new(".temp/store_snapshot");
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let snapshot_path = dir.join("store.snapshot");
+        let json_ad_path = dir.join("store.json");
+
+        let store = Store::init().unwrap();
+        store.populate().unwrap();
+        std::fs::write(&json_ad_path, store.export(true).unwrap()).unwrap();
+        store.write_snapshot(&snapshot_path).unwrap();
+
+        let loaded = Store::read_snapshot(&snapshot_path).unwrap();
+        assert_eq!(loaded.stats().resources, store.stats().resources);
+        loaded.get_property(urls::DESCRIPTION).unwrap();
+
+        let mut bytes = std::fs::read(&snapshot_path).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+        std::fs::write(&snapshot_path, bytes).unwrap();
+        let Err(err) = Store::read_snapshot(&snapshot_path) else {
+            panic!("A corrupt snapshot should not be loaded");
+        };
+        assert!(err.message.contains("checksum"), "{}", err);
+
+        let recovered = Store::load_with_snapshot(&snapshot_path, &json_ad_path).unwrap();
+        recovered.get_property(urls::DESCRIPTION).unwrap();
+        // The fallback replaced the corrupt snapshot
+        Store::read_snapshot(&snapshot_path).unwrap();
+    }
+}
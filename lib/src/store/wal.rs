@@ -0,0 +1,323 @@
+//! Write-ahead log for the in-memory [Store], so Commits survive a crash between two snapshots.
+//! Every applied Commit is appended as a single line: `{sequence} {checksum} {commit as JSON-AD}`.
+//! Snapshots record the sequence of the last entry they contain, so opening a Store replays only the newer entries.
+//! [Store::checkpoint] writes a snapshot and then empties the log.
+//! Lines that are cut off or damaged, e.g. by a crash halfway through a write, fail the checksum and are skipped with a warning.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    commit::{CommitOpts, CommitResponse},
+    errors::AtomicResult,
+    parse::parse_json_ad_commit_resource,
+    serialize::propvals_to_json_ad_map,
+    Commit,
+};
+
+use super::{binary_snapshot::checksum, Store};
+
+/// When entries of the write-ahead log are flushed to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WalSync {
+    /// Before [Store::apply_commit] returns. No acknowledged Commit is ever lost.
+    #[default]
+    Always,
+    /// At most once per interval. Commits applied since the last sync can be lost when the machine crashes, but not when only the process does.
+    Interval(Duration),
+    /// Leaves flushing to the operating system.
+    Never,
+}
+
+pub(super) struct Wal {
+    path: PathBuf,
+    snapshot_path: PathBuf,
+    sync: WalSync,
+    state: Mutex<WalState>,
+}
+
+pub(super) struct WalState {
+    file: File,
+    /// Sequence of the last entry that was written
+    pub(super) sequence: u64,
+    last_sync: Instant,
+}
+
+impl Wal {
+    /// Runs `f` while no entries can be written.
+    pub(super) fn with_state<T>(&self, f: impl FnOnce(&mut WalState) -> T) -> T {
+        f(&mut self.state.lock().unwrap())
+    }
+
+    fn append(&self, state: &mut WalState, commit_json: &str) -> AtomicResult<()> {
+        let sequence = state.sequence + 1;
+        let line = format!(
+            "{} {:016x} {}\n",
+            sequence,
+            entry_checksum(sequence, commit_json),
+            commit_json
+        );
+        state.file.write_all(line.as_bytes())?;
+        let sync = match self.sync {
+            WalSync::Always => true,
+            WalSync::Interval(interval) => state.last_sync.elapsed() >= interval,
+            WalSync::Never => false,
+        };
+        if sync {
+            state.file.sync_data()?;
+            state.last_sync = Instant::now();
+        }
+        state.sequence = sequence;
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for Wal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Wal")
+            .field("path", &self.path)
+            .field("sync", &self.sync)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Store {
+    /// Loads the Store from the snapshot at `snapshot_path`, or starts empty if there is none,
+    /// and then applies the Commits in the write-ahead log at `wal_path` that are newer than the snapshot.
+    /// Commits applied using [Store::apply_commit] are appended to the log from then on.
+    /// Other changes, like [crate::Storelike::add_resource], are not logged, and are only saved by the next [Store::checkpoint].
+    pub fn open_durable(
+        snapshot_path: &Path,
+        wal_path: &Path,
+        sync: WalSync,
+    ) -> AtomicResult<Store> {
+        let (mut store, snapshot_sequence) = if snapshot_path.exists() {
+            Store::read_snapshot_with_sequence(snapshot_path)?
+        } else {
+            (Store::init()?, 0)
+        };
+
+        let contents = match std::fs::read(wal_path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(format!("Unable to read {}: {}", wal_path.display(), e).into());
+            }
+        };
+        let mut sequence = snapshot_sequence;
+        let mut replayed = 0;
+        // Only complete lines count, a missing newline means the last write was interrupted
+        let complete = contents
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        if complete < contents.len() {
+            tracing::warn!(
+                "Skipping an incomplete entry at the end of {}",
+                wal_path.display()
+            );
+        }
+        for (number, line) in contents[..complete]
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .enumerate()
+        {
+            let Some((entry_sequence, commit_json)) = parse_entry(line) else {
+                tracing::warn!(
+                    "Skipping damaged entry on line {} of {}",
+                    number + 1,
+                    wal_path.display()
+                );
+                continue;
+            };
+            if entry_sequence <= sequence {
+                continue;
+            }
+            let resource = parse_json_ad_commit_resource(commit_json, &store)?;
+            Commit::from_resource(resource)?.apply_unsafe(&store)?;
+            sequence = entry_sequence;
+            replayed += 1;
+        }
+        if replayed > 0 {
+            tracing::info!("Replayed {} Commits from {}", replayed, wal_path.display());
+        }
+
+        if let Some(dir) = wal_path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(false)
+            .open(wal_path)?;
+        // Removes an incomplete last entry, so new entries don't get appended to it
+        file.set_len(complete as u64)?;
+        file.seek(SeekFrom::End(0))?;
+
+        store.wal = Some(Arc::new(Wal {
+            path: wal_path.into(),
+            snapshot_path: snapshot_path.into(),
+            sync,
+            state: Mutex::new(WalState {
+                file,
+                sequence,
+                last_sync: Instant::now(),
+            }),
+        }));
+        Ok(store)
+    }
+
+    /// Applies the Commit, and for durable Stores appends it to the write-ahead log before returning.
+    /// Commits are applied one at a time, so the order in the log is the order in which they were applied.
+    pub fn apply_commit(&self, commit: &Commit, opts: &CommitOpts) -> AtomicResult<CommitResponse> {
+        let Some(wal) = &self.wal else {
+            return commit.apply_opts(self, opts);
+        };
+        wal.with_state(|state| {
+            let response = commit.apply_opts(self, opts)?;
+            let commit_resource = response.commit_resource.clone();
+            // Without an `@id`, parsing doesn't save the Commit before it is replayed.
+            // Its subject is derived from the signature.
+            let json = serde_json::to_string(&propvals_to_json_ad_map(
+                commit_resource.get_propvals(),
+                None,
+            )?)?;
+            wal.append(state, &json)?;
+            Ok(response)
+        })
+    }
+
+    /// Writes a snapshot of a durable Store and empties its write-ahead log.
+    pub fn checkpoint(&self) -> AtomicResult<()> {
+        let wal = self
+            .wal
+            .as_ref()
+            .ok_or("Only Stores opened with open_durable have a write-ahead log")?;
+        wal.with_state(|state| {
+            self.write_snapshot_at(&wal.snapshot_path, state.sequence)?;
+            state.file.set_len(0)?;
+            state.file.seek(SeekFrom::Start(0))?;
+            state.file.sync_all()?;
+            Ok(())
+        })
+    }
+}
+
+fn entry_checksum(sequence: u64, commit_json: &str) -> u64 {
+    checksum(format!("{} {}", sequence, commit_json).as_bytes())
+}
+
+/// Returns the sequence and the Commit of a line, if its checksum matches.
+fn parse_entry(line: &[u8]) -> Option<(u64, &str)> {
+    let line = std::str::from_utf8(line).ok()?;
+    let (sequence, rest) = line.split_once(' ')?;
+    let (expected, commit_json) = rest.split_once(' ')?;
+    let sequence: u64 = sequence.parse().ok()?;
+    let expected = u64::from_str_radix(expected, 16).ok()?;
+    (entry_checksum(sequence, commit_json) == expected).then_some((sequence, commit_json))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{commit::CommitBuilder, urls, Resource, Storelike, Value};
+
+    fn set_description(store: &Store, subject: &str, description: &str) -> Commit {
+        let agent = store.get_default_agent().unwrap();
+        let resource = store
+            .get_resource(subject)
+            .unwrap_or_else(|_| Resource::new(subject.into()));
+        let mut builder = CommitBuilder::new(subject.into());
+        builder.set(
+            urls::DESCRIPTION.into(),
+            Value::Markdown(description.into()),
+        );
+        builder.sign(&agent, store, &resource).unwrap()
+    }
+
+    fn description(store: &Store, subject: &str) -> String {
+        store
+            .get_resource(subject)
+            .unwrap()
+            .get(urls::DESCRIPTION)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn acknowledged_commits_survive_a_crash() {
+        let dir = Path::new(".temp/store_wal");
+        let _ = std::fs::remove_dir_all(dir);
+        let snapshot_path = dir.join("store.snapshot");
+        let wal_path = dir.join("store.wal");
+        let opts = CommitOpts {
+            validate_schema: true,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_rights: false,
+            validate_previous_commit: true,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+
+        let store = Store::open_durable(&snapshot_path, &wal_path, WalSync::Always).unwrap();
+        store.populate().unwrap();
+        let agent = store.create_agent(Some("wal")).unwrap();
+        store.set_default_agent(agent);
+        store.checkpoint().unwrap();
+        let first = "https://localhost/first";
+        let second = "https://localhost/second";
+        store
+            .apply_commit(&set_description(&store, first, "one"), &opts)
+            .unwrap();
+        store.checkpoint().unwrap();
+        store
+            .apply_commit(&set_description(&store, first, "two"), &opts)
+            .unwrap();
+        store
+            .apply_commit(&set_description(&store, second, "three"), &opts)
+            .unwrap();
+        // Crash between writing the log and the next snapshot, halfway through writing another entry
+        drop(store);
+        let mut file = OpenOptions::new().append(true).open(&wal_path).unwrap();
+        file.write_all(b"4 0123456789abcdef {\"https://atomicdata.dev/pro")
+            .unwrap();
+        drop(file);
+
+        let recovered = Store::open_durable(&snapshot_path, &wal_path, WalSync::Always).unwrap();
+        assert_eq!(description(&recovered, first), "two");
+        assert_eq!(description(&recovered, second), "three");
+
+        // The incomplete entry is gone, so new entries can be read again
+        let agent = recovered.create_agent(Some("wal_again")).unwrap();
+        recovered.set_default_agent(agent);
+        recovered
+            .apply_commit(&set_description(&recovered, second, "four"), &opts)
+            .unwrap();
+        drop(recovered);
+        let recovered = Store::open_durable(&snapshot_path, &wal_path, WalSync::Always).unwrap();
+        assert_eq!(description(&recovered, second), "four");
+
+        recovered.checkpoint().unwrap();
+        assert_eq!(std::fs::metadata(&wal_path).unwrap().len(), 0);
+        let reopened = Store::open_durable(&snapshot_path, &wal_path, WalSync::Always).unwrap();
+        assert_eq!(description(&reopened, first), "two");
+    }
+
+    #[test]
+    fn damaged_entries_are_skipped() {
+        let line = b"1 0000000000000000 {}";
+        assert!(parse_entry(line).is_none());
+        let json = "{\"a\":1}";
+        let valid = format!("7 {:016x} {}", entry_checksum(7, json), json);
+        assert_eq!(parse_entry(valid.as_bytes()), Some((7, json)));
+    }
+}
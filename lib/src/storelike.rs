@@ -67,7 +67,7 @@ pub trait Storelike: Sized {
     fn build_index(&self, include_external: bool) -> AtomicResult<()> {
         tracing::info!("Building index (this could take a few minutes for larger databases)");
         for r in self.all_resources(include_external) {
-            for atom in r.to_atoms() {
+            for atom in r.to_index_atoms() {
                 self.add_atom_to_index(&atom, &r)
                     .map_err(|e| format!("Failed to add atom to index {}. {}", atom, e))?;
             }
@@ -194,6 +194,41 @@ pub trait Storelike: Sized {
         Ok(resource)
     }
 
+    /// Parent chains that are longer than this are treated as broken, see [crate::hierarchy::ParentWalk].
+    fn get_max_hierarchy_depth(&self) -> usize {
+        crate::hierarchy::DEFAULT_MAX_DEPTH
+    }
+
+    /// Returns the advisory locks of this store, if it supports them.
+    /// Commits by other Agents to locked Resources are refused.
+    fn get_locks(&self) -> Option<&crate::locks::LockRegistry> {
+        None
+    }
+
+    /// Returns the counters and timers of this store, if it keeps them. See [crate::metrics].
+    fn get_metrics(&self) -> Option<&crate::metrics::StoreMetrics> {
+        None
+    }
+
+    /// Returns the guard for requests to other servers, if this store runs on a server. See [crate::outbound].
+    /// Stores without one, such as the in-memory store of a client, send requests directly.
+    #[cfg(feature = "client")]
+    fn get_outbound(&self) -> Option<crate::outbound::OutboundHttp> {
+        None
+    }
+
+    /// Returns the subjects that were recently generated, but may not have been saved yet.
+    /// Used by [crate::subjects] to prevent handing out the same subject twice.
+    fn get_subject_reservations(&self) -> Option<&crate::subjects::SubjectReservations> {
+        None
+    }
+
+    /// Returns the subjects that Commits are being applied to in this store, so Commits to the same subject wait for each other.
+    /// Stores without them apply Commits concurrently.
+    fn get_subject_locks(&self) -> Option<&crate::commit::SubjectLocks> {
+        None
+    }
+
     /// This function is called whenever a Commit is applied.
     /// Implement this if you want to have custom handlers for Commits.
     fn handle_commit(&self, _commit_response: &CommitResponse) {}
@@ -347,7 +382,7 @@ pub trait Storelike: Sized {
 }
 
 /// Use this to construct a list of Resources
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Query {
     /// Filter by Property
     pub property: Option<String>,
@@ -0,0 +1,294 @@
+//! Generates subjects for new Resources.
+//! A Drive can set a [SubjectStrategy] using the `subjectStrategy` property, which is used for all new Resources in it.
+//! Without a strategy, subjects are generated the way the call site always did, so existing clients see no difference.
+//! Changing the strategy only affects new Resources, existing subjects are never changed.
+
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+
+use crate::{errors::AtomicResult, urls, Storelike};
+
+/// How long a generated subject stays reserved, so concurrent requests don't get the same one before it is saved.
+const RESERVATION_TTL: Duration = Duration::from_secs(5 * 60);
+/// Length of the ids of [SubjectStrategy::Random].
+const RANDOM_ID_LENGTH: usize = 16;
+/// Gives up after this many taken candidates, which means something is wrong with the strategy.
+const MAX_ATTEMPTS: usize = 1000;
+/// After this many siblings with the same slug, a random suffix is used instead of a number.
+const NUMBERED_SLUGS: usize = 10;
+/// Limits how far up the hierarchy we look for a Drive.
+const MAX_DEPTH: usize = 50;
+const BASE62: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+const CROCKFORD_BASE32: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubjectStrategy {
+    /// `{parent}/{slug}`, based on the name or shortname. Gets a `-2`, `-3`... suffix if a sibling has the same slug.
+    /// Resources created without a parent use the root of the server as their parent.
+    Slug,
+    /// `{server}/r/{random base62 id}`
+    Random,
+    /// `{parent}/{unix timestamp in milliseconds}`
+    Timestamped,
+    /// `{server}/u/{ULID}`, which sort by creation time
+    Ulid,
+}
+
+impl SubjectStrategy {
+    pub const ALL: [SubjectStrategy; 4] = [
+        SubjectStrategy::Slug,
+        SubjectStrategy::Random,
+        SubjectStrategy::Timestamped,
+        SubjectStrategy::Ulid,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubjectStrategy::Slug => "slug",
+            SubjectStrategy::Random => "random",
+            SubjectStrategy::Timestamped => "timestamped",
+            SubjectStrategy::Ulid => "ulid",
+        }
+    }
+}
+
+impl std::fmt::Display for SubjectStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for SubjectStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SubjectStrategy::ALL
+            .into_iter()
+            .find(|strategy| strategy.as_str() == s)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown subject strategy '{}'. Use one of: {}",
+                    s,
+                    SubjectStrategy::ALL.map(|s| s.as_str()).join(", ")
+                )
+            })
+    }
+}
+
+/// What a new subject can be based on. Strategies ignore what they don't use.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SubjectHint<'a> {
+    /// Where the Resource is placed in the hierarchy. Also used to find its Drive.
+    pub parent: Option<&'a str>,
+    /// A name, shortname, filename or localId
+    pub name: Option<&'a str>,
+}
+
+/// Reserves generated subjects for a while, so two requests can't get the same subject before either is saved.
+/// Cheap to clone, clones share the same reservations.
+#[derive(Clone, Debug, Default)]
+pub struct SubjectReservations {
+    reserved: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl SubjectReservations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns false if the subject has already been reserved.
+    pub fn reserve(&self, subject: &str) -> bool {
+        let mut reserved = self.reserved.lock().unwrap();
+        reserved.retain(|_, at| at.elapsed() < RESERVATION_TTL);
+        if reserved.contains_key(subject) {
+            return false;
+        }
+        reserved.insert(subject.to_string(), Instant::now());
+        true
+    }
+}
+
+/// The strategy of the Drive that contains `parent`, or of the root Drive if there is no `parent`.
+pub fn strategy_for(store: &impl Storelike, parent: Option<&str>) -> Option<SubjectStrategy> {
+    // Stores without a self URL would fetch the Drive from the web
+    let server_url = store.get_self_url()?;
+    let mut current = parent.unwrap_or(&server_url).to_string();
+    for _ in 0..MAX_DEPTH {
+        let resource = store.get_resource(&current).ok()?;
+        if crate::hierarchy::is_drive(&resource) {
+            return resource
+                .get(urls::SUBJECT_STRATEGY)
+                .ok()
+                .and_then(|value| value.to_string().parse().ok());
+        }
+        current = resource.get(urls::PARENT).ok()?.to_string();
+    }
+    None
+}
+
+/// Returns a new subject using the `strategy`, which does not exist in the store and has not been handed out before.
+pub fn generate_subject(
+    store: &impl Storelike,
+    strategy: SubjectStrategy,
+    hint: SubjectHint,
+) -> AtomicResult<String> {
+    let server_url = store.get_server_url().trim_end_matches('/').to_string();
+    let parent = hint
+        .parent
+        .unwrap_or(&server_url)
+        .trim_end_matches('/')
+        .to_string();
+    for attempt in 0..MAX_ATTEMPTS {
+        let candidate = match strategy {
+            SubjectStrategy::Slug => {
+                let slug = slugify(hint.name.unwrap_or("resource"));
+                match attempt {
+                    0 => format!("{}/{}", parent, slug),
+                    n if n < NUMBERED_SLUGS => format!("{}/{}-{}", parent, slug, n + 1),
+                    // Prevents checking every number when there are many siblings with the same slug
+                    _ => format!("{}/{}-{}", parent, slug, &random_base62()[..6]),
+                }
+            }
+            SubjectStrategy::Random => format!("{}/r/{}", server_url, random_base62()),
+            SubjectStrategy::Timestamped => match attempt {
+                0 => format!("{}/{}", parent, crate::utils::now()),
+                n => format!("{}/{}-{}", parent, crate::utils::now(), n + 1),
+            },
+            SubjectStrategy::Ulid => format!("{}/u/{}", server_url, ulid()),
+        };
+        if is_available(store, &candidate) {
+            return Ok(candidate);
+        }
+    }
+    Err(format!(
+        "Could not find an unused subject using the '{}' strategy",
+        strategy
+    )
+    .into())
+}
+
+/// Uses the strategy of the Drive, or `fallback` if it has none.
+pub fn new_subject(
+    store: &impl Storelike,
+    hint: SubjectHint,
+    fallback: impl FnOnce() -> String,
+) -> AtomicResult<String> {
+    match strategy_for(store, hint.parent) {
+        Some(strategy) => generate_subject(store, strategy, hint),
+        None => Ok(fallback()),
+    }
+}
+
+/// Derives the subject of an imported Resource from its `localId`.
+/// The same `localId` always gets the same subject, so references to it can be resolved, and importing again updates the Resource.
+/// Only the Slug and Random strategies can be derived like this, the others use the Slug form.
+/// LocalIds that aren't slugs get a hash suffix, so `My Note` and `my-note` don't end up at the same subject.
+pub fn subject_from_local_id(store: &impl Storelike, importer: &str, local_id: &str) -> String {
+    let importer_url = importer.trim_end_matches('/');
+    match strategy_for(store, Some(importer)) {
+        None => format!("{}/{}", importer, local_id),
+        Some(SubjectStrategy::Random) => format!(
+            "{}/r/{}",
+            store.get_server_url().trim_end_matches('/'),
+            hashed_base62(&format!("{}\n{}", importer_url, local_id), RANDOM_ID_LENGTH)
+        ),
+        Some(_) => {
+            let slug = slugify(local_id);
+            if slug == local_id {
+                format!("{}/{}", importer_url, slug)
+            } else {
+                format!("{}/{}-{}", importer_url, slug, hashed_base62(local_id, 6))
+            }
+        }
+    }
+}
+
+fn is_available(store: &impl Storelike, subject: &str) -> bool {
+    if store.get_resource(subject).is_ok() {
+        return false;
+    }
+    match store.get_subject_reservations() {
+        Some(reservations) => reservations.reserve(subject),
+        None => true,
+    }
+}
+
+/// Lowercase letters, digits and single dashes. Never empty.
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.trim().to_lowercase().chars() {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.ends_with('-') && !slug.is_empty() {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    match slug {
+        "" => "resource".into(),
+        slug => urlencoding::encode(slug).into_owned(),
+    }
+}
+
+fn hashed_base62(input: &str, length: usize) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, input.as_bytes());
+    digest
+        .as_ref()
+        .iter()
+        .take(length)
+        .map(|byte| BASE62[*byte as usize % BASE62.len()] as char)
+        .collect()
+}
+
+fn random_base62() -> String {
+    let mut rng = rand::thread_rng();
+    (0..RANDOM_ID_LENGTH)
+        .map(|_| BASE62[rng.gen_range(0..BASE62.len())] as char)
+        .collect()
+}
+
+/// A 48 bit timestamp in milliseconds, followed by 80 random bits, encoded in Crockford's base32.
+/// See https://github.com/ulid/spec
+fn ulid() -> String {
+    let timestamp = (crate::utils::now() as u128) & ((1 << 48) - 1);
+    let random = rand::random::<u128>() & ((1 << 80) - 1);
+    let value = (timestamp << 80) | random;
+    (0..26)
+        .map(|i| CROCKFORD_BASE32[((value >> (5 * (25 - i))) & 31) as usize] as char)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn slugs() {
+        assert_eq!(slugify("My Great  Document!"), "my-great-document");
+        assert_eq!(slugify("  "), "resource");
+        assert_eq!(slugify("--a--"), "a");
+    }
+
+    #[test]
+    fn ulids_sort_by_time() {
+        let first = ulid();
+        std::thread::sleep(Duration::from_millis(2));
+        let second = ulid();
+        assert_eq!(first.len(), 26);
+        assert!(first < second);
+    }
+
+    #[test]
+    fn parses_strategies() {
+        for strategy in SubjectStrategy::ALL {
+            assert_eq!(strategy.as_str().parse::<SubjectStrategy>(), Ok(strategy));
+        }
+        assert!("uuid".parse::<SubjectStrategy>().is_err());
+    }
+}
@@ -0,0 +1,1450 @@
+//! Structs for Classes, generated by [crate::codegen]. Do not edit this file, regenerate it instead.
+
+use crate::{codegen, errors::AtomicResult, Resource};
+use crate::datatype::DataType;
+
+/// The URLs of the Properties of the generated Classes.
+pub mod properties {
+    /// The Agents that created the most Commits in the period, with their amount of Commits.
+    pub const ACTIVE_AGENTS: &str = "https://atomicdata.dev/properties/activity/activeAgents";
+    /// The Agent whose activity is counted.
+    pub const AGENT: &str = "https://atomicdata.dev/properties/activity/agent";
+    /// The size in bytes of the Resources and files that were served.
+    pub const BYTES_SERVED: &str = "https://atomicdata.dev/properties/activity/bytesServed";
+    /// Amount of Commits in the period.
+    pub const COMMIT_COUNT: &str = "https://atomicdata.dev/properties/activity/commitCount";
+    /// The amount of applied Commits.
+    pub const COMMITS: &str = "https://atomicdata.dev/properties/activity/commits";
+    /// The day (in UTC) of the counted activity.
+    pub const DATE: &str = "https://atomicdata.dev/properties/activity/date";
+    /// The size in bytes of the downloaded files.
+    pub const DOWNLOAD_BYTES: &str = "https://atomicdata.dev/properties/activity/downloadBytes";
+    /// The amount of downloaded files.
+    pub const DOWNLOADS: &str = "https://atomicdata.dev/properties/activity/downloads";
+    /// The Drive that this activity summary describes.
+    pub const DRIVE: &str = "https://atomicdata.dev/properties/activity/drive";
+    /// Amount of times an Invite to a Resource in the Drive was accepted in the period.
+    pub const INVITE_REDEMPTIONS: &str = "https://atomicdata.dev/properties/activity/inviteRedemptions";
+    /// The amount of signed requests.
+    pub const READS: &str = "https://atomicdata.dev/properties/activity/reads";
+    /// Resources that were created in the period, newest first.
+    pub const RECENTLY_CREATED: &str = "https://atomicdata.dev/properties/activity/recentlyCreated";
+    /// Start of the period that the activity summary covers.
+    pub const SINCE: &str = "https://atomicdata.dev/properties/activity/since";
+    /// Total size in bytes of the Files in the Drive.
+    pub const STORAGE_USED: &str = "https://atomicdata.dev/properties/activity/storageUsed";
+    /// The amount of distinct subjects that were read, downloaded or changed.
+    pub const SUBJECTS: &str = "https://atomicdata.dev/properties/activity/subjects";
+    /// The property of an Atom is the relationship between the resource (subject) and the value. For example, in the sentence `john is born in 1991`, the property is `is born in`.
+    pub const PROPERTY: &str = "https://atomicdata.dev/properties/atom/property";
+    /// The subject of an Atom is the (URL of the) Resource that is being described. For example, in the sentence `john is born in 1991`, the subject is `john`.
+    pub const SUBJECT: &str = "https://atomicdata.dev/properties/atom/subject";
+    /// The value of an Atom is the actual content of the information that is being described. For example, in the sentence `john is born in 1991`, the value is `1991`. When you use this property, the datatype is always a String, even if the actual property would set something different.
+    pub const VALUE: &str = "https://atomicdata.dev/properties/atom/value";
+    /// Base64 encoded SHA-256 checksum of a binary object.
+    pub const CHECKSUM: &str = "https://atomicdata.dev/properties/checksum";
+    /// The children of a Resource are the items that have this Resource set as Parent. Children are 'below' their Parents, hierarchically. Parents are the boss of children, which means that parents influents things like read and write rights.
+    pub const CHILDREN: &str = "https://atomicdata.dev/properties/children";
+    /// The current page number of the collection. Defaults to 0.
+    pub const CURRENT_PAGE: &str = "https://atomicdata.dev/properties/collection/currentPage";
+    /// If true, resources will be included in this collection that are not present in the Server.
+    pub const INCLUDE_EXTERNAL: &str = "https://atomicdata.dev/properties/collection/includeExternal";
+    /// The members are the list of resources in a collection.
+    pub const MEMBERS: &str = "https://atomicdata.dev/properties/collection/members";
+    /// The maximum number of members per page.
+    pub const PAGE_SIZE: &str = "https://atomicdata.dev/properties/collection/pageSize";
+    /// Filter collection by the Property of Atoms. The property is the second field of an atom. Similar to `predicate` in RDF.
+    pub const PROPERTY_: &str = "https://atomicdata.dev/properties/collection/property";
+    /// Sort collection by this property. Sorts ascending by default.
+    pub const SORT_BY: &str = "https://atomicdata.dev/properties/collection/sortBy";
+    /// Sort collection descendingly. Sorts ascending by default.
+    pub const SORT_DESC: &str = "https://atomicdata.dev/properties/collection/sortDesc";
+    /// The count of items (members) in the collection.
+    pub const TOTAL_MEMBERS: &str = "https://atomicdata.dev/properties/collection/totalMembers";
+    /// The total number of pages in the collection.
+    pub const TOTAL_PAGES: &str = "https://atomicdata.dev/properties/collection/totalPages";
+    /// Filter collection by the Value of Atoms. The Value is the third field of an Atom. Similar to `object` in RDF.
+    pub const VALUE_: &str = "https://atomicdata.dev/properties/collection/value";
+    /// Maximum amount of Commits per minute that an Agent can send to the `/commit` endpoint. On an Agent, it overrides the server-wide limit in the ServerSettings. `0` means no limit. Can only be set by Agents with write rights to the root Drive of the server.
+    pub const COMMIT_RATE_LIMIT: &str = "https://atomicdata.dev/properties/commitRateLimit";
+    /// Timestamp when the Commit was created (usually when it was signed).
+    pub const CREATED_AT: &str = "https://atomicdata.dev/properties/createdAt";
+    /// Who created this resource.
+    pub const CREATED_BY: &str = "https://atomicdata.dev/properties/createdBy";
+    /// JavaScript that is included in the body of every HTML page served by the server, e.g. for analytics.
+    pub const CUSTOM_SCRIPT: &str = "https://atomicdata.dev/properties/customScript";
+    /// Agents that get read rights to new Resources in this Drive, unless the Commit that creates the Resource sets rights itself. Does not change existing Resources.
+    pub const DEFAULT_READ: &str = "https://atomicdata.dev/properties/defaultRead";
+    /// Agents that get write rights to new Resources in this Drive, unless the Commit that creates the Resource sets rights itself. Does not change existing Resources.
+    pub const DEFAULT_WRITE: &str = "https://atomicdata.dev/properties/defaultWrite";
+    /// A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.
+    pub const DESCRIPTION: &str = "https://atomicdata.dev/properties/description";
+    /// Where a redirect should point to.
+    pub const DESTINATION: &str = "https://atomicdata.dev/properties/destination";
+    /// If set to true, the entire Subject resource will be removed in this commit. This will be executed _before_ other commands, such as set.
+    pub const DESTROY: &str = "https://atomicdata.dev/properties/destroy";
+    /// Set of sections in a document
+    pub const ELEMENTS: &str = "https://atomicdata.dev/properties/documents/elements";
+    /// The URL where a file can be downloaded.
+    pub const DOWNLOAD_URL: &str = "https://atomicdata.dev/properties/downloadURL";
+    /// Only Resources that are an instance of this Class are members of the DynamicCollection.
+    pub const DYNAMIC_COLLECTION_CLASS: &str = "https://atomicdata.dev/properties/dynamicCollection/class";
+    /// A JSON array of conditions that members of the DynamicCollection must match, e.g. `[{"property": "https://example.com/status", "operator": "eq", "value": "open"}]`. Operators are `eq`, `neq`, `lt`, `gt` and `contains`. The value `$agent` is replaced by the Agent that fetches the collection.
+    pub const DYNAMIC_COLLECTION_CONDITIONS: &str = "https://atomicdata.dev/properties/dynamicCollection/conditions";
+    /// Only descendants of this Resource are members of the DynamicCollection.
+    pub const DYNAMIC_COLLECTION_SCOPE: &str = "https://atomicdata.dev/properties/dynamicCollection/scope";
+    /// The query parameters of the endpoint
+    pub const PARAMETERS: &str = "https://atomicdata.dev/properties/endpoint/parameters";
+    /// Hex encoded SHA-256 checksum of the contents of the File that `extracted-text` was taken from. Extraction is skipped while the contents stay the same.
+    pub const EXTRACTED_CHECKSUM: &str = "https://atomicdata.dev/properties/extractedChecksum";
+    /// Text extracted from the contents of a File (e.g. a PDF, or an image using OCR), so the File can be found by searching. Truncated to the maximum length that the server allows.
+    pub const EXTRACTED_TEXT: &str = "https://atomicdata.dev/properties/extractedText";
+    /// Why text could not be extracted from the contents of a File.
+    pub const EXTRACTION_ERROR: &str = "https://atomicdata.dev/properties/extractionError";
+    /// A filename does not contain strings, and ends with a dot and a file extension.
+    pub const FILENAME: &str = "https://atomicdata.dev/properties/filename";
+    /// Size of a file in bytes
+    pub const FILESIZE: &str = "https://atomicdata.dev/properties/filesize";
+    /// The graph that the Resources in this Drive are put in when they are exported as N-Quads. Defaults to the subject of the Drive.
+    pub const GRAPH_IRI: &str = "https://atomicdata.dev/properties/graphIri";
+    /// The format of the documents that an [ImportProfile](https://atomicdata.dev/classes/ImportProfile) reads: `csv` (with a header row) or `json` (an array of objects).
+    pub const FORMAT: &str = "https://atomicdata.dev/properties/importProfile/format";
+    /// A mapped Property that identifies a row, such as an id from the source tool. Rows with the same value as an existing Resource update that Resource instead of creating a duplicate, so importing the same document twice changes nothing.
+    pub const KEY: &str = "https://atomicdata.dev/properties/importProfile/key";
+    /// JSON object that maps the columns (for CSV) or keys (for JSON) of an imported document to the Properties they are stored in, e.g. `{"Title": "https://atomicdata.dev/properties/name"}`. Columns that are not mentioned are ignored.
+    pub const MAPPING: &str = "https://atomicdata.dev/properties/importProfile/mapping";
+    /// The Class of the Resources that an [ImportProfile](https://atomicdata.dev/classes/ImportProfile) creates.
+    pub const TARGET_CLASS: &str = "https://atomicdata.dev/properties/importProfile/targetClass";
+    /// The parent of the Resources that an [ImportProfile](https://atomicdata.dev/classes/ImportProfile) creates. Rows are only matched with existing Resources inside this parent.
+    pub const TARGET_PARENT: &str = "https://atomicdata.dev/properties/importProfile/targetParent";
+    /// JSON object with the transformations that are applied to the values of a Property before they are stored, in order. For example `{"https://atomicdata.dev/properties/published": [{"dateFormat": "%d-%m-%Y"}]}`. Available: `dateFormat` (for Date and Timestamp Properties, using `%Y`, `%m`, `%d`, `%H`, `%M` and `%S`), `split` (for ResourceArray Properties, splits the value by a separator) and `replace` (an object that replaces entire values).
+    pub const TRANSFORMS: &str = "https://atomicdata.dev/properties/importProfile/transforms";
+    /// If this is true, this Resource does not contain all the values that it should. This is probably done because it was included as part of a larger Resource, such as a Collection, Fetch this resource directly (send a GET request to its Subject URL) to get all the properties.
+    pub const INCOMPLETE: &str = "https://atomicdata.dev/properties/incomplete";
+    /// An identifier used internally by the system.
+    pub const INTERNAL_ID: &str = "https://atomicdata.dev/properties/internalId";
+    /// The Agent that is created in a Redirect action. See the [Invite docs](https://docs.atomicdata.dev/invitations.html).
+    pub const REDIRECT_AGENT: &str = "https://atomicdata.dev/properties/invite/redirectAgent";
+    /// The resource that the invite will grant rights for. It will often also be the target of a redirection.
+    pub const TARGET: &str = "https://atomicdata.dev/properties/invite/target";
+    /// The amount of usages that are left for this invite. When this reaches 0, the invite will no longer be functional.
+    pub const USAGES_LEFT: &str = "https://atomicdata.dev/properties/invite/usagesLeft";
+    /// The list of Agents that have used this Invite.
+    pub const USERS: &str = "https://atomicdata.dev/properties/invite/users";
+    /// If true, provides the one who is invited with Write rights, which means allowing to edit the resource, its properties and its children.
+    pub const WRITE: &str = "https://atomicdata.dev/properties/invite/write";
+    /// Whether Invites can be accepted on this server. If false, no new Agents can get rights using an Invite.
+    pub const INVITES_ENABLED: &str = "https://atomicdata.dev/properties/invitesEnabled";
+    /// Why a [Job](https://atomicdata.dev/classes/Job) failed.
+    pub const JOB_ERROR: &str = "https://atomicdata.dev/properties/job/error";
+    /// JSON object with the parameters of a [Job](https://atomicdata.dev/classes/Job), such as the `subject` of the resource to export.
+    pub const JOB_PARAMS: &str = "https://atomicdata.dev/properties/job/params";
+    /// How far along a [Job](https://atomicdata.dev/classes/Job) is, from `0` to `1`.
+    pub const JOB_PROGRESS: &str = "https://atomicdata.dev/properties/job/progress";
+    /// The resource that a finished [Job](https://atomicdata.dev/classes/Job) produced, such as an exported File.
+    pub const JOB_RESULT: &str = "https://atomicdata.dev/properties/job/result";
+    /// The state of a [Job](https://atomicdata.dev/classes/Job). One of `pending`, `running`, `done`, `failed` or `cancelled`. The creator of a Job can cancel it by setting this to `cancelled`.
+    pub const JOB_STATUS: &str = "https://atomicdata.dev/properties/job/status";
+    /// The kind of work a [Job](https://atomicdata.dev/classes/Job) performs, e.g. `rebuild-search` or `export-subtree`.
+    pub const JOB_TYPE: &str = "https://atomicdata.dev/properties/job/type";
+    /// The links to other servers that could not be fetched, with the Resource and Property that contain them.
+    pub const BROKEN_LINKS: &str = "https://atomicdata.dev/properties/linkReport/brokenLinks";
+    /// When the link (or the LinkReport) was last checked.
+    pub const CHECKED_AT: &str = "https://atomicdata.dev/properties/linkReport/checkedAt";
+    /// Maximum length of an array that an Agent can `set` or `push` in a single Commit. On an Agent, it overrides the server-wide limit in the ServerSettings. `0` means no limit. Can only be set by Agents with write rights to the root Drive of the server.
+    pub const MAX_COMMIT_ARRAY_LENGTH: &str = "https://atomicdata.dev/properties/maxCommitArrayLength";
+    /// Maximum size in bytes of a single Commit sent by an Agent. On an Agent, it overrides the server-wide limit in the ServerSettings. `0` means no limit. Can only be set by Agents with write rights to the root Drive of the server.
+    pub const MAX_COMMIT_SIZE: &str = "https://atomicdata.dev/properties/maxCommitSize";
+    /// Maximum size in bytes of a single file uploaded to the `/upload` endpoint. `0` means no limit.
+    pub const MAX_UPLOAD_SIZE: &str = "https://atomicdata.dev/properties/maxUploadSize";
+    /// Mimetype of a file sets is the type of data that is stored in the file. See https://developer.mozilla.org/en-US/docs/Web/HTTP/Basics_of_HTTP/MIME_types
+    pub const MIMETYPE: &str = "https://atomicdata.dev/properties/mimetype";
+    /// The name of a thing or person.
+    pub const NAME: &str = "https://atomicdata.dev/properties/name";
+    /// If true, new Resources in this Drive can be read by anyone, unless the Commit that creates the Resource sets rights itself.
+    pub const NEW_RESOURCES_PUBLIC: &str = "https://atomicdata.dev/properties/newResourcesPublic";
+    /// The Resource that a [Notification](https://atomicdata.dev/classes/Notification) is about, such as the Task that the recipient has been assigned to.
+    pub const ABOUT: &str = "https://atomicdata.dev/properties/notification/about";
+    /// The Commit that created a [Notification](https://atomicdata.dev/classes/Notification).
+    pub const COMMIT: &str = "https://atomicdata.dev/properties/notification/commit";
+    /// Whether the recipient has read a [Notification](https://atomicdata.dev/classes/Notification). The recipient marks it as read with a Commit that sets this to `true`.
+    pub const IS_READ: &str = "https://atomicdata.dev/properties/notification/isRead";
+    /// The Property that the recipient of a [Notification](https://atomicdata.dev/classes/Notification) was set to, such as `assignedTo` or `mentions`.
+    pub const PROPERTY__: &str = "https://atomicdata.dev/properties/notification/property";
+    /// The Agent that a [Notification](https://atomicdata.dev/classes/Notification), Inbox or [Subscription](https://atomicdata.dev/classes/Subscription) belongs to. Only this Agent can read it, whatever the rights of its parents.
+    pub const RECIPIENT: &str = "https://atomicdata.dev/properties/notification/recipient";
+    /// The Properties that create a [Notification](https://atomicdata.dev/classes/Notification) for the Agents they are set to, such as `assignedTo` and `mentions`.
+    pub const NOTIFY_ON: &str = "https://atomicdata.dev/properties/notifyOn";
+    /// The parent of a Resource sets the hierarchical structure of the Resource, and therefore also the rights / grants. It is used for both navigation, structure and authorization. Parents are the inverse of [children](https://atomicdata.dev/properties/children).
+    pub const PARENT: &str = "https://atomicdata.dev/properties/parent";
+    /// DateTime at which an item is made public.
+    pub const PUBLISHED_AT: &str = "https://atomicdata.dev/properties/published-at";
+    /// The agents that can read this resource and its children.
+    pub const READ: &str = "https://atomicdata.dev/properties/read";
+    /// A list of property URLs that should be removed from the resource.
+    pub const REMOVE: &str = "https://atomicdata.dev/properties/remove";
+    /// The Property that only the Agents in `write` of this PropertyRight may change.
+    pub const RESTRICTED_PROPERTY: &str = "https://atomicdata.dev/properties/restrictedProperty";
+    /// Resources that have not been changed for this many days are deleted permanently.
+    pub const DELETE_INACTIVE_AFTER: &str = "https://atomicdata.dev/properties/retention/deleteInactiveAfter";
+    /// Keeps the affected Resources and all of their Commits forever, regardless of other RetentionPolicies.
+    pub const EXEMPT: &str = "https://atomicdata.dev/properties/retention/exempt";
+    /// Commits older than this many days are removed. The last of them is kept as the starting point of the history.
+    pub const KEEP_VERSIONS_FOR: &str = "https://atomicdata.dev/properties/retention/keepVersionsFor";
+    /// When the server last applied the RetentionPolicy.
+    pub const LAST_RUN: &str = "https://atomicdata.dev/properties/retention/lastRun";
+    /// Only instances of this Class are affected by the RetentionPolicy.
+    pub const TARGET_CLASS_: &str = "https://atomicdata.dev/properties/retention/targetClass";
+    /// The RetentionPolicy affects the descendants of this Resource. Defaults to the parent of the policy.
+    pub const TARGET_SUBTREE: &str = "https://atomicdata.dev/properties/retention/targetSubtree";
+    /// The `set` Property describes the fields that are changed in the Commit. It is a Nested Resource, and each of its Property-Value combinations will be added to the Subject resource. If the Property existed before, it will be overwritten.
+    pub const SET: &str = "https://atomicdata.dev/properties/set";
+    /// The signature proves that a Commit is created by a specific Agent. It is a cryptographic proof - an RSA signature of the JSON serialized commit, minus the signature.
+    pub const SIGNATURE: &str = "https://atomicdata.dev/properties/signature";
+    /// The signer is the agent (person, organization or something else) that issued the commit.
+    pub const SIGNER: &str = "https://atomicdata.dev/properties/signer";
+    /// The thing that the Commit is changing - the resource ID that is being targeted. It must not contain any query parameters.
+    pub const SUBJECT_: &str = "https://atomicdata.dev/properties/subject";
+    /// How the subjects of new Resources in this Drive are generated. One of `slug` (based on the name, unique among siblings), `random` (random ids under `/r/`), `timestamped` or `ulid` (sortable ids under `/u/`). Changing it does not change the subjects of existing Resources.
+    pub const SUBJECT_STRATEGY: &str = "https://atomicdata.dev/properties/subjectStrategy";
+    /// A list of resources (usually its children) that appear under this resource in a hierarchy.
+    pub const SUB_RESOURCES: &str = "https://atomicdata.dev/properties/subresources";
+    /// The `commit-sequence` of the last Commit that a [Subscription](https://atomicdata.dev/classes/Subscription) acknowledged, or that a [Webhook](https://atomicdata.dev/classes/Webhook) received. Later Commits are sent again after a restart, so every Commit is delivered at least once.
+    pub const DELIVERY_CURSOR: &str = "https://atomicdata.dev/properties/subscription/cursor";
+    /// If true, a [Subscription](https://atomicdata.dev/classes/Subscription) also receives the Commits of all descendants of its target.
+    pub const INCLUDE_CHILDREN: &str = "https://atomicdata.dev/properties/subscription/includeChildren";
+    /// The newest Commit that the client of a [Subscription](https://atomicdata.dev/classes/Subscription) has acknowledged with `ACK`. Newer Commits are replayed when it reconnects.
+    pub const LAST_ACKNOWLEDGED: &str = "https://atomicdata.dev/properties/subscription/lastAcknowledged";
+    /// When the Agent of a [Subscription](https://atomicdata.dev/classes/Subscription) last connected or acknowledged a Commit. Subscriptions that are not seen for a while are removed.
+    pub const LAST_SEEN: &str = "https://atomicdata.dev/properties/subscription/lastSeen";
+    /// The Resource that a [Subscription](https://atomicdata.dev/classes/Subscription) receives the Commits of.
+    pub const TARGET_: &str = "https://atomicdata.dev/properties/subscription/target";
+    /// How the client of a [Subscription](https://atomicdata.dev/classes/Subscription) receives Commits, such as `websocket`. A hint for the server, not a guarantee.
+    pub const TRANSPORT: &str = "https://atomicdata.dev/properties/subscription/transport";
+    /// List of tags / categories / themes. Useful for categorizing posts.
+    pub const TAGS: &str = "https://atomicdata.dev/properties/tags";
+    /// Resources that have been in the trash for longer than this many days are permanently removed. If not set, the trash is only emptied manually.
+    pub const TRASH_RETENTION_DAYS: &str = "https://atomicdata.dev/properties/trashRetentionDays";
+    /// Why the last delivery of a [Webhook](https://atomicdata.dev/classes/Webhook) failed. Removed when a delivery succeeds.
+    pub const LAST_DELIVERY_ERROR: &str = "https://atomicdata.dev/properties/webhook/lastError";
+    /// The http or https URL that a [Webhook](https://atomicdata.dev/classes/Webhook) POSTs Commits to.
+    pub const WEBHOOK_URL: &str = "https://atomicdata.dev/properties/webhook/url";
+    /// The agents that can edit this resource and its children.
+    pub const WRITE_: &str = "https://atomicdata.dev/properties/write";
+}
+
+/// What happened in a Drive in a recent period: Commits, active Agents, new Resources, storage and accepted Invites. Generated by the `/activity` endpoint.
+///
+/// <https://atomicdata.dev/classes/ActivitySummary>
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivitySummary {
+    pub subject: String,
+    /// The Drive that this activity summary describes.
+    pub drive: String,
+    /// Start of the period that the activity summary covers.
+    pub since: i64,
+    /// Amount of Commits in the period.
+    pub commit_count: i64,
+    /// The Agents that created the most Commits in the period, with their amount of Commits.
+    pub active_agents: Option<Vec<String>>,
+    /// Resources that were created in the period, newest first.
+    pub recently_created: Option<Vec<String>>,
+    /// Total size in bytes of the Files in the Drive.
+    pub storage_used: Option<i64>,
+    /// Amount of times an Invite to a Resource in the Drive was accepted in the period.
+    pub invite_redemptions: Option<i64>,
+}
+
+impl ActivitySummary {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/ActivitySummary";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(ActivitySummary {
+            subject: resource.get_subject().clone(),
+            drive: codegen::required(resource, properties::DRIVE, DataType::AtomicUrl)?,
+            since: codegen::required(resource, properties::SINCE, DataType::Timestamp)?,
+            commit_count: codegen::required(resource, properties::COMMIT_COUNT, DataType::Integer)?,
+            active_agents: codegen::optional(resource, properties::ACTIVE_AGENTS, DataType::ResourceArray)?,
+            recently_created: codegen::optional(resource, properties::RECENTLY_CREATED, DataType::ResourceArray)?,
+            storage_used: codegen::optional(resource, properties::STORAGE_USED, DataType::Integer)?,
+            invite_redemptions: codegen::optional(resource, properties::INVITE_REDEMPTIONS, DataType::Integer)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::DRIVE, &self.drive, DataType::AtomicUrl);
+        codegen::set(resource, properties::SINCE, &self.since, DataType::Timestamp);
+        codegen::set(resource, properties::COMMIT_COUNT, &self.commit_count, DataType::Integer);
+        codegen::set_optional(resource, properties::ACTIVE_AGENTS, &self.active_agents, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::RECENTLY_CREATED, &self.recently_created, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::STORAGE_USED, &self.storage_used, DataType::Integer);
+        codegen::set_optional(resource, properties::INVITE_REDEMPTIONS, &self.invite_redemptions, DataType::Integer);
+    }
+}
+
+/// What an Agent did on a single day, counted by the server to detect abuse of leaked credentials. Only readable by the server.
+///
+/// <https://atomicdata.dev/classes/AgentActivity>
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentActivity {
+    pub subject: String,
+    /// The Agent whose activity is counted.
+    pub agent: String,
+    /// The day (in UTC) of the counted activity.
+    pub date: String,
+    /// The amount of signed requests.
+    pub reads: Option<i64>,
+    /// The amount of applied Commits.
+    pub commits: Option<i64>,
+    /// The amount of downloaded files.
+    pub downloads: Option<i64>,
+    /// The size in bytes of the downloaded files.
+    pub download_bytes: Option<i64>,
+    /// The size in bytes of the Resources and files that were served.
+    pub bytes_served: Option<i64>,
+    /// The amount of distinct subjects that were read, downloaded or changed.
+    pub subjects: Option<i64>,
+}
+
+impl AgentActivity {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/AgentActivity";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(AgentActivity {
+            subject: resource.get_subject().clone(),
+            agent: codegen::required(resource, properties::AGENT, DataType::AtomicUrl)?,
+            date: codegen::required(resource, properties::DATE, DataType::Date)?,
+            reads: codegen::optional(resource, properties::READS, DataType::Integer)?,
+            commits: codegen::optional(resource, properties::COMMITS, DataType::Integer)?,
+            downloads: codegen::optional(resource, properties::DOWNLOADS, DataType::Integer)?,
+            download_bytes: codegen::optional(resource, properties::DOWNLOAD_BYTES, DataType::Integer)?,
+            bytes_served: codegen::optional(resource, properties::BYTES_SERVED, DataType::Integer)?,
+            subjects: codegen::optional(resource, properties::SUBJECTS, DataType::Integer)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::AGENT, &self.agent, DataType::AtomicUrl);
+        codegen::set(resource, properties::DATE, &self.date, DataType::Date);
+        codegen::set_optional(resource, properties::READS, &self.reads, DataType::Integer);
+        codegen::set_optional(resource, properties::COMMITS, &self.commits, DataType::Integer);
+        codegen::set_optional(resource, properties::DOWNLOADS, &self.downloads, DataType::Integer);
+        codegen::set_optional(resource, properties::DOWNLOAD_BYTES, &self.download_bytes, DataType::Integer);
+        codegen::set_optional(resource, properties::BYTES_SERVED, &self.bytes_served, DataType::Integer);
+        codegen::set_optional(resource, properties::SUBJECTS, &self.subjects, DataType::Integer);
+    }
+}
+
+/// A written article / blogpost / blog.
+///
+/// <https://atomicdata.dev/classes/Article>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Article {
+    pub subject: String,
+    /// A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.
+    pub description: String,
+    /// The name of a thing or person.
+    pub name: String,
+    /// List of tags / categories / themes. Useful for categorizing posts.
+    pub tags: Option<Vec<String>>,
+    /// DateTime at which an item is made public.
+    pub published_at: Option<i64>,
+}
+
+impl Article {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Article";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Article {
+            subject: resource.get_subject().clone(),
+            description: codegen::required(resource, properties::DESCRIPTION, DataType::Markdown)?,
+            name: codegen::required(resource, properties::NAME, DataType::String)?,
+            tags: codegen::optional(resource, properties::TAGS, DataType::ResourceArray)?,
+            published_at: codegen::optional(resource, properties::PUBLISHED_AT, DataType::Timestamp)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::DESCRIPTION, &self.description, DataType::Markdown);
+        codegen::set(resource, properties::NAME, &self.name, DataType::String);
+        codegen::set_optional(resource, properties::TAGS, &self.tags, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::PUBLISHED_AT, &self.published_at, DataType::Timestamp);
+    }
+}
+
+/// An Atom is the smallest piece of meaningful data in Atomic Data. It consists of a Subject, a Property and a Value.
+///
+/// <https://atomicdata.dev/classes/Atom>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Atom {
+    pub subject: String,
+    /// The subject of an Atom is the (URL of the) Resource that is being described. For example, in the sentence `john is born in 1991`, the subject is `john`.
+    pub subject_: String,
+    /// The property of an Atom is the relationship between the resource (subject) and the value. For example, in the sentence `john is born in 1991`, the property is `is born in`.
+    pub property: String,
+    /// The value of an Atom is the actual content of the information that is being described. For example, in the sentence `john is born in 1991`, the value is `1991`. When you use this property, the datatype is always a String, even if the actual property would set something different.
+    pub value: String,
+}
+
+impl Atom {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Atom";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Atom {
+            subject: resource.get_subject().clone(),
+            subject_: codegen::required(resource, properties::SUBJECT, DataType::AtomicUrl)?,
+            property: codegen::required(resource, properties::PROPERTY, DataType::AtomicUrl)?,
+            value: codegen::required(resource, properties::VALUE, DataType::String)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::SUBJECT, &self.subject_, DataType::AtomicUrl);
+        codegen::set(resource, properties::PROPERTY, &self.property, DataType::AtomicUrl);
+        codegen::set(resource, properties::VALUE, &self.value, DataType::String);
+    }
+}
+
+/// A paginated set of resources that can be sorted. Accepts query parameters for setting the current page number, page size, sort attribute, sort direction
+///
+/// <https://atomicdata.dev/classes/Collection>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collection {
+    pub subject: String,
+    /// The name of a thing or person.
+    pub name: Option<String>,
+    /// A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.
+    pub description: Option<String>,
+    /// The current page number of the collection. Defaults to 0.
+    pub current_page: Option<i64>,
+    /// The members are the list of resources in a collection.
+    pub members: Option<Vec<String>>,
+    /// The maximum number of members per page.
+    pub page_size: Option<i64>,
+    /// Filter collection by the Property of Atoms. The property is the second field of an atom. Similar to `predicate` in RDF.
+    pub property: Option<String>,
+    /// Sort collection by this property. Sorts ascending by default.
+    pub sort_by: Option<String>,
+    /// Sort collection descendingly. Sorts ascending by default.
+    pub sort_desc: Option<bool>,
+    /// The count of items (members) in the collection.
+    pub total_members: Option<i64>,
+    /// The total number of pages in the collection.
+    pub total_pages: Option<i64>,
+    /// Filter collection by the Value of Atoms. The Value is the third field of an Atom. Similar to `object` in RDF.
+    pub value: Option<String>,
+    /// If true, resources will be included in this collection that are not present in the Server.
+    pub include_external: Option<bool>,
+    /// If this is true, this Resource does not contain all the values that it should. This is probably done because it was included as part of a larger Resource, such as a Collection, Fetch this resource directly (send a GET request to its Subject URL) to get all the properties.
+    pub incomplete: Option<bool>,
+}
+
+impl Collection {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Collection";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Collection {
+            subject: resource.get_subject().clone(),
+            name: codegen::optional(resource, properties::NAME, DataType::String)?,
+            description: codegen::optional(resource, properties::DESCRIPTION, DataType::Markdown)?,
+            current_page: codegen::optional(resource, properties::CURRENT_PAGE, DataType::Integer)?,
+            members: codegen::optional(resource, properties::MEMBERS, DataType::ResourceArray)?,
+            page_size: codegen::optional(resource, properties::PAGE_SIZE, DataType::Integer)?,
+            property: codegen::optional(resource, properties::PROPERTY_, DataType::AtomicUrl)?,
+            sort_by: codegen::optional(resource, properties::SORT_BY, DataType::AtomicUrl)?,
+            sort_desc: codegen::optional(resource, properties::SORT_DESC, DataType::Boolean)?,
+            total_members: codegen::optional(resource, properties::TOTAL_MEMBERS, DataType::Integer)?,
+            total_pages: codegen::optional(resource, properties::TOTAL_PAGES, DataType::Integer)?,
+            value: codegen::optional(resource, properties::VALUE_, DataType::String)?,
+            include_external: codegen::optional(resource, properties::INCLUDE_EXTERNAL, DataType::Boolean)?,
+            incomplete: codegen::optional(resource, properties::INCOMPLETE, DataType::Boolean)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set_optional(resource, properties::NAME, &self.name, DataType::String);
+        codegen::set_optional(resource, properties::DESCRIPTION, &self.description, DataType::Markdown);
+        codegen::set_optional(resource, properties::CURRENT_PAGE, &self.current_page, DataType::Integer);
+        codegen::set_optional(resource, properties::MEMBERS, &self.members, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::PAGE_SIZE, &self.page_size, DataType::Integer);
+        codegen::set_optional(resource, properties::PROPERTY_, &self.property, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::SORT_BY, &self.sort_by, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::SORT_DESC, &self.sort_desc, DataType::Boolean);
+        codegen::set_optional(resource, properties::TOTAL_MEMBERS, &self.total_members, DataType::Integer);
+        codegen::set_optional(resource, properties::TOTAL_PAGES, &self.total_pages, DataType::Integer);
+        codegen::set_optional(resource, properties::VALUE_, &self.value, DataType::String);
+        codegen::set_optional(resource, properties::INCLUDE_EXTERNAL, &self.include_external, DataType::Boolean);
+        codegen::set_optional(resource, properties::INCOMPLETE, &self.incomplete, DataType::Boolean);
+    }
+}
+
+/// A Commit is a Resource that describes how a Resource must be updated.
+///
+/// <https://atomicdata.dev/classes/Commit>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Commit {
+    pub subject: String,
+    /// Timestamp when the Commit was created (usually when it was signed).
+    pub created_at: i64,
+    /// The signature proves that a Commit is created by a specific Agent. It is a cryptographic proof - an RSA signature of the JSON serialized commit, minus the signature.
+    pub signature: String,
+    /// The signer is the agent (person, organization or something else) that issued the commit.
+    pub signer: String,
+    /// The thing that the Commit is changing - the resource ID that is being targeted. It must not contain any query parameters.
+    pub subject_: String,
+    /// If set to true, the entire Subject resource will be removed in this commit. This will be executed _before_ other commands, such as set.
+    pub destroy: Option<bool>,
+    /// A list of property URLs that should be removed from the resource.
+    pub remove: Option<Vec<String>>,
+    /// The `set` Property describes the fields that are changed in the Commit. It is a Nested Resource, and each of its Property-Value combinations will be added to the Subject resource. If the Property existed before, it will be overwritten.
+    pub set: Option<String>,
+}
+
+impl Commit {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Commit";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Commit {
+            subject: resource.get_subject().clone(),
+            created_at: codegen::required(resource, properties::CREATED_AT, DataType::Timestamp)?,
+            signature: codegen::required(resource, properties::SIGNATURE, DataType::String)?,
+            signer: codegen::required(resource, properties::SIGNER, DataType::AtomicUrl)?,
+            subject_: codegen::required(resource, properties::SUBJECT_, DataType::AtomicUrl)?,
+            destroy: codegen::optional(resource, properties::DESTROY, DataType::Boolean)?,
+            remove: codegen::optional(resource, properties::REMOVE, DataType::ResourceArray)?,
+            set: codegen::optional(resource, properties::SET, DataType::AtomicUrl)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::CREATED_AT, &self.created_at, DataType::Timestamp);
+        codegen::set(resource, properties::SIGNATURE, &self.signature, DataType::String);
+        codegen::set(resource, properties::SIGNER, &self.signer, DataType::AtomicUrl);
+        codegen::set(resource, properties::SUBJECT_, &self.subject_, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::DESTROY, &self.destroy, DataType::Boolean);
+        codegen::set_optional(resource, properties::REMOVE, &self.remove, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::SET, &self.set, DataType::AtomicUrl);
+    }
+}
+
+/// A nice documnet
+///
+/// <https://atomicdata.dev/classes/Document>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    pub subject: String,
+    /// Set of sections in a document
+    pub elements: Option<Vec<String>>,
+    /// The name of a thing or person.
+    pub name: Option<String>,
+}
+
+impl Document {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Document";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Document {
+            subject: resource.get_subject().clone(),
+            elements: codegen::optional(resource, properties::ELEMENTS, DataType::ResourceArray)?,
+            name: codegen::optional(resource, properties::NAME, DataType::String)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set_optional(resource, properties::ELEMENTS, &self.elements, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::NAME, &self.name, DataType::String);
+    }
+}
+
+/// The Drive node is at the top of the hierarchy in an Atomic Server. It can be thought of as a hard drive at the top of a filesystem. It can be used as a starting point to navigate to any Resource. A Drive needs to provide read and write access at least to one node.
+///
+/// <https://atomicdata.dev/classes/Drive>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Drive {
+    pub subject: String,
+    /// The agents that can read this resource and its children.
+    pub read: Option<Vec<String>>,
+    /// The children of a Resource are the items that have this Resource set as Parent. Children are 'below' their Parents, hierarchically. Parents are the boss of children, which means that parents influents things like read and write rights.
+    pub children: Option<Vec<String>>,
+    /// A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.
+    pub description: Option<String>,
+    /// A list of resources (usually its children) that appear under this resource in a hierarchy.
+    pub sub_resources: Option<Vec<String>>,
+    /// The agents that can edit this resource and its children.
+    pub write: Option<Vec<String>>,
+    /// Agents that get read rights to new Resources in this Drive, unless the Commit that creates the Resource sets rights itself. Does not change existing Resources.
+    pub default_read: Option<Vec<String>>,
+    /// Agents that get write rights to new Resources in this Drive, unless the Commit that creates the Resource sets rights itself. Does not change existing Resources.
+    pub default_write: Option<Vec<String>>,
+    /// If true, new Resources in this Drive can be read by anyone, unless the Commit that creates the Resource sets rights itself.
+    pub new_resources_public: Option<bool>,
+    /// The graph that the Resources in this Drive are put in when they are exported as N-Quads. Defaults to the subject of the Drive.
+    pub graph_iri: Option<String>,
+    /// How the subjects of new Resources in this Drive are generated. One of `slug` (based on the name, unique among siblings), `random` (random ids under `/r/`), `timestamped` or `ulid` (sortable ids under `/u/`). Changing it does not change the subjects of existing Resources.
+    pub subject_strategy: Option<String>,
+}
+
+impl Drive {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Drive";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Drive {
+            subject: resource.get_subject().clone(),
+            read: codegen::optional(resource, properties::READ, DataType::ResourceArray)?,
+            children: codegen::optional(resource, properties::CHILDREN, DataType::ResourceArray)?,
+            description: codegen::optional(resource, properties::DESCRIPTION, DataType::Markdown)?,
+            sub_resources: codegen::optional(resource, properties::SUB_RESOURCES, DataType::ResourceArray)?,
+            write: codegen::optional(resource, properties::WRITE_, DataType::ResourceArray)?,
+            default_read: codegen::optional(resource, properties::DEFAULT_READ, DataType::ResourceArray)?,
+            default_write: codegen::optional(resource, properties::DEFAULT_WRITE, DataType::ResourceArray)?,
+            new_resources_public: codegen::optional(resource, properties::NEW_RESOURCES_PUBLIC, DataType::Boolean)?,
+            graph_iri: codegen::optional(resource, properties::GRAPH_IRI, DataType::AtomicUrl)?,
+            subject_strategy: codegen::optional(resource, properties::SUBJECT_STRATEGY, DataType::String)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set_optional(resource, properties::READ, &self.read, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::CHILDREN, &self.children, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::DESCRIPTION, &self.description, DataType::Markdown);
+        codegen::set_optional(resource, properties::SUB_RESOURCES, &self.sub_resources, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::WRITE_, &self.write, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::DEFAULT_READ, &self.default_read, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::DEFAULT_WRITE, &self.default_write, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::NEW_RESOURCES_PUBLIC, &self.new_resources_public, DataType::Boolean);
+        codegen::set_optional(resource, properties::GRAPH_IRI, &self.graph_iri, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::SUBJECT_STRATEGY, &self.subject_strategy, DataType::String);
+    }
+}
+
+/// A stored query. Fetching it returns the Resources that match its Class, conditions and scope as its members, sorted and paginated. Subscribers are notified when a Resource enters or leaves the results.
+///
+/// <https://atomicdata.dev/classes/DynamicCollection>
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicCollection {
+    pub subject: String,
+    /// The name of a thing or person.
+    pub name: String,
+    /// Only Resources that are an instance of this Class are members of the DynamicCollection.
+    pub dynamic_collection_class: Option<String>,
+    /// A JSON array of conditions that members of the DynamicCollection must match, e.g. `[{"property": "https://example.com/status", "operator": "eq", "value": "open"}]`. Operators are `eq`, `neq`, `lt`, `gt` and `contains`. The value `$agent` is replaced by the Agent that fetches the collection.
+    pub dynamic_collection_conditions: Option<String>,
+    /// Only descendants of this Resource are members of the DynamicCollection.
+    pub dynamic_collection_scope: Option<String>,
+    /// Sort collection by this property. Sorts ascending by default.
+    pub sort_by: Option<String>,
+    /// Sort collection descendingly. Sorts ascending by default.
+    pub sort_desc: Option<bool>,
+    /// The maximum number of members per page.
+    pub page_size: Option<i64>,
+    /// A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.
+    pub description: Option<String>,
+}
+
+impl DynamicCollection {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/DynamicCollection";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(DynamicCollection {
+            subject: resource.get_subject().clone(),
+            name: codegen::required(resource, properties::NAME, DataType::String)?,
+            dynamic_collection_class: codegen::optional(resource, properties::DYNAMIC_COLLECTION_CLASS, DataType::AtomicUrl)?,
+            dynamic_collection_conditions: codegen::optional(resource, properties::DYNAMIC_COLLECTION_CONDITIONS, DataType::String)?,
+            dynamic_collection_scope: codegen::optional(resource, properties::DYNAMIC_COLLECTION_SCOPE, DataType::AtomicUrl)?,
+            sort_by: codegen::optional(resource, properties::SORT_BY, DataType::AtomicUrl)?,
+            sort_desc: codegen::optional(resource, properties::SORT_DESC, DataType::Boolean)?,
+            page_size: codegen::optional(resource, properties::PAGE_SIZE, DataType::Integer)?,
+            description: codegen::optional(resource, properties::DESCRIPTION, DataType::Markdown)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::NAME, &self.name, DataType::String);
+        codegen::set_optional(resource, properties::DYNAMIC_COLLECTION_CLASS, &self.dynamic_collection_class, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::DYNAMIC_COLLECTION_CONDITIONS, &self.dynamic_collection_conditions, DataType::String);
+        codegen::set_optional(resource, properties::DYNAMIC_COLLECTION_SCOPE, &self.dynamic_collection_scope, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::SORT_BY, &self.sort_by, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::SORT_DESC, &self.sort_desc, DataType::Boolean);
+        codegen::set_optional(resource, properties::PAGE_SIZE, &self.page_size, DataType::Integer);
+        codegen::set_optional(resource, properties::DESCRIPTION, &self.description, DataType::Markdown);
+    }
+}
+
+/// Endpoints are dynamic Resources, which means that their values can be generated by a computer. They can be used to do things like construct, filter and sort lists (done in [Collections](https://atomicdata.dev/classes/Collection), for example), or to construct a version of a resource (see the [Version endpoint](https://atomicdata.dev/version)).
+///
+/// <https://atomicdata.dev/classes/Endpoint>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    pub subject: String,
+    /// A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.
+    pub description: String,
+    /// The query parameters of the endpoint
+    pub parameters: Vec<String>,
+}
+
+impl Endpoint {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Endpoint";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Endpoint {
+            subject: resource.get_subject().clone(),
+            description: codegen::required(resource, properties::DESCRIPTION, DataType::Markdown)?,
+            parameters: codegen::required(resource, properties::PARAMETERS, DataType::ResourceArray)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::DESCRIPTION, &self.description, DataType::Markdown);
+        codegen::set(resource, properties::PARAMETERS, &self.parameters, DataType::ResourceArray);
+    }
+}
+
+/// A single binary file.
+///
+/// <https://atomicdata.dev/classes/File>
+#[derive(Debug, Clone, PartialEq)]
+pub struct File {
+    pub subject: String,
+    /// The URL where a file can be downloaded.
+    pub download_url: String,
+    /// A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.
+    pub description: Option<String>,
+    /// Size of a file in bytes
+    pub filesize: Option<i64>,
+    /// A filename does not contain strings, and ends with a dot and a file extension.
+    pub filename: Option<String>,
+    /// Base64 encoded SHA-256 checksum of a binary object.
+    pub checksum: Option<String>,
+    /// Mimetype of a file sets is the type of data that is stored in the file. See https://developer.mozilla.org/en-US/docs/Web/HTTP/Basics_of_HTTP/MIME_types
+    pub mimetype: Option<String>,
+    /// An identifier used internally by the system.
+    pub internal_id: Option<String>,
+    /// Text extracted from the contents of a File (e.g. a PDF, or an image using OCR), so the File can be found by searching. Truncated to the maximum length that the server allows.
+    pub extracted_text: Option<String>,
+    /// Hex encoded SHA-256 checksum of the contents of the File that `extracted-text` was taken from. Extraction is skipped while the contents stay the same.
+    pub extracted_checksum: Option<String>,
+    /// Why text could not be extracted from the contents of a File.
+    pub extraction_error: Option<String>,
+}
+
+impl File {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/File";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(File {
+            subject: resource.get_subject().clone(),
+            download_url: codegen::required(resource, properties::DOWNLOAD_URL, DataType::String)?,
+            description: codegen::optional(resource, properties::DESCRIPTION, DataType::Markdown)?,
+            filesize: codegen::optional(resource, properties::FILESIZE, DataType::Integer)?,
+            filename: codegen::optional(resource, properties::FILENAME, DataType::String)?,
+            checksum: codegen::optional(resource, properties::CHECKSUM, DataType::String)?,
+            mimetype: codegen::optional(resource, properties::MIMETYPE, DataType::String)?,
+            internal_id: codegen::optional(resource, properties::INTERNAL_ID, DataType::String)?,
+            extracted_text: codegen::optional(resource, properties::EXTRACTED_TEXT, DataType::String)?,
+            extracted_checksum: codegen::optional(resource, properties::EXTRACTED_CHECKSUM, DataType::String)?,
+            extraction_error: codegen::optional(resource, properties::EXTRACTION_ERROR, DataType::String)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::DOWNLOAD_URL, &self.download_url, DataType::String);
+        codegen::set_optional(resource, properties::DESCRIPTION, &self.description, DataType::Markdown);
+        codegen::set_optional(resource, properties::FILESIZE, &self.filesize, DataType::Integer);
+        codegen::set_optional(resource, properties::FILENAME, &self.filename, DataType::String);
+        codegen::set_optional(resource, properties::CHECKSUM, &self.checksum, DataType::String);
+        codegen::set_optional(resource, properties::MIMETYPE, &self.mimetype, DataType::String);
+        codegen::set_optional(resource, properties::INTERNAL_ID, &self.internal_id, DataType::String);
+        codegen::set_optional(resource, properties::EXTRACTED_TEXT, &self.extracted_text, DataType::String);
+        codegen::set_optional(resource, properties::EXTRACTED_CHECKSUM, &self.extracted_checksum, DataType::String);
+        codegen::set_optional(resource, properties::EXTRACTION_ERROR, &self.extraction_error, DataType::String);
+    }
+}
+
+/// A saved mapping for importing CSV or JSON documents from other tools, used with `/import?profile=`. It is checked when it is saved, so an import doesn't fail halfway because of an unknown Property or a transformation that doesn't fit the datatype.
+///
+/// <https://atomicdata.dev/classes/ImportProfile>
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportProfile {
+    pub subject: String,
+    /// The name of a thing or person.
+    pub name: String,
+    /// The format of the documents that an [ImportProfile](https://atomicdata.dev/classes/ImportProfile) reads: `csv` (with a header row) or `json` (an array of objects).
+    pub format: String,
+    /// JSON object that maps the columns (for CSV) or keys (for JSON) of an imported document to the Properties they are stored in, e.g. `{"Title": "https://atomicdata.dev/properties/name"}`. Columns that are not mentioned are ignored.
+    pub mapping: String,
+    /// The Class of the Resources that an [ImportProfile](https://atomicdata.dev/classes/ImportProfile) creates.
+    pub target_class: String,
+    /// The parent of the Resources that an [ImportProfile](https://atomicdata.dev/classes/ImportProfile) creates. Rows are only matched with existing Resources inside this parent.
+    pub target_parent: String,
+    /// A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.
+    pub description: Option<String>,
+    /// A mapped Property that identifies a row, such as an id from the source tool. Rows with the same value as an existing Resource update that Resource instead of creating a duplicate, so importing the same document twice changes nothing.
+    pub key: Option<String>,
+    /// JSON object with the transformations that are applied to the values of a Property before they are stored, in order. For example `{"https://atomicdata.dev/properties/published": [{"dateFormat": "%d-%m-%Y"}]}`. Available: `dateFormat` (for Date and Timestamp Properties, using `%Y`, `%m`, `%d`, `%H`, `%M` and `%S`), `split` (for ResourceArray Properties, splits the value by a separator) and `replace` (an object that replaces entire values).
+    pub transforms: Option<String>,
+}
+
+impl ImportProfile {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/ImportProfile";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(ImportProfile {
+            subject: resource.get_subject().clone(),
+            name: codegen::required(resource, properties::NAME, DataType::String)?,
+            format: codegen::required(resource, properties::FORMAT, DataType::String)?,
+            mapping: codegen::required(resource, properties::MAPPING, DataType::String)?,
+            target_class: codegen::required(resource, properties::TARGET_CLASS, DataType::AtomicUrl)?,
+            target_parent: codegen::required(resource, properties::TARGET_PARENT, DataType::AtomicUrl)?,
+            description: codegen::optional(resource, properties::DESCRIPTION, DataType::Markdown)?,
+            key: codegen::optional(resource, properties::KEY, DataType::AtomicUrl)?,
+            transforms: codegen::optional(resource, properties::TRANSFORMS, DataType::String)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::NAME, &self.name, DataType::String);
+        codegen::set(resource, properties::FORMAT, &self.format, DataType::String);
+        codegen::set(resource, properties::MAPPING, &self.mapping, DataType::String);
+        codegen::set(resource, properties::TARGET_CLASS, &self.target_class, DataType::AtomicUrl);
+        codegen::set(resource, properties::TARGET_PARENT, &self.target_parent, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::DESCRIPTION, &self.description, DataType::Markdown);
+        codegen::set_optional(resource, properties::KEY, &self.key, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::TRANSFORMS, &self.transforms, DataType::String);
+    }
+}
+
+/// An Importer helps with importing data from external sources. You can post JSON-AD bodies to it, or give it a URL of a JSON-AD resource, and it will import the data into Atomic Data.
+///
+/// <https://atomicdata.dev/classes/Importer>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Importer {
+    pub subject: String,
+}
+
+impl Importer {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Importer";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Importer {
+            subject: resource.get_subject().clone(),
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+    }
+}
+
+/// Contains the [Notifications](https://atomicdata.dev/classes/Notification) of an Agent. Only the recipient can read it.
+///
+/// <https://atomicdata.dev/classes/Inbox>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inbox {
+    pub subject: String,
+    /// The Agent that a [Notification](https://atomicdata.dev/classes/Notification), Inbox or [Subscription](https://atomicdata.dev/classes/Subscription) belongs to. Only this Agent can read it, whatever the rights of its parents.
+    pub recipient: String,
+    /// The name of a thing or person.
+    pub name: Option<String>,
+}
+
+impl Inbox {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Inbox";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Inbox {
+            subject: resource.get_subject().clone(),
+            recipient: codegen::required(resource, properties::RECIPIENT, DataType::AtomicUrl)?,
+            name: codegen::optional(resource, properties::NAME, DataType::String)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::RECIPIENT, &self.recipient, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::NAME, &self.name, DataType::String);
+    }
+}
+
+/// An Invite allows you to share a link that, upon opening, grants the visitor some read or write rights. See the [Invite docs](https://docs.atomicdata.dev/invitations.html).
+///
+/// <https://atomicdata.dev/classes/Invite>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Invite {
+    pub subject: String,
+    /// The resource that the invite will grant rights for. It will often also be the target of a redirection.
+    pub target: String,
+    /// If true, provides the one who is invited with Write rights, which means allowing to edit the resource, its properties and its children.
+    pub write: Option<bool>,
+    /// Who created this resource.
+    pub created_by: Option<String>,
+    /// The list of Agents that have used this Invite.
+    pub users: Option<Vec<String>>,
+    /// The amount of usages that are left for this invite. When this reaches 0, the invite will no longer be functional.
+    pub usages_left: Option<i64>,
+}
+
+impl Invite {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Invite";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Invite {
+            subject: resource.get_subject().clone(),
+            target: codegen::required(resource, properties::TARGET, DataType::AtomicUrl)?,
+            write: codegen::optional(resource, properties::WRITE, DataType::Boolean)?,
+            created_by: codegen::optional(resource, properties::CREATED_BY, DataType::AtomicUrl)?,
+            users: codegen::optional(resource, properties::USERS, DataType::ResourceArray)?,
+            usages_left: codegen::optional(resource, properties::USAGES_LEFT, DataType::Integer)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::TARGET, &self.target, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::WRITE, &self.write, DataType::Boolean);
+        codegen::set_optional(resource, properties::CREATED_BY, &self.created_by, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::USERS, &self.users, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::USAGES_LEFT, &self.usages_left, DataType::Integer);
+    }
+}
+
+/// A long-running task that is executed by the server in the background, such as rebuilding the search index or exporting a part of the hierarchy. Poll or subscribe to a Job to see its progress.
+///
+/// <https://atomicdata.dev/classes/Job>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    pub subject: String,
+    /// The kind of work a [Job](https://atomicdata.dev/classes/Job) performs, e.g. `rebuild-search` or `export-subtree`.
+    pub job_type: String,
+    /// The state of a [Job](https://atomicdata.dev/classes/Job). One of `pending`, `running`, `done`, `failed` or `cancelled`. The creator of a Job can cancel it by setting this to `cancelled`.
+    pub job_status: String,
+    /// JSON object with the parameters of a [Job](https://atomicdata.dev/classes/Job), such as the `subject` of the resource to export.
+    pub job_params: Option<String>,
+    /// How far along a [Job](https://atomicdata.dev/classes/Job) is, from `0` to `1`.
+    pub job_progress: Option<f64>,
+    /// The resource that a finished [Job](https://atomicdata.dev/classes/Job) produced, such as an exported File.
+    pub job_result: Option<String>,
+    /// Why a [Job](https://atomicdata.dev/classes/Job) failed.
+    pub job_error: Option<String>,
+    /// Who created this resource.
+    pub created_by: Option<String>,
+}
+
+impl Job {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Job";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Job {
+            subject: resource.get_subject().clone(),
+            job_type: codegen::required(resource, properties::JOB_TYPE, DataType::Slug)?,
+            job_status: codegen::required(resource, properties::JOB_STATUS, DataType::Slug)?,
+            job_params: codegen::optional(resource, properties::JOB_PARAMS, DataType::String)?,
+            job_progress: codegen::optional(resource, properties::JOB_PROGRESS, DataType::Float)?,
+            job_result: codegen::optional(resource, properties::JOB_RESULT, DataType::AtomicUrl)?,
+            job_error: codegen::optional(resource, properties::JOB_ERROR, DataType::String)?,
+            created_by: codegen::optional(resource, properties::CREATED_BY, DataType::AtomicUrl)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::JOB_TYPE, &self.job_type, DataType::Slug);
+        codegen::set(resource, properties::JOB_STATUS, &self.job_status, DataType::Slug);
+        codegen::set_optional(resource, properties::JOB_PARAMS, &self.job_params, DataType::String);
+        codegen::set_optional(resource, properties::JOB_PROGRESS, &self.job_progress, DataType::Float);
+        codegen::set_optional(resource, properties::JOB_RESULT, &self.job_result, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::JOB_ERROR, &self.job_error, DataType::String);
+        codegen::set_optional(resource, properties::CREATED_BY, &self.created_by, DataType::AtomicUrl);
+    }
+}
+
+/// Lists the links to other servers that are broken. Created by the `check-links` Job, and served at `/link-report`.
+///
+/// <https://atomicdata.dev/classes/LinkReport>
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkReport {
+    pub subject: String,
+    /// The links to other servers that could not be fetched, with the Resource and Property that contain them.
+    pub broken_links: Option<Vec<String>>,
+    /// When the link (or the LinkReport) was last checked.
+    pub checked_at: Option<i64>,
+}
+
+impl LinkReport {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/LinkReport";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(LinkReport {
+            subject: resource.get_subject().clone(),
+            broken_links: codegen::optional(resource, properties::BROKEN_LINKS, DataType::ResourceArray)?,
+            checked_at: codegen::optional(resource, properties::CHECKED_AT, DataType::Timestamp)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set_optional(resource, properties::BROKEN_LINKS, &self.broken_links, DataType::ResourceArray);
+        codegen::set_optional(resource, properties::CHECKED_AT, &self.checked_at, DataType::Timestamp);
+    }
+}
+
+/// Tells an Agent that it has been assigned or mentioned, or warns it about something on the server. Created by the server in the Inbox of the Agent, when a Commit sets one of the `notifyOn` Properties to the Agent. Only the recipient can read it, and it can only mark it as read or destroy it.
+///
+/// <https://atomicdata.dev/classes/Notification>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub subject: String,
+    /// The Agent that a [Notification](https://atomicdata.dev/classes/Notification), Inbox or [Subscription](https://atomicdata.dev/classes/Subscription) belongs to. Only this Agent can read it, whatever the rights of its parents.
+    pub recipient: String,
+    /// The Resource that a [Notification](https://atomicdata.dev/classes/Notification) is about, such as the Task that the recipient has been assigned to.
+    pub about: String,
+    /// The Commit that created a [Notification](https://atomicdata.dev/classes/Notification).
+    pub commit: Option<String>,
+    /// The Property that the recipient of a [Notification](https://atomicdata.dev/classes/Notification) was set to, such as `assignedTo` or `mentions`.
+    pub property: Option<String>,
+    /// Whether the recipient has read a [Notification](https://atomicdata.dev/classes/Notification). The recipient marks it as read with a Commit that sets this to `true`.
+    pub is_read: Option<bool>,
+    /// Timestamp when the Commit was created (usually when it was signed).
+    pub created_at: Option<i64>,
+    /// A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.
+    pub description: Option<String>,
+}
+
+impl Notification {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Notification";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Notification {
+            subject: resource.get_subject().clone(),
+            recipient: codegen::required(resource, properties::RECIPIENT, DataType::AtomicUrl)?,
+            about: codegen::required(resource, properties::ABOUT, DataType::AtomicUrl)?,
+            commit: codegen::optional(resource, properties::COMMIT, DataType::AtomicUrl)?,
+            property: codegen::optional(resource, properties::PROPERTY__, DataType::AtomicUrl)?,
+            is_read: codegen::optional(resource, properties::IS_READ, DataType::Boolean)?,
+            created_at: codegen::optional(resource, properties::CREATED_AT, DataType::Timestamp)?,
+            description: codegen::optional(resource, properties::DESCRIPTION, DataType::Markdown)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::RECIPIENT, &self.recipient, DataType::AtomicUrl);
+        codegen::set(resource, properties::ABOUT, &self.about, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::COMMIT, &self.commit, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::PROPERTY__, &self.property, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::IS_READ, &self.is_read, DataType::Boolean);
+        codegen::set_optional(resource, properties::CREATED_AT, &self.created_at, DataType::Timestamp);
+        codegen::set_optional(resource, properties::DESCRIPTION, &self.description, DataType::Markdown);
+    }
+}
+
+/// A rule in `propertyRights` that restricts who can change a Property, e.g. so collaborators can edit the body of a document, but not its rights, parent or shortname. Usually a nested resource. The Agents in `write` may change the `restrictedProperty`, other Agents can't, even when they have write rights to the Resource. List the PublicAgent to allow everyone with write rights.
+///
+/// <https://atomicdata.dev/classes/PropertyRight>
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyRight {
+    pub subject: String,
+    /// The Property that only the Agents in `write` of this PropertyRight may change.
+    pub restricted_property: String,
+    /// The agents that can edit this resource and its children.
+    pub write: Vec<String>,
+}
+
+impl PropertyRight {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/PropertyRight";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(PropertyRight {
+            subject: resource.get_subject().clone(),
+            restricted_property: codegen::required(resource, properties::RESTRICTED_PROPERTY, DataType::AtomicUrl)?,
+            write: codegen::required(resource, properties::WRITE_, DataType::ResourceArray)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::RESTRICTED_PROPERTY, &self.restricted_property, DataType::AtomicUrl);
+        codegen::set(resource, properties::WRITE_, &self.write, DataType::ResourceArray);
+    }
+}
+
+/// A Resource that should redirect the browser to a new location. It can also set a `redirectAgent`, which is used in Invites to create an Agent Resource on the Server from a Public Key that the user posesses. See the [Invite docs](https://docs.atomicdata.dev/invitations.html).
+///
+/// <https://atomicdata.dev/classes/Redirect>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    pub subject: String,
+    /// Where a redirect should point to.
+    pub destination: String,
+    /// The Agent that is created in a Redirect action. See the [Invite docs](https://docs.atomicdata.dev/invitations.html).
+    pub redirect_agent: Option<String>,
+}
+
+impl Redirect {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Redirect";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Redirect {
+            subject: resource.get_subject().clone(),
+            destination: codegen::required(resource, properties::DESTINATION, DataType::AtomicUrl)?,
+            redirect_agent: codegen::optional(resource, properties::REDIRECT_AGENT, DataType::AtomicUrl)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::DESTINATION, &self.destination, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::REDIRECT_AGENT, &self.redirect_agent, DataType::AtomicUrl);
+    }
+}
+
+/// Removes old Commits and inactive Resources, e.g. to purge logs and Notifications after a fixed period. It affects the descendants of its `target-subtree` (or of its parent, if that is not set), optionally only the instances of its `target-class`. Commits and RetentionPolicies are never affected. A policy only takes effect if the Agent that last changed it has write rights to its subtree.
+///
+/// <https://atomicdata.dev/classes/RetentionPolicy>
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    pub subject: String,
+    /// The name of a thing or person.
+    pub name: Option<String>,
+    /// Only instances of this Class are affected by the RetentionPolicy.
+    pub target_class: Option<String>,
+    /// The RetentionPolicy affects the descendants of this Resource. Defaults to the parent of the policy.
+    pub target_subtree: Option<String>,
+    /// Commits older than this many days are removed. The last of them is kept as the starting point of the history.
+    pub keep_versions_for: Option<i64>,
+    /// Resources that have not been changed for this many days are deleted permanently.
+    pub delete_inactive_after: Option<i64>,
+    /// Keeps the affected Resources and all of their Commits forever, regardless of other RetentionPolicies.
+    pub exempt: Option<bool>,
+    /// When the server last applied the RetentionPolicy.
+    pub last_run: Option<i64>,
+}
+
+impl RetentionPolicy {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/RetentionPolicy";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(RetentionPolicy {
+            subject: resource.get_subject().clone(),
+            name: codegen::optional(resource, properties::NAME, DataType::String)?,
+            target_class: codegen::optional(resource, properties::TARGET_CLASS_, DataType::AtomicUrl)?,
+            target_subtree: codegen::optional(resource, properties::TARGET_SUBTREE, DataType::AtomicUrl)?,
+            keep_versions_for: codegen::optional(resource, properties::KEEP_VERSIONS_FOR, DataType::Integer)?,
+            delete_inactive_after: codegen::optional(resource, properties::DELETE_INACTIVE_AFTER, DataType::Integer)?,
+            exempt: codegen::optional(resource, properties::EXEMPT, DataType::Boolean)?,
+            last_run: codegen::optional(resource, properties::LAST_RUN, DataType::Timestamp)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set_optional(resource, properties::NAME, &self.name, DataType::String);
+        codegen::set_optional(resource, properties::TARGET_CLASS_, &self.target_class, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::TARGET_SUBTREE, &self.target_subtree, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::KEEP_VERSIONS_FOR, &self.keep_versions_for, DataType::Integer);
+        codegen::set_optional(resource, properties::DELETE_INACTIVE_AFTER, &self.delete_inactive_after, DataType::Integer);
+        codegen::set_optional(resource, properties::EXEMPT, &self.exempt, DataType::Boolean);
+        codegen::set_optional(resource, properties::LAST_RUN, &self.last_run, DataType::Timestamp);
+    }
+}
+
+/// The settings of an Atomic Server that can be changed at runtime. Only Agents with write rights to the root Drive can change them. Settings that affect security, such as TLS, the bind address and data paths, can only be set in the config file or environment.
+///
+/// <https://atomicdata.dev/classes/ServerSettings>
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerSettings {
+    pub subject: String,
+    /// Maximum amount of Commits per minute that an Agent can send to the `/commit` endpoint. On an Agent, it overrides the server-wide limit in the ServerSettings. `0` means no limit. Can only be set by Agents with write rights to the root Drive of the server.
+    pub commit_rate_limit: Option<i64>,
+    /// Maximum size in bytes of a single Commit sent by an Agent. On an Agent, it overrides the server-wide limit in the ServerSettings. `0` means no limit. Can only be set by Agents with write rights to the root Drive of the server.
+    pub max_commit_size: Option<i64>,
+    /// Maximum length of an array that an Agent can `set` or `push` in a single Commit. On an Agent, it overrides the server-wide limit in the ServerSettings. `0` means no limit. Can only be set by Agents with write rights to the root Drive of the server.
+    pub max_commit_array_length: Option<i64>,
+    /// Resources that have been in the trash for longer than this many days are permanently removed. If not set, the trash is only emptied manually.
+    pub trash_retention_days: Option<i64>,
+    /// Maximum size in bytes of a single file uploaded to the `/upload` endpoint. `0` means no limit.
+    pub max_upload_size: Option<i64>,
+    /// Whether Invites can be accepted on this server. If false, no new Agents can get rights using an Invite.
+    pub invites_enabled: Option<bool>,
+    /// JavaScript that is included in the body of every HTML page served by the server, e.g. for analytics.
+    pub custom_script: Option<String>,
+    /// The Properties that create a [Notification](https://atomicdata.dev/classes/Notification) for the Agents they are set to, such as `assignedTo` and `mentions`.
+    pub notify_on: Option<Vec<String>>,
+}
+
+impl ServerSettings {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/ServerSettings";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(ServerSettings {
+            subject: resource.get_subject().clone(),
+            commit_rate_limit: codegen::optional(resource, properties::COMMIT_RATE_LIMIT, DataType::Integer)?,
+            max_commit_size: codegen::optional(resource, properties::MAX_COMMIT_SIZE, DataType::Integer)?,
+            max_commit_array_length: codegen::optional(resource, properties::MAX_COMMIT_ARRAY_LENGTH, DataType::Integer)?,
+            trash_retention_days: codegen::optional(resource, properties::TRASH_RETENTION_DAYS, DataType::Integer)?,
+            max_upload_size: codegen::optional(resource, properties::MAX_UPLOAD_SIZE, DataType::Integer)?,
+            invites_enabled: codegen::optional(resource, properties::INVITES_ENABLED, DataType::Boolean)?,
+            custom_script: codegen::optional(resource, properties::CUSTOM_SCRIPT, DataType::String)?,
+            notify_on: codegen::optional(resource, properties::NOTIFY_ON, DataType::ResourceArray)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set_optional(resource, properties::COMMIT_RATE_LIMIT, &self.commit_rate_limit, DataType::Integer);
+        codegen::set_optional(resource, properties::MAX_COMMIT_SIZE, &self.max_commit_size, DataType::Integer);
+        codegen::set_optional(resource, properties::MAX_COMMIT_ARRAY_LENGTH, &self.max_commit_array_length, DataType::Integer);
+        codegen::set_optional(resource, properties::TRASH_RETENTION_DAYS, &self.trash_retention_days, DataType::Integer);
+        codegen::set_optional(resource, properties::MAX_UPLOAD_SIZE, &self.max_upload_size, DataType::Integer);
+        codegen::set_optional(resource, properties::INVITES_ENABLED, &self.invites_enabled, DataType::Boolean);
+        codegen::set_optional(resource, properties::CUSTOM_SCRIPT, &self.custom_script, DataType::String);
+        codegen::set_optional(resource, properties::NOTIFY_ON, &self.notify_on, DataType::ResourceArray);
+    }
+}
+
+/// A durable WebSocket subscription of an Agent, which survives restarts of the server. When the Agent connects again, it is subscribed again and receives the Commits it missed since its last acknowledged Commit. Only the recipient can read it.
+///
+/// <https://atomicdata.dev/classes/Subscription>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subscription {
+    pub subject: String,
+    /// The Agent that a [Notification](https://atomicdata.dev/classes/Notification), Inbox or [Subscription](https://atomicdata.dev/classes/Subscription) belongs to. Only this Agent can read it, whatever the rights of its parents.
+    pub recipient: String,
+    /// The Resource that a [Subscription](https://atomicdata.dev/classes/Subscription) receives the Commits of.
+    pub target: String,
+    /// Timestamp when the Commit was created (usually when it was signed).
+    pub created_at: i64,
+    /// If true, a [Subscription](https://atomicdata.dev/classes/Subscription) also receives the Commits of all descendants of its target.
+    pub include_children: Option<bool>,
+    /// How the client of a [Subscription](https://atomicdata.dev/classes/Subscription) receives Commits, such as `websocket`. A hint for the server, not a guarantee.
+    pub transport: Option<String>,
+    /// The newest Commit that the client of a [Subscription](https://atomicdata.dev/classes/Subscription) has acknowledged with `ACK`. Newer Commits are replayed when it reconnects.
+    pub last_acknowledged: Option<String>,
+    /// When the Agent of a [Subscription](https://atomicdata.dev/classes/Subscription) last connected or acknowledged a Commit. Subscriptions that are not seen for a while are removed.
+    pub last_seen: Option<i64>,
+    /// The `commit-sequence` of the last Commit that a [Subscription](https://atomicdata.dev/classes/Subscription) acknowledged, or that a [Webhook](https://atomicdata.dev/classes/Webhook) received. Later Commits are sent again after a restart, so every Commit is delivered at least once.
+    pub delivery_cursor: Option<i64>,
+}
+
+impl Subscription {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Subscription";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Subscription {
+            subject: resource.get_subject().clone(),
+            recipient: codegen::required(resource, properties::RECIPIENT, DataType::AtomicUrl)?,
+            target: codegen::required(resource, properties::TARGET_, DataType::AtomicUrl)?,
+            created_at: codegen::required(resource, properties::CREATED_AT, DataType::Timestamp)?,
+            include_children: codegen::optional(resource, properties::INCLUDE_CHILDREN, DataType::Boolean)?,
+            transport: codegen::optional(resource, properties::TRANSPORT, DataType::String)?,
+            last_acknowledged: codegen::optional(resource, properties::LAST_ACKNOWLEDGED, DataType::AtomicUrl)?,
+            last_seen: codegen::optional(resource, properties::LAST_SEEN, DataType::Timestamp)?,
+            delivery_cursor: codegen::optional(resource, properties::DELIVERY_CURSOR, DataType::Integer)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::RECIPIENT, &self.recipient, DataType::AtomicUrl);
+        codegen::set(resource, properties::TARGET_, &self.target, DataType::AtomicUrl);
+        codegen::set(resource, properties::CREATED_AT, &self.created_at, DataType::Timestamp);
+        codegen::set_optional(resource, properties::INCLUDE_CHILDREN, &self.include_children, DataType::Boolean);
+        codegen::set_optional(resource, properties::TRANSPORT, &self.transport, DataType::String);
+        codegen::set_optional(resource, properties::LAST_ACKNOWLEDGED, &self.last_acknowledged, DataType::AtomicUrl);
+        codegen::set_optional(resource, properties::LAST_SEEN, &self.last_seen, DataType::Timestamp);
+        codegen::set_optional(resource, properties::DELIVERY_CURSOR, &self.delivery_cursor, DataType::Integer);
+    }
+}
+
+/// A single tag / theme / category. This is used for categorizing information using the [tags](https://atomicdata.dev/properties/tags) property
+///
+/// <https://atomicdata.dev/classes/Tag>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub subject: String,
+    /// The name of a thing or person.
+    pub name: String,
+    /// A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.
+    pub description: Option<String>,
+}
+
+impl Tag {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Tag";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Tag {
+            subject: resource.get_subject().clone(),
+            name: codegen::required(resource, properties::NAME, DataType::String)?,
+            description: codegen::optional(resource, properties::DESCRIPTION, DataType::Markdown)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::NAME, &self.name, DataType::String);
+        codegen::set_optional(resource, properties::DESCRIPTION, &self.description, DataType::Markdown);
+    }
+}
+
+/// Contains the Resources that were deleted from a Drive. Deleting a Resource that is in the Trash removes it permanently.
+///
+/// <https://atomicdata.dev/classes/Trash>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trash {
+    pub subject: String,
+    /// The name of a thing or person.
+    pub name: Option<String>,
+}
+
+impl Trash {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Trash";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Trash {
+            subject: resource.get_subject().clone(),
+            name: codegen::optional(resource, properties::NAME, DataType::String)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set_optional(resource, properties::NAME, &self.name, DataType::String);
+    }
+}
+
+/// POSTs the Commits of its target (and optionally of its descendants) to a URL, in the order in which they were applied. Only Commits that the Agent that last edited the Webhook can read are sent. Every delivery includes the `commit-sequence`, since a Commit can be sent again after a restart or a failed delivery.
+///
+/// <https://atomicdata.dev/classes/Webhook>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Webhook {
+    pub subject: String,
+    /// The Resource that a [Subscription](https://atomicdata.dev/classes/Subscription) receives the Commits of.
+    pub target: String,
+    /// The http or https URL that a [Webhook](https://atomicdata.dev/classes/Webhook) POSTs Commits to.
+    pub webhook_url: String,
+    /// If true, a [Subscription](https://atomicdata.dev/classes/Subscription) also receives the Commits of all descendants of its target.
+    pub include_children: Option<bool>,
+    /// The `commit-sequence` of the last Commit that a [Subscription](https://atomicdata.dev/classes/Subscription) acknowledged, or that a [Webhook](https://atomicdata.dev/classes/Webhook) received. Later Commits are sent again after a restart, so every Commit is delivered at least once.
+    pub delivery_cursor: Option<i64>,
+    /// Why the last delivery of a [Webhook](https://atomicdata.dev/classes/Webhook) failed. Removed when a delivery succeeds.
+    pub last_delivery_error: Option<String>,
+}
+
+impl Webhook {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/Webhook";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Webhook {
+            subject: resource.get_subject().clone(),
+            target: codegen::required(resource, properties::TARGET_, DataType::AtomicUrl)?,
+            webhook_url: codegen::required(resource, properties::WEBHOOK_URL, DataType::String)?,
+            include_children: codegen::optional(resource, properties::INCLUDE_CHILDREN, DataType::Boolean)?,
+            delivery_cursor: codegen::optional(resource, properties::DELIVERY_CURSOR, DataType::Integer)?,
+            last_delivery_error: codegen::optional(resource, properties::LAST_DELIVERY_ERROR, DataType::String)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::TARGET_, &self.target, DataType::AtomicUrl);
+        codegen::set(resource, properties::WEBHOOK_URL, &self.webhook_url, DataType::String);
+        codegen::set_optional(resource, properties::INCLUDE_CHILDREN, &self.include_children, DataType::Boolean);
+        codegen::set_optional(resource, properties::DELIVERY_CURSOR, &self.delivery_cursor, DataType::Integer);
+        codegen::set_optional(resource, properties::LAST_DELIVERY_ERROR, &self.last_delivery_error, DataType::String);
+    }
+}
+
+/// A single paragraph in a Document
+///
+/// <https://atomicdata.dev/classes/elements/Paragraph>
+#[derive(Debug, Clone, PartialEq)]
+pub struct Paragraph {
+    pub subject: String,
+    /// A textual description of something. When making a description, make sure that the first few words tell the most important part. Give examples. Since the text supports markdown, you're free to use links and more.
+    pub description: String,
+    /// The parent of a Resource sets the hierarchical structure of the Resource, and therefore also the rights / grants. It is used for both navigation, structure and authorization. Parents are the inverse of [children](https://atomicdata.dev/properties/children).
+    pub parent: String,
+}
+
+impl Paragraph {
+    pub const CLASS: &'static str = "https://atomicdata.dev/classes/elements/Paragraph";
+
+    /// Reads the Properties of the Resource. Fails if a required Property is missing, or if a Value has another datatype.
+    pub fn from_resource(resource: &Resource) -> AtomicResult<Self> {
+        Ok(Paragraph {
+            subject: resource.get_subject().clone(),
+            description: codegen::required(resource, properties::DESCRIPTION, DataType::Markdown)?,
+            parent: codegen::required(resource, properties::PARENT, DataType::AtomicUrl)?,
+        })
+    }
+
+    /// Adds the Class to the Resource and sets its Properties. Removes the recommended Properties that are `None`.
+    pub fn apply_to(&self, resource: &mut Resource) {
+        codegen::add_class(resource, Self::CLASS);
+        codegen::set(resource, properties::DESCRIPTION, &self.description, DataType::Markdown);
+        codegen::set(resource, properties::PARENT, &self.parent, DataType::AtomicUrl);
+    }
+}
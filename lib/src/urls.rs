@@ -9,6 +9,8 @@ pub const AGENT: &str = "https://atomicdata.dev/classes/Agent";
 pub const COLLECTION: &str = "https://atomicdata.dev/classes/Collection";
 pub const ENDPOINT: &str = "https://atomicdata.dev/classes/Endpoint";
 pub const DRIVE: &str = "https://atomicdata.dev/classes/Drive";
+/// Defined by the Data Browser
+pub const FOLDER: &str = "https://atomicdata.dev/classes/Folder";
 pub const INVITE: &str = "https://atomicdata.dev/classes/Invite";
 pub const REDIRECT: &str = "https://atomicdata.dev/classes/Redirect";
 pub const ATOM: &str = "https://atomicdata.dev/classes/Atom";
@@ -22,6 +24,20 @@ pub const BOOKMARK: &str = "https://atomicdata.dev/class/Bookmark";
 pub const ONTOLOGY: &str = "https://atomicdata.dev/class/ontology";
 pub const ENDPOINT_RESPONSE: &str =
     "https://atomicdata.dev/ontology/server/class/endpoint-response";
+pub const JOB: &str = "https://atomicdata.dev/classes/Job";
+pub const TRASH: &str = "https://atomicdata.dev/classes/Trash";
+pub const ACTIVITY_SUMMARY: &str = "https://atomicdata.dev/classes/ActivitySummary";
+pub const LINK_REPORT: &str = "https://atomicdata.dev/classes/LinkReport";
+pub const SERVER_SETTINGS: &str = "https://atomicdata.dev/classes/ServerSettings";
+pub const IMPORT_PROFILE: &str = "https://atomicdata.dev/classes/ImportProfile";
+pub const NOTIFICATION: &str = "https://atomicdata.dev/classes/Notification";
+pub const INBOX: &str = "https://atomicdata.dev/classes/Inbox";
+pub const SUBSCRIPTION: &str = "https://atomicdata.dev/classes/Subscription";
+pub const WEBHOOK: &str = "https://atomicdata.dev/classes/Webhook";
+pub const DYNAMIC_COLLECTION: &str = "https://atomicdata.dev/classes/DynamicCollection";
+pub const AGENT_ACTIVITY: &str = "https://atomicdata.dev/classes/AgentActivity";
+pub const RETENTION_POLICY: &str = "https://atomicdata.dev/classes/RetentionPolicy";
+pub const PROPERTY_RIGHT: &str = "https://atomicdata.dev/classes/PropertyRight";
 
 // Properties
 pub const SHORTNAME: &str = "https://atomicdata.dev/properties/shortname";
@@ -34,6 +50,9 @@ pub const IS_LOCKED: &str = "https://atomicdata.dev/properties/isLocked";
 pub const DATATYPE_PROP: &str = "https://atomicdata.dev/properties/datatype";
 pub const CLASSTYPE_PROP: &str = "https://atomicdata.dev/properties/classtype";
 pub const ALLOWS_ONLY: &str = "https://atomicdata.dev/properties/allowsOnly";
+pub const KEEP_WHITESPACE: &str = "https://atomicdata.dev/properties/keepWhitespace";
+pub const MINIMUM: &str = "https://atomicdata.dev/properties/minimum";
+pub const MAXIMUM: &str = "https://atomicdata.dev/properties/maximum";
 // ... for Classes
 pub const REQUIRES: &str = "https://atomicdata.dev/properties/requires";
 pub const RECOMMENDS: &str = "https://atomicdata.dev/properties/recommends";
@@ -41,17 +60,33 @@ pub const RECOMMENDS: &str = "https://atomicdata.dev/properties/recommends";
 pub const SUBJECT: &str = "https://atomicdata.dev/properties/subject";
 pub const SET: &str = "https://atomicdata.dev/properties/set";
 pub const PUSH: &str = "https://atomicdata.dev/properties/push";
+pub const PULL: &str = "https://atomicdata.dev/properties/pull";
+pub const PUSH_UNIQUE: &str = "https://atomicdata.dev/properties/pushUnique";
+pub const PATCH: &str = "https://atomicdata.dev/properties/patch";
+pub const INCREMENT: &str = "https://atomicdata.dev/properties/increment";
+pub const MATERIALIZED: &str = "https://atomicdata.dev/properties/materialized";
+pub const BASELINE: &str = "https://atomicdata.dev/properties/baseline";
 pub const REMOVE: &str = "https://atomicdata.dev/properties/remove";
 pub const DESTROY: &str = "https://atomicdata.dev/properties/destroy";
+pub const PURGE: &str = "https://atomicdata.dev/properties/purge";
+pub const FORCE: &str = "https://atomicdata.dev/properties/force";
 pub const SIGNER: &str = "https://atomicdata.dev/properties/signer";
 pub const CREATED_AT: &str = "https://atomicdata.dev/properties/createdAt";
 pub const SIGNATURE: &str = "https://atomicdata.dev/properties/signature";
+pub const CLIENT_ID: &str = "https://atomicdata.dev/properties/clientId";
 pub const PREVIOUS_COMMIT: &str = "https://atomicdata.dev/properties/previousCommit";
 pub const LAST_COMMIT: &str = "https://atomicdata.dev/properties/lastCommit";
+pub const SCHEMA_VERSION: &str = "https://atomicdata.dev/properties/schemaVersion";
 // ... for Agents
 pub const PUBLIC_KEY: &str = "https://atomicdata.dev/properties/publicKey";
 pub const NAME: &str = "https://atomicdata.dev/properties/name";
 pub const DRIVES: &str = "https://atomicdata.dev/properties/drives";
+pub const COMMIT_RATE_LIMIT: &str = "https://atomicdata.dev/properties/commitRateLimit";
+pub const MAX_COMMIT_SIZE: &str = "https://atomicdata.dev/properties/maxCommitSize";
+pub const MAX_COMMIT_ARRAY_LENGTH: &str = "https://atomicdata.dev/properties/maxCommitArrayLength";
+pub const PINS: &str = "https://atomicdata.dev/properties/pins";
+pub const SUSPENDED: &str = "https://atomicdata.dev/properties/suspended";
+pub const SUSPENSION_REASON: &str = "https://atomicdata.dev/properties/suspensionReason";
 // ... for Collections
 pub const COLLECTION_PROPERTY: &str = "https://atomicdata.dev/properties/collection/property";
 pub const COLLECTION_VALUE: &str = "https://atomicdata.dev/properties/collection/value";
@@ -68,6 +103,8 @@ pub const COLLECTION_INCLUDE_EXTERNAL: &str =
 pub const COLLECTION_PAGE_SIZE: &str = "https://atomicdata.dev/properties/collection/pageSize";
 pub const COLLECTION_SORT_BY: &str = "https://atomicdata.dev/properties/collection/sortBy";
 pub const COLLECTION_SORT_DESC: &str = "https://atomicdata.dev/properties/collection/sortDesc";
+pub const COLLECTION_CURSOR: &str = "https://atomicdata.dev/properties/collection/cursor";
+pub const COLLECTION_NEXT_CURSOR: &str = "https://atomicdata.dev/properties/collection/nextCursor";
 // ... for Endpoints
 pub const ENDPOINT_PARAMETERS: &str = "https://atomicdata.dev/properties/endpoint/parameters";
 pub const ENDPOINT_RESULTS: &str = "https://atomicdata.dev/properties/endpoint/results";
@@ -86,6 +123,7 @@ pub const WRITE: &str = "https://atomicdata.dev/properties/write";
 pub const APPEND: &str = "https://atomicdata.dev/properties/append";
 pub const CHILDREN: &str = "https://atomicdata.dev/properties/children";
 pub const SUBRESOURCES: &str = "https://atomicdata.dev/properties/subresources";
+pub const SORT_POSITION: &str = "https://atomicdata.dev/properties/sortPosition";
 // ... for Inivtations
 pub const DESTINATION: &str = "https://atomicdata.dev/properties/destination";
 pub const TARGET: &str = "https://atomicdata.dev/properties/invite/target";
@@ -108,6 +146,9 @@ pub const MIMETYPE: &str = "https://atomicdata.dev/properties/mimetype";
 pub const INTERNAL_ID: &str = "https://atomicdata.dev/properties/internalId";
 pub const DOWNLOAD_URL: &str = "https://atomicdata.dev/properties/downloadURL";
 pub const ATTACHMENTS: &str = "https://atomicdata.dev/properties/attachments";
+pub const EXTRACTED_TEXT: &str = "https://atomicdata.dev/properties/extractedText";
+pub const EXTRACTED_CHECKSUM: &str = "https://atomicdata.dev/properties/extractedChecksum";
+pub const EXTRACTION_ERROR: &str = "https://atomicdata.dev/properties/extractionError";
 // ... for ChatRooms and Messages
 pub const MESSAGES: &str = "https://atomicdata.dev/properties/messages";
 pub const NEXT_PAGE: &str = "https://atomicdata.dev/properties/nextPage";
@@ -121,10 +162,123 @@ pub const LOCAL_ID: &str = "https://atomicdata.dev/properties/localId";
 pub const PROPERTIES: &str = "https://atomicdata.dev/properties/properties";
 pub const CLASSES: &str = "https://atomicdata.dev/properties/classes";
 pub const INSTANCES: &str = "https://atomicdata.dev/properties/instances";
+// ... for ImportProfiles
+pub const IMPORT_PROFILE_FORMAT: &str = "https://atomicdata.dev/properties/importProfile/format";
+pub const IMPORT_PROFILE_MAPPING: &str = "https://atomicdata.dev/properties/importProfile/mapping";
+pub const IMPORT_PROFILE_TARGET_CLASS: &str =
+    "https://atomicdata.dev/properties/importProfile/targetClass";
+pub const IMPORT_PROFILE_TARGET_PARENT: &str =
+    "https://atomicdata.dev/properties/importProfile/targetParent";
+pub const IMPORT_PROFILE_KEY: &str = "https://atomicdata.dev/properties/importProfile/key";
+pub const IMPORT_PROFILE_TRANSFORMS: &str =
+    "https://atomicdata.dev/properties/importProfile/transforms";
+// ... for DynamicCollections
+pub const DYNAMIC_COLLECTION_CLASS: &str =
+    "https://atomicdata.dev/properties/dynamicCollection/class";
+pub const DYNAMIC_COLLECTION_CONDITIONS: &str =
+    "https://atomicdata.dev/properties/dynamicCollection/conditions";
+pub const DYNAMIC_COLLECTION_SCOPE: &str =
+    "https://atomicdata.dev/properties/dynamicCollection/scope";
 // ... for Endpoint-Response
 pub const STATUS: &str = "https://atomicdata.dev/ontology/server/property/status";
 pub const RESPONSE_MESSAGE: &str =
     "https://atomicdata.dev/ontology/server/property/response-message";
+// ... for Jobs
+pub const JOB_TYPE: &str = "https://atomicdata.dev/properties/job/type";
+pub const JOB_PARAMS: &str = "https://atomicdata.dev/properties/job/params";
+pub const JOB_STATUS: &str = "https://atomicdata.dev/properties/job/status";
+pub const JOB_PROGRESS: &str = "https://atomicdata.dev/properties/job/progress";
+pub const JOB_RESULT: &str = "https://atomicdata.dev/properties/job/result";
+pub const JOB_ERROR: &str = "https://atomicdata.dev/properties/job/error";
+pub const CREATED_BY: &str = "https://atomicdata.dev/properties/createdBy";
+// ... for Locks
+pub const LOCKED_BY: &str = "https://atomicdata.dev/properties/lockedBy";
+pub const LOCKED_UNTIL: &str = "https://atomicdata.dev/properties/lockedUntil";
+// ... for Deprecation
+pub const DEPRECATED: &str = "https://atomicdata.dev/properties/deprecated";
+pub const REPLACED_BY: &str = "https://atomicdata.dev/properties/replacedBy";
+// ... for Trash
+pub const DELETED_AT: &str = "https://atomicdata.dev/properties/deletedAt";
+pub const TRASHED_FROM: &str = "https://atomicdata.dev/properties/trashedFrom";
+// ... for ActivitySummaries
+pub const ACTIVITY_DRIVE: &str = "https://atomicdata.dev/properties/activity/drive";
+pub const ACTIVITY_SINCE: &str = "https://atomicdata.dev/properties/activity/since";
+pub const COMMIT_COUNT: &str = "https://atomicdata.dev/properties/activity/commitCount";
+pub const ACTIVE_AGENTS: &str = "https://atomicdata.dev/properties/activity/activeAgents";
+pub const RECENTLY_CREATED: &str = "https://atomicdata.dev/properties/activity/recentlyCreated";
+pub const STORAGE_USED: &str = "https://atomicdata.dev/properties/activity/storageUsed";
+pub const INVITE_REDEMPTIONS: &str = "https://atomicdata.dev/properties/activity/inviteRedemptions";
+// ... for default rights of Drives
+pub const DEFAULT_READ: &str = "https://atomicdata.dev/properties/defaultRead";
+pub const DEFAULT_WRITE: &str = "https://atomicdata.dev/properties/defaultWrite";
+pub const NEW_RESOURCES_PUBLIC: &str = "https://atomicdata.dev/properties/newResourcesPublic";
+pub const GRAPH_IRI: &str = "https://atomicdata.dev/properties/graphIri";
+pub const SUBJECT_STRATEGY: &str = "https://atomicdata.dev/properties/subjectStrategy";
+pub const APPLIED_READ: &str = "https://atomicdata.dev/properties/appliedRead";
+pub const APPLIED_WRITE: &str = "https://atomicdata.dev/properties/appliedWrite";
+// ... for LinkReports
+pub const BROKEN_LINKS: &str = "https://atomicdata.dev/properties/linkReport/brokenLinks";
+pub const LINK_URL: &str = "https://atomicdata.dev/properties/linkReport/url";
+pub const LINK_SUBJECT: &str = "https://atomicdata.dev/properties/linkReport/subject";
+pub const LINK_PROPERTY: &str = "https://atomicdata.dev/properties/linkReport/property";
+pub const LINK_STATUS: &str = "https://atomicdata.dev/properties/linkReport/status";
+pub const LINK_ERROR: &str = "https://atomicdata.dev/properties/linkReport/error";
+pub const LINK_CHECKED_AT: &str = "https://atomicdata.dev/properties/linkReport/checkedAt";
+// ... for ServerSettings
+pub const TRASH_RETENTION_DAYS: &str = "https://atomicdata.dev/properties/trashRetentionDays";
+pub const MAX_UPLOAD_SIZE: &str = "https://atomicdata.dev/properties/maxUploadSize";
+pub const INVITES_ENABLED: &str = "https://atomicdata.dev/properties/invitesEnabled";
+pub const CUSTOM_SCRIPT: &str = "https://atomicdata.dev/properties/customScript";
+pub const NOTIFY_ON: &str = "https://atomicdata.dev/properties/notifyOn";
+// ... for Notifications
+pub const ASSIGNED_TO: &str = "https://atomicdata.dev/properties/assignedTo";
+pub const MENTIONS: &str = "https://atomicdata.dev/properties/mentions";
+pub const RECIPIENT: &str = "https://atomicdata.dev/properties/notification/recipient";
+pub const NOTIFICATION_ABOUT: &str = "https://atomicdata.dev/properties/notification/about";
+pub const NOTIFICATION_COMMIT: &str = "https://atomicdata.dev/properties/notification/commit";
+pub const NOTIFICATION_PROPERTY: &str = "https://atomicdata.dev/properties/notification/property";
+pub const IS_READ: &str = "https://atomicdata.dev/properties/notification/isRead";
+// ... for durable Subscriptions
+pub const SUBSCRIPTION_TARGET: &str = "https://atomicdata.dev/properties/subscription/target";
+pub const INCLUDE_CHILDREN: &str = "https://atomicdata.dev/properties/subscription/includeChildren";
+pub const TRANSPORT: &str = "https://atomicdata.dev/properties/subscription/transport";
+pub const LAST_ACKNOWLEDGED: &str =
+    "https://atomicdata.dev/properties/subscription/lastAcknowledged";
+pub const LAST_SEEN: &str = "https://atomicdata.dev/properties/subscription/lastSeen";
+pub const DELIVERY_CURSOR: &str = "https://atomicdata.dev/properties/subscription/cursor";
+pub const COMMIT_SEQUENCE: &str = "https://atomicdata.dev/properties/commitSequence";
+// ... for Webhooks
+pub const WEBHOOK_URL: &str = "https://atomicdata.dev/properties/webhook/url";
+pub const WEBHOOK_LAST_ERROR: &str = "https://atomicdata.dev/properties/webhook/lastError";
+// ... for AgentActivity
+pub const ACTIVITY_AGENT: &str = "https://atomicdata.dev/properties/activity/agent";
+pub const ACTIVITY_DATE: &str = "https://atomicdata.dev/properties/activity/date";
+pub const ACTIVITY_READS: &str = "https://atomicdata.dev/properties/activity/reads";
+pub const ACTIVITY_COMMITS: &str = "https://atomicdata.dev/properties/activity/commits";
+pub const ACTIVITY_DOWNLOADS: &str = "https://atomicdata.dev/properties/activity/downloads";
+pub const ACTIVITY_DOWNLOAD_BYTES: &str =
+    "https://atomicdata.dev/properties/activity/downloadBytes";
+pub const ACTIVITY_BYTES_SERVED: &str = "https://atomicdata.dev/properties/activity/bytesServed";
+pub const ACTIVITY_SUBJECTS: &str = "https://atomicdata.dev/properties/activity/subjects";
+// ... for Errors
+pub const ERROR_SUBJECT: &str = "https://atomicdata.dev/properties/error/subject";
+pub const ERROR_PROPERTY: &str = "https://atomicdata.dev/properties/error/property";
+pub const ERROR_PROPERTY_DESCRIPTION: &str =
+    "https://atomicdata.dev/properties/error/propertyDescription";
+pub const ERROR_DATATYPE: &str = "https://atomicdata.dev/properties/error/datatype";
+pub const ERROR_EXAMPLE: &str = "https://atomicdata.dev/properties/error/example";
+pub const ERROR_RIGHTS_TRACE: &str = "https://atomicdata.dev/properties/error/rightsTrace";
+// ... for RetentionPolicies
+pub const RETENTION_TARGET_CLASS: &str = "https://atomicdata.dev/properties/retention/targetClass";
+pub const RETENTION_TARGET_SUBTREE: &str =
+    "https://atomicdata.dev/properties/retention/targetSubtree";
+pub const KEEP_VERSIONS_FOR: &str = "https://atomicdata.dev/properties/retention/keepVersionsFor";
+pub const DELETE_INACTIVE_AFTER: &str =
+    "https://atomicdata.dev/properties/retention/deleteInactiveAfter";
+pub const RETENTION_EXEMPT: &str = "https://atomicdata.dev/properties/retention/exempt";
+pub const RETENTION_LAST_RUN: &str = "https://atomicdata.dev/properties/retention/lastRun";
+pub const PROPERTY_RIGHTS: &str = "https://atomicdata.dev/properties/propertyRights";
+pub const RESTRICTED_PROPERTY: &str = "https://atomicdata.dev/properties/restrictedProperty";
 // Datatypes
 pub const STRING: &str = "https://atomicdata.dev/datatypes/string";
 pub const MARKDOWN: &str = "https://atomicdata.dev/datatypes/markdown";
@@ -156,3 +310,6 @@ pub const PATH_IMPORT: &str = "/import";
 pub const PATH_FETCH_BOOKMARK: &str = "/fetch-bookmark";
 pub const PATH_QUERY: &str = "/query";
 pub const PATH_PRUNE_TESTS: &str = "/prunetests";
+pub const PATH_RESTORE: &str = "/restore";
+pub const PATH_REORDER: &str = "/reorder";
+pub const PATH_ACTIVITY: &str = "/activity";
@@ -27,6 +27,38 @@ pub fn check_valid_url(url: &str) -> AtomicResult<()> {
     Ok(())
 }
 
+/// Whether the value is a relative reference (RFC 3986) like `./attachment`, `../other`, `#section` or `/path`.
+/// Bare names like `attachment` are not seen as relative references, as these are `localId`s when importing.
+pub fn is_relative_reference(value: &str) -> bool {
+    value == "."
+        || value == ".."
+        || ["./", "../", "#", "/", "?"]
+            .iter()
+            .any(|prefix| value.starts_with(prefix))
+}
+
+/// Resolves a relative reference against `base`, following RFC 3986.
+/// Values that are not relative references, such as absolute URLs, are returned as they are.
+/// If `strict` is true, references that resolve to another origin than the `base` are rejected.
+pub fn resolve_reference(base: &str, value: &str, strict: bool) -> AtomicResult<String> {
+    if !is_relative_reference(value) {
+        return Ok(value.into());
+    }
+    let base_url = Url::parse(base)
+        .map_err(|e| format!("Can't resolve {} against base {}: {}", value, base, e))?;
+    let resolved = base_url
+        .join(value)
+        .map_err(|e| format!("Can't resolve {} against base {}: {}", value, base, e))?;
+    if strict && resolved.origin() != base_url.origin() {
+        return Err(format!(
+            "Relative reference {} resolves to {}, which is outside of {}",
+            value, resolved, base
+        )
+        .into());
+    }
+    Ok(resolved.to_string())
+}
+
 /// Returns the current timestamp in milliseconds since UNIX epoch
 pub fn now() -> i64 {
     std::time::SystemTime::now()
@@ -45,3 +77,58 @@ pub fn random_string(n: usize) -> String {
         .collect();
     random_string.to_lowercase()
 }
+
+/// Converts a `YYYY-MM-DD` date to a unix timestamp in milliseconds (at midnight UTC).
+pub fn date_to_millis(date: &str) -> Option<i64> {
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // Days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+    Some(days * 24 * 60 * 60 * 1000)
+}
+
+/// Converts an ISO 8601 / RFC 3339 datetime (e.g. `2024-01-31T12:00:00.5+01:00`) to a unix timestamp in milliseconds.
+/// Datetimes without an offset are treated as UTC. A plain date is read as midnight UTC.
+pub fn datetime_to_millis(datetime: &str) -> Option<i64> {
+    let (date, time) = match datetime.split_once(['T', ' ']) {
+        Some((date, time)) => (date, time),
+        None => return date_to_millis(datetime),
+    };
+    let day_millis = date_to_millis(date)?;
+    let (time, offset_minutes) = if let Some(time) = time.strip_suffix(['Z', 'z']) {
+        (time, 0)
+    } else if let Some(pos) = time.rfind(['+', '-']) {
+        let (time, offset) = time.split_at(pos);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let offset = &offset[1..];
+        let (hours, minutes) = match offset.split_once(':') {
+            Some(parts) => parts,
+            None if offset.len() == 4 => offset.split_at(2),
+            None => (offset, "0"),
+        };
+        let minutes = hours.parse::<i64>().ok()? * 60 + minutes.parse::<i64>().ok()?;
+        (time, sign * minutes)
+    } else {
+        (time, 0)
+    };
+    let mut parts = time.splitn(3, ':');
+    let hours: i64 = parts.next()?.parse().ok()?;
+    let minutes: i64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next().unwrap_or("0").parse().ok()?;
+    if hours > 24 || minutes > 59 || !(0.0..61.0).contains(&seconds) {
+        return None;
+    }
+    let time_millis = (hours * 60 + minutes - offset_minutes) * 60 * 1000;
+    Some(day_millis + time_millis + (seconds * 1000.0).round() as i64)
+}
@@ -7,8 +7,12 @@
 /// Validates:
 ///
 /// - [X] If the Values can be parsed using their Datatype (e.g. if Integers are integers)
+/// - [X] If the Values still match their Property, after its datatype or allowsOnly has been changed
 /// - [X] If all required fields of the class are present
 /// - [X] If the URLs are publicly accessible
+/// - [X] If the parents of a Resource form a loop
+/// - [X] If `replaced-by` points to an existing Resource that is not deprecated (as a warning)
+/// - [X] Groups the Resources with issues by the schema version they were last written under
 /// - [ ] ..and return the right type of data?
 /// - [X] Returns a report, instead of throwing an error
 #[allow(dead_code, unreachable_code)]
@@ -17,19 +21,26 @@ pub fn validate_store(
     fetch_items: bool,
 ) -> crate::validate::ValidationReport {
     type Error = String;
-    let mut resource_count: u8 = 0;
-    let mut atom_count: u8 = 0;
+    let mut resource_count: usize = 0;
+    let mut atom_count: usize = 0;
     let mut unfetchable: Vec<(String, Error)> = Vec::new();
     let mut invalid_value: Vec<(crate::Atom, Error)> = Vec::new();
+    let mut schema_violations: Vec<(crate::Atom, Error)> = Vec::new();
     let mut unfetchable_props: Vec<(String, Error)> = Vec::new();
     let mut unfetchable_classes: Vec<(String, Error)> = Vec::new();
     // subject, property, class
     let mut missing_props: Vec<(String, String, String)> = Vec::new();
+    let mut replacement_warnings: Vec<(String, String)> = Vec::new();
+    let mut datatype_mismatches: Vec<crate::coerce::DatatypeMismatch> = Vec::new();
+    // Property => its datatype before the last change, read from its Commits once
+    let mut previous_datatypes = std::collections::HashMap::new();
+    let mut issues_by_schema_version: std::collections::BTreeMap<String, usize> =
+        std::collections::BTreeMap::new();
     for resource in store.all_resources(true) {
+        let issues_before =
+            unfetchable.len() + invalid_value.len() + schema_violations.len() + missing_props.len();
         let subject = resource.get_subject();
         let propvals = resource.get_propvals();
-        println!("Subject: {:?}", subject);
-        println!("Resource: {:?}", propvals);
         resource_count += 1;
 
         if fetch_items {
@@ -39,6 +50,16 @@ pub fn validate_store(
             }
         }
 
+        if let Some(warning) = replacement_warning(store, &resource) {
+            replacement_warnings.push((subject.clone(), warning));
+        }
+
+        datatype_mismatches.extend(crate::coerce::find_mismatches(
+            store,
+            &resource,
+            &mut previous_datatypes,
+        ));
+
         let mut found_props: Vec<String> = Vec::new();
 
         for (prop_url, value) in propvals {
@@ -52,14 +73,32 @@ pub fn validate_store(
                 }
             };
 
-            // Maybe this is no longer needed, because no store uses strings anymore
-            match crate::Value::new(&value.to_string(), &property.data_type) {
-                Ok(_) => {}
-                Err(e) => invalid_value.push((
-                    crate::Atom::new(subject.clone(), prop_url.clone(), value.clone()),
-                    e.to_string(),
-                )),
+            // Values are stored typed, so only the ones with another datatype are parsed again.
+            // Parsing the string of a ResourceArray would fail, since it isn't a JSON array.
+            if value.datatype() != property.data_type {
+                if let Err(e) = crate::Value::new(&value.to_string(), &property.data_type) {
+                    invalid_value.push((
+                        crate::Atom::new(subject.clone(), prop_url.clone(), value.clone()),
+                        e.to_string(),
+                    ));
+                }
+            }
+            // Values that were stored before a forced change to the Property
+            let violation = if value.datatype() != property.data_type {
+                Err(format!(
+                    "Stored as {}, but the Property requires {}",
+                    value.datatype(),
+                    property.data_type
+                ))
+            } else {
+                property.check_value(value)
             };
+            if let Err(e) = violation {
+                schema_violations.push((
+                    crate::Atom::new(subject.clone(), prop_url.clone(), value.clone()),
+                    e,
+                ));
+            }
             found_props.push(prop_url.clone());
         }
         let classes = match store.get_classes_for_subject(subject) {
@@ -70,12 +109,9 @@ pub fn validate_store(
             }
         };
         for class in classes {
-            println!("Class: {:?}", class.shortname);
-            println!("Found: {:?}", found_props);
             for required_prop_subject in class.requires {
                 match store.get_property(&required_prop_subject) {
                     Ok(required_prop) => {
-                        println!("Required: {:?}", required_prop.shortname);
                         if !found_props.contains(&required_prop.subject) {
                             missing_props.push((
                                 subject.clone(),
@@ -88,25 +124,70 @@ pub fn validate_store(
                 }
             }
         }
-        println!("{:?} Valid", subject);
+        let issues_after =
+            unfetchable.len() + invalid_value.len() + schema_violations.len() + missing_props.len();
+        if issues_after > issues_before {
+            let version = crate::schema_version::label(crate::schema_version::last_written_under(
+                store, &resource,
+            ));
+            *issues_by_schema_version.entry(version).or_default() += 1;
+        }
     }
     crate::validate::ValidationReport {
+        hierarchy_cycles: crate::hierarchy::find_cycles(store),
         unfetchable,
         unfetchable_classes,
         unfetchable_props,
         invalid_value,
+        schema_violations,
+        datatype_mismatches,
+        replacement_warnings,
+        issues_by_schema_version,
         resource_count,
         atom_count,
     }
 }
 
+/// Returns a warning if the `replaced-by` of the Resource points to a Resource that can't be found, or that is deprecated itself.
+pub fn replacement_warning(
+    store: &impl crate::Storelike,
+    resource: &crate::Resource,
+) -> Option<String> {
+    let replacement = resource.get(crate::urls::REPLACED_BY).ok()?.to_string();
+    match store.get_resource(&replacement) {
+        Err(e) => Some(format!("Replacement {} can't be found: {}", replacement, e)),
+        Ok(found)
+            if found
+                .get(crate::urls::DEPRECATED)
+                .and_then(|v| v.to_bool())
+                .unwrap_or(false) =>
+        {
+            Some(format!("Replacement {} is deprecated itself", replacement))
+        }
+        Ok(_) => None,
+    }
+}
+
 pub struct ValidationReport {
-    pub resource_count: u8,
-    pub atom_count: u8,
+    pub resource_count: usize,
+    pub atom_count: usize,
     pub unfetchable: Vec<(String, String)>,
     pub invalid_value: Vec<(crate::Atom, String)>,
+    /// Values that don't match the current datatype or `allowsOnly` of their Property,
+    /// e.g. because the Property was changed with `force`.
+    pub schema_violations: Vec<(crate::Atom, String)>,
+    /// The schema violations caused by a changed datatype, with the previous datatype of the Property if its Commits are known.
+    /// Use [crate::coerce::coerce_store] to convert the ones that are `convertible`.
+    pub datatype_mismatches: Vec<crate::coerce::DatatypeMismatch>,
     pub unfetchable_props: Vec<(String, String)>,
     pub unfetchable_classes: Vec<(String, String)>,
+    /// Loops in the hierarchy, which make every rights check on these Resources fail. See [crate::hierarchy::find_cycles].
+    pub hierarchy_cycles: Vec<Vec<String>>,
+    /// Deprecated Resources whose `replaced-by` is missing or deprecated itself.
+    /// These are warnings, they don't make the store invalid.
+    pub replacement_warnings: Vec<(String, String)>,
+    /// The number of Resources with issues, by the schema version they were last written under, see [crate::schema_version].
+    pub issues_by_schema_version: std::collections::BTreeMap<String, usize>,
 }
 
 impl ValidationReport {
@@ -115,11 +196,16 @@ impl ValidationReport {
             && self.unfetchable_classes.is_empty()
             && self.unfetchable_props.is_empty()
             && self.invalid_value.is_empty()
+            && self.schema_violations.is_empty()
+            && self.hierarchy_cycles.is_empty()
     }
 }
 
 impl std::fmt::Display for ValidationReport {
     fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (subject, warning) in &self.replacement_warnings {
+            fmt.write_str(&format!("Warning for {}: {} \n", subject, warning))?;
+        }
         if self.is_valid() {
             fmt.write_str("Valid!")?;
             return Ok(());
@@ -136,6 +222,49 @@ impl std::fmt::Display for ValidationReport {
         for (atom, error) in &self.invalid_value {
             fmt.write_str(&format!("Invalid value {:?}: {} \n", atom, error))?;
         }
+        for cycle in &self.hierarchy_cycles {
+            fmt.write_str(&format!(
+                "Hierarchy cycle: {} -> {} \n",
+                cycle.join(" -> "),
+                cycle[0]
+            ))?;
+        }
+        if !self.schema_violations.is_empty() {
+            fmt.write_str("Values that don't match their current Property:\n")?;
+        }
+        for (atom, error) in &self.schema_violations {
+            fmt.write_str(&format!(
+                "  {} {}: {} \n",
+                atom.subject, atom.property, error
+            ))?;
+        }
+        if !self.datatype_mismatches.is_empty() {
+            fmt.write_str("Values stored with another datatype than their Property requires:\n")?;
+        }
+        for mismatch in &self.datatype_mismatches {
+            fmt.write_str(&format!(
+                "  {} {}: stored as {}, requires {}{}{} \n",
+                mismatch.subject,
+                mismatch.property,
+                mismatch.stored,
+                mismatch.expected,
+                match &mismatch.previous {
+                    Some(previous) => format!(" (was {})", previous),
+                    None => String::new(),
+                },
+                if mismatch.convertible {
+                    ", can be converted"
+                } else {
+                    ""
+                }
+            ))?;
+        }
+        if !self.issues_by_schema_version.is_empty() {
+            fmt.write_str("Resources with issues by schema version:\n")?;
+        }
+        for (version, count) in &self.issues_by_schema_version {
+            fmt.write_str(&format!("  {}: {} \n", version, count))?;
+        }
         Ok(())
     }
 }
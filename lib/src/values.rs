@@ -1,8 +1,12 @@
 //! A value is the part of an Atom that contains the actual information.
 
 use crate::{
-    datatype::match_datatype, datatype::DataType, errors::AtomicResult, resources::PropVals,
-    utils::check_valid_url, Resource,
+    datatype::match_datatype,
+    datatype::DataType,
+    errors::AtomicResult,
+    resources::PropVals,
+    utils::{check_valid_url, resolve_reference},
+    Resource,
 };
 use regex::Regex;
 use serde::{Deserialize, Serialize};
@@ -141,20 +145,14 @@ impl Value {
                 value: value.into(),
                 datatype: unsup_url.into(),
             })),
-            DataType::Boolean => {
-                let bool = match value {
-                    "true" => true,
-                    "false" => false,
-                    other => {
-                        return Err(format!(
-                            "Not a valid boolean value: {}, should be 'true' or 'false'.",
-                            other
-                        )
-                        .into())
-                    }
-                };
-                Ok(Value::Boolean(bool))
-            }
+            DataType::Boolean => match crate::normalize::parse_bool(value) {
+                Some(bool) => Ok(Value::Boolean(bool)),
+                None => Err(format!(
+                    "Not a valid boolean value: {}, should be 'true' or 'false'.",
+                    value
+                )
+                .into()),
+            },
         }
     }
 
@@ -219,6 +217,31 @@ impl Value {
         }
     }
 
+    /// Returns a Float, if the Atom is a number.
+    pub fn to_float(&self) -> AtomicResult<f64> {
+        match self {
+            Value::Float(float) => Ok(float.to_owned()),
+            Value::Integer(int) => Ok(*int as f64),
+            _ => self.to_string().parse::<f64>().map_err(|e| {
+                format!("Value {} cannot be converted into float. {}", self, e).into()
+            }),
+        }
+    }
+
+    /// Adds two numbers. Two Integers make an Integer, otherwise the sum is a Float.
+    pub fn add_number(&self, other: &Value) -> AtomicResult<Value> {
+        match (self, other) {
+            (Value::Integer(a), Value::Integer(b)) => a
+                .checked_add(*b)
+                .map(Value::Integer)
+                .ok_or_else(|| format!("Adding {} to {} overflows", b, a).into()),
+            (Value::Integer(_) | Value::Float(_), Value::Integer(_) | Value::Float(_)) => {
+                Ok(Value::Float(self.to_float()? + other.to_float()?))
+            }
+            _ => Err(format!("Can only add numbers, not {} and {}", self, other).into()),
+        }
+    }
+
     /// Returns a PropVals Hashmap, if the Atom is a NestedResource
     pub fn to_nested(&self) -> AtomicResult<&PropVals> {
         if let Value::NestedResource(SubResource::Nested(nested)) = self {
@@ -227,6 +250,25 @@ impl Value {
         Err(format!("Value {} is not a Nested Resource", self).into())
     }
 
+    /// Resolves relative references in `AtomicUrl` and `ResourceArray` values against `base`, see [crate::utils::resolve_reference].
+    /// Other values, and URLs that are already absolute, are returned unchanged.
+    pub fn resolve_references(&self, base: &str, strict: bool) -> AtomicResult<Value> {
+        match self {
+            Value::AtomicUrl(url) => Ok(Value::AtomicUrl(resolve_reference(base, url, strict)?)),
+            Value::ResourceArray(items) => items
+                .iter()
+                .map(|item| match item {
+                    SubResource::Subject(s) => {
+                        Ok(SubResource::Subject(resolve_reference(base, s, strict)?))
+                    }
+                    other => Ok(other.clone()),
+                })
+                .collect::<AtomicResult<Vec<SubResource>>>()
+                .map(Value::ResourceArray),
+            other => Ok(other.clone()),
+        }
+    }
+
     /// Returns a Lexicographically sortable string representation of the value
     pub fn to_sortable_string(&self) -> SortableValue {
         match self {
@@ -235,6 +277,42 @@ impl Value {
         }
     }
 
+    /// Length in bytes of textual values, such as Strings and Markdown.
+    /// Returns 0 for other values, like numbers and ResourceArrays.
+    pub fn text_len(&self) -> usize {
+        match self {
+            Value::String(s)
+            | Value::Markdown(s)
+            | Value::Slug(s)
+            | Value::AtomicUrl(s)
+            | Value::Date(s) => s.len(),
+            Value::Unsupported(u) => u.value.len(),
+            _ => 0,
+        }
+    }
+
+    /// Shortens Strings and Markdown that are longer than `max` bytes, and appends a marker with their original length.
+    /// Returns the original length if the value was shortened.
+    /// Only use this for serializing responses - never save a truncated Value.
+    pub fn truncate_text(&mut self, max: usize) -> Option<usize> {
+        let text = match self {
+            Value::String(s) | Value::Markdown(s) => s,
+            Value::Unsupported(u) => &mut u.value,
+            _ => return None,
+        };
+        let len = text.len();
+        if len <= max {
+            return None;
+        }
+        let mut end = max;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str(&format!("{}{} bytes]", TRUNCATED_MARKER, len));
+        Some(len)
+    }
+
     /// Converts one Value to a bunch of indexable items.
     /// Returns None for unsupported types.
     pub fn to_reference_index_strings(&self) -> Option<Vec<ReferenceString>> {
@@ -252,6 +330,9 @@ impl Value {
     }
 }
 
+/// Appended to truncated values, followed by their original length, see [Value::truncate_text].
+pub const TRUNCATED_MARKER: &str = "… [truncated, total length ";
+
 /// A value that is meant for checking reference indexes.
 /// short. Vectors of subjects are turned into individual ReferenceStrings.
 pub type ReferenceString = String;
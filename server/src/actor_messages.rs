@@ -15,6 +15,76 @@ pub struct Subscribe {
     pub addr: Addr<crate::handlers::web_sockets::WebSocketConnection>,
     pub subject: String,
     pub agent: String,
+    /// Stores the subscription, so it survives restarts, see [crate::durable_subscriptions].
+    pub durable: Option<crate::durable_subscriptions::DurableOptions>,
+}
+
+/// Unsubscribes a WebSocketConnection from a Subject, and removes the durable subscription of the Agent.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Unsubscribe {
+    pub addr: Addr<crate::handlers::web_sockets::WebSocketConnection>,
+    pub subject: String,
+    pub agent: String,
+}
+
+/// Moves the high-water mark of the durable subscriptions of the Agent to this Commit, see [crate::durable_subscriptions].
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Acknowledge {
+    pub addr: Addr<crate::handlers::web_sockets::WebSocketConnection>,
+    pub commit: String,
+    pub agent: String,
+}
+
+/// Sets the presence of a WebSocketConnection on a Subject, see [crate::presence].
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetPresence {
+    pub addr: Addr<crate::handlers::web_sockets::WebSocketConnection>,
+    pub subject: String,
+    pub agent: String,
+    pub state: crate::presence::PresenceState,
+}
+
+/// Asks for the presences on a Subject. The answer is sent to `addr` as a `PRESENCE_LIST` message.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct WhoIsPresent {
+    pub addr: Addr<crate::handlers::web_sockets::WebSocketConnection>,
+    pub subject: String,
+    pub agent: String,
+}
+
+/// Sent when a WebSocketConnection signs in, so it receives the new Notifications of the Agent, see [atomic_lib::plugins::notifications].
+/// The connection is also subscribed again to the durable subscriptions of the Agent, and receives the Commits it missed.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Connect {
+    pub addr: Addr<crate::handlers::web_sockets::WebSocketConnection>,
+    pub agent: String,
+}
+
+/// Sent when a WebSocketConnection closes, which removes its presences.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Disconnect {
+    pub addr: Addr<crate::handlers::web_sockets::WebSocketConnection>,
+}
+
+/// Subscribes to all Commits, see [crate::replication].
+/// The JSON-AD of every applied Commit is sent to `sender`, until the receiver is dropped.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SubscribeAll {
+    pub sender: futures::channel::mpsc::UnboundedSender<String>,
+}
+
+/// Lets the CommitMonitor schedule repairs of failed side effects, see [crate::side_effects].
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetJobQueue {
+    pub job_queue: crate::jobs::JobQueue,
 }
 
 /// A message containing a Resource, which should be sent to subscribers
@@ -23,4 +93,6 @@ pub struct Subscribe {
 pub struct CommitMessage {
     /// Full resource of the Commit itself, the new resource, and the old one
     pub commit_response: atomic_lib::commit::CommitResponse,
+    /// The place of the Commit in the commit log, which clients use to skip Commits they already received
+    pub sequence: Option<u64>,
 }
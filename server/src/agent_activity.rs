@@ -0,0 +1,535 @@
+//! Counts what every Agent does, to detect leaked credentials that are used to quietly read or download data.
+//! Signed requests (reads), applied Commits, downloads, the bytes of Resources and files that were served, and the distinct subjects are counted per Agent, per UTC hour and day.
+//! Counting only takes a lock and a few additions. Every [FLUSH_INTERVAL], the counters of the day are written to an `AgentActivity` Resource per Agent,
+//! without a Commit, so the history doesn't grow. These Resources have no parent, so only the server can read them. Admins use `/agent-activity`.
+//!
+//! The counters are compared to the `--activity-limits` when flushing. An exceeded limit is reported once per hour or day, with a Notification to every admin (the Agents with write rights to the root Drive).
+//! With `--activity-suspend`, the Agent is suspended as well, by setting [urls::SUSPENDED] on it with a Commit. Suspended Agents can't sign requests or Commits, see [atomic_lib::agents::check_not_suspended].
+//! An admin lifts the suspension with a Commit that removes `suspended` or sets it to `false`. Admins are never suspended, so the server can't lock everyone out.
+//! The counters in memory start at zero when the server restarts.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use atomic_lib::{
+    agents::ForAgent, commit::CommitBuilder, hierarchy::check_write, plugins::notifications, urls,
+    Db, Resource, Storelike, Value,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+
+use crate::errors::{AtomicServerError, AtomicServerResult};
+
+/// How often the counters are stored and compared to the limits.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+/// Days of counters that are kept in memory, including today.
+const RETAINED_DAYS: usize = 7;
+/// Hours of counters that are kept in memory, including the current hour.
+const RETAINED_HOURS: usize = 24;
+/// Distinct subjects that are remembered per Agent per day, so a scraper can't use up the memory. The count stops at this number.
+const MAX_TRACKED_SUBJECTS: usize = 100_000;
+
+/// What is counted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    Reads,
+    Commits,
+    Downloads,
+    DownloadBytes,
+    BytesServed,
+    Subjects,
+}
+
+impl Metric {
+    const ALL: [Metric; 6] = [
+        Metric::Reads,
+        Metric::Commits,
+        Metric::Downloads,
+        Metric::DownloadBytes,
+        Metric::BytesServed,
+        Metric::Subjects,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Metric::Reads => "reads",
+            Metric::Commits => "commits",
+            Metric::Downloads => "downloads",
+            Metric::DownloadBytes => "download-bytes",
+            Metric::BytesServed => "bytes-served",
+            Metric::Subjects => "subjects",
+        }
+    }
+
+    fn count(&self, counts: &Counts) -> u64 {
+        match self {
+            Metric::Reads => counts.reads,
+            Metric::Commits => counts.commits,
+            Metric::Downloads => counts.downloads,
+            Metric::DownloadBytes => counts.download_bytes,
+            Metric::BytesServed => counts.bytes_served,
+            Metric::Subjects => counts.subjects,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Period {
+    Hour,
+    Day,
+}
+
+/// A limit set with `--activity-limits`, such as `reads-per-hour=5000`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ActivityLimit {
+    pub metric: Metric,
+    pub period: Period,
+    pub max: u64,
+}
+
+impl ActivityLimit {
+    /// The name without the maximum, e.g. `reads-per-hour`.
+    pub fn name(&self) -> String {
+        let period = match self.period {
+            Period::Hour => "hour",
+            Period::Day => "day",
+        };
+        format!("{}-per-{}", self.metric.as_str(), period)
+    }
+}
+
+impl fmt::Display for ActivityLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name(), self.max)
+    }
+}
+
+impl FromStr for ActivityLimit {
+    type Err = AtomicServerError;
+
+    fn from_str(limit: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            AtomicServerError::from(format!(
+                "Invalid activity limit '{}'. Use e.g. `reads-per-hour=5000`, with `reads`, `commits`, `downloads`, `download-bytes`, `bytes-served` or `subjects`, per `hour` or `day`.",
+                limit
+            ))
+        };
+        let (name, max) = limit.trim().split_once('=').ok_or_else(invalid)?;
+        let (metric, period) = name.trim().rsplit_once("-per-").ok_or_else(invalid)?;
+        let metric = Metric::ALL
+            .into_iter()
+            .find(|m| m.as_str() == metric)
+            .ok_or_else(invalid)?;
+        let period = match period {
+            "hour" => Period::Hour,
+            "day" => Period::Day,
+            _ => return Err(invalid()),
+        };
+        if metric == Metric::Subjects && period == Period::Hour {
+            return Err(
+                "Distinct subjects are only counted per day, use `subjects-per-day`".into(),
+            );
+        }
+        let max = max.trim().parse().map_err(|_| invalid())?;
+        Ok(ActivityLimit {
+            metric,
+            period,
+            max,
+        })
+    }
+}
+
+/// The activity of an Agent in an hour or on a day.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Counts {
+    pub reads: u64,
+    pub commits: u64,
+    pub downloads: u64,
+    pub download_bytes: u64,
+    pub bytes_served: u64,
+    /// Distinct subjects. Only counted per day.
+    pub subjects: u64,
+}
+
+#[derive(Default)]
+struct AgentCounters {
+    days: BTreeMap<NaiveDate, Counts>,
+    /// Keyed by hours since the unix epoch
+    hours: BTreeMap<i64, Counts>,
+    /// The distinct subjects of the day in `subjects_day`
+    subjects: HashSet<String>,
+    subjects_day: Option<NaiveDate>,
+    /// Limits that were reported, as `{limit}@{hour or day}`, so every limit is reported once per period
+    reported: HashSet<String>,
+    /// Changed since the last flush
+    dirty: bool,
+}
+
+impl AgentCounters {
+    /// The limits that are exceeded at `at`, with the count and the key of the period.
+    fn exceeded(
+        &self,
+        limits: &[ActivityLimit],
+        at: DateTime<Utc>,
+    ) -> Vec<(ActivityLimit, u64, String)> {
+        let today = at.date_naive();
+        let hour = at.timestamp() / 3600;
+        limits
+            .iter()
+            .filter_map(|limit| {
+                let (counts, period) = match limit.period {
+                    Period::Hour => (self.hours.get(&hour)?, hour.to_string()),
+                    Period::Day => (self.days.get(&today)?, today.to_string()),
+                };
+                let count = limit.metric.count(counts);
+                (count > limit.max).then(|| (limit.clone(), count, format!("{}@{}", limit, period)))
+            })
+            .collect()
+    }
+}
+
+/// The activity of an Agent, as shown at `/agent-activity`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentReport {
+    pub agent: String,
+    pub current_hour: Counts,
+    /// The days that are kept in memory, newest first
+    pub days: Vec<DayReport>,
+    /// The limits that are currently exceeded, e.g. `reads-per-hour=5000`
+    pub exceeded: Vec<String>,
+    pub suspended: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DayReport {
+    pub date: String,
+    #[serde(flatten)]
+    pub counts: Counts,
+}
+
+/// The counters of all Agents. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct AgentActivity {
+    agents: Arc<Mutex<HashMap<String, AgentCounters>>>,
+}
+
+impl AgentActivity {
+    /// Counts a signed request for `subject`. Requests by the Public Agent are not counted.
+    pub fn record_read(&self, agent: &ForAgent, subject: &str) {
+        self.record(agent, Some(subject), Utc::now(), |counts| counts.reads += 1);
+    }
+
+    /// Counts an applied Commit.
+    pub fn record_commit(&self, agent: &ForAgent, subject: &str) {
+        self.record(agent, Some(subject), Utc::now(), |counts| {
+            counts.commits += 1
+        });
+    }
+
+    /// Counts a downloaded file of `bytes` long.
+    pub fn record_download(&self, agent: &ForAgent, subject: &str, bytes: u64) {
+        self.record(agent, Some(subject), Utc::now(), |counts| {
+            counts.downloads += 1;
+            counts.download_bytes += bytes;
+            counts.bytes_served += bytes;
+        });
+    }
+
+    /// Counts the size of a served Resource.
+    pub fn record_served(&self, agent: &ForAgent, bytes: u64) {
+        self.record(agent, None, Utc::now(), |counts| {
+            counts.bytes_served += bytes
+        });
+    }
+
+    fn record(
+        &self,
+        agent: &ForAgent,
+        subject: Option<&str>,
+        at: DateTime<Utc>,
+        update: impl Fn(&mut Counts),
+    ) {
+        let ForAgent::AgentSubject(agent) = agent else {
+            return;
+        };
+        let today = at.date_naive();
+        let mut agents = self.agents.lock().unwrap();
+        let counters = agents.entry(agent.clone()).or_default();
+        if counters.subjects_day != Some(today) {
+            counters.subjects.clear();
+            counters.subjects_day = Some(today);
+        }
+        let new_subject = match subject {
+            Some(subject) if counters.subjects.len() < MAX_TRACKED_SUBJECTS => {
+                counters.subjects.insert(subject.to_string())
+            }
+            _ => false,
+        };
+
+        let day = counters.days.entry(today).or_default();
+        update(day);
+        if new_subject {
+            day.subjects += 1;
+        }
+        update(counters.hours.entry(at.timestamp() / 3600).or_default());
+        while counters.days.len() > RETAINED_DAYS {
+            counters.days.pop_first();
+        }
+        while counters.hours.len() > RETAINED_HOURS {
+            counters.hours.pop_first();
+        }
+        counters.dirty = true;
+    }
+
+    /// The activity of the Agent, or of every Agent with activity, most reads today first.
+    pub fn report(
+        &self,
+        store: &impl Storelike,
+        limits: &[ActivityLimit],
+        agent: Option<&str>,
+    ) -> Vec<AgentReport> {
+        let now = Utc::now();
+        let today = now.date_naive();
+        let mut reports: Vec<AgentReport> = {
+            let agents = self.agents.lock().unwrap();
+            agents
+                .iter()
+                .filter(|(subject, _)| agent.map(|a| a == subject.as_str()).unwrap_or(true))
+                .map(|(subject, counters)| AgentReport {
+                    agent: subject.clone(),
+                    current_hour: counters
+                        .hours
+                        .get(&(now.timestamp() / 3600))
+                        .cloned()
+                        .unwrap_or_default(),
+                    days: counters
+                        .days
+                        .iter()
+                        .rev()
+                        .map(|(date, counts)| DayReport {
+                            date: date.to_string(),
+                            counts: counts.clone(),
+                        })
+                        .collect(),
+                    exceeded: counters
+                        .exceeded(limits, now)
+                        .into_iter()
+                        .map(|(limit, _, _)| limit.to_string())
+                        .collect(),
+                    suspended: false,
+                })
+                .collect()
+        };
+        for report in reports.iter_mut() {
+            report.suspended = store
+                .get_resource(&report.agent)
+                .map(|agent| atomic_lib::agents::check_not_suspended(&agent).is_err())
+                .unwrap_or(false);
+        }
+        let reads_today = |report: &AgentReport| {
+            report
+                .days
+                .iter()
+                .find(|day| day.date == today.to_string())
+                .map(|day| day.counts.reads)
+                .unwrap_or(0)
+        };
+        reports.sort_by(|a, b| {
+            reads_today(b)
+                .cmp(&reads_today(a))
+                .then_with(|| a.agent.cmp(&b.agent))
+        });
+        reports
+    }
+
+    /// Stores the counters that changed, and reports the limits that are newly exceeded.
+    pub fn flush(&self, store: &Db, limits: &[ActivityLimit], suspend: bool) {
+        let now = Utc::now();
+        let today = now.date_naive();
+        let mut changed = Vec::new();
+        let mut exceeded = Vec::new();
+        {
+            let mut agents = self.agents.lock().unwrap();
+            for (agent, counters) in agents.iter_mut().filter(|(_, c)| c.dirty) {
+                counters.dirty = false;
+                if let Some(counts) = counters.days.get(&today) {
+                    changed.push((agent.clone(), counts.clone()));
+                }
+                for (limit, count, key) in counters.exceeded(limits, now) {
+                    if counters.reported.insert(key) {
+                        exceeded.push((agent.clone(), limit, count));
+                    }
+                }
+                // Only the current periods can still be reported
+                let hour = now.timestamp() / 3600;
+                counters.reported.retain(|key| {
+                    key.ends_with(&format!("@{}", hour)) || key.ends_with(&format!("@{}", today))
+                });
+            }
+        }
+        for (agent, counts) in changed {
+            if let Err(e) = save_counts(store, &agent, today, &counts) {
+                tracing::error!("Could not store the activity of {}: {}", agent, e);
+            }
+        }
+        for (agent, limit, count) in exceeded {
+            if let Err(e) = handle_exceeded(store, &agent, &limit, count, suspend) {
+                tracing::error!(
+                    "Could not handle the exceeded activity limit of {}: {}",
+                    agent,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Flushes every [FLUSH_INTERVAL] on a background thread.
+    pub fn start(&self, store: Db, limits: Vec<ActivityLimit>, suspend: bool) {
+        let activity = self.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(FLUSH_INTERVAL);
+            activity.flush(&store, &limits, suspend);
+        });
+    }
+}
+
+/// The `AgentActivity` Resource of the Agent on the day. Agent subjects can't be part of a path, so it uses a hash.
+pub fn activity_subject(store: &impl Storelike, agent: &str, date: NaiveDate) -> String {
+    format!(
+        "{}/agent-activity/{}-{}",
+        store.get_server_url(),
+        &atomic_lib::patch::checksum(agent)[..32],
+        date
+    )
+}
+
+fn save_counts(
+    store: &impl Storelike,
+    agent: &str,
+    date: NaiveDate,
+    counts: &Counts,
+) -> AtomicServerResult<()> {
+    let mut resource = Resource::new(activity_subject(store, agent, date));
+    resource.set_class(urls::AGENT_ACTIVITY);
+    resource.set_propval_unsafe(urls::ACTIVITY_AGENT.into(), Value::AtomicUrl(agent.into()));
+    resource.set_propval_unsafe(urls::ACTIVITY_DATE.into(), Value::Date(date.to_string()));
+    for (prop, count) in [
+        (urls::ACTIVITY_READS, counts.reads),
+        (urls::ACTIVITY_COMMITS, counts.commits),
+        (urls::ACTIVITY_DOWNLOADS, counts.downloads),
+        (urls::ACTIVITY_DOWNLOAD_BYTES, counts.download_bytes),
+        (urls::ACTIVITY_BYTES_SERVED, counts.bytes_served),
+        (urls::ACTIVITY_SUBJECTS, counts.subjects),
+    ] {
+        resource.set_propval_unsafe(prop.into(), Value::Integer(count as i64));
+    }
+    // Without a Commit, since this is overwritten every minute
+    store.add_resource(&resource)?;
+    Ok(())
+}
+
+/// The local Agents with write rights to the root Drive.
+fn admins(store: &impl Storelike) -> AtomicServerResult<Vec<String>> {
+    let drive = store.get_resource(store.get_server_url())?;
+    let writers = drive
+        .get(urls::WRITE)
+        .and_then(|write| write.to_subjects(None))
+        .unwrap_or_default();
+    Ok(writers
+        .into_iter()
+        .filter(|agent| agent != urls::PUBLIC_AGENT && agent.starts_with(store.get_server_url()))
+        .collect())
+}
+
+fn handle_exceeded(
+    store: &Db,
+    agent: &str,
+    limit: &ActivityLimit,
+    count: u64,
+    suspend: bool,
+) -> AtomicServerResult<()> {
+    tracing::warn!("Agent {} exceeded {} with {}", agent, limit, count);
+    let drive = store.get_resource(store.get_server_url())?;
+    let is_admin = check_write(store, &drive, &ForAgent::AgentSubject(agent.into())).is_ok();
+    let suspended = suspend && !is_admin;
+    let mut message = format!(
+        "Agent {} exceeded the activity limit `{}` with {}.",
+        agent, limit, count
+    );
+    if suspended {
+        let reason = format!("Exceeded the activity limit `{}`.", limit);
+        let resource = store.get_resource(agent)?;
+        let mut builder = CommitBuilder::new(agent.into());
+        builder.set(urls::SUSPENDED.into(), Value::Boolean(true));
+        builder.set(urls::SUSPENSION_REASON.into(), Value::String(reason));
+        builder
+            .sign(&store.get_default_agent()?, store, &resource)?
+            .apply_opts(store, &crate::jobs::server_commit_opts())?;
+        message.push_str(" The Agent is suspended until an admin removes `suspended` from it.");
+    }
+    for admin in admins(store)? {
+        notifications::notify_agent(store, &admin, agent, &message)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_limits() {
+        let limit: ActivityLimit = "reads-per-hour=5000".parse().unwrap();
+        assert_eq!(
+            limit,
+            ActivityLimit {
+                metric: Metric::Reads,
+                period: Period::Hour,
+                max: 5000
+            }
+        );
+        assert_eq!(limit.to_string(), "reads-per-hour=5000");
+        let limit: ActivityLimit = "download-bytes-per-day=100".parse().unwrap();
+        assert_eq!(limit.metric, Metric::DownloadBytes);
+        assert!("subjects-per-hour=10".parse::<ActivityLimit>().is_err());
+        assert!("reads-per-week=10".parse::<ActivityLimit>().is_err());
+        assert!("reads=10".parse::<ActivityLimit>().is_err());
+    }
+
+    #[test]
+    fn counts_per_hour_and_day() {
+        let activity = AgentActivity::default();
+        let alice = ForAgent::AgentSubject("https://example.com/agents/alice".into());
+        let at = Utc::now();
+        let an_hour_later = at + chrono::Duration::hours(1);
+        for _ in 0..3 {
+            activity.record(&alice, Some("https://example.com/a"), at, |c| c.reads += 1);
+        }
+        activity.record(&alice, Some("https://example.com/b"), an_hour_later, |c| {
+            c.reads += 1
+        });
+        activity.record(&ForAgent::Public, Some("https://example.com/a"), at, |c| {
+            c.reads += 1
+        });
+
+        let agents = activity.agents.lock().unwrap();
+        assert_eq!(agents.len(), 1, "the Public Agent is not counted");
+        let counters = &agents["https://example.com/agents/alice"];
+        assert_eq!(counters.hours[&(at.timestamp() / 3600)].reads, 3);
+        let limits = vec!["reads-per-hour=2".parse().unwrap()];
+        assert_eq!(counters.exceeded(&limits, at).len(), 1);
+        assert!(counters.exceeded(&limits, an_hour_later).is_empty());
+        if at.date_naive() == an_hour_later.date_naive() {
+            let today = &counters.days[&at.date_naive()];
+            assert_eq!(today.reads, 4);
+            assert_eq!(today.subjects, 2);
+        }
+    }
+}
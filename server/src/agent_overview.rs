@@ -0,0 +1,227 @@
+//! Builds an overview of what an Agent has access to, served at `/agent-overview`.
+//! Lists the Resources that name the Agent in their rights, the Resources the Agent created and the Agent's recent Commits.
+//! Agents can see their own overview, and Agents with write rights to the root Drive can see anyone's.
+//! Every listed Resource is checked against the rights of the viewer, so inspecting another Agent never shows more than the viewer can read.
+
+use atomic_lib::{
+    agents::ForAgent,
+    hierarchy::{check_read, check_write},
+    storelike::Query,
+    urls, Commit, Resource, Storelike, Value,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{AppErrorType, AtomicServerError, AtomicServerResult};
+
+pub const PAGE_SIZE: usize = 25;
+/// Properties that give an Agent rights to a Resource and its descendants.
+const RIGHTS_PROPERTIES: [(&str, &str); 3] = [
+    (urls::READ, "read"),
+    (urls::WRITE, "write"),
+    (urls::APPEND, "append"),
+];
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct OverviewParams {
+    /// Subject of the Agent. Defaults to the Agent that makes the request.
+    pub agent: Option<String>,
+    /// Applies to every list in the overview
+    #[serde(default)]
+    pub page: usize,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentOverview {
+    pub agent: String,
+    pub agent_name: Option<String>,
+    /// Whether the viewer is looking at their own overview
+    pub is_self: bool,
+    pub page: usize,
+    pub page_size: usize,
+    /// Resources that name the Agent in their `read`, `write` or `append` rights
+    pub rights: Page<RightsEntry>,
+    /// Resources that were created by a Commit of the Agent, newest first
+    pub created: Page<ResourceEntry>,
+    /// Commits signed by the Agent, newest first
+    pub commits: Page<CommitEntry>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The amount of items the viewer can see, on all pages
+    pub total: usize,
+    pub has_next_page: bool,
+}
+
+impl<T> Page<T> {
+    fn of(mut all: Vec<T>, page: usize) -> Self {
+        let total = all.len();
+        let start = (page * PAGE_SIZE).min(total);
+        let end = (start + PAGE_SIZE).min(total);
+        let items = all.drain(start..end).collect();
+        Page {
+            items,
+            total,
+            has_next_page: end < total,
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceEntry {
+    pub subject: String,
+    pub title: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RightsEntry {
+    pub subject: String,
+    pub title: String,
+    /// Which of `read`, `write` and `append` name the Agent
+    pub rights: Vec<&'static str>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitEntry {
+    pub commit: String,
+    /// The Resource that the Commit changed
+    pub subject: String,
+    /// Unix timestamp in milliseconds
+    pub created_at: i64,
+    pub destroy: bool,
+}
+
+/// Returns the overview of the requested Agent, as seen by `for_agent`.
+/// Errors if `for_agent` is not the requested Agent, and has no write rights to the root Drive.
+pub fn build_overview(
+    store: &impl Storelike,
+    params: &OverviewParams,
+    for_agent: &ForAgent,
+) -> AtomicServerResult<AgentOverview> {
+    let agent = match (&params.agent, for_agent) {
+        (Some(agent), _) => agent.clone(),
+        (None, ForAgent::AgentSubject(viewer)) => viewer.clone(),
+        (None, _) => {
+            return Err(AtomicServerError::new(
+                "Sign in, or pass the `agent` query parameter.".into(),
+                AppErrorType::Unauthorized,
+            ))
+        }
+    };
+    let is_self = matches!(for_agent, ForAgent::AgentSubject(viewer) if viewer == &agent);
+    if !is_self {
+        let drive = store.get_resource(store.get_server_url())?;
+        check_write(store, &drive, for_agent).map_err(|_| {
+            AtomicServerError::new(
+                "Only the Agent itself and admins of the server can see this overview".into(),
+                AppErrorType::Unauthorized,
+            )
+        })?;
+    }
+    let agent_name = store
+        .get_resource(&agent)
+        .ok()
+        .and_then(|resource| resource.get(urls::NAME).ok().map(|name| name.to_string()));
+
+    let (created, commits) = commits_of(store, &agent, for_agent, is_self)?;
+    Ok(AgentOverview {
+        rights: Page::of(rights_of(store, &agent, for_agent)?, params.page),
+        created: Page::of(created, params.page),
+        commits: Page::of(commits, params.page),
+        agent,
+        agent_name,
+        is_self,
+        page: params.page,
+        page_size: PAGE_SIZE,
+    })
+}
+
+/// Uses the property-value index of the rights Properties.
+fn rights_of(
+    store: &impl Storelike,
+    agent: &str,
+    for_agent: &ForAgent,
+) -> AtomicServerResult<Vec<RightsEntry>> {
+    let mut entries: Vec<RightsEntry> = Vec::new();
+    for (property, right) in RIGHTS_PROPERTIES {
+        let mut query = Query::new_prop_val(property, agent);
+        // Skips Resources that the viewer can't read
+        query.for_agent = for_agent.clone();
+        for resource in store.query(&query)?.resources {
+            match entries
+                .iter_mut()
+                .find(|entry| &entry.subject == resource.get_subject())
+            {
+                Some(entry) => entry.rights.push(right),
+                None => entries.push(RightsEntry {
+                    subject: resource.get_subject().clone(),
+                    title: title_of(&resource),
+                    rights: vec![right],
+                }),
+            }
+        }
+    }
+    entries.sort_by(|a, b| a.title.cmp(&b.title));
+    Ok(entries)
+}
+
+/// Reads the Commits signed by the Agent, newest first.
+/// Commits to Resources the viewer can't read are left out. Agents see their own Commits to Resources that were destroyed.
+fn commits_of(
+    store: &impl Storelike,
+    agent: &str,
+    for_agent: &ForAgent,
+    is_self: bool,
+) -> AtomicServerResult<(Vec<ResourceEntry>, Vec<CommitEntry>)> {
+    let mut query = Query::new_prop_val(urls::SIGNER, agent);
+    query.sort_by = Some(urls::CREATED_AT.into());
+    query.sort_desc = true;
+    let mut created = Vec::new();
+    let mut commits = Vec::new();
+    for resource in store.query(&query)?.resources {
+        let commit_subject = resource.get_subject().clone();
+        let Ok(commit) = Commit::from_resource(resource) else {
+            continue;
+        };
+        let target = store.get_resource(&commit.subject).ok();
+        let readable = match &target {
+            Some(target) => check_read(store, target, for_agent).is_ok(),
+            None => is_self,
+        };
+        if !readable {
+            continue;
+        }
+        let destroy = commit.destroy.unwrap_or(false);
+        if let Some(target) = &target {
+            if commit.previous_commit.is_none() && !destroy {
+                created.push(ResourceEntry {
+                    subject: commit.subject.clone(),
+                    title: title_of(target),
+                });
+            }
+        }
+        commits.push(CommitEntry {
+            commit: commit_subject,
+            subject: commit.subject,
+            created_at: commit.created_at,
+            destroy,
+        });
+    }
+    Ok((created, commits))
+}
+
+fn title_of(resource: &Resource) -> String {
+    [urls::NAME, urls::SHORTNAME, urls::FILENAME]
+        .iter()
+        .find_map(|prop| match resource.get(prop) {
+            Ok(Value::String(s)) | Ok(Value::Slug(s)) => Some(s.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| resource.get_subject().clone())
+}
@@ -1,6 +1,21 @@
 //! App state, which is accessible from handlers
 use crate::{
-    commit_monitor::CommitMonitor, config::Config, errors::AtomicServerResult, search::SearchState,
+    agent_activity::AgentActivity,
+    audit::AuditLog,
+    commit_limits::CommitLimiter,
+    commit_monitor::CommitMonitor,
+    config::Config,
+    connections::ConnectionStats,
+    errors::AtomicServerResult,
+    jobs::{JobQueue, JobType},
+    link_preview::ExternalPreviews,
+    locale::Translations,
+    replication::Replica,
+    search::SearchState,
+    settings::Settings,
+    setup::SetupState,
+    upload_progress::UploadProgressRegistry,
+    webhooks::Webhooks,
 };
 use atomic_lib::{
     agents::{generate_public_key, Agent},
@@ -23,12 +38,36 @@ pub struct AppState {
     /// The Actix Address of the CommitMonitor, which should receive updates when a commit is applied
     pub commit_monitor: actix::Addr<CommitMonitor>,
     pub search_state: SearchState,
+    /// Schedules long-running tasks on background workers
+    pub job_queue: JobQueue,
+    /// Tracks recent Commits per Agent, to enforce the Commit rate limit
+    pub commit_limiter: CommitLimiter,
+    /// Counts reads, Commits and downloads per Agent, see `/agent-activity`
+    pub agent_activity: AgentActivity,
+    /// Open connections and running downloads, see [crate::connections]
+    pub connections: ConnectionStats,
+    /// Bytes received by running uploads, see `/upload-progress`
+    pub upload_progress: UploadProgressRegistry,
+    /// Cached previews of web pages on other servers, see `/preview`
+    pub link_previews: ExternalPreviews,
+    /// Translations of the static strings in HTML pages
+    pub translations: Translations,
+    /// The first-run setup, which creates the admin Agent
+    pub setup: SetupState,
+    /// The settings that can be changed at runtime, read from the ServerSettings resource
+    pub settings: Settings,
+    /// Appends applied Commits to the audit files, if enabled
+    pub audit: AuditLog,
+    /// Set when this server is a read-only replica of another server
+    pub replica: Option<Replica>,
+    /// Delivers Commits to Webhooks, and is stopped last when the server shuts down
+    pub webhooks: Webhooks,
 }
 
 /// Creates the AppState (the server's context available in Handlers).
 /// Initializes or opens a store on disk.
 /// Creates a new agent, if necessary.
-pub fn init(config: Config) -> AtomicServerResult<AppState> {
+pub fn init(mut config: Config) -> AtomicServerResult<AppState> {
     tracing::info!("Initializing AppState");
 
     // Check if atomic-server is already running somewhere, and try to stop it. It's not a problem if things go wrong here, so errors are simply logged.
@@ -40,9 +79,22 @@ pub fn init(config: Config) -> AtomicServerResult<AppState> {
         }
     }
 
+    tracing::info!("Running self-check");
+    let mut self_check = crate::self_check::check_environment(&config);
+    // Opening the store in a directory that is not writable fails with confusing errors
+    self_check.fail_on_fatal()?;
+
     tracing::info!("Opening database at {:?}", &config.store_path);
     let should_init = !&config.store_path.exists() || config.initialize;
     let mut store = atomic_lib::Db::init(&config.store_path, config.server_url.clone())?;
+    configure_store(&config, &mut store);
+    crate::self_check::check_store(&config, &store, should_init, &mut self_check);
+    self_check.log();
+    self_check.fail_on_fatal()?;
+    if self_check.rebuild_indexes {
+        // Clears the search index before it is opened, and schedules a rebuild in `serve`
+        config.opts.rebuild_indexes = true;
+    }
     if should_init {
         tracing::info!("Initialize: creating and populating new Database...");
         atomic_lib::populate::populate_default_store(&store)
@@ -56,18 +108,43 @@ pub fn init(config: Config) -> AtomicServerResult<AppState> {
     tracing::info!("Starting search service");
     let search_state =
         SearchState::new(&config).map_err(|e| format!("Failed to start search service: {}", e))?;
+    if search_state.is_stale() && !should_init && !config.opts.rebuild_indexes {
+        let search_state = search_state.clone();
+        let store = store.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = crate::search::add_all_resources(&search_state, &store) {
+                tracing::error!("Failed to rebuild the search index: {}", e);
+            }
+        });
+    }
+
+    // Uses the config until the ServerSettings resource is read, after the Drive has been created
+    let settings = Settings::new(&config.opts, &config.server_url);
 
     // Initialize commit monitor, which watches commits and sends these to the commit_monitor actor
     tracing::info!("Starting commit monitor");
-    let commit_monitor =
-        crate::commit_monitor::create_commit_monitor(store.clone(), search_state.clone());
+    // Started below, once it's known whether this server is writable
+    let webhooks = Webhooks::default();
+    let commit_monitor = crate::commit_monitor::create_commit_monitor(
+        store.clone(),
+        search_state.clone(),
+        config.uploads_path.clone(),
+        settings.clone(),
+        crate::cache::purger_from_opts(&config.opts, &store),
+        std::time::Duration::from_secs(config.opts.durable_subscription_days * 24 * 60 * 60),
+        webhooks.clone(),
+    );
 
     let commit_monitor_clone = commit_monitor.clone();
+    let audit = AuditLog::from_opts(&config.opts)?;
+    let audit_clone = audit.clone();
 
     // This closure is called every time a Commit is created
-    let send_commit = move |commit_response: &CommitResponse| {
+    let send_commit = move |commit_response: &CommitResponse, sequence: Option<u64>| {
+        audit_clone.append(commit_response);
         commit_monitor_clone.do_send(crate::actor_messages::CommitMessage {
             commit_response: commit_response.clone(),
+            sequence,
         });
     };
     store.set_handle_commit(Box::new(send_commit));
@@ -76,6 +153,11 @@ pub fn init(config: Config) -> AtomicServerResult<AppState> {
     // In this situation, we should re-build a new drive from scratch.
     if should_init {
         atomic_lib::populate::populate_all(&store)?;
+        if let Some(file) = &config.opts.serve_file {
+            let count = crate::serve_file::load(&store, file)
+                .map_err(|e| format!("Could not load {:?}: {}", file, e))?;
+            tracing::info!("Loaded {} resources from {:?}", count, file);
+        }
         // Building the index here is needed to perform Queries on imported resources
         let store_clone = store.clone();
         std::thread::spawn(move || {
@@ -85,8 +167,11 @@ pub fn init(config: Config) -> AtomicServerResult<AppState> {
             }
         });
 
-        set_up_initial_invite(&store)
-            .map_err(|e| format!("Error while setting up initial invite: {}", e))?;
+        // A served file can't be edited, so it has no use for the setup Invite
+        if config.opts.serve_file.is_none() {
+            set_up_initial_invite(&store)
+                .map_err(|e| format!("Error while setting up initial invite: {}", e))?;
+        }
         // This means that editing the .env does _not_ grant you the rights to edit the Drive.
         tracing::info!("Setting rights to Drive {}", store.get_server_url());
 
@@ -94,16 +179,126 @@ pub fn init(config: Config) -> AtomicServerResult<AppState> {
         crate::search::add_all_resources(&search_state, &store)?
     }
 
+    tracing::info!("Loading server settings");
+    if let Err(e) = settings.seed(&store) {
+        tracing::error!(
+            "Could not load server settings, using the config instead: {}",
+            e
+        );
+    }
+
+    tracing::info!("Starting job workers");
+    let job_queue = JobQueue::start(
+        store.clone(),
+        search_state.clone(),
+        config.clone(),
+        settings.clone(),
+    )?;
+    commit_monitor.do_send(crate::actor_messages::SetJobQueue {
+        job_queue: job_queue.clone(),
+    });
+    let replica = Replica::from_config(&config);
+    if let Some(replica) = &replica {
+        tracing::info!("Starting replication from {}", replica.primary());
+        replica.start(store.clone(), search_state.clone());
+    }
+    // Replicas get these changes from the primary, and a served file is read-only
+    let writable = replica.is_none() && config.opts.serve_file.is_none();
+    let purge_settings = settings.clone();
+    job_queue.repeat_when(
+        JobType::PurgeTrash,
+        std::time::Duration::from_secs(24 * 60 * 60),
+        move |_store| writable && purge_settings.get().trash_retention_days.is_some(),
+    )?;
+    job_queue.repeat_when(
+        JobType::RemoveExpired,
+        std::time::Duration::from_secs(config.opts.expiry_interval.max(1)),
+        move |store| {
+            writable
+                && atomic_lib::plugins::expiry::expired_subjects(store, atomic_lib::utils::now(), 1)
+                    .map(|expired| !expired.is_empty())
+                    .unwrap_or(false)
+        },
+    )?;
+    job_queue.repeat_when(
+        JobType::ApplyRetention,
+        std::time::Duration::from_secs(24 * 60 * 60),
+        move |store| {
+            writable
+                && store
+                    .query(&atomic_lib::storelike::Query {
+                        limit: Some(1),
+                        ..atomic_lib::storelike::Query::new_class(
+                            atomic_lib::urls::RETENTION_POLICY,
+                        )
+                    })
+                    .map(|result| result.count > 0)
+                    .unwrap_or(false)
+        },
+    )?;
+    if config.opts.backup_interval_hours > 0 {
+        job_queue.repeat_when(
+            JobType::Backup,
+            std::time::Duration::from_secs(config.opts.backup_interval_hours * 60 * 60),
+            move |_store| writable,
+        )?;
+    }
+
+    let agent_activity = AgentActivity::default();
+    if writable {
+        agent_activity.start(
+            store.clone(),
+            config.activity_limits.clone(),
+            config.opts.activity_suspend,
+        );
+        // A replica has the same Webhooks as its primary, which already delivers them
+        tracing::info!("Starting webhook deliveries");
+        webhooks.start(store.clone());
+    }
+
+    let translations = Translations::load(&config.config_dir);
+    let setup = SetupState::init(&store)?;
+
     Ok(AppState {
         store,
         config,
         commit_monitor,
         search_state,
+        job_queue,
+        commit_limiter: CommitLimiter::default(),
+        agent_activity,
+        connections: ConnectionStats::default(),
+        upload_progress: UploadProgressRegistry::default(),
+        link_previews: ExternalPreviews::default(),
+        translations,
+        setup,
+        settings,
+        audit,
+        replica,
+        webhooks,
     })
 }
 
+/// Applies the options of the [Config] that change how the store behaves.
+/// Also used by the store subcommands, so they treat the data the same way as the server, see [crate::store_cli].
+pub fn configure_store(config: &Config, store: &mut atomic_lib::Db) {
+    store.set_slow_threshold(
+        config
+            .opts
+            .slow_operation_ms
+            .map(std::time::Duration::from_millis),
+    );
+    if config.opts.max_value_size > 0 {
+        store.set_max_indexed_value_size(config.opts.max_value_size as usize);
+    }
+    store.set_max_hierarchy_depth(config.opts.max_hierarchy_depth);
+    store.set_outbound(atomic_lib::outbound::OutboundHttp::new(
+        config.outbound.clone(),
+    ));
+}
+
 /// Create a new agent if it does not yet exist.
-fn set_default_agent(config: &Config, store: &impl Storelike) -> AtomicServerResult<()> {
+pub fn set_default_agent(config: &Config, store: &impl Storelike) -> AtomicServerResult<()> {
     let ag_cfg: atomic_lib::config::Config = match atomic_lib::config::read_config(
         &config.config_file_path,
     ) {
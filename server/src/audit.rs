@@ -0,0 +1,451 @@
+//! Optional audit log, which appends every applied Commit as a JSON line to files outside of the store.
+//! Enable it using `--audit-dir`. Files are rotated by size or per day, and can be checked against the store using `atomic-server verify-audit`.
+//! A failing write never undoes a Commit. It is logged and shown at `/health`, and in strict mode, new Commits are refused until the audit log can be written again.
+
+use std::{
+    collections::HashSet,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use atomic_lib::{commit::CommitResponse, storelike::Query, urls, Storelike, Value};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{AuditFsync, AuditRotation, Opts},
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+};
+
+/// The file that is currently written to. Rotated files are named `commits-{last sequence number}.jsonl`, so sorting them by name sorts them by sequence.
+const CURRENT_FILE: &str = "commits.jsonl";
+
+/// A single line in the audit log.
+/// The fields are always serialized in this order, without whitespace.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// Increases by one for every Commit, also across rotated files
+    pub seq: u64,
+    /// Subject of the Commit
+    pub commit: String,
+    pub signer: String,
+    /// The Resource that was changed
+    pub subject: String,
+    pub created_at: i64,
+    pub destroy: bool,
+    pub signature: Option<String>,
+}
+
+impl AuditEntry {
+    fn new(seq: u64, commit_response: &CommitResponse) -> Self {
+        let commit = &commit_response.commit_struct;
+        AuditEntry {
+            seq,
+            commit: commit_response.commit_resource.get_subject().clone(),
+            signer: commit.signer.clone(),
+            subject: commit.subject.clone(),
+            created_at: commit.created_at,
+            destroy: commit.destroy.unwrap_or(false),
+            signature: commit.signature.clone(),
+        }
+    }
+}
+
+/// Shown at `/health`.
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditStatus {
+    pub enabled: bool,
+    pub strict: bool,
+    /// Sequence number of the last written entry
+    pub last_seq: u64,
+    /// Commits that were applied, but could not be written, since the server started
+    pub missed: u64,
+    /// The last error, cleared when an entry is written successfully
+    pub error: Option<String>,
+}
+
+/// Appends applied Commits to the audit log. Cheap to clone, clones share the file.
+#[derive(Clone, Default)]
+pub struct AuditLog {
+    strict: bool,
+    writer: Option<Arc<Mutex<Writer>>>,
+}
+
+struct Writer {
+    dir: PathBuf,
+    rotation: AuditRotation,
+    /// Only used for [AuditRotation::Size]
+    max_bytes: u64,
+    fsync: AuditFsync,
+    file: File,
+    size: u64,
+    /// Day of the last write, used for [AuditRotation::Daily]
+    day: NaiveDate,
+    last_seq: u64,
+    missed: u64,
+    error: Option<String>,
+}
+
+impl AuditLog {
+    /// Opens the audit log in `--audit-dir`, or returns a disabled log if it is not set.
+    pub fn from_opts(opts: &Opts) -> AtomicServerResult<AuditLog> {
+        match &opts.audit_dir {
+            Some(dir) => AuditLog::open(
+                dir,
+                opts.audit_rotation.clone(),
+                opts.audit_max_size.saturating_mul(1024 * 1024),
+                opts.audit_fsync.clone(),
+                opts.audit_strict,
+            ),
+            None => Ok(AuditLog::default()),
+        }
+    }
+
+    /// Opens (or creates) the audit log, and continues the sequence of the last entry.
+    pub fn open(
+        dir: &Path,
+        rotation: AuditRotation,
+        max_bytes: u64,
+        fsync: AuditFsync,
+        strict: bool,
+    ) -> AtomicServerResult<AuditLog> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Could not create audit log directory {:?}: {}", dir, e))?;
+        let last_seq = read_entries(dir)?
+            .into_iter()
+            .filter_map(|line| line.entry)
+            .last()
+            .map(|entry| entry.seq)
+            .unwrap_or(0);
+        let file = open_current(dir)?;
+        let metadata = file.metadata()?;
+        let day = metadata
+            .modified()
+            .map(|modified| chrono::DateTime::<chrono::Local>::from(modified).date_naive())
+            .unwrap_or_else(|_| today());
+        tracing::info!(
+            "Writing Commits to the audit log in {:?}, starting at sequence {}",
+            dir,
+            last_seq + 1
+        );
+        Ok(AuditLog {
+            strict,
+            writer: Some(Arc::new(Mutex::new(Writer {
+                dir: dir.to_path_buf(),
+                rotation,
+                max_bytes,
+                fsync,
+                size: metadata.len(),
+                file,
+                day,
+                last_seq,
+                missed: 0,
+                error: None,
+            }))),
+        })
+    }
+
+    /// Writes the Commit to the audit log. Called after the Commit has been applied, so errors are not returned, but stored in the [AuditStatus].
+    pub fn append(&self, commit_response: &CommitResponse) {
+        let Some(writer) = &self.writer else {
+            return;
+        };
+        let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = AuditEntry::new(writer.last_seq + 1, commit_response);
+        match writer.write(&entry) {
+            Ok(()) => writer.error = None,
+            Err(e) => {
+                tracing::error!(
+                    "Could not write Commit {} to the audit log: {}",
+                    entry.commit,
+                    e
+                );
+                writer.missed += 1;
+                writer.error = Some(format!(
+                    "Could not write Commit {} to the audit log: {}",
+                    entry.commit, e
+                ));
+            }
+        }
+    }
+
+    /// In strict mode, returns an error if the last write failed and the audit log still can't be opened.
+    /// Called before applying incoming Commits.
+    pub fn check_writable(&self) -> AtomicServerResult<()> {
+        let Some(writer) = &self.writer else {
+            return Ok(());
+        };
+        if !self.strict {
+            return Ok(());
+        }
+        let mut writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+        if writer.error.is_none() {
+            return Ok(());
+        }
+        match open_current(&writer.dir) {
+            Ok(file) => {
+                writer.size = file.metadata()?.len();
+                writer.file = file;
+                writer.error = None;
+                Ok(())
+            }
+            Err(e) => Err(AtomicServerError::new(
+                format!(
+                    "Commits are refused, because the audit log can't be written. {}",
+                    e
+                ),
+                AppErrorType::ServiceUnavailable,
+            )),
+        }
+    }
+
+    pub fn status(&self) -> AuditStatus {
+        let Some(writer) = &self.writer else {
+            return AuditStatus::default();
+        };
+        let writer = writer.lock().unwrap_or_else(|e| e.into_inner());
+        AuditStatus {
+            enabled: true,
+            strict: self.strict,
+            last_seq: writer.last_seq,
+            missed: writer.missed,
+            error: writer.error.clone(),
+        }
+    }
+}
+
+impl Writer {
+    fn write(&mut self, entry: &AuditEntry) -> std::io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let rotate = self.size > 0
+            && match self.rotation {
+                AuditRotation::Size => self.size + line.len() as u64 > self.max_bytes,
+                AuditRotation::Daily => self.day != today(),
+            };
+        if rotate {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        if let AuditFsync::Always = self.fsync {
+            self.file.sync_data()?;
+        }
+        self.size += line.len() as u64;
+        self.day = today();
+        self.last_seq = entry.seq;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.sync_all()?;
+        std::fs::rename(
+            self.dir.join(CURRENT_FILE),
+            self.dir
+                .join(format!("commits-{:012}.jsonl", self.last_seq)),
+        )?;
+        self.file = open_current(&self.dir)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn open_current(dir: &Path) -> std::io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(CURRENT_FILE))
+}
+
+fn today() -> NaiveDate {
+    chrono::Local::now().date_naive()
+}
+
+/// The audit files in the order they were written: rotated files first, the current file last.
+fn audit_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut rotated: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map(|name| name.starts_with("commits-") && name.ends_with(".jsonl"))
+                .unwrap_or(false)
+        })
+        .collect();
+    rotated.sort();
+    let current = dir.join(CURRENT_FILE);
+    if current.exists() {
+        rotated.push(current);
+    }
+    Ok(rotated)
+}
+
+struct Line {
+    /// `{file}:{line number}`, for error messages
+    location: String,
+    /// `None` if the line can't be parsed
+    entry: Option<AuditEntry>,
+}
+
+fn read_entries(dir: &Path) -> AtomicServerResult<Vec<Line>> {
+    let mut lines = Vec::new();
+    for path in audit_files(dir)? {
+        let reader = BufReader::new(File::open(&path)?);
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            lines.push(Line {
+                location: format!("{}:{}", path.display(), i + 1),
+                entry: serde_json::from_str(&line).ok(),
+            });
+        }
+    }
+    Ok(lines)
+}
+
+/// The result of [verify].
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditVerification {
+    pub entries: u64,
+    pub first_seq: Option<u64>,
+    pub last_seq: Option<u64>,
+    /// Places where the sequence does not increase by one, as (expected, found)
+    pub gaps: Vec<(u64, u64)>,
+    /// Lines that are not valid entries, as `{file}:{line number}`
+    pub invalid_lines: Vec<String>,
+    /// Commits in the audit log that are not in the store
+    pub missing_in_store: Vec<String>,
+    /// Commits in the store, created since the first entry, that are not in the audit log
+    pub missing_in_log: Vec<String>,
+}
+
+impl AuditVerification {
+    // Only called by the binary
+    #[allow(dead_code)]
+    pub fn is_ok(&self) -> bool {
+        self.gaps.is_empty()
+            && self.invalid_lines.is_empty()
+            && self.missing_in_store.is_empty()
+            && self.missing_in_log.is_empty()
+    }
+}
+
+/// Checks that the sequence numbers in the audit log are continuous, and compares the logged Commits with the Commits in the store.
+/// Older files may have been removed, so the log doesn't have to start at sequence 1.
+// Only called by the binary, for `atomic-server audit verify`
+#[allow(dead_code)]
+pub fn verify(dir: &Path, store: &impl Storelike) -> AtomicServerResult<AuditVerification> {
+    let mut report = AuditVerification::default();
+    let mut logged: HashSet<String> = HashSet::new();
+    let mut first_created_at: Option<i64> = None;
+    for line in read_entries(dir)? {
+        let Some(entry) = line.entry else {
+            report.invalid_lines.push(line.location);
+            continue;
+        };
+        report.entries += 1;
+        if let Some(last) = report.last_seq {
+            if entry.seq != last + 1 {
+                report.gaps.push((last + 1, entry.seq));
+            }
+        }
+        report.first_seq.get_or_insert(entry.seq);
+        report.last_seq = Some(entry.seq);
+        first_created_at.get_or_insert(entry.created_at);
+        if store.get_resource(&entry.commit).is_err() {
+            report.missing_in_store.push(entry.commit.clone());
+        }
+        logged.insert(entry.commit);
+    }
+
+    if let Some(first_created_at) = first_created_at {
+        let mut query = Query::new_class(urls::COMMIT);
+        query.sort_by = Some(urls::CREATED_AT.into());
+        query.start_val = Some(Value::Timestamp(first_created_at));
+        report.missing_in_log = store
+            .query(&query)?
+            .subjects
+            .into_iter()
+            .filter(|commit| !logged.contains(commit))
+            .collect();
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atomic_lib::{
+        commit::{CommitBuilder, CommitOpts},
+        Db,
+    };
+
+    fn apply(store: &Db, description: &str) -> CommitResponse {
+        let drive = store.get_resource(store.get_server_url()).unwrap();
+        let mut commitbuilder = CommitBuilder::new(drive.get_subject().clone());
+        commitbuilder.set(
+            urls::DESCRIPTION.into(),
+            Value::Markdown(description.into()),
+        );
+        let opts = CommitOpts {
+            validate_schema: false,
+            validate_signature: false,
+            validate_timestamp: false,
+            validate_rights: false,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        commitbuilder
+            .sign(&store.get_default_agent().unwrap(), store, &drive)
+            .unwrap()
+            .apply_opts(store, &opts)
+            .unwrap()
+    }
+
+    #[test]
+    fn audit_log_rotates_and_verifies() {
+        let store = Db::init_temp("audit_log_rotates_and_verifies").unwrap();
+        let dir = std::env::temp_dir().join(format!(
+            "atomic-audit-{}",
+            atomic_lib::utils::random_string(8)
+        ));
+        // Small enough to put every entry in its own file
+        let open = || AuditLog::open(&dir, AuditRotation::Size, 10, AuditFsync::Never, true);
+        let audit = open().unwrap();
+        for i in 0..3 {
+            audit.append(&apply(&store, &format!("version {}", i)));
+        }
+        assert_eq!(audit_files(&dir).unwrap().len(), 3);
+        let report = verify(&dir, &store).unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+        assert_eq!((report.first_seq, report.last_seq), (Some(1), Some(3)));
+
+        // Reopening continues the sequence
+        let audit = open().unwrap();
+        audit.append(&apply(&store, "reopened"));
+        assert_eq!(audit.status().last_seq, 4);
+        assert!(audit.check_writable().is_ok());
+
+        // A Commit that was applied without being logged
+        let unlogged = apply(&store, "unlogged");
+        let report = verify(&dir, &store).unwrap();
+        assert_eq!(
+            report.missing_in_log,
+            vec![unlogged.commit_resource.get_subject().clone()]
+        );
+
+        // Removing a file leaves a gap in the sequence
+        std::fs::remove_file(&audit_files(&dir).unwrap()[1]).unwrap();
+        let report = verify(&dir, &store).unwrap();
+        assert_eq!(report.gaps, vec![(2, 3)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,477 @@
+//! Backups that can be checked without touching the live store.
+//! A backup is a directory in `{config_dir}/backups` with the JSON-AD export of the store (`store.json`),
+//! copies of the uploaded files that File Resources refer to (`uploads/`), and a [BackupManifest] with the amount of Resources and Commits and the checksums of the files.
+//! [verify] loads the export into a temporary store, validates it, and compares it with the manifest and with the live store.
+//! Backups are made by the `backup` Job and subcommand, and every `--backup-interval-hours`.
+//! With `--verify-backups`, scheduled backups are verified right away, and failures are reported to the admins of the Drive and to `--backup-alert-url`.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use atomic_lib::{
+    agents::ForAgent,
+    outbound::OutboundFeature,
+    parse::{ParseOpts, SaveOpts},
+    urls,
+    utils::{now, random_string},
+    Db, Resource, Storelike,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{errors::AtomicServerResult, handlers::download::is_safe_file_id};
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const STORE_FILE: &str = "store.json";
+const UPLOADS_DIR: &str = "uploads";
+/// How many Resources are compared with the live store, unless another amount is given.
+pub const DEFAULT_SAMPLE_SIZE: usize = 100;
+
+/// Written at backup time, so a verification knows what the backup should contain.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+    pub created_at: i64,
+    pub server_url: String,
+    pub resources: usize,
+    pub commits: usize,
+    /// The uploaded files that File Resources refer to
+    pub files: Vec<BlobEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BlobEntry {
+    /// The name of the file in the uploads directory, see [urls::INTERNAL_ID]
+    pub internal_id: String,
+    pub size: u64,
+    /// SHA-256 of the contents, as hex
+    pub checksum: String,
+}
+
+/// The result of [verify]. The backup passes if there are no discrepancies.
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupVerification {
+    pub backup: PathBuf,
+    pub passed: bool,
+    /// Loaded from the export
+    pub resources: usize,
+    pub commits: usize,
+    /// According to the manifest
+    pub expected_resources: usize,
+    pub expected_commits: usize,
+    /// Resources that were compared with the live store
+    pub sampled: usize,
+    /// Sampled Resources that are edited or removed since the backup, so they can't be compared
+    pub changed_since_backup: usize,
+    pub missing_in_live: usize,
+    pub files_checked: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Discrepancy {
+    /// `import`, `count`, `validation`, `sample` or `file`
+    pub kind: &'static str,
+    pub subject: Option<String>,
+    pub message: String,
+}
+
+impl BackupVerification {
+    fn discrepancy(&mut self, kind: &'static str, subject: Option<&str>, message: String) {
+        self.discrepancies.push(Discrepancy {
+            kind,
+            subject: subject.map(String::from),
+            message,
+        });
+    }
+}
+
+/// Where new backups are placed, and where the latest one is looked up.
+pub fn backups_dir(config: &crate::config::Config) -> PathBuf {
+    config.config_dir.join("backups")
+}
+
+fn is_commit(resource: &Resource) -> bool {
+    resource
+        .get(urls::IS_A)
+        .and_then(|classes| classes.to_subjects(None))
+        .map(|classes| classes.iter().any(|class| class == urls::COMMIT))
+        .unwrap_or(false)
+}
+
+/// The uploaded file of a File Resource.
+fn blob_of(resource: &Resource) -> Option<String> {
+    if !atomic_lib::plugins::attachments::is_file(resource) {
+        return None;
+    }
+    resource
+        .get(urls::INTERNAL_ID)
+        .ok()
+        .map(|id| id.to_string())
+        .filter(|id| is_safe_file_id(id))
+}
+
+/// Writes a backup of the store and of the uploaded files that its File Resources refer to into `dir`.
+/// Reads from a snapshot, so Commits that are applied in the meantime don't end up in half of the backup.
+pub fn create(store: &Db, uploads_path: &Path, dir: &Path) -> AtomicServerResult<BackupManifest> {
+    std::fs::create_dir_all(dir.join(UPLOADS_DIR))
+        .map_err(|e| format!("Failed to create directory {:?}. {}", dir, e))?;
+    let snapshot = store.snapshot();
+    let mut manifest = BackupManifest {
+        created_at: now(),
+        server_url: store.get_server_url().into(),
+        resources: 0,
+        commits: 0,
+        files: Vec::new(),
+    };
+    for resource in snapshot.all_resources(true) {
+        manifest.resources += 1;
+        if is_commit(&resource) {
+            manifest.commits += 1;
+        }
+        let Some(internal_id) = blob_of(&resource) else {
+            continue;
+        };
+        let bytes = match std::fs::read(uploads_path.join(&internal_id)) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                // Backed up anyway, verifying the backup reports the missing file
+                tracing::warn!(
+                    "The uploaded file of {} can't be read: {}",
+                    resource.get_subject(),
+                    e
+                );
+                continue;
+            }
+        };
+        std::fs::write(dir.join(UPLOADS_DIR).join(&internal_id), &bytes)?;
+        manifest.files.push(BlobEntry {
+            internal_id,
+            size: bytes.len() as u64,
+            checksum: atomic_lib::patch::checksum_bytes(&bytes),
+        });
+    }
+    std::fs::write(dir.join(STORE_FILE), snapshot.export(true)?)?;
+    let manifest_json = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Could not serialize the backup manifest: {}", e))?;
+    std::fs::write(dir.join(MANIFEST_FILE), manifest_json)?;
+    Ok(manifest)
+}
+
+/// The most recent backup in `dir`, according to their manifests.
+pub fn latest(dir: &Path) -> AtomicServerResult<PathBuf> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("No backups in {:?}. {}", dir, e))?;
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let manifest = read_manifest(&entry.path()).ok()?;
+            Some((manifest.created_at, entry.path()))
+        })
+        .max_by_key(|(created_at, _)| *created_at)
+        .map(|(_, path)| path)
+        .ok_or_else(|| format!("No backups with a {} in {:?}", MANIFEST_FILE, dir).into())
+}
+
+fn read_manifest(backup: &Path) -> AtomicServerResult<BackupManifest> {
+    let path = backup.join(MANIFEST_FILE);
+    let json =
+        std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {:?}. {}", path, e))?;
+    Ok(serde_json::from_str(&json).map_err(|e| format!("Invalid {:?}. {}", path, e))?)
+}
+
+/// Removes the temporary store when the verification is done, also when it fails.
+struct TempDir(PathBuf);
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Loads the backup in `dir` into a temporary store, and checks it.
+/// Up to `sample_size` Resources are compared with `live`, but only the ones that neither side has changed since the backup.
+/// The live store is only read.
+pub fn verify(
+    live: Option<&Db>,
+    dir: &Path,
+    sample_size: usize,
+) -> AtomicServerResult<BackupVerification> {
+    let manifest = read_manifest(dir)?;
+    let mut report = BackupVerification {
+        backup: dir.to_path_buf(),
+        expected_resources: manifest.resources,
+        expected_commits: manifest.commits,
+        ..Default::default()
+    };
+    let export_path = dir.join(STORE_FILE);
+    let export = std::fs::read_to_string(&export_path)
+        .map_err(|e| format!("Failed to read {:?}. {}", export_path, e))?;
+
+    let temp_dir =
+        TempDir(std::env::temp_dir().join(format!("atomic-verify-backup-{}", random_string(10))));
+    let restored = Db::init(&temp_dir.0, manifest.server_url.clone())?;
+    let parse_opts = ParseOpts {
+        importer: None,
+        for_agent: ForAgent::Sudo,
+        signer: None,
+        save: SaveOpts::Save,
+        overwrite_outside: true,
+        base: None,
+    };
+    match restored.import(&export, &parse_opts) {
+        Ok(count) => report.resources = count,
+        Err(e) => {
+            report.discrepancy("import", None, format!("The export can't be loaded: {}", e));
+            return Ok(report);
+        }
+    }
+    // Counted in the export, because the temporary store has its own base models
+    report.commits = serde_json::from_str::<Vec<serde_json::Value>>(&export)
+        .unwrap_or_default()
+        .iter()
+        .filter(|object| {
+            object
+                .get(urls::IS_A)
+                .and_then(|classes| classes.as_array())
+                .is_some_and(|classes| classes.iter().any(|class| class == urls::COMMIT))
+        })
+        .count();
+    if report.resources != manifest.resources {
+        let message = format!(
+            "The export has {} Resources, the manifest {}",
+            report.resources, manifest.resources
+        );
+        report.discrepancy("count", None, message);
+    }
+    if report.commits != manifest.commits {
+        let message = format!(
+            "The export has {} Commits, the manifest {}",
+            report.commits, manifest.commits
+        );
+        report.discrepancy("count", None, message);
+    }
+
+    let validation = atomic_lib::validate::validate_store(&restored, false);
+    if !validation.is_valid() {
+        report.discrepancy("validation", None, validation.to_string());
+    }
+
+    let mut subjects: Vec<String> = restored
+        .all_resources(false)
+        .map(|resource| resource.get_subject().clone())
+        .collect();
+    subjects.sort();
+    if let Some(live) = live {
+        let step = (subjects.len() / sample_size.max(1)).max(1);
+        for subject in subjects.iter().step_by(step).take(sample_size) {
+            compare_with_live(&restored, live, subject, &mut report);
+        }
+    }
+
+    let listed: HashMap<&str, &BlobEntry> = manifest
+        .files
+        .iter()
+        .map(|entry| (entry.internal_id.as_str(), entry))
+        .collect();
+    let mut referenced = HashSet::new();
+    for resource in restored.all_resources(false) {
+        let Some(internal_id) = blob_of(&resource) else {
+            continue;
+        };
+        if !listed.contains_key(internal_id.as_str()) {
+            let message = format!("{} is not in the upload manifest", internal_id);
+            report.discrepancy("file", Some(resource.get_subject()), message);
+        }
+        referenced.insert(internal_id);
+    }
+    for entry in &manifest.files {
+        report.files_checked += 1;
+        let path = dir.join(UPLOADS_DIR).join(&entry.internal_id);
+        match std::fs::read(&path) {
+            Ok(bytes) if bytes.len() as u64 != entry.size => report.discrepancy(
+                "file",
+                None,
+                format!(
+                    "{} has {} bytes, the manifest {}",
+                    entry.internal_id,
+                    bytes.len(),
+                    entry.size
+                ),
+            ),
+            Ok(bytes) if atomic_lib::patch::checksum_bytes(&bytes) != entry.checksum => report
+                .discrepancy(
+                    "file",
+                    None,
+                    format!(
+                        "The checksum of {} does not match the manifest",
+                        entry.internal_id
+                    ),
+                ),
+            Ok(_) => {}
+            Err(e) => report.discrepancy(
+                "file",
+                None,
+                format!("{} is missing from the backup: {}", entry.internal_id, e),
+            ),
+        }
+        if !referenced.contains(&entry.internal_id) {
+            let message = format!("{} is not used by a File in the export", entry.internal_id);
+            report.discrepancy("file", None, message);
+        }
+    }
+
+    // The temporary store must be closed before its directory is removed
+    drop(restored);
+    report.passed = report.discrepancies.is_empty();
+    Ok(report)
+}
+
+/// Compares a Resource that neither the backup nor the live store has changed since the backup was made.
+fn compare_with_live(restored: &Db, live: &Db, subject: &str, report: &mut BackupVerification) {
+    report.sampled += 1;
+    let Ok(backed_up) = restored.get_resource(subject) else {
+        return;
+    };
+    let Ok(current) = live.get_resource(subject) else {
+        report.missing_in_live += 1;
+        return;
+    };
+    let last_commit = |resource: &Resource| {
+        resource
+            .get(urls::LAST_COMMIT)
+            .ok()
+            .map(|commit| commit.to_string())
+    };
+    if last_commit(&backed_up) != last_commit(&current) {
+        report.changed_since_backup += 1;
+        return;
+    }
+    let mut props: Vec<&String> = backed_up
+        .get_propvals()
+        .keys()
+        .chain(current.get_propvals().keys())
+        .collect();
+    props.sort();
+    props.dedup();
+    // Compared as JSON, since the string of a Nested Resource has no fixed order
+    let json = |resource: &Resource, prop: &str| {
+        resource
+            .get(prop)
+            .ok()
+            .and_then(|v| serde_json::to_value(v).ok())
+    };
+    let differing: Vec<&str> = props
+        .into_iter()
+        .filter(|prop| json(&backed_up, prop) != json(&current, prop))
+        .map(|prop| prop.as_str())
+        .collect();
+    if !differing.is_empty() {
+        let message = format!(
+            "Differs from the live store in {}, although it has not changed since the backup",
+            differing.join(", ")
+        );
+        report.discrepancy("sample", Some(subject), message);
+    }
+}
+
+/// Tells the admins of the Drive (the Agents with write rights to it) that a backup failed its verification,
+/// and POSTs the report to `alert_url` if it is set.
+pub fn alert(
+    store: &Db,
+    report: &BackupVerification,
+    report_subject: &str,
+    alert_url: Option<&str>,
+) -> AtomicServerResult<()> {
+    let message = format!(
+        "The backup {} failed its verification with {} discrepancies.",
+        report.backup.display(),
+        report.discrepancies.len()
+    );
+    let drive = store.get_resource(store.get_server_url())?;
+    let admins = drive
+        .get(urls::WRITE)
+        .and_then(|agents| agents.to_subjects(None))
+        .unwrap_or_default();
+    for admin in admins.iter().filter(|agent| *agent != urls::PUBLIC_AGENT) {
+        atomic_lib::plugins::notifications::notify_agent(store, admin, report_subject, &message)?;
+    }
+    if let Some(url) = alert_url {
+        let body = serde_json::json!({ "message": message, "report": report }).to_string();
+        let headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+        let response = store.get_outbound().unwrap_or_default().request(
+            OutboundFeature::Webhook,
+            "POST",
+            url,
+            &headers,
+            Some(body.as_bytes()),
+        )?;
+        if response.status() >= 400 {
+            return Err(format!(
+                "The backup alert to {} failed with status {}",
+                url,
+                response.status()
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atomic_lib::Value;
+
+    #[test]
+    fn verifies_a_backup() {
+        let store = Db::init_temp("verifies_a_backup").unwrap();
+        let uploads = PathBuf::from(".temp/verifies_a_backup/uploads");
+        let backup = PathBuf::from(".temp/verifies_a_backup/backup");
+        let _ = std::fs::remove_dir_all(".temp/verifies_a_backup");
+        std::fs::create_dir_all(&uploads).unwrap();
+        std::fs::write(uploads.join("blob.txt"), b"contents").unwrap();
+        let mut file = Resource::new(format!("{}/files/blob", store.get_server_url()));
+        file.set_class(urls::FILE);
+        for (prop, value) in [
+            (
+                urls::PARENT,
+                Value::AtomicUrl(store.get_server_url().into()),
+            ),
+            (urls::INTERNAL_ID, Value::String("blob.txt".into())),
+            (urls::FILESIZE, Value::Integer(8)),
+            (urls::MIMETYPE, Value::String("text/plain".into())),
+            (urls::FILENAME, Value::String("blob.txt".into())),
+            (
+                urls::DOWNLOAD_URL,
+                Value::String(format!("{}/download/files/blob", store.get_server_url())),
+            ),
+        ] {
+            file.set_propval_unsafe(prop.into(), value);
+        }
+        file.save_locally(&store).unwrap();
+
+        let manifest = create(&store, &uploads, &backup).unwrap();
+        assert_eq!(manifest.files.len(), 1);
+        assert!(manifest.commits > 0);
+        assert_eq!(latest(backup.parent().unwrap()).unwrap(), backup);
+
+        let report = verify(Some(&store), &backup, 1000).unwrap();
+        assert!(report.passed, "{:?}", report.discrepancies);
+        assert_eq!(report.resources, manifest.resources);
+        assert!(report.sampled > 0);
+        assert_eq!(report.files_checked, 1);
+
+        // Changes in the live store after the backup are not discrepancies
+        file.set_propval_unsafe(urls::FILENAME.into(), Value::String("renamed.txt".into()));
+        file.save_locally(&store).unwrap();
+        std::fs::write(backup.join(UPLOADS_DIR).join("blob.txt"), b"tampered").unwrap();
+        let report = verify(Some(&store), &backup, 1000).unwrap();
+        assert!(!report.passed);
+        assert!(report.changed_since_backup > 0);
+        assert_eq!(report.discrepancies.len(), 1, "{:?}", report.discrepancies);
+        assert_eq!(report.discrepancies[0].kind, "file");
+    }
+}
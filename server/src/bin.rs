@@ -1,27 +1,57 @@
-use atomic_lib::{agents::ForAgent, urls, Storelike};
+use atomic_lib::Storelike;
 use atomic_server_lib::config::Opts;
 use std::{fs::File, io::Write};
 
 mod actor_messages;
+mod agent_activity;
+mod agent_overview;
 mod appstate;
+mod audit;
+mod backup;
+mod cache;
+mod collection_watch;
+mod commit_limits;
 mod commit_monitor;
 pub mod config;
+mod connections;
 mod content_types;
+mod durable_subscriptions;
 mod errors;
 mod handlers;
 mod helpers;
+mod html_export;
 #[cfg(feature = "https")]
 mod https;
+mod jobs;
 mod jsonerrors;
+mod link_checker;
+mod link_preview;
+mod locale;
+mod openapi;
+mod presence;
 #[cfg(feature = "process-management")]
 mod process;
+mod query_table;
+mod replication;
 mod routes;
+mod schema;
+mod self_check;
 pub mod serve;
+mod serve_file;
+mod settings;
+mod setup;
+mod side_effects;
+mod table_view;
 // #[cfg(feature = "search")]
 mod search;
+mod security_headers;
+mod store_cli;
 #[cfg(test)]
 mod tests;
+mod text_extraction;
 mod trace;
+mod upload_progress;
+mod webhooks;
 
 #[actix_web::main]
 async fn main() -> () {
@@ -38,55 +68,55 @@ async fn main_wrapped() -> errors::AtomicServerResult<()> {
     let config = config::build_config(config::read_opts())
         .map_err(|e| format!("Initialization failed: {}", e))?;
 
+    if config.opts.check && config.opts.command.is_none() {
+        return self_check::run_only(&config);
+    }
+
     match &config.opts.command {
-        Some(config::Command::Export(e)) => {
-            let path = match e.path.clone() {
-                Some(p) => std::path::Path::new(&p).to_path_buf(),
-                None => {
-                    let date = chrono::Local::now().to_rfc3339();
-                    let pathstr = format!("backups/{}.json", date);
-                    let mut pt = config.config_dir.clone();
-                    pt.push(&pathstr);
-                    pt
-                }
-            };
+        Some(
+            config::Command::Export(_)
+            | config::Command::Import(_)
+            | config::Command::Show(_)
+            | config::Command::RebuildIndex
+            | config::Command::Check
+            | config::Command::Backup(_)
+            | config::Command::VerifyBackup(_),
+        ) => {
+            let code = store_cli::run(&config).unwrap_or(store_cli::EXIT_ERROR);
+            std::process::exit(code);
+        }
+        Some(config::Command::PopulateTestData(opts)) => {
             let appstate = appstate::init(config.clone())?;
-            let outstr = appstate.store.export(!e.only_internal)?;
-            std::fs::create_dir_all(path.parent().unwrap())
-                .map_err(|e| format!("Failed to create directory {:?}. {}", path, e))?;
-            let mut file = File::create(&path)
-                .map_err(|e| format!("Failed to write file to {:?}. {}", path, e))?;
-            write!(file, "{}", outstr)?;
-            println!("Succesfully exported data to {}", path.to_str().unwrap());
+            let parent = opts
+                .parent
+                .clone()
+                .unwrap_or_else(|| appstate.store.get_server_url().into());
+            println!("Generating {} instances of {}...", opts.count, opts.class);
+            let resources = atomic_lib::fixtures::generate_instances(
+                &appstate.store,
+                &opts.class,
+                opts.count,
+                opts.seed,
+            )?;
+            let saved = atomic_lib::fixtures::save_instances(&appstate.store, resources, &parent)?;
+            println!("Saved {} Resources under {}", saved, parent);
             Ok(())
         }
-        Some(config::Command::Import(import_opts)) => {
-            let readstring = {
-                let path = std::path::Path::new(&import_opts.file);
-                std::fs::read_to_string(path)?
-            };
-
-            let appstate = appstate::init(config.clone())?;
-            let importer_subject = if let Some(i) = &import_opts.parent {
-                i.into()
-            } else {
-                urls::construct_path_import(&appstate.store.get_self_url().expect("No self url"))
-            };
-            let parse_opts = atomic_lib::parse::ParseOpts {
-                importer: Some(importer_subject),
-                for_agent: ForAgent::Sudo,
-                overwrite_outside: true,
-                save: if import_opts.force {
-                    atomic_lib::parse::SaveOpts::Save
-                } else {
-                    atomic_lib::parse::SaveOpts::Commit
-                },
-                signer: Some(appstate.store.get_default_agent()?),
-            };
-            println!("Importing...");
-            appstate.store.import(&readstring, &parse_opts)?;
-
-            println!("Sucesfully imported {:?} to store.", import_opts.file);
+        Some(config::Command::VerifyAudit) => {
+            let dir = config
+                .opts
+                .audit_dir
+                .clone()
+                .ok_or("Set the audit log directory using `--audit-dir`")?;
+            let store = atomic_lib::Db::init(&config.store_path, config.server_url.clone())?;
+            let report = audit::verify(&dir, &store)?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?
+            );
+            if !report.is_ok() {
+                return Err("The audit log does not match the store".into());
+            }
             Ok(())
         }
         Some(config::Command::ShowConfig) => {
@@ -0,0 +1,200 @@
+//! HTTP caching headers for Resources and Files, and purging CDN caches when Resources change.
+//! Caching is disabled unless `--cache-max-age` is set.
+//! Only anonymous requests for publicly readable Resources can be cached by CDNs.
+//! Responses depend on the authentication headers and cookies, which are listed in the `Vary` header,
+//! so a CDN never serves a private rendering to someone else.
+
+use atomic_lib::{
+    agents::ForAgent,
+    commit::CommitResponse,
+    hierarchy::check_read,
+    outbound::{OutboundFeature, OutboundHttp},
+    urls, Db, Resource, Storelike,
+};
+
+use crate::{config::Opts, errors::AtomicServerResult};
+
+/// The request headers that can change the response for a Resource.
+pub const VARY: &str =
+    "Accept, Cookie, x-atomic-agent, x-atomic-public-key, x-atomic-signature, x-atomic-timestamp";
+/// Used when caching is disabled.
+/// This prevents the browser from displaying the JSON response upon re-opening a closed tab
+/// https://github.com/atomicdata-dev/atomic-server/issues/137
+const NO_CACHE: &str = "no-store, no-cache, must-revalidate, private";
+const PRIVATE: &str = "private, no-store";
+/// Files are never changed after uploading, so they can be cached for a year.
+const FILE_MAX_AGE: u64 = 365 * 24 * 60 * 60;
+
+/// Which `Cache-Control` header is set on responses.
+#[derive(Clone, Debug)]
+pub struct CachePolicy {
+    max_age: Option<u64>,
+    stale_while_revalidate: u64,
+}
+
+impl CachePolicy {
+    pub fn from_opts(opts: &Opts) -> Self {
+        CachePolicy {
+            max_age: opts.cache_max_age,
+            stale_while_revalidate: opts.cache_stale_while_revalidate,
+        }
+    }
+
+    /// `Cache-Control` for a Resource (JSON, JSON-AD, HTML, RDF).
+    /// `public` means that the Resource was requested anonymously, and is readable by the Public Agent.
+    pub fn resource(&self, public: bool) -> String {
+        match self.max_age {
+            None => NO_CACHE.into(),
+            Some(max_age) if public => format!(
+                "public, max-age={}, stale-while-revalidate={}",
+                max_age, self.stale_while_revalidate
+            ),
+            Some(_) => PRIVATE.into(),
+        }
+    }
+
+    /// `Cache-Control` for a `/preview`, which always carries an `ETag`.
+    /// Without `--cache-max-age`, clients may store it, but revalidate it on every use.
+    pub fn preview(&self, public: bool) -> String {
+        match self.max_age {
+            None => "private, no-cache".into(),
+            Some(_) => self.resource(public),
+        }
+    }
+
+    /// `Cache-Control` for a downloaded File, or `None` if caching is disabled.
+    pub fn file(&self, public: bool) -> Option<String> {
+        self.max_age?;
+        let scope = if public { "public" } else { "private" };
+        Some(format!("{}, max-age={}, immutable", scope, FILE_MAX_AGE))
+    }
+}
+
+/// Whether a CDN may store the response for `resource`, which has been retrieved for `for_agent`.
+pub fn is_public(store: &impl Storelike, resource: &Resource, for_agent: &ForAgent) -> bool {
+    for_agent == &ForAgent::Public && check_read(store, resource, &ForAgent::Public).is_ok()
+}
+
+/// Removes changed URLs from the cache of a CDN. Called by the [crate::commit_monitor::CommitMonitor] after every Commit,
+/// so implementations should not block.
+pub trait CdnPurger: Send + Sync {
+    fn purge(&self, urls: Vec<String>);
+}
+
+/// Used when no CDN is configured.
+pub struct NoopPurger;
+
+impl CdnPurger for NoopPurger {
+    fn purge(&self, _urls: Vec<String>) {}
+}
+
+/// POSTs `{"urls": [...]}` to an endpoint, which most CDNs (or a small proxy in front of them) can handle.
+/// Requests are sent from a separate thread, failures are logged.
+#[derive(Clone)]
+pub struct HttpPurger {
+    endpoint: String,
+    token: Option<String>,
+    outbound: OutboundHttp,
+}
+
+impl HttpPurger {
+    pub fn new(endpoint: String, token: Option<String>, outbound: OutboundHttp) -> Self {
+        HttpPurger {
+            endpoint,
+            token,
+            outbound,
+        }
+    }
+
+    fn send(&self, urls: &[String]) -> AtomicServerResult<()> {
+        let mut headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+        if let Some(token) = &self.token {
+            headers.push(("Authorization".into(), format!("Bearer {}", token)));
+        }
+        let body = serde_json::json!({ "urls": urls }).to_string();
+        let response = self.outbound.request(
+            OutboundFeature::CdnPurge,
+            "POST",
+            &self.endpoint,
+            &headers,
+            Some(body.as_bytes()),
+        )?;
+        if response.status() >= 400 {
+            return Err(format!(
+                "CDN purge request to {} failed with status {}",
+                self.endpoint,
+                response.status()
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl CdnPurger for HttpPurger {
+    fn purge(&self, urls: Vec<String>) {
+        let purger = self.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = purger.send(&urls) {
+                tracing::warn!("{}", e);
+            }
+        });
+    }
+}
+
+/// Returns the [HttpPurger] if `cdn_purge_url` is set, or else the [NoopPurger].
+pub fn purger_from_opts(opts: &Opts, store: &Db) -> Box<dyn CdnPurger> {
+    match &opts.cdn_purge_url {
+        Some(endpoint) => Box::new(HttpPurger::new(
+            endpoint.clone(),
+            opts.cdn_purge_token.clone(),
+            store.get_outbound().unwrap_or_default(),
+        )),
+        None => Box::new(NoopPurger),
+    }
+}
+
+/// The URLs that have a different response after the Commit: the subject, and the download URL of Files.
+pub fn changed_urls(server_url: &str, commit_response: &CommitResponse) -> Vec<String> {
+    let subject = &commit_response.commit_struct.subject;
+    let mut changed = vec![subject.clone()];
+    let is_file = commit_response
+        .resource_new
+        .iter()
+        .chain(commit_response.resource_old.iter())
+        .any(|resource| resource.get(urls::INTERNAL_ID).is_ok());
+    if let Some(path) = subject.strip_prefix(server_url).filter(|_| is_file) {
+        changed.push(format!("{}/download{}", server_url, path));
+    }
+    changed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cache_control() {
+        let disabled = CachePolicy {
+            max_age: None,
+            stale_while_revalidate: 60,
+        };
+        assert_eq!(disabled.resource(true), NO_CACHE);
+        assert_eq!(disabled.file(true), None);
+
+        let enabled = CachePolicy {
+            max_age: Some(300),
+            stale_while_revalidate: 60,
+        };
+        assert_eq!(
+            enabled.resource(true),
+            "public, max-age=300, stale-while-revalidate=60"
+        );
+        assert_eq!(enabled.resource(false), "private, no-store");
+        assert_eq!(
+            enabled.file(true).unwrap(),
+            "public, max-age=31536000, immutable"
+        );
+        assert!(enabled.file(false).unwrap().starts_with("private"));
+    }
+}
@@ -0,0 +1,306 @@
+//! Live membership of DynamicCollections, see [atomic_lib::plugins::dynamic_collection].
+//! Connections that subscribe to a DynamicCollection receive `MEMBERSHIP ${MembershipChange}` when a Commit makes a Resource enter or leave its results.
+//! Collections are indexed by the Properties their queries depend on, so a Commit only evaluates the collections it can affect.
+//! Membership is checked on the changed Resource only: moving an ancestor to another scope, or changing the rights of a parent, does not send changes for its descendants.
+
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
+
+use atomic_lib::{
+    agents::ForAgent, commit::CommitResponse, plugins::dynamic_collection::DynamicCollection, Db,
+    Resource,
+};
+use serde::Serialize;
+
+/// Sent to the subscribers of a DynamicCollection.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MembershipChange {
+    pub collection: String,
+    pub member: String,
+    /// `true` if the member entered the results, `false` if it left them
+    pub added: bool,
+    /// The `client-id` of the Commit that changed the membership, if the client set one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+}
+
+struct Watched<C> {
+    collection: DynamicCollection,
+    /// The subscribed connections, and the Agent of each, since the results depend on rights and `$agent`
+    connections: HashMap<C, String>,
+}
+
+/// The DynamicCollections that have subscribers, per connection `C`.
+pub struct CollectionWatcher<C> {
+    collections: HashMap<String, Watched<C>>,
+    /// Property URL -> the collections whose query depends on it
+    by_property: HashMap<String, HashSet<String>>,
+}
+
+impl<C> Default for CollectionWatcher<C> {
+    fn default() -> Self {
+        CollectionWatcher {
+            collections: HashMap::new(),
+            by_property: HashMap::new(),
+        }
+    }
+}
+
+impl<C: Hash + Eq + Clone> CollectionWatcher<C> {
+    /// Sends the membership changes of the collection to the connection, until it unsubscribes or disconnects.
+    pub fn watch(&mut self, collection: DynamicCollection, connection: C, agent: &str) {
+        let subject = collection.subject.clone();
+        self.index(&collection);
+        self.collections
+            .entry(subject)
+            .or_insert_with(|| Watched {
+                collection,
+                connections: HashMap::new(),
+            })
+            .connections
+            .insert(connection, agent.into());
+    }
+
+    pub fn unwatch(&mut self, subject: &str, connection: &C) {
+        if let Some(watched) = self.collections.get_mut(subject) {
+            watched.connections.remove(connection);
+            if watched.connections.is_empty() {
+                self.remove(subject);
+            }
+        }
+    }
+
+    pub fn remove_connection(&mut self, connection: &C) {
+        let subjects: Vec<String> = self.collections.keys().cloned().collect();
+        for subject in subjects {
+            self.unwatch(&subject, connection);
+        }
+    }
+
+    /// Uses the new query after the collection itself was changed.
+    /// Stops watching it if it was destroyed, or if its query is no longer valid.
+    pub fn refresh(&mut self, subject: &str, resource_new: Option<&Resource>) {
+        if !self.collections.contains_key(subject) {
+            return;
+        }
+        let collection = resource_new.and_then(|r| DynamicCollection::from_resource(r).ok());
+        self.unindex(subject);
+        match collection {
+            Some(collection) => {
+                self.index(&collection);
+                if let Some(watched) = self.collections.get_mut(subject) {
+                    watched.collection = collection;
+                }
+            }
+            None => {
+                self.collections.remove(subject);
+            }
+        }
+    }
+
+    /// The membership changes caused by the Commit, per connection.
+    /// Only the collections that depend on one of the changed Properties are evaluated, for every Agent that watches them.
+    pub fn changes(&self, store: &Db, response: &CommitResponse) -> Vec<(C, MembershipChange)> {
+        let commit = &response.commit_struct;
+        let affected: HashSet<&String> = if commit.destroy == Some(true) {
+            self.collections.keys().collect()
+        } else {
+            let changed = [
+                &commit.set,
+                &commit.push,
+                &commit.pull,
+                &commit.patch,
+                &commit.increment,
+            ]
+            .into_iter()
+            .flatten()
+            .flat_map(|propvals| propvals.keys())
+            .chain(commit.remove.iter().flatten());
+            changed
+                .filter_map(|prop| self.by_property.get(prop))
+                .flatten()
+                .collect()
+        };
+
+        let mut changes = Vec::new();
+        for subject in affected {
+            if subject == &commit.subject {
+                continue;
+            }
+            let Some(watched) = self.collections.get(subject) else {
+                continue;
+            };
+            // Agents with several connections are only evaluated once
+            let mut members: HashMap<&str, (bool, bool)> = HashMap::new();
+            for (connection, agent) in &watched.connections {
+                let (was, is) = *members.entry(agent.as_str()).or_insert_with(|| {
+                    let for_agent = ForAgent::from(agent.as_str());
+                    let is_member = |resource: &Option<Resource>| {
+                        resource
+                            .as_ref()
+                            .map(|r| watched.collection.is_member(store, r, &for_agent))
+                            .unwrap_or(false)
+                    };
+                    (
+                        is_member(&response.resource_old),
+                        is_member(&response.resource_new),
+                    )
+                });
+                if was != is {
+                    changes.push((
+                        connection.clone(),
+                        MembershipChange {
+                            collection: subject.clone(),
+                            member: commit.subject.clone(),
+                            added: is,
+                            client_id: commit.client_id.clone(),
+                        },
+                    ));
+                }
+            }
+        }
+        changes
+    }
+
+    fn index(&mut self, collection: &DynamicCollection) {
+        for property in collection.properties() {
+            self.by_property
+                .entry(property)
+                .or_default()
+                .insert(collection.subject.clone());
+        }
+    }
+
+    fn unindex(&mut self, subject: &str) {
+        self.by_property.retain(|_property, subjects| {
+            subjects.remove(subject);
+            !subjects.is_empty()
+        });
+    }
+
+    fn remove(&mut self, subject: &str) {
+        self.unindex(subject);
+        self.collections.remove(subject);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atomic_lib::{
+        commit::{CommitBuilder, CommitOpts},
+        urls, Storelike, Value,
+    };
+
+    fn apply(store: &Db, subject: &str, propvals: Vec<(&str, Value)>) -> CommitResponse {
+        let resource = store
+            .get_resource(subject)
+            .unwrap_or_else(|_| Resource::new(subject.into()));
+        let mut commitbuilder = CommitBuilder::new(subject.into());
+        for (prop, val) in propvals {
+            commitbuilder.set(prop.into(), val);
+        }
+        let opts = CommitOpts {
+            validate_schema: false,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_rights: false,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        let agent = store.get_default_agent().unwrap();
+        commitbuilder
+            .sign(&agent, store, &resource)
+            .unwrap()
+            .apply_opts(store, &opts)
+            .unwrap()
+    }
+
+    #[test]
+    fn commits_that_change_membership_are_reported() {
+        let store = Db::init_temp("collection_watch_membership").unwrap();
+        let drive = store.get_server_url().to_string();
+        let agent = store.get_default_agent().unwrap().subject;
+        let conditions = serde_json::json!([
+            {"property": urls::NAME, "operator": "contains", "value": "todo"}
+        ]);
+        let collection_subject = format!("{}/open-tasks", drive);
+        let response = apply(
+            &store,
+            &collection_subject,
+            vec![
+                (
+                    urls::IS_A,
+                    Value::from(vec![urls::DYNAMIC_COLLECTION.to_string()]),
+                ),
+                (urls::PARENT, Value::AtomicUrl(drive.clone())),
+                (
+                    urls::DYNAMIC_COLLECTION_SCOPE,
+                    Value::AtomicUrl(drive.clone()),
+                ),
+                (
+                    urls::DYNAMIC_COLLECTION_CONDITIONS,
+                    Value::String(conditions.to_string()),
+                ),
+            ],
+        );
+        let collection =
+            DynamicCollection::from_resource(response.resource_new.as_ref().unwrap()).unwrap();
+        let mut watcher: CollectionWatcher<u32> = CollectionWatcher::default();
+        watcher.watch(collection, 1, &agent);
+
+        let task = format!("{}/task", drive);
+        let added = apply(
+            &store,
+            &task,
+            vec![
+                (urls::PARENT, Value::AtomicUrl(drive.clone())),
+                (urls::NAME, Value::String("todo: write docs".into())),
+            ],
+        );
+        let changes = watcher.changes(&store, &added);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(
+            changes[0],
+            (
+                1,
+                MembershipChange {
+                    collection: collection_subject.clone(),
+                    member: task.clone(),
+                    added: true,
+                    client_id: None,
+                }
+            )
+        );
+
+        // Properties that the query doesn't use are not evaluated
+        let unrelated = apply(
+            &store,
+            &task,
+            vec![(urls::DESCRIPTION, Value::Markdown("details".into()))],
+        );
+        assert!(watcher.changes(&store, &unrelated).is_empty());
+
+        let removed = apply(
+            &store,
+            &task,
+            vec![(urls::NAME, Value::String("done".into()))],
+        );
+        let changes = watcher.changes(&store, &removed);
+        assert!(!changes[0].1.added);
+
+        watcher.remove_connection(&1);
+        let again = apply(
+            &store,
+            &task,
+            vec![(urls::NAME, Value::String("todo again".into()))],
+        );
+        assert!(watcher.changes(&store, &again).is_empty());
+        assert!(watcher.by_property.is_empty());
+    }
+}
@@ -0,0 +1,275 @@
+//! Per-Agent limits for Commits that are sent to the `/commit` endpoint.
+//! Commits that the server applies itself (e.g. cleanup, invites) don't pass through the endpoint, so they are never limited.
+//! The limits are set in the [crate::settings::ServerSettings], and can be overridden using properties on the Agent resource.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use atomic_lib::{
+    agents::ForAgent, hierarchy::check_write, storelike::Query, urls, utils::now, Commit,
+    Storelike, Value,
+};
+
+use crate::{
+    appstate::AppState,
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Properties of an Agent that override the server-wide limits.
+/// Agents can edit their own resource, so only admins are allowed to set these.
+/// A suspension is lifted the same way, see [crate::agent_activity].
+pub const OVERRIDE_PROPS: &[&str] = &[
+    urls::COMMIT_RATE_LIMIT,
+    urls::MAX_COMMIT_SIZE,
+    urls::MAX_COMMIT_ARRAY_LENGTH,
+    urls::SUSPENDED,
+    urls::SUSPENSION_REASON,
+];
+
+/// The limits that apply to a single Agent. `None` means unlimited.
+#[derive(Debug, Default, PartialEq)]
+pub struct CommitLimits {
+    pub commits_per_minute: Option<u64>,
+    pub max_size: Option<u64>,
+    pub max_array_length: Option<u64>,
+    /// Maximum size of a single textual value. Set using the `max_value_size` option, and not overridden per Agent.
+    pub max_value_size: Option<u64>,
+}
+
+impl CommitLimits {
+    /// Reads the limits for the signer of a Commit.
+    /// Returns `None` for the Agent of the server itself, which is never limited.
+    pub fn for_signer(appstate: &AppState, signer: &str) -> Option<CommitLimits> {
+        let store = &appstate.store;
+        if let Ok(server_agent) = store.get_default_agent() {
+            if server_agent.subject == signer {
+                return None;
+            }
+        }
+        let settings = appstate.settings.get();
+        let agent = store.get_resource(signer).ok();
+        let read = |prop: &str, default: Option<u64>| -> Option<u64> {
+            match agent.as_ref().and_then(|a| a.get(prop).ok()) {
+                Some(Value::Integer(0)) => None,
+                Some(Value::Integer(limit)) if *limit > 0 => Some(*limit as u64),
+                _ => default,
+            }
+        };
+        Some(CommitLimits {
+            commits_per_minute: read(urls::COMMIT_RATE_LIMIT, settings.commit_rate_limit),
+            max_size: read(urls::MAX_COMMIT_SIZE, settings.max_commit_size),
+            max_array_length: read(
+                urls::MAX_COMMIT_ARRAY_LENGTH,
+                settings.max_commit_array_length,
+            ),
+            max_value_size: Some(appstate.config.opts.max_value_size).filter(|max| *max > 0),
+        })
+    }
+
+    /// Checks the size of the Commit body, and the arrays and values it sets or pushes.
+    pub fn check_payload(&self, body_len: usize, commit: &Commit) -> AtomicServerResult<()> {
+        if let Some(max) = self.max_size {
+            if body_len as u64 > max {
+                return Err(AtomicServerError::new(
+                    format!(
+                        "Commit is {} bytes, the maximum for this Agent is {} bytes",
+                        body_len, max
+                    ),
+                    AppErrorType::PayloadTooLarge,
+                ));
+            }
+        }
+        if let Some(max) = self.max_array_length {
+            let changes = commit.set.iter().chain(commit.push.iter()).flatten();
+            for (prop, value) in changes {
+                if let Value::ResourceArray(array) = value {
+                    if array.len() as u64 > max {
+                        return Err(AtomicServerError::new(
+                            format!(
+                                "Commit contains {} items for {}, the maximum for this Agent is {}",
+                                array.len(),
+                                prop,
+                                max
+                            ),
+                            AppErrorType::PayloadTooLarge,
+                        ));
+                    }
+                }
+            }
+        }
+        if let Some(max) = self.max_value_size {
+            let changes = commit.set.iter().chain(commit.push.iter()).flatten();
+            for (prop, value) in changes {
+                let size = value.text_len();
+                if size as u64 > max {
+                    return Err(AtomicServerError::new(
+                        format!(
+                            "The value of {} is {} bytes, the maximum is {} bytes. Upload large content as a File using `/upload`, and link to it instead.",
+                            prop, size, max
+                        ),
+                        AppErrorType::PayloadTooLarge,
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Keeps track of the Commits that were recently applied per Agent.
+#[derive(Clone, Default)]
+pub struct CommitLimiter {
+    recent: Arc<Mutex<HashMap<String, VecDeque<Instant>>>>,
+}
+
+impl CommitLimiter {
+    /// Errors if the Agent has already applied `limit` Commits in the last minute.
+    pub fn check_rate(&self, agent: &str, limit: Option<u64>) -> AtomicServerResult<()> {
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+        let mut recent = self.recent.lock().unwrap();
+        let Some(times) = recent.get_mut(agent) else {
+            return Ok(());
+        };
+        let cutoff = Instant::now() - RATE_WINDOW;
+        while times.front().map(|t| *t < cutoff).unwrap_or(false) {
+            times.pop_front();
+        }
+        if times.len() as u64 >= limit {
+            let retry_after = times
+                .front()
+                .map(|t| RATE_WINDOW.saturating_sub(t.elapsed()).as_secs() + 1)
+                .unwrap_or(1);
+            return Err(AtomicServerError::new(
+                format!(
+                    "Agent {} can send {} Commits per minute. Try again in {} seconds.",
+                    agent, limit, retry_after
+                ),
+                AppErrorType::TooManyRequests,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Registers an applied Commit. Only applied Commits count, so Commits with a forged signer can't use up the limit of another Agent.
+    pub fn record(&self, agent: &str) {
+        let mut recent = self.recent.lock().unwrap();
+        let cutoff = Instant::now() - RATE_WINDOW;
+        recent.retain(|_, times| times.back().map(|t| *t >= cutoff).unwrap_or(false));
+        recent
+            .entry(agent.to_string())
+            .or_default()
+            .push_back(Instant::now());
+    }
+}
+
+/// Only admins (Agents with write rights to the root Drive) can change the limits of Agents.
+pub fn check_override_rights(
+    appstate: &AppState,
+    commit: &Commit,
+    for_agent: &ForAgent,
+) -> AtomicServerResult<()> {
+    let sets_override = commit
+        .set
+        .iter()
+        .flat_map(|set| set.keys())
+        .chain(commit.remove.iter().flatten())
+        .any(|prop| OVERRIDE_PROPS.contains(&prop.as_str()));
+    if sets_override {
+        let store = &appstate.store;
+        let drive = store.get_resource(store.get_server_url())?;
+        check_write(store, &drive, for_agent).map_err(|e| {
+            format!(
+                "Only Agents with write rights to the root Drive can change Commit limits and suspensions. {}",
+                e
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Whether the Commit only changes the limits or the suspension of an existing Agent, and is signed by an admin.
+/// Agents have no parent, so admins need this to change them. Rights are not checked for these Commits.
+pub fn is_admin_override(appstate: &AppState, commit: &Commit) -> bool {
+    let is_empty = |map: &Option<HashMap<String, Value>>| map.as_ref().is_none_or(|m| m.is_empty());
+    let only_set_and_remove = is_empty(&commit.push)
+        && is_empty(&commit.pull)
+        && is_empty(&commit.patch)
+        && is_empty(&commit.increment)
+        && commit.destroy != Some(true)
+        && commit.purge != Some(true);
+    let mut props = commit
+        .set
+        .iter()
+        .flat_map(|set| set.keys())
+        .chain(commit.remove.iter().flatten())
+        .peekable();
+    if !only_set_and_remove
+        || props.peek().is_none()
+        || !props.all(|prop| OVERRIDE_PROPS.contains(&prop.as_str()))
+    {
+        return false;
+    }
+    let store = &appstate.store;
+    let is_agent = store
+        .get_resource(&commit.subject)
+        .and_then(|resource| resource.get_main_class())
+        .map(|class| class == urls::AGENT)
+        .unwrap_or(false);
+    is_agent
+        && store
+            .get_resource(store.get_server_url())
+            .and_then(|drive| check_write(store, &drive, &commit.signer.clone().into()))
+            .is_ok()
+}
+
+/// Counts the Commits per signer that were created after `since` (a unix timestamp in milliseconds), most active Agents first.
+pub fn top_committers(
+    store: &impl Storelike,
+    since: i64,
+    limit: usize,
+) -> AtomicServerResult<Vec<(String, usize)>> {
+    let mut query = Query::new_class(urls::COMMIT);
+    query.sort_by = Some(urls::CREATED_AT.into());
+    query.start_val = Some(Value::Timestamp(since));
+    let commits = store.query(&query)?;
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for commit in commits.resources {
+        if let Ok(signer) = commit.get(urls::SIGNER) {
+            *counts.entry(signer.to_string()).or_default() += 1;
+        }
+    }
+    let mut counts: Vec<(String, usize)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(limit);
+    Ok(counts)
+}
+
+/// The start of the report window, `hours` before now.
+pub fn hours_ago(hours: u64) -> i64 {
+    now() - (hours as i64) * 60 * 60 * 1000
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rate_limit() {
+        let limiter = CommitLimiter::default();
+        limiter.check_rate("alice", Some(2)).unwrap();
+        limiter.record("alice");
+        limiter.record("alice");
+        let err = limiter.check_rate("alice", Some(2)).unwrap_err();
+        assert!(matches!(err.error_type, AppErrorType::TooManyRequests));
+        limiter.check_rate("alice", None).unwrap();
+        limiter.check_rate("bob", Some(2)).unwrap();
+    }
+}
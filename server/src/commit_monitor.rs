@@ -1,34 +1,85 @@
 //! The Commit Monitor checks for new commits and notifies listeners.
 //! It is used for WebSockets to notify front-end clients of changes in Resources,
-//! and to update the Search index.
+//! and to update the Search index and the [crate::settings::ServerSettings].
+//! The search index and uploaded files are updated in phases, see [crate::side_effects].
+//! Changed URLs are purged from the CDN, see [crate::cache::CdnPurger].
+//! It also keeps track of who is present on which Resource, see [crate::presence].
+//! Commits that assign or mention Agents create Notifications, which are sent to the connections of those Agents, see [atomic_lib::plugins::notifications].
+//! Durable subscriptions are loaded when it starts, and are restored when their Agent connects, see [crate::durable_subscriptions].
+//! Subscribers of a DynamicCollection are told when Resources enter or leave its results, see [crate::collection_watch].
+//! Every Commit wakes the delivery of Webhooks, see [crate::webhooks].
 
 use crate::{
-    actor_messages::{CommitMessage, Subscribe},
+    actor_messages::{
+        Acknowledge, CommitMessage, Connect, Disconnect, SetJobQueue, SetPresence, Subscribe,
+        SubscribeAll, Unsubscribe, WhoIsPresent, WsMessage,
+    },
+    cache::CdnPurger,
+    collection_watch::CollectionWatcher,
+    durable_subscriptions::{ancestors, DurableSubscriptions},
     errors::AtomicServerResult,
     handlers::web_sockets::WebSocketConnection,
+    jobs::{JobQueue, JobType},
+    presence::{Presence, PresenceRegistry, PresenceState, PRESENCE_TIMEOUT},
     search::SearchState,
+    settings::Settings,
+    side_effects::SideEffects,
+    webhooks::Webhooks,
 };
 use actix::{
     prelude::{Actor, Context, Handler},
     ActorStreamExt, Addr, ContextFutureSpawner,
 };
-use atomic_lib::{agents::ForAgent, Db, Storelike};
+use atomic_lib::{
+    agents::ForAgent, plugins::dynamic_collection::DynamicCollection, urls, Db, Storelike,
+};
 use chrono::Local;
-use std::collections::{HashMap, HashSet};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 
 /// The Commit Monitor is an Actor that manages subscriptions for subjects and sends Commits to listeners.
 /// It's also responsible for checking whether the rights are present
 pub struct CommitMonitor {
     /// Maintains a list of all the resources that are being subscribed to, and maps these to websocket connections.
     subscriptions: HashMap<String, HashSet<Addr<WebSocketConnection>>>,
+    /// Connections that receive the Commits of a Resource and all its descendants
+    subtree_subscriptions: HashMap<String, HashSet<Addr<WebSocketConnection>>>,
+    /// The stored subscriptions, which are restored when their Agent connects
+    durable: DurableSubscriptions,
+    /// Durable subscriptions whose Agent has not been seen for this long are removed
+    durable_max_idle: Duration,
+    last_durable_cleanup: Instant,
+    /// Receive every Commit, e.g. the streams of replicas
+    all_commits: Vec<futures::channel::mpsc::UnboundedSender<String>>,
     store: Db,
     search_state: SearchState,
+    /// Refreshed when a Commit changes the ServerSettings resource
+    settings: Settings,
+    /// Removes changed Resources from the CDN cache
+    purger: Box<dyn CdnPurger>,
     last_search_commit: chrono::DateTime<Local>,
     run_expensive_next_tick: bool,
+    /// Also removes the uploaded files of destroyed Files
+    side_effects: SideEffects,
+    /// Runs the repairs of failed side effects. Set after the job workers have started.
+    job_queue: Option<JobQueue>,
+    /// Who is viewing or editing which Resource, per connection
+    presence: PresenceRegistry<Addr<WebSocketConnection>>,
+    /// The connections of every signed in Agent, which receive its Notifications
+    agents: HashMap<String, HashSet<Addr<WebSocketConnection>>>,
+    /// The subscribed DynamicCollections, whose subscribers receive membership changes
+    collections: CollectionWatcher<Addr<WebSocketConnection>>,
+    /// Delivers the Commits to Webhooks
+    webhooks: Webhooks,
 }
 
 // Only runs expensive index operation (tantivy) once every x seconds
 const REBUILD_INDEX_TIME: std::time::Duration = std::time::Duration::from_secs(5);
+/// How often to look for durable subscriptions that have not been used for too long
+const DURABLE_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
 // Since his Actor only starts once, there is no need to handle its lifecycle
 impl Actor for CommitMonitor {
@@ -67,14 +118,43 @@ impl Handler<Subscribe> for CommitMonitor {
                     &ForAgent::AgentSubject(msg.agent.clone()),
                 ) {
                     Ok(_explanation) => {
-                        let mut set = if let Some(set) = self.subscriptions.get(&msg.subject) {
-                            set.clone()
-                        } else {
-                            HashSet::new()
-                        };
-                        set.insert(msg.addr);
+                        let include_children = msg
+                            .durable
+                            .as_ref()
+                            .map(|options| options.include_children)
+                            .unwrap_or(false);
+                        if let Some(options) = msg.durable {
+                            if let Err(e) = self.durable.subscribe(
+                                &self.store,
+                                &msg.agent,
+                                &msg.subject,
+                                options,
+                            ) {
+                                msg.addr.do_send(WsMessage(format!(
+                                    "ERROR Can't store the subscription to {}: {}",
+                                    msg.subject, e
+                                )));
+                                return;
+                            }
+                        }
                         tracing::debug!("handle subscribe {} ", msg.subject);
-                        self.subscriptions.insert(msg.subject.clone(), set);
+                        if resource
+                            .get_main_class()
+                            .map(|class| class == urls::DYNAMIC_COLLECTION)
+                            .unwrap_or(false)
+                        {
+                            match DynamicCollection::from_resource(&resource) {
+                                Ok(collection) => {
+                                    self.collections
+                                        .watch(collection, msg.addr.clone(), &msg.agent)
+                                }
+                                Err(e) => msg.addr.do_send(WsMessage(format!(
+                                    "ERROR Can't watch the members of {}: {}",
+                                    msg.subject, e
+                                ))),
+                            }
+                        }
+                        self.add_subscriber(&msg.subject, msg.addr, include_children);
                     }
                     Err(unauthorized_err) => {
                         tracing::debug!(
@@ -98,6 +178,132 @@ impl Handler<Subscribe> for CommitMonitor {
     }
 }
 
+impl Handler<Unsubscribe> for CommitMonitor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Unsubscribe, _ctx: &mut Context<Self>) {
+        for subscriptions in [&mut self.subscriptions, &mut self.subtree_subscriptions] {
+            if let Some(set) = subscriptions.get_mut(&msg.subject) {
+                set.remove(&msg.addr);
+            }
+        }
+        self.collections.unwatch(&msg.subject, &msg.addr);
+        if let Err(e) = self
+            .durable
+            .unsubscribe(&self.store, &msg.agent, &msg.subject)
+        {
+            msg.addr.do_send(WsMessage(format!(
+                "ERROR Can't remove the subscription to {}: {}",
+                msg.subject, e
+            )));
+        }
+    }
+}
+
+impl Handler<Acknowledge> for CommitMonitor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Acknowledge, _ctx: &mut Context<Self>) {
+        if let Err(e) = self
+            .durable
+            .acknowledge(&self.store, &msg.agent, &msg.commit)
+        {
+            msg.addr.do_send(WsMessage(format!(
+                "ERROR Can't acknowledge {}: {}",
+                msg.commit, e
+            )));
+        }
+    }
+}
+
+impl Handler<SetPresence> for CommitMonitor {
+    type Result = ();
+
+    #[tracing::instrument(name = "handle_set_presence", skip_all, fields(on = %msg.subject, agent = %msg.agent))]
+    fn handle(&mut self, msg: SetPresence, _ctx: &mut Context<Self>) {
+        // Leaving is always possible, even if the Agent lost its rights in the meantime
+        if msg.state != PresenceState::Left {
+            if let Err(e) = self.check_presence_read(&msg.subject, &msg.agent) {
+                msg.addr.do_send(WsMessage(format!(
+                    "ERROR Can't set presence on {}: {}",
+                    msg.subject, e
+                )));
+                return;
+            }
+        }
+        let name = self
+            .store
+            .get_resource(&msg.agent)
+            .ok()
+            .and_then(|agent| agent.get(urls::NAME).ok().map(|name| name.to_string()));
+        match self
+            .presence
+            .set(&msg.subject, msg.addr.clone(), &msg.agent, name, msg.state)
+        {
+            Ok(Some(presence)) => self.broadcast_presence(&msg.subject, &presence, Some(&msg.addr)),
+            Ok(None) => {}
+            Err(e) => msg.addr.do_send(WsMessage(format!("ERROR {}", e))),
+        }
+    }
+}
+
+impl Handler<WhoIsPresent> for CommitMonitor {
+    type Result = ();
+
+    fn handle(&mut self, msg: WhoIsPresent, _ctx: &mut Context<Self>) {
+        let message = match self.check_presence_read(&msg.subject, &msg.agent) {
+            Ok(()) => match serde_json::to_string(&self.presence.list(&msg.subject)) {
+                Ok(list) => format!("PRESENCE_LIST {} {}", msg.subject, list),
+                Err(e) => format!("ERROR {}", e),
+            },
+            Err(e) => format!("ERROR Can't see presence on {}: {}", msg.subject, e),
+        };
+        msg.addr.do_send(WsMessage(message));
+    }
+}
+
+impl Handler<Connect> for CommitMonitor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Connect, _ctx: &mut Context<Self>) {
+        // A connection that authenticates again no longer receives the Notifications of the previous Agent
+        self.remove_connection(&msg.addr);
+        self.agents
+            .entry(msg.agent.clone())
+            .or_default()
+            .insert(msg.addr.clone());
+        self.restore_durable(&msg.agent, &msg.addr);
+    }
+}
+
+impl Handler<Disconnect> for CommitMonitor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Disconnect, _ctx: &mut Context<Self>) {
+        self.remove_connection(&msg.addr);
+        for (subject, presence) in self.presence.remove_connection(&msg.addr) {
+            self.broadcast_presence(&subject, &presence, None);
+        }
+    }
+}
+
+impl Handler<SubscribeAll> for CommitMonitor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SubscribeAll, _ctx: &mut Context<Self>) {
+        self.all_commits.push(msg.sender);
+    }
+}
+
+impl Handler<SetJobQueue> for CommitMonitor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetJobQueue, _ctx: &mut Context<Self>) {
+        self.job_queue = Some(msg.job_queue);
+        self.run_expensive_next_tick = true;
+    }
+}
+
 impl CommitMonitor {
     /// When a commit comes in, send it to any listening subscribers,
     /// and update the value index.
@@ -105,6 +311,21 @@ impl CommitMonitor {
     fn handle_internal(&mut self, msg: CommitMessage) -> AtomicServerResult<()> {
         let target = msg.commit_response.commit_struct.subject.clone();
 
+        // Update the search index first, so failures below don't skip it
+        match crate::side_effects::stage(&msg.commit_response, &self.search_state, &self.store) {
+            Ok(staged) => self.side_effects.apply(staged, &self.search_state),
+            Err(e) => {
+                tracing::error!(
+                    "Could not prepare the search document of {}, scheduling a repair: {}",
+                    target,
+                    e
+                );
+                self.side_effects.stage_failed(&target);
+            }
+        }
+        self.run_expensive_next_tick = true;
+        self.webhooks.notify();
+
         // Notify websocket listeners
         if let Some(subscribers) = self.subscriptions.get(&target) {
             tracing::debug!(
@@ -118,24 +339,234 @@ impl CommitMonitor {
         } else {
             tracing::debug!("No subscribers for {}", target);
         }
+        if !self.subtree_subscriptions.is_empty() {
+            self.send_to_subtree_subscribers(&msg);
+        }
+        if msg.commit_response.commit_struct.destroy == Some(true) {
+            self.durable.forget(&target);
+        }
+        self.collections
+            .refresh(&target, msg.commit_response.resource_new.as_ref());
+        self.send_membership_changes(&msg);
 
-        // Update the search index
-        if let Some(resource) = &msg.commit_response.resource_new {
-            // We could one day re-(allow) to keep old resources,
-            // but then we also should index the older versions when re-indexing.
-            crate::search::remove_resource(&self.search_state, &target)?;
-            // Add new resource to search index
-            crate::search::add_resource(&self.search_state, resource, &self.store)?;
-            self.run_expensive_next_tick = true;
+        if !self.all_commits.is_empty() {
+            let json = msg.commit_response.echoed_commit_resource().to_json_ad()?;
+            // Closed streams are removed
+            self.all_commits
+                .retain(|sender| sender.unbounded_send(json.clone()).is_ok());
+        }
+
+        self.purger.purge(crate::cache::changed_urls(
+            self.store.get_server_url(),
+            &msg.commit_response,
+        ));
+
+        if target == self.settings.subject() {
+            self.settings
+                .refresh(msg.commit_response.resource_new.as_ref());
+        }
+
+        let notifications = atomic_lib::plugins::notifications::notify(
+            &self.store,
+            &msg.commit_response,
+            &self.settings.get().notify_on,
+        )?;
+        for notification in notifications {
+            self.send_notification(
+                &notification,
+                msg.commit_response.commit_struct.client_id.as_deref(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Sends `NOTIFICATION ${Notification}` to the connections of the recipient.
+    /// The `client-id` of the Commit that caused it is added to the sent copy only, since the stored Notification is shared by every Commit.
+    fn send_notification(
+        &self,
+        notification: &atomic_lib::Resource,
+        client_id: Option<&str>,
+    ) -> AtomicServerResult<()> {
+        let recipient = notification.get(urls::RECIPIENT)?.to_string();
+        let Some(connections) = self.agents.get(&recipient) else {
+            return Ok(());
+        };
+        let mut notification = notification.clone();
+        if let Some(client_id) = client_id {
+            notification.set_propval_unsafe(
+                urls::CLIENT_ID.into(),
+                atomic_lib::Value::String(client_id.into()),
+            );
+        }
+        let message = format!("NOTIFICATION {}", notification.to_json_ad()?);
+        for connection in connections {
+            connection.do_send(WsMessage(message.clone()));
+        }
+        Ok(())
+    }
+
+    /// Sends `MEMBERSHIP ${MembershipChange}` to the subscribers of the DynamicCollections that the Commit adds a member to, or removes one from.
+    fn send_membership_changes(&self, msg: &CommitMessage) {
+        for (connection, change) in self.collections.changes(&self.store, &msg.commit_response) {
+            match serde_json::to_string(&change) {
+                Ok(json) => connection.do_send(WsMessage(format!("MEMBERSHIP {}", json))),
+                Err(e) => tracing::error!("Could not serialize membership change: {}", e),
+            }
+        }
+    }
+
+    fn remove_connection(&mut self, addr: &Addr<WebSocketConnection>) {
+        self.agents.retain(|_agent, connections| {
+            connections.remove(addr);
+            !connections.is_empty()
+        });
+        self.subtree_subscriptions.retain(|_subject, connections| {
+            connections.remove(addr);
+            !connections.is_empty()
+        });
+        self.collections.remove_connection(addr);
+    }
+
+    fn add_subscriber(
+        &mut self,
+        subject: &str,
+        addr: Addr<WebSocketConnection>,
+        include_children: bool,
+    ) {
+        let subscriptions = if include_children {
+            &mut self.subtree_subscriptions
         } else {
-            // If there is no new resource, it must have been deleted, so let's remove it from the search index.
-            crate::search::remove_resource(&self.search_state, &target)?;
+            &mut self.subscriptions
+        };
+        subscriptions
+            .entry(subject.into())
+            .or_default()
+            .insert(addr);
+    }
+
+    /// Sends the Commit to the connections that are subscribed to the Resource or one of its parents, and not to the Resource itself.
+    fn send_to_subtree_subscribers(&self, msg: &CommitMessage) {
+        let target = &msg.commit_response.commit_struct.subject;
+        let parents = msg
+            .commit_response
+            .resource_new
+            .as_ref()
+            .or(msg.commit_response.resource_old.as_ref())
+            .map(|resource| ancestors(&self.store, resource))
+            .unwrap_or_default();
+        let direct = self.subscriptions.get(target);
+        let connections: HashSet<&Addr<WebSocketConnection>> = std::iter::once(target)
+            .chain(parents.iter())
+            .filter_map(|subject| self.subtree_subscriptions.get(subject))
+            .flatten()
+            .filter(|connection| !direct.map(|d| d.contains(*connection)).unwrap_or(false))
+            .collect();
+        for connection in connections {
+            connection.do_send(msg.clone());
+        }
+    }
+
+    /// Subscribes the connection to the durable subscriptions of the Agent, and replays the Commits it has not acknowledged yet.
+    fn restore_durable(&mut self, agent: &str, addr: &Addr<WebSocketConnection>) {
+        let mut replay = BTreeMap::new();
+        for subscription in self.durable.connected(&self.store, agent) {
+            // The Agent may have lost its rights while it was away
+            let readable = self
+                .store
+                .get_resource(&subscription.target)
+                .and_then(|resource| {
+                    atomic_lib::hierarchy::check_read(
+                        &self.store,
+                        &resource,
+                        &ForAgent::AgentSubject(agent.into()),
+                    )
+                });
+            if readable.is_err() {
+                continue;
+            }
+            match subscription.catch_up(&self.store) {
+                Ok(commits) => replay.extend(commits),
+                Err(e) => tracing::error!(
+                    "Could not replay the Commits of {}: {}",
+                    subscription.subject,
+                    e
+                ),
+            }
+            self.add_subscriber(
+                &subscription.target,
+                addr.clone(),
+                subscription.options.include_children,
+            );
+        }
+        for (sequence, commit) in replay.iter_mut() {
+            commit.set_propval_unsafe(
+                urls::COMMIT_SEQUENCE.into(),
+                atomic_lib::Value::Integer(*sequence as i64),
+            );
+            match commit.to_json_ad() {
+                Ok(json) => addr.do_send(WsMessage(format!("COMMIT {}", json))),
+                Err(e) => tracing::error!("Could not serialize Commit: {}", e),
+            }
         }
+    }
+
+    /// Presence is only shared on local Resources that the Agent can read.
+    fn check_presence_read(&self, subject: &str, agent: &str) -> AtomicServerResult<()> {
+        if !subject.starts_with(self.store.get_server_url()) {
+            return Err("Presence is only available for local Resources".into());
+        }
+        let resource = self.store.get_resource(subject)?;
+        atomic_lib::hierarchy::check_read(
+            &self.store,
+            &resource,
+            &ForAgent::AgentSubject(agent.into()),
+        )?;
         Ok(())
     }
 
+    /// Sends `PRESENCE_UPDATE ${subject} ${Presence}` to the subscribers of the subject, except the connection the update came from.
+    fn broadcast_presence(
+        &self,
+        subject: &str,
+        presence: &Presence,
+        except: Option<&Addr<WebSocketConnection>>,
+    ) {
+        let Some(subscribers) = self.subscriptions.get(subject) else {
+            return;
+        };
+        let json = match serde_json::to_string(presence) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::error!("Could not serialize presence: {}", e);
+                return;
+            }
+        };
+        let message = format!("PRESENCE_UPDATE {} {}", subject, json);
+        for connection in subscribers
+            .iter()
+            .filter(|connection| Some(*connection) != except)
+        {
+            connection.do_send(WsMessage(message.clone()));
+        }
+    }
+
     /// Runs every X seconds to perform expensive operations.
     fn tick(&mut self, _ctx: &mut Context<Self>) {
+        for (subject, _connection, presence) in self.presence.expire(PRESENCE_TIMEOUT) {
+            self.broadcast_presence(&subject, &presence, None);
+        }
+        if self.last_durable_cleanup.elapsed() > DURABLE_CLEANUP_INTERVAL {
+            self.last_durable_cleanup = Instant::now();
+            let agents = &self.agents;
+            let removed =
+                self.durable
+                    .collect_garbage(&self.store, self.durable_max_idle, |agent| {
+                        agents.contains_key(agent)
+                    });
+            if removed > 0 {
+                tracing::info!("Removed {} unused durable subscriptions", removed);
+            }
+        }
         if self.run_expensive_next_tick {
             _ = self.update_expensive().map_err(|e| {
                 tracing::error!(
@@ -149,9 +580,28 @@ impl CommitMonitor {
     /// Run expensive updates that should not be run after every single Commit
     fn update_expensive(&mut self) -> AtomicServerResult<()> {
         tracing::debug!("Update expensive");
-        self.search_state.writer.write()?.commit()?;
+        self.side_effects.flush(&self.search_state);
         self.last_search_commit = chrono::Local::now();
         self.run_expensive_next_tick = false;
+        self.schedule_repairs()
+    }
+
+    /// Enqueues a Job for the side effects that failed. Keeps them until the job workers have started.
+    fn schedule_repairs(&mut self) -> AtomicServerResult<()> {
+        let Some(job_queue) = &self.job_queue else {
+            return Ok(());
+        };
+        let repairs = self.side_effects.take_repairs();
+        if repairs.is_empty() {
+            return Ok(());
+        }
+        let params = serde_json::to_value(&repairs).map_err(|e| e.to_string())?;
+        if let Err(e) = job_queue.enqueue(JobType::RepairSideEffects, params, &ForAgent::Sudo) {
+            self.side_effects.restore_repairs(repairs);
+            // Try again on the next tick
+            self.run_expensive_next_tick = true;
+            return Err(e);
+        }
         Ok(())
     }
 }
@@ -175,13 +625,38 @@ impl Handler<CommitMessage> for CommitMonitor {
 }
 
 /// Spawns a commit monitor actor
-pub fn create_commit_monitor(store: Db, search_state: SearchState) -> Addr<CommitMonitor> {
+pub fn create_commit_monitor(
+    store: Db,
+    search_state: SearchState,
+    uploads_path: PathBuf,
+    settings: Settings,
+    purger: Box<dyn CdnPurger>,
+    durable_max_idle: Duration,
+    webhooks: Webhooks,
+) -> Addr<CommitMonitor> {
+    let durable = DurableSubscriptions::load(&store).unwrap_or_else(|e| {
+        tracing::error!("Could not load the durable subscriptions: {}", e);
+        DurableSubscriptions::default()
+    });
     crate::commit_monitor::CommitMonitor::create(|_ctx: &mut Context<CommitMonitor>| {
         CommitMonitor {
             subscriptions: HashMap::new(),
+            subtree_subscriptions: HashMap::new(),
+            durable,
+            durable_max_idle,
+            last_durable_cleanup: Instant::now(),
+            all_commits: Vec::new(),
             store,
             search_state,
+            settings,
+            purger,
             run_expensive_next_tick: false,
+            side_effects: SideEffects::new(uploads_path),
+            job_queue: None,
+            presence: PresenceRegistry::default(),
+            agents: HashMap::new(),
+            collections: CollectionWatcher::default(),
+            webhooks,
             last_search_commit: chrono::Local::now(),
         }
     })
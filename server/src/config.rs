@@ -1,10 +1,12 @@
 //! Parse CLI options, setup on boot, read .env values
 
 use crate::errors::AtomicServerResult;
+use atomic_lib::outbound::{HostRule, OutboundConfig, OutboundFeature};
 use clap::Parser;
 use dotenv::dotenv;
 use std::net::IpAddr;
 use std::path::PathBuf;
+use std::time::Duration;
 
 /// Store and share Atomic Data! Visit https://atomicdata.dev for more info. Pass no subcommands to launch the server. The `.env` of your current directory will be read.
 #[derive(Clone, Parser, Debug)]
@@ -23,6 +25,22 @@ pub struct Opts {
     #[clap(long, env = "ATOMIC_REBUILD_INDEX")]
     pub rebuild_indexes: bool,
 
+    /// Only run the startup self-check (config, directories, indexes and a sample of the store), print the results and exit.
+    /// Exits with a non-zero code if the server would not start.
+    #[clap(long, env = "ATOMIC_CHECK")]
+    pub check: bool,
+
+    /// How the `export`, `import`, `show`, `rebuild-index` and `check` subcommands report their result.
+    /// `json` prints a single JSON object, for deployment scripts.
+    #[clap(
+        value_enum,
+        long,
+        default_value = "text",
+        env = "ATOMIC_OUTPUT",
+        global = true
+    )]
+    pub output: OutputFormat,
+
     /// Use staging environments for services like LetsEncrypt
     #[clap(long, env = "ATOMIC_DEVELOPMENT")]
     pub development: bool,
@@ -94,6 +112,296 @@ pub struct Opts {
     /// Introduces random delays in the server, to simulate a slow connection. Useful for testing.
     #[clap(long, env = "ATOMIC_SLOW_MODE")]
     pub slow_mode: bool,
+
+    /// Logs every store operation (read, write, index lookup, query, Commit) that takes at least this many milliseconds, with its subject and call site.
+    /// The most recent ones are listed at `/metrics`.
+    #[clap(long, env = "ATOMIC_SLOW_OPERATION_MS")]
+    pub slow_operation_ms: Option<u64>,
+
+    /// How many background Jobs (index rebuilds, exports) can run at the same time.
+    #[clap(long, default_value = "2", env = "ATOMIC_JOB_WORKERS")]
+    pub job_workers: usize,
+
+    /// Permanently remove Resources that have been in the trash for longer than this many days.
+    /// If not set, trashed Resources are kept until they are deleted from the trash.
+    #[clap(long, env = "ATOMIC_TRASH_RETENTION_DAYS")]
+    pub trash_retention_days: Option<u64>,
+
+    /// Properties that notify the Agents they are set to, see `/inbox`. Comma separated.
+    #[clap(
+        long,
+        env = "ATOMIC_NOTIFY_ON",
+        value_delimiter = ',',
+        default_values = atomic_lib::plugins::notifications::DEFAULT_NOTIFY_ON
+    )]
+    pub notify_on: Vec<String>,
+
+    /// Durable WebSocket subscriptions whose Agent has not connected for this many days are removed.
+    #[clap(long, default_value = "7", env = "ATOMIC_DURABLE_SUBSCRIPTION_DAYS")]
+    pub durable_subscription_days: u64,
+
+    /// When the server stops, it keeps delivering Commits to Webhooks for at most this many seconds. Commits that are not delivered by then are sent after the next start.
+    #[clap(long, default_value = "10", env = "ATOMIC_SHUTDOWN_GRACE_SECONDS")]
+    pub shutdown_grace_seconds: u64,
+
+    /// How often (in seconds) to look for Resources whose `expiresAt` has passed, and remove them.
+    #[clap(long, default_value = "60", env = "ATOMIC_EXPIRY_INTERVAL")]
+    pub expiry_interval: u64,
+
+    /// Writes a backup to the `backups` folder of the config directory every this many hours. `0` turns scheduled backups off.
+    #[clap(long, default_value = "0", env = "ATOMIC_BACKUP_INTERVAL_HOURS")]
+    pub backup_interval_hours: u64,
+
+    /// Verifies every scheduled backup by restoring it in a temporary store, see the `verify-backup` subcommand.
+    #[clap(long, env = "ATOMIC_VERIFY_BACKUPS")]
+    pub verify_backups: bool,
+
+    /// A URL that receives a POST with the report when a backup fails its verification. The admins of the Drive always get a Notification.
+    #[clap(long, env = "ATOMIC_BACKUP_ALERT_URL")]
+    pub backup_alert_url: Option<String>,
+
+    /// Maximum amount of Commits per minute that a single Agent can send to `/commit`.
+    /// Can be overridden per Agent using the `commitRateLimit` property.
+    #[clap(long, env = "ATOMIC_COMMIT_RATE_LIMIT")]
+    pub commit_rate_limit: Option<u64>,
+
+    /// Maximum size in bytes of a Commit sent to `/commit`.
+    /// Can be overridden per Agent using the `maxCommitSize` property.
+    #[clap(long, env = "ATOMIC_MAX_COMMIT_SIZE")]
+    pub max_commit_size: Option<u64>,
+
+    /// Maximum length of an array that is `set` or `push`ed in a single Commit sent to `/commit`.
+    /// Can be overridden per Agent using the `maxCommitArrayLength` property.
+    #[clap(long, env = "ATOMIC_MAX_COMMIT_ARRAY_LENGTH")]
+    pub max_commit_array_length: Option<u64>,
+
+    /// Limits on the activity of a single Agent, such as `reads-per-hour=5000` or `download-bytes-per-day=10000000000`. Comma separated.
+    /// Counts `reads` (signed requests), `commits`, `downloads`, `download-bytes`, `bytes-served` or `subjects` (distinct, only per day), per UTC `hour` or `day`.
+    /// Admins get a Notification when an Agent exceeds a limit, see `/agent-activity`.
+    #[clap(long, env = "ATOMIC_ACTIVITY_LIMITS", value_delimiter = ',')]
+    pub activity_limits: Vec<String>,
+
+    /// Suspends Agents that exceed one of the `--activity-limits`, until an admin removes `suspended` from the Agent. Admins are never suspended.
+    #[clap(long, env = "ATOMIC_ACTIVITY_SUSPEND")]
+    pub activity_suspend: bool,
+
+    /// Maximum size in bytes of a single value (e.g. a String or Markdown) in a Commit sent to `/commit`. Use file uploads for larger content.
+    /// Longer values that are already stored are left out of the indexes and full-text search. `0` means no limit.
+    #[clap(long, default_value = "1048576", env = "ATOMIC_MAX_VALUE_SIZE")]
+    pub max_value_size: u64,
+
+    /// Maximum amount of parents of a Resource. Rights checks and breadcrumbs on deeper Resources fail, so a broken hierarchy can't keep requests busy.
+    #[clap(long, default_value = "256", env = "ATOMIC_MAX_HIERARCHY_DEPTH")]
+    pub max_hierarchy_depth: usize,
+
+    /// Maximum size in bytes of a single file uploaded to `/upload`.
+    #[clap(long, env = "ATOMIC_MAX_UPLOAD_SIZE")]
+    pub max_upload_size: Option<u64>,
+
+    /// Amount of worker threads that handle HTTP requests. Defaults to the amount of physical CPU cores.
+    #[clap(long, env = "ATOMIC_WORKERS")]
+    pub workers: Option<usize>,
+
+    /// Maximum amount of open connections per worker. New connections wait until one is closed.
+    #[clap(long, default_value = "25000", env = "ATOMIC_MAX_CONNECTIONS")]
+    pub max_connections: usize,
+
+    /// Milliseconds a client gets to send the headers of a request, before the connection is closed. `0` disables the timeout.
+    #[clap(long, default_value = "5000", env = "ATOMIC_CLIENT_REQUEST_TIMEOUT_MS")]
+    pub client_request_timeout_ms: u64,
+
+    /// Milliseconds a client gets to acknowledge the closing of a connection. `0` disables the timeout.
+    #[clap(
+        long,
+        default_value = "1000",
+        env = "ATOMIC_CLIENT_DISCONNECT_TIMEOUT_MS"
+    )]
+    pub client_disconnect_timeout_ms: u64,
+
+    /// Seconds an idle connection is kept open for the next request. `0` closes connections after every response.
+    #[clap(long, default_value = "5", env = "ATOMIC_KEEP_ALIVE")]
+    pub keep_alive: u64,
+
+    /// Minimum speed in bytes per second of uploads to `/upload` and downloads from `/download`. Slower transfers are dropped and logged.
+    #[clap(long, env = "ATOMIC_MIN_TRANSFER_RATE")]
+    pub min_transfer_rate: Option<u64>,
+
+    /// Seconds a transfer may lag behind the `min_transfer_rate`, e.g. while a connection starts.
+    #[clap(long, default_value = "10", env = "ATOMIC_TRANSFER_GRACE_SECONDS")]
+    pub transfer_grace_seconds: u64,
+
+    /// Maximum amount of downloads from `/download` that a single Agent, or IP address for anonymous requests, can run at the same time.
+    #[clap(long, env = "ATOMIC_MAX_CONCURRENT_DOWNLOADS")]
+    pub max_concurrent_downloads: Option<usize>,
+
+    /// Command that prints the text of an image on STDOUT, used to make uploaded images searchable. `{file}` is replaced by the path of the image, e.g. `tesseract {file} -`.
+    /// Without it, only text is extracted from PDFs and text files.
+    #[clap(long, env = "ATOMIC_OCR_COMMAND")]
+    pub ocr_command: Option<String>,
+
+    /// Maximum size in bytes of the text extracted from an uploaded file. Longer text is truncated.
+    #[clap(long, default_value = "100000", env = "ATOMIC_MAX_EXTRACTED_TEXT")]
+    pub max_extracted_text: usize,
+
+    /// Refuse accepting Invites, so no new Agents can get rights using an Invite.
+    #[clap(long, env = "ATOMIC_DISABLE_INVITES")]
+    pub disable_invites: bool,
+
+    /// Append every applied Commit as a JSON line to files in this directory, as an audit log that is independent of the store.
+    #[clap(long, env = "ATOMIC_AUDIT_DIR")]
+    pub audit_dir: Option<PathBuf>,
+
+    /// When to start a new audit log file.
+    #[clap(
+        value_enum,
+        long,
+        default_value = "size",
+        env = "ATOMIC_AUDIT_ROTATION"
+    )]
+    pub audit_rotation: AuditRotation,
+
+    /// Maximum size in megabytes of an audit log file, when rotating by size.
+    #[clap(long, default_value = "100", env = "ATOMIC_AUDIT_MAX_SIZE")]
+    pub audit_max_size: u64,
+
+    /// When to flush audit log entries to disk.
+    #[clap(value_enum, long, default_value = "always", env = "ATOMIC_AUDIT_FSYNC")]
+    pub audit_fsync: AuditFsync,
+
+    /// Refuse Commits while the audit log can't be written. By default, failures are only logged and shown at `/health`.
+    #[clap(long, env = "ATOMIC_AUDIT_STRICT")]
+    pub audit_strict: bool,
+
+    /// Lets browsers and CDNs cache publicly readable Resources for this many seconds. By default, Resources are not cached.
+    #[clap(long, env = "ATOMIC_CACHE_MAX_AGE")]
+    pub cache_max_age: Option<u64>,
+
+    /// How many seconds a cached Resource may be served while it is refreshed in the background, if `cache_max_age` is set.
+    #[clap(
+        long,
+        default_value = "60",
+        env = "ATOMIC_CACHE_STALE_WHILE_REVALIDATE"
+    )]
+    pub cache_stale_while_revalidate: u64,
+
+    /// Sends a POST request with the changed URLs to this endpoint after every Commit, to purge them from a CDN.
+    #[clap(long, env = "ATOMIC_CDN_PURGE_URL")]
+    pub cdn_purge_url: Option<String>,
+
+    /// Sent as a Bearer token in the `Authorization` header of CDN purge requests.
+    #[clap(long, env = "ATOMIC_CDN_PURGE_TOKEN", requires = "cdn_purge_url")]
+    pub cdn_purge_token: Option<String>,
+
+    /// Rejects Commits with relative URLs (like `./attachment`) that resolve to another origin than the Resource they are in.
+    #[clap(long, env = "ATOMIC_STRICT_RELATIVE_URLS")]
+    pub strict_relative_urls: bool,
+
+    /// How full-text search treats Resources that are marked as `deprecated`.
+    #[clap(
+        value_enum,
+        long,
+        default_value = "demote",
+        env = "ATOMIC_SEARCH_DEPRECATED"
+    )]
+    pub search_deprecated: SearchDeprecated,
+
+    /// Language of the full-text search analyzer, which enables stemming and stop words. `raw` only splits and lowercases words.
+    /// Changing it clears the search index, which is then rebuilt.
+    #[clap(
+        value_enum,
+        long,
+        default_value = "raw",
+        env = "ATOMIC_SEARCH_LANGUAGE"
+    )]
+    pub search_language: SearchLanguage,
+
+    /// Makes full-text search ignore diacritics, so `café` matches `cafe`.
+    /// Changing it clears the search index, which is then rebuilt.
+    #[clap(long, env = "ATOMIC_SEARCH_ASCII_FOLDING")]
+    pub search_ascii_folding: bool,
+
+    /// Runs this server as a read-only replica of the atomic-server at this URL, e.g. `https://eu.example.com`.
+    /// Use the same `server_url` and config file (which contains the server Agent) as the primary.
+    #[clap(long, env = "ATOMIC_REPLICA_OF")]
+    pub replica_of: Option<String>,
+
+    /// Hosts (`wiki.example.com`), subdomains (`*.example.com`) or networks (`10.1.0.0/16`) that requests to other servers may always reach, also when they are internal. Comma separated.
+    /// The hosts of `replica_of` and `cdn_purge_url` are allowed automatically.
+    #[clap(long, env = "ATOMIC_OUTBOUND_ALLOW", value_delimiter = ',')]
+    pub outbound_allow: Vec<String>,
+
+    /// Hosts, subdomains or networks that requests to other servers may never reach. Internal addresses are always refused, unless they are allowed. Comma separated.
+    #[clap(long, env = "ATOMIC_OUTBOUND_DENY", value_delimiter = ',')]
+    pub outbound_deny: Vec<String>,
+
+    /// Timeout in seconds of requests to other servers.
+    #[clap(long, default_value = "10", env = "ATOMIC_OUTBOUND_TIMEOUT")]
+    pub outbound_timeout: u64,
+
+    /// Timeouts in seconds for specific hosts or networks, e.g. `slow.example.com=30`. Comma separated.
+    #[clap(long, env = "ATOMIC_OUTBOUND_HOST_TIMEOUTS", value_delimiter = ',')]
+    pub outbound_host_timeouts: Vec<String>,
+
+    /// Maximum size in megabytes of a response from another server.
+    #[clap(long, default_value = "10", env = "ATOMIC_OUTBOUND_MAX_RESPONSE_SIZE")]
+    pub outbound_max_response_size: u64,
+
+    /// How many redirects a request to another server may follow.
+    #[clap(long, default_value = "5", env = "ATOMIC_OUTBOUND_MAX_REDIRECTS")]
+    pub outbound_max_redirects: u32,
+
+    /// Features that may not send requests to other servers at all: `resolve`, `import`, `bookmark`, `json-ld-context`, `link-check`, `replication`, `cdn-purge`, `link-preview` or `webhook`. Comma separated.
+    #[clap(long, env = "ATOMIC_OUTBOUND_DISABLE", value_delimiter = ',')]
+    pub outbound_disable: Vec<String>,
+
+    /// Lets `/preview` fetch the title, description and image of web pages on other servers, using the `link-preview` outbound feature.
+    #[clap(long, env = "ATOMIC_EXTERNAL_LINK_PREVIEWS")]
+    pub external_link_previews: bool,
+
+    /// Content-Security-Policy directives for HTML pages that replace the default ones, e.g. `script-src 'self' https://widgets.example.com` to embed an external widget.
+    /// A nonce for inline scripts and styles is always added to `script-src` and `style-src`. Separated by `;`.
+    #[clap(long, env = "ATOMIC_CSP", value_delimiter = ';')]
+    pub csp: Vec<String>,
+
+    /// Serves this JSON-AD or `.ad3` file read-only on a random free port, without using the data or config directory. Nothing is kept after the server stops.
+    /// Subjects in the file are moved to the local server URL.
+    #[clap(long, env = "ATOMIC_SERVE_FILE")]
+    pub serve_file: Option<PathBuf>,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq)]
+pub enum SearchDeprecated {
+    /// Rank deprecated Resources like any other
+    Include,
+    /// List deprecated Resources after all others
+    Demote,
+    /// Leave deprecated Resources out of the results
+    Exclude,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchLanguage {
+    /// No stemming or stop words
+    Raw,
+    English,
+    Dutch,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum AuditRotation {
+    /// Start a new file when the current one exceeds `audit_max_size`
+    Size,
+    /// Start a new file every day
+    Daily,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum AuditFsync {
+    /// Sync after every entry, so no entry is lost when the machine crashes
+    Always,
+    /// Leave flushing to the operating system, which is faster
+    Never,
 }
 
 #[derive(clap::ValueEnum, Clone, Debug)]
@@ -106,6 +414,14 @@ pub enum Tracing {
     Opentelemetry,
 }
 
+#[derive(clap::ValueEnum, Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human readable text
+    Text,
+    /// One JSON object on STDOUT, also for errors
+    Json,
+}
+
 #[derive(clap::ValueEnum, Clone, Debug)]
 pub enum LogLevel {
     Warn,
@@ -116,18 +432,42 @@ pub enum LogLevel {
 
 #[derive(Parser, Clone, Debug)]
 pub enum Command {
-    /// Create and save a JSON-AD backup of the store.
+    /// Create and save a JSON-AD backup of the store. Can't run while the server uses the store.
     #[clap(name = "export")]
     Export(ExportOpts),
     /// Import a JSON-AD file or stream to the store. By default creates Commits for all changes, maintaining version history. Use --force to allow importing other types of files.
+    /// Can't run while the server uses the store.
     #[clap(name = "import", trailing_var_arg = true)]
     Import(ImportOpts),
+    /// Print one Resource from the store. Can't run while the server uses the store.
+    #[clap(name = "show")]
+    Show(ShowOpts),
+    /// Clear and rebuild the value index and the search index. Can't run while the server uses the store.
+    #[clap(name = "rebuild-index")]
+    RebuildIndex,
+    /// Run the self-check and validate every Resource in the store. Exits with code 2 if problems are found.
+    /// Can't run while the server uses the store.
+    #[clap(name = "check")]
+    Check,
+    /// Generates random, valid instances of a Class and saves them with Commits, for benchmarks and demos.
+    #[clap(name = "populate-test-data")]
+    PopulateTestData(PopulateTestDataOpts),
     /// Creates a `.env` file in your current directory that shows various options that you can set.
     #[clap(name = "generate-dotenv")]
     CreateDotEnv,
     /// Returns the currently selected options, based on the passed flags and parsed environment variables.
     #[clap(name = "show-config")]
     ShowConfig,
+    /// Checks the sequence of the audit log in `audit_dir`, and compares it with the Commits in the store.
+    #[clap(name = "verify-audit")]
+    VerifyAudit,
+    /// Write a backup of the store and the uploaded files, with a manifest, to a new folder. Can't run while the server uses the store.
+    #[clap(name = "backup")]
+    Backup(BackupOpts),
+    /// Restore a backup in a temporary store and check it against its manifest, the uploaded files and the live store. Exits with code 2 if it fails.
+    /// The live store is only read. While a server uses it, the comparison with the live store is skipped.
+    #[clap(name = "verify-backup")]
+    VerifyBackup(VerifyBackupOpts),
     /// Danger! Removes all data from the store.
     #[clap(name = "reset")]
     Reset,
@@ -136,17 +476,57 @@ pub enum Command {
 #[derive(Parser, Clone, Debug)]
 pub struct ExportOpts {
     /// Where the exported file should be saved  "~/.config/atomic/backups/{date}.json"
-    #[clap(short)]
+    #[clap(short, long)]
     pub path: Option<PathBuf>,
     /// Do not export resources that are externally defined, which are cached by this Server.
     #[clap(long)]
     pub only_internal: bool,
+    /// The serialization of the export.
+    #[clap(value_enum, long, default_value = "json-ad")]
+    pub format: ExportFormat,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct BackupOpts {
+    /// The folder to write the backup to  "~/.config/atomic/backups/{date}"
+    #[clap(short, long)]
+    pub path: Option<PathBuf>,
+    /// Verify the backup after writing it, like the `verify-backup` command.
+    #[clap(long)]
+    pub verify: bool,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct VerifyBackupOpts {
+    /// The folder of the backup. Defaults to the latest backup in "~/.config/atomic/backups".
+    #[clap(short, long)]
+    pub path: Option<PathBuf>,
+    /// How many Resources are compared with the live store.
+    #[clap(long, default_value = "100")]
+    pub sample: usize,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum ExportFormat {
+    /// JSON-AD, which can be imported again
+    JsonAd,
+    /// RDF N-Quads, with the Resources of every Drive in a separate graph
+    NQuads,
+}
+
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::JsonAd => "json",
+            ExportFormat::NQuads => "nq",
+        }
+    }
 }
 
 #[derive(Parser, Clone, Debug)]
 pub struct ImportOpts {
     /// Path of the file to be imported.
-    #[clap(long)]
+    #[clap(long, visible_alias = "path")]
     pub file: PathBuf,
     /// The URL of the  Importer (parent) Resource to be used.
     /// This will set the hierarchical location of the imported items.
@@ -156,6 +536,47 @@ pub struct ImportOpts {
     /// Skip checks, allows for importing things like Commits.
     #[clap(long)]
     pub force: bool,
+    /// The base URL that relative references (like `./attachment`) in the file are resolved against.
+    /// If not passed, they are resolved against the `@id` of the Resource they are in.
+    #[clap(long)]
+    pub base: Option<String>,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct ShowOpts {
+    /// The subject of the Resource.
+    pub subject: String,
+    /// The serialization of the Resource.
+    #[clap(value_enum, long, default_value = "json-ad")]
+    pub format: ShowFormat,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum ShowFormat {
+    JsonAd,
+    /// Plain JSON, with shortnames as keys
+    Json,
+    JsonLd,
+    Turtle,
+    NTriples,
+    NQuads,
+}
+
+#[derive(Parser, Clone, Debug)]
+pub struct PopulateTestDataOpts {
+    /// The URL of the Class to create instances of.
+    #[clap(long)]
+    pub class: String,
+    /// How many instances to create.
+    #[clap(long, default_value = "100")]
+    pub count: usize,
+    /// The URL of the parent Resource of the instances.
+    /// If not passed, they are added to the root Drive.
+    #[clap(long)]
+    pub parent: Option<String>,
+    /// The same seed generates the same instances.
+    #[clap(long, default_value = "0")]
+    pub seed: u64,
 }
 
 /// Start atomic-server, oi mate
@@ -190,8 +611,18 @@ pub struct Config {
     pub uploads_path: PathBuf,
     /// Path to where the search index for tantivy full text search is located
     pub search_index_path: PathBuf,
+    /// Path to where a replica remembers the last Commit it applied, see [crate::replication]
+    pub replica_state_path: PathBuf,
     /// If true, the initialization scripts will be ran (create first Drive, Agent, indexing, etc)
     pub initialize: bool,
+    /// Which requests to other servers are permitted, built from the `outbound_*` options
+    pub outbound: OutboundConfig,
+    /// The Content-Security-Policy and other headers, built from `csp`
+    pub security_headers: crate::security_headers::SecurityHeaders,
+    /// Parsed from `activity_limits`
+    pub activity_limits: Vec<crate::agent_activity::ActivityLimit>,
+    /// Set when serving a single file, see [crate::serve_file]. Contains all other paths, and is removed when the server stops.
+    pub ephemeral_dir: Option<PathBuf>,
 }
 
 /// Parse .env and CLI options
@@ -204,27 +635,40 @@ pub fn read_opts() -> Opts {
 }
 
 /// Creates the server config, reads .env values and sets defaults
-pub fn build_config(opts: Opts) -> AtomicServerResult<Config> {
+pub fn build_config(mut opts: Opts) -> AtomicServerResult<Config> {
+    let ephemeral_dir = match opts.serve_file {
+        Some(_) => Some(crate::serve_file::prepare(&mut opts)?),
+        None => None,
+    };
+
     // Directories & file system
     let project_dirs = directories::ProjectDirs::from("", "", "atomic-data")
         .expect("Could not find Project directories on your OS");
 
     // Persistent user data
-    let data_dir = opts
-        .data_dir
-        .clone()
-        .unwrap_or_else(|| project_dirs.data_dir().to_owned());
+    let data_dir = match &ephemeral_dir {
+        Some(dir) => dir.join("data"),
+        None => opts
+            .data_dir
+            .clone()
+            .unwrap_or_else(|| project_dirs.data_dir().to_owned()),
+    };
     let mut store_path = data_dir.clone();
     store_path.push("store");
 
     let mut uploads_path = data_dir.clone();
     uploads_path.push("uploads");
 
+    let mut replica_state_path = data_dir.clone();
+    replica_state_path.push("replica.json");
+
     let mut static_path = data_dir;
     static_path.push("static");
 
     // Config data
-    let config_dir = if let Some(dir) = &opts.config_dir {
+    let config_dir = if let Some(dir) = &ephemeral_dir {
+        dir.join("config")
+    } else if let Some(dir) = &opts.config_dir {
         dir.clone()
     } else {
         atomic_lib::config::default_config_dir_path()?
@@ -242,7 +686,10 @@ pub fn build_config(opts: Opts) -> AtomicServerResult<Config> {
 
     // Cache data
 
-    let cache_dir = project_dirs.cache_dir();
+    let cache_dir = match &ephemeral_dir {
+        Some(dir) => dir.as_path(),
+        None => project_dirs.cache_dir(),
+    };
 
     let mut search_index_path = cache_dir.to_owned();
     search_index_path.push("search_index");
@@ -267,8 +714,22 @@ pub fn build_config(opts: Opts) -> AtomicServerResult<Config> {
         format!("{}://{}:{}", schema, opts.domain, opts.port)
     };
 
+    let outbound = build_outbound_config(&opts)?;
+
+    let security_headers = crate::security_headers::SecurityHeaders::new(&opts.csp)?;
+
+    let activity_limits = opts
+        .activity_limits
+        .iter()
+        .filter(|limit| !limit.trim().is_empty())
+        .map(|limit| limit.parse())
+        .collect::<AtomicServerResult<Vec<_>>>()?;
+
     Ok(Config {
         initialize,
+        outbound,
+        security_headers,
+        activity_limits,
         opts,
         cert_path,
         config_dir,
@@ -280,5 +741,60 @@ pub fn build_config(opts: Opts) -> AtomicServerResult<Config> {
         store_path,
         search_index_path,
         uploads_path,
+        replica_state_path,
+        ephemeral_dir,
+    })
+}
+
+/// Parses the `outbound_*` options. The hosts of the primary and the CDN purge endpoint are chosen by the operator, so they are allowed.
+fn build_outbound_config(opts: &Opts) -> AtomicServerResult<OutboundConfig> {
+    let parse_rules = |rules: &[String]| -> AtomicServerResult<Vec<HostRule>> {
+        rules
+            .iter()
+            .filter(|rule| !rule.trim().is_empty())
+            .map(|rule| rule.parse::<HostRule>().map_err(Into::into))
+            .collect()
+    };
+    let mut allow = parse_rules(&opts.outbound_allow)?;
+    for configured in [&opts.replica_of, &opts.cdn_purge_url]
+        .into_iter()
+        .flatten()
+    {
+        allow.push(HostRule::from_url(configured)?);
+    }
+    let host_timeouts = opts
+        .outbound_host_timeouts
+        .iter()
+        .filter(|rule| !rule.trim().is_empty())
+        .map(|rule| {
+            let (host, seconds) = rule
+                .split_once('=')
+                .ok_or(format!("Expected host=seconds, got '{}'", rule))?;
+            let seconds: u64 = seconds
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid timeout in '{}'", rule))?;
+            Ok((host.parse()?, Duration::from_secs(seconds)))
+        })
+        .collect::<AtomicServerResult<Vec<_>>>()?;
+    let disabled = opts
+        .outbound_disable
+        .iter()
+        .filter(|feature| !feature.trim().is_empty())
+        .map(|feature| {
+            feature
+                .trim()
+                .parse::<OutboundFeature>()
+                .map_err(Into::into)
+        })
+        .collect::<AtomicServerResult<Vec<_>>>()?;
+    Ok(OutboundConfig {
+        allow,
+        deny: parse_rules(&opts.outbound_deny)?,
+        timeout: Duration::from_secs(opts.outbound_timeout),
+        host_timeouts,
+        max_response_size: opts.outbound_max_response_size * 1024 * 1024,
+        max_redirects: opts.outbound_max_redirects,
+        disabled,
     })
 }
@@ -0,0 +1,302 @@
+//! Limits on connections and transfers, so a few slow or greedy clients can't keep the server from answering everyone else.
+//! The amount of workers, connections and the timeouts are set on the [actix_web::HttpServer] in [crate::serve].
+//! Uploads to `/upload` and downloads from `/download` have to keep up with the `min_transfer_rate`, after a grace period. Slower transfers are dropped and logged.
+//! Downloads are only checked when the client reads more data, so clients that stop reading altogether are closed by the TCP stack of the OS.
+//! Every Agent (or IP address, for anonymous requests) can run `max_concurrent_downloads` downloads at the same time.
+//! The counts are shown at `/health`.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::{BodySize, BoxBody, MessageBody},
+    dev::Extensions,
+    error::PayloadError,
+    web::Bytes,
+};
+use futures::Stream;
+use serde::Serialize;
+
+use crate::{
+    config::Opts,
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+};
+
+/// Open connections and running downloads, shared by all workers.
+#[derive(Clone, Default)]
+pub struct ConnectionStats {
+    open: Arc<AtomicUsize>,
+    total: Arc<AtomicU64>,
+    /// Running downloads, by Agent or IP address
+    downloads: Arc<Mutex<HashMap<String, usize>>>,
+}
+
+/// The connection counts, as shown at `/health`.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionCounts {
+    /// Connections that are currently open
+    pub open: usize,
+    /// Connections that were opened since the server started
+    pub total: u64,
+    /// Downloads from `/download` that are currently running
+    pub downloads: usize,
+}
+
+impl ConnectionStats {
+    /// Pass this to [actix_web::HttpServer::on_connect].
+    /// The connection is counted until it closes, because the data of a connection is dropped with it.
+    pub fn on_connect(&self, extensions: &mut Extensions) {
+        self.open.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(1, Ordering::Relaxed);
+        extensions.insert(OpenConnection(self.open.clone()));
+    }
+
+    pub fn counts(&self) -> ConnectionCounts {
+        ConnectionCounts {
+            open: self.open.load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+            downloads: self.downloads.lock().unwrap().values().sum(),
+        }
+    }
+
+    /// Claims one of the `max` download slots of `client`, which is released when the returned slot is dropped.
+    pub fn start_download(
+        &self,
+        client: &str,
+        max: Option<usize>,
+    ) -> AtomicServerResult<DownloadSlot> {
+        let mut downloads = self.downloads.lock().unwrap();
+        let running = downloads.entry(client.to_string()).or_default();
+        if let Some(max) = max {
+            if *running >= max {
+                return Err(AtomicServerError::new(
+                    format!(
+                        "{} is already running {} downloads. Try again when one of them has finished.",
+                        client, running
+                    ),
+                    AppErrorType::TooManyRequests,
+                ));
+            }
+        }
+        *running += 1;
+        Ok(DownloadSlot {
+            downloads: self.downloads.clone(),
+            client: client.to_string(),
+        })
+    }
+}
+
+/// Stored in the data of a connection, see [ConnectionStats::on_connect].
+struct OpenConnection(Arc<AtomicUsize>);
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A running download, see [ConnectionStats::start_download].
+pub struct DownloadSlot {
+    downloads: Arc<Mutex<HashMap<String, usize>>>,
+    client: String,
+}
+
+impl Drop for DownloadSlot {
+    fn drop(&mut self) {
+        let mut downloads = self.downloads.lock().unwrap();
+        if let Some(running) = downloads.get_mut(&self.client) {
+            *running = running.saturating_sub(1);
+            if *running == 0 {
+                downloads.remove(&self.client);
+            }
+        }
+    }
+}
+
+/// The slowest transfer that is allowed: after `grace`, on average at least `bytes_per_second`.
+#[derive(Clone, Copy, Debug)]
+pub struct MinRate {
+    pub bytes_per_second: u64,
+    pub grace: Duration,
+}
+
+impl MinRate {
+    /// Returns `None` if no `min_transfer_rate` is set.
+    pub fn from_opts(opts: &Opts) -> Option<MinRate> {
+        opts.min_transfer_rate
+            .filter(|rate| *rate > 0)
+            .map(|bytes_per_second| MinRate {
+                bytes_per_second,
+                grace: Duration::from_secs(opts.transfer_grace_seconds),
+            })
+    }
+
+    /// The moment at which a transfer that started at `started` should have transferred more than `bytes`.
+    fn deadline(&self, started: Instant, bytes: u64) -> Instant {
+        started + self.grace + Duration::from_secs_f64(bytes as f64 / self.bytes_per_second as f64)
+    }
+
+    fn too_slow(&self, what: &str, started: Instant, bytes: u64) -> String {
+        let message = format!(
+            "Dropped {}, because it is slower than {} bytes per second: {} bytes in {:.1} seconds",
+            what,
+            self.bytes_per_second,
+            bytes,
+            started.elapsed().as_secs_f64()
+        );
+        tracing::warn!("{}", message);
+        message
+    }
+}
+
+/// Fails a request body with a timeout when it falls behind the [MinRate].
+/// Also fails when no bytes arrive at all, because the request handler keeps polling it.
+pub struct RateCheckedPayload<S> {
+    inner: S,
+    rate: MinRate,
+    what: String,
+    started: Instant,
+    bytes: u64,
+    deadline: Pin<Box<tokio::time::Sleep>>,
+}
+
+impl<S> RateCheckedPayload<S> {
+    /// `what` describes the transfer in the log, e.g. the path and the client.
+    pub fn new(inner: S, rate: MinRate, what: String) -> Self {
+        let started = Instant::now();
+        RateCheckedPayload {
+            inner,
+            rate,
+            what,
+            started,
+            bytes: 0,
+            deadline: Box::pin(tokio::time::sleep_until(rate.deadline(started, 0).into())),
+        }
+    }
+}
+
+impl<S> Stream for RateCheckedPayload<S>
+where
+    S: Stream<Item = Result<Bytes, PayloadError>> + Unpin,
+{
+    type Item = Result<Bytes, PayloadError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        match Pin::new(&mut this.inner).poll_next(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                this.bytes += chunk.len() as u64;
+                let deadline = this.rate.deadline(this.started, this.bytes);
+                this.deadline.as_mut().reset(deadline.into());
+                Poll::Ready(Some(Ok(chunk)))
+            }
+            Poll::Pending => {
+                if this.deadline.as_mut().poll(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                let message = this.rate.too_slow(&this.what, this.started, this.bytes);
+                Poll::Ready(Some(Err(PayloadError::Io(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    message,
+                )))))
+            }
+            done => done,
+        }
+    }
+}
+
+/// Wraps the body of a download. Fails when the client reads slower than the [MinRate], which closes the connection.
+/// Holds the [DownloadSlot] until the body is dropped, which is when the download finishes or the connection closes.
+pub struct RateCheckedBody {
+    inner: BoxBody,
+    rate: Option<MinRate>,
+    what: String,
+    started: Instant,
+    bytes: u64,
+    _slot: DownloadSlot,
+}
+
+impl RateCheckedBody {
+    pub fn new(inner: BoxBody, rate: Option<MinRate>, what: String, slot: DownloadSlot) -> Self {
+        RateCheckedBody {
+            inner,
+            rate,
+            what,
+            started: Instant::now(),
+            bytes: 0,
+            _slot: slot,
+        }
+    }
+}
+
+impl MessageBody for RateCheckedBody {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        self.inner.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.get_mut();
+        if let Some(rate) = this.rate {
+            if Instant::now() > rate.deadline(this.started, this.bytes) {
+                let message = rate.too_slow(&this.what, this.started, this.bytes);
+                return Poll::Ready(Some(Err(message.into())));
+            }
+        }
+        let next = Pin::new(&mut this.inner).poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &next {
+            this.bytes += chunk.len() as u64;
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn download_slots_are_released() {
+        let stats = ConnectionStats::default();
+        let first = stats.start_download("1.2.3.4", Some(2)).unwrap();
+        let _second = stats.start_download("1.2.3.4", Some(2)).unwrap();
+        let Err(err) = stats.start_download("1.2.3.4", Some(2)) else {
+            panic!("A third download should be refused");
+        };
+        assert!(matches!(err.error_type, AppErrorType::TooManyRequests));
+        // Other clients have their own slots
+        let _other = stats.start_download("5.6.7.8", Some(2)).unwrap();
+        assert_eq!(stats.counts().downloads, 3);
+        drop(first);
+        assert!(stats.start_download("1.2.3.4", Some(2)).is_ok());
+        assert_eq!(stats.counts().downloads, 2);
+    }
+
+    #[test]
+    fn deadline_grows_with_bytes() {
+        let rate = MinRate {
+            bytes_per_second: 1000,
+            grace: Duration::from_secs(10),
+        };
+        let started = Instant::now();
+        assert_eq!(rate.deadline(started, 0), started + Duration::from_secs(10));
+        assert_eq!(
+            rate.deadline(started, 5000),
+            started + Duration::from_secs(15)
+        );
+    }
+}
@@ -1,6 +1,7 @@
 //! Content-type / Accept header negotiation, MIME types
 
 use actix_web::http::header::HeaderMap;
+use atomic_lib::{errors::AtomicResult, Resource, Storelike};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ContentType {
@@ -20,6 +21,9 @@ pub enum ContentType {
     /// RDF N-Triples format
     /// https://www.w3.org/TR/n-triples/
     NTriples,
+    /// RDF N-Quads format, with the Drive of the Resource as graph
+    /// https://www.w3.org/TR/n-quads/
+    NQuads,
 }
 
 const MIME_HTML: &str = "text/html";
@@ -29,6 +33,7 @@ const MIME_JSONLD: &str = "application/ld+json";
 const MIME_JSONAD: &str = "application/ad+json";
 const MIME_TURTLE: &str = "text/turtle";
 const MIME_NT: &str = "application/n-triples";
+const MIME_NQ: &str = "application/n-quads";
 
 impl ContentType {
     pub fn to_mime(&self) -> &str {
@@ -39,6 +44,19 @@ impl ContentType {
             ContentType::Html => MIME_HTML,
             ContentType::Turtle => MIME_TURTLE,
             ContentType::NTriples => MIME_NT,
+            ContentType::NQuads => MIME_NQ,
+        }
+    }
+
+    /// Serializes the Resource. HTML pages are rendered by the browser app, so they get JSON-AD.
+    pub fn serialize(&self, resource: &Resource, store: &impl Storelike) -> AtomicResult<String> {
+        match self {
+            ContentType::Json => resource.to_json(store),
+            ContentType::JsonLd => resource.to_json_ld(store),
+            ContentType::JsonAd | ContentType::Html => resource.to_json_ad(),
+            ContentType::Turtle => resource.to_turtle(store),
+            ContentType::NTriples => resource.to_n_triples(store),
+            ContentType::NQuads => resource.to_n_quads(store),
         }
     }
 }
@@ -80,6 +98,9 @@ pub fn parse_accept_header(header: &str) -> ContentType {
         if mimepart.contains(MIME_NT) {
             return ContentType::NTriples;
         }
+        if mimepart.contains(MIME_NQ) {
+            return ContentType::NQuads;
+        }
     }
     tracing::info!("Unknown Accept header, defaut to HTML: {}", header);
     ContentType::Html
@@ -94,6 +115,9 @@ mod test {
         assert!(parse_accept_header("text/html,application/xml") == ContentType::Html);
         assert!(parse_accept_header("application/ad+json") == ContentType::JsonAd);
         assert!(parse_accept_header("application/ld+json") == ContentType::JsonLd);
+        assert!(parse_accept_header("text/turtle") == ContentType::Turtle);
+        assert!(parse_accept_header("application/n-triples") == ContentType::NTriples);
+        assert!(parse_accept_header("application/n-quads") == ContentType::NQuads);
     }
 
     #[test]
@@ -0,0 +1,487 @@
+//! Durable subscriptions: WebSocket subscriptions that survive restarts of the server.
+//! A client asks for one with `SUBSCRIBE ${subject} durable=true`, which stores a Subscription Resource for its Agent.
+//! When the Agent connects again, the [crate::commit_monitor::CommitMonitor] subscribes the connection again,
+//! and replays the Commits since the last one that the client acknowledged with `ACK ${commit}`.
+//! Subscriptions whose Agent doesn't connect within `--durable-subscription-days` are removed.
+//!
+//! Subscriptions are stored without Commits, since every acknowledgement would add one.
+//! Commits are replayed in the order of the commit log of the store, see [Db::commits_after].
+//! Every subscription keeps the sequence number of the newest acknowledged Commit as its cursor.
+//! Every replayed or sent Commit includes its `commitSequence`, so clients can skip Commits they already received.
+
+use std::{collections::HashMap, time::Duration};
+
+use atomic_lib::{
+    agents::ForAgent, storelike::Query, urls, utils::now, Db, Resource, Storelike, Value,
+};
+
+use crate::errors::AtomicServerResult;
+
+/// The options of `SUBSCRIBE ${subject} durable=true`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DurableOptions {
+    /// Also receive the Commits of all descendants of the subject
+    pub include_children: bool,
+    /// How the client receives Commits, e.g. `websocket`
+    pub transport: Option<String>,
+}
+
+impl DurableOptions {
+    /// Parses the `key=value` options after the subject of a `SUBSCRIBE` message.
+    /// Returns `None` if the subscription is not durable.
+    pub fn parse<'a>(
+        options: impl Iterator<Item = &'a str>,
+    ) -> AtomicServerResult<Option<DurableOptions>> {
+        let mut durable = false;
+        let mut parsed = DurableOptions::default();
+        for option in options {
+            match option.split_once('=') {
+                Some(("durable", value)) => durable = parse_bool(option, value)?,
+                Some(("include-children", value)) => {
+                    parsed.include_children = parse_bool(option, value)?
+                }
+                Some(("transport", value)) if !value.is_empty() => {
+                    parsed.transport = Some(value.into())
+                }
+                _ => return Err(format!("Unknown SUBSCRIBE option '{}'", option).into()),
+            }
+        }
+        if !durable && parsed != DurableOptions::default() {
+            return Err("include-children and transport require durable=true".into());
+        }
+        Ok(durable.then_some(parsed))
+    }
+}
+
+fn parse_bool(option: &str, value: &str) -> AtomicServerResult<bool> {
+    atomic_lib::normalize::parse_bool(value)
+        .ok_or_else(|| format!("Invalid SUBSCRIBE option '{}', use true or false", option).into())
+}
+
+#[derive(Clone, Debug)]
+pub struct DurableSubscription {
+    /// The subject of the Subscription Resource
+    pub subject: String,
+    pub agent: String,
+    /// The Resource whose Commits are sent
+    pub target: String,
+    pub options: DurableOptions,
+    /// The newest Commit that the client acknowledged
+    pub last_acknowledged: Option<String>,
+    /// The sequence number of `last_acknowledged`, or of the last Commit before the subscription was created
+    pub cursor: u64,
+    pub created_at: i64,
+    /// When the Agent last connected or acknowledged a Commit
+    pub last_seen: i64,
+}
+
+impl DurableSubscription {
+    fn from_resource(store: &Db, resource: &Resource) -> AtomicServerResult<Self> {
+        let last_acknowledged = resource
+            .get(urls::LAST_ACKNOWLEDGED)
+            .ok()
+            .map(|v| v.to_string());
+        // Subscriptions from before the commit log continue from their last acknowledged Commit, or from now
+        let cursor = match resource.get(urls::DELIVERY_CURSOR) {
+            Ok(cursor) => cursor.to_int()? as u64,
+            Err(_) => match &last_acknowledged {
+                Some(commit) => store.commit_sequence_of(commit)?,
+                None => None,
+            }
+            .unwrap_or_else(|| store.last_commit_sequence()),
+        };
+        Ok(DurableSubscription {
+            subject: resource.get_subject().clone(),
+            agent: resource.get(urls::RECIPIENT)?.to_string(),
+            target: resource.get(urls::SUBSCRIPTION_TARGET)?.to_string(),
+            options: DurableOptions {
+                include_children: resource
+                    .get(urls::INCLUDE_CHILDREN)
+                    .and_then(|v| v.to_bool())
+                    .unwrap_or(false),
+                transport: resource.get(urls::TRANSPORT).ok().map(|v| v.to_string()),
+            },
+            last_acknowledged,
+            cursor,
+            created_at: resource.get(urls::CREATED_AT)?.to_int()?,
+            last_seen: resource
+                .get(urls::LAST_SEEN)
+                .and_then(|v| v.to_int())
+                .unwrap_or(0),
+        })
+    }
+
+    fn save(&self, store: &Db) -> AtomicServerResult<()> {
+        let mut resource = Resource::new(self.subject.clone());
+        resource.set_class(urls::SUBSCRIPTION);
+        for (prop, value) in [
+            (
+                urls::PARENT,
+                Value::AtomicUrl(store.get_server_url().into()),
+            ),
+            (urls::RECIPIENT, Value::AtomicUrl(self.agent.clone())),
+            (
+                urls::SUBSCRIPTION_TARGET,
+                Value::AtomicUrl(self.target.clone()),
+            ),
+            (
+                urls::INCLUDE_CHILDREN,
+                Value::Boolean(self.options.include_children),
+            ),
+            (urls::CREATED_AT, Value::Timestamp(self.created_at)),
+            (urls::LAST_SEEN, Value::Timestamp(self.last_seen)),
+            (urls::DELIVERY_CURSOR, Value::Integer(self.cursor as i64)),
+        ] {
+            resource.set_propval_unsafe(prop.into(), value);
+        }
+        if let Some(transport) = &self.options.transport {
+            resource.set_propval_unsafe(urls::TRANSPORT.into(), Value::String(transport.clone()));
+        }
+        if let Some(commit) = &self.last_acknowledged {
+            resource.set_propval_unsafe(
+                urls::LAST_ACKNOWLEDGED.into(),
+                Value::AtomicUrl(commit.clone()),
+            );
+        }
+        store.add_resource_opts(&resource, false, true, true)?;
+        Ok(())
+    }
+
+    /// Whether the Commits of `target` are sent to this subscription. `ancestors` are the parents of `target`.
+    pub fn matches(&self, target: &str, ancestors: &[String]) -> bool {
+        target == self.target || (self.options.include_children && ancestors.contains(&self.target))
+    }
+
+    /// The Commits after the cursor with their sequence numbers, oldest first.
+    /// Only the Commits of Resources that the Agent can read are included.
+    /// Destroyed descendants are no longer part of the tree, so only the Commits of the target itself are replayed after it is destroyed.
+    pub fn catch_up(&self, store: &Db) -> AtomicServerResult<Vec<(u64, Resource)>> {
+        let for_agent = ForAgent::AgentSubject(self.agent.clone());
+        let mut commits = Vec::new();
+        let mut cursor = self.cursor;
+        loop {
+            let batch = store.commits_after(cursor, CATCH_UP_BATCH)?;
+            let Some((last, _)) = batch.last() else {
+                break;
+            };
+            cursor = *last;
+            for (sequence, subject) in batch {
+                let Ok(commit) = store.get_resource(&subject) else {
+                    continue;
+                };
+                let Ok(target) = commit.get(urls::SUBJECT).map(|v| v.to_string()) else {
+                    continue;
+                };
+                let readable = match store.get_resource(&target) {
+                    Ok(resource) => {
+                        self.matches(&target, &ancestors(store, &resource))
+                            && atomic_lib::hierarchy::check_read(store, &resource, &for_agent)
+                                .is_ok()
+                    }
+                    Err(_) => target == self.target,
+                };
+                if readable {
+                    commits.push((sequence, commit));
+                }
+            }
+        }
+        Ok(commits)
+    }
+}
+
+/// How many entries of the commit log are read at once when catching up.
+const CATCH_UP_BATCH: usize = 1000;
+
+/// The subjects of the parents of a Resource.
+pub fn ancestors(store: &impl Storelike, resource: &Resource) -> Vec<String> {
+    resource
+        .get_parent_tree(store)
+        .map(|parents| parents.iter().map(|p| p.get_subject().clone()).collect())
+        .unwrap_or_default()
+}
+
+/// All durable subscriptions, by Agent. Loaded from the store when the server starts.
+#[derive(Default)]
+pub struct DurableSubscriptions {
+    by_agent: HashMap<String, Vec<DurableSubscription>>,
+}
+
+impl DurableSubscriptions {
+    pub fn load(store: &Db) -> AtomicServerResult<Self> {
+        let mut durable = DurableSubscriptions::default();
+        for resource in store
+            .query(&Query::new_class(urls::SUBSCRIPTION))?
+            .resources
+        {
+            match DurableSubscription::from_resource(store, &resource) {
+                Ok(subscription) => durable
+                    .by_agent
+                    .entry(subscription.agent.clone())
+                    .or_default()
+                    .push(subscription),
+                Err(e) => tracing::warn!(
+                    "Skipping invalid Subscription {}: {}",
+                    resource.get_subject(),
+                    e
+                ),
+            }
+        }
+        Ok(durable)
+    }
+
+    /// Stores the subscription, or updates the options of an existing one for the same Agent and subject.
+    pub fn subscribe(
+        &mut self,
+        store: &Db,
+        agent: &str,
+        target: &str,
+        options: DurableOptions,
+    ) -> AtomicServerResult<DurableSubscription> {
+        let subscriptions = self.by_agent.entry(agent.into()).or_default();
+        let subscription = match subscriptions.iter_mut().find(|s| s.target == target) {
+            Some(existing) => {
+                existing.options = options;
+                existing.last_seen = now();
+                existing
+            }
+            None => {
+                subscriptions.push(DurableSubscription {
+                    subject: format!(
+                        "{}/subscriptions/{}",
+                        store.get_server_url(),
+                        atomic_lib::utils::random_string(10)
+                    ),
+                    agent: agent.into(),
+                    target: target.into(),
+                    options,
+                    last_acknowledged: None,
+                    cursor: store.last_commit_sequence(),
+                    created_at: now(),
+                    last_seen: now(),
+                });
+                subscriptions.last_mut().unwrap()
+            }
+        };
+        subscription.save(store)?;
+        Ok(subscription.clone())
+    }
+
+    /// Removes the durable subscription of the Agent on the subject, if it has one.
+    pub fn unsubscribe(&mut self, store: &Db, agent: &str, target: &str) -> AtomicServerResult<()> {
+        let Some(subscriptions) = self.by_agent.get_mut(agent) else {
+            return Ok(());
+        };
+        if let Some(index) = subscriptions.iter().position(|s| s.target == target) {
+            let removed = subscriptions.remove(index);
+            store.remove_resource(&removed.subject)?;
+        }
+        Ok(())
+    }
+
+    /// Forgets a Subscription Resource that was destroyed by its Agent.
+    pub fn forget(&mut self, subject: &str) {
+        for subscriptions in self.by_agent.values_mut() {
+            subscriptions.retain(|s| s.subject != subject);
+        }
+    }
+
+    /// Returns the subscriptions of an Agent that connected, and remembers that it was seen.
+    pub fn connected(&mut self, store: &Db, agent: &str) -> Vec<DurableSubscription> {
+        let Some(subscriptions) = self.by_agent.get_mut(agent) else {
+            return Vec::new();
+        };
+        for subscription in subscriptions.iter_mut() {
+            subscription.last_seen = now();
+            if let Err(e) = subscription.save(store) {
+                tracing::error!(
+                    "Could not save Subscription {}: {}",
+                    subscription.subject,
+                    e
+                );
+            }
+        }
+        subscriptions.clone()
+    }
+
+    /// Moves the cursor of the subscriptions of the Agent that the Commit belongs to.
+    /// Older Commits don't move it back.
+    pub fn acknowledge(&mut self, store: &Db, agent: &str, commit: &str) -> AtomicServerResult<()> {
+        let commit_resource = store.get_resource(commit)?;
+        let sequence = store.commit_sequence_of(commit)?;
+        let target = commit_resource.get(urls::SUBJECT)?.to_string();
+        let parents = store
+            .get_resource(&target)
+            .map(|resource| ancestors(store, &resource))
+            .unwrap_or_default();
+        let Some(subscriptions) = self.by_agent.get_mut(agent) else {
+            return Err(format!("{} has no durable subscriptions", agent).into());
+        };
+        for subscription in subscriptions
+            .iter_mut()
+            .filter(|s| s.matches(&target, &parents))
+        {
+            if let Some(sequence) = sequence.filter(|s| *s > subscription.cursor) {
+                subscription.cursor = sequence;
+                subscription.last_acknowledged = Some(commit.into());
+            }
+            subscription.last_seen = now();
+            subscription.save(store)?;
+        }
+        Ok(())
+    }
+
+    /// Removes the subscriptions that have not been seen for `max_idle`, unless their Agent is connected.
+    /// Returns the amount of removed subscriptions.
+    pub fn collect_garbage(
+        &mut self,
+        store: &Db,
+        max_idle: Duration,
+        is_connected: impl Fn(&str) -> bool,
+    ) -> usize {
+        let cutoff = now() - max_idle.as_millis() as i64;
+        let mut removed = 0;
+        for (agent, subscriptions) in self.by_agent.iter_mut() {
+            if is_connected(agent) {
+                continue;
+            }
+            subscriptions.retain(|subscription| {
+                if subscription.last_seen > cutoff {
+                    return true;
+                }
+                if let Err(e) = store.remove_resource(&subscription.subject) {
+                    tracing::error!(
+                        "Could not remove Subscription {}: {}",
+                        subscription.subject,
+                        e
+                    );
+                }
+                removed += 1;
+                false
+            });
+        }
+        self.by_agent
+            .retain(|_, subscriptions| !subscriptions.is_empty());
+        removed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atomic_lib::{
+        agents::Agent,
+        commit::{CommitBuilder, CommitOpts},
+    };
+
+    /// Renames the Resource, and returns the subject of the Commit.
+    fn rename(store: &Db, subject: &str, name: &str) -> String {
+        let resource = store
+            .get_resource(subject)
+            .unwrap_or_else(|_| Resource::new(subject.into()));
+        let mut commitbuilder = CommitBuilder::new(subject.into());
+        if resource.get(urls::PARENT).is_err() {
+            commitbuilder.set(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+            );
+        }
+        commitbuilder.set(urls::NAME.into(), Value::String(name.into()));
+        let opts = CommitOpts {
+            validate_schema: true,
+            validate_signature: true,
+            validate_timestamp: true,
+            validate_rights: false,
+            validate_previous_commit: false,
+            validate_for_agent: None,
+            update_index: true,
+            validate_relative_urls: false,
+        };
+        let agent = store.get_default_agent().unwrap();
+        commitbuilder
+            .sign(&agent, store, &resource)
+            .unwrap()
+            .apply_opts(store, &opts)
+            .unwrap()
+            .commit_resource
+            .get_subject()
+            .clone()
+    }
+
+    #[test]
+    fn no_commits_are_missed_across_a_restart() {
+        let store = Db::init_temp("durable_subscriptions_restart").unwrap();
+        let subscriber = Agent::new(Some("subscriber"), &store).unwrap();
+        subscriber
+            .to_resource()
+            .unwrap()
+            .save_locally(&store)
+            .unwrap();
+        let mut drive = store.get_resource(store.get_server_url()).unwrap();
+        drive
+            .set_propval(
+                urls::READ.into(),
+                vec![subscriber.subject.clone()].into(),
+                &store,
+            )
+            .unwrap();
+        drive.save_locally(&store).unwrap();
+        let task = format!("{}/task", store.get_server_url());
+        rename(&store, &task, "created");
+
+        let options = DurableOptions::parse("durable=true transport=websocket".split(' '))
+            .unwrap()
+            .unwrap();
+        assert_eq!(options.transport.as_deref(), Some("websocket"));
+        assert!(DurableOptions::parse("include-children=true".split(' ')).is_err());
+
+        let mut durable = DurableSubscriptions::load(&store).unwrap();
+        durable
+            .subscribe(&store, &subscriber.subject, &task, options)
+            .unwrap();
+        let first = rename(&store, &task, "first");
+        durable
+            .acknowledge(&store, &subscriber.subject, &first)
+            .unwrap();
+        // Sent to the client, but never acknowledged
+        let second = rename(&store, &task, "second");
+
+        // The server restarts, which loses everything that is only kept in memory
+        drop(durable);
+        // Applied before the client reconnects
+        let third = rename(&store, &task, "third");
+        let other = format!("{}/other", store.get_server_url());
+        rename(&store, &other, "not subscribed");
+
+        let mut durable = DurableSubscriptions::load(&store).unwrap();
+        let subscriptions = durable.connected(&store, &subscriber.subject);
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[0].last_acknowledged.as_ref(), Some(&first));
+        assert_eq!(
+            Some(subscriptions[0].cursor),
+            store.commit_sequence_of(&first).unwrap()
+        );
+        let replayed: Vec<String> = subscriptions[0]
+            .catch_up(&store)
+            .unwrap()
+            .iter()
+            .map(|(_, commit)| commit.get_subject().clone())
+            .collect();
+        // In the order in which they were applied
+        assert_eq!(replayed, vec![second, third]);
+
+        // Connected Agents and recently seen subscriptions are kept
+        assert_eq!(durable.collect_garbage(&store, Duration::ZERO, |_| true), 0);
+        assert_eq!(
+            durable.collect_garbage(&store, Duration::from_secs(3600), |_| false),
+            0
+        );
+        assert_eq!(
+            durable.collect_garbage(&store, Duration::ZERO, |_| false),
+            1
+        );
+        assert!(DurableSubscriptions::load(&store)
+            .unwrap()
+            .by_agent
+            .is_empty());
+    }
+}
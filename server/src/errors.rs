@@ -3,6 +3,8 @@ use atomic_lib::{parse::JSON_AD_MIME, urls, Resource, Value};
 use serde::Serialize;
 use std::error::Error;
 
+const ERROR_TEMPLATE: &str = include_str!("../templates/error.html");
+
 // More strict Result type
 pub type AtomicServerResult<T> = std::result::Result<T, AtomicServerError>;
 
@@ -11,6 +13,23 @@ pub enum AppErrorType {
     NotFound,
     Unauthorized,
     MethodNotAllowed,
+    /// The request is malformed, e.g. a multipart body without files
+    BadRequest,
+    Locked,
+    /// The Agent exceeded its Commit rate limit
+    TooManyRequests,
+    /// The request body exceeds a configured limit
+    PayloadTooLarge,
+    /// The Resource has expired
+    Gone,
+    /// The server can't handle the request right now, e.g. because the audit log can't be written
+    ServiceUnavailable,
+    /// A request to another server was refused by the outbound guard
+    OutboundBlocked,
+    /// The parents of a Resource form a loop
+    Conflict,
+    /// Another server responded with an error, or with something that can't be used
+    BadGateway,
     Other,
 }
 
@@ -22,7 +41,15 @@ pub struct AtomicServerError {
     pub error_resource: Option<Box<Resource>>,
 }
 
-impl AtomicServerError {}
+impl AtomicServerError {
+    pub fn new(message: String, error_type: AppErrorType) -> Self {
+        AtomicServerError {
+            message,
+            error_type,
+            error_resource: None,
+        }
+    }
+}
 
 impl std::fmt::Debug for AtomicServerError {
     // The derive impl is too verbose, as it includes the full `error_resource`.
@@ -36,6 +63,51 @@ pub struct AppErrorResponse {
     pub error: String,
 }
 
+/// The fields of an Error that are shown on its HTML page.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorPage {
+    status: u16,
+    message: String,
+    subject: Option<String>,
+    property: Option<String>,
+    property_description: Option<String>,
+    datatype: Option<String>,
+    example: Option<String>,
+}
+
+impl AtomicServerError {
+    /// Renders the Error as an HTML page, for handlers that respond to browsers.
+    /// Includes the Property hints of validation errors, see [atomic_lib::errors::PropertyHint].
+    pub fn html_response(&self) -> HttpResponse {
+        let get = |prop: &str| -> Option<String> {
+            self.error_resource
+                .as_ref()
+                .and_then(|r| r.get(prop).ok())
+                .map(|v| v.to_string())
+        };
+        let page = ErrorPage {
+            status: self.status_code().as_u16(),
+            message: self.message.clone(),
+            subject: get(urls::ERROR_SUBJECT),
+            property: get(urls::ERROR_PROPERTY),
+            property_description: get(urls::ERROR_PROPERTY_DESCRIPTION),
+            datatype: get(urls::ERROR_DATATYPE),
+            example: get(urls::ERROR_EXAMPLE),
+        };
+        let body = tera::Context::from_serialize(&page)
+            .and_then(|context| tera::Tera::one_off(ERROR_TEMPLATE, &context, true))
+            .unwrap_or_else(|_| self.message.clone());
+        HttpResponse::build(self.status_code())
+            .content_type("text/html")
+            .insert_header((
+                "Content-Security-Policy",
+                crate::security_headers::ERROR_PAGE_POLICY,
+            ))
+            .body(body)
+    }
+}
+
 impl Error for AtomicServerError {}
 
 impl ResponseError for AtomicServerError {
@@ -43,6 +115,15 @@ impl ResponseError for AtomicServerError {
         match self.error_type {
             AppErrorType::NotFound => StatusCode::NOT_FOUND,
             AppErrorType::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            AppErrorType::BadRequest => StatusCode::BAD_REQUEST,
+            AppErrorType::Locked => StatusCode::LOCKED,
+            AppErrorType::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            AppErrorType::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            AppErrorType::Gone => StatusCode::GONE,
+            AppErrorType::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            AppErrorType::OutboundBlocked => StatusCode::FORBIDDEN,
+            AppErrorType::Conflict => StatusCode::CONFLICT,
+            AppErrorType::BadGateway => StatusCode::BAD_GATEWAY,
             AppErrorType::Other => StatusCode::INTERNAL_SERVER_ERROR,
             AppErrorType::Unauthorized => StatusCode::UNAUTHORIZED,
         }
@@ -85,6 +166,10 @@ impl From<atomic_lib::errors::AtomicError> for AtomicServerError {
             atomic_lib::AtomicErrorType::NotFoundError => AppErrorType::NotFound,
             atomic_lib::AtomicErrorType::UnauthorizedError => AppErrorType::Unauthorized,
             atomic_lib::AtomicErrorType::MethodNotAllowed => AppErrorType::MethodNotAllowed,
+            atomic_lib::AtomicErrorType::Locked => AppErrorType::Locked,
+            atomic_lib::AtomicErrorType::Gone => AppErrorType::Gone,
+            atomic_lib::AtomicErrorType::OutboundBlocked => AppErrorType::OutboundBlocked,
+            atomic_lib::AtomicErrorType::HierarchyCycle => AppErrorType::Conflict,
             atomic_lib::AtomicErrorType::ParseError => AppErrorType::Other,
             atomic_lib::AtomicErrorType::OtherError => AppErrorType::Other,
         };
@@ -0,0 +1,62 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{plugins::activity::get_summary, Storelike};
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent, locale,
+    security_headers,
+};
+
+const ACTIVITY_TEMPLATE: &str = include_str!("../../templates/activity.html");
+
+#[derive(Deserialize, Debug)]
+pub struct ActivityQuery {
+    drive: String,
+    days: Option<u64>,
+}
+
+/// Renders the activity summary of a Drive as an HTML page, in the language of the request.
+/// Other clients get the JSON-AD ActivitySummary from the `/activity` Endpoint.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn activity_page(
+    appstate: web::Data<AppState>,
+    query: web::Query<ActivityQuery>,
+    req: actix_web::HttpRequest,
+) -> HttpResponse {
+    render_activity(&appstate, &query, &req).unwrap_or_else(|e| e.html_response())
+}
+
+fn render_activity(
+    appstate: &AppState,
+    query: &ActivityQuery,
+    req: &actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), appstate, requested)?;
+    let summary = get_summary(store, &query.drive, query.days.unwrap_or(7), &for_agent)?;
+
+    let (lang, from_query) = locale::negotiate(req);
+    let mut context = tera::Context::from_serialize(&summary)
+        .map_err(|e| format!("Failed to build activity page: {}", e))?;
+    context.insert("cspNonce", &security_headers::nonce(req));
+    let body = locale::render(
+        "activity.html",
+        ACTIVITY_TEMPLATE,
+        &mut context,
+        lang,
+        &appstate.translations,
+    )?;
+    let mut builder = HttpResponse::Ok();
+    if from_query {
+        builder.cookie(locale::lang_cookie(lang));
+    }
+    Ok(builder.content_type("text/html").body(body))
+}
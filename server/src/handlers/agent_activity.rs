@@ -0,0 +1,57 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{hierarchy::check_write, Storelike};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agent_activity::AgentReport, appstate::AppState, errors::AtomicServerResult,
+    helpers::get_client_agent,
+};
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ActivityQuery {
+    /// Only show this Agent
+    agent: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ActivityResponse {
+    /// The `--activity-limits`
+    limits: Vec<String>,
+    /// Whether Agents that exceed a limit are suspended
+    suspend: bool,
+    agents: Vec<AgentReport>,
+}
+
+/// Shows the reads, Commits and downloads per Agent, counted since the server started, see [crate::agent_activity].
+/// Requires write rights to the root Drive.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn agent_activity(
+    appstate: web::Data<AppState>,
+    query: web::Query<ActivityQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), &appstate, requested)?;
+    let drive = store.get_resource(store.get_server_url())?;
+    check_write(store, &drive, &for_agent)?;
+
+    let limits = &appstate.config.activity_limits;
+    let response = ActivityResponse {
+        limits: limits.iter().map(|limit| limit.to_string()).collect(),
+        suspend: appstate.config.opts.activity_suspend,
+        agents: appstate
+            .agent_activity
+            .report(store, limits, query.agent.as_deref()),
+    };
+    Ok(HttpResponse::Ok().json(response))
+}
@@ -0,0 +1,58 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::Storelike;
+
+use crate::{
+    agent_overview::{build_overview, OverviewParams},
+    appstate::AppState,
+    content_types::{get_accept, ContentType},
+    errors::AtomicServerResult,
+    helpers::get_client_agent,
+    locale, security_headers,
+};
+
+const AGENT_OVERVIEW_TEMPLATE: &str = include_str!("../../templates/agent_overview.html");
+
+/// Lists what an Agent has access to, see [crate::agent_overview].
+/// Responds with an HTML page to browsers, in the language of the request, and with JSON otherwise.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn agent_overview(
+    appstate: web::Data<AppState>,
+    query: web::Query<OverviewParams>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), &appstate, requested)?;
+    let overview = build_overview(store, &query, &for_agent)?;
+
+    match get_accept(req.headers()) {
+        ContentType::Html => {
+            let (lang, from_query) = locale::negotiate(&req);
+            let mut context = tera::Context::from_serialize(&overview)
+                .map_err(|e| format!("Failed to build agent overview page: {}", e))?;
+            context.insert("cspNonce", &security_headers::nonce(&req));
+            let body = locale::render(
+                "agent_overview.html",
+                AGENT_OVERVIEW_TEMPLATE,
+                &mut context,
+                lang,
+                &appstate.translations,
+            )?;
+            let mut builder = HttpResponse::Ok();
+            if from_query {
+                builder.cookie(locale::lang_cookie(lang));
+            }
+            Ok(builder.content_type("text/html").body(body))
+        }
+        _ => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string(&overview).map_err(|e| e.to_string())?)),
+    }
+}
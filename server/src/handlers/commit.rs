@@ -1,9 +1,19 @@
-use crate::{appstate::AppState, errors::AtomicServerResult};
+use crate::{
+    appstate::AppState,
+    commit_limits::{self, CommitLimits},
+    errors::AtomicServerResult,
+};
 use actix_web::{web, HttpResponse};
-use atomic_lib::{commit::CommitOpts, parse::parse_json_ad_commit_resource, Commit, Storelike};
+use atomic_lib::{
+    commit::{CommitOpts, CommitResponse},
+    parse::parse_json_ad_commit_resource,
+    Commit, Storelike,
+};
+use serde::Deserialize;
 
 /// Send and process a Commit.
-/// Currently only accepts JSON-AD
+/// Currently only accepts JSON-AD.
+/// Commits that exceed the limits of their signer are rejected with `413` (size) or `429` (rate), see [CommitLimits].
 #[tracing::instrument(skip(appstate))]
 pub async fn post_commit(
     appstate: web::Data<AppState>,
@@ -15,9 +25,22 @@ pub async fn post_commit(
         let random_number = rng.gen_range(100..1000);
         tokio::time::sleep(tokio::time::Duration::from_millis(random_number)).await;
     }
+    let commit_response = apply_incoming_commit(&appstate, &body)?;
+    let message = commit_response.echoed_commit_resource().to_json_ad()?;
+
+    Ok(HttpResponse::Ok().body(message))
+}
+
+/// Parses, checks and applies a JSON-AD Commit sent by a client.
+/// Shared by the `/commit` endpoint and the `COMMIT` WebSocket message, so both enforce the same limits, signature and rights checks.
+pub fn apply_incoming_commit(
+    appstate: &AppState,
+    body: &str,
+) -> AtomicServerResult<CommitResponse> {
     let store = &appstate.store;
-    let mut builder = HttpResponse::Ok();
-    let incoming_commit_resource = parse_json_ad_commit_resource(&body, store)?;
+    crate::replication::reject_writes(appstate)?;
+    appstate.audit.check_writable()?;
+    let incoming_commit_resource = parse_json_ad_commit_resource(body, store)?;
     let incoming_commit = Commit::from_resource(incoming_commit_resource)?;
     if !incoming_commit.subject.contains(
         &store
@@ -26,19 +49,76 @@ pub async fn post_commit(
     ) {
         return Err("Subject of commit should be sent to other domain - this store can not own this resource.".into());
     }
+    let signer = incoming_commit.signer.clone();
+    appstate
+        .settings
+        .check_commit(store, &incoming_commit, &signer.clone().into())?;
+    let limits = CommitLimits::for_signer(appstate, &signer);
+    if let Some(limits) = &limits {
+        limits.check_payload(body.len(), &incoming_commit)?;
+        appstate
+            .commit_limiter
+            .check_rate(&signer, limits.commits_per_minute)?;
+        commit_limits::check_override_rights(appstate, &incoming_commit, &signer.clone().into())?;
+    }
     let opts = CommitOpts {
         validate_schema: true,
         validate_signature: true,
         validate_timestamp: true,
-        validate_rights: true,
+        validate_rights: !commit_limits::is_admin_override(appstate, &incoming_commit),
         // https://github.com/atomicdata-dev/atomic-server/issues/412
         validate_previous_commit: false,
         validate_for_agent: Some(incoming_commit.signer.to_string()),
         update_index: true,
+        validate_relative_urls: appstate.config.opts.strict_relative_urls,
     };
     let commit_response = incoming_commit.apply_opts(store, &opts)?;
+    appstate
+        .agent_activity
+        .record_commit(&signer.clone().into(), &incoming_commit.subject);
+    if limits.is_some() {
+        appstate.commit_limiter.record(&signer);
+    }
+    Ok(commit_response)
+}
 
-    let message = commit_response.commit_resource.to_json_ad()?;
+#[derive(Deserialize, Debug)]
+pub struct CommitReportQuery {
+    /// How far back the report goes. Defaults to 24 hours.
+    hours: Option<u64>,
+    /// Maximum amount of Agents in the report. Defaults to 20.
+    limit: Option<usize>,
+}
+
+/// Lists the Agents that created the most Commits recently, derived from the Commits in the store.
+/// Requires write rights to the root Drive.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn commit_report(
+    appstate: web::Data<AppState>,
+    query: web::Query<CommitReportQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = crate::helpers::get_client_agent(req.headers(), &appstate, requested)?;
+    let drive = store.get_resource(store.get_server_url())?;
+    atomic_lib::hierarchy::check_write(store, &drive, &for_agent)?;
 
-    Ok(builder.body(message))
+    let since = commit_limits::hours_ago(query.hours.unwrap_or(24));
+    let agents: Vec<serde_json::Value> =
+        commit_limits::top_committers(store, since, query.limit.unwrap_or(20))?
+            .into_iter()
+            .map(|(agent, commits)| serde_json::json!({ "agent": agent, "commits": commits }))
+            .collect();
+    let report = serde_json::json!({ "since": since, "agents": agents });
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .body(report.to_string()))
 }
@@ -0,0 +1,80 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{agents::ForAgent, plugins::copy::copy_resource, Storelike};
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState,
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+    handlers::{download::is_safe_file_id, upload::create_unique_file},
+    helpers::get_client_agent,
+};
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct CopyQuery {
+    /// The Resource to copy
+    subject: String,
+    /// The parent of the copy
+    parent: String,
+    /// Also copy all descendants
+    #[serde(default)]
+    recursive: bool,
+    /// Also copy Files, including their contents
+    #[serde(default)]
+    include_files: bool,
+}
+
+/// Copies a Resource (and with `recursive=true` its descendants) to the `parent`, see [copy_resource].
+/// Requires read rights to the copied Resources, and append rights to the new parent.
+/// Responds with a JSON object that maps the copied subjects to the subjects of their copies.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn copy(
+    appstate: web::Data<AppState>,
+    query: web::Query<CopyQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    crate::replication::reject_writes(&appstate)?;
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), &appstate, requested)?;
+    if for_agent == ForAgent::Public {
+        return Err(AtomicServerError::new(
+            "Sign in to copy Resources".into(),
+            AppErrorType::Unauthorized,
+        ));
+    }
+
+    let uploads_path = &appstate.config.uploads_path;
+    let copy_file = |internal_id: &str| -> atomic_lib::errors::AtomicResult<String> {
+        if !is_safe_file_id(internal_id) {
+            return Err(format!("Invalid internal ID of file: {}", internal_id).into());
+        }
+        // Internal IDs start with a unique prefix, followed by the file name
+        let filename = internal_id
+            .split_once('-')
+            .map(|(_prefix, name)| name)
+            .unwrap_or(internal_id);
+        let (new_id, mut target) =
+            create_unique_file(uploads_path, filename).map_err(|e| e.message)?;
+        let mut source = std::fs::File::open(uploads_path.join(internal_id))?;
+        std::io::copy(&mut source, &mut target)?;
+        Ok(new_id)
+    };
+    let copies = copy_resource(
+        store,
+        &query.subject,
+        &query.parent,
+        query.recursive,
+        query.include_files,
+        &for_agent,
+        &copy_file,
+    )?;
+    Ok(HttpResponse::Ok().json(copies))
+}
@@ -1,10 +1,18 @@
 use actix_files::NamedFile;
-use actix_web::{web, HttpRequest, HttpResponse};
-use atomic_lib::{urls, Resource, Storelike};
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use atomic_lib::{agents::ForAgent, urls, Resource, Storelike};
 
-use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+use crate::{
+    appstate::AppState,
+    cache::{is_public, CachePolicy, VARY},
+    connections::{MinRate, RateCheckedBody},
+    errors::AtomicServerResult,
+    helpers::get_client_agent,
+};
 
 /// Downloads the File of the Resource that matches the same URL minus the `/download` path.
+/// Files can't be changed after uploading, so they get a long `Cache-Control` lifetime if caching is enabled.
+/// Downloads are refused with `429` when the client already runs `max_concurrent_downloads`, and dropped when they are slower than `min_transfer_rate`, see [crate::connections].
 #[tracing::instrument(skip(appstate, req))]
 pub async fn handle_download(
     path: Option<web::Path<String>>,
@@ -27,19 +35,78 @@ pub async fn handle_download(
     let for_agent = get_client_agent(headers, &appstate, subject.clone())?;
     tracing::info!("handle_download: {}", subject);
     let resource = store.get_resource_extended(&subject, false, &for_agent)?;
-    download_file_handler_partial(&resource, &req, &appstate)
+    download_file_handler_partial(&resource, &req, &appstate, &for_agent)
 }
 
 pub fn download_file_handler_partial(
     resource: &Resource,
     req: &HttpRequest,
     appstate: &AppState,
+    for_agent: &ForAgent,
 ) -> AtomicServerResult<HttpResponse> {
     let file_name = resource
         .get(urls::INTERNAL_ID)
-        .map_err(|e| format!("Internal ID of file could not be resolved. {}", e))?;
+        .map_err(|e| format!("Internal ID of file could not be resolved. {}", e))?
+        .to_string();
+    if !is_safe_file_id(&file_name) {
+        return Err(format!("Invalid internal ID of file: {}", file_name).into());
+    }
     let mut file_path = appstate.config.uploads_path.clone();
-    file_path.push(file_name.to_string());
+    file_path.push(file_name);
     let file = NamedFile::open(file_path)?;
-    Ok(file.into_response(req))
+    // Signed in Agents are limited by their subject, anonymous downloads by the IP address of the connection
+    let client = match for_agent {
+        ForAgent::AgentSubject(subject) => subject.clone(),
+        _ => req
+            .peer_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_else(|| "unknown".into()),
+    };
+    let slot = appstate
+        .connections
+        .start_download(&client, appstate.config.opts.max_concurrent_downloads)?;
+    appstate.agent_activity.record_download(
+        for_agent,
+        resource.get_subject(),
+        file.metadata().len(),
+    );
+    let rate = MinRate::from_opts(&appstate.config.opts);
+    let what = format!("download of {} by {}", resource.get_subject(), client);
+    let mut response = file
+        .into_response(req)
+        .map_body(|_head, body| RateCheckedBody::new(body, rate, what, slot))
+        .map_into_boxed_body();
+    let public = is_public(&appstate.store, resource, for_agent);
+    if let Some(cache_control) = CachePolicy::from_opts(&appstate.config.opts).file(public) {
+        let headers = response.headers_mut();
+        headers.insert(
+            header::CACHE_CONTROL,
+            header::HeaderValue::from_str(&cache_control).map_err(|e| e.to_string())?,
+        );
+        headers.insert(header::VARY, header::HeaderValue::from_static(VARY));
+    }
+    Ok(response)
+}
+
+/// Internal IDs are file names in the uploads directory.
+/// Rejects anything that could point outside of it.
+/// Without separators, only `.` and `..` themselves can traverse, so older IDs like `v1..2.pdf` keep working.
+pub(crate) fn is_safe_file_id(file_id: &str) -> bool {
+    !file_id.is_empty() && !file_id.contains(['/', '\\', '\0']) && file_id != "." && file_id != ".."
+}
+
+#[cfg(test)]
+mod test {
+    use super::is_safe_file_id;
+
+    #[test]
+    fn rejects_traversal() {
+        assert!(is_safe_file_id("1676281556124-report.pdf"));
+        assert!(is_safe_file_id("1676281556124-v1..2.pdf"));
+        assert!(!is_safe_file_id("../config.toml"));
+        assert!(!is_safe_file_id(".."));
+        assert!(!is_safe_file_id("sub/file"));
+        assert!(!is_safe_file_id("..\\file"));
+        assert!(!is_safe_file_id(""));
+    }
 }
@@ -0,0 +1,90 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{
+    agents::ForAgent, hierarchy::check_write, parse::JSON_AD_MIME,
+    plugins::duplicates::merge_resources, urls, Storelike,
+};
+use serde::Deserialize;
+
+use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+
+#[derive(Deserialize, Debug)]
+pub struct DuplicatesQuery {
+    /// Only compare instances of this Class
+    class: Option<String>,
+    /// Comma-separated Property URLs that must all have equal values
+    props: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct MergeQuery {
+    /// The duplicate that is kept
+    keep: String,
+    /// Comma-separated subjects of the duplicates that are merged into `keep` and trashed
+    remove: String,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Lists groups of Resources with equal values for the `props`, as a JSON-AD array with a nested Resource per group.
+/// Requires write rights to the root Drive.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn find_duplicates(
+    appstate: web::Data<AppState>,
+    query: web::Query<DuplicatesQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    check_admin(&appstate, &req)?;
+    let props: Vec<&str> = split_list(&query.props);
+    let groups = store.find_duplicates(query.class.as_deref(), &props)?;
+    let body: serde_json::Value = groups
+        .into_iter()
+        .map(|members| serde_json::json!({ urls::COLLECTION_MEMBERS: members }))
+        .collect();
+    Ok(HttpResponse::Ok()
+        .content_type(JSON_AD_MIME)
+        .body(body.to_string()))
+}
+
+/// Merges duplicates into the `keep` Resource, see [merge_resources].
+/// With `dry-run=true`, only responds with the changes that would be made.
+/// Requires write rights to the root Drive.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn merge_duplicates(
+    appstate: web::Data<AppState>,
+    query: web::Query<MergeQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    crate::replication::reject_writes(&appstate)?;
+    check_admin(&appstate, &req)?;
+    let remove: Vec<String> = split_list(&query.remove)
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let report = merge_resources(&appstate.store, &query.keep, &remove, query.dry_run)?;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+fn check_admin(appstate: &AppState, req: &actix_web::HttpRequest) -> AtomicServerResult<ForAgent> {
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), appstate, requested)?;
+    let drive = store.get_resource(store.get_server_url())?;
+    check_write(store, &drive, &for_agent)?;
+    Ok(for_agent)
+}
+
+fn split_list(list: &str) -> Vec<&str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
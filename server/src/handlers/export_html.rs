@@ -0,0 +1,66 @@
+use actix_web::{http::header, web, HttpResponse};
+use atomic_lib::Storelike;
+
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    helpers::get_client_agent,
+    html_export::{build_export, ExportParams},
+    locale, security_headers,
+};
+
+const EXPORT_TEMPLATE: &str = include_str!("../../templates/export.html");
+
+/// Renders a Resource, and optionally its children, as one self-contained HTML document, see [crate::html_export].
+#[tracing::instrument(skip(appstate, req))]
+pub async fn export_html(
+    appstate: web::Data<AppState>,
+    query: web::Query<ExportParams>,
+    req: actix_web::HttpRequest,
+) -> HttpResponse {
+    render_export(&appstate, &query, &req).unwrap_or_else(|e| e.html_response())
+}
+
+fn render_export(
+    appstate: &AppState,
+    params: &ExportParams,
+    req: &actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), appstate, requested)?;
+    let document = build_export(store, &appstate.config, params, &for_agent)?;
+
+    let lang = match params.lang.as_deref().and_then(locale::Locale::from_tag) {
+        Some(lang) => lang,
+        None => locale::negotiate(req).0,
+    };
+    let mut context = tera::Context::from_serialize(&document)
+        .map_err(|e| format!("Failed to build export: {}", e))?;
+    let body = locale::render(
+        "export.html",
+        EXPORT_TEMPLATE,
+        &mut context,
+        lang,
+        &appstate.translations,
+    )?;
+    let file_name = format!("{}.html", sanitize_filename::sanitize(&document.title));
+    Ok(HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            format!("inline; filename=\"{}\"", file_name.replace('"', "")),
+        ))
+        .insert_header((
+            header::CONTENT_SECURITY_POLICY,
+            security_headers::EXPORT_POLICY,
+        ))
+        .body(body))
+}
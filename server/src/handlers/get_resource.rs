@@ -1,16 +1,35 @@
 use crate::{
     appstate::AppState,
+    cache::{is_public, CachePolicy, VARY},
     content_types::get_accept,
     content_types::ContentType,
-    errors::AtomicServerResult,
-    helpers::{get_client_agent, try_extension},
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+    handlers::rights::explain_denial,
+    helpers::{
+        get_client_agent, split_explain_from_query, split_fields_from_query,
+        split_truncate_values_from_query, try_extension, ArrayPagination,
+    },
 };
 use actix_web::{web, HttpResponse};
-use atomic_lib::Storelike;
+use atomic_lib::{
+    plugins::deprecation::{is_deprecated, replaced_by},
+    urls, Storelike,
+};
 use simple_server_timing_header::Timer;
 
 /// Respond to a single resource.
 /// The URL should match the Subject of the resource.
+/// The `array_limit` and `array_offset` query parameters truncate large ResourceArrays in the response, such as `children` and `attachments`, see [atomic_lib::resources::PAGINATED_ARRAYS].
+/// The original length and next offset are then returned in the `X-Array-Total-Length` and `X-Array-Next-Offset` headers.
+/// The `fields` query parameter limits JSON, JSON-AD and HTML responses to some properties, see [atomic_lib::Resource::select_fields].
+/// Unknown fields are listed in a `Warning` header. RDF serializations ignore `fields`, so they stay lossless.
+/// The `truncate_values` query parameter shortens textual values longer than that many bytes, see [atomic_lib::Resource::truncate_values].
+/// The truncated properties are listed in a `Warning` header. Exports and versions always contain the full values.
+/// Values that don't match the current datatype of their Property are served as they are stored, and listed in a `Warning` header with `datatype-mismatch`, see [atomic_lib::coerce].
+/// If access is denied and the `explain` query parameter is `true`, the error lists the Resources that were checked and where the check ended, see [crate::handlers::rights::explain_denial].
+/// Accepting an Invite is refused with `401` if the `invitesEnabled` server setting is false.
+/// The `Cache-Control` header is set by the [CachePolicy].
+/// Deprecated Resources get a `Deprecation` header, and a `Link` to their `replaced-by` with `rel="successor-version"`.
 #[tracing::instrument(skip(appstate, req))]
 pub async fn handle_get_resource(
     path: Option<web::Path<String>>,
@@ -22,6 +41,10 @@ pub async fn handle_get_resource(
     let headers = req.headers();
     let mut content_type = get_accept(headers);
     let server_url = &appstate.config.server_url;
+    let (querystring, array_pagination) = ArrayPagination::split_from_query(req.query_string())?;
+    let (querystring, fields) = split_fields_from_query(&querystring)?;
+    let (querystring, truncate_values) = split_truncate_values_from_query(&querystring)?;
+    let (querystring, explain) = split_explain_from_query(&querystring);
     // Get the subject from the path, or return the home URL
     let subject = if let Some(subj_end) = path {
         let mut subj_end_string = subj_end.as_str();
@@ -37,10 +60,10 @@ pub async fn handle_get_resource(
             }
             // Check extensions and set datatype. Harder than it looks to get right...
             // This might not be the best way of creating the subject. But I can't access the full URL from any actix stuff!
-            let querystring = if req.query_string().is_empty() {
+            let querystring = if querystring.is_empty() {
                 "".to_string()
             } else {
-                format!("?{}", req.query_string())
+                format!("?{}", querystring)
             };
             let subject = format!("{}/{}{}", server_url, subj_end_string, querystring);
             subject
@@ -56,30 +79,126 @@ pub async fn handle_get_resource(
     let for_agent = get_client_agent(headers, &appstate, subject.clone())?;
     timer.add("get_agent");
 
+    if !appstate.settings.get().invites_enabled && is_invite_acceptance(store, &subject) {
+        return Err(AtomicServerError::new(
+            "Accepting Invites is disabled on this server".into(),
+            AppErrorType::Unauthorized,
+        ));
+    }
+
     let mut builder = HttpResponse::Ok();
 
     tracing::debug!("get_resource: {} as {}", subject, content_type.to_mime());
     builder.append_header(("Content-Type", content_type.to_mime()));
-    // This prevents the browser from displaying the JSON response upon re-opening a closed tab
-    // https://github.com/atomicdata-dev/atomic-server/issues/137
+    builder.append_header(("Vary", VARY));
+
+    let mut resource = match store.get_resource_extended(&subject, false, &for_agent) {
+        Err(e)
+            if explain
+                && matches!(e.error_type, atomic_lib::AtomicErrorType::UnauthorizedError) =>
+        {
+            return Err(explain_denial(store, &subject, &for_agent, e));
+        }
+        result => result?,
+    };
+    timer.add("get_resource");
+
+    if is_deprecated(&resource) {
+        builder.append_header(("Deprecation", "true"));
+        if let Some(replacement) = replaced_by(&resource) {
+            builder.append_header((
+                "Link",
+                format!("<{}>; rel=\"successor-version\"", replacement),
+            ));
+        }
+    }
+
+    let public = is_public(store, &resource, &for_agent);
     builder.append_header((
         "Cache-Control",
-        "no-store, no-cache, must-revalidate, private",
+        CachePolicy::from_opts(&appstate.config.opts).resource(public),
     ));
 
-    let resource = store.get_resource_extended(&subject, false, &for_agent)?;
-    timer.add("get_resource");
+    if let Some(pagination) = array_pagination {
+        if let Some(page) = pagination.paginate(&mut resource) {
+            builder.append_header(("X-Array-Total-Length", page.total_length.to_string()));
+            if let Some(next) = page.next_offset {
+                builder.append_header(("X-Array-Next-Offset", next.to_string()));
+                builder.append_header((
+                    "Link",
+                    format!("<{}>; rel=\"next\"", pagination.page_url(&subject, next)),
+                ));
+            }
+        }
+    }
 
-    let response_body = match content_type {
-        ContentType::Json => resource.to_json(store)?,
-        ContentType::JsonLd => resource.to_json_ld(store)?,
-        ContentType::JsonAd => resource.to_json_ad()?,
-        ContentType::Html => resource.to_json_ad()?,
-        ContentType::Turtle | ContentType::NTriples => {
-            let atoms = resource.to_atoms();
-            atomic_lib::serialize::atoms_to_ntriples(atoms, store)?
+    if let Some(fields) = fields {
+        if matches!(
+            content_type,
+            ContentType::Json | ContentType::JsonAd | ContentType::Html
+        ) {
+            let unknown = resource.select_fields(&fields, store);
+            if !unknown.is_empty() {
+                builder.append_header((
+                    "Warning",
+                    format!("299 - \"Unknown fields: {}\"", unknown.join(", ")),
+                ));
+            }
         }
-    };
+    }
+
+    let mismatches = resource.datatype_mismatches(store);
+    if !mismatches.is_empty() {
+        builder.append_header((
+            "Warning",
+            format!("299 - \"datatype-mismatch: {}\"", mismatches.join(", ")),
+        ));
+    }
+
+    if let Some(max) = truncate_values {
+        let truncated = resource.truncate_values(max);
+        if !truncated.is_empty() {
+            let mut props: Vec<&String> = truncated.keys().collect();
+            props.sort();
+            builder.append_header((
+                "Warning",
+                format!(
+                    "299 - \"Truncated values: {}\"",
+                    props
+                        .iter()
+                        .map(|p| p.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            ));
+        }
+    }
+
+    let response_body = content_type.serialize(&resource, store)?;
     timer.add("serialize");
+    appstate
+        .agent_activity
+        .record_served(&for_agent, response_body.len() as u64);
     Ok(builder.body(response_body))
 }
+
+/// Invites are accepted by opening them with a `public-key` or `agent` query parameter.
+fn is_invite_acceptance(store: &impl Storelike, subject: &str) -> bool {
+    let Some((base, query)) = subject.split_once('?') else {
+        return false;
+    };
+    let has_agent_param = query.split('&').any(|pair| {
+        let key = pair.split('=').next().unwrap_or_default();
+        let key = urlencoding::decode(key).unwrap_or_default();
+        matches!(
+            key.as_ref(),
+            "public-key" | "agent" | urls::INVITE_PUBKEY | urls::AGENT
+        )
+    });
+    has_agent_param
+        && store
+            .get_resource(base)
+            .and_then(|invite| invite.get(urls::IS_A)?.to_subjects(None))
+            .map(|classes| classes.iter().any(|class| class == urls::INVITE))
+            .unwrap_or(false)
+}
@@ -0,0 +1,58 @@
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+
+use crate::{
+    appstate::AppState, audit::AuditStatus, connections::ConnectionCounts,
+    errors::AtomicServerResult, replication::ReplicationHealth, search::AnalyzerSettings,
+};
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Health {
+    /// `ok`, or `degraded` when something needs attention
+    status: &'static str,
+    audit: AuditStatus,
+    search: SearchStatus,
+    connections: ConnectionCounts,
+    /// Only for read replicas
+    #[serde(skip_serializing_if = "Option::is_none")]
+    replication: Option<ReplicationHealth>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SearchStatus {
+    #[serde(flatten)]
+    analyzer: AnalyzerSettings,
+    /// The analyzer changed, and the search index is being rebuilt. Results are incomplete until then.
+    stale: bool,
+}
+
+/// Reports whether the server works as expected, e.g. for uptime monitors and load balancers.
+/// Responds with `503 Service Unavailable` when the audit log can't be written, or when a replica has lost its primary.
+#[tracing::instrument(skip(appstate))]
+pub async fn health(appstate: web::Data<AppState>) -> AtomicServerResult<HttpResponse> {
+    let audit = appstate.audit.status();
+    let replication = appstate.replica.as_ref().map(|replica| replica.health());
+    let degraded = audit.error.is_some()
+        || replication
+            .as_ref()
+            .map(|replication| !replication.connected)
+            .unwrap_or(false);
+    let health = Health {
+        status: if degraded { "degraded" } else { "ok" },
+        audit,
+        search: SearchStatus {
+            analyzer: appstate.search_state.analyzer.clone(),
+            stale: appstate.search_state.is_stale(),
+        },
+        connections: appstate.connections.counts(),
+        replication,
+    };
+    let mut response = if degraded {
+        HttpResponse::ServiceUnavailable()
+    } else {
+        HttpResponse::Ok()
+    };
+    Ok(response.json(health))
+}
@@ -0,0 +1,281 @@
+use actix_web::{http::header, web, HttpResponse};
+use atomic_lib::{
+    agents::ForAgent,
+    errors::AtomicResult,
+    hierarchy::check_read,
+    parse::{import_json_ad_stream, ImportEvent, ImportMode, ParseOpts, SaveOpts, JSON_LD_MIME},
+    plugins::import_profile::ImportProfile,
+    Storelike,
+};
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState,
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+    handlers::post_resource::handle_post_resource,
+    helpers::get_client_agent,
+    serve::PAYLOAD_MAX,
+};
+
+/// How many chunks of the body are buffered while the import catches up.
+const BODY_BUFFER: usize = 16;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub struct ImportQuery {
+    /// Where the imported Resources are placed
+    #[serde(alias = "https://atomicdata.dev/properties/importer/parent")]
+    parent: Option<String>,
+    #[serde(default)]
+    #[serde(alias = "https://atomicdata.dev/properties/importer/overwrite-outside")]
+    overwrite_outside: bool,
+    /// Fetch the document from this URL, instead of reading the body
+    url: Option<String>,
+    base: Option<String>,
+    format: Option<String>,
+    /// `abort` (default) or `continue`, see [ImportMode]
+    mode: Option<String>,
+    /// Respond with Server-Sent Events, like when accepting `text/event-stream`
+    #[serde(default)]
+    progress: bool,
+    /// Subject of an ImportProfile, for importing CSV or JSON documents from other tools
+    profile: Option<String>,
+}
+
+/// Imports a JSON-AD body while it is being uploaded, so there is no limit to its size.
+/// When the client accepts `text/event-stream` (or passes `progress=true`), it receives [ImportEvent]s as Server-Sent Events, ending with a `summary`.
+/// Otherwise, it gets a response when the import is done, like the other Endpoints.
+/// JSON-LD documents and documents fetched using `url` are handled by the `/import` Endpoint, see [atomic_lib::plugins::importer].
+/// With a `profile`, CSV and JSON documents from other tools are imported using an ImportProfile instead.
+#[tracing::instrument(skip(appstate, req, payload))]
+pub async fn import(
+    appstate: web::Data<AppState>,
+    query: web::Query<ImportQuery>,
+    req: actix_web::HttpRequest,
+    mut payload: web::Payload,
+) -> AtomicServerResult<HttpResponse> {
+    crate::replication::reject_writes(&appstate)?;
+    let first = match payload.next().await {
+        Some(chunk) => chunk.map_err(|e| format!("Error while reading the body. {}", e))?,
+        None => web::Bytes::new(),
+    };
+    if query.profile.is_none() && (query.url.is_some() || is_json_ld(&query, &req, &first)) {
+        let body = read_body(first, payload).await?;
+        let path = web::Path::from("import".to_string());
+        return handle_post_resource(Some(path), appstate, req, body).await;
+    }
+
+    let store = appstate.store.clone();
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), &appstate, requested)?;
+    if for_agent == ForAgent::Public {
+        return Err(AtomicServerError::new(
+            "Sign in to import Resources".into(),
+            AppErrorType::Unauthorized,
+        ));
+    }
+    if let Some(profile) = &query.profile {
+        let body = read_body(first, payload).await?;
+        return import_with_profile(&appstate, profile, body, for_agent).await;
+    }
+    let mode: ImportMode = match &query.mode {
+        Some(mode) => mode.parse()?,
+        None => ImportMode::default(),
+    };
+    let parse_opts = ParseOpts {
+        importer: Some(
+            query
+                .parent
+                .clone()
+                .ok_or("No parent specified for importer")?,
+        ),
+        for_agent,
+        // We sign the importer Commits with the default agent,
+        // not the one performing the import, because we don't have their private key.
+        signer: Some(store.get_default_agent()?),
+        save: SaveOpts::Commit,
+        overwrite_outside: query.overwrite_outside,
+        base: query.base.clone(),
+    };
+    let progress = query.progress || accepts_event_stream(&req);
+
+    let (chunks_sender, chunks) = mpsc::channel(BODY_BUFFER);
+    let (events_sender, events) = mpsc::unbounded();
+    let import = web::block(move || {
+        let body = ChannelReader {
+            chunks,
+            current: web::Bytes::new(),
+        };
+        let mut on_event = |event: &ImportEvent| -> AtomicResult<()> {
+            if !progress {
+                return Ok(());
+            }
+            events_sender
+                .unbounded_send(event.clone())
+                .map_err(|_| "The client is gone".into())
+        };
+        import_json_ad_stream(body, &store, &parse_opts, mode, &mut on_event)
+    });
+    let upload = send_body(first, payload, chunks_sender);
+
+    if progress {
+        actix_web::rt::spawn(async move {
+            upload.await;
+            match import.await {
+                Ok(Err(e)) => tracing::warn!("{}", e),
+                Err(e) => tracing::error!("Import failed. {}", e),
+                Ok(Ok(_summary)) => {}
+            }
+        });
+        let body = events.map(|event| Ok::<_, std::convert::Infallible>(server_sent_event(&event)));
+        return Ok(HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header((header::CACHE_CONTROL, "no-cache"))
+            // Prevents the compression middleware from buffering the events
+            .insert_header((header::CONTENT_ENCODING, "identity"))
+            .streaming(body));
+    }
+
+    upload.await;
+    import
+        .await
+        .map_err(|e| format!("Import failed. {}", e))??;
+    let endpoint = atomic_lib::plugins::importer::import_endpoint().to_resource(&appstate.store)?;
+    Ok(HttpResponse::Ok()
+        .content_type(atomic_lib::parse::JSON_AD_MIME)
+        .body(endpoint.to_json_ad()?))
+}
+
+/// Imports a CSV or JSON document using an ImportProfile, and responds with a report of the rows, see [atomic_lib::plugins::import_profile].
+async fn import_with_profile(
+    appstate: &AppState,
+    profile: &str,
+    body: web::Bytes,
+    for_agent: ForAgent,
+) -> AtomicServerResult<HttpResponse> {
+    let store = appstate.store.clone();
+    let profile_resource = store.get_resource(profile)?;
+    check_read(&store, &profile_resource, &for_agent)?;
+    let profile = ImportProfile::from_resource(&profile_resource)?;
+    let body = String::from_utf8(body.to_vec())
+        .map_err(|e| format!("The document is not valid UTF-8. {}", e))?;
+    let signer = store.get_default_agent()?;
+    let report = web::block(move || {
+        atomic_lib::plugins::import_profile::import_with_profile(
+            &store, &profile, &body, &for_agent, &signer,
+        )
+    })
+    .await
+    .map_err(|e| format!("Import failed. {}", e))??;
+    Ok(HttpResponse::Ok().json(report))
+}
+
+fn accepts_event_stream(req: &actix_web::HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.contains("text/event-stream"))
+        .unwrap_or(false)
+}
+
+/// JSON-LD can't be imported while it is being read, since its `@context` applies to the entire document.
+fn is_json_ld(query: &ImportQuery, req: &actix_web::HttpRequest, first: &[u8]) -> bool {
+    match query.format.as_deref() {
+        Some("json-ld") | Some(JSON_LD_MIME) => return true,
+        Some(_) => return false,
+        None => {}
+    }
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if content_type.starts_with(JSON_LD_MIME) {
+        return true;
+    }
+    let start = String::from_utf8_lossy(first);
+    start.contains("\"@context\"") || start.contains("\"@graph\"")
+}
+
+/// Reads the entire body, up to the maximum payload size.
+async fn read_body(first: web::Bytes, mut payload: web::Payload) -> AtomicServerResult<web::Bytes> {
+    let mut body = web::BytesMut::from(&first[..]);
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| format!("Error while reading the body. {}", e))?;
+        if body.len() + chunk.len() > PAYLOAD_MAX {
+            return Err(AtomicServerError::new(
+                format!(
+                    "The body is larger than {} bytes. Only JSON-AD bodies can be larger.",
+                    PAYLOAD_MAX
+                ),
+                AppErrorType::PayloadTooLarge,
+            ));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body.freeze())
+}
+
+/// Sends the chunks of the body to the import, until it is done or stops reading.
+async fn send_body(
+    first: web::Bytes,
+    mut payload: web::Payload,
+    mut chunks: mpsc::Sender<Result<web::Bytes, String>>,
+) {
+    let mut next = Some(Ok(first));
+    while let Some(chunk) = next {
+        let chunk = chunk.map_err(|e: actix_web::error::PayloadError| {
+            format!("Error while reading the body. {}", e)
+        });
+        let failed = chunk.is_err();
+        if chunks.send(chunk).await.is_err() || failed {
+            return;
+        }
+        next = payload.next().await;
+    }
+}
+
+/// Reads the chunks of the body on a blocking thread, see [send_body].
+/// A dropped connection is an error, so the import is aborted.
+struct ChannelReader {
+    chunks: mpsc::Receiver<Result<web::Bytes, String>>,
+    current: web::Bytes,
+}
+
+impl std::io::Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.current.is_empty() {
+            match futures::executor::block_on(self.chunks.next()) {
+                Some(Ok(chunk)) => self.current = chunk,
+                Some(Err(e)) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::ConnectionAborted,
+                        e,
+                    ))
+                }
+                None => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.current.len());
+        buf[..len].copy_from_slice(&self.current.split_to(len));
+        Ok(len)
+    }
+}
+
+fn server_sent_event(event: &ImportEvent) -> web::Bytes {
+    let name = match event {
+        ImportEvent::Progress { .. } => "progress",
+        ImportEvent::Failed { .. } => "failed",
+        ImportEvent::Summary(_) => "summary",
+    };
+    let data = serde_json::to_string(event).unwrap_or_default();
+    format!("event: {}\ndata: {}\n\n", name, data).into()
+}
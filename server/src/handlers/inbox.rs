@@ -0,0 +1,49 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{agents::ForAgent, plugins::notifications::unread, Storelike};
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState,
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+    helpers::get_client_agent,
+};
+
+/// The most Notifications that are returned at once.
+const MAX_PAGE_SIZE: usize = 100;
+
+#[derive(Deserialize, Debug)]
+pub struct InboxQuery {
+    #[serde(default)]
+    page: usize,
+    #[serde(rename = "page-size", default = "default_page_size")]
+    page_size: usize,
+}
+
+fn default_page_size() -> usize {
+    30
+}
+
+/// Lists the unread Notifications of the Agent that signs the request, newest first, see [atomic_lib::plugins::notifications].
+/// Notifications are marked as read with a Commit that sets their `isRead` to `true`.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn inbox(
+    appstate: web::Data<AppState>,
+    query: web::Query<InboxQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!("{}/inbox", store.get_server_url());
+    let ForAgent::AgentSubject(agent) = get_client_agent(req.headers(), &appstate, requested)?
+    else {
+        return Err(AtomicServerError::new(
+            "Sign in to see your notifications".into(),
+            AppErrorType::Unauthorized,
+        ));
+    };
+    let page_size = query.page_size.clamp(1, MAX_PAGE_SIZE);
+    let page = unread(store, &agent, query.page * page_size, page_size)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header(("Cache-Control", "private, no-store"))
+        .body(serde_json::to_string(&page).map_err(|e| e.to_string())?))
+}
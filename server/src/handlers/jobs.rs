@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+use actix_web::{web, HttpResponse};
+use atomic_lib::{
+    hierarchy::{check_read, check_write},
+    parse::JSON_AD_MIME,
+    Storelike,
+};
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent, jobs::JobType,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct JobQuery {
+    /// The [JobType], e.g. `rebuild-indexes` or `export-subtree`
+    #[serde(rename = "type")]
+    job_type: String,
+    /// The Resource that the Job acts on. Required for `export-subtree` and `extract-text`, optional for `check-links`.
+    subject: Option<String>,
+    /// For `normalize-values`: only report the Values that are not normalized.
+    /// For `coerce-values`: only report the Values that don't match their datatype.
+    /// For `check-attachments`: only report the issues. Defaults to `true` there, pass `false` to repair them.
+    #[serde(rename = "dry-run")]
+    dry_run: Option<bool>,
+    /// For `backup`: verify the backup after writing it. Defaults to `--verify-backups`.
+    verify: Option<bool>,
+    /// For `verify-backup`: the name of the folder in the backups directory. Defaults to the latest backup.
+    backup: Option<String>,
+    /// For `backup` and `verify-backup`: how many Resources are compared with the live store.
+    sample: Option<u64>,
+}
+
+/// Creates a background Job and responds with the Job Resource.
+/// The client can poll (or subscribe to) the subject of the Job to follow its progress.
+/// Rebuilding indexes, purging the trash, checking attachments and backups require write rights to the Drive, exporting requires read rights to the exported Resource.
+/// Checking links requires write rights to the Resource, or to the Drive when all links are checked.
+/// Extracting text requires write rights to the File.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn create_job(
+    appstate: web::Data<AppState>,
+    query: web::Query<JobQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    crate::replication::reject_writes(&appstate)?;
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), &appstate, requested)?;
+    let job_type = JobType::from_str(&query.job_type)?;
+
+    let params = match job_type {
+        JobType::RebuildIndexes
+        | JobType::PurgeTrash
+        | JobType::RemoveExpired
+        | JobType::ApplyRetention => {
+            let drive = store.get_resource(store.get_server_url())?;
+            check_write(store, &drive, &for_agent)?;
+            serde_json::json!({})
+        }
+        JobType::NormalizeValues | JobType::CoerceValues => {
+            let drive = store.get_resource(store.get_server_url())?;
+            check_write(store, &drive, &for_agent)?;
+            serde_json::json!({ "dryRun": query.dry_run.unwrap_or(false) })
+        }
+        JobType::Backup | JobType::VerifyBackup => {
+            let drive = store.get_resource(store.get_server_url())?;
+            check_write(store, &drive, &for_agent)?;
+            let mut params = serde_json::json!({});
+            if let Some(verify) = query.verify {
+                params["verify"] = verify.into();
+            }
+            if let Some(backup) = &query.backup {
+                params["backup"] = backup.as_str().into();
+            }
+            if let Some(sample) = query.sample {
+                params["sample"] = sample.into();
+            }
+            params
+        }
+        JobType::CheckAttachments => {
+            let drive = store.get_resource(store.get_server_url())?;
+            check_write(store, &drive, &for_agent)?;
+            serde_json::json!({ "dryRun": query.dry_run.unwrap_or(true) })
+        }
+        JobType::CheckLinks => match &query.subject {
+            Some(subject) => {
+                check_write(store, &store.get_resource(subject)?, &for_agent)?;
+                serde_json::json!({ "subject": subject })
+            }
+            None => {
+                let drive = store.get_resource(store.get_server_url())?;
+                check_write(store, &drive, &for_agent)?;
+                serde_json::json!({})
+            }
+        },
+        JobType::CompactHistory => {
+            let subject = query
+                .subject
+                .clone()
+                .ok_or("The `subject` query parameter is required for compacting history")?;
+            check_write(store, &store.get_resource(&subject)?, &for_agent)?;
+            serde_json::json!({ "subject": subject })
+        }
+        JobType::ExtractText => {
+            let subject = query
+                .subject
+                .clone()
+                .ok_or("The `subject` query parameter is required for extracting text")?;
+            let file = store.get_resource(&subject)?;
+            check_write(store, &file, &for_agent)?;
+            if !atomic_lib::plugins::attachments::is_file(&file) {
+                return Err(format!("{} is not a File", subject).into());
+            }
+            serde_json::json!({ "subjects": [subject] })
+        }
+        JobType::RepairSideEffects => {
+            return Err("Repairs are scheduled by the server itself".into());
+        }
+        JobType::ExportSubtree => {
+            let subject = query
+                .subject
+                .clone()
+                .ok_or("The `subject` query parameter is required for exports")?;
+            check_read(store, &store.get_resource(&subject)?, &for_agent)?;
+            serde_json::json!({ "subject": subject })
+        }
+    };
+
+    let job = appstate.job_queue.enqueue(job_type, params, &for_agent)?;
+    Ok(HttpResponse::Ok()
+        .content_type(JSON_AD_MIME)
+        .body(job.to_json_ad()?))
+}
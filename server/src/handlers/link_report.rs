@@ -0,0 +1,41 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{hierarchy::check_read, parse::JSON_AD_MIME, Storelike};
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    helpers::get_client_agent,
+    link_checker::{filter_report, report_subject},
+};
+
+#[derive(Deserialize, Debug)]
+pub struct LinkReportQuery {
+    /// Only return the broken links of this Resource
+    subject: Option<String>,
+}
+
+/// Returns the LinkReport with the broken links found by the last `check-links` Job, optionally for a single Resource.
+/// To check the links again, create a `check-links` Job at `/jobs`.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn link_report(
+    appstate: web::Data<AppState>,
+    query: web::Query<LinkReportQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let subject = report_subject(store);
+    let for_agent = get_client_agent(req.headers(), &appstate, subject.clone())?;
+    let mut report = store.get_resource(&subject).map_err(|_| {
+        atomic_lib::AtomicError::not_found(
+            "No links have been checked yet. Create a `check-links` Job first.".into(),
+        )
+    })?;
+    check_read(store, &report, &for_agent)?;
+    if let Some(subject) = &query.subject {
+        filter_report(&mut report, subject);
+    }
+    Ok(HttpResponse::Ok()
+        .content_type(JSON_AD_MIME)
+        .body(report.to_json_ad()?))
+}
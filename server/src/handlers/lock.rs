@@ -0,0 +1,101 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{
+    agents::ForAgent, hierarchy::check_write, locks::DEFAULT_LOCK_DURATION, parse::JSON_AD_MIME,
+    Storelike,
+};
+use serde::Deserialize;
+
+use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+
+#[derive(Deserialize, Debug)]
+pub struct LockQuery {
+    /// The Resource to lock or unlock
+    subject: String,
+    /// Duration of the lock in seconds
+    duration: Option<i64>,
+}
+
+/// Locks a Resource for the requesting Agent, or refreshes the lock the Agent already has.
+/// Requires write rights. Until the lock expires or is released, Commits by other Agents fail with `423 Locked`.
+/// Responds with the Resource, which includes the `lockedBy` and `lockedUntil` properties.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn lock_resource(
+    appstate: web::Data<AppState>,
+    query: web::Query<LockQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    crate::replication::reject_writes(&appstate)?;
+    let store = &appstate.store;
+    let agent = lock_agent(&appstate, &req)?;
+    check_write(store, &store.get_resource(&query.subject)?, &agent)?;
+
+    let duration = query
+        .duration
+        .map(|seconds| seconds.saturating_mul(1000))
+        .unwrap_or(DEFAULT_LOCK_DURATION);
+    store
+        .get_locks()
+        .ok_or("This store does not support locks")?
+        .acquire(&query.subject, &agent.to_string(), duration)?;
+
+    respond_with_resource(&appstate, &query.subject, &agent)
+}
+
+/// Releases the lock of a Resource.
+/// The Agent holding the lock can always release it.
+/// Others can force the release if they have write rights on the parent of the Resource.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn unlock_resource(
+    appstate: web::Data<AppState>,
+    query: web::Query<LockQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    crate::replication::reject_writes(&appstate)?;
+    let store = &appstate.store;
+    let agent = lock_agent(&appstate, &req)?;
+    let locks = store
+        .get_locks()
+        .ok_or("This store does not support locks")?;
+
+    if let Some(lock) = locks.get(&query.subject) {
+        if lock.agent != agent.to_string() {
+            let parent = store.get_resource(&query.subject)?.get_parent(store)?;
+            check_write(store, &parent, &agent).map_err(|e| {
+                format!(
+                    "Only {} or Agents with write rights on the parent can release this lock. {}",
+                    lock.agent, e
+                )
+            })?;
+        }
+        locks.release(&query.subject);
+    }
+
+    respond_with_resource(&appstate, &query.subject, &agent)
+}
+
+/// Locks are held by a specific Agent, so anonymous requests can't use them.
+fn lock_agent(appstate: &AppState, req: &actix_web::HttpRequest) -> AtomicServerResult<ForAgent> {
+    let requested = format!(
+        "{}{}",
+        appstate.store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    match get_client_agent(req.headers(), appstate, requested)? {
+        ForAgent::Public => Err("Sign in to lock or unlock Resources".into()),
+        agent => Ok(agent),
+    }
+}
+
+fn respond_with_resource(
+    appstate: &AppState,
+    subject: &str,
+    agent: &ForAgent,
+) -> AtomicServerResult<HttpResponse> {
+    let resource = appstate.store.get_resource_extended(subject, true, agent)?;
+    Ok(HttpResponse::Ok()
+        .content_type(JSON_AD_MIME)
+        .body(resource.to_json_ad()?))
+}
@@ -0,0 +1,26 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{hierarchy::check_write, Storelike};
+
+use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+
+/// Counts and timings of store operations, and the most recent slow operations, see [atomic_lib::metrics].
+/// Requires write rights to the root Drive, since slow operations include the subjects they were about.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn metrics(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), &appstate, requested)?;
+    let drive = store.get_resource(store.get_server_url())?;
+    check_write(store, &drive, &for_agent)?;
+    Ok(HttpResponse::Ok().json(store.metrics()))
+}
@@ -5,11 +5,34 @@ Most of the logic for routing and handling resides in [atomic_lib::Storelike::ge
 However, some features reside in atomic-server.
 */
 
+pub mod activity;
+pub mod agent_activity;
+pub mod agent_overview;
 pub mod commit;
+pub mod copy;
 pub mod download;
+pub mod duplicates;
+pub mod export_html;
 pub mod get_resource;
+pub mod health;
+pub mod import;
+pub mod inbox;
+pub mod jobs;
+pub mod link_report;
+pub mod lock;
+pub mod metrics;
+pub mod openapi;
+pub mod pins;
 pub mod post_resource;
+pub mod preview;
+pub mod query;
+pub mod replication;
+pub mod retention;
+pub mod rights;
+pub mod schema;
 pub mod search;
+pub mod setup;
 pub mod single_page_app;
+pub mod table;
 pub mod upload;
 pub mod web_sockets;
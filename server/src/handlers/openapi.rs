@@ -0,0 +1,25 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+
+use crate::{appstate::AppState, errors::AtomicServerResult, openapi, security_headers};
+
+/// Responds with the OpenAPI description of this server, see [openapi::build_openapi].
+#[tracing::instrument(skip(appstate))]
+pub async fn openapi_json(appstate: web::Data<AppState>) -> AtomicServerResult<HttpResponse> {
+    let document = openapi::build_openapi(&appstate)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .body(document.to_string()))
+}
+
+/// Renders the OpenAPI description using Swagger-UI.
+/// Swagger-UI is loaded from a CDN and sets inline styles, so this page has its own Content-Security-Policy.
+pub async fn api_docs(req: HttpRequest) -> HttpResponse {
+    let nonce = security_headers::nonce(&req);
+    HttpResponse::Ok()
+        .content_type("text/html")
+        .insert_header((
+            "Content-Security-Policy",
+            openapi::swagger_ui_policy(&nonce),
+        ))
+        .body(openapi::swagger_ui_html("/openapi.json", &nonce))
+}
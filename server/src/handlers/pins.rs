@@ -0,0 +1,30 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{agents::ForAgent, plugins::pins::pins_for, Storelike};
+
+use crate::{
+    appstate::AppState,
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+    helpers::get_client_agent,
+};
+
+/// Lists the pinned Resources of the Agent that signs the request, see [atomic_lib::plugins::pins].
+#[tracing::instrument(skip(appstate, req))]
+pub async fn pins(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!("{}/pins", store.get_server_url());
+    let ForAgent::AgentSubject(agent) = get_client_agent(req.headers(), &appstate, requested)?
+    else {
+        return Err(AtomicServerError::new(
+            "Sign in to see your pins".into(),
+            AppErrorType::Unauthorized,
+        ));
+    };
+    let pins = pins_for(store, &agent)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header(("Cache-Control", "private, no-store"))
+        .body(serde_json::to_string(&pins).map_err(|e| e.to_string())?))
+}
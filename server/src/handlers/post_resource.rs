@@ -17,6 +17,7 @@ pub async fn handle_post_resource(
     req: actix_web::HttpRequest,
     body: web::Bytes,
 ) -> AtomicServerResult<HttpResponse> {
+    crate::replication::reject_writes(&appstate)?;
     let mut timer = Timer::new();
 
     let headers = req.headers();
@@ -37,10 +38,18 @@ pub async fn handle_post_resource(
             }
             // Check extensions and set datatype. Harder than it looks to get right...
             // This might not be the best way of creating the subject. But I can't access the full URL from any actix stuff!
-            let querystring = if req.query_string().is_empty() {
+            let mut query = req.query_string().to_string();
+            // Endpoints can't read headers, so we tell them that the body is JSON-LD (used by `/import`)
+            if is_json_ld_body(headers) && !query.contains("format=") {
+                if !query.is_empty() {
+                    query.push('&');
+                }
+                query.push_str("format=json-ld");
+            }
+            let querystring = if query.is_empty() {
                 "".to_string()
             } else {
-                format!("?{}", req.query_string())
+                format!("?{}", query)
             };
             let subject = format!("{}/{}{}", server_url, subj_end_string, querystring);
             subject
@@ -75,12 +84,19 @@ pub async fn handle_post_resource(
         ContentType::JsonLd => resource.to_json_ld(store)?,
         ContentType::JsonAd => resource.to_json_ad()?,
         ContentType::Html => resource.to_json_ad()?,
-        ContentType::Turtle | ContentType::NTriples => {
-            let atoms = resource.to_atoms();
-            atomic_lib::serialize::atoms_to_ntriples(atoms, store)?
-        }
+        ContentType::Turtle => resource.to_turtle(store)?,
+        ContentType::NTriples => resource.to_n_triples(store)?,
+        ContentType::NQuads => resource.to_n_quads(store)?,
     };
     timer.add("serialize");
     builder.append_header(("Server-Timing", timer.header_value()));
     Ok(builder.body(response_body))
 }
+
+fn is_json_ld_body(headers: &actix_web::http::header::HeaderMap) -> bool {
+    headers
+        .get(actix_web::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.starts_with(atomic_lib::parse::JSON_LD_MIME))
+        .unwrap_or(false)
+}
@@ -0,0 +1,96 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use atomic_lib::{agents::ForAgent, Storelike};
+use serde::Deserialize;
+
+use crate::{
+    appstate::AppState,
+    cache::{CachePolicy, VARY},
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+    helpers::get_client_agent,
+    link_preview::{local_preview, Preview},
+};
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct PreviewQuery {
+    subject: String,
+}
+
+/// Returns a small JSON summary of a Resource (name, plain text description, class, thumbnail and child count), for rendering link cards.
+/// Responds with `404` if the Resource doesn't exist, and `401` if the Agent can't read it.
+/// Web pages on other servers are previewed using their `<meta>` tags with `--external-link-previews`, otherwise they are refused with `400`.
+/// The `ETag` changes whenever a Commit changes the preview, so clients can revalidate with `If-None-Match`.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn preview(
+    appstate: web::Data<AppState>,
+    query: web::Query<PreviewQuery>,
+    req: HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), &appstate, requested)?;
+    let subject = &query.subject;
+
+    let local = subject == store.get_server_url()
+        || subject.starts_with(&format!("{}/", store.get_server_url()));
+    let preview: Preview = if local {
+        local_preview(store, subject, &for_agent)?
+    } else if appstate.config.opts.external_link_previews {
+        if !subject.starts_with("http://") && !subject.starts_with("https://") {
+            return Err(AtomicServerError::new(
+                format!("{} is not an HTTP URL", subject),
+                AppErrorType::BadRequest,
+            ));
+        }
+        let previews = appstate.link_previews.clone();
+        let store = store.clone();
+        let url = subject.clone();
+        web::block(move || previews.get(&store, &url))
+            .await
+            .map_err(|e| format!("Fetching the preview failed. {}", e))??
+    } else {
+        return Err(AtomicServerError::new(
+            "Previews of other servers are disabled. Start the server with `--external-link-previews` to enable them.".into(),
+            AppErrorType::BadRequest,
+        ));
+    };
+
+    let body = serde_json::to_string(&preview).map_err(|e| e.to_string())?;
+    // Depends on the rights of the Agent, so the Agent is part of the tag
+    let etag = format!(
+        "W/\"{}\"",
+        &atomic_lib::patch::checksum(&format!("{}\n{}", for_agent, body))[..32]
+    );
+    // Anonymous requests only get a preview of what the Public Agent can read
+    let public = for_agent == ForAgent::Public;
+    let cache_control = CachePolicy::from_opts(&appstate.config.opts).preview(public);
+    let not_modified = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(|tags| {
+            tags.split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        })
+        .unwrap_or(false);
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header((header::ETAG, etag))
+        .insert_header((header::CACHE_CONTROL, cache_control))
+        .insert_header((header::VARY, VARY));
+    if not_modified {
+        return Ok(response.finish());
+    }
+    Ok(response.content_type("application/json").body(body))
+}
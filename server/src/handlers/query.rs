@@ -0,0 +1,113 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{db::StructuredQuery, urls, Storelike};
+
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    handlers::search::text_search_subjects,
+    helpers::get_client_agent,
+    locale,
+    query_table::{build_query_table, export_csv, export_json_ad, QueryTableParams},
+    security_headers,
+};
+
+const QUERY_TABLE_TEMPLATE: &str = include_str!("../../templates/query_table.html");
+
+/// Maximum amount of full-text matches that are intersected with the other filters.
+const TEXT_MATCH_LIMIT: usize = 10_000;
+
+/// Runs a [StructuredQuery] sent as a JSON body, and responds with the matching Resources as JSON-AD.
+/// The full-text term is resolved using the search index, the other filters using the indexes in the store.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn structured_query(
+    appstate: web::Data<AppState>,
+    query: web::Json<StructuredQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let subject = format!("{}{}", store.get_server_url(), urls::PATH_QUERY);
+    let for_agent = get_client_agent(req.headers(), &appstate, subject.clone())?;
+
+    let text_matches = match &query.text {
+        Some(text) => Some(text_search_subjects(&appstate, text, TEXT_MATCH_LIMIT)?),
+        None => None,
+    };
+    let result = store.structured_query(&query, text_matches, &for_agent)?;
+    let resource = result.into_resource(subject, query.include_nested);
+
+    Ok(HttpResponse::Ok()
+        .content_type(atomic_lib::parse::JSON_AD_MIME)
+        .body(resource.to_json_ad()?))
+}
+
+/// Renders a [StructuredQuery] from the query parameters as an HTML table, see [crate::query_table].
+/// With `format=csv` or `format=json-ad`, responds with all results in that format instead.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn query_table_page(
+    appstate: web::Data<AppState>,
+    query: web::Query<QueryTableParams>,
+    req: actix_web::HttpRequest,
+) -> HttpResponse {
+    render_query_table(&appstate, &query.normalized(), &req).unwrap_or_else(|e| e.html_response())
+}
+
+fn render_query_table(
+    appstate: &AppState,
+    params: &QueryTableParams,
+    req: &actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), appstate, requested)?;
+    let text_matches = match &params.text {
+        Some(text) => Some(text_search_subjects(appstate, text, TEXT_MATCH_LIMIT)?),
+        None => None,
+    };
+
+    match params.format.as_deref() {
+        None | Some("html") => {}
+        Some("csv") => {
+            let query = params.to_query(store, None)?;
+            let result = store.structured_query(&query, text_matches, &for_agent)?;
+            return Ok(HttpResponse::Ok()
+                .content_type("text/csv; charset=utf-8")
+                .insert_header(("Content-Disposition", "attachment; filename=\"query.csv\""))
+                .body(export_csv(store, params, &result)?));
+        }
+        Some("json-ad") => {
+            let query = params.to_query(store, None)?;
+            let result = store.structured_query(&query, text_matches, &for_agent)?;
+            return Ok(HttpResponse::Ok()
+                .content_type(atomic_lib::parse::JSON_AD_MIME)
+                .body(export_json_ad(store, result, params)?));
+        }
+        Some(other) => {
+            return Err(format!("Unknown format '{}', use html, csv or json-ad", other).into())
+        }
+    }
+
+    let table = build_query_table(store, params, text_matches, &for_agent)?;
+    let (lang, from_query) = locale::negotiate(req);
+    let mut context = tera::Context::from_serialize(&table)
+        .map_err(|e| format!("Failed to build query table: {}", e))?;
+    context.insert("cspNonce", &security_headers::nonce(req));
+    let body = locale::render(
+        "query_table.html",
+        QUERY_TABLE_TEMPLATE,
+        &mut context,
+        lang,
+        &appstate.translations,
+    )?;
+    let mut builder = HttpResponse::Ok();
+    if from_query {
+        builder.cookie(locale::lang_cookie(lang));
+    }
+    Ok(builder.content_type("text/html").body(body))
+}
@@ -0,0 +1,107 @@
+//! Endpoints that read replicas follow, see [crate::replication].
+//! Both require write rights to the root Drive, which replicas have since they share the server Agent.
+
+use actix_web::{http::header, web, HttpResponse};
+use atomic_lib::{hierarchy::check_write, storelike::Query, urls, Storelike, Value};
+use futures::StreamExt;
+use serde::Deserialize;
+
+use crate::{
+    actor_messages::SubscribeAll,
+    appstate::AppState,
+    errors::AtomicServerResult,
+    helpers::get_client_agent,
+    replication::{KEEP_ALIVE, SINCE_HEADER},
+};
+
+#[derive(Deserialize, Debug)]
+pub struct StreamParams {
+    /// Only send Commits created at or after this time, in milliseconds since the Unix epoch
+    pub since: Option<i64>,
+}
+
+/// Exports all Resources as JSON-AD.
+/// The [SINCE_HEADER] tells the replica from which moment on it should follow the stream.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn export(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    check_admin(&appstate, &req)?;
+    let since = atomic_lib::utils::now();
+    let store = appstate.store.clone();
+    let body = web::block(move || store.export(false))
+        .await
+        .map_err(|e| format!("Export failed. {}", e))??;
+    Ok(HttpResponse::Ok()
+        .content_type(atomic_lib::parse::JSON_AD_MIME)
+        .insert_header((SINCE_HEADER, since.to_string()))
+        .body(body))
+}
+
+/// Sends every applied Commit as a Server-Sent Event, starting with the Commits created since `since`.
+/// Sends a comment every [KEEP_ALIVE], so replicas can tell a quiet primary from a lost connection.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn stream(
+    appstate: web::Data<AppState>,
+    params: web::Query<StreamParams>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    check_admin(&appstate, &req)?;
+    // Subscribe before reading the backlog, so no Commit falls in between.
+    // Commits that end up in both are skipped by the replica.
+    let (sender, receiver) = futures::channel::mpsc::unbounded();
+    appstate
+        .commit_monitor
+        .send(SubscribeAll { sender })
+        .await
+        .map_err(|e| format!("Could not subscribe to Commits. {}", e))?;
+
+    let mut backlog = Vec::new();
+    if let Some(since) = params.since {
+        let mut query = Query::new_class(urls::COMMIT);
+        query.sort_by = Some(urls::CREATED_AT.into());
+        query.start_val = Some(Value::Timestamp(since));
+        for commit in appstate.store.query(&query)?.resources {
+            backlog.push(commit.to_json_ad()?);
+        }
+    }
+
+    let keep_alive = futures::stream::unfold(
+        actix_web::rt::time::interval(KEEP_ALIVE),
+        |mut interval| async move {
+            interval.tick().await;
+            Some((None, interval))
+        },
+    );
+    let commits = futures::stream::iter(backlog).chain(receiver).map(Some);
+    let body = futures::stream::select(commits, keep_alive).map(|event| {
+        let event = match event {
+            Some(json) => format!("data: {}\n\n", json),
+            None => ": keep-alive\n\n".to_string(),
+        };
+        Ok::<_, std::convert::Infallible>(web::Bytes::from(event))
+    });
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        // Prevents the compression middleware from buffering the events
+        .insert_header((header::CONTENT_ENCODING, "identity"))
+        .streaming(body))
+}
+
+fn check_admin(appstate: &AppState, req: &actix_web::HttpRequest) -> AtomicServerResult<()> {
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), appstate, requested)?;
+    let drive = store.get_resource(store.get_server_url())?;
+    check_write(store, &drive, &for_agent)?;
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{hierarchy::check_write, plugins::retention, Storelike};
+use serde::Deserialize;
+
+use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionQuery {
+    /// Plan the run at this unix timestamp in milliseconds, instead of now
+    at: Option<i64>,
+}
+
+/// Shows what the RetentionPolicies would remove, without changing anything, see [atomic_lib::plugins::retention].
+/// The `apply-retention` Job carries it out.
+/// Requires write rights to the root Drive.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn retention(
+    appstate: web::Data<AppState>,
+    query: web::Query<RetentionQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), &appstate, requested)?;
+    let drive = store.get_resource(store.get_server_url())?;
+    check_write(store, &drive, &for_agent)?;
+
+    let moment = query.at.unwrap_or_else(atomic_lib::utils::now);
+    let plan = retention::plan(store, moment)?;
+    Ok(HttpResponse::Ok().json(plan))
+}
@@ -0,0 +1,95 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::{
+    agents::ForAgent,
+    hierarchy::{check_write, trace_rights, Right, RightsTrace},
+    plugins::property_rights::{decisions, PropertyDecision},
+    urls, AtomicError, Storelike, Value,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    appstate::AppState,
+    errors::{AtomicServerError, AtomicServerResult},
+    helpers::get_client_agent,
+};
+
+#[derive(Deserialize, Debug)]
+pub struct RightsQuery {
+    subject: String,
+    /// Defaults to the Agent that sends the request
+    agent: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RightsExplanation {
+    read: RightsTrace,
+    write: RightsTrace,
+    /// Whether the Agent may change the properties that are restricted by `propertyRights`.
+    /// These only matter if the Agent can write the Resource at all.
+    properties: Vec<PropertyDecision>,
+}
+
+/// Explains why an Agent can or can't read and write a Resource, by listing every Resource that was checked, see [atomic_lib::hierarchy::trace_rights].
+/// Also lists which restricted properties the Agent may change, see [atomic_lib::plugins::property_rights].
+/// Agents can ask about themselves. Asking about other Agents requires write rights to the Resource.
+/// The rights arrays (who is allowed) are only shown to Agents with write rights to the Resource.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn rights(
+    appstate: web::Data<AppState>,
+    query: web::Query<RightsQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let requester = get_client_agent(req.headers(), &appstate, requested)?;
+    let resource = store.get_resource(&query.subject)?;
+    let agent = query
+        .agent
+        .clone()
+        .map(ForAgent::from)
+        .unwrap_or_else(|| requester.clone());
+    let can_write = check_write(store, &resource, &requester).is_ok();
+    if agent != requester && !can_write {
+        return Err(AtomicError::unauthorized(format!(
+            "Explaining the rights of other Agents requires write rights to {}",
+            query.subject
+        ))
+        .into());
+    }
+    let trace = |right: Right| -> AtomicServerResult<RightsTrace> {
+        let trace = trace_rights(store, &resource, &agent, right)?;
+        Ok(if can_write { trace } else { trace.redacted() })
+    };
+    Ok(HttpResponse::Ok().json(RightsExplanation {
+        read: trace(Right::Read)?,
+        write: trace(Right::Write)?,
+        properties: decisions(store, &resource, &agent.to_string())?,
+    }))
+}
+
+/// Adds the trace of the denied read to an authorization error, for requests with `explain=true`.
+/// The trace is [RightsTrace::redacted], so it shows where the check ended, but not who is allowed.
+pub fn explain_denial(
+    store: &impl Storelike,
+    subject: &str,
+    for_agent: &ForAgent,
+    error: AtomicError,
+) -> AtomicServerError {
+    let trace = store
+        .get_resource(subject)
+        .and_then(|resource| trace_rights(store, &resource, for_agent, Right::Read));
+    let mut server_error = AtomicServerError::from(error);
+    if let (Ok(trace), Some(error_resource)) = (trace, server_error.error_resource.as_mut()) {
+        if let Ok(json) = serde_json::to_string(&trace.redacted()) {
+            error_resource.set_propval_unsafe(urls::ERROR_RIGHTS_TRACE.into(), Value::String(json));
+        }
+    }
+    server_error
+}
@@ -0,0 +1,52 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::Storelike;
+
+use crate::{
+    appstate::AppState,
+    content_types::{get_accept, ContentType},
+    errors::AtomicServerResult,
+    helpers::get_client_agent,
+    locale,
+    schema::build_schema_overview,
+    security_headers,
+};
+
+const SCHEMA_TEMPLATE: &str = include_str!("../../templates/schema.html");
+
+/// Lists all Classes and Properties, see [build_schema_overview].
+/// Responds with an HTML page to browsers, in the language of the request, and with JSON otherwise.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn schema_overview(
+    appstate: web::Data<AppState>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!("{}/schema", store.get_server_url());
+    let for_agent = get_client_agent(req.headers(), &appstate, requested)?;
+    let mut overview = build_schema_overview(store, &for_agent)?;
+    overview.usage = Some(store.schema_usage()?);
+
+    match get_accept(req.headers()) {
+        ContentType::Html => {
+            let (lang, from_query) = locale::negotiate(&req);
+            let mut context = tera::Context::from_serialize(&overview)
+                .map_err(|e| format!("Failed to build schema page: {}", e))?;
+            context.insert("cspNonce", &security_headers::nonce(&req));
+            let body = locale::render(
+                "schema.html",
+                SCHEMA_TEMPLATE,
+                &mut context,
+                lang,
+                &appstate.translations,
+            )?;
+            let mut builder = HttpResponse::Ok();
+            if from_query {
+                builder.cookie(locale::lang_cookie(lang));
+            }
+            Ok(builder.content_type("text/html").body(body))
+        }
+        _ => Ok(HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string(&overview).map_err(|e| e.to_string())?)),
+    }
+}
@@ -4,19 +4,22 @@
 
 use crate::{
     appstate::AppState,
+    config::SearchDeprecated,
     errors::{AtomicServerError, AtomicServerResult},
     search::{resource_to_facet, Fields},
 };
 use actix_web::{web, HttpResponse};
-use atomic_lib::{errors::AtomicResult, urls, Db, Resource, Storelike};
+use atomic_lib::{
+    errors::AtomicResult, plugins::deprecation::is_deprecated, urls, Db, Resource, Storelike,
+};
 use serde::Deserialize;
 use serde_with::{formats::CommaSeparator, StringWithSeparator};
 use simple_server_timing_header::Timer;
 use tantivy::{
     collector::TopDocs,
-    query::{BooleanQuery, BoostQuery, Occur, Query, QueryParser, TermQuery},
-    schema::IndexRecordOption,
-    tokenizer::{TokenStream, Tokenizer},
+    query::{BooleanQuery, BoostQuery, Occur, PhraseQuery, Query, QueryParser, TermQuery},
+    schema::{Field, IndexRecordOption},
+    tokenizer::TokenStream,
     Term,
 };
 use tracing::instrument;
@@ -103,6 +106,21 @@ pub async fn search_query(
     Ok(builder.body(results_resource.to_json_ad()?))
 }
 
+/// Returns the subjects of the best full-text matches for `q`, without checking rights.
+pub fn text_search_subjects(
+    appstate: &AppState,
+    q: &str,
+    limit: usize,
+) -> AtomicServerResult<Vec<String>> {
+    let searcher = appstate.search_state.reader.searcher();
+    let fields = crate::search::get_schema_fields(&appstate.search_state)?;
+    let query = build_text_query(&fields, q, &appstate.search_state.index)?;
+    let top_docs = searcher
+        .search(&query, &TopDocs::with_limit(limit))
+        .map_err(|e| format!("Error with creating search results: {} ", e))?;
+    docs_to_subjects(top_docs, &fields, &searcher)
+}
+
 #[derive(Debug, std::hash::Hash, Eq, PartialEq)]
 pub struct StringAtom {
     pub subject: String,
@@ -126,10 +144,17 @@ fn get_resources(
     // https://github.com/atomicdata-dev/atomic-server/issues/279
     // https://github.com/atomicdata-dev/atomic-server/issues/280/
     let for_agent = crate::helpers::get_client_agent(req.headers(), appstate, subject.into())?;
+    let search_deprecated = &appstate.config.opts.search_deprecated;
+    // Deprecated results are kept separately, so they can be demoted
+    let mut deprecated: Vec<Resource> = Vec::new();
     for s in subjects {
         match appstate.store.get_resource_extended(&s, true, &for_agent) {
             Ok(r) => {
-                if resources.len() < limit {
+                if *search_deprecated != SearchDeprecated::Include && is_deprecated(&r) {
+                    if *search_deprecated == SearchDeprecated::Demote {
+                        deprecated.push(r);
+                    }
+                } else if resources.len() < limit {
                     resources.push(r);
                 } else {
                     break;
@@ -141,6 +166,8 @@ fn get_resources(
             }
         }
     }
+    let room = limit.saturating_sub(resources.len());
+    resources.extend(deprecated.into_iter().take(room));
     Ok(resources)
 }
 
@@ -164,7 +191,7 @@ fn query_from_params(
     }
 
     if let Some(q) = &params.q {
-        let text_query = build_text_query(fields, q)?;
+        let text_query = build_text_query(fields, q, &appstate.search_state.index)?;
 
         query_list.push((Occur::Must, Box::new(text_query)));
     }
@@ -185,45 +212,92 @@ fn query_from_params(
 
 /// Performs both fuzzy and exact queries on the text and description fields.
 /// Boosts titles and exact matches over descriptions and fuzzy matches.
+/// Text between double quotes is a phrase, which has to appear in the title or description as a whole.
+/// The words are analyzed like the indexed text, see [crate::search::AnalyzerSettings].
 /// Does not yet search in JSON fields:
 /// https://github.com/atomicdata-dev/atomic-server/issues/597
-#[tracing::instrument]
-fn build_text_query(fields: &Fields, q: &str) -> AtomicResult<impl Query> {
-    let mut tokenizer = tantivy::tokenizer::SimpleTokenizer::default();
-    let mut token_stream = tokenizer.token_stream(q);
+#[tracing::instrument(skip(index))]
+pub(crate) fn build_text_query(
+    fields: &Fields,
+    q: &str,
+    index: &tantivy::Index,
+) -> AtomicResult<impl Query> {
+    let mut analyzer = index
+        .tokenizer_for_field(fields.title)
+        .map_err(|e| format!("No analyzer for search: {}", e))?;
     let mut queries: Queries = Vec::new();
-    // for every word, create a fuzzy query and an exact query
-    token_stream.process(&mut |token| {
-        let word = &token.text;
-        let title_term = Term::from_field_text(fields.title, word);
-        let description_term = Term::from_field_text(fields.description, word);
-        let title_fuzzy = tantivy::query::FuzzyTermQuery::new_prefix(title_term.clone(), 1, true);
-        let description_fuzzy =
-            tantivy::query::FuzzyTermQuery::new_prefix(description_term.clone(), 1, true);
-        let title_exact = TermQuery::new(title_term, IndexRecordOption::Basic);
-        let description_exact = TermQuery::new(description_term, IndexRecordOption::Basic);
-
-        // Boost the title higher than the description
-        queries.push((
-            Occur::Should,
-            Box::new(BoostQuery::new(Box::new(title_exact), 10.)),
-        ));
-        queries.push((
-            Occur::Should,
-            Box::new(BoostQuery::new(Box::new(description_exact), 2.0)),
-        ));
-
-        // Rank exact higher than fuzzy
-        queries.push((
-            Occur::Should,
-            Box::new(BoostQuery::new(Box::new(title_fuzzy), 4.0)),
-        ));
-        queries.push((Occur::Should, Box::new(description_fuzzy)));
-    });
+    // Every other part of the query is quoted
+    for (i, part) in q.split('"').enumerate() {
+        let mut words = Vec::new();
+        analyzer
+            .token_stream(part)
+            .process(&mut |token| words.push(token.text.clone()));
+        if i % 2 == 1 {
+            if let Some(phrase) = phrase_query(fields, &words) {
+                queries.push((Occur::Must, phrase));
+            }
+            continue;
+        }
+        // for every word, create a fuzzy query and an exact query
+        for word in &words {
+            let title_term = Term::from_field_text(fields.title, word);
+            let description_term = Term::from_field_text(fields.description, word);
+            let title_fuzzy =
+                tantivy::query::FuzzyTermQuery::new_prefix(title_term.clone(), 1, true);
+            let description_fuzzy =
+                tantivy::query::FuzzyTermQuery::new_prefix(description_term.clone(), 1, true);
+            let title_exact = TermQuery::new(title_term, IndexRecordOption::Basic);
+            let description_exact = TermQuery::new(description_term, IndexRecordOption::Basic);
+
+            // Boost the title higher than the description
+            queries.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(Box::new(title_exact), 10.)),
+            ));
+            queries.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(Box::new(description_exact), 2.0)),
+            ));
+
+            // Rank exact higher than fuzzy
+            queries.push((
+                Occur::Should,
+                Box::new(BoostQuery::new(Box::new(title_fuzzy), 4.0)),
+            ));
+            queries.push((Occur::Should, Box::new(description_fuzzy)));
+        }
+    }
 
     Ok(BooleanQuery::from(queries))
 }
 
+/// Matches the analyzed words in this order, in either the title or the description.
+fn phrase_query(fields: &Fields, words: &[String]) -> Option<Box<dyn Query>> {
+    let for_field = |field: Field| -> Box<dyn Query> {
+        let terms: Vec<Term> = words
+            .iter()
+            .map(|word| Term::from_field_text(field, word))
+            .collect();
+        match terms.len() {
+            1 => Box::new(TermQuery::new(
+                terms[0].clone(),
+                IndexRecordOption::WithFreqs,
+            )),
+            _ => Box::new(PhraseQuery::new(terms)),
+        }
+    };
+    if words.is_empty() {
+        return None;
+    }
+    Some(Box::new(BooleanQuery::new(vec![
+        (
+            Occur::Should,
+            Box::new(BoostQuery::new(for_field(fields.title), 10.)),
+        ),
+        (Occur::Should, for_field(fields.description)),
+    ])))
+}
+
 #[tracing::instrument(skip(index))]
 fn build_filter_query(
     fields: &Fields,
@@ -0,0 +1,28 @@
+use actix_web::{web, HttpResponse};
+use serde::Deserialize;
+
+use crate::{appstate::AppState, errors::AtomicServerResult, setup::SetupRequest};
+
+#[derive(Deserialize, Debug)]
+pub struct SetupQuery {
+    /// The one-time token printed in the log at startup
+    token: String,
+}
+
+/// Creates the admin Agent of a new server, see [crate::setup].
+/// The JSON body can contain a `publicKey` and a `name`. Without a `publicKey`, the generated private key is returned.
+#[tracing::instrument(skip(appstate, body))]
+pub async fn complete_setup(
+    appstate: web::Data<AppState>,
+    query: web::Query<SetupQuery>,
+    body: Option<web::Json<SetupRequest>>,
+) -> AtomicServerResult<HttpResponse> {
+    crate::replication::reject_writes(&appstate)?;
+    let request = body.map(|json| json.into_inner()).unwrap_or_default();
+    let response = appstate
+        .setup
+        .complete(&appstate.store, &query.token, request)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&response).map_err(|e| e.to_string())?))
+}
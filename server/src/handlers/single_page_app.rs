@@ -1,39 +1,85 @@
 use std::fmt::Display;
 use std::fmt::Formatter;
 
-use crate::{appstate::AppState, errors::AtomicServerResult};
+use crate::{
+    appstate::AppState,
+    cache::{is_public, CachePolicy, VARY},
+    errors::AtomicServerResult,
+    helpers::{get_client_agent, ArrayPagination},
+    security_headers,
+};
 use actix_web::HttpResponse;
 
-/// Returns the atomic-data-browser single page application
-#[tracing::instrument(skip(appstate))]
+/// Returns the atomic-data-browser single page application.
+/// Supports the same `array_limit` and `array_offset` query parameters as [crate::handlers::get_resource::handle_get_resource],
+/// and adds links to the previous and next pages.
+/// Deprecated Resources get a banner that links to their replacement.
+#[tracing::instrument(skip(appstate, req))]
 pub async fn single_page(
     appstate: actix_web::web::Data<AppState>,
     path: actix_web::web::Path<String>,
+    req: actix_web::HttpRequest,
 ) -> AtomicServerResult<HttpResponse> {
     let template = include_str!("../../assets_tmp/index.html");
     let subject = format!("{}/{}", appstate.store.get_server_url(), path);
-    let meta_tags: MetaTags = if let Ok(resource) =
+    let (_rest, array_pagination) = ArrayPagination::split_from_query(req.query_string())?;
+    let mut public = false;
+    let meta_tags: MetaTags = if let Ok(mut resource) =
         appstate
             .store
             .get_resource_extended(&subject, true, &ForAgent::Public)
     {
-        resource.into()
+        public = is_public(&appstate.store, &resource, &ForAgent::Public);
+        let page = array_pagination.and_then(|p| p.paginate(&mut resource).map(|page| (p, page)));
+        let mut meta_tags: MetaTags = resource.into();
+        if let Some((pagination, page)) = page {
+            if pagination.offset > 0 {
+                let prev = pagination.offset.saturating_sub(pagination.limit);
+                meta_tags.prev_page = Some(pagination.page_url(&subject, prev));
+            }
+            meta_tags.next_page = page
+                .next_offset
+                .map(|next| pagination.page_url(&subject, next));
+        }
+        meta_tags
     } else {
         MetaTags::default()
     };
 
-    let script = format!("<script>{}</script>", appstate.config.opts.script);
-    let body = template
+    // The scripts and styles of the template are trusted, so they get the nonce before anything else is added
+    let nonce = security_headers::nonce(&req);
+    let script = format!(
+        "<script nonce=\"{}\">{}</script>",
+        nonce,
+        appstate.settings.get().custom_script
+    );
+    let mut body = template
+        .replace("<script", &format!("<script nonce=\"{}\"", nonce))
+        .replace("<style", &format!("<style nonce=\"{}\"", nonce))
         .replace("<!-- { inject_html_head } -->", &meta_tags.to_string())
         .replace("<!-- { inject_script } -->", &script);
+    if let Some(banner) = meta_tags.deprecation_banner() {
+        body = insert_after_body_tag(&body, &banner);
+    }
+    // Signed in users see their pins, so the page can't be shared with others
+    if let Ok(ForAgent::AgentSubject(agent)) =
+        get_client_agent(req.headers(), &appstate, subject.clone())
+    {
+        public = false;
+        if let Some(nav) = pins_for(&appstate.store, &agent)
+            .ok()
+            .and_then(|pins| pins_nav(&pins))
+        {
+            body = insert_after_body_tag(&body, &nav);
+        }
+    }
 
     let resp = HttpResponse::Ok()
         .content_type("text/html")
-        // This prevents the browser from displaying the JSON response upon re-opening a closed tab
-        // https://github.com/atomicdata-dev/atomic-server/issues/137
+        .insert_header(("Vary", VARY))
         .insert_header((
             "Cache-Control",
-            "no-store, no-cache, must-revalidate, private",
+            CachePolicy::from_opts(&appstate.config.opts).resource(public),
         ))
         .body(body);
 
@@ -41,6 +87,8 @@ pub async fn single_page(
 }
 
 use atomic_lib::agents::ForAgent;
+use atomic_lib::plugins::deprecation::{is_deprecated, replaced_by};
+use atomic_lib::plugins::pins::{pins_for, Pins};
 use atomic_lib::urls;
 use atomic_lib::Resource;
 use atomic_lib::Storelike;
@@ -51,6 +99,11 @@ struct MetaTags {
     title: String,
     image: String,
     json: Option<String>,
+    /// Links to other pages of a paginated Resource
+    prev_page: Option<String>,
+    next_page: Option<String>,
+    deprecated: bool,
+    replaced_by: Option<String>,
 }
 
 impl From<Resource> for MetaTags {
@@ -82,6 +135,10 @@ impl From<Resource> for MetaTags {
             title,
             image,
             json,
+            prev_page: None,
+            next_page: None,
+            deprecated: is_deprecated(&r),
+            replaced_by: replaced_by(&r),
         }
     }
 }
@@ -93,6 +150,10 @@ impl Default for MetaTags {
             title: "Atomic Server".to_string(),
             image: "/default_social_preview.jpg".to_string(),
             json: None,
+            prev_page: None,
+            next_page: None,
+            deprecated: false,
+            replaced_by: None,
         }
     }
 }
@@ -114,6 +175,19 @@ impl Display for MetaTags {
 <meta property=\"twitter:description\" content=\"{description}\">
 <meta property=\"twitter:image\" content=\"{image}\">"
         )?;
+        if let Some(prev) = &self.prev_page {
+            write!(f, "\n<link rel=\"prev\" href=\"{}\">", escape_html(prev))?;
+        }
+        if let Some(next) = &self.next_page {
+            write!(f, "\n<link rel=\"next\" href=\"{}\">", escape_html(next))?;
+        }
+        if let Some(replacement) = &self.replaced_by {
+            write!(
+                f,
+                "\n<link rel=\"successor-version\" href=\"{}\">",
+                escape_html(replacement)
+            )?;
+        }
         if let Some(json_unsafe) = &self.json {
             let json_base64 = base64::encode(json_unsafe);
             write!(
@@ -126,6 +200,60 @@ impl Display for MetaTags {
     }
 }
 
+impl MetaTags {
+    /// A notice that is shown above the page of a deprecated Resource.
+    fn deprecation_banner(&self) -> Option<String> {
+        if !self.deprecated {
+            return None;
+        }
+        let link = match &self.replaced_by {
+            Some(replacement) => format!(
+                " <a href=\"{}\" style=\"color: inherit\">Go to its replacement</a>.",
+                escape_html(replacement)
+            ),
+            None => String::new(),
+        };
+        Some(format!(
+            "<div role=\"alert\" style=\"padding: 1rem; background: #fff3cd; color: #664d03; border-bottom: 1px solid #ffda6a; font-family: sans-serif; text-align: center;\">This page is deprecated.{}</div>",
+            link
+        ))
+    }
+}
+
+/// The pinned Resources of the signed in Agent, as a list of links.
+fn pins_nav(pins: &Pins) -> Option<String> {
+    if pins.pins.is_empty() {
+        return None;
+    }
+    let links: String = pins
+        .pins
+        .iter()
+        .map(|pin| {
+            format!(
+                "<li><a href=\"{}\" style=\"color: inherit\">{}</a></li>",
+                escape_html(&pin.subject),
+                escape_html(pin.name.as_deref().unwrap_or(&pin.subject))
+            )
+        })
+        .collect();
+    Some(format!(
+        "<nav aria-label=\"Pinned\" style=\"padding: 0.5rem 1rem; border-bottom: 1px solid #ddd; font-family: sans-serif;\"><ul style=\"display: flex; gap: 1rem; list-style: none; margin: 0; padding: 0;\">{}</ul></nav>",
+        links
+    ))
+}
+
+/// Inserts `html` at the start of the `<body>` of the page.
+fn insert_after_body_tag(page: &str, html: &str) -> String {
+    let Some(body_start) = page.find("<body") else {
+        return page.to_string();
+    };
+    let Some(tag_end) = page[body_start..].find('>') else {
+        return page.to_string();
+    };
+    let at = body_start + tag_end + 1;
+    format!("{}{}{}", &page[..at], html, &page[at..])
+}
+
 fn escape_html(s: &str) -> String {
     s.replace('<', "&lt;")
         .replace('>', "&gt;")
@@ -150,4 +278,39 @@ mod test {
         println!("{}", html);
         assert!(!html.contains("<script>"));
     }
+
+    #[test]
+    fn pins_nav_escapes_names() {
+        use atomic_lib::plugins::pins::{PinSummary, Pins};
+
+        let pins = Pins {
+            pins: vec![PinSummary {
+                subject: "https://example.com/a".into(),
+                name: Some("<script>alert('evil')</script>".into()),
+                class: None,
+                mimetype: None,
+            }],
+            unavailable: vec![],
+        };
+        let nav = super::pins_nav(&pins).unwrap();
+        assert!(nav.starts_with("<nav aria-label=\"Pinned\""));
+        assert!(!nav.contains("<script>"));
+        assert!(super::pins_nav(&Pins::default()).is_none());
+    }
+
+    #[test]
+    fn deprecation_banner() {
+        let tags = MetaTags {
+            deprecated: true,
+            replaced_by: Some("https://example.com/new".to_string()),
+            ..Default::default()
+        };
+        assert!(tags.to_string().contains("successor-version"));
+        let page = super::insert_after_body_tag(
+            "<html><body class=\"app\"><div id=\"root\"></div></body></html>",
+            &tags.deprecation_banner().unwrap(),
+        );
+        assert!(page.starts_with("<html><body class=\"app\"><div role=\"alert\""));
+        assert!(MetaTags::default().deprecation_banner().is_none());
+    }
 }
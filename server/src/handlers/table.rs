@@ -0,0 +1,58 @@
+use actix_web::{web, HttpResponse};
+use atomic_lib::Storelike;
+
+use crate::{
+    appstate::AppState,
+    errors::AtomicServerResult,
+    helpers::get_client_agent,
+    locale, security_headers,
+    table_view::{build_table, TableParams},
+};
+
+const TABLE_TEMPLATE: &str = include_str!("../../templates/table.html");
+
+/// Renders the children of a Resource as an HTML table, see [crate::table_view].
+/// Other clients can use the Collection that is linked on the page.
+#[tracing::instrument(skip(appstate, req))]
+pub async fn table_page(
+    appstate: web::Data<AppState>,
+    query: web::Query<TableParams>,
+    req: actix_web::HttpRequest,
+) -> HttpResponse {
+    render_table(&appstate, &query, &req).unwrap_or_else(|e| e.html_response())
+}
+
+fn render_table(
+    appstate: &AppState,
+    params: &TableParams,
+    req: &actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let store = &appstate.store;
+    let requested = format!(
+        "{}{}",
+        store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let for_agent = get_client_agent(req.headers(), appstate, requested)?;
+    let table = build_table(store, params, &for_agent)?;
+
+    let (lang, from_query) = locale::negotiate(req);
+    let mut context = tera::Context::from_serialize(&table)
+        .map_err(|e| format!("Failed to build table page: {}", e))?;
+    context.insert("cspNonce", &security_headers::nonce(req));
+    let body = locale::render(
+        "table.html",
+        TABLE_TEMPLATE,
+        &mut context,
+        lang,
+        &appstate.translations,
+    )?;
+    let mut builder = HttpResponse::Ok();
+    if from_query {
+        builder.cookie(locale::lang_cookie(lang));
+    }
+    Ok(builder.content_type("text/html").body(body))
+}
@@ -1,35 +1,72 @@
-use std::{ffi::OsStr, io::Write, path::Path};
+use std::{
+    ffi::OsStr,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 use actix_multipart::Multipart;
 use actix_web::{web, HttpResponse};
 use atomic_lib::{
-    commit::CommitResponse, hierarchy::check_write, urls, utils::now, Resource, Storelike, Value,
+    agents::ForAgent,
+    commit::CommitResponse,
+    hierarchy::check_write,
+    subjects::{self, SubjectHint},
+    urls, Resource, Storelike, Value,
 };
 use futures::{StreamExt, TryStreamExt};
 use serde::Deserialize;
+use serde_json::Value as JsonValue;
 
-use crate::{appstate::AppState, errors::AtomicServerResult, helpers::get_client_agent};
+use crate::{
+    appstate::AppState,
+    connections::{MinRate, RateCheckedPayload},
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+    helpers::get_client_agent,
+    jobs::JobType,
+    text_extraction::is_extractable,
+    upload_progress::ProgressGuard,
+};
 
 #[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct UploadQuery {
     parent: String,
+    /// Token for following the progress at `/upload-progress`, see [crate::upload_progress]
+    progress: Option<String>,
 }
 
 /// Allows the user to upload files tot the `/upload` endpoint.
 /// A parent Query parameter is required for checking rights and for placing the file in a Hierarchy.
 /// Creates new File resources for every submitted file.
 /// Submission is done using multipart/form-data.
+/// Every field must be a file (it needs a filename), or one of these text fields:
+/// - `name`, which sets the name of the file field before it.
+/// - A field with the same name as a file field, containing a JSON object with metadata for the last file with that name.
+/// - `metadata`, containing a JSON array with a metadata object for every file, in the order of the files.
+///
+/// Other fields, or a body without files, are refused with `400`, listing the problem with every field.
+/// Metadata can set the `name`, `description`, `shortname` and `mimetype` (which replaces the guessed one) of a File, and is checked against the datatypes of these Properties.
+/// A file with invalid metadata is not saved, and an Error resource takes its position in the response, so clients can match it by index.
+/// If no file could be saved, the upload is refused with `400`.
+/// Pass a `progress` token to follow the received bytes at `/upload-progress`.
 /// The file is stored in the `/uploads` directory.
+/// The subject of the File is generated using the [atomic_lib::subjects::SubjectStrategy] of the Drive of the parent.
 /// An `attachment` relationship is created from the parent
-#[tracing::instrument(skip(appstate, req, body))]
+/// Files larger than the `maxUploadSize` server setting are refused with `413`.
+/// Uploads slower than the `min_transfer_rate` option are refused with `400`.
+///
+/// The store is only used before and after streaming the files to disk.
+/// Writing to disk happens on the blocking thread pool, so slow uploads don't hold up other requests.
+/// Because rights can change during a long upload, they are checked again before the File resources are saved.
+#[tracing::instrument(skip(appstate, req, payload))]
 pub async fn upload_handler(
-    mut body: Multipart,
+    payload: web::Payload,
     appstate: web::Data<AppState>,
     query: web::Query<UploadQuery>,
     req: actix_web::HttpRequest,
 ) -> AtomicServerResult<HttpResponse> {
+    crate::replication::reject_writes(&appstate)?;
     let store = &appstate.store;
-    let parent = store.get_resource(&query.parent)?;
     let subject = format!(
         "{}{}",
         store.get_server_url(),
@@ -38,83 +75,644 @@ pub async fn upload_handler(
             .path_and_query()
             .ok_or("Path must be given")?
     );
-    let agent = get_client_agent(req.headers(), &appstate, subject)?;
-    check_write(store, &parent, &agent)?;
+    let agent = get_client_agent(req.headers(), &appstate, subject.clone())?;
+    check_write(store, &store.get_resource(&query.parent)?, &agent)?;
+    let content_length = req
+        .headers()
+        .get(actix_web::http::header::CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok()?.parse::<u64>().ok());
+    // Removed from the registry when dropped, which is also when the upload fails
+    let progress = query
+        .progress
+        .as_deref()
+        .map(|token| {
+            appstate
+                .upload_progress
+                .start(token, &agent.to_string(), content_length)
+        })
+        .transpose()?;
 
-    let mut created_resources: Vec<Resource> = Vec::new();
-    let mut commit_responses: Vec<CommitResponse> = Vec::new();
+    // Uploads that are too slow fail like a broken body, see [crate::connections]
+    let mut body = match MinRate::from_opts(&appstate.config.opts) {
+        Some(rate) => {
+            let what = format!("upload to {} by {}", query.parent, agent);
+            Multipart::new(req.headers(), RateCheckedPayload::new(payload, rate, what))
+        }
+        None => Multipart::new(req.headers(), payload),
+    };
 
-    while let Ok(Some(mut field)) = body.try_next().await {
-        let content_type = field.content_disposition().clone();
-        let filename = content_type.get_filename().ok_or("Filename is missing")?;
+    std::fs::create_dir_all(&appstate.config.uploads_path)?;
+    let mut uploaded: Vec<UploadedFile> = Vec::new();
+    let max_size = appstate.settings.get().max_upload_size;
 
-        std::fs::create_dir_all(&appstate.config.uploads_path)?;
+    let mut field_errors: Vec<FieldError> = Vec::new();
+    // The `metadata` field and its index, which is parsed once all files are known
+    let mut metadata_field: Option<(usize, String)> = None;
+    let mut index = 0;
+    loop {
+        let field = match body.try_next().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => {
+                return Err(AtomicServerError::new(
+                    format!("Invalid multipart body. {}", e),
+                    AppErrorType::BadRequest,
+                ));
+            }
+        };
+        let field_name = field
+            .content_disposition()
+            .get_name()
+            .unwrap_or_default()
+            .to_string();
+        let has_filename = field.content_disposition().get_filename().is_some();
 
-        let file_id = format!(
-            "{}-{}",
-            now(),
-            sanitize_filename::sanitize(filename)
-                // Spacebars lead to very annoying bugs in browsers
-                .replace(' ', "-")
-        );
+        if !has_filename {
+            let reason = if field_name == NAME_FIELD {
+                match uploaded.last_mut() {
+                    Some(file) if file.name.is_none() => {
+                        match read_text_field(field, MAX_NAME_LENGTH).await {
+                            Ok(name) => {
+                                file.name = Some(name);
+                                None
+                            }
+                            Err(reason) => Some(reason),
+                        }
+                    }
+                    Some(_) => Some("the file before it already has a name".into()),
+                    None => Some("must come after the file it names".into()),
+                }
+            } else if field_name == METADATA_FIELD {
+                if metadata_field.is_some() {
+                    Some("is given twice".into())
+                } else {
+                    match read_text_field(field, MAX_METADATA_LENGTH).await {
+                        Ok(json) => {
+                            metadata_field = Some((index, json));
+                            None
+                        }
+                        Err(reason) => Some(reason),
+                    }
+                }
+            } else if let Some(file) = uploaded
+                .iter_mut()
+                .rev()
+                .find(|file| file.field_name == field_name)
+            {
+                if file.metadata.is_some() {
+                    Some("the file before it already has metadata".into())
+                } else {
+                    match read_text_field(field, MAX_METADATA_LENGTH).await {
+                        Ok(json) => {
+                            file.metadata = Some(
+                                serde_json::from_str(&json)
+                                    .map_err(|e| format!("it is not valid JSON: {}", e)),
+                            );
+                            None
+                        }
+                        Err(reason) => Some(reason),
+                    }
+                }
+            } else {
+                Some("has no filename, so it is not a file".into())
+            };
+            if let Some(reason) = reason {
+                field_errors.push(FieldError {
+                    index,
+                    name: field_name,
+                    reason,
+                });
+            }
+            index += 1;
+            continue;
+        }
 
-        let mut file_path = appstate.config.uploads_path.clone();
-        file_path.push(&file_id);
-        let mut file = std::fs::File::create(file_path)?;
+        // Files that were already written are removed when `uploaded` is dropped
+        uploaded.push(
+            stream_field_to_disk(
+                field,
+                &appstate.config.uploads_path,
+                max_size,
+                progress.as_ref(),
+            )
+            .await?,
+        );
+        index += 1;
+    }
 
-        // Field in turn is stream of *Bytes* object
-        while let Some(chunk) = field.next().await {
-            let data = chunk.map_err(|e| format!("Error while reading multipart data. {}", e))?;
-            // TODO: Update a SHA256 hash here for checksum
-            file.write_all(&data)?;
+    if let Some((index, json)) = metadata_field {
+        if let Err(reason) = add_metadata_array(&mut uploaded, &json) {
+            field_errors.push(FieldError {
+                index,
+                name: METADATA_FIELD.into(),
+                reason,
+            });
         }
+    }
+
+    if uploaded.is_empty() || !field_errors.is_empty() {
+        let problem = if uploaded.is_empty() {
+            "The multipart body contains no files."
+        } else {
+            "The multipart body contains fields that are not files."
+        };
+        let details: Vec<String> = field_errors.iter().map(|e| e.to_string()).collect();
+        return Err(AtomicServerError::new(
+            format!("{} {}", problem, details.join(" "))
+                .trim()
+                .to_string(),
+            AppErrorType::BadRequest,
+        ));
+    }
+
+    // The parent might have changed while we were streaming, so we read it and check the rights again.
+    let mut parent = store.get_resource(&query.parent)?;
+    check_write(store, &parent, &agent)?;
 
-        let byte_count: i64 = file
-            .metadata()?
-            .len()
-            .try_into()
-            .map_err(|_e| "Too large")?;
+    let mut created_resources: Vec<Resource> = Vec::new();
+    // The created Files, with an Error resource at the index of every file that was refused
+    let mut response_resources: Vec<Resource> = Vec::new();
+    let mut file_errors: Vec<String> = Vec::new();
+    let mut commit_responses: Vec<CommitResponse> = Vec::new();
 
-        let subject_path = format!("files/{}", urlencoding::encode(&file_id));
-        let new_subject = format!("{}/{}", store.get_server_url(), subject_path);
-        let download_url = format!("{}/download/{}", store.get_server_url(), subject_path);
+    for (file_index, mut file) in uploaded.into_iter().enumerate() {
+        let metadata = match file.metadata.take().map(|m| metadata_propvals(store, m)) {
+            Some(Ok(propvals)) => propvals,
+            None => Vec::new(),
+            // The file is removed when it is dropped at the end of this iteration
+            Some(Err(reason)) => {
+                let message = format!(
+                    "File {} ('{}') has invalid metadata: {}.",
+                    file_index, file.filename, reason
+                );
+                response_resources.push(
+                    atomic_lib::errors::AtomicError::other_error(message.clone())
+                        .into_resource(format!("{}#file-{}", subject, file_index)),
+                );
+                file_errors.push(message);
+                continue;
+            }
+        };
+        let metadata_name = metadata
+            .iter()
+            .find(|(prop, _)| prop == urls::NAME)
+            .map(|(_, name)| name.to_string());
+        let name = metadata_name.or(file.name.clone());
+        let hint = SubjectHint {
+            parent: Some(&query.parent),
+            name: Some(name.as_deref().unwrap_or(&file.filename)),
+        };
+        let new_subject = subjects::new_subject(store, hint, || {
+            format!(
+                "{}/files/{}",
+                store.get_server_url(),
+                urlencoding::encode(&file.file_id)
+            )
+        })?;
+        // The download handler finds the File by removing `/download` from the URL
+        let download_url = new_subject.replacen(
+            store.get_server_url(),
+            &format!("{}/download", store.get_server_url()),
+            1,
+        );
 
-        let mut resource = atomic_lib::Resource::new_instance(urls::FILE, store)?;
-        resource.set_subject(new_subject);
+        let mut resource = Resource::new(new_subject);
+        resource.set_class(urls::FILE);
         resource.set_propval_string(urls::PARENT.into(), &query.parent, store)?;
-        resource.set_propval_string(urls::INTERNAL_ID.into(), &file_id, store)?;
-        resource.set_propval(urls::FILESIZE.into(), Value::Integer(byte_count), store)?;
+        resource.set_propval_string(urls::INTERNAL_ID.into(), &file.file_id, store)?;
+        resource.set_propval(
+            urls::FILESIZE.into(),
+            Value::Integer(file.byte_count),
+            store,
+        )?;
         resource.set_propval_string(
             urls::MIMETYPE.into(),
-            &guess_mime_for_filename(filename),
+            &guess_mime_for_filename(&file.filename),
             store,
         )?;
-        resource.set_propval_string(urls::FILENAME.into(), filename, store)?;
+        resource.set_propval_string(urls::FILENAME.into(), &file.filename, store)?;
+        if let Some(name) = &file.name {
+            resource.set_propval_string(urls::NAME.into(), name, store)?;
+        }
         resource.set_propval_string(urls::DOWNLOAD_URL.into(), &download_url, store)?;
+        // Metadata was checked against the Properties, and overrides the `name` field and the guessed mimetype
+        for (prop, value) in metadata {
+            resource.set_propval_unsafe(prop, value);
+        }
         commit_responses.push(resource.save(store)?);
+        file.guard.keep();
+        response_resources.push(resource.clone());
         created_resources.push(resource);
     }
 
+    if created_resources.is_empty() {
+        return Err(AtomicServerError::new(
+            format!("No file could be saved. {}", file_errors.join(" ")),
+            AppErrorType::BadRequest,
+        ));
+    }
+
+    // Add the files as `attachments` to the parent
     let created_file_subjects = created_resources
         .iter()
         .map(|r| r.get_subject().to_string())
         .collect::<Vec<String>>();
-
-    // Add the files as `attachments` to the parent
-    let mut parent = store.get_resource(&query.parent)?;
-    // parent.append_subjects(urls::ATTACHMENTS, created_file_subjects, false, store)?;
-    for created in created_file_subjects {
-        parent.push_propval(urls::ATTACHMENTS, created.into(), false)?;
-    }
+    parent.append_subjects(urls::ATTACHMENTS, created_file_subjects, false)?;
     commit_responses.push(parent.save(store)?);
+    queue_text_extraction(&appstate, &created_resources, &agent);
 
     let mut builder = HttpResponse::Ok();
 
     Ok(builder.body(atomic_lib::serialize::resources_to_json_ad(
-        &created_resources,
+        &response_resources,
     )?))
 }
 
+/// Queues an `extract-text` Job for the Files that contain searchable text, see [crate::text_extraction].
+/// The upload has succeeded already, so a Job that can't be queued is only logged.
+fn queue_text_extraction(appstate: &AppState, files: &[Resource], agent: &ForAgent) {
+    let subjects: Vec<&str> = files
+        .iter()
+        .filter(|file| {
+            file.get(urls::MIMETYPE)
+                .map(|mimetype| is_extractable(&mimetype.to_string(), &appstate.config))
+                .unwrap_or(false)
+        })
+        .map(|file| file.get_subject().as_str())
+        .collect();
+    if subjects.is_empty() {
+        return;
+    }
+    if let Err(e) = appstate.job_queue.enqueue(
+        JobType::ExtractText,
+        serde_json::json!({ "subjects": subjects }),
+        agent,
+    ) {
+        tracing::error!("Could not queue text extraction: {}", e);
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct UploadProgressQuery {
+    token: String,
+}
+
+/// Returns the bytes received so far by a running upload that was started with `?progress={token}`.
+/// Only the Agent that started the upload can see its progress.
+/// Returns `404` once the upload has finished, see [crate::upload_progress].
+#[tracing::instrument(skip(appstate, req))]
+pub async fn upload_progress(
+    appstate: web::Data<AppState>,
+    query: web::Query<UploadProgressQuery>,
+    req: actix_web::HttpRequest,
+) -> AtomicServerResult<HttpResponse> {
+    let subject = format!(
+        "{}{}",
+        appstate.store.get_server_url(),
+        req.head()
+            .uri
+            .path_and_query()
+            .ok_or("Path must be given")?
+    );
+    let agent = get_client_agent(req.headers(), &appstate, subject)?;
+    let progress = appstate
+        .upload_progress
+        .get(&query.token, &agent.to_string())?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header(("Cache-Control", "no-store"))
+        .body(serde_json::to_string(&progress).map_err(|e| e.to_string())?))
+}
+
+/// The text field that sets the name of the file field before it.
+const NAME_FIELD: &str = "name";
+/// Maximum length in bytes of the `name` field.
+const MAX_NAME_LENGTH: usize = 1024;
+/// The text field with a JSON array of metadata objects, one for every file in the order of the files.
+const METADATA_FIELD: &str = "metadata";
+/// Maximum length in bytes of a metadata field.
+const MAX_METADATA_LENGTH: usize = 64 * 1024;
+/// The keys of a metadata object, and the Properties they set on the File.
+const METADATA_KEYS: &[(&str, &str)] = &[
+    ("name", urls::NAME),
+    ("description", urls::DESCRIPTION),
+    ("shortname", urls::SHORTNAME),
+    ("mimetype", urls::MIMETYPE),
+];
+
+/// A file that has been written to the uploads directory, but has no File resource yet.
+/// The file is removed when this is dropped, unless its File resource has been saved.
+struct UploadedFile {
+    file_id: String,
+    guard: UploadGuard,
+    filename: String,
+    byte_count: i64,
+    /// The `name` of the multipart field, used to find the file for a metadata field with the same name
+    field_name: String,
+    /// Set by a `name` field, otherwise the filename is used
+    name: Option<String>,
+    /// The metadata object of the file, or why it could not be read
+    metadata: Option<Result<JsonValue, String>>,
+}
+
+/// A multipart field that could not be used.
+struct FieldError {
+    /// Position of the field in the body, starting at 0
+    index: usize,
+    /// The `name` of the field in its Content-Disposition
+    name: String,
+    reason: String,
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Field {} ('{}') {}.", self.index, self.name, self.reason)
+    }
+}
+
+/// Reads a small text field, such as `name` or `metadata`.
+async fn read_text_field(
+    mut field: actix_multipart::Field,
+    max_length: usize,
+) -> Result<String, String> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = field.next().await {
+        let data = chunk.map_err(|e| format!("could not be read: {}", e))?;
+        if bytes.len() + data.len() > max_length {
+            return Err(format!("is longer than {} bytes", max_length));
+        }
+        bytes.extend_from_slice(&data);
+    }
+    let text = String::from_utf8(bytes).map_err(|_| "is not valid UTF-8".to_string())?;
+    match text.trim() {
+        "" => Err("is empty".into()),
+        trimmed => Ok(trimmed.to_string()),
+    }
+}
+
+/// Adds the entries of the `metadata` field to the files with the same index.
+/// A file that also has its own metadata field gets an error, since it's unclear which one to use.
+fn add_metadata_array(files: &mut [UploadedFile], json: &str) -> Result<(), String> {
+    let entries: Vec<JsonValue> = serde_json::from_str(json)
+        .map_err(|e| format!("is not a JSON array of metadata objects: {}", e))?;
+    if entries.len() > files.len() {
+        return Err(format!(
+            "has {} entries, but there are only {} files",
+            entries.len(),
+            files.len()
+        ));
+    }
+    for (file, entry) in files.iter_mut().zip(entries) {
+        if entry.is_null() {
+            continue;
+        }
+        file.metadata = Some(match file.metadata {
+            Some(_) => Err(format!(
+                "it is given both in its own field and in the `{}` field",
+                METADATA_FIELD
+            )),
+            None => Ok(entry),
+        });
+    }
+    Ok(())
+}
+
+/// Checks a metadata object against the Properties of [METADATA_KEYS], and returns the Values to set on the File.
+fn metadata_propvals(
+    store: &impl Storelike,
+    metadata: Result<JsonValue, String>,
+) -> Result<Vec<(String, Value)>, String> {
+    let JsonValue::Object(object) = metadata? else {
+        return Err("it is not a JSON object".into());
+    };
+    let mut propvals = Vec::new();
+    for (key, value) in object {
+        let Some((_, prop)) = METADATA_KEYS.iter().find(|(k, _)| *k == key) else {
+            let keys: Vec<&str> = METADATA_KEYS.iter().map(|(k, _)| *k).collect();
+            return Err(format!("`{}` is not one of {}", key, keys.join(", ")));
+        };
+        let JsonValue::String(text) = value else {
+            return Err(format!("`{}` must be a string", key));
+        };
+        if *prop == urls::MIMETYPE && !text.contains('/') {
+            return Err(format!("`{}` is not a mimetype like `text/plain`", text));
+        }
+        let property = store.get_property(prop).map_err(|e| e.to_string())?;
+        let value = Value::new(&text, &property.data_type)
+            .map_err(|e| format!("`{}` is invalid. {}", key, e))?;
+        property
+            .check_value(&value)
+            .map_err(|e| format!("`{}` is invalid. {}", key, e))?;
+        propvals.push((prop.to_string(), value));
+    }
+    Ok(propvals)
+}
+
+/// Writes a multipart field to the uploads directory.
+/// Every chunk is written on the blocking thread pool, so we never block the async executor.
+/// Stops and removes the file as soon as it exceeds `max_size` bytes.
+/// Every written chunk is added to the `progress` of the upload, if it has one.
+async fn stream_field_to_disk(
+    mut field: actix_multipart::Field,
+    uploads_path: &Path,
+    max_size: Option<u64>,
+    progress: Option<&ProgressGuard>,
+) -> AtomicServerResult<UploadedFile> {
+    let field_name = field
+        .content_disposition()
+        .get_name()
+        .unwrap_or_default()
+        .to_string();
+    let filename = field
+        .content_disposition()
+        .get_filename()
+        .ok_or("Filename is missing")?
+        .to_string();
+
+    let path = uploads_path.to_path_buf();
+    let name = filename.clone();
+    let (file_id, file) = web::block(move || create_unique_file(&path, &name))
+        .await
+        .map_err(|e| format!("Could not create file. {}", e))??;
+    // Removes the partially written file on every error, and when the client aborts the upload
+    let guard = UploadGuard::new(uploads_path, &file_id);
+    // Declared after the guard, so the file is closed before the guard removes it
+    let mut file = file;
+
+    // Field in turn is stream of *Bytes* object
+    let mut written: u64 = 0;
+    while let Some(chunk) = field.next().await {
+        let data = match chunk {
+            Ok(data) => data,
+            Err(e) => {
+                return Err(AtomicServerError::new(
+                    format!("Error while reading file '{}'. {}", filename, e),
+                    AppErrorType::BadRequest,
+                ));
+            }
+        };
+        let chunk_length = data.len() as u64;
+        written += chunk_length;
+        if let Some(max) = max_size.filter(|max| written > *max) {
+            return Err(AtomicServerError::new(
+                format!(
+                    "File '{}' is larger than the maximum upload size of {} bytes",
+                    filename, max
+                ),
+                AppErrorType::PayloadTooLarge,
+            ));
+        }
+        // TODO: Update a SHA256 hash here for checksum
+        let written_file = web::block(move || file.write_all(&data).map(|_| file))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result.map_err(|e| e.to_string()));
+        file = match written_file {
+            Ok(file) => file,
+            Err(e) => {
+                return Err(format!("Could not write file. {}", e).into());
+            }
+        };
+        if let Some(progress) = progress {
+            progress.add_bytes(chunk_length);
+        }
+    }
+
+    let metadata = web::block(move || file.metadata())
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result.map_err(|e| e.to_string()));
+    let byte_count: i64 = match metadata {
+        Ok(metadata) => metadata.len().try_into().map_err(|_e| "Too large")?,
+        Err(e) => {
+            return Err(format!("Could not read file. {}", e).into());
+        }
+    };
+
+    if let Some(progress) = progress {
+        progress.file_done();
+    }
+    Ok(UploadedFile {
+        file_id,
+        guard,
+        filename,
+        byte_count,
+        field_name,
+        name: None,
+        metadata: None,
+    })
+}
+
+/// Maximum length in bytes of an internal id. Most file systems allow 255.
+const MAX_FILE_ID_LENGTH: usize = 200;
+/// Length of the random hex prefix of an internal id (128 bits).
+const FILE_ID_PREFIX_LENGTH: usize = 32;
+/// The longest extension that is preserved when a filename is truncated.
+const MAX_EXTENSION_LENGTH: usize = 16;
+
+/// Creates a new, empty file in the uploads directory.
+/// Returns the internal id, which is `<random 128 bit hex>-<sanitized filename>`.
+/// The file is created exclusively, so an existing file is never overwritten.
+pub fn create_unique_file(
+    uploads_path: &Path,
+    filename: &str,
+) -> AtomicServerResult<(String, std::fs::File)> {
+    let name = sanitize_file_name(filename);
+    // A collision of 128 random bits is practically impossible, but we check it anyway.
+    for _attempt in 0..3 {
+        let file_id = format!(
+            "{:0width$x}-{}",
+            rand::random::<u128>(),
+            name,
+            width = FILE_ID_PREFIX_LENGTH
+        );
+        let mut file_path = uploads_path.to_path_buf();
+        file_path.push(&file_id);
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&file_path)
+        {
+            Ok(file) => return Ok((file_id, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Err("Could not find an unused internal id for the file".into())
+}
+
+/// Makes a filename safe for file systems and URLs.
+/// Never returns an empty or dot-only name.
+/// Long names are truncated, but keep their extension.
+fn sanitize_file_name(filename: &str) -> String {
+    // Split off the extension before sanitizing, which truncates long names as well
+    let (stem, extension) = match filename.rfind('.') {
+        Some(i) if i > 0 && filename.len() - i <= MAX_EXTENSION_LENGTH => filename.split_at(i),
+        _ => (filename, ""),
+    };
+    let clean = |part: &str| {
+        sanitize_filename::sanitize(part)
+            // Spacebars lead to very annoying bugs in browsers
+            .replace(' ', "-")
+    };
+    let extension = match clean(extension.trim_start_matches('.')) {
+        ext if ext.trim_matches('.').is_empty() => String::new(),
+        ext => format!(".{}", ext),
+    };
+    let stem = clean(stem);
+    let stem = match stem.trim_matches('.') {
+        "" => "file",
+        trimmed => trimmed,
+    };
+    let max = MAX_FILE_ID_LENGTH - FILE_ID_PREFIX_LENGTH - 1;
+    let mut end = stem.len().min(max - extension.len());
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &stem[..end], extension)
+}
+
+/// Cleans up a file that was written, but won't get a File resource.
+/// Removes the file when dropped, unless [UploadGuard::keep] was called.
+struct UploadGuard {
+    path: Option<PathBuf>,
+}
+
+impl UploadGuard {
+    fn new(uploads_path: &Path, file_id: &str) -> Self {
+        UploadGuard {
+            path: Some(uploads_path.join(file_id)),
+        }
+    }
+
+    /// The File resource has been saved, so the file stays.
+    fn keep(mut self) {
+        self.path = None;
+    }
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        if let Some(path) = self.path.take() {
+            if let Err(e) = std::fs::remove_file(&path) {
+                tracing::warn!("Could not remove uploaded file {:?}: {}", path, e);
+            }
+        }
+    }
+}
+
+/// Returns the id of the uploaded file of a File Resource, if it has one and it is safe to use as a file name.
+/// Used to remove the file when a File is destroyed permanently, see [crate::side_effects].
+pub fn file_blob_id(resource: &Resource) -> Option<String> {
+    let file_id = resource.get(urls::INTERNAL_ID).ok()?.to_string();
+    if !crate::handlers::download::is_safe_file_id(&file_id) {
+        tracing::warn!("Not removing file with unsafe internal id {}", file_id);
+        return None;
+    }
+    Some(file_id)
+}
+
 fn guess_mime_for_filename(filename: &str) -> String {
     if let Some(ext) = get_extension_from_filename(filename) {
         actix_files::file_extension_to_mime(ext).to_string()
@@ -126,3 +724,32 @@ fn guess_mime_for_filename(filename: &str) -> String {
 fn get_extension_from_filename(filename: &str) -> Option<&str> {
     Path::new(filename).extension().and_then(OsStr::to_str)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sanitizes_file_names() {
+        assert_eq!(sanitize_file_name("my report.pdf"), "my-report.pdf");
+        assert_eq!(sanitize_file_name(".."), "file");
+        assert_eq!(sanitize_file_name("///"), "file");
+        assert_eq!(sanitize_file_name(".hidden"), "hidden");
+
+        let long = format!("{}.pdf", "é".repeat(300));
+        let sanitized = sanitize_file_name(&long);
+        assert!(sanitized.len() + FILE_ID_PREFIX_LENGTH + 1 <= MAX_FILE_ID_LENGTH);
+        assert!(sanitized.ends_with(".pdf"));
+    }
+
+    #[test]
+    fn creates_unique_files() {
+        let dir = format!("./.temp/{}/uploads", atomic_lib::utils::random_string(10));
+        let dir = Path::new(&dir);
+        std::fs::create_dir_all(dir).unwrap();
+        let (first, _) = create_unique_file(dir, "report.pdf").unwrap();
+        let (second, _) = create_unique_file(dir, "report.pdf").unwrap();
+        assert_ne!(first, second);
+        assert!(first.ends_with("-report.pdf"));
+    }
+}
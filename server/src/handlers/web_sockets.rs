@@ -3,23 +3,37 @@
 
 For every Connection to `/ws`, the [web_socket_handler] creates a [WebSocketConnection].
 This keeps track of the Agent and handles messages.
+Presence (`PRESENCE` and `WHO`) is kept by the [CommitMonitor], see [crate::presence].
+Connections of signed in Agents receive their new Notifications as `NOTIFICATION` messages.
+Subscriptions with `durable=true` are stored, and restored when the Agent connects again, see [crate::durable_subscriptions].
 
 For information about the protocol, see https://docs.atomicdata.dev/websockets.html
  */
-use actix::{Actor, ActorContext, Addr, AsyncContext, Handler, StreamHandler};
+use actix::{Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Handler, StreamHandler};
 use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
 use atomic_lib::{
     agents::ForAgent,
     authentication::{get_agent_from_auth_values_and_check, AuthValues},
     errors::AtomicResult,
-    Db, Storelike,
+    urls, Db, Storelike, Value,
+};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
 };
-use std::time::{Duration, Instant};
 
 use crate::{
-    actor_messages::CommitMessage, appstate::AppState, commit_monitor::CommitMonitor,
-    errors::AtomicServerResult, helpers::get_auth_headers,
+    actor_messages::{
+        Acknowledge, CommitMessage, Connect, Disconnect, SetPresence, Unsubscribe, WhoIsPresent,
+        WsMessage,
+    },
+    appstate::AppState,
+    commit_monitor::CommitMonitor,
+    durable_subscriptions::DurableOptions,
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+    handlers::commit::apply_incoming_commit,
+    helpers::get_auth_headers,
 };
 
 /// Get an HTTP request, upgrade it to a Websocket connection
@@ -43,6 +57,7 @@ pub async fn web_socket_handler(
             for_agent,
             // We need to make sure this is easily clone-able
             appstate.store.clone(),
+            appstate.clone(),
         ),
         &req,
         stream,
@@ -52,6 +67,9 @@ pub async fn web_socket_handler(
 
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
 const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Maximum amount of `COMMIT` messages that wait to be applied for a single connection.
+/// Clients that send more receive a `429` error for the extra Commits.
+const MAX_PENDING_COMMITS: usize = 32;
 
 pub struct WebSocketConnection {
     /// Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT),
@@ -65,6 +83,17 @@ pub struct WebSocketConnection {
     /// If it's not specified, it's the Public Agent.
     agent: ForAgent,
     store: Db,
+    appstate: web::Data<AppState>,
+    /// `COMMIT` messages that are waiting to be applied, in the order they were received.
+    pending_commits: VecDeque<PendingCommit>,
+    /// Whether a Commit of this connection is currently being applied.
+    applying_commit: bool,
+}
+
+struct PendingCommit {
+    /// Client-supplied id, used to correlate the response
+    id: String,
+    body: String,
 }
 
 impl Actor for WebSocketConnection {
@@ -72,6 +101,13 @@ impl Actor for WebSocketConnection {
 
     fn started(&mut self, ctx: &mut Self::Context) {
         self.hb(ctx);
+        self.connect(ctx);
+    }
+
+    fn stopped(&mut self, ctx: &mut Self::Context) {
+        self.commit_monitor_addr.do_send(Disconnect {
+            addr: ctx.address(),
+        });
     }
 }
 
@@ -106,29 +142,60 @@ fn handle_ws_message(
             tracing::debug!("Incoming websocket text message: {:?}", text);
             match text.as_str() {
                 s if s.starts_with("SUBSCRIBE ") => {
-                    let mut parts = s.split("SUBSCRIBE ");
-                    if let Some(subject) = parts.nth(1) {
-                        conn.commit_monitor_addr
-                            .do_send(crate::actor_messages::Subscribe {
-                                addr: ctx.address(),
-                                subject: subject.to_string(),
-                                agent: conn.agent.to_string(),
-                            });
-                        conn.subscribed.insert(subject.into());
-                        Ok(())
-                    } else {
-                        Err("SUBSCRIBE needs a subject".into())
+                    // Subjects can't contain spaces, so the rest are options like `durable=true`
+                    let mut parts = s["SUBSCRIBE ".len()..].split(' ');
+                    match parts.next() {
+                        Some(subject) if !subject.is_empty() => {
+                            let durable = DurableOptions::parse(parts).map_err(|e| e.message)?;
+                            if durable.is_some() && !matches!(conn.agent, ForAgent::AgentSubject(_))
+                            {
+                                return Err(
+                                    "Durable subscriptions need an authenticated Agent".into()
+                                );
+                            }
+                            conn.commit_monitor_addr
+                                .do_send(crate::actor_messages::Subscribe {
+                                    addr: ctx.address(),
+                                    subject: subject.to_string(),
+                                    agent: conn.agent.to_string(),
+                                    durable,
+                                });
+                            conn.subscribed.insert(subject.into());
+                            Ok(())
+                        }
+                        _ => Err("SUBSCRIBE needs a subject".into()),
                     }
                 }
                 s if s.starts_with("UNSUBSCRIBE ") => {
                     let mut parts = s.split("UNSUBSCRIBE ");
                     if let Some(subject) = parts.nth(1) {
                         conn.subscribed.remove(subject);
+                        conn.commit_monitor_addr.do_send(Unsubscribe {
+                            addr: ctx.address(),
+                            subject: subject.into(),
+                            agent: conn.agent.to_string(),
+                        });
                         Ok(())
                     } else {
                         Err("UNSUBSCRIBE needs a subject".into())
                     }
                 }
+                s if s.starts_with("ACK ") => {
+                    let ForAgent::AgentSubject(agent) = &conn.agent else {
+                        return Err("ACK needs an authenticated Agent".into());
+                    };
+                    match s.split("ACK ").nth(1) {
+                        Some(commit) if !commit.is_empty() => {
+                            conn.commit_monitor_addr.do_send(Acknowledge {
+                                addr: ctx.address(),
+                                commit: commit.into(),
+                                agent: agent.clone(),
+                            });
+                            Ok(())
+                        }
+                        _ => Err("ACK needs the subject of a Commit".into()),
+                    }
+                }
                 s if s.starts_with("GET ") => {
                     let mut parts = s.split("GET ");
                     if let Some(subject) = parts.nth(1) {
@@ -154,6 +221,47 @@ fn handle_ws_message(
                         Err("GET needs a subject".into())
                     }
                 }
+                s if s.starts_with("COMMIT ") => {
+                    let mut parts = s.splitn(3, ' ').skip(1);
+                    match (parts.next(), parts.next()) {
+                        (Some(id), Some(body)) if !id.is_empty() => {
+                            conn.queue_commit(id.into(), body.into(), ctx);
+                            Ok(())
+                        }
+                        _ => Err("COMMIT needs a message id and a JSON-AD Commit".into()),
+                    }
+                }
+                s if s.starts_with("PRESENCE ") => {
+                    let mut parts = s.splitn(3, ' ').skip(1);
+                    match (parts.next(), parts.next()) {
+                        (Some(subject), Some(state)) if !subject.is_empty() => {
+                            let ForAgent::AgentSubject(agent) = &conn.agent else {
+                                return Err("PRESENCE needs an authenticated Agent".into());
+                            };
+                            conn.commit_monitor_addr.do_send(SetPresence {
+                                addr: ctx.address(),
+                                subject: subject.into(),
+                                agent: agent.clone(),
+                                state: state.parse()?,
+                            });
+                            Ok(())
+                        }
+                        _ => Err("PRESENCE needs a subject and a state".into()),
+                    }
+                }
+                s if s.starts_with("WHO ") => {
+                    let mut parts = s.split("WHO ");
+                    if let Some(subject) = parts.nth(1) {
+                        conn.commit_monitor_addr.do_send(WhoIsPresent {
+                            addr: ctx.address(),
+                            subject: subject.into(),
+                            agent: conn.agent.to_string(),
+                        });
+                        Ok(())
+                    } else {
+                        Err("WHO needs a subject".into())
+                    }
+                }
                 s if s.starts_with("AUTHENTICATE ") => {
                     let mut parts = s.split("AUTHENTICATE ");
                     if let Some(json) = parts.nth(1) {
@@ -171,6 +279,7 @@ fn handle_ws_message(
                             Ok(a) => {
                                 tracing::debug!("Authenticated websocket for {}", a);
                                 conn.agent = a;
+                                conn.connect(ctx);
                                 Ok(())
                             }
                             Err(e) => Err(format!("Authentication failed: {}", e).into()),
@@ -199,7 +308,12 @@ fn handle_ws_message(
 }
 
 impl WebSocketConnection {
-    fn new(commit_monitor_addr: Addr<CommitMonitor>, agent: ForAgent, store: Db) -> Self {
+    fn new(
+        commit_monitor_addr: Addr<CommitMonitor>,
+        agent: ForAgent,
+        store: Db,
+        appstate: web::Data<AppState>,
+    ) -> Self {
         let size = std::mem::size_of::<Db>();
         if size > 10000 {
             tracing::warn!(
@@ -215,7 +329,64 @@ impl WebSocketConnection {
             commit_monitor_addr,
             agent,
             store,
+            appstate,
+            pending_commits: VecDeque::new(),
+            applying_commit: false,
+        }
+    }
+
+    /// Lets the CommitMonitor send the Notifications of the signed in Agent to this connection.
+    fn connect(&self, ctx: &mut <Self as Actor>::Context) {
+        if let ForAgent::AgentSubject(agent) = &self.agent {
+            self.commit_monitor_addr.do_send(Connect {
+                addr: ctx.address(),
+                agent: agent.clone(),
+            });
+        }
+    }
+
+    /// Adds a Commit to the queue of this connection.
+    /// Commits are applied one at a time, so they are applied (and answered) in the order they were sent.
+    fn queue_commit(&mut self, id: String, body: String, ctx: &mut <Self as Actor>::Context) {
+        if self.pending_commits.len() >= MAX_PENDING_COMMITS {
+            let err = AtomicServerError::new(
+                format!(
+                    "Too many Commits are waiting to be applied for this connection (max {}). Wait for a response before sending more.",
+                    MAX_PENDING_COMMITS
+                ),
+                AppErrorType::TooManyRequests,
+            );
+            ctx.text(commit_error_message(&id, &err));
+            return;
+        }
+        self.pending_commits.push_back(PendingCommit { id, body });
+        self.apply_next_commit(ctx);
+    }
+
+    /// Applies the oldest pending Commit on a blocking thread, and continues with the next one when it's done.
+    fn apply_next_commit(&mut self, ctx: &mut <Self as Actor>::Context) {
+        if self.applying_commit {
+            return;
         }
+        let Some(PendingCommit { id, body }) = self.pending_commits.pop_front() else {
+            return;
+        };
+        self.applying_commit = true;
+        let appstate = self.appstate.clone();
+        let applying = web::block(move || apply_incoming_commit(&appstate, &body));
+        let applying = actix::fut::wrap_future::<_, Self>(applying).map(move |result, act, ctx| {
+            let message = match result {
+                Ok(Ok(response)) => commit_response_message(&id, response),
+                Ok(Err(e)) => commit_error_message(&id, &e),
+                Err(e) => {
+                    commit_error_message(&id, &AtomicServerError::from(actix_web::Error::from(e)))
+                }
+            };
+            ctx.text(message);
+            act.applying_commit = false;
+            act.apply_next_commit(ctx);
+        });
+        ctx.spawn(applying);
     }
 
     /// Sends ping to client every second. If there is no response, the Actor is stopped.
@@ -240,12 +411,44 @@ impl WebSocketConnection {
     }
 }
 
+/// `COMMIT_RESPONSE ${id} ${CommitResource}`, sent when a Commit from the client is applied.
+fn commit_response_message(id: &str, response: atomic_lib::commit::CommitResponse) -> String {
+    match response.echoed_commit_resource().to_json_ad() {
+        Ok(json) => format!("COMMIT_RESPONSE {id} {json}"),
+        Err(e) => commit_error_message(id, &e.into()),
+    }
+}
+
+/// `COMMIT_ERROR ${id} ${ErrorBody}`, where the body contains the HTTP status code that the `/commit` endpoint would return.
+fn commit_error_message(id: &str, err: &AtomicServerError) -> String {
+    use actix_web::ResponseError;
+    let body = serde_json::json!({
+        "status": err.status_code().as_u16(),
+        "message": err.message,
+    });
+    format!("COMMIT_ERROR {id} {body}")
+}
+
+impl Handler<WsMessage> for WebSocketConnection {
+    type Result = ();
+
+    fn handle(&mut self, msg: WsMessage, ctx: &mut ws::WebsocketContext<Self>) {
+        ctx.text(msg.0);
+    }
+}
+
 impl Handler<CommitMessage> for WebSocketConnection {
     type Result = ();
 
     #[tracing::instrument(name = "handle_commit", skip_all)]
     fn handle(&mut self, msg: CommitMessage, ctx: &mut ws::WebsocketContext<Self>) {
-        let resource = msg.commit_response.commit_resource;
+        let mut resource = msg.commit_response.echoed_commit_resource();
+        if let Some(sequence) = msg.sequence {
+            resource.set_propval_unsafe(
+                urls::COMMIT_SEQUENCE.into(),
+                Value::Integer(sequence as i64),
+            );
+        }
         let formatted_commit = format!("COMMIT {}", resource.to_json_ad().unwrap());
         ctx.text(formatted_commit);
     }
@@ -170,12 +170,22 @@ pub fn get_client_agent(
         return Ok(ForAgent::Public);
     }
     // Authentication check. If the user has no headers, continue with the Public Agent.
-    let auth_header_values = get_auth(headers, requested_subject)?;
+    let auth_header_values = get_auth(headers, requested_subject.clone())?;
     let for_agent = atomic_lib::authentication::get_agent_from_auth_values_and_check(
         auth_header_values,
         &appstate.store,
     )
-    .map_err(|e| format!("Authentication failed: {}", e))?;
+    .map_err(|e| {
+        // Suspended Agents get a 401
+        let error_type = match e.error_type {
+            atomic_lib::AtomicErrorType::UnauthorizedError => AppErrorType::Unauthorized,
+            _ => AppErrorType::Other,
+        };
+        AtomicServerError::new(format!("Authentication failed: {}", e), error_type)
+    })?;
+    appstate
+        .agent_activity
+        .record_read(&for_agent, &requested_subject);
     Ok(for_agent)
 }
 
@@ -190,6 +200,8 @@ pub fn try_extension(path: &str) -> Option<(ContentType, &str)> {
             "jsonad" => ContentType::JsonAd,
             "html" => ContentType::Html,
             "ttl" => ContentType::Turtle,
+            "nt" => ContentType::NTriples,
+            "nq" => ContentType::NQuads,
             _ => return None,
         };
         return Some((content_type, path));
@@ -197,6 +209,159 @@ pub fn try_extension(path: &str) -> Option<(ContentType, &str)> {
     None
 }
 
+/// Used when only one of `array_limit` and `array_offset` is passed.
+pub const DEFAULT_ARRAY_LIMIT: usize = 100;
+
+/// Parses the value of `array_limit` or `array_offset`. Invalid values are refused with `400`.
+fn parse_array_param(name: &str, val: &str) -> AtomicServerResult<usize> {
+    val.parse::<usize>().map_err(|e| {
+        AtomicServerError::new(
+            format!("Invalid {} '{}': {}", name, val, e),
+            AppErrorType::BadRequest,
+        )
+    })
+}
+
+/// A window into the ResourceArrays of a Resource, set using the `array_limit` and `array_offset` query parameters.
+/// Prevents huge responses for Resources with many children or attachments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArrayPagination {
+    pub limit: usize,
+    pub offset: usize,
+}
+
+/// The result of applying [ArrayPagination] to a Resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArrayPage {
+    /// Length of the longest array, before truncating.
+    pub total_length: usize,
+    /// Offset for the next page, if any array has more items.
+    pub next_offset: Option<usize>,
+}
+
+impl ArrayPagination {
+    /// Removes the `array_limit` and `array_offset` parameters from a query string, as these are not part of the Subject.
+    /// Returns the remaining query string and the requested pagination, if any.
+    pub fn split_from_query(query: &str) -> AtomicServerResult<(String, Option<ArrayPagination>)> {
+        let mut limit = None;
+        let mut offset = None;
+        let mut rest = Vec::new();
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            match pair.split_once('=') {
+                Some(("array_limit", val)) => limit = Some(parse_array_param("array_limit", val)?),
+                Some(("array_offset", val)) => {
+                    offset = Some(parse_array_param("array_offset", val)?)
+                }
+                _ => rest.push(pair),
+            }
+        }
+        let pagination = if limit.is_none() && offset.is_none() {
+            None
+        } else {
+            let pagination = ArrayPagination {
+                limit: limit.unwrap_or(DEFAULT_ARRAY_LIMIT),
+                offset: offset.unwrap_or(0),
+            };
+            // The offset of the next page must be representable
+            if pagination.offset.checked_add(pagination.limit).is_none() {
+                return Err(AtomicServerError::new(
+                    "array_offset plus array_limit is too large".into(),
+                    AppErrorType::BadRequest,
+                ));
+            }
+            Some(pagination)
+        };
+        Ok((rest.join("&"), pagination))
+    }
+
+    /// Truncates the ResourceArrays in the Resource. Returns None if nothing was truncated.
+    pub fn paginate(&self, resource: &mut atomic_lib::Resource) -> Option<ArrayPage> {
+        let total_length = resource
+            .paginate_arrays(self.offset, self.limit)
+            .into_values()
+            .max()?;
+        let next = self.offset.saturating_add(self.limit);
+        Some(ArrayPage {
+            total_length,
+            next_offset: if next < total_length {
+                Some(next)
+            } else {
+                None
+            },
+        })
+    }
+
+    /// The URL for the page at `offset` of the Resource with this Subject.
+    pub fn page_url(&self, subject: &str, offset: usize) -> String {
+        let separator = if subject.contains('?') { '&' } else { '?' };
+        format!(
+            "{}{}array_limit={}&array_offset={}",
+            subject, separator, self.limit, offset
+        )
+    }
+}
+
+/// Removes the `fields` parameter from a query string, as it is not part of the Subject.
+/// Returns the remaining query string and the comma separated fields, if any.
+/// See [atomic_lib::Resource::select_fields].
+pub fn split_fields_from_query(query: &str) -> AtomicServerResult<(String, Option<Vec<String>>)> {
+    let mut fields = None;
+    let mut rest = Vec::new();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        match pair.split_once('=') {
+            Some(("fields", val)) => {
+                let decoded = urlencoding::decode(val)
+                    .map_err(|e| format!("Invalid fields '{}': {}", val, e))?;
+                fields = Some(
+                    decoded
+                        .split(',')
+                        .map(|f| f.trim().to_string())
+                        .filter(|f| !f.is_empty())
+                        .collect(),
+                )
+            }
+            _ => rest.push(pair),
+        }
+    }
+    Ok((rest.join("&"), fields))
+}
+
+/// Removes the `explain` parameter from a query string, as it is not part of the Subject.
+/// Returns the remaining query string, and whether authorization errors should explain themselves, see [crate::handlers::rights::explain_denial].
+pub fn split_explain_from_query(query: &str) -> (String, bool) {
+    let mut explain = false;
+    let mut rest = Vec::new();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        match pair.split_once('=') {
+            Some(("explain", val)) => explain = val == "true",
+            _ => rest.push(pair),
+        }
+    }
+    (rest.join("&"), explain)
+}
+
+/// Removes the `truncate_values` parameter from a query string, as it is not part of the Subject.
+/// Returns the remaining query string and the maximum value length in bytes, if any.
+/// See [atomic_lib::Resource::truncate_values].
+pub fn split_truncate_values_from_query(
+    query: &str,
+) -> AtomicServerResult<(String, Option<usize>)> {
+    let mut max = None;
+    let mut rest = Vec::new();
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        match pair.split_once('=') {
+            Some(("truncate_values", val)) => {
+                max = Some(
+                    val.parse()
+                        .map_err(|e| format!("Invalid truncate_values '{}': {}", val, e))?,
+                )
+            }
+            _ => rest.push(pair),
+        }
+    }
+    Ok((rest.join("&"), max))
+}
+
 fn session_cookies_from_header(header: &HeaderValue) -> AtomicServerResult<Vec<String>> {
     let cookies: Vec<&str> = header
         .to_str()
@@ -225,6 +390,61 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn split_array_pagination() {
+        let (rest, pagination) =
+            ArrayPagination::split_from_query("page_size=3&array_limit=10&array_offset=20")
+                .unwrap();
+        assert_eq!(rest, "page_size=3");
+        assert_eq!(
+            pagination,
+            Some(ArrayPagination {
+                limit: 10,
+                offset: 20
+            })
+        );
+
+        let (rest, pagination) = ArrayPagination::split_from_query("page_size=3").unwrap();
+        assert_eq!(rest, "page_size=3");
+        assert_eq!(pagination, None);
+
+        ArrayPagination::split_from_query("array_limit=many").unwrap_err();
+
+        // The next offset would overflow
+        let query = format!("array_limit=10&array_offset={}", usize::MAX);
+        let err = ArrayPagination::split_from_query(&query).unwrap_err();
+        assert!(matches!(err.error_type, AppErrorType::BadRequest));
+    }
+
+    #[test]
+    fn split_fields() {
+        let (rest, fields) = split_fields_from_query(
+            "include_nested=true&fields=shortname,%20members.name,https%3A%2F%2Fatomicdata.dev%2Fproperties%2Fparent",
+        )
+        .unwrap();
+        assert_eq!(rest, "include_nested=true");
+        assert_eq!(
+            fields.unwrap(),
+            vec![
+                "shortname",
+                "members.name",
+                "https://atomicdata.dev/properties/parent"
+            ]
+        );
+
+        let (rest, fields) = split_fields_from_query("page_size=3").unwrap();
+        assert_eq!(rest, "page_size=3");
+        assert_eq!(fields, None);
+    }
+
+    #[test]
+    fn split_explain() {
+        let (rest, explain) = split_explain_from_query("page_size=3&explain=true");
+        assert_eq!(rest, "page_size=3");
+        assert!(explain);
+        assert!(!split_explain_from_query("page_size=3").1);
+    }
+
     #[test]
     fn parse_cookie() {
         let cookie = "atomic_session=eyJodHRwczovL2F0b21pY2RhdGEuZGV2L3Byb3BlcnRpZXMvYXV0aC9hZ2VudCI6Imh0dHA6Ly9sb2NhbGhvc3Q6OTg4My9hZ2VudHMvaGVua2llcGVuayIsImh0dHBzOi8vYXRvbWljZGF0YS5kZXYvcHJvcGVydGllcy9hdXRoL3JlcXVlc3RlZFN1YmplY3QiOiJodHRwOi8vbG9jYWxob3N0Ojk4ODMiLCJodHRwczovL2F0b21pY2RhdGEuZGV2L3Byb3BlcnRpZXMvYXV0aC9wdWJsaWNLZXkiOiJLM3hsa0UxQmFIVXNnRzlYT0h4MVZaVUQ1TGs3ODJua09UcDVHNFN0SDdBPSIsImh0dHBzOi8vYXRvbWljZGF0YS5kZXYvcHJvcGVydGllcy9hdXRoL3RpbWVzdGFtcCI6MTY3NjI4MTU1NjEyNCwiaHR0cHM6Ly9hdG9taWNkYXRhLmRldi9wcm9wZXJ0aWVzL2F1dGgvc2lnbmF0dXJlIjoiMlprdFFWNTNkMVhNUWp4YklSN1pYRkhCMExGT2hHcVlpVlEyRENWc3BkZHVuL3ZHRkhJN3lqdU5jRitIMmpLa0Y0L0R4amEraHdTeUJlZ2ZvTWlxQ1E9PSJ9";
@@ -0,0 +1,414 @@
+//! Exports a Resource as a single HTML document that works without the server, for archiving or sending by email. Served at `/export-html`.
+//! CSS is inlined, Markdown is rendered, relative links become absolute URLs, and small images of local Files are embedded as data URIs.
+//! With a `depth`, the readable children are exported too (depth first, sorted by title), preceded by a table of contents.
+//!
+//! The output only depends on the state of the store: the footer shows the time of the latest Commit of the exported Resources instead of the time of the request,
+//! so exports of the same state are identical and can be diffed.
+
+use std::collections::{HashMap, HashSet};
+
+use atomic_lib::{
+    agents::ForAgent, hierarchy::check_read, storelike::Query, urls, Resource, Storelike, Value,
+};
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, errors::AtomicServerResult, table_view::title_of};
+
+/// The deepest level of children that can be exported.
+pub const MAX_DEPTH: usize = 5;
+/// Exports stop after this many Resources.
+pub const MAX_RESOURCES: usize = 500;
+/// Larger images are linked instead of embedded.
+pub const MAX_EMBEDDED_IMAGE_SIZE: u64 = 256 * 1024;
+/// The total size of the embedded images of one export. Images after that are linked.
+const MAX_EMBEDDED_TOTAL: u64 = 10 * 1024 * 1024;
+/// Shown in their own part of a section, or not at all.
+const HIDDEN_PROPERTIES: [&str; 10] = [
+    urls::IS_A,
+    urls::PARENT,
+    urls::LAST_COMMIT,
+    urls::READ,
+    urls::WRITE,
+    urls::NAME,
+    urls::DESCRIPTION,
+    urls::INTERNAL_ID,
+    urls::EXTRACTED_TEXT,
+    urls::EXTRACTED_CHECKSUM,
+];
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ExportParams {
+    pub subject: String,
+    /// How many levels of children to include. `0` exports only the Resource itself.
+    #[serde(default)]
+    pub depth: usize,
+    /// The language of the export. Without it, it is negotiated using [crate::locale::negotiate].
+    pub lang: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportDocument {
+    pub subject: String,
+    pub title: String,
+    pub sections: Vec<Section>,
+    /// Whether [MAX_RESOURCES] was reached, so some children are missing
+    pub truncated: bool,
+    /// The latest Commit of all exported Resources
+    pub last_commit: Option<String>,
+    /// The `createdAt` of [ExportDocument::last_commit], used as the time of the export
+    pub last_commit_at: Option<i64>,
+}
+
+/// One exported Resource.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Section {
+    pub subject: String,
+    pub title: String,
+    /// The `id` of the section, for the table of contents
+    pub anchor: String,
+    /// `0` for the exported Resource, `1` for its children, etc.
+    pub level: usize,
+    pub class: Option<String>,
+    pub class_shortname: Option<String>,
+    /// Rendered Markdown
+    pub description: Option<String>,
+    /// A data URI or the download URL, for image Files
+    pub image: Option<String>,
+    pub fields: Vec<Field>,
+    pub last_commit: Option<String>,
+}
+
+/// A value formatted for its datatype. The template uses `kind` to pick a format.
+#[derive(Serialize, Debug)]
+pub struct Field {
+    pub property: String,
+    pub shortname: String,
+    /// `html`, `text`, `number`, `timestamp`, `date`, `boolean` or `links`
+    pub kind: &'static str,
+    pub value: serde_json::Value,
+    pub links: Vec<Link>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Link {
+    pub href: String,
+    pub title: String,
+}
+
+/// Collects the Resources to export and formats their values. Only Resources that `for_agent` can read are included.
+pub fn build_export(
+    store: &impl Storelike,
+    config: &Config,
+    params: &ExportParams,
+    for_agent: &ForAgent,
+) -> AtomicServerResult<ExportDocument> {
+    let root = store.get_resource(&params.subject)?;
+    check_read(store, &root, for_agent)?;
+
+    let mut exporter = Exporter {
+        store,
+        config,
+        for_agent,
+        max_depth: params.depth.min(MAX_DEPTH),
+        sections: Vec::new(),
+        visited: HashSet::new(),
+        truncated: false,
+        images: HashMap::new(),
+        embedded_total: 0,
+    };
+    exporter.add(&root, 0)?;
+
+    // Ties are broken by the subject, so the same state always gives the same Commit
+    let (last_commit, last_commit_at) = exporter
+        .sections
+        .iter()
+        .filter_map(|section| section.last_commit.as_ref())
+        .filter_map(|commit| {
+            let created_at = match store.get_resource(commit).ok()?.get(urls::CREATED_AT) {
+                Ok(Value::Timestamp(t)) => *t,
+                _ => return None,
+            };
+            Some((created_at, commit.clone()))
+        })
+        .max()
+        .map(|(at, commit)| (Some(commit), Some(at)))
+        .unwrap_or((None, None));
+
+    Ok(ExportDocument {
+        subject: root.get_subject().clone(),
+        title: title_of(&root),
+        sections: exporter.sections,
+        truncated: exporter.truncated,
+        last_commit,
+        last_commit_at,
+    })
+}
+
+struct Exporter<'a, S: Storelike> {
+    store: &'a S,
+    config: &'a Config,
+    for_agent: &'a ForAgent,
+    max_depth: usize,
+    sections: Vec<Section>,
+    /// Prevents exporting a Resource twice, should the hierarchy contain a cycle
+    visited: HashSet<String>,
+    truncated: bool,
+    /// The `src` of every image URL that was seen, so an image is read once
+    images: HashMap<String, String>,
+    embedded_total: u64,
+}
+
+impl<S: Storelike> Exporter<'_, S> {
+    fn add(&mut self, resource: &Resource, level: usize) -> AtomicServerResult<()> {
+        let subject = resource.get_subject().clone();
+        if !self.visited.insert(subject.clone()) {
+            return Ok(());
+        }
+        if self.sections.len() >= MAX_RESOURCES {
+            self.truncated = true;
+            return Ok(());
+        }
+        let section = self.section(resource, level);
+        self.sections.push(section);
+        if level >= self.max_depth {
+            return Ok(());
+        }
+
+        let mut query = Query::new_prop_val(urls::PARENT, &subject);
+        query.include_nested = false;
+        query.for_agent = self.for_agent.clone();
+        let mut children = self.store.query(&query)?.resources;
+        children.sort_by_cached_key(|child| {
+            (title_of(child).to_lowercase(), child.get_subject().clone())
+        });
+        for child in children {
+            self.add(&child, level + 1)?;
+        }
+        Ok(())
+    }
+
+    fn section(&mut self, resource: &Resource, level: usize) -> Section {
+        let subject = resource.get_subject();
+        let class = resource
+            .get_main_class()
+            .ok()
+            .and_then(|class| self.store.get_class(&class).ok());
+
+        // The Properties of the Class come first, in the order of the Class
+        let mut properties: Vec<String> = class
+            .iter()
+            .flat_map(|class| class.requires.iter().chain(class.recommends.iter()))
+            .filter(|prop| resource.get(prop).is_ok())
+            .cloned()
+            .collect();
+        let mut other: Vec<String> = resource
+            .get_propvals()
+            .keys()
+            .filter(|prop| !properties.contains(prop))
+            .cloned()
+            .collect();
+        other.sort();
+        properties.extend(other);
+        properties.retain(|prop| !HIDDEN_PROPERTIES.contains(&prop.as_str()));
+
+        let fields = properties
+            .iter()
+            .filter_map(|prop| {
+                let value = resource.get(prop).ok()?;
+                Some(self.field(subject, prop, value))
+            })
+            .collect();
+
+        let image = match (
+            resource.get(urls::MIMETYPE),
+            resource.get(urls::DOWNLOAD_URL),
+        ) {
+            (Ok(Value::String(mimetype)), Ok(url)) if mimetype.starts_with("image/") => {
+                Some(self.image_src(&url.to_string()))
+            }
+            _ => None,
+        };
+
+        Section {
+            subject: subject.clone(),
+            title: title_of(resource),
+            anchor: format!("section-{}", self.sections.len()),
+            level,
+            class: class.as_ref().map(|class| class.subject.clone()),
+            class_shortname: class.map(|class| class.shortname),
+            description: resource
+                .get(urls::DESCRIPTION)
+                .ok()
+                .map(|description| self.markdown(subject, &description.to_string())),
+            image,
+            fields,
+            last_commit: resource.get(urls::LAST_COMMIT).ok().map(|c| c.to_string()),
+        }
+    }
+
+    fn field(&mut self, subject: &str, property: &str, value: &Value) -> Field {
+        let shortname = self
+            .store
+            .get_property(property)
+            .map(|p| p.shortname)
+            .unwrap_or_else(|_| property.to_string());
+        let (kind, value, links) = match value {
+            Value::Markdown(markdown) => ("html", self.markdown(subject, markdown).into(), vec![]),
+            Value::Integer(i) => ("number", (*i).into(), vec![]),
+            Value::Float(f) => ("number", (*f).into(), vec![]),
+            Value::Timestamp(t) => ("timestamp", (*t).into(), vec![]),
+            Value::Date(d) => ("date", d.clone().into(), vec![]),
+            Value::Boolean(b) => ("boolean", (*b).into(), vec![]),
+            Value::AtomicUrl(url) => ("links", serde_json::Value::Null, vec![self.link(url)]),
+            Value::ResourceArray(_) => {
+                let links = value
+                    .to_subjects(None)
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|url| self.link(url))
+                    .collect();
+                ("links", serde_json::Value::Null, links)
+            }
+            other => ("text", other.to_string().into(), vec![]),
+        };
+        Field {
+            property: property.into(),
+            shortname,
+            kind,
+            value,
+            links,
+        }
+    }
+
+    fn link(&self, url: &str) -> Link {
+        Link {
+            href: url.into(),
+            title: self
+                .store
+                .get_resource(url)
+                .map(|linked| title_of(&linked))
+                .unwrap_or_else(|_| url.into()),
+        }
+    }
+
+    /// Renders Markdown as HTML. Raw HTML is escaped, since the text can come from anyone with write rights.
+    fn markdown(&mut self, subject: &str, markdown: &str) -> String {
+        let base = self.store.get_server_url();
+        let mut options = Options::empty();
+        options.insert(Options::ENABLE_TABLES);
+        options.insert(Options::ENABLE_STRIKETHROUGH);
+        let events: Vec<Event> = Parser::new_ext(markdown, options)
+            .map(|event| match event {
+                Event::Html(raw) => Event::Text(raw),
+                Event::Start(Tag::Link(kind, href, title)) => Event::Start(Tag::Link(
+                    kind,
+                    CowStr::from(absolute_url(base, subject, &href)),
+                    title,
+                )),
+                Event::Start(Tag::Image(kind, src, title)) => {
+                    let src = self.image_src(&absolute_url(base, subject, &src));
+                    Event::Start(Tag::Image(kind, CowStr::from(src), title))
+                }
+                other => other,
+            })
+            .collect();
+        let mut rendered = String::new();
+        html::push_html(&mut rendered, events.into_iter());
+        rendered
+    }
+
+    /// Returns a data URI for small images of local Files that the Agent can read, or else the URL itself.
+    fn image_src(&mut self, url: &str) -> String {
+        if let Some(src) = self.images.get(url) {
+            return src.clone();
+        }
+        let src = self.embed(url).unwrap_or_else(|| url.to_string());
+        self.images.insert(url.into(), src.clone());
+        src
+    }
+
+    fn embed(&mut self, url: &str) -> Option<String> {
+        let server_url = self.store.get_server_url();
+        let path = url.strip_prefix(&format!("{}/download/", server_url))?;
+        let file = self
+            .store
+            .get_resource(&format!("{}/{}", server_url, path))
+            .ok()?;
+        check_read(self.store, &file, self.for_agent).ok()?;
+        let mimetype = file.get(urls::MIMETYPE).ok()?.to_string();
+        if !mimetype.starts_with("image/") {
+            return None;
+        }
+        let file_id = file.get(urls::INTERNAL_ID).ok()?.to_string();
+        if !crate::handlers::download::is_safe_file_id(&file_id) {
+            return None;
+        }
+        let path = self.config.uploads_path.join(file_id);
+        let size = std::fs::metadata(&path).ok()?.len();
+        if size > MAX_EMBEDDED_IMAGE_SIZE || self.embedded_total + size > MAX_EMBEDDED_TOTAL {
+            return None;
+        }
+        let bytes = std::fs::read(&path).ok()?;
+        self.embedded_total += size;
+        Some(format!(
+            "data:{};base64,{}",
+            mimetype,
+            base64::encode(bytes)
+        ))
+    }
+}
+
+/// Resolves links relative to the server (`/path`) or to the Resource (`path`), and removes `javascript:` links.
+fn absolute_url(server_url: &str, subject: &str, href: &str) -> String {
+    let scheme = href
+        .split(['/', '?', '#'])
+        .next()
+        .and_then(|first| first.split_once(':'))
+        .map(|(scheme, _)| scheme.to_lowercase());
+    match scheme.as_deref() {
+        Some("javascript") | Some("vbscript") => "#".into(),
+        Some(_) => href.into(),
+        None if href.starts_with('#') || href.starts_with("//") || href.is_empty() => href.into(),
+        None if href.starts_with('/') => format!("{}{}", server_url, href),
+        None => {
+            let base = subject
+                .rsplit_once('/')
+                .map(|(base, _)| base)
+                .unwrap_or(subject);
+            format!("{}/{}", base, href)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn links_become_absolute() {
+        let server = "https://example.com";
+        let subject = "https://example.com/docs/intro";
+        assert_eq!(
+            absolute_url(server, subject, "/files/a"),
+            "https://example.com/files/a"
+        );
+        assert_eq!(
+            absolute_url(server, subject, "setup"),
+            "https://example.com/docs/setup"
+        );
+        assert_eq!(
+            absolute_url(server, subject, "https://other.org/x"),
+            "https://other.org/x"
+        );
+        assert_eq!(
+            absolute_url(server, subject, "mailto:a@b.c"),
+            "mailto:a@b.c"
+        );
+        assert_eq!(absolute_url(server, subject, "#top"), "#top");
+        assert_eq!(absolute_url(server, subject, "JavaScript:alert(1)"), "#");
+    }
+}
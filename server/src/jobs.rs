@@ -0,0 +1,1095 @@
+//! Background Jobs for tasks that take too long to run inside an HTTP request,
+//! such as rebuilding the indexes or exporting a part of the hierarchy.
+//! A Job is stored as a Resource (see [urls::JOB]), so clients can poll or subscribe to its subject to follow its progress.
+//! Jobs are executed by a fixed amount of worker threads (see `--job-workers`).
+//! Jobs that were pending or running when the server stopped are re-queued on startup.
+
+use std::{
+    collections::HashMap,
+    io::Write,
+    str::FromStr,
+    sync::{mpsc, Arc, Mutex},
+};
+
+use atomic_lib::{
+    agents::ForAgent,
+    commit::{CommitBuilder, CommitOpts},
+    storelike::Query,
+    urls,
+    utils::now,
+    Db, Resource, Storelike, Value,
+};
+
+use crate::{
+    config::Config,
+    errors::AtomicServerResult,
+    link_checker::{HttpLinkClient, LinkChecker},
+    search::SearchState,
+    settings::Settings,
+};
+
+/// The kinds of work that can be done by a Job.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobType {
+    /// Clears and rebuilds the value index and the full-text search index.
+    RebuildIndexes,
+    /// Exports a Resource and all of its descendants to a JSON-AD File, which can be downloaded.
+    ExportSubtree,
+    /// Permanently removes Resources that have been in the trash for too long.
+    PurgeTrash,
+    /// Requests the links to other servers and stores the broken ones in the LinkReport, see [crate::link_checker].
+    CheckLinks,
+    /// Destroys Resources whose `expiresAt` has passed, see [atomic_lib::plugins::expiry].
+    RemoveExpired,
+    /// Stores the complete values that the patches in the Commits of the `subject` param resulted in, see [atomic_lib::patch].
+    CompactHistory,
+    /// Rewrites Values that are not normalized, see [atomic_lib::normalize]. With the `dryRun` param, only reports them.
+    NormalizeValues,
+    /// Retries updates of the search index and removals of uploaded files that failed after a Commit, see [crate::side_effects].
+    RepairSideEffects,
+    /// Checks that `attachments`, File Resources and uploaded files agree, see [atomic_lib::plugins::attachments]. Only repairs when `dryRun` is false.
+    CheckAttachments,
+    /// Converts Values that were stored with another datatype than their Property requires, see [atomic_lib::coerce]. With the `dryRun` param, only reports them.
+    CoerceValues,
+    /// Extracts the text of the Files in the `subjects` param for the search index, see [crate::text_extraction].
+    ExtractText,
+    /// Runs the RetentionPolicies and stores what they removed, see [atomic_lib::plugins::retention].
+    ApplyRetention,
+    /// Writes a backup with a manifest to the `backups` folder of the config directory, see [crate::backup].
+    /// Verifies it when the `verify` param or `--verify-backups` is set.
+    Backup,
+    /// Loads the backup in the `backup` param (the latest by default) into a temporary store and checks it, see [crate::backup::verify].
+    VerifyBackup,
+}
+
+impl JobType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobType::RebuildIndexes => "rebuild-indexes",
+            JobType::ExportSubtree => "export-subtree",
+            JobType::PurgeTrash => "purge-trash",
+            JobType::CheckLinks => "check-links",
+            JobType::RemoveExpired => "remove-expired",
+            JobType::CompactHistory => "compact-history",
+            JobType::NormalizeValues => "normalize-values",
+            JobType::RepairSideEffects => "repair-side-effects",
+            JobType::CheckAttachments => "check-attachments",
+            JobType::CoerceValues => "coerce-values",
+            JobType::ExtractText => "extract-text",
+            JobType::ApplyRetention => "apply-retention",
+            JobType::Backup => "backup",
+            JobType::VerifyBackup => "verify-backup",
+        }
+    }
+}
+
+impl FromStr for JobType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rebuild-indexes" => Ok(JobType::RebuildIndexes),
+            "export-subtree" => Ok(JobType::ExportSubtree),
+            "purge-trash" => Ok(JobType::PurgeTrash),
+            "check-links" => Ok(JobType::CheckLinks),
+            "remove-expired" => Ok(JobType::RemoveExpired),
+            "compact-history" => Ok(JobType::CompactHistory),
+            "normalize-values" => Ok(JobType::NormalizeValues),
+            "repair-side-effects" => Ok(JobType::RepairSideEffects),
+            "check-attachments" => Ok(JobType::CheckAttachments),
+            "coerce-values" => Ok(JobType::CoerceValues),
+            "extract-text" => Ok(JobType::ExtractText),
+            "apply-retention" => Ok(JobType::ApplyRetention),
+            "backup" => Ok(JobType::Backup),
+            "verify-backup" => Ok(JobType::VerifyBackup),
+            other => Err(format!("Unknown job type: {}", other)),
+        }
+    }
+}
+
+/// The lifecycle of a Job. Stored as a slug in [urls::JOB_STATUS].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Done,
+    Failed,
+    /// Set by the creator of the Job. The worker stops at the next progress update.
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JobStatus::Pending => "pending",
+            JobStatus::Running => "running",
+            JobStatus::Done => "done",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+
+    fn of(resource: &Resource) -> Option<JobStatus> {
+        match resource.get(urls::JOB_STATUS).ok()?.to_string().as_str() {
+            "pending" => Some(JobStatus::Pending),
+            "running" => Some(JobStatus::Running),
+            "done" => Some(JobStatus::Done),
+            "failed" => Some(JobStatus::Failed),
+            "cancelled" => Some(JobStatus::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+/// Everything a worker needs to execute Jobs.
+#[derive(Clone)]
+struct Worker {
+    store: Db,
+    search_state: SearchState,
+    config: Config,
+    settings: Settings,
+    link_checker: LinkChecker,
+}
+
+/// Sends Jobs to the worker threads.
+/// Cheap to clone, so it can be part of the [crate::appstate::AppState].
+#[derive(Clone)]
+pub struct JobQueue {
+    store: Db,
+    sender: Arc<Mutex<mpsc::Sender<String>>>,
+}
+
+impl JobQueue {
+    /// Starts `config.opts.job_workers` worker threads and re-queues Jobs that were not finished.
+    pub fn start(
+        store: Db,
+        search_state: SearchState,
+        config: Config,
+        settings: Settings,
+    ) -> AtomicServerResult<JobQueue> {
+        let (sender, receiver) = mpsc::channel::<String>();
+        let worker = Worker {
+            store: store.clone(),
+            search_state,
+            config: config.clone(),
+            settings,
+            link_checker: LinkChecker::new(Arc::new(HttpLinkClient::new(
+                store.get_outbound().unwrap_or_default(),
+            ))),
+        };
+        spawn_workers(config.opts.job_workers, receiver, move |subject| {
+            worker.run(subject)
+        })?;
+        let queue = JobQueue {
+            store,
+            sender: Arc::new(Mutex::new(sender)),
+        };
+        queue.requeue_unfinished()?;
+        Ok(queue)
+    }
+
+    /// Creates a Job Resource and schedules it. Returns the Job, which can be polled for progress.
+    /// The `creator` gets read and write rights to the Job, so they can cancel it.
+    pub fn enqueue(
+        &self,
+        job_type: JobType,
+        params: serde_json::Value,
+        creator: &ForAgent,
+    ) -> AtomicServerResult<Resource> {
+        let store = &self.store;
+        let subject = format!(
+            "{}/jobs/{}",
+            store.get_server_url(),
+            atomic_lib::utils::random_string(10)
+        );
+        let mut job = Resource::new(subject);
+        job.set_class(urls::JOB);
+        job.set_propval(
+            urls::PARENT.into(),
+            Value::AtomicUrl(store.get_server_url().into()),
+            store,
+        )?;
+        job.set_propval(
+            urls::JOB_TYPE.into(),
+            Value::Slug(job_type.as_str().into()),
+            store,
+        )?;
+        job.set_propval(
+            urls::JOB_PARAMS.into(),
+            Value::String(params.to_string()),
+            store,
+        )?;
+        job.set_propval(
+            urls::JOB_STATUS.into(),
+            Value::Slug(JobStatus::Pending.as_str().into()),
+            store,
+        )?;
+        job.set_propval(urls::JOB_PROGRESS.into(), Value::Float(0.0), store)?;
+        job.set_propval(urls::CREATED_AT.into(), Value::Timestamp(now()), store)?;
+        if let ForAgent::AgentSubject(agent) = creator {
+            job.set_propval(
+                urls::CREATED_BY.into(),
+                Value::AtomicUrl(agent.into()),
+                store,
+            )?;
+            job.push_propval(urls::READ, agent.clone().into(), true)?;
+            job.push_propval(urls::WRITE, agent.clone().into(), true)?;
+        }
+        job.save_locally(store)?;
+        self.schedule(job.get_subject())?;
+        Ok(job)
+    }
+
+    /// Enqueues a Job every `interval`, starting now, if `needed` returns true. Used for maintenance, such as emptying the trash.
+    /// Prevents creating a Job Resource for every tick of frequent maintenance tasks.
+    pub fn repeat_when(
+        &self,
+        job_type: JobType,
+        interval: std::time::Duration,
+        needed: impl Fn(&Db) -> bool + Send + 'static,
+    ) -> AtomicServerResult<()> {
+        let queue = self.clone();
+        std::thread::Builder::new()
+            .name(format!("schedule-{}", job_type.as_str()))
+            .spawn(move || loop {
+                if needed(&queue.store) {
+                    if let Err(e) = queue.enqueue(job_type, serde_json::json!({}), &ForAgent::Sudo)
+                    {
+                        tracing::error!("Could not schedule {} job: {}", job_type.as_str(), e);
+                    }
+                }
+                std::thread::sleep(interval);
+            })?;
+        Ok(())
+    }
+
+    fn schedule(&self, subject: &str) -> AtomicServerResult<()> {
+        self.sender
+            .lock()?
+            .send(subject.to_string())
+            .map_err(|e| format!("Job workers are not running: {}", e))?;
+        Ok(())
+    }
+
+    /// Jobs that were interrupted by a restart are set to `pending` and scheduled again.
+    fn requeue_unfinished(&self) -> AtomicServerResult<()> {
+        let result = self.store.query(&Query::new_class(urls::JOB))?;
+        for mut job in result.resources {
+            match JobStatus::of(&job) {
+                Some(JobStatus::Pending) => {}
+                Some(JobStatus::Running) => {
+                    job.set_propval(
+                        urls::JOB_STATUS.into(),
+                        Value::Slug(JobStatus::Pending.as_str().into()),
+                        &self.store,
+                    )?;
+                    job.save_locally(&self.store)?;
+                }
+                _ => continue,
+            }
+            tracing::info!("Re-queueing job {}", job.get_subject());
+            self.schedule(job.get_subject())?;
+        }
+        Ok(())
+    }
+}
+
+/// Starts `count` (at least one) threads that `run` the Jobs sent to `receiver`, so at most `count` Jobs run at the same time.
+fn spawn_workers(
+    count: usize,
+    receiver: mpsc::Receiver<String>,
+    run: impl Fn(&str) + Clone + Send + 'static,
+) -> AtomicServerResult<()> {
+    let receiver = Arc::new(Mutex::new(receiver));
+    for i in 0..count.max(1) {
+        let receiver = receiver.clone();
+        let run = run.clone();
+        std::thread::Builder::new()
+            .name(format!("job-worker-{}", i))
+            .spawn(move || loop {
+                // Only hold the lock while waiting, so other workers can pick up the next Job.
+                let next = receiver.lock().unwrap().recv();
+                match next {
+                    Ok(subject) => run(&subject),
+                    // The queue has been dropped, the server is stopping.
+                    Err(_) => break,
+                }
+            })?;
+    }
+    Ok(())
+}
+
+/// Passed to the code executing a Job, to report progress.
+pub struct JobContext<'a> {
+    pub store: &'a Db,
+    pub search_state: &'a SearchState,
+    pub config: &'a Config,
+    pub settings: &'a Settings,
+    pub link_checker: &'a LinkChecker,
+    pub params: serde_json::Value,
+    subject: String,
+}
+
+impl JobContext<'_> {
+    /// Stores the progress (`0` to `1`) in the Job.
+    /// Returns an error if the Job has been cancelled, which should stop the Job.
+    pub fn progress(&self, progress: f64) -> AtomicServerResult<()> {
+        let mut job = self.store.get_resource(&self.subject)?;
+        if JobStatus::of(&job) == Some(JobStatus::Cancelled) {
+            return Err("Job has been cancelled".into());
+        }
+        job.set_propval(
+            urls::JOB_PROGRESS.into(),
+            Value::Float(progress),
+            self.store,
+        )?;
+        job.save_locally(self.store)?;
+        Ok(())
+    }
+
+    /// Replaces the params of the Job, so a Job that is interrupted by a restart can continue where it stopped.
+    pub fn save_params(&self, params: &serde_json::Value) -> AtomicServerResult<()> {
+        let mut job = self.store.get_resource(&self.subject)?;
+        job.set_propval(
+            urls::JOB_PARAMS.into(),
+            Value::String(params.to_string()),
+            self.store,
+        )?;
+        job.save_locally(self.store)?;
+        Ok(())
+    }
+
+    /// Returns a string parameter of the Job.
+    pub fn param(&self, key: &str) -> AtomicServerResult<String> {
+        Ok(self
+            .params
+            .get(key)
+            .and_then(|v| v.as_str())
+            .ok_or(format!("Job is missing the `{}` parameter", key))?
+            .to_string())
+    }
+}
+
+impl Worker {
+    #[tracing::instrument(skip(self))]
+    fn run(&self, subject: &str) {
+        if let Err(e) = self.run_internal(subject) {
+            tracing::error!("Job {} failed: {}", subject, e);
+            let _ = self.finish(subject, Err(e.message));
+        }
+    }
+
+    fn run_internal(&self, subject: &str) -> AtomicServerResult<()> {
+        let mut job = self.store.get_resource(subject)?;
+        if JobStatus::of(&job) != Some(JobStatus::Pending) {
+            tracing::info!("Skipping job {}, it is no longer pending", subject);
+            return Ok(());
+        }
+        let job_type = JobType::from_str(&job.get(urls::JOB_TYPE)?.to_string())?;
+        let params = match job.get(urls::JOB_PARAMS) {
+            Ok(p) => serde_json::from_str(&p.to_string())
+                .map_err(|e| format!("Invalid job params: {}", e))?,
+            Err(_) => serde_json::Value::Null,
+        };
+        job.set_propval(
+            urls::JOB_STATUS.into(),
+            Value::Slug(JobStatus::Running.as_str().into()),
+            &self.store,
+        )?;
+        job.save_locally(&self.store)?;
+
+        let context = JobContext {
+            store: &self.store,
+            search_state: &self.search_state,
+            config: &self.config,
+            settings: &self.settings,
+            link_checker: &self.link_checker,
+            params,
+            subject: subject.to_string(),
+        };
+        let result = match job_type {
+            JobType::RebuildIndexes => rebuild_indexes(&context).map(|_| None),
+            JobType::ExportSubtree => export_subtree(&context).map(Some),
+            JobType::PurgeTrash => purge_trash(&context).map(|_| None),
+            JobType::CheckLinks => check_links(&context).map(Some),
+            JobType::RemoveExpired => remove_expired(&context).map(|_| None),
+            JobType::CompactHistory => compact_history(&context).map(|_| None),
+            JobType::NormalizeValues => normalize_values(&context).map(Some),
+            JobType::RepairSideEffects => repair_side_effects(&context).map(|_| None),
+            JobType::CheckAttachments => check_attachments(&context).map(Some),
+            JobType::CoerceValues => coerce_values(&context).map(Some),
+            JobType::ExtractText => extract_text(&context).map(|_| None),
+            JobType::ApplyRetention => apply_retention(&context).map(Some),
+            JobType::Backup => backup(&context).map(Some),
+            JobType::VerifyBackup => verify_backup(&context).map(Some),
+        };
+        self.finish(subject, result.map_err(|e| e.message))
+    }
+
+    /// Stores the outcome of the Job, unless it has been cancelled in the meantime.
+    fn finish(
+        &self,
+        subject: &str,
+        result: Result<Option<String>, String>,
+    ) -> AtomicServerResult<()> {
+        let mut job = self.store.get_resource(subject)?;
+        if JobStatus::of(&job) == Some(JobStatus::Cancelled) {
+            return Ok(());
+        }
+        match result {
+            Ok(result_subject) => {
+                job.set_propval(
+                    urls::JOB_STATUS.into(),
+                    Value::Slug(JobStatus::Done.as_str().into()),
+                    &self.store,
+                )?;
+                job.set_propval(urls::JOB_PROGRESS.into(), Value::Float(1.0), &self.store)?;
+                if let Some(result_subject) = result_subject {
+                    job.set_propval(
+                        urls::JOB_RESULT.into(),
+                        Value::AtomicUrl(result_subject),
+                        &self.store,
+                    )?;
+                }
+            }
+            Err(message) => {
+                job.set_propval(
+                    urls::JOB_STATUS.into(),
+                    Value::Slug(JobStatus::Failed.as_str().into()),
+                    &self.store,
+                )?;
+                job.set_propval(urls::JOB_ERROR.into(), Value::String(message), &self.store)?;
+            }
+        }
+        job.save_locally(&self.store)?;
+        Ok(())
+    }
+}
+
+/// Clears and rebuilds the value index, then re-indexes all resources for full-text search.
+pub fn rebuild_indexes(context: &JobContext) -> AtomicServerResult<()> {
+    tracing::info!("Rebuilding value index...");
+    context.store.clear_index()?;
+    context.store.build_index(true)?;
+    context.progress(0.5)?;
+    tracing::info!("Removing existing search index...");
+    context
+        .search_state
+        .writer
+        .write()?
+        .delete_all_documents()?;
+    crate::search::add_all_resources(context.search_state, context.store)?;
+    Ok(())
+}
+
+/// Runs the [crate::side_effects::Repairs] in the params.
+pub fn repair_side_effects(context: &JobContext) -> AtomicServerResult<()> {
+    let repairs: crate::side_effects::Repairs = serde_json::from_value(context.params.clone())
+        .map_err(|e| format!("Invalid repair params: {}", e))?;
+    repairs.run(
+        context.store,
+        context.search_state,
+        &context.config.uploads_path,
+    )
+}
+
+/// Options for Commits that the server signs itself, which skip the checks that only make sense for Commits from clients.
+pub fn server_commit_opts() -> CommitOpts {
+    CommitOpts {
+        validate_schema: false,
+        validate_signature: false,
+        validate_timestamp: false,
+        validate_rights: false,
+        validate_previous_commit: false,
+        validate_for_agent: None,
+        update_index: true,
+        validate_relative_urls: false,
+    }
+}
+
+/// Permanently removes the Resources that have been in the trash for longer than the `days` param.
+/// Defaults to the `trashRetentionDays` server setting, or empties the entire trash if that is not set either.
+pub fn purge_trash(context: &JobContext) -> AtomicServerResult<()> {
+    let store = context.store;
+    let days = context
+        .params
+        .get("days")
+        .and_then(|d| d.as_u64())
+        .or(context.settings.get().trash_retention_days)
+        .unwrap_or(0);
+    let deleted_before = now() - (days as i64) * 24 * 60 * 60 * 1000;
+    let expired = atomic_lib::plugins::trash::expired_trash(store, deleted_before);
+    tracing::info!("Purging {} resources from the trash", expired.len());
+
+    let agent = store.get_default_agent()?;
+    let opts = server_commit_opts();
+    for (i, subject) in expired.iter().enumerate() {
+        let resource = store.get_resource(subject)?;
+        // A purging Commit keeps the history, and the Commit Monitor removes uploaded files and search entries.
+        let mut commitbuilder = CommitBuilder::new(subject.clone());
+        commitbuilder.destroy(true);
+        commitbuilder.purge(true);
+        commitbuilder
+            .sign(&agent, store, &resource)?
+            .apply_opts(store, &opts)?;
+        context.progress((i + 1) as f64 / expired.len() as f64)?;
+    }
+    Ok(())
+}
+
+/// Checks the links of the `subject` param, or of all Resources if it is not set.
+/// Returns the subject of the LinkReport.
+pub fn check_links(context: &JobContext) -> AtomicServerResult<String> {
+    let subject = context.param("subject").ok();
+    context.link_checker.run(context.store, subject.as_deref())
+}
+
+/// How many expired Resources are destroyed between two progress updates.
+const EXPIRED_BATCH_SIZE: usize = 100;
+
+/// Destroys all Resources whose `expiresAt` has passed, in batches.
+pub fn remove_expired(context: &JobContext) -> AtomicServerResult<()> {
+    let mut total = 0;
+    loop {
+        let removed =
+            atomic_lib::plugins::expiry::remove_expired(context.store, EXPIRED_BATCH_SIZE)?;
+        total += removed;
+        if removed < EXPIRED_BATCH_SIZE {
+            break;
+        }
+        // The total amount is unknown, so this only shows that the Job is still alive.
+        context.progress(0.5)?;
+    }
+    tracing::info!("Removed {} expired resources", total);
+    Ok(())
+}
+
+/// Materializes the patches in the history of the `subject` param.
+pub fn compact_history(context: &JobContext) -> AtomicServerResult<()> {
+    let subject = context.param("subject")?;
+    let updated = atomic_lib::plugins::versioning::materialize_patches(&subject, context.store)?;
+    tracing::info!("Materialized {} patched commits of {}", updated, subject);
+    Ok(())
+}
+
+/// Extracts the text of every File in the `subjects` param. Files that fail are skipped, their error is stored on the File.
+pub fn extract_text(context: &JobContext) -> AtomicServerResult<()> {
+    let subjects: Vec<String> = context
+        .params
+        .get("subjects")
+        .and_then(|s| serde_json::from_value(s.clone()).ok())
+        .ok_or("Job is missing the `subjects` parameter")?;
+    for (i, subject) in subjects.iter().enumerate() {
+        // The File may have been removed since the Job was queued
+        match context.store.get_resource(subject) {
+            Ok(file) => {
+                let outcome =
+                    crate::text_extraction::extract_file(context.store, context.config, &file)?;
+                tracing::info!("Text extraction of {}: {:?}", subject, outcome);
+            }
+            Err(e) => tracing::warn!("Skipping text extraction of {}: {}", subject, e),
+        }
+        context.progress((i + 1) as f64 / subjects.len() as f64)?;
+    }
+    Ok(())
+}
+
+/// Rewrites the Values that are not normalized, or only lists them if the `dryRun` param is true.
+/// Returns the subject of a JSON File listing the changed Values.
+pub fn normalize_values(context: &JobContext) -> AtomicServerResult<String> {
+    let store = context.store;
+    let dry_run = context
+        .params
+        .get("dryRun")
+        .and_then(|d| d.as_bool())
+        .unwrap_or(false);
+    let changes = atomic_lib::normalize::normalize_store(store, dry_run)?;
+    tracing::info!(
+        "Found {} values that are not normalized{}",
+        changes.len(),
+        if dry_run { " (dry run)" } else { "" }
+    );
+    context.progress(0.9)?;
+    let report = serde_json::to_string_pretty(&changes)
+        .map_err(|e| format!("Could not serialize the report: {}", e))?;
+    save_file(
+        context,
+        store.get_server_url(),
+        "normalize-report.json",
+        "application/json",
+        report.as_bytes(),
+    )
+}
+
+/// Removes the old Commits and inactive Resources that the RetentionPolicies select.
+/// Returns the subject of a JSON File listing what was removed, which `/retention` shows before the run.
+pub fn apply_retention(context: &JobContext) -> AtomicServerResult<String> {
+    use atomic_lib::plugins::retention;
+    let store = context.store;
+    let plan = retention::plan(store, atomic_lib::utils::now())?;
+    context.progress(0.3)?;
+    retention::apply(store, &plan)?;
+    tracing::info!(
+        "Applied {} retention policies: compacted {} histories, deleted {} resources, flagged {}",
+        plan.policies.len(),
+        plan.compact.len(),
+        plan.delete.len(),
+        plan.flagged.len()
+    );
+    context.progress(0.9)?;
+    let report = serde_json::to_string_pretty(&plan)
+        .map_err(|e| format!("Could not serialize the report: {}", e))?;
+    save_file(
+        context,
+        store.get_server_url(),
+        "retention-report.json",
+        "application/json",
+        report.as_bytes(),
+    )
+}
+
+/// Writes a backup to a new folder in the backups directory, and verifies it if the `verify` param or `--verify-backups` is set.
+/// Returns the subject of a JSON File with the manifest, or with the verification report if it was verified.
+pub fn backup(context: &JobContext) -> AtomicServerResult<String> {
+    let store = context.store;
+    let dir = crate::backup::backups_dir(context.config).join(chrono::Local::now().to_rfc3339());
+    let manifest = crate::backup::create(store, &context.config.uploads_path, &dir)?;
+    tracing::info!(
+        "Backed up {} resources and {} files to {:?}",
+        manifest.resources,
+        manifest.files.len(),
+        dir
+    );
+    let verify = context
+        .params
+        .get("verify")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(context.config.opts.verify_backups);
+    if !verify {
+        context.progress(0.9)?;
+        let manifest = serde_json::to_string_pretty(&manifest)
+            .map_err(|e| format!("Could not serialize the manifest: {}", e))?;
+        return save_file(
+            context,
+            store.get_server_url(),
+            "backup-manifest.json",
+            "application/json",
+            manifest.as_bytes(),
+        );
+    }
+    context.progress(0.5)?;
+    save_verification(context, &dir)
+}
+
+/// Verifies the backup folder in the `backup` param, or the latest backup. The `sample` param sets how many Resources are compared with the live store.
+/// Returns the subject of a JSON File with the report.
+pub fn verify_backup(context: &JobContext) -> AtomicServerResult<String> {
+    let backups = crate::backup::backups_dir(context.config);
+    let dir = match context.params.get("backup").and_then(|b| b.as_str()) {
+        // Only folders in the backups directory can be verified
+        Some(name) if crate::handlers::download::is_safe_file_id(name) => backups.join(name),
+        Some(name) => return Err(format!("Invalid backup name: {}", name).into()),
+        None => crate::backup::latest(&backups)?,
+    };
+    save_verification(context, &dir)
+}
+
+/// Verifies a backup, stores the report and alerts the admins if it failed.
+fn save_verification(context: &JobContext, dir: &std::path::Path) -> AtomicServerResult<String> {
+    let store = context.store;
+    let sample = context
+        .params
+        .get("sample")
+        .and_then(|s| s.as_u64())
+        .map(|s| s as usize)
+        .unwrap_or(crate::backup::DEFAULT_SAMPLE_SIZE);
+    let report = crate::backup::verify(Some(store), dir, sample)?;
+    tracing::info!(
+        "Verified backup {:?}: {} resources, {} sampled, {} discrepancies",
+        dir,
+        report.resources,
+        report.sampled,
+        report.discrepancies.len()
+    );
+    context.progress(0.9)?;
+    let json = serde_json::to_string_pretty(&report)
+        .map_err(|e| format!("Could not serialize the report: {}", e))?;
+    let file = save_file(
+        context,
+        store.get_server_url(),
+        "backup-verification.json",
+        "application/json",
+        json.as_bytes(),
+    )?;
+    if !report.passed {
+        crate::backup::alert(
+            store,
+            &report,
+            &file,
+            context.config.opts.backup_alert_url.as_deref(),
+        )?;
+    }
+    Ok(file)
+}
+
+/// Converts the Values that don't match the datatype of their Property, or only lists them if the `dryRun` param is true.
+/// Returns the subject of a JSON File listing the converted Values, and the ones that have to be fixed by hand.
+pub fn coerce_values(context: &JobContext) -> AtomicServerResult<String> {
+    let store = context.store;
+    let dry_run = context
+        .params
+        .get("dryRun")
+        .and_then(|d| d.as_bool())
+        .unwrap_or(false);
+    let report = atomic_lib::coerce::coerce_store(store, dry_run)?;
+    tracing::info!(
+        "Coerced {} values, {} can't be converted{}",
+        report.coerced.len(),
+        report.remaining.len(),
+        if dry_run { " (dry run)" } else { "" }
+    );
+    context.progress(0.9)?;
+    let report = serde_json::to_string_pretty(&report)
+        .map_err(|e| format!("Could not serialize the report: {}", e))?;
+    save_file(
+        context,
+        store.get_server_url(),
+        "coerce-report.json",
+        "application/json",
+        report.as_bytes(),
+    )
+}
+
+/// Checks the attachments in batches, and repairs them unless the `dryRun` param is true (the default).
+/// The state of the check is stored in the `check` param after every batch, so it resumes after a restart.
+/// Returns the subject of a JSON File with the report.
+pub fn check_attachments(context: &JobContext) -> AtomicServerResult<String> {
+    use atomic_lib::plugins::attachments::{AttachmentsCheck, Phase, BATCH_SIZE};
+    let store = context.store;
+    let dry_run = context
+        .params
+        .get("dryRun")
+        .and_then(|d| d.as_bool())
+        .unwrap_or(true);
+    let mut check = match context.params.get("check") {
+        Some(state) => serde_json::from_value(state.clone())
+            .map_err(|e| format!("Invalid attachments check state: {}", e))?,
+        None => AttachmentsCheck::new(dry_run),
+    };
+    while !check.is_done() {
+        check.next_batch(store, &context.config.uploads_path, BATCH_SIZE)?;
+        context.save_params(&serde_json::json!({ "dryRun": dry_run, "check": check }))?;
+        // The total amount is unknown, so this only shows which phase the check is in.
+        context.progress(match check.phase {
+            Phase::Resources => 0.3,
+            Phase::Blobs => 0.6,
+            Phase::Done => 0.9,
+        })?;
+    }
+    let report = &check.report;
+    tracing::info!(
+        "Checked attachments: {} dangling, {} unlisted files, {} orphaned blobs, {} missing blobs{}",
+        report.dangling_attachments.found,
+        report.unlisted_files.found,
+        report.orphaned_blobs.found,
+        report.missing_blobs.found,
+        if dry_run { " (dry run)" } else { "" }
+    );
+    let report = serde_json::to_string_pretty(report)
+        .map_err(|e| format!("Could not serialize the report: {}", e))?;
+    save_file(
+        context,
+        store.get_server_url(),
+        "attachments-report.json",
+        "application/json",
+        report.as_bytes(),
+    )
+}
+
+/// Exports the `subject` param and all its descendants to a JSON-AD File, placed as a child of the exported Resource.
+/// Returns the subject of the File.
+pub fn export_subtree(context: &JobContext) -> AtomicServerResult<String> {
+    let store = context.store;
+    let root = context.param("subject")?;
+    // Read from a snapshot, so the export is consistent even if Commits are applied in the meantime.
+    let snapshot = store.snapshot();
+
+    // A single pass over all resources is much cheaper than a query per level of the hierarchy.
+    let mut children: HashMap<String, Vec<Resource>> = HashMap::new();
+    for resource in snapshot.all_resources(false) {
+        if let Ok(parent) = resource.get(urls::PARENT) {
+            children
+                .entry(parent.to_string())
+                .or_default()
+                .push(resource);
+        }
+    }
+    context.progress(0.5)?;
+
+    let mut resources = vec![snapshot.get_resource(&root)?];
+    let mut i = 0;
+    while i < resources.len() {
+        if let Some(mut found) = children.remove(resources[i].get_subject()) {
+            resources.append(&mut found);
+        }
+        i += 1;
+    }
+    let json = atomic_lib::serialize::resources_to_json_ad(&resources)?;
+    context.progress(0.9)?;
+    save_file(
+        context,
+        &root,
+        "export.json",
+        atomic_lib::parse::JSON_AD_MIME,
+        json.as_bytes(),
+    )
+}
+
+/// Writes `contents` to the uploads folder, and creates a File Resource for it as a child of `parent`, listed in its `attachments`.
+/// Returns the subject of the File.
+fn save_file(
+    context: &JobContext,
+    parent: &str,
+    filename: &str,
+    mimetype: &str,
+    contents: &[u8],
+) -> AtomicServerResult<String> {
+    let store = context.store;
+    std::fs::create_dir_all(&context.config.uploads_path)?;
+    let (file_id, mut disk_file) =
+        crate::handlers::upload::create_unique_file(&context.config.uploads_path, filename)?;
+    disk_file.write_all(contents)?;
+
+    let subject_path = format!("files/{}", urlencoding::encode(&file_id));
+    let mut file = Resource::new(format!("{}/{}", store.get_server_url(), subject_path));
+    file.set_class(urls::FILE);
+    file.set_propval(urls::PARENT.into(), Value::AtomicUrl(parent.into()), store)?;
+    file.set_propval_string(urls::INTERNAL_ID.into(), &file_id, store)?;
+    file.set_propval(
+        urls::FILESIZE.into(),
+        Value::Integer(contents.len() as i64),
+        store,
+    )?;
+    file.set_propval_string(urls::MIMETYPE.into(), mimetype, store)?;
+    file.set_propval_string(urls::FILENAME.into(), filename, store)?;
+    file.set_propval_string(
+        urls::DOWNLOAD_URL.into(),
+        &format!("{}/download/{}", store.get_server_url(), subject_path),
+        store,
+    )?;
+    file.save_locally(store)?;
+    let mut parent = store.get_resource(parent)?;
+    parent.push_propval(urls::ATTACHMENTS, file.get_subject().clone().into(), true)?;
+    parent.save_locally(store)?;
+    Ok(file.get_subject().to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, Instant},
+    };
+
+    /// A populated store, config and search index in a unique temp directory.
+    fn setup() -> Worker {
+        use clap::Parser;
+        let dir = format!("./.temp/{}", atomic_lib::utils::random_string(10));
+        let opts = crate::config::Opts::parse_from([
+            "atomic-server",
+            "--data-dir",
+            &format!("{}/data", dir),
+            "--config-dir",
+            &format!("{}/config", dir),
+        ]);
+        let mut config = crate::config::build_config(opts).unwrap();
+        config.search_index_path = format!("{}/search_index", dir).into();
+        let store = Db::init(&config.store_path, config.server_url.clone()).unwrap();
+        let agent = store.create_agent(None).unwrap();
+        store.set_default_agent(agent);
+        store.populate().unwrap();
+        Worker {
+            search_state: SearchState::new(&config).unwrap(),
+            settings: Settings::new(&config.opts, &config.server_url),
+            link_checker: LinkChecker::new(Arc::new(HttpLinkClient::new(
+                store.get_outbound().unwrap_or_default(),
+            ))),
+            store,
+            config,
+        }
+    }
+
+    /// A queue without workers, so its Jobs stay pending.
+    fn idle_queue(store: &Db) -> (JobQueue, mpsc::Receiver<String>) {
+        let (sender, receiver) = mpsc::channel();
+        let queue = JobQueue {
+            store: store.clone(),
+            sender: Arc::new(Mutex::new(sender)),
+        };
+        (queue, receiver)
+    }
+
+    fn enqueue(queue: &JobQueue, params: serde_json::Value) -> String {
+        queue
+            .enqueue(JobType::RemoveExpired, params, &ForAgent::Sudo)
+            .unwrap()
+            .get_subject()
+            .clone()
+    }
+
+    fn status(store: &Db, subject: &str) -> Option<JobStatus> {
+        JobStatus::of(&store.get_resource(subject).unwrap())
+    }
+
+    fn set_status(store: &Db, subject: &str, status: JobStatus) {
+        let mut job = store.get_resource(subject).unwrap();
+        job.set_propval(
+            urls::JOB_STATUS.into(),
+            Value::Slug(status.as_str().into()),
+            store,
+        )
+        .unwrap();
+        job.save_locally(store).unwrap();
+    }
+
+    fn wait_until(done: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !done() {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "Timed out waiting for the jobs"
+            );
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn unfinished_jobs_are_requeued_after_a_restart() {
+        let worker = setup();
+        let store = &worker.store;
+        let (queue, receiver) = idle_queue(store);
+        let pending = enqueue(&queue, serde_json::json!({}));
+        let interrupted = enqueue(&queue, serde_json::json!({}));
+        set_status(store, &interrupted, JobStatus::Running);
+        let failed = enqueue(&queue, serde_json::json!({}));
+        set_status(store, &failed, JobStatus::Failed);
+        // The server stops before the workers picked them up
+        drop(receiver);
+        drop(queue);
+
+        let _queue = JobQueue::start(
+            store.clone(),
+            worker.search_state.clone(),
+            worker.config.clone(),
+            worker.settings.clone(),
+        )
+        .unwrap();
+        wait_until(|| {
+            status(store, &pending) == Some(JobStatus::Done)
+                && status(store, &interrupted) == Some(JobStatus::Done)
+        });
+        assert_eq!(status(store, &failed), Some(JobStatus::Failed));
+    }
+
+    #[test]
+    fn cancelled_jobs_stop() {
+        let worker = setup();
+        let store = &worker.store;
+        let (queue, _receiver) = idle_queue(store);
+
+        // Cancelled before it started
+        let skipped = enqueue(&queue, serde_json::json!({}));
+        set_status(store, &skipped, JobStatus::Cancelled);
+        worker.run(&skipped);
+        assert_eq!(status(store, &skipped), Some(JobStatus::Cancelled));
+
+        // Cancelled while running: the next progress update fails, and the result is not stored
+        let running = enqueue(&queue, serde_json::json!({}));
+        set_status(store, &running, JobStatus::Running);
+        let context = JobContext {
+            store,
+            search_state: &worker.search_state,
+            config: &worker.config,
+            settings: &worker.settings,
+            link_checker: &worker.link_checker,
+            params: serde_json::Value::Null,
+            subject: running.clone(),
+        };
+        context.progress(0.5).unwrap();
+        set_status(store, &running, JobStatus::Cancelled);
+        context.progress(0.6).unwrap_err();
+        worker.finish(&running, Ok(None)).unwrap();
+        assert_eq!(status(store, &running), Some(JobStatus::Cancelled));
+    }
+
+    #[test]
+    fn workers_limit_concurrency() {
+        let (sender, receiver) = mpsc::channel::<String>();
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let finished = Arc::new(AtomicUsize::new(0));
+        let (r, m, f) = (running.clone(), most.clone(), finished.clone());
+        spawn_workers(2, receiver, move |_subject| {
+            let now = r.fetch_add(1, Ordering::SeqCst) + 1;
+            m.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(50));
+            r.fetch_sub(1, Ordering::SeqCst);
+            f.fetch_add(1, Ordering::SeqCst);
+        })
+        .unwrap();
+        for i in 0..6 {
+            sender.send(i.to_string()).unwrap();
+        }
+        wait_until(|| finished.load(Ordering::SeqCst) == 6);
+        assert_eq!(most.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn exports_a_subtree() {
+        let worker = setup();
+        let store = &worker.store;
+        let create = |path: &str, parent: &str| {
+            let mut resource = Resource::new(format!("{}/{}", store.get_server_url(), path));
+            resource
+                .set_propval(urls::PARENT.into(), Value::AtomicUrl(parent.into()), store)
+                .unwrap();
+            resource
+                .set_propval(urls::NAME.into(), Value::String(path.into()), store)
+                .unwrap();
+            resource.save_locally(store).unwrap();
+            resource.get_subject().clone()
+        };
+        let drive = store.get_server_url().to_string();
+        let root = create("export-root", &drive);
+        let child = create("export-child", &root);
+        let grandchild = create("export-grandchild", &child);
+        create("export-sibling", &drive);
+        let expected = atomic_lib::serialize::resources_to_json_ad(&[
+            store.get_resource(&root).unwrap(),
+            store.get_resource(&child).unwrap(),
+            store.get_resource(&grandchild).unwrap(),
+        ])
+        .unwrap();
+
+        let (queue, _receiver) = idle_queue(store);
+        let job = enqueue(&queue, serde_json::json!({ "subject": root }));
+        let context = JobContext {
+            store,
+            search_state: &worker.search_state,
+            config: &worker.config,
+            settings: &worker.settings,
+            link_checker: &worker.link_checker,
+            params: serde_json::json!({ "subject": root }),
+            subject: job,
+        };
+        let file = store
+            .get_resource(&export_subtree(&context).unwrap())
+            .unwrap();
+        assert_eq!(file.get(urls::PARENT).unwrap().to_string(), root);
+        let internal_id = file.get(urls::INTERNAL_ID).unwrap().to_string();
+        let exported =
+            std::fs::read_to_string(worker.config.uploads_path.join(internal_id)).unwrap();
+        assert_eq!(exported, expected);
+    }
+}
@@ -4,22 +4,54 @@ It is currently used as an embedded server in the Tauri distribution of Atomic S
 See https://github.com/atomicdata-dev/atomic-server/tree/master/src-tauri
 */
 mod actor_messages;
+mod agent_activity;
+mod agent_overview;
 mod appstate;
+mod audit;
+mod backup;
+mod cache;
+mod collection_watch;
+mod commit_limits;
 mod commit_monitor;
 pub mod config;
+mod connections;
 mod content_types;
+mod durable_subscriptions;
 mod errors;
 mod handlers;
 mod helpers;
+mod html_export;
 #[cfg(feature = "https")]
 mod https;
+mod jobs;
 mod jsonerrors;
+mod link_checker;
+mod link_preview;
+mod locale;
+mod openapi;
+mod presence;
 #[cfg(feature = "process-management")]
 mod process;
+mod query_table;
+mod replication;
 mod routes;
+mod schema;
+mod self_check;
 pub mod serve;
+mod serve_file;
+mod settings;
+mod setup;
+mod side_effects;
+mod table_view;
 // #[cfg(feature = "search")]
 mod search;
+mod security_headers;
+// Only the binary runs the store subcommands
+#[allow(dead_code)]
+mod store_cli;
 #[cfg(test)]
 mod tests;
+mod text_extraction;
 mod trace;
+mod upload_progress;
+mod webhooks;
@@ -0,0 +1,427 @@
+//! Finds broken links: AtomicURL and ResourceArray values that point outside this server, and that can't be fetched.
+//! Runs as a background Job (see [crate::jobs::JobType::CheckLinks]), as checking can take minutes.
+//! The results are stored in the LinkReport resource at `/link-report`, which only lists broken links.
+//!
+//! Every host is visited by one thread at a time, with a delay between requests, so we don't flood other servers.
+//! Results are cached for a while, so re-checking a single Resource doesn't request all of its links again.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use atomic_lib::{
+    outbound::{OutboundFeature, OutboundHttp},
+    resources::PropVals,
+    urls,
+    utils::now,
+    values::{SubResource, Value},
+    Db, Resource, Storelike,
+};
+
+use crate::errors::AtomicServerResult;
+
+/// How many hosts are checked at the same time.
+const CONCURRENCY: usize = 8;
+/// Time between two requests to the same host.
+const HOST_DELAY: Duration = Duration::from_millis(1000);
+/// Links are not requested again within this time.
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Performs the requests for the link checker. Replaced by a mock in tests.
+pub trait LinkClient: Send + Sync {
+    /// Returns the HTTP status code of the URL, or a description of the error if there is no response.
+    fn status(&self, url: &str) -> Result<u16, String>;
+}
+
+/// Sends a HEAD request, and a GET request if the server does not support HEAD.
+/// Links to internal addresses are refused by the [OutboundHttp] guard, and reported as broken.
+pub struct HttpLinkClient {
+    outbound: OutboundHttp,
+}
+
+impl HttpLinkClient {
+    pub fn new(outbound: OutboundHttp) -> Self {
+        HttpLinkClient { outbound }
+    }
+
+    fn request(&self, method: &str, url: &str) -> Result<u16, String> {
+        self.outbound
+            .request(OutboundFeature::LinkCheck, method, url, &[], None)
+            .map(|response| response.status())
+            .map_err(|e| e.to_string())
+    }
+}
+
+impl LinkClient for HttpLinkClient {
+    fn status(&self, url: &str) -> Result<u16, String> {
+        match self.request("HEAD", url)? {
+            405 | 501 => self.request("GET", url),
+            status => Ok(status),
+        }
+    }
+}
+
+/// A value that points to another server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub url: String,
+    /// The Resource that contains the link
+    pub subject: String,
+    pub property: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkResult {
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    /// Unix timestamp in milliseconds
+    pub checked_at: i64,
+}
+
+impl LinkResult {
+    pub fn is_broken(&self) -> bool {
+        match self.status {
+            Some(status) => status >= 400,
+            None => true,
+        }
+    }
+}
+
+/// The client and the cache that are shared by all link checking Jobs.
+#[derive(Clone)]
+pub struct LinkChecker {
+    client: Arc<dyn LinkClient>,
+    cache: Arc<Mutex<HashMap<String, (LinkResult, Instant)>>>,
+}
+
+impl LinkChecker {
+    pub fn new(client: Arc<dyn LinkClient>) -> Self {
+        LinkChecker {
+            client,
+            cache: Default::default(),
+        }
+    }
+
+    /// Checks the links of one Resource, or of all local Resources if `subject` is `None`, and updates the LinkReport.
+    /// Returns the subject of the report.
+    pub fn run(&self, store: &Db, subject: Option<&str>) -> AtomicServerResult<String> {
+        let links = match subject {
+            Some(subject) => links_in(store.get_server_url(), &store.get_resource(subject)?),
+            None => store
+                .all_resources(false)
+                .flat_map(|resource| links_in(store.get_server_url(), &resource))
+                .collect(),
+        };
+        let results = self.check(&links);
+        save_report(store, &links, &results, subject)
+    }
+
+    /// Requests every URL once. Hosts are checked in parallel, the URLs of a single host one after another.
+    pub fn check(&self, links: &[Link]) -> HashMap<String, LinkResult> {
+        let mut per_host: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for link in links {
+            let urls = per_host.entry(host_of(&link.url).into()).or_default();
+            if !urls.contains(&link.url) {
+                urls.push(link.url.clone());
+            }
+        }
+        let hosts = Mutex::new(per_host.into_values().collect::<Vec<_>>());
+        let results = Mutex::new(HashMap::new());
+        std::thread::scope(|scope| {
+            for _ in 0..CONCURRENCY {
+                scope.spawn(|| loop {
+                    let Some(urls) = hosts.lock().unwrap().pop() else {
+                        break;
+                    };
+                    let mut requested = false;
+                    for url in urls {
+                        if let Some(cached) = self.cached(&url) {
+                            results.lock().unwrap().insert(url, cached);
+                            continue;
+                        }
+                        if requested {
+                            std::thread::sleep(HOST_DELAY);
+                        }
+                        requested = true;
+                        let result = self.request(&url);
+                        results.lock().unwrap().insert(url, result);
+                    }
+                });
+            }
+        });
+        results.into_inner().unwrap()
+    }
+
+    fn cached(&self, url: &str) -> Option<LinkResult> {
+        let cache = self.cache.lock().unwrap();
+        let (result, checked) = cache.get(url)?;
+        if checked.elapsed() > CACHE_TTL {
+            return None;
+        }
+        Some(result.clone())
+    }
+
+    fn request(&self, url: &str) -> LinkResult {
+        let (status, error) = match self.client.status(url) {
+            Ok(status) => (Some(status), None),
+            Err(e) => (None, Some(e)),
+        };
+        let result = LinkResult {
+            status,
+            error,
+            checked_at: now(),
+        };
+        self.cache
+            .lock()
+            .unwrap()
+            .insert(url.into(), (result.clone(), Instant::now()));
+        result
+    }
+}
+
+/// The host (and port) of a URL, e.g. `example.com` for `https://example.com/a`.
+fn host_of(url: &str) -> &str {
+    let without_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    without_scheme
+        .split(['/', '?', '#'])
+        .next()
+        .unwrap_or(without_scheme)
+}
+
+/// Lists the values of the Resource that point to other servers.
+pub fn links_in(server_url: &str, resource: &Resource) -> Vec<Link> {
+    let mut links = Vec::new();
+    for (property, value) in resource.get_propvals() {
+        let urls = match value {
+            Value::AtomicUrl(url) => vec![url.clone()],
+            Value::ResourceArray(items) => items
+                .iter()
+                .filter_map(|item| match item {
+                    SubResource::Subject(url) => Some(url.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => continue,
+        };
+        for url in urls {
+            let is_http = url.starts_with("http://") || url.starts_with("https://");
+            if is_http && !url.starts_with(server_url) {
+                links.push(Link {
+                    url,
+                    subject: resource.get_subject().clone(),
+                    property: property.clone(),
+                });
+            }
+        }
+    }
+    links
+}
+
+pub fn report_subject(store: &impl Storelike) -> String {
+    format!("{}/link-report", store.get_server_url())
+}
+
+/// Stores the broken links in the LinkReport.
+/// If `subject` is given, only the entries of that Resource are replaced.
+fn save_report(
+    store: &Db,
+    links: &[Link],
+    results: &HashMap<String, LinkResult>,
+    subject: Option<&str>,
+) -> AtomicServerResult<String> {
+    let report_subject = report_subject(store);
+    let mut report = match store.get_resource(&report_subject) {
+        Ok(report) => report,
+        Err(_) => {
+            let mut report = Resource::new(report_subject.clone());
+            report.set_class(urls::LINK_REPORT);
+            report.set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                store,
+            )?;
+            report
+        }
+    };
+
+    let mut entries: Vec<SubResource> = match (subject, report.get(urls::BROKEN_LINKS)) {
+        (Some(subject), Ok(Value::ResourceArray(existing))) => existing
+            .iter()
+            .filter(|entry| entry_subject(entry).as_deref() != Some(subject))
+            .cloned()
+            .collect(),
+        _ => Vec::new(),
+    };
+    for link in links {
+        let Some(result) = results.get(&link.url) else {
+            continue;
+        };
+        if result.is_broken() {
+            entries.push(SubResource::Nested(entry_propvals(link, result)));
+        }
+    }
+
+    report.set_propval(
+        urls::BROKEN_LINKS.into(),
+        Value::ResourceArray(entries),
+        store,
+    )?;
+    report.set_propval(urls::LINK_CHECKED_AT.into(), Value::Timestamp(now()), store)?;
+    report.save_locally(store)?;
+    Ok(report_subject)
+}
+
+fn entry_propvals(link: &Link, result: &LinkResult) -> PropVals {
+    let mut propvals = PropVals::new();
+    propvals.insert(urls::LINK_URL.into(), Value::String(link.url.clone()));
+    propvals.insert(
+        urls::LINK_SUBJECT.into(),
+        Value::AtomicUrl(link.subject.clone()),
+    );
+    propvals.insert(
+        urls::LINK_PROPERTY.into(),
+        Value::AtomicUrl(link.property.clone()),
+    );
+    if let Some(status) = result.status {
+        propvals.insert(urls::LINK_STATUS.into(), Value::Integer(status.into()));
+    }
+    if let Some(error) = &result.error {
+        propvals.insert(urls::LINK_ERROR.into(), Value::String(error.clone()));
+    }
+    propvals.insert(
+        urls::LINK_CHECKED_AT.into(),
+        Value::Timestamp(result.checked_at),
+    );
+    propvals
+}
+
+fn entry_subject(entry: &SubResource) -> Option<String> {
+    match entry {
+        SubResource::Nested(propvals) => propvals.get(urls::LINK_SUBJECT).map(|v| v.to_string()),
+        _ => None,
+    }
+}
+
+/// Keeps only the entries of the report that refer to `subject`.
+pub fn filter_report(report: &mut Resource, subject: &str) {
+    if let Ok(Value::ResourceArray(entries)) = report.get(urls::BROKEN_LINKS) {
+        let filtered: Vec<SubResource> = entries
+            .iter()
+            .filter(|entry| entry_subject(entry).as_deref() == Some(subject))
+            .cloned()
+            .collect();
+        report.set_propval_unsafe(urls::BROKEN_LINKS.into(), Value::ResourceArray(filtered));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Responds with 404 for URLs containing `broken`, and counts the requests.
+    #[derive(Default)]
+    struct MockClient {
+        requests: Mutex<Vec<String>>,
+    }
+
+    impl LinkClient for MockClient {
+        fn status(&self, url: &str) -> Result<u16, String> {
+            self.requests.lock().unwrap().push(url.into());
+            if url.contains("unreachable") {
+                Err("Connection refused".into())
+            } else if url.contains("broken") {
+                Ok(404)
+            } else {
+                Ok(200)
+            }
+        }
+    }
+
+    fn broken_entries(store: &Db) -> Vec<PropVals> {
+        match store
+            .get_resource(&report_subject(store))
+            .unwrap()
+            .get(urls::BROKEN_LINKS)
+        {
+            Ok(Value::ResourceArray(entries)) => entries
+                .iter()
+                .filter_map(|entry| match entry {
+                    SubResource::Nested(propvals) => Some(propvals.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn reports_broken_links() {
+        let store = Db::init_temp("reports_broken_links").unwrap();
+        let mut resource = Resource::new_generate_subject(&store);
+        resource
+            .set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                &store,
+            )
+            .unwrap();
+        resource
+            .set_propval(
+                urls::WRITE.into(),
+                vec![
+                    "https://example.com/broken".to_string(),
+                    "https://unreachable.example.com/agent".to_string(),
+                ]
+                .into(),
+                &store,
+            )
+            .unwrap();
+        resource.save_locally(&store).unwrap();
+
+        let client = Arc::new(MockClient::default());
+        let checker = LinkChecker::new(client.clone());
+        checker.run(&store, Some(resource.get_subject())).unwrap();
+
+        let entries = broken_entries(&store);
+        assert_eq!(entries.len(), 2);
+        let not_found = entries
+            .iter()
+            .find(|e| e.get(urls::LINK_STATUS).is_some())
+            .unwrap();
+        assert!(matches!(
+            not_found.get(urls::LINK_STATUS),
+            Some(Value::Integer(404))
+        ));
+        assert_eq!(
+            not_found.get(urls::LINK_SUBJECT).unwrap().to_string(),
+            *resource.get_subject()
+        );
+        assert_eq!(
+            not_found.get(urls::LINK_PROPERTY).unwrap().to_string(),
+            urls::WRITE
+        );
+        assert!(entries.iter().any(|e| e.get(urls::LINK_ERROR).is_some()));
+
+        // Re-checking the subject replaces its entries. Cached results are not requested again.
+        let requests_before = client.requests.lock().unwrap().len();
+        resource.remove_propval(urls::WRITE);
+        resource
+            .set_propval(
+                urls::READ.into(),
+                vec!["https://example.com/broken".to_string()].into(),
+                &store,
+            )
+            .unwrap();
+        resource.save_locally(&store).unwrap();
+        checker.run(&store, Some(resource.get_subject())).unwrap();
+        let entries = broken_entries(&store);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].get(urls::LINK_PROPERTY).unwrap().to_string(),
+            urls::READ
+        );
+        assert_eq!(client.requests.lock().unwrap().len(), requests_before);
+    }
+}
@@ -0,0 +1,216 @@
+//! Small summaries of links, which clients use to render cards for pasted URLs, see `/preview`.
+//! Previews of local Resources need at most two store lookups (besides the parents that the rights check reads):
+//! the Resource itself, and either its Class (for the shortname) or its children (for Drives and Folders).
+//! Previews of web pages on other servers are only fetched with `--external-link-previews`, and are cached for [EXTERNAL_CACHE_TTL].
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use atomic_lib::{
+    agents::ForAgent, hierarchy::check_read, outbound::OutboundFeature,
+    plugins::attachments::is_file, storelike::Query, urls, Db, Resource, Storelike, Value,
+};
+use serde::Serialize;
+
+use crate::errors::{AppErrorType, AtomicServerError, AtomicServerResult};
+
+/// Descriptions are shortened to this many characters.
+pub const DESCRIPTION_LENGTH: usize = 200;
+/// How long a preview of a web page (or the error when fetching it) is reused.
+pub const EXTERNAL_CACHE_TTL: Duration = Duration::from_secs(60 * 60);
+/// The most previews of web pages that are kept in memory.
+const EXTERNAL_CACHE_SIZE: usize = 1000;
+
+/// The response of `/preview`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Preview {
+    pub subject: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Plain text, without Markdown
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The shortname of the main Class, e.g. `folder`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+    /// The download URL of an image File, or the image of a web page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail: Option<String>,
+    /// The amount of children that the Agent can read, for Drives and Folders
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub child_count: Option<usize>,
+    /// Whether the preview comes from a web page on another server
+    pub external: bool,
+}
+
+/// Builds the preview of a local Resource, if the Agent can read it.
+pub fn local_preview(
+    store: &Db,
+    subject: &str,
+    for_agent: &ForAgent,
+) -> AtomicServerResult<Preview> {
+    let resource = store.get_resource(subject)?;
+    check_read(store, &resource, for_agent)?;
+
+    let class = resource.get_main_class().ok();
+    let is_container = matches!(class.as_deref(), Some(urls::DRIVE) | Some(urls::FOLDER));
+    let class_shortname = match class.as_deref() {
+        Some(urls::DRIVE) => Some("drive".to_string()),
+        Some(urls::FOLDER) => Some("folder".to_string()),
+        Some(class) => store
+            .get_resource(class)
+            .ok()
+            .and_then(|class| class.get(urls::SHORTNAME).ok().map(|s| s.to_string())),
+        None => None,
+    };
+    let child_count = if is_container {
+        let mut query = Query::new_prop_val(urls::PARENT, subject);
+        query.include_nested = false;
+        query.for_agent = for_agent.clone();
+        Some(store.query(&query)?.subjects.len())
+    } else {
+        None
+    };
+
+    Ok(Preview {
+        subject: subject.into(),
+        name: [urls::NAME, urls::SHORTNAME, urls::FILENAME]
+            .iter()
+            .find_map(|prop| resource.get(prop).ok())
+            .map(|name| name.to_string()),
+        description: resource
+            .get(urls::DESCRIPTION)
+            .ok()
+            .map(|description| plain_text(&description.to_string(), DESCRIPTION_LENGTH))
+            .filter(|description| !description.is_empty()),
+        class: class_shortname,
+        thumbnail: image_url(&resource),
+        child_count,
+        external: false,
+    })
+}
+
+fn image_url(resource: &Resource) -> Option<String> {
+    if !is_file(resource) {
+        return None;
+    }
+    match resource.get(urls::MIMETYPE) {
+        Ok(Value::String(mimetype)) if mimetype.starts_with("image/") => resource
+            .get(urls::DOWNLOAD_URL)
+            .ok()
+            .map(|url| url.to_string()),
+        _ => None,
+    }
+}
+
+/// Removes Markdown syntax and line breaks, and shortens the text to `max` characters.
+pub fn plain_text(markdown: &str, max: usize) -> String {
+    // Images and links keep their text, not their URL
+    let links = regex::Regex::new(r"!?\[([^\]]*)\]\([^)]*\)").unwrap();
+    let text = links.replace_all(markdown, "$1");
+    let markup = regex::Regex::new(r"(?m)^\s*(#{1,6}|>|[-*+]|\d+\.)\s+|[*_`~]").unwrap();
+    let text = markup.replace_all(&text, "");
+    let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if text.chars().count() <= max {
+        return text;
+    }
+    let mut shortened: String = text.chars().take(max).collect();
+    shortened.push('…');
+    shortened
+}
+
+struct CachedPreview {
+    fetched: Instant,
+    result: Result<Preview, String>,
+}
+
+/// Previews of web pages on other servers, which are fetched through the outbound guard.
+/// Failures are cached as well, so an unreachable page isn't requested for every card.
+#[derive(Clone, Default)]
+pub struct ExternalPreviews {
+    cache: Arc<Mutex<HashMap<String, CachedPreview>>>,
+}
+
+impl ExternalPreviews {
+    /// Returns the cached preview, or fetches the page. Blocks while fetching.
+    pub fn get(&self, store: &Db, url: &str) -> AtomicServerResult<Preview> {
+        if let Some(cached) = self.cache.lock().unwrap().get(url) {
+            if cached.fetched.elapsed() < EXTERNAL_CACHE_TTL {
+                return cached.result.clone().map_err(bad_gateway);
+            }
+        }
+        let result = match fetch_preview(store, url) {
+            Ok(preview) => Ok(preview),
+            // Refused by the outbound guard, which is not worth caching
+            Err(e) if matches!(e.error_type, AppErrorType::OutboundBlocked) => return Err(e),
+            Err(e) => Err(e.message),
+        };
+        let mut cache = self.cache.lock().unwrap();
+        cache.retain(|_url, cached| cached.fetched.elapsed() < EXTERNAL_CACHE_TTL);
+        if cache.len() < EXTERNAL_CACHE_SIZE {
+            cache.insert(
+                url.into(),
+                CachedPreview {
+                    fetched: Instant::now(),
+                    result: result.clone(),
+                },
+            );
+        }
+        result.map_err(bad_gateway)
+    }
+}
+
+fn bad_gateway(message: String) -> AtomicServerError {
+    AtomicServerError::new(message, AppErrorType::BadGateway)
+}
+
+/// Reads the title, description and OpenGraph image of a web page.
+fn fetch_preview(store: &Db, url: &str) -> AtomicServerResult<Preview> {
+    let outbound = store.get_outbound().unwrap_or_default();
+    let (status, html) =
+        outbound.get_string(OutboundFeature::LinkPreview, url, "text/html", &[])?;
+    if !(200..300).contains(&status) {
+        return Err(bad_gateway(format!(
+            "Fetching {} for a preview failed with status {}",
+            url, status
+        )));
+    }
+    let meta = atomic_lib::plugins::bookmark::site_meta(url, &html)
+        .map_err(|e| bad_gateway(format!("Could not read the page at {}: {}", url, e)))?;
+    Ok(Preview {
+        subject: url.into(),
+        name: meta
+            .title
+            .map(|title| plain_text(&title, DESCRIPTION_LENGTH)),
+        description: meta
+            .description
+            .map(|description| plain_text(&description, DESCRIPTION_LENGTH))
+            .filter(|description| !description.is_empty()),
+        class: None,
+        thumbnail: meta.image,
+        child_count: None,
+        external: true,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn markdown_becomes_short_plain_text() {
+        assert_eq!(
+            plain_text(
+                "# Notes\n\nSee **the [docs](https://example.com)** and `code`\n- one\n- two",
+                100
+            ),
+            "Notes See the docs and code one two"
+        );
+        assert_eq!(plain_text("![alt text](image.png)", 100), "alt text");
+        assert_eq!(plain_text("ééééé", 3), "ééé…");
+    }
+}
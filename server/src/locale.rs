@@ -0,0 +1,361 @@
+//! Picks the language of the HTML pages rendered by the server, and formats numbers and dates for it.
+//! The language is taken from the `lang` query parameter, the `atomic_lang` cookie or the `Accept-Language` header, in that order.
+//! A `lang` query parameter is stored in the cookie, so it applies to the next pages as well.
+//!
+//! Templates are rendered with [render], which adds these Tera filters:
+//!
+//! - `t` translates a static string, see [Translations]. `{n}` is replaced by the formatted `n` argument.
+//! - `number` formats integers and floats with the digit grouping of the language.
+//! - `timestamp` formats unix timestamps in milliseconds, `date` formats ISO dates (`2024-01-31`).
+
+use std::{collections::HashMap, path::Path};
+
+use actix_web::{cookie::Cookie, web, HttpRequest};
+use chrono::{Datelike, NaiveDate, TimeZone, Timelike, Utc};
+use tera::Value as TeraValue;
+
+use crate::errors::AtomicServerResult;
+
+pub const LANG_COOKIE: &str = "atomic_lang";
+const TRANSLATIONS_FILE: &str = "translations.json";
+
+/// The languages that HTML pages can be shown in. English is the fallback.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Locale {
+    #[default]
+    En,
+    Nl,
+    De,
+    Fr,
+}
+
+impl Locale {
+    /// Matches a language tag such as `nl-NL` or `en`. Regions are ignored.
+    pub fn from_tag(tag: &str) -> Option<Locale> {
+        let language = tag.trim().split(['-', '_']).next()?.to_lowercase();
+        match language.as_str() {
+            "en" => Some(Locale::En),
+            "nl" => Some(Locale::Nl),
+            "de" => Some(Locale::De),
+            "fr" => Some(Locale::Fr),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::Nl => "nl",
+            Locale::De => "de",
+            Locale::Fr => "fr",
+        }
+    }
+
+    /// The digit group separator and the decimal separator.
+    fn separators(&self) -> (&'static str, &'static str) {
+        match self {
+            Locale::En => (",", "."),
+            Locale::Nl | Locale::De => (".", ","),
+            // Narrow no-break space
+            Locale::Fr => ("\u{202f}", ","),
+        }
+    }
+
+    fn months(&self) -> [&'static str; 12] {
+        match self {
+            Locale::En => [
+                "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+            ],
+            Locale::Nl => [
+                "jan", "feb", "mrt", "apr", "mei", "jun", "jul", "aug", "sep", "okt", "nov", "dec",
+            ],
+            Locale::De => [
+                "Jan.", "Feb.", "März", "Apr.", "Mai", "Juni", "Juli", "Aug.", "Sept.", "Okt.",
+                "Nov.", "Dez.",
+            ],
+            Locale::Fr => [
+                "janv.", "févr.", "mars", "avr.", "mai", "juin", "juil.", "août", "sept.", "oct.",
+                "nov.", "déc.",
+            ],
+        }
+    }
+
+    pub fn format_integer(&self, number: i64) -> String {
+        let (group, _) = self.separators();
+        let digits = number.unsigned_abs().to_string();
+        let mut grouped = String::new();
+        for (i, digit) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                grouped.push_str(group);
+            }
+            grouped.push(digit);
+        }
+        if number < 0 {
+            grouped.insert(0, '-');
+        }
+        grouped
+    }
+
+    /// Shows at most two decimals.
+    pub fn format_float(&self, number: f64) -> String {
+        let (_, decimal) = self.separators();
+        let rounded = format!("{:.2}", number);
+        let (integer, fraction) = rounded.split_once('.').unwrap_or((&rounded, ""));
+        let integer_part = match integer.parse::<i64>() {
+            // Keep the sign of e.g. -0.5
+            Ok(0) if number < 0.0 => "-0".to_string(),
+            Ok(integer) => self.format_integer(integer),
+            Err(_) => integer.to_string(),
+        };
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.is_empty() {
+            integer_part
+        } else {
+            format!("{}{}{}", integer_part, decimal, fraction)
+        }
+    }
+
+    pub fn format_date(&self, date: NaiveDate) -> String {
+        let month = self.months()[date.month0() as usize];
+        match self {
+            Locale::En => format!("{} {}, {}", month, date.day(), date.year()),
+            Locale::De => format!("{}. {} {}", date.day(), month, date.year()),
+            Locale::Nl | Locale::Fr => format!("{} {} {}", date.day(), month, date.year()),
+        }
+    }
+
+    /// Formats a unix timestamp in milliseconds as a date and a time in UTC.
+    pub fn format_timestamp(&self, millis: i64) -> String {
+        let Some(datetime) = Utc.timestamp_millis_opt(millis).single() else {
+            return millis.to_string();
+        };
+        let time = match self {
+            Locale::En => {
+                let (pm, hour) = datetime.hour12();
+                format!(
+                    "{}:{:02} {}",
+                    hour,
+                    datetime.minute(),
+                    if pm { "PM" } else { "AM" }
+                )
+            }
+            _ => format!("{:02}:{:02}", datetime.hour(), datetime.minute()),
+        };
+        format!("{} {} UTC", self.format_date(datetime.date_naive()), time)
+    }
+}
+
+/// Picks the language for the request. Returns `true` as well if it was set with the `lang` query parameter,
+/// in which case the response should set the cookie returned by [lang_cookie].
+pub fn negotiate(req: &HttpRequest) -> (Locale, bool) {
+    let from_query = web::Query::<HashMap<String, String>>::from_query(req.query_string())
+        .ok()
+        .and_then(|query| query.get("lang").and_then(|lang| Locale::from_tag(lang)));
+    if let Some(locale) = from_query {
+        return (locale, true);
+    }
+    if let Some(locale) = req
+        .cookie(LANG_COOKIE)
+        .and_then(|cookie| Locale::from_tag(cookie.value()))
+    {
+        return (locale, false);
+    }
+    let accept_language = req
+        .headers()
+        .get("Accept-Language")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+    (from_accept_language(accept_language), false)
+}
+
+/// Returns the supported language with the highest quality in an `Accept-Language` header, or English.
+pub fn from_accept_language(header: &str) -> Locale {
+    let mut languages: Vec<(Locale, f32)> = header
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let locale = Locale::from_tag(parts.next()?)?;
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            Some((locale, quality))
+        })
+        .collect();
+    // Stable, so equal qualities keep the order of the header
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+    languages
+        .first()
+        .map(|(locale, _)| *locale)
+        .unwrap_or_default()
+}
+
+/// A session cookie that remembers the language chosen with the `lang` query parameter.
+pub fn lang_cookie(locale: Locale) -> Cookie<'static> {
+    Cookie::build(LANG_COOKIE, locale.code()).path("/").finish()
+}
+
+/// Translations of the static strings in templates, per language code.
+/// English strings are used as keys, and are shown when there is no translation.
+/// Extend or override them with a `translations.json` file in the config directory, e.g. `{ "nl": { "Classes": "Klassen" } }`.
+#[derive(Clone, Debug, Default)]
+pub struct Translations {
+    tables: HashMap<String, HashMap<String, String>>,
+}
+
+impl Translations {
+    pub fn load(config_dir: &Path) -> Translations {
+        let mut translations = Translations::default();
+        translations.merge(
+            serde_json::from_str(include_str!("../templates/translations.json"))
+                .expect("Built-in translations are invalid JSON"),
+        );
+        let path = config_dir.join(TRANSLATIONS_FILE);
+        if path.exists() {
+            match std::fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|file| serde_json::from_str(&file).map_err(|e| e.to_string()))
+            {
+                Ok(tables) => translations.merge(tables),
+                Err(e) => tracing::error!("Could not read translations from {:?}: {}", path, e),
+            }
+        }
+        translations
+    }
+
+    fn merge(&mut self, tables: HashMap<String, HashMap<String, String>>) {
+        for (language, table) in tables {
+            self.tables.entry(language).or_default().extend(table);
+        }
+    }
+
+    pub fn translate(&self, locale: Locale, text: &str) -> String {
+        self.tables
+            .get(locale.code())
+            .and_then(|table| table.get(text))
+            .cloned()
+            .unwrap_or_else(|| text.to_string())
+    }
+}
+
+/// Renders an HTML template in the language of the request. Adds `lang` to the context.
+pub fn render(
+    name: &str,
+    template: &str,
+    context: &mut tera::Context,
+    locale: Locale,
+    translations: &Translations,
+) -> AtomicServerResult<String> {
+    let mut tera = tera::Tera::default();
+    tera.add_raw_template(name, template)
+        .map_err(|e| format!("Invalid template {}: {}", name, e))?;
+
+    let translations = translations.clone();
+    tera.register_filter(
+        "t",
+        move |value: &TeraValue, args: &HashMap<String, TeraValue>| {
+            let text = tera::try_get_value!("t", "value", String, value);
+            let mut translated = translations.translate(locale, &text);
+            if let Some(n) = args.get("n") {
+                translated = translated.replace("{n}", &format_number(locale, n));
+            }
+            Ok(TeraValue::String(translated))
+        },
+    );
+    tera.register_filter(
+        "number",
+        move |value: &TeraValue, _: &HashMap<String, TeraValue>| {
+            Ok(TeraValue::String(format_number(locale, value)))
+        },
+    );
+    tera.register_filter(
+        "timestamp",
+        move |value: &TeraValue, _: &HashMap<String, TeraValue>| {
+            let millis = tera::try_get_value!("timestamp", "value", i64, value);
+            Ok(TeraValue::String(locale.format_timestamp(millis)))
+        },
+    );
+    tera.register_filter(
+        "date",
+        move |value: &TeraValue, _: &HashMap<String, TeraValue>| {
+            let date = tera::try_get_value!("date", "value", String, value);
+            Ok(TeraValue::String(
+                NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                    .map(|date| locale.format_date(date))
+                    .unwrap_or(date),
+            ))
+        },
+    );
+
+    context.insert("lang", locale.code());
+    Ok(tera
+        .render(name, context)
+        .map_err(|e| format!("Failed to render {}: {}", name, e))?)
+}
+
+fn format_number(locale: Locale, value: &TeraValue) -> String {
+    match value {
+        TeraValue::Number(number) => match number.as_i64() {
+            Some(integer) => locale.format_integer(integer),
+            None => locale.format_float(number.as_f64().unwrap_or_default()),
+        },
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn formats_for_locale() {
+        assert_eq!(Locale::Nl.format_integer(1234567), "1.234.567");
+        assert_eq!(Locale::En.format_integer(-1234), "-1,234");
+        assert_eq!(Locale::Nl.format_float(1234.5), "1.234,5");
+        assert_eq!(Locale::En.format_float(0.25), "0.25");
+        assert_eq!(
+            Locale::Nl.format_timestamp(1706709900000),
+            "31 jan 2024 14:05 UTC"
+        );
+        assert_eq!(
+            Locale::En.format_timestamp(1706709900000),
+            "Jan 31, 2024 2:05 PM UTC"
+        );
+    }
+
+    #[test]
+    fn negotiates_language() {
+        assert_eq!(from_accept_language("nl-NL,nl;q=0.9,en;q=0.8"), Locale::Nl);
+        assert_eq!(
+            from_accept_language("es;q=1, de;q=0.5, en;q=0.7"),
+            Locale::En
+        );
+        assert_eq!(from_accept_language("es"), Locale::En);
+
+        let req = actix_web::test::TestRequest::with_uri("/schema?lang=nl")
+            .insert_header(("Accept-Language", "de"))
+            .to_http_request();
+        assert_eq!(negotiate(&req), (Locale::Nl, true));
+        let req = actix_web::test::TestRequest::with_uri("/schema")
+            .cookie(lang_cookie(Locale::Fr))
+            .insert_header(("Accept-Language", "de"))
+            .to_http_request();
+        assert_eq!(negotiate(&req), (Locale::Fr, false));
+    }
+
+    #[test]
+    fn translates_templates() {
+        let translations = Translations::load(Path::new("/nonexistent"));
+        let mut context = tera::Context::new();
+        context.insert("days", &7);
+        let html = render(
+            "test.html",
+            "{{ \"Activity in the last {n} days\" | t(n=days) }} {{ 1500 | number }}",
+            &mut context,
+            Locale::Nl,
+            &translations,
+        )
+        .unwrap();
+        assert_eq!(html, "Activiteit in de afgelopen 7 dagen 1.500");
+    }
+}
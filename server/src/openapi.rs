@@ -0,0 +1,1063 @@
+//! Generates an [OpenAPI 3](https://spec.openapis.org/oas/v3.0.3) description of the HTTP API.
+//! The document is built at runtime, so it only lists the Endpoints that are active in the store and reflects the server configuration.
+
+use atomic_lib::{datatype::DataType, endpoints::Endpoint, schema::Property, Storelike};
+use serde_json::{json, Map, Value as JsonValue};
+
+use crate::{appstate::AppState, content_types::ContentType, errors::AtomicServerResult};
+
+pub const OPENAPI_VERSION: &str = "3.0.3";
+
+/// Paths of Endpoints that are handled (and documented) by the server itself, instead of by the Endpoint handler in the store.
+const SERVER_HANDLED_ENDPOINTS: &[&str] = &["/search", "/upload"];
+
+/// Serializations the server can respond with for Resources, see [ContentType].
+const RESOURCE_CONTENT_TYPES: &[ContentType] = &[
+    ContentType::JsonAd,
+    ContentType::Json,
+    ContentType::JsonLd,
+    ContentType::Turtle,
+    ContentType::NTriples,
+    ContentType::NQuads,
+    ContentType::Html,
+];
+
+/// Builds the OpenAPI document for this server.
+pub fn build_openapi(appstate: &AppState) -> AtomicServerResult<JsonValue> {
+    let store = &appstate.store;
+    let mut paths = Map::new();
+
+    paths.insert("/commit".into(), commit_path());
+    paths.insert("/commit-report".into(), commit_report_path());
+    paths.insert("/copy".into(), copy_path());
+    paths.insert("/health".into(), health_path());
+    paths.insert("/upload".into(), upload_path());
+    paths.insert("/upload-progress".into(), upload_progress_path());
+    paths.insert("/download/{path}".into(), download_path());
+    paths.insert("/search".into(), search_path());
+    paths.insert("/jobs".into(), jobs_path());
+    paths.insert("/duplicates".into(), duplicates_path());
+    paths.insert("/duplicates/merge".into(), merge_duplicates_path());
+    paths.insert("/export-html".into(), export_html_path());
+    paths.insert("/link-report".into(), link_report_path());
+    paths.insert("/lock".into(), lock_path());
+    paths.insert("/agent-activity".into(), agent_activity_path());
+    paths.insert("/agent-overview".into(), agent_overview_path());
+    paths.insert("/pins".into(), pins_path());
+    paths.insert("/preview".into(), preview_path());
+    paths.insert("/inbox".into(), inbox_path());
+    paths.insert("/metrics".into(), metrics_path());
+    paths.insert("/replication/export".into(), replication_export_path());
+    paths.insert("/replication/stream".into(), replication_stream_path());
+    paths.insert("/retention".into(), retention_path());
+    paths.insert("/rights".into(), rights_path());
+    paths.insert("/schema".into(), schema_path());
+    paths.insert("/setup".into(), setup_path());
+    paths.insert("/table".into(), table_path());
+
+    for endpoint in store.get_endpoints() {
+        if SERVER_HANDLED_ENDPOINTS.contains(&endpoint.path.as_str()) {
+            continue;
+        }
+        paths.insert(endpoint.path.clone(), endpoint_path(appstate, endpoint));
+    }
+    if let Some(query) = paths
+        .get_mut(atomic_lib::urls::PATH_QUERY)
+        .and_then(|p| p.as_object_mut())
+    {
+        query.insert("post".into(), structured_query_operation());
+    }
+
+    // Catch-all, registered last just like in `routes.rs`
+    paths.insert("/{path}".into(), resource_path());
+
+    let mut document = json!({
+        "openapi": OPENAPI_VERSION,
+        "info": {
+            "title": "Atomic-Server",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "HTTP API of Atomic-Server. Every Resource on this server can be fetched at its subject, using content negotiation. See https://docs.atomicdata.dev",
+        },
+        "servers": [{ "url": store.get_server_url() }],
+        "paths": paths,
+        "components": {
+            "schemas": {
+                "Resource": {
+                    "type": "object",
+                    "description": "A JSON-AD Resource. Keys are Property URLs, `@id` is the subject.",
+                    "properties": { "@id": { "type": "string", "format": "uri" } },
+                    "additionalProperties": true,
+                },
+                "Commit": {
+                    "type": "object",
+                    "description": "A signed JSON-AD Commit. See https://docs.atomicdata.dev/commits/intro.html",
+                    "required": [
+                        "https://atomicdata.dev/properties/subject",
+                        "https://atomicdata.dev/properties/signer",
+                        "https://atomicdata.dev/properties/createdAt",
+                        "https://atomicdata.dev/properties/signature",
+                    ],
+                    "additionalProperties": true,
+                },
+                "Error": {
+                    "type": "object",
+                    "description": "An Error Resource, see https://atomicdata.dev/classes/Error",
+                    "additionalProperties": true,
+                },
+            },
+            "responses": {
+                "Error": {
+                    "description": "Something went wrong. The body describes the error.",
+                    "content": { "application/ad+json": { "schema": { "$ref": "#/components/schemas/Error" } } },
+                },
+            },
+        },
+    });
+
+    // In public mode, no authentication is checked at all
+    if !appstate.config.opts.public_mode {
+        document["components"]["securitySchemes"] = security_schemes();
+        document["security"] = json!([
+            {},
+            { "bearerAuth": [] },
+            {
+                "atomicAgent": [],
+                "atomicPublicKey": [],
+                "atomicSignature": [],
+                "atomicTimestamp": [],
+            },
+        ]);
+    }
+
+    Ok(document)
+}
+
+/// The signed headers and the bearer token described in https://docs.atomicdata.dev/authentication.html
+fn security_schemes() -> JsonValue {
+    json!({
+        "bearerAuth": {
+            "type": "http",
+            "scheme": "bearer",
+            "description": "A base64 encoded JSON object containing the same values as the `x-atomic-*` headers.",
+        },
+        "atomicAgent": {
+            "type": "apiKey",
+            "in": "header",
+            "name": "x-atomic-agent",
+            "description": "Subject of the Agent signing the request.",
+        },
+        "atomicPublicKey": {
+            "type": "apiKey",
+            "in": "header",
+            "name": "x-atomic-public-key",
+            "description": "Base64 encoded ed25519 public key of the Agent.",
+        },
+        "atomicSignature": {
+            "type": "apiKey",
+            "in": "header",
+            "name": "x-atomic-signature",
+            "description": "Base64 encoded signature of `{requested subject} {timestamp}`.",
+        },
+        "atomicTimestamp": {
+            "type": "apiKey",
+            "in": "header",
+            "name": "x-atomic-timestamp",
+            "description": "Unix timestamp in milliseconds. Signatures expire after a short while.",
+        },
+    })
+}
+
+fn error_responses() -> JsonValue {
+    json!({
+        "400": { "$ref": "#/components/responses/Error" },
+        "401": { "$ref": "#/components/responses/Error" },
+        "404": { "$ref": "#/components/responses/Error" },
+    })
+}
+
+/// Merges the error responses with the given successful responses.
+fn responses(success: JsonValue) -> JsonValue {
+    let mut responses = error_responses();
+    if let (Some(map), JsonValue::Object(success)) = (responses.as_object_mut(), success) {
+        map.extend(success);
+    }
+    responses
+}
+
+fn resource_response(description: &str) -> JsonValue {
+    let content: Map<String, JsonValue> = RESOURCE_CONTENT_TYPES
+        .iter()
+        .map(|ct| {
+            let schema = match ct {
+                ContentType::JsonAd | ContentType::Json | ContentType::JsonLd => {
+                    json!({ "$ref": "#/components/schemas/Resource" })
+                }
+                _ => json!({ "type": "string" }),
+            };
+            (ct.to_mime().to_string(), json!({ "schema": schema }))
+        })
+        .collect();
+    json!({ "description": description, "content": content })
+}
+
+fn json_ad_response(description: &str) -> JsonValue {
+    json!({
+        "description": description,
+        "content": { "application/ad+json": { "schema": { "$ref": "#/components/schemas/Resource" } } },
+    })
+}
+
+fn query_param(name: &str, description: &str, required: bool, schema: JsonValue) -> JsonValue {
+    json!({
+        "name": name,
+        "in": "query",
+        "description": description,
+        "required": required,
+        "schema": schema,
+    })
+}
+
+fn resource_path() -> JsonValue {
+    json!({
+        "get": {
+            "operationId": "getResource",
+            "summary": "Fetch any Resource by its subject",
+            "description": "The serialization is picked using the `Accept` header. Accepting an Invite works by adding a `public-key` or `agent` query parameter to the subject of the Invite.",
+            "parameters": [
+                { "name": "path", "in": "path", "required": true, "schema": { "type": "string" } },
+                query_param("offset", "Offset for paginated ResourceArrays.", false, json!({ "type": "integer", "minimum": 0 })),
+                query_param("limit", "Maximum amount of items in paginated ResourceArrays.", false, json!({ "type": "integer", "minimum": 1 })),
+                query_param("fields", "Comma separated Property URLs or shortnames. Only these properties are serialized. Use dots for nested Resources, e.g. `members.name`.", false, json!({ "type": "string" })),
+                query_param("explain", "If access is denied, add the Resources that were checked and where the check ended to the Error, as `error-rights-trace`. See `/rights`.", false, json!({ "type": "boolean" })),
+                query_param("public-key", "Invites only: public key of a new Agent accepting the Invite.", false, json!({ "type": "string" })),
+                query_param("agent", "Invites only: subject of the Agent accepting the Invite.", false, json!({ "type": "string", "format": "uri" })),
+            ],
+            "responses": responses(json!({ "200": resource_response("The requested Resource") })),
+        },
+        "post": {
+            "operationId": "postResource",
+            "summary": "Send a POST request to an Endpoint",
+            "requestBody": { "content": { "application/octet-stream": { "schema": { "type": "string", "format": "binary" } } } },
+            "parameters": [
+                { "name": "path", "in": "path", "required": true, "schema": { "type": "string" } },
+            ],
+            "responses": responses(json!({ "200": resource_response("The resulting Resource") })),
+        },
+    })
+}
+
+fn commit_path() -> JsonValue {
+    json!({
+        "post": {
+            "operationId": "postCommit",
+            "summary": "Apply a signed Commit",
+            "requestBody": {
+                "required": true,
+                "content": { "application/ad+json": { "schema": { "$ref": "#/components/schemas/Commit" } } },
+            },
+            "responses": responses(json!({
+                "200": json_ad_response("The applied Commit"),
+                "413": { "$ref": "#/components/responses/Error" },
+                "429": { "$ref": "#/components/responses/Error" },
+            })),
+        },
+    })
+}
+
+fn structured_query_operation() -> JsonValue {
+    let condition = json!({
+        "type": "object",
+        "required": ["property", "operator", "value"],
+        "properties": {
+            "property": { "type": "string", "format": "uri" },
+            "operator": { "type": "string", "enum": ["eq", "neq", "lt", "gt", "contains"] },
+            "value": { "description": "A string, number or boolean. Dates (`YYYY-MM-DD`) can be compared with Timestamps." },
+        },
+    });
+    json!({
+        "operationId": "structuredQuery",
+        "summary": "Query Resources by Class, parent, property conditions and full-text",
+        "description": "Every filter is resolved using an index. At least one filter is required. Only Resources that the Agent can read are returned and counted.",
+        "requestBody": {
+            "required": true,
+            "content": { "application/json": { "schema": {
+                "type": "object",
+                "properties": {
+                    "class": { "type": "string", "format": "uri" },
+                    "parent": { "type": "string", "format": "uri", "description": "Only include descendants of this Resource." },
+                    "conditions": { "type": "array", "items": condition },
+                    "text": { "type": "string", "description": "Full-text search term." },
+                    "sortBy": { "type": "string", "format": "uri" },
+                    "sortDesc": { "type": "boolean" },
+                    "limit": { "type": "integer", "minimum": 1 },
+                    "offset": { "type": "integer", "minimum": 0 },
+                    "includeNested": { "type": "boolean" },
+                    "cursor": { "type": "string", "description": "The `nextCursor` of the previous page. Use an empty string for the first page." },
+                },
+            } } },
+        },
+        "responses": responses(json!({
+            "200": json_ad_response("A Resource with the matching `members` and `totalMembers`, and a `nextCursor` if a `cursor` was passed and more members follow"),
+            "410": { "$ref": "#/components/responses/Error" },
+        })),
+    })
+}
+
+fn health_path() -> JsonValue {
+    let health = json!({ "application/json": { "schema": { "type": "object", "properties": {
+        "status": { "type": "string", "enum": ["ok", "degraded"] },
+        "audit": { "type": "object", "properties": {
+            "enabled": { "type": "boolean" },
+            "strict": { "type": "boolean" },
+            "lastSeq": { "type": "integer" },
+            "missed": { "type": "integer" },
+            "error": { "type": "string", "nullable": true },
+        } },
+        "search": { "type": "object", "properties": {
+            "language": { "type": "string", "enum": ["raw", "english", "dutch"] },
+            "asciiFolding": { "type": "boolean" },
+            "stale": { "type": "boolean", "description": "The search index is being rebuilt after the analyzer changed" },
+        } },
+        "replication": { "type": "object", "description": "Only for read replicas", "properties": {
+            "primary": { "type": "string" },
+            "connected": { "type": "boolean" },
+            "lastAppliedCommit": { "type": "integer", "nullable": true },
+            "lagMs": { "type": "integer", "nullable": true },
+            "lastError": { "type": "string", "nullable": true },
+        } },
+        "connections": { "type": "object", "properties": {
+            "open": { "type": "integer", "description": "Connections that are currently open" },
+            "total": { "type": "integer", "description": "Connections that were opened since the server started" },
+            "downloads": { "type": "integer", "description": "Downloads that are currently running" },
+        } },
+    } } } });
+    json!({
+        "get": {
+            "operationId": "health",
+            "summary": "Check whether the server works as expected",
+            "responses": {
+                "200": { "description": "The server is healthy", "content": health },
+                "503": { "description": "The audit log can't be written, or a replica lost its primary", "content": health },
+            },
+        },
+    })
+}
+
+fn replication_export_path() -> JsonValue {
+    json!({
+        "get": {
+            "operationId": "replicationExport",
+            "summary": "Export all Resources, to bootstrap a read replica. Requires write rights to the root Drive.",
+            "responses": responses(json!({ "200": {
+                "description": "All Resources as JSON-AD",
+                "headers": { "X-Replication-Since": {
+                    "description": "When the export was started, in milliseconds since the Unix epoch. Pass it as `since` to the stream.",
+                    "schema": { "type": "integer" },
+                } },
+                "content": { "application/ad+json": { "schema": { "type": "array", "items": { "$ref": "#/components/schemas/Resource" } } } },
+            } })),
+        },
+    })
+}
+
+fn replication_stream_path() -> JsonValue {
+    json!({
+        "get": {
+            "operationId": "replicationStream",
+            "summary": "Follow all applied Commits as Server-Sent Events, for read replicas. Requires write rights to the root Drive.",
+            "parameters": [
+                query_param("since", "First send the Commits created at or after this time, in milliseconds since the Unix epoch.", false, json!({ "type": "integer" })),
+            ],
+            "responses": responses(json!({ "200": {
+                "description": "A `data` event with the JSON-AD of every Commit, and a `keep-alive` comment every 15 seconds",
+                "content": { "text/event-stream": { "schema": { "type": "string" } } },
+            } })),
+        },
+    })
+}
+
+fn agent_activity_path() -> JsonValue {
+    let counts = json!({ "type": "object", "properties": {
+        "reads": { "type": "integer" },
+        "commits": { "type": "integer" },
+        "downloads": { "type": "integer" },
+        "downloadBytes": { "type": "integer" },
+        "bytesServed": { "type": "integer" },
+        "subjects": { "type": "integer" },
+    } });
+    let mut day = counts.clone();
+    day["properties"]["date"] = json!({ "type": "string", "format": "date" });
+    json!({
+        "get": {
+            "operationId": "agentActivity",
+            "summary": "Show the reads, Commits and downloads per Agent since the server started, and which `--activity-limits` they exceed. Requires write rights to the root Drive.",
+            "parameters": [
+                query_param("agent", "Only show this Agent.", false, json!({ "type": "string", "format": "uri" })),
+            ],
+            "responses": responses(json!({ "200": {
+                "description": "The activity, most reads today first",
+                "content": { "application/json": { "schema": { "type": "object", "properties": {
+                    "limits": { "type": "array", "items": { "type": "string" } },
+                    "suspend": { "type": "boolean" },
+                    "agents": { "type": "array", "items": { "type": "object", "properties": {
+                        "agent": { "type": "string" },
+                        "currentHour": counts,
+                        "days": { "type": "array", "items": day },
+                        "exceeded": { "type": "array", "items": { "type": "string" } },
+                        "suspended": { "type": "boolean" },
+                    } } },
+                } } } },
+            } })),
+        },
+    })
+}
+
+fn agent_overview_path() -> JsonValue {
+    let page = |item: JsonValue| {
+        json!({ "type": "object", "properties": {
+            "items": { "type": "array", "items": item },
+            "total": { "type": "integer" },
+            "hasNextPage": { "type": "boolean" },
+        } })
+    };
+    let resource = json!({ "type": "object", "properties": {
+        "subject": { "type": "string" },
+        "title": { "type": "string" },
+    } });
+    let rights = json!({ "type": "object", "properties": {
+        "subject": { "type": "string" },
+        "title": { "type": "string" },
+        "rights": { "type": "array", "items": { "type": "string", "enum": ["read", "write", "append"] } },
+    } });
+    let commit = json!({ "type": "object", "properties": {
+        "commit": { "type": "string" },
+        "subject": { "type": "string" },
+        "createdAt": { "type": "integer" },
+        "destroy": { "type": "boolean" },
+    } });
+    json!({
+        "get": {
+            "operationId": "agentOverview",
+            "summary": "List the Resources an Agent has rights to, the Resources it created and its recent Commits. Only the Agent itself and Agents with write rights to the root Drive can see it.",
+            "parameters": [
+                query_param("agent", "Subject of the Agent. Defaults to the Agent that signs the request.", false, json!({ "type": "string", "format": "uri" })),
+                query_param("page", "Page number, starting at 0. Applies to every list.", false, json!({ "type": "integer", "minimum": 0 })),
+            ],
+            "responses": responses(json!({ "200": {
+                "description": "The overview. Browsers get an HTML page instead.",
+                "content": {
+                    "application/json": { "schema": { "type": "object", "properties": {
+                        "agent": { "type": "string" },
+                        "agentName": { "type": "string", "nullable": true },
+                        "isSelf": { "type": "boolean" },
+                        "page": { "type": "integer" },
+                        "pageSize": { "type": "integer" },
+                        "rights": page(rights),
+                        "created": page(resource),
+                        "commits": page(commit),
+                    } } },
+                    "text/html": { "schema": { "type": "string" } },
+                },
+            } })),
+        },
+    })
+}
+
+fn pins_path() -> JsonValue {
+    let pin = json!({ "type": "object", "properties": {
+        "subject": { "type": "string" },
+        "name": { "type": "string", "nullable": true },
+        "class": { "type": "string", "nullable": true },
+        "mimetype": { "type": "string", "nullable": true },
+    } });
+    json!({
+        "get": {
+            "operationId": "pins",
+            "summary": "List the pinned Resources of the Agent that signs the request, in order. Change them with Commits on the Agent's `pins` array.",
+            "responses": responses(json!({ "200": {
+                "description": "The pins that the Agent can read, and the subjects of those it can no longer read",
+                "content": { "application/json": { "schema": { "type": "object", "properties": {
+                    "pins": { "type": "array", "items": pin },
+                    "unavailable": { "type": "array", "items": { "type": "string" } },
+                } } } },
+            } })),
+        },
+    })
+}
+
+fn inbox_path() -> JsonValue {
+    let notification = json!({ "type": "object", "properties": {
+        "subject": { "type": "string" },
+        "about": { "type": "string" },
+        "name": { "type": "string", "nullable": true },
+        "property": { "type": "string", "nullable": true },
+        "commit": { "type": "string" },
+        "createdAt": { "type": "integer", "nullable": true },
+    } });
+    json!({
+        "get": {
+            "operationId": "inbox",
+            "summary": "List the unread Notifications of the Agent that signs the request, newest first. Mark them as read with a Commit that sets `isRead` to `true`.",
+            "parameters": [
+                query_param("page", "The page, starting at 0.", false, json!({ "type": "integer" })),
+                query_param("page-size", "Notifications per page, at most 100. Defaults to 30.", false, json!({ "type": "integer" })),
+            ],
+            "responses": responses(json!({ "200": {
+                "description": "A page of unread Notifications, and the total amount of unread Notifications",
+                "content": { "application/json": { "schema": { "type": "object", "properties": {
+                    "notifications": { "type": "array", "items": notification },
+                    "total": { "type": "integer" },
+                } } } },
+            } })),
+        },
+    })
+}
+
+fn metrics_path() -> JsonValue {
+    let operation = json!({ "type": "object", "properties": {
+        "count": { "type": "integer" },
+        "totalMs": { "type": "number" },
+        "p50Ms": { "type": "number" },
+        "p99Ms": { "type": "number" },
+    } });
+    let metrics = json!({ "application/json": { "schema": { "type": "object", "properties": {
+        "operations": { "type": "object", "additionalProperties": operation },
+        "slowThresholdMs": { "type": "number", "nullable": true },
+        "slowOperations": { "type": "array", "items": { "type": "object", "properties": {
+            "operation": { "type": "string" },
+            "subject": { "type": "string" },
+            "location": { "type": "string" },
+            "durationMs": { "type": "number" },
+            "at": { "type": "integer" },
+        } } },
+    } } } });
+    json!({
+        "get": {
+            "operationId": "metrics",
+            "summary": "Count and time store operations, and list the most recent slow ones. Requires write rights to the root Drive.",
+            "responses": responses(json!({ "200": { "description": "Metrics per operation", "content": metrics } })),
+        },
+    })
+}
+
+fn retention_path() -> JsonValue {
+    let subjects = json!({ "type": "array", "items": { "type": "string", "format": "uri" } });
+    json!({
+        "get": {
+            "operationId": "retention",
+            "summary": "Show what the RetentionPolicies would remove, without changing anything. The `apply-retention` Job carries it out. Requires write rights to the root Drive.",
+            "parameters": [
+                query_param("at", "Plan the run at this unix timestamp in milliseconds. Defaults to now.", false, json!({ "type": "integer" })),
+            ],
+            "responses": responses(json!({ "200": {
+                "description": "The plan of the run",
+                "content": { "application/json": { "schema": { "type": "object", "properties": {
+                    "moment": { "type": "integer" },
+                    "policies": subjects,
+                    "skippedPolicies": { "type": "array", "items": { "type": "object", "properties": {
+                        "subject": { "type": "string", "format": "uri" },
+                        "reason": { "type": "string" },
+                    } } },
+                    "compact": { "type": "array", "items": { "type": "object", "properties": {
+                        "subject": { "type": "string", "format": "uri" },
+                        "commits": subjects,
+                        "baseline": { "type": "string", "format": "uri", "nullable": true },
+                    } } },
+                    "delete": subjects,
+                    "flagged": { "type": "array", "items": { "type": "object", "properties": {
+                        "subject": { "type": "string", "format": "uri" },
+                        "keptDescendants": subjects,
+                    } } },
+                } } } },
+            } })),
+        },
+    })
+}
+
+fn rights_path() -> JsonValue {
+    let trace = json!({ "type": "object", "properties": {
+        "subject": { "type": "string" },
+        "agent": { "type": "string" },
+        "right": { "type": "string" },
+        "allowed": { "type": "boolean" },
+        "explanation": { "type": "string" },
+        "decidedAt": { "type": "string", "nullable": true },
+        "steps": { "type": "array", "items": { "type": "object", "properties": {
+            "subject": { "type": "string" },
+            "rights": { "type": "array", "items": { "type": "string" }, "nullable": true },
+            "grant": { "type": "string", "enum": ["root", "itself", "recipient", "agent", "public"], "nullable": true },
+            "note": { "type": "string", "nullable": true },
+        } } },
+    } });
+    json!({
+        "get": {
+            "operationId": "explainRights",
+            "summary": "Explain why an Agent can or can't read and write a Resource",
+            "description": "Agents can ask about themselves, asking about other Agents requires write rights to the Resource. The rights of each step are only shown to Agents with write rights.",
+            "parameters": [
+                query_param("subject", "The Resource to check.", true, json!({ "type": "string", "format": "uri" })),
+                query_param("agent", "The Agent to check. Defaults to the Agent that signs the request.", false, json!({ "type": "string", "format": "uri" })),
+            ],
+            "responses": responses(json!({ "200": {
+                "description": "The read and write decision, with every Resource that was checked, and which properties restricted by `propertyRights` the Agent may change",
+                "content": { "application/json": { "schema": { "type": "object", "properties": {
+                    "read": trace,
+                    "write": trace,
+                    "properties": { "type": "array", "items": { "type": "object", "properties": {
+                        "property": { "type": "string", "format": "uri" },
+                        "allowed": { "type": "boolean" },
+                        "restrictedIn": { "type": "array", "items": { "type": "string", "format": "uri" } },
+                        "explanation": { "type": "string" },
+                    } } },
+                } } } },
+            } })),
+        },
+    })
+}
+
+fn commit_report_path() -> JsonValue {
+    json!({
+        "get": {
+            "operationId": "commitReport",
+            "summary": "List the Agents that created the most Commits recently",
+            "description": "Requires write rights to the root Drive.",
+            "parameters": [
+                query_param("hours", "How far back the report goes. Defaults to 24.", false, json!({ "type": "integer", "minimum": 1 })),
+                query_param("limit", "Maximum amount of Agents. Defaults to 20.", false, json!({ "type": "integer", "minimum": 1 })),
+            ],
+            "responses": responses(json!({ "200": {
+                "description": "Commit counts per Agent",
+                "content": { "application/json": { "schema": { "type": "object", "properties": {
+                    "since": { "type": "integer" },
+                    "agents": { "type": "array", "items": { "type": "object", "properties": {
+                        "agent": { "type": "string", "format": "uri" },
+                        "commits": { "type": "integer" },
+                    } } },
+                } } } },
+            } })),
+        },
+    })
+}
+
+fn upload_path() -> JsonValue {
+    json!({
+        "post": {
+            "operationId": "upload",
+            "summary": "Upload one or more files",
+            "description": "Creates a File Resource for every uploaded file. Requires write rights on the parent.",
+            "parameters": [
+                query_param("parent", "Subject of the Resource the files are attached to.", true, json!({ "type": "string", "format": "uri" })),
+                query_param("progress", "A random token of 16 to 128 characters, for following the upload at `/upload-progress`.", false, json!({ "type": "string" })),
+            ],
+            "requestBody": {
+                "required": true,
+                "content": { "multipart/form-data": { "schema": {
+                    "type": "object",
+                    "properties": {
+                        "assets": { "type": "array", "items": { "type": "string", "format": "binary" } },
+                        "metadata": { "type": "string", "description": "JSON array with a `{name, description, shortname, mimetype}` object for every file, in the order of the files." },
+                    },
+                } } },
+            },
+            "responses": responses(json!({ "200": {
+                "description": "The created File Resources. A file with invalid metadata is replaced by an Error Resource at its index.",
+                "content": { "application/ad+json": { "schema": { "type": "array", "items": { "$ref": "#/components/schemas/Resource" } } } },
+            } })),
+        },
+    })
+}
+
+fn upload_progress_path() -> JsonValue {
+    json!({
+        "get": {
+            "operationId": "uploadProgress",
+            "summary": "Bytes received by a running upload",
+            "description": "Only available to the Agent that started the upload, until it finishes.",
+            "parameters": [
+                query_param("token", "The `progress` token that was passed to `/upload`.", true, json!({ "type": "string" })),
+            ],
+            "responses": responses(json!({ "200": {
+                "description": "The progress of the upload",
+                "content": { "application/json": { "schema": { "type": "object", "properties": {
+                    "received": { "type": "integer" },
+                    "total": { "type": "integer", "nullable": true },
+                    "files": { "type": "integer" },
+                } } } },
+            } })),
+        },
+    })
+}
+
+fn download_path() -> JsonValue {
+    json!({
+        "get": {
+            "operationId": "download",
+            "summary": "Download the contents of an uploaded File",
+            "parameters": [
+                { "name": "path", "in": "path", "required": true, "schema": { "type": "string" } },
+            ],
+            "responses": responses(json!({
+                "200": {
+                    "description": "The file contents",
+                    "content": { "application/octet-stream": { "schema": { "type": "string", "format": "binary" } } },
+                },
+                "429": { "$ref": "#/components/responses/Error" },
+            })),
+        },
+    })
+}
+
+fn search_path() -> JsonValue {
+    json!({
+        "get": {
+            "operationId": "search",
+            "summary": "Full-text search",
+            "parameters": [
+                query_param("q", "The text to search for. Supports `AND`, `OR` and quoted phrases.", false, json!({ "type": "string" })),
+                query_param("include", "Include the full Resources in the response.", false, json!({ "type": "boolean" })),
+                query_param("limit", "Maximum amount of results.", false, json!({ "type": "integer", "minimum": 1 })),
+                query_param("parents", "Comma separated subjects. Only returns descendants of these Resources.", false, json!({ "type": "string" })),
+                query_param("filters", "Property filters using the tantivy QueryParser syntax, e.g. `prop:val`.", false, json!({ "type": "string" })),
+            ],
+            "responses": responses(json!({ "200": json_ad_response("A search result Resource") })),
+        },
+    })
+}
+
+fn jobs_path() -> JsonValue {
+    json!({
+        "post": {
+            "operationId": "createJob",
+            "summary": "Start a background Job, such as exporting a subtree",
+            "parameters": [
+                query_param("type", "The kind of Job.", true, json!({ "type": "string", "enum": ["rebuild-indexes", "export-subtree", "purge-trash", "check-links", "remove-expired", "compact-history", "normalize-values", "check-attachments", "coerce-values", "extract-text", "apply-retention", "backup", "verify-backup"] })),
+                query_param("subject", "The Resource the Job acts on. Required for `export-subtree`, `compact-history` and `extract-text`, optional for `check-links`.", false, json!({ "type": "string", "format": "uri" })),
+                query_param("dry-run", "For `normalize-values`: only report the Values that are not normalized. For `coerce-values`: only report the Values that don't match their datatype. For `check-attachments`: only report the issues, defaults to `true`.", false, json!({ "type": "boolean" })),
+                query_param("verify", "For `backup`: verify the backup after writing it. Defaults to `--verify-backups`.", false, json!({ "type": "boolean" })),
+                query_param("backup", "For `verify-backup`: the name of the folder in the backups directory. Defaults to the latest backup.", false, json!({ "type": "string" })),
+                query_param("sample", "For `backup` and `verify-backup`: how many Resources are compared with the live store. Defaults to 100.", false, json!({ "type": "integer" })),
+            ],
+            "responses": responses(json!({ "200": json_ad_response("The created Job") })),
+        },
+    })
+}
+
+fn copy_path() -> JsonValue {
+    json!({
+        "post": {
+            "operationId": "copyResource",
+            "summary": "Copy a Resource, and optionally its descendants, to a new parent",
+            "parameters": [
+                query_param("subject", "The Resource to copy.", true, json!({ "type": "string", "format": "uri" })),
+                query_param("parent", "The parent of the copy.", true, json!({ "type": "string", "format": "uri" })),
+                query_param("recursive", "Also copy all descendants.", false, json!({ "type": "boolean" })),
+                query_param("include-files", "Also copy Files, including their contents.", false, json!({ "type": "boolean" })),
+            ],
+            "responses": responses(json!({ "200": {
+                "description": "The copied subjects, mapped to the subjects of their copies",
+                "content": { "application/json": { "schema": {
+                    "type": "object",
+                    "additionalProperties": { "type": "string", "format": "uri" },
+                } } },
+            } })),
+        },
+    })
+}
+
+fn link_report_path() -> JsonValue {
+    json!({
+        "get": {
+            "operationId": "linkReport",
+            "summary": "List the broken links to other servers, found by the last `check-links` Job",
+            "parameters": [
+                query_param("subject", "Only list the broken links of this Resource.", false, json!({ "type": "string", "format": "uri" })),
+            ],
+            "responses": responses(json!({ "200": json_ad_response("The LinkReport") })),
+        },
+    })
+}
+
+fn preview_path() -> JsonValue {
+    json!({
+        "get": {
+            "operationId": "preview",
+            "summary": "A small summary of a Resource or web page, for rendering link cards",
+            "description": "`404` means the Resource doesn't exist, `401` that the Agent can't read it. Web pages on other servers require `--external-link-previews`, and respond with `502` if they can't be fetched. Send the `ETag` as `If-None-Match` to get `304` while the preview is unchanged.",
+            "parameters": [
+                query_param("subject", "The Resource or web page to preview.", true, json!({ "type": "string", "format": "uri" })),
+            ],
+            "responses": responses(json!({
+                "200": {
+                    "description": "The preview",
+                    "content": { "application/json": { "schema": { "type": "object", "properties": {
+                        "subject": { "type": "string", "format": "uri" },
+                        "name": { "type": "string" },
+                        "description": { "type": "string" },
+                        "class": { "type": "string" },
+                        "thumbnail": { "type": "string", "format": "uri" },
+                        "childCount": { "type": "integer" },
+                        "external": { "type": "boolean" },
+                    } } } },
+                },
+                "304": { "description": "The preview has not changed" },
+                "502": { "description": "The web page could not be fetched" },
+            })),
+        },
+    })
+}
+
+fn duplicates_path() -> JsonValue {
+    json!({
+        "get": {
+            "operationId": "findDuplicates",
+            "summary": "List groups of Resources with equal values for the given Properties. Requires write rights to the root Drive.",
+            "parameters": [
+                query_param("props", "Comma-separated Property URLs that must all have equal values.", true, json!({ "type": "string" })),
+                query_param("class", "Only compare instances of this Class.", false, json!({ "type": "string", "format": "uri" })),
+            ],
+            "responses": responses(json!({ "200": json_ad_response("An array with a nested Resource per group, listing the subjects as `members`") })),
+        },
+    })
+}
+
+fn merge_duplicates_path() -> JsonValue {
+    json!({
+        "post": {
+            "operationId": "mergeDuplicates",
+            "summary": "Move the references to the removed duplicates to the kept Resource, and move the removed duplicates to the trash. Requires write rights to the root Drive.",
+            "parameters": [
+                query_param("keep", "The duplicate that is kept.", true, json!({ "type": "string", "format": "uri" })),
+                query_param("remove", "Comma-separated subjects of the duplicates to remove.", true, json!({ "type": "string" })),
+                query_param("dry-run", "Only report the changes, without making them.", false, json!({ "type": "boolean" })),
+            ],
+            "responses": responses(json!({ "200": {
+                "description": "The updated referrers and the removed duplicates",
+                "content": { "application/json": { "schema": { "type": "object", "properties": {
+                    "updated": { "type": "array", "items": { "type": "object", "properties": {
+                        "subject": { "type": "string", "format": "uri" },
+                        "properties": { "type": "array", "items": { "type": "string", "format": "uri" } },
+                    } } },
+                    "removed": { "type": "array", "items": { "type": "string", "format": "uri" } },
+                } } } },
+            } })),
+        },
+    })
+}
+
+fn lock_path() -> JsonValue {
+    let parameters = json!([
+        query_param(
+            "subject",
+            "The Resource to lock or unlock.",
+            true,
+            json!({ "type": "string", "format": "uri" })
+        ),
+        query_param(
+            "duration",
+            "Duration of the lock in seconds.",
+            false,
+            json!({ "type": "integer", "minimum": 1 })
+        ),
+    ]);
+    json!({
+        "post": {
+            "operationId": "lockResource",
+            "summary": "Lock a Resource for the requesting Agent",
+            "parameters": parameters,
+            "responses": responses(json!({
+                "200": json_ad_response("The locked Resource"),
+                "423": { "$ref": "#/components/responses/Error" },
+            })),
+        },
+        "delete": {
+            "operationId": "unlockResource",
+            "summary": "Release the lock of a Resource",
+            "parameters": parameters,
+            "responses": responses(json!({ "200": json_ad_response("The unlocked Resource") })),
+        },
+    })
+}
+
+fn setup_path() -> JsonValue {
+    json!({
+        "post": {
+            "operationId": "completeSetup",
+            "summary": "Create the admin Agent of a new server, using the setup token from the log",
+            "parameters": [
+                query_param("token", "The one-time setup token.", true, json!({ "type": "string" })),
+            ],
+            "requestBody": {
+                "required": false,
+                "content": { "application/json": { "schema": { "type": "object", "properties": {
+                    "publicKey": { "type": "string", "description": "If not set, a keypair is generated." },
+                    "name": { "type": "string" },
+                } } } },
+            },
+            "responses": responses(json!({
+                "200": {
+                    "description": "The admin Agent, and its private key if it was generated",
+                    "content": { "application/json": { "schema": { "type": "object", "properties": {
+                        "agent": { "type": "string", "format": "uri" },
+                        "privateKey": { "type": "string" },
+                    } } } },
+                },
+                "410": { "$ref": "#/components/responses/Error" },
+            })),
+        },
+    })
+}
+
+fn export_html_path() -> JsonValue {
+    json!({
+        "get": {
+            "operationId": "exportHtml",
+            "summary": "Export a Resource, and optionally its children, as a self-contained HTML document",
+            "description": "CSS is inlined, Markdown is rendered, links are absolute and small images of Files are embedded. The output only changes when the exported Resources change.",
+            "parameters": [
+                query_param("subject", "The Resource to export.", true, json!({ "type": "string", "format": "uri" })),
+                query_param("depth", "How many levels of children to include, at most 5. Children are preceded by a table of contents.", false, json!({ "type": "integer", "minimum": 0, "maximum": 5, "default": 0 })),
+                query_param("lang", "The language of the labels, e.g. `nl`.", false, json!({ "type": "string" })),
+            ],
+            "responses": responses(json!({ "200": {
+                "description": "The document",
+                "content": { "text/html": { "schema": { "type": "string" } } },
+            } })),
+        },
+    })
+}
+
+fn table_path() -> JsonValue {
+    json!({
+        "get": {
+            "operationId": "childrenTable",
+            "summary": "Show the children of a Resource as an HTML table, with a column per Property of their Class",
+            "parameters": [
+                query_param("parent", "The Resource whose children are shown.", true, json!({ "type": "string", "format": "uri" })),
+                query_param("class", "The Class whose Properties are the columns. Defaults to the Class most children share.", false, json!({ "type": "string", "format": "uri" })),
+                query_param("sort", "Shortname or subject of the Property to sort by.", false, json!({ "type": "string" })),
+                query_param("desc", "Sort descending.", false, json!({ "type": "boolean" })),
+                query_param("columns", "Comma separated shortnames of the columns to show.", false, json!({ "type": "string" })),
+                query_param("page", "Page number, starting at 0.", false, json!({ "type": "integer", "minimum": 0 })),
+            ],
+            "responses": responses(json!({ "200": {
+                "description": "The table",
+                "content": { "text/html": { "schema": { "type": "string" } } },
+            } })),
+        },
+    })
+}
+
+fn schema_path() -> JsonValue {
+    json!({
+        "get": {
+            "operationId": "schema",
+            "summary": "List all Classes and Properties, with their instance counts",
+            "responses": responses(json!({ "200": {
+                "description": "The schema overview. Browsers get an HTML page instead.",
+                "content": {
+                    "application/json": { "schema": { "type": "object", "properties": {
+                        "classes": { "type": "array", "items": { "type": "object" } },
+                        "properties": { "type": "array", "items": { "type": "object" } },
+                    } } },
+                    "text/html": { "schema": { "type": "string" } },
+                },
+            } })),
+        },
+    })
+}
+
+/// Describes an [Endpoint] using its metadata. The query parameters are derived from the Properties in `params`.
+fn endpoint_path(appstate: &AppState, endpoint: &Endpoint) -> JsonValue {
+    let parameters: Vec<JsonValue> = endpoint
+        .params
+        .iter()
+        .map(|param| match appstate.store.get_property(param) {
+            Ok(property) => query_param(
+                &property.shortname,
+                &format!("{} ({})", property.description, property.subject),
+                false,
+                property_schema(&property),
+            ),
+            Err(_) => query_param(param, param, false, json!({ "type": "string" })),
+        })
+        .collect();
+    let operation_id = endpoint.path.trim_start_matches('/').replace('/', "-");
+
+    let mut path = json!({
+        "get": {
+            "operationId": format!("get-{}", operation_id),
+            "summary": endpoint.shortname,
+            "description": endpoint.description,
+            "parameters": parameters,
+            "responses": responses(json!({ "200": resource_response("The resulting Resource") })),
+        },
+    });
+    if endpoint.handle_post.is_some() {
+        path["post"] = json!({
+            "operationId": format!("post-{}", operation_id),
+            "summary": endpoint.shortname,
+            "description": endpoint.description,
+            "parameters": parameters,
+            "requestBody": { "content": { "application/ad+json": { "schema": {} } } },
+            "responses": responses(json!({ "200": json_ad_response("The resulting Resource") })),
+        });
+    }
+    path
+}
+
+/// Converts the Datatype of a Property to a JSON Schema.
+fn property_schema(property: &Property) -> JsonValue {
+    let mut schema = match property.data_type {
+        DataType::Integer => json!({ "type": "integer" }),
+        DataType::Float => json!({ "type": "number" }),
+        DataType::Boolean => json!({ "type": "boolean" }),
+        DataType::Timestamp => json!({ "type": "integer", "format": "int64" }),
+        DataType::Date => json!({ "type": "string", "format": "date" }),
+        DataType::AtomicUrl => json!({ "type": "string", "format": "uri" }),
+        DataType::Slug => json!({ "type": "string", "pattern": "^[a-z0-9]+(?:-[a-z0-9]+)*$" }),
+        DataType::ResourceArray => {
+            json!({ "type": "array", "items": { "type": "string", "format": "uri" } })
+        }
+        DataType::Markdown | DataType::String | DataType::Unsupported(_) => {
+            json!({ "type": "string" })
+        }
+    };
+    if let Some(allowed) = &property.allows_only {
+        schema["enum"] = json!(allowed);
+    }
+    schema
+}
+
+/// Where Swagger-UI is loaded from.
+const SWAGGER_UI_CDN: &str = "https://unpkg.com";
+
+/// Allows the scripts and styles of Swagger-UI, and the inline script of [swagger_ui_html].
+pub fn swagger_ui_policy(nonce: &str) -> String {
+    format!(
+        "default-src 'self'; script-src 'self' {cdn} 'nonce-{nonce}'; style-src 'self' {cdn} 'unsafe-inline'; img-src 'self' data:; object-src 'none'; frame-ancestors 'none'",
+        cdn = SWAGGER_UI_CDN
+    )
+}
+
+/// A minimal page that renders the OpenAPI document using Swagger-UI.
+pub fn swagger_ui_html(spec_url: &str, nonce: &str) -> String {
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>Atomic-Server API</title>
+  <link rel="stylesheet" href="{SWAGGER_UI_CDN}/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="{SWAGGER_UI_CDN}/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+  <script nonce="{nonce}">
+    window.onload = () => {{
+      window.ui = SwaggerUIBundle({{ url: "{spec_url}", dom_id: "#swagger-ui" }});
+    }};
+  </script>
+</body>
+</html>"##
+    )
+}
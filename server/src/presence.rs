@@ -0,0 +1,274 @@
+//! Collaborative presence: which Agents are viewing or editing a Resource, announced over the WebSocket with `PRESENCE`.
+//! Presence is only kept in memory by the [crate::commit_monitor::CommitMonitor], and is never stored.
+//! It is removed when the connection closes, or when it is not announced again within [PRESENCE_TIMEOUT].
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+/// Clients announce their presence again before this runs out, or they are removed.
+pub const PRESENCE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Maximum amount of Resources that a single connection can be present on.
+pub const MAX_PRESENCE_PER_CONNECTION: usize = 16;
+/// Maximum amount of presences on the whole server.
+pub const MAX_PRESENCE: usize = 10_000;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceState {
+    Viewing,
+    Editing,
+    Idle,
+    /// Sent to others when a client leaves, closes the connection or times out. Clients can announce it to leave.
+    Left,
+}
+
+impl std::str::FromStr for PresenceState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewing" => Ok(PresenceState::Viewing),
+            "editing" => Ok(PresenceState::Editing),
+            "idle" => Ok(PresenceState::Idle),
+            "left" => Ok(PresenceState::Left),
+            other => Err(format!(
+                "Unknown presence state '{}', use viewing, editing, idle or left",
+                other
+            )),
+        }
+    }
+}
+
+/// The presence of an Agent on a Resource, as sent to clients.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Presence {
+    pub agent: String,
+    /// The `name` of the Agent
+    pub name: Option<String>,
+    pub state: PresenceState,
+    /// When the Agent entered this state, in milliseconds since the Unix epoch
+    pub since: i64,
+    #[serde(skip)]
+    last_seen: Instant,
+}
+
+/// Presences per subject, and per connection `C`.
+pub struct PresenceRegistry<C> {
+    subjects: HashMap<String, HashMap<C, Presence>>,
+    /// Amount of presences per connection
+    connections: HashMap<C, usize>,
+    len: usize,
+}
+
+impl<C> Default for PresenceRegistry<C> {
+    fn default() -> Self {
+        PresenceRegistry {
+            subjects: HashMap::new(),
+            connections: HashMap::new(),
+            len: 0,
+        }
+    }
+}
+
+impl<C: Hash + Eq + Clone> PresenceRegistry<C> {
+    /// Sets the state of the connection on the subject, and returns the Presence if it changed, so others can be told.
+    /// Announcing the same state again only keeps the presence from expiring.
+    /// [PresenceState::Left] removes the presence.
+    pub fn set(
+        &mut self,
+        subject: &str,
+        connection: C,
+        agent: &str,
+        name: Option<String>,
+        state: PresenceState,
+    ) -> Result<Option<Presence>, String> {
+        if state == PresenceState::Left {
+            return Ok(self.remove(subject, &connection));
+        }
+        let now = Instant::now();
+        if let Some(existing) = self
+            .subjects
+            .get_mut(subject)
+            .and_then(|connections| connections.get_mut(&connection))
+        {
+            existing.last_seen = now;
+            if existing.state == state {
+                return Ok(None);
+            }
+            existing.state = state;
+            existing.since = atomic_lib::utils::now();
+            return Ok(Some(existing.clone()));
+        }
+        if self.len >= MAX_PRESENCE {
+            return Err("Too many clients are present on this server, try again later".into());
+        }
+        let count = self.connections.entry(connection.clone()).or_default();
+        if *count >= MAX_PRESENCE_PER_CONNECTION {
+            return Err(format!(
+                "This connection is present on too many Resources (max {}). Leave one first.",
+                MAX_PRESENCE_PER_CONNECTION
+            ));
+        }
+        *count += 1;
+        let presence = Presence {
+            agent: agent.into(),
+            name,
+            state,
+            since: atomic_lib::utils::now(),
+            last_seen: now,
+        };
+        self.subjects
+            .entry(subject.into())
+            .or_default()
+            .insert(connection, presence.clone());
+        self.len += 1;
+        Ok(Some(presence))
+    }
+
+    /// Removes the presence of the connection on the subject, and returns it with the [PresenceState::Left] state.
+    pub fn remove(&mut self, subject: &str, connection: &C) -> Option<Presence> {
+        let connections = self.subjects.get_mut(subject)?;
+        let mut removed = connections.remove(connection)?;
+        if connections.is_empty() {
+            self.subjects.remove(subject);
+        }
+        if let Some(count) = self.connections.get_mut(connection) {
+            *count -= 1;
+            if *count == 0 {
+                self.connections.remove(connection);
+            }
+        }
+        self.len -= 1;
+        removed.state = PresenceState::Left;
+        removed.since = atomic_lib::utils::now();
+        Some(removed)
+    }
+
+    /// Removes all presences of a closed connection.
+    pub fn remove_connection(&mut self, connection: &C) -> Vec<(String, Presence)> {
+        if !self.connections.contains_key(connection) {
+            return Vec::new();
+        }
+        let subjects: Vec<String> = self
+            .subjects
+            .iter()
+            .filter(|(_, connections)| connections.contains_key(connection))
+            .map(|(subject, _)| subject.clone())
+            .collect();
+        subjects
+            .into_iter()
+            .filter_map(|subject| {
+                let removed = self.remove(&subject, connection)?;
+                Some((subject, removed))
+            })
+            .collect()
+    }
+
+    /// Removes the presences that were not announced within the timeout.
+    pub fn expire(&mut self, timeout: Duration) -> Vec<(String, C, Presence)> {
+        let expired: Vec<(String, C)> = self
+            .subjects
+            .iter()
+            .flat_map(|(subject, connections)| {
+                connections
+                    .iter()
+                    .filter(|(_, presence)| presence.last_seen.elapsed() > timeout)
+                    .map(|(connection, _)| (subject.clone(), connection.clone()))
+            })
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|(subject, connection)| {
+                let removed = self.remove(&subject, &connection)?;
+                Some((subject, connection, removed))
+            })
+            .collect()
+    }
+
+    /// The presences on the subject, by how long they have been in their state.
+    pub fn list(&self, subject: &str) -> Vec<Presence> {
+        let mut list: Vec<Presence> = self
+            .subjects
+            .get(subject)
+            .map(|connections| connections.values().cloned().collect())
+            .unwrap_or_default();
+        // Presences from the same millisecond are ordered by Agent, so the order is stable
+        list.sort_by(|a, b| a.since.cmp(&b.since).then_with(|| a.agent.cmp(&b.agent)));
+        list
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn presence_is_tracked_and_expires() {
+        let mut registry: PresenceRegistry<u32> = PresenceRegistry::default();
+        let alice = "https://example.com/agents/alice";
+        let changed = registry
+            .set(
+                "doc",
+                1,
+                alice,
+                Some("Alice".into()),
+                PresenceState::Viewing,
+            )
+            .unwrap();
+        assert_eq!(changed.unwrap().state, PresenceState::Viewing);
+        // Announcing the same state again is not an update
+        assert!(registry
+            .set("doc", 1, alice, None, PresenceState::Viewing)
+            .unwrap()
+            .is_none());
+        let changed = registry
+            .set("doc", 1, alice, None, PresenceState::Editing)
+            .unwrap();
+        assert_eq!(changed.unwrap().state, PresenceState::Editing);
+        registry
+            .set("other", 1, alice, None, PresenceState::Idle)
+            .unwrap();
+        registry
+            .set(
+                "doc",
+                2,
+                "https://example.com/agents/bob",
+                None,
+                PresenceState::Viewing,
+            )
+            .unwrap();
+        assert_eq!(registry.list("doc").len(), 2);
+        assert_eq!(registry.list("doc")[0].name.as_deref(), Some("Alice"));
+
+        let left = registry.remove_connection(&1);
+        assert_eq!(left.len(), 2);
+        assert!(left.iter().all(|(_, p)| p.state == PresenceState::Left));
+        assert_eq!(registry.list("doc").len(), 1);
+        assert!(registry.list("other").is_empty());
+
+        for n in 0..MAX_PRESENCE_PER_CONNECTION {
+            registry
+                .set(&n.to_string(), 3, alice, None, PresenceState::Viewing)
+                .unwrap();
+        }
+        assert!(registry
+            .set("one too many", 3, alice, None, PresenceState::Viewing)
+            .is_err());
+        assert_eq!(
+            registry.remove_connection(&3).len(),
+            MAX_PRESENCE_PER_CONNECTION
+        );
+
+        assert!(registry.expire(PRESENCE_TIMEOUT).is_empty());
+        let expired = registry.expire(Duration::ZERO);
+        assert_eq!(expired.len(), 1);
+        assert!(registry.list("doc").is_empty());
+        assert_eq!(registry.len, 0);
+    }
+}
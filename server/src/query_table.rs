@@ -0,0 +1,579 @@
+//! Renders the results of a [StructuredQuery] as an HTML table, served at `/query/table`.
+//! The query is kept in the query parameters, so the filter form, sorting, paging and bookmarks all work with plain links, without JavaScript.
+//! The columns are the Properties of the `class`, the `columns` param, or the Properties that the results use. Cells are formatted like in [crate::table_view].
+//! The same query can be downloaded as CSV or JSON-AD with `format=`.
+
+use atomic_lib::{
+    db::{Condition, Operator, StructuredQuery, StructuredQueryResult},
+    schema::Property,
+    urls, Db, Resource, Storelike,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::AtomicServerResult,
+    table_view::{format_cell, present_properties, resolve_property, title_of, Cell, Column, Row},
+};
+
+pub const PAGE_SIZE: usize = 50;
+/// The maximum amount of Resources in a CSV or JSON-AD export.
+pub const EXPORT_LIMIT: usize = 10_000;
+/// The interval that is offered for reloading the page.
+const REFRESH_SECONDS: u64 = 30;
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct QueryTableParams {
+    /// Only include instances of this Class
+    pub class: Option<String>,
+    /// Only include descendants of this Resource
+    pub parent: Option<String>,
+    /// Full-text search term
+    pub text: Option<String>,
+    /// Conditions separated by `;`, each as `${property} ${operator} ${value}`, e.g. `status eq done; priority gt 2`.
+    /// Properties can be shortnames. Operators are `eq`, `neq`, `lt`, `gt` and `contains`.
+    pub filter: Option<String>,
+    /// Subject or shortname of the Property to sort by
+    pub sort: Option<String>,
+    #[serde(default)]
+    pub desc: bool,
+    /// Comma separated shortnames of the columns to show
+    pub columns: Option<String>,
+    #[serde(default)]
+    pub page: usize,
+    /// `html` (the default), `csv` or `json-ad`
+    pub format: Option<String>,
+    /// Reloads the page every this many seconds
+    pub refresh: Option<u64>,
+}
+
+impl QueryTableParams {
+    /// Empty fields of the filter form mean that they are not used.
+    pub fn normalized(&self) -> QueryTableParams {
+        let non_empty = |field: &Option<String>| {
+            field
+                .as_ref()
+                .map(|v| v.trim())
+                .filter(|v| !v.is_empty())
+                .map(String::from)
+        };
+        QueryTableParams {
+            class: non_empty(&self.class),
+            parent: non_empty(&self.parent),
+            text: non_empty(&self.text),
+            filter: non_empty(&self.filter),
+            sort: non_empty(&self.sort),
+            columns: non_empty(&self.columns),
+            format: non_empty(&self.format),
+            ..self.clone()
+        }
+    }
+
+    /// The [StructuredQuery] for a page of the results, or for an export if `page` is `None`.
+    pub fn to_query(
+        &self,
+        store: &impl Storelike,
+        page: Option<usize>,
+    ) -> AtomicServerResult<StructuredQuery> {
+        let sort_by = match &self.sort {
+            Some(sort) => Some(resolve_property(store, sort)?.subject),
+            None => None,
+        };
+        Ok(StructuredQuery {
+            class: self.class.clone(),
+            parent: self.parent.clone(),
+            conditions: parse_filter(store, self.filter.as_deref().unwrap_or_default())?,
+            text: self.text.clone(),
+            sort_by,
+            sort_desc: self.desc,
+            limit: Some(page.map(|_| PAGE_SIZE).unwrap_or(EXPORT_LIMIT)),
+            offset: page.unwrap_or(0) * PAGE_SIZE,
+            include_nested: true,
+            cursor: None,
+        })
+    }
+}
+
+/// Parses the `filter` param, e.g. `status eq done; priority gt 2`.
+fn parse_filter(store: &impl Storelike, filter: &str) -> AtomicServerResult<Vec<Condition>> {
+    filter
+        .split(';')
+        .map(str::trim)
+        .filter(|condition| !condition.is_empty())
+        .map(|condition| -> AtomicServerResult<Condition> {
+            let mut parts = condition.splitn(3, ' ');
+            let (Some(property), Some(operator), Some(value)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                return Err(format!(
+                    "Invalid filter '{}', use `${{property}} ${{operator}} ${{value}}`",
+                    condition
+                )
+                .into());
+            };
+            let operator: Operator =
+                serde_json::from_value(serde_json::Value::String(operator.to_lowercase()))
+                    .map_err(|_| {
+                        format!(
+                            "Unknown operator '{}', use eq, neq, lt, gt or contains",
+                            operator
+                        )
+                    })?;
+            Ok(Condition {
+                property: resolve_property(store, property)?.subject,
+                operator,
+                value: serde_json::Value::String(value.trim().into()),
+            })
+        })
+        .collect()
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryTableView {
+    /// The values of the filter form
+    pub class: Option<String>,
+    pub class_shortname: Option<String>,
+    pub parent: Option<String>,
+    pub text: Option<String>,
+    pub filter: Option<String>,
+    pub sort: Option<String>,
+    pub desc: bool,
+    /// The `columns` param, kept when the form is submitted
+    pub columns_param: Option<String>,
+    /// The columns that are shown
+    pub columns: Vec<Column>,
+    /// All columns that can be selected, with links that toggle them
+    pub available_columns: Vec<Column>,
+    pub rows: Vec<Row>,
+    pub total_count: usize,
+    pub page: usize,
+    pub prev_page: Option<String>,
+    pub next_page: Option<String>,
+    /// The same query, without paging
+    pub csv_export: String,
+    pub json_ad_export: String,
+    pub refresh: Option<u64>,
+    /// Starts or stops reloading the page
+    pub refresh_url: String,
+}
+
+/// The Properties that can be shown for the results of a query, and the subjects of the selected ones in order.
+struct Table {
+    available: Vec<Property>,
+    selected: Vec<String>,
+}
+
+fn table_columns(
+    store: &impl Storelike,
+    params: &QueryTableParams,
+    resources: &[Resource],
+) -> AtomicServerResult<Table> {
+    let mut available: Vec<Property> = match &params.class {
+        Some(class) => {
+            let class = store.get_class(class)?;
+            class
+                .requires
+                .iter()
+                .chain(class.recommends.iter())
+                .filter_map(|prop| store.get_property(prop).ok())
+                .collect()
+        }
+        None => present_properties(store, resources),
+    };
+    let selected = match &params.columns {
+        Some(columns) => {
+            let mut selected = Vec::new();
+            for column in columns.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+                let property = match available
+                    .iter()
+                    .find(|p| p.shortname == column || p.subject == column)
+                {
+                    Some(property) => property.clone(),
+                    None => {
+                        let property = resolve_property(store, column)?;
+                        available.push(property.clone());
+                        property
+                    }
+                };
+                selected.push(property.subject);
+            }
+            selected
+        }
+        None => available.iter().map(|p| p.subject.clone()).collect(),
+    };
+    Ok(Table {
+        available,
+        selected,
+    })
+}
+
+/// Builds the page of the query results. `text_matches` are the subjects that match `params.text`, found by the caller.
+/// The results only include Resources that `for_agent` can read.
+pub fn build_query_table(
+    store: &Db,
+    params: &QueryTableParams,
+    text_matches: Option<Vec<String>>,
+    for_agent: &atomic_lib::agents::ForAgent,
+) -> AtomicServerResult<QueryTableView> {
+    let query = params.to_query(store, Some(params.page))?;
+    let result = store.structured_query(&query, text_matches, for_agent)?;
+    let Table {
+        available,
+        selected,
+    } = table_columns(store, params, &result.resources)?;
+
+    let available_columns: Vec<Column> = available
+        .iter()
+        .map(|property| {
+            let sorted = match &query.sort_by {
+                Some(sort) if sort == &property.subject => {
+                    Some(if params.desc { "desc" } else { "asc" })
+                }
+                _ => None,
+            };
+            let toggled: Vec<&str> = available
+                .iter()
+                .filter(|p| (p.subject == property.subject) != selected.contains(&p.subject))
+                .map(|p| p.shortname.as_str())
+                .collect();
+            Column {
+                subject: property.subject.clone(),
+                shortname: property.shortname.clone(),
+                datatype: property.data_type.to_string(),
+                selected: selected.contains(&property.subject),
+                sorted,
+                sort_url: query_table_url(
+                    store,
+                    &QueryTableParams {
+                        sort: Some(property.shortname.clone()),
+                        desc: sorted == Some("asc"),
+                        page: 0,
+                        ..params.clone()
+                    },
+                ),
+                toggle_url: query_table_url(
+                    store,
+                    &QueryTableParams {
+                        columns: Some(toggled.join(",")),
+                        ..params.clone()
+                    },
+                ),
+            }
+        })
+        .collect();
+    // Keep the order of the `columns` param
+    let columns: Vec<Column> = selected
+        .iter()
+        .filter_map(|subject| available_columns.iter().find(|c| &c.subject == subject))
+        .cloned()
+        .collect();
+    let rows = rows(store, &result.resources, &columns);
+
+    let page_url = |page: usize| {
+        query_table_url(
+            store,
+            &QueryTableParams {
+                page,
+                ..params.clone()
+            },
+        )
+    };
+    let export_url = |format: &str| {
+        query_table_url(
+            store,
+            &QueryTableParams {
+                format: Some(format.into()),
+                page: 0,
+                refresh: None,
+                ..params.clone()
+            },
+        )
+    };
+    let class_shortname = match &params.class {
+        Some(class) => store.get_class(class).ok().map(|c| c.shortname),
+        None => None,
+    };
+
+    Ok(QueryTableView {
+        class: params.class.clone(),
+        class_shortname,
+        parent: params.parent.clone(),
+        text: params.text.clone(),
+        filter: params.filter.clone(),
+        sort: params.sort.clone(),
+        desc: params.desc,
+        columns_param: params.columns.clone(),
+        columns,
+        available_columns,
+        rows,
+        total_count: result.count,
+        page: params.page,
+        prev_page: (params.page > 0).then(|| page_url(params.page - 1)),
+        next_page: ((params.page + 1) * PAGE_SIZE < result.count)
+            .then(|| page_url(params.page + 1)),
+        csv_export: export_url("csv"),
+        json_ad_export: export_url("json-ad"),
+        refresh: params.refresh,
+        refresh_url: query_table_url(
+            store,
+            &QueryTableParams {
+                refresh: match params.refresh {
+                    Some(_) => None,
+                    None => Some(REFRESH_SECONDS),
+                },
+                ..params.clone()
+            },
+        ),
+    })
+}
+
+fn rows(store: &impl Storelike, resources: &[Resource], columns: &[Column]) -> Vec<Row> {
+    resources
+        .iter()
+        .map(|resource| Row {
+            subject: resource.get_subject().clone(),
+            title: title_of(resource),
+            cells: columns
+                .iter()
+                .map(|column| format_cell(store, resource, &column.subject))
+                .collect(),
+        })
+        .collect()
+}
+
+/// Runs the query without paging, and returns the results as CSV, with the subject, the title and the columns.
+/// Links are exported as their subject.
+pub fn export_csv(
+    store: &Db,
+    params: &QueryTableParams,
+    result: &StructuredQueryResult,
+) -> AtomicServerResult<String> {
+    let Table {
+        available,
+        selected,
+    } = table_columns(store, params, &result.resources)?;
+    let columns: Vec<&Property> = selected
+        .iter()
+        .filter_map(|subject| available.iter().find(|p| &p.subject == subject))
+        .collect();
+    let mut csv = String::new();
+    let header = ["subject", "title"]
+        .into_iter()
+        .chain(columns.iter().map(|p| p.shortname.as_str()));
+    push_csv_record(&mut csv, header);
+    for resource in &result.resources {
+        let cells: Vec<String> = columns
+            .iter()
+            .map(|p| cell_text(format_cell(store, resource, &p.subject)))
+            .collect();
+        let title = title_of(resource);
+        let record = [resource.get_subject().as_str(), title.as_str()]
+            .into_iter()
+            .chain(cells.iter().map(String::as_str));
+        push_csv_record(&mut csv, record);
+    }
+    Ok(csv)
+}
+
+/// Runs the query without paging, and returns the results as a JSON-AD Resource with `members`, like `POST /query`.
+pub fn export_json_ad(
+    store: &Db,
+    result: StructuredQueryResult,
+    params: &QueryTableParams,
+) -> AtomicServerResult<String> {
+    let subject = query_table_url(
+        store,
+        &QueryTableParams {
+            format: Some("json-ad".into()),
+            page: 0,
+            refresh: None,
+            ..params.clone()
+        },
+    );
+    Ok(result.into_resource(subject, true).to_json_ad()?)
+}
+
+fn cell_text(cell: Cell) -> String {
+    match (cell.kind, cell.href, cell.value) {
+        ("empty", _, _) => String::new(),
+        ("link", Some(href), _) => href,
+        (_, _, serde_json::Value::String(text)) => text,
+        (_, _, value) => value.to_string(),
+    }
+}
+
+/// Quotes fields that contain commas, quotes or line breaks.
+fn push_csv_record<'a>(csv: &mut String, fields: impl Iterator<Item = &'a str>) {
+    let fields: Vec<String> = fields
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    csv.push_str(&fields.join(","));
+    csv.push_str("\r\n");
+}
+
+pub fn query_table_url(store: &impl Storelike, params: &QueryTableParams) -> String {
+    let mut pairs: Vec<(&str, String)> = Vec::new();
+    for (key, value) in [
+        ("class", &params.class),
+        ("parent", &params.parent),
+        ("text", &params.text),
+        ("filter", &params.filter),
+        ("sort", &params.sort),
+    ] {
+        if let Some(value) = value {
+            pairs.push((key, value.clone()));
+        }
+    }
+    if params.sort.is_some() && params.desc {
+        pairs.push(("desc", "true".into()));
+    }
+    if let Some(columns) = &params.columns {
+        pairs.push(("columns", columns.clone()));
+    }
+    if params.page > 0 {
+        pairs.push(("page", params.page.to_string()));
+    }
+    if let Some(format) = &params.format {
+        pairs.push(("format", format.clone()));
+    }
+    if let Some(refresh) = params.refresh {
+        pairs.push(("refresh", refresh.to_string()));
+    }
+    let query: Vec<String> = pairs
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, urlencoding::encode(value)))
+        .collect();
+    format!(
+        "{}{}/table?{}",
+        store.get_server_url(),
+        urls::PATH_QUERY,
+        query.join("&")
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atomic_lib::{agents::ForAgent, Value};
+
+    fn save(store: &Db, subject: &str, parent: &str, props: &[(&str, &str)]) {
+        let mut resource = Resource::new(subject.into());
+        resource
+            .set_propval(urls::PARENT.into(), Value::AtomicUrl(parent.into()), store)
+            .unwrap();
+        for (prop, value) in props {
+            resource
+                .set_propval_string(prop.to_string(), value, store)
+                .unwrap();
+        }
+        resource.save_locally(store).unwrap();
+    }
+
+    #[test]
+    fn renders_query_results() {
+        let store = Db::init_temp("renders_query_results").unwrap();
+        let folder = format!("{}/tasks", store.get_server_url());
+        save(
+            &store,
+            &folder,
+            store.get_server_url(),
+            &[(urls::NAME, "Tasks")],
+        );
+        for (name, description) in [("b", "Second"), ("a", "First"), ("c", "Third, last")] {
+            save(
+                &store,
+                &format!("{}/{}", folder, name),
+                &folder,
+                &[(urls::SHORTNAME, name), (urls::DESCRIPTION, description)],
+            );
+        }
+
+        let params = QueryTableParams {
+            parent: Some(folder.clone()),
+            filter: Some("description contains i".into()),
+            sort: Some("shortname".into()),
+            desc: true,
+            columns: Some("description".into()),
+            class: Some(String::new()),
+            ..Default::default()
+        }
+        .normalized();
+        let table = build_query_table(&store, &params, None, &ForAgent::Sudo).unwrap();
+        let base = "https://localhost/query/table?parent=https%3A%2F%2Flocalhost%2Ftasks&filter=description%20contains%20i";
+        assert_eq!(
+            serde_json::to_value(&table).unwrap(),
+            serde_json::json!({
+                "class": null,
+                "classShortname": null,
+                "parent": folder,
+                "text": null,
+                "filter": "description contains i",
+                "sort": "shortname",
+                "desc": true,
+                "columnsParam": "description",
+                "columns": [{
+                    "subject": urls::DESCRIPTION,
+                    "shortname": "description",
+                    "datatype": urls::MARKDOWN,
+                    "selected": true,
+                    "sorted": null,
+                    "sortUrl": format!("{}&sort=description&columns=description", base),
+                    "toggleUrl": format!("{}&sort=shortname&desc=true&columns=", base),
+                }],
+                "availableColumns": [{
+                    "subject": urls::DESCRIPTION,
+                    "shortname": "description",
+                    "datatype": urls::MARKDOWN,
+                    "selected": true,
+                    "sorted": null,
+                    "sortUrl": format!("{}&sort=description&columns=description", base),
+                    "toggleUrl": format!("{}&sort=shortname&desc=true&columns=", base),
+                }, {
+                    "subject": urls::SHORTNAME,
+                    "shortname": "shortname",
+                    "datatype": urls::SLUG,
+                    "selected": false,
+                    "sorted": "desc",
+                    "sortUrl": format!("{}&sort=shortname&columns=description", base),
+                    "toggleUrl": format!("{}&sort=shortname&desc=true&columns=description%2Cshortname", base),
+                }],
+                "rows": [{
+                    "subject": format!("{}/c", folder),
+                    "title": "c",
+                    "cells": [{ "kind": "text", "value": "Third, last", "href": null }],
+                }, {
+                    "subject": format!("{}/a", folder),
+                    "title": "a",
+                    "cells": [{ "kind": "text", "value": "First", "href": null }],
+                }],
+                "totalCount": 2,
+                "page": 0,
+                "prevPage": null,
+                "nextPage": null,
+                "csvExport": format!("{}&sort=shortname&desc=true&columns=description&format=csv", base),
+                "jsonAdExport": format!("{}&sort=shortname&desc=true&columns=description&format=json-ad", base),
+                "refresh": null,
+                "refreshUrl": format!("{}&sort=shortname&desc=true&columns=description&refresh=30", base),
+            })
+        );
+
+        let export = store
+            .structured_query(
+                &params.to_query(&store, None).unwrap(),
+                None,
+                &ForAgent::Sudo,
+            )
+            .unwrap();
+        assert_eq!(
+            export_csv(&store, &params, &export).unwrap(),
+            "subject,title,description\r\nhttps://localhost/tasks/c,c,\"Third, last\"\r\nhttps://localhost/tasks/a,a,First\r\n"
+        );
+        assert!(parse_filter(&store, "description like i").is_err());
+    }
+}
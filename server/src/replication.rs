@@ -0,0 +1,297 @@
+//! Read replicas follow another atomic-server, the primary, e.g. to serve readers in another region.
+//! A replica starts by importing a full export of the primary, and then applies the Commits from the primary's `/replication/stream`.
+//! It remembers when the last applied Commit was created, so after a reconnect or a restart it only asks for newer Commits.
+//! Incoming Commits are applied after checking their signature. If the previous Commit of an incoming Commit does not match the local Resource,
+//! the replica has diverged, and fetches that Resource from the primary instead of applying the Commit on top of the wrong state.
+//! Endpoints that change data are refused on a replica, see [reject_writes].
+//!
+//! Replicas use the same `server_url` and server Agent as the primary, so subjects and signatures are valid on both.
+
+use std::{
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use atomic_lib::{
+    commit::CommitOpts,
+    outbound::OutboundFeature,
+    parse::{parse_json_ad_commit_resource, parse_json_ad_resource, ParseOpts},
+    urls, Commit, Db, Storelike,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    appstate::AppState,
+    config::Config,
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+    search::SearchState,
+};
+
+/// How often the primary sends a comment to keep the stream open.
+pub const KEEP_ALIVE: Duration = Duration::from_secs(15);
+/// The replica reconnects if it receives nothing for this long.
+const READ_TIMEOUT: Duration = Duration::from_secs(45);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Commits that were being applied while the export was made can be older than the export, so the stream starts a bit earlier.
+/// Commits that the replica already has are skipped.
+const BOOTSTRAP_MARGIN_MS: i64 = 60 * 1000;
+/// Set by the primary on the export, contains the time in milliseconds at which the export was started.
+pub const SINCE_HEADER: &str = "X-Replication-Since";
+
+/// The replication state of a server that runs with `--replica-of`.
+/// Cheap to clone, clones share the same state.
+#[derive(Clone, Debug)]
+pub struct Replica {
+    /// URL at which the primary can be reached. Can differ from the `server_url`.
+    primary: String,
+    state: Arc<Mutex<ReplicaState>>,
+    state_path: PathBuf,
+}
+
+#[derive(Default, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReplicaState {
+    /// Creation time of the last applied Commit. `None` until the replica has been bootstrapped.
+    last_applied: Option<i64>,
+    #[serde(skip)]
+    connected: bool,
+    #[serde(skip)]
+    last_error: Option<String>,
+}
+
+/// Shown on the `/health` endpoint of replicas.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicationHealth {
+    pub primary: String,
+    pub connected: bool,
+    /// Creation time of the last applied Commit, in milliseconds since the Unix epoch
+    pub last_applied_commit: Option<i64>,
+    /// Time since the last applied Commit was created. Also grows when nothing changes on the primary.
+    pub lag_ms: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+impl Replica {
+    /// Returns `None` unless `--replica-of` is set.
+    pub fn from_config(config: &Config) -> Option<Replica> {
+        let primary = config.opts.replica_of.as_ref()?;
+        let state = std::fs::read_to_string(&config.replica_state_path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Some(Replica {
+            primary: primary.trim_end_matches('/').to_string(),
+            state: Arc::new(Mutex::new(state)),
+            state_path: config.replica_state_path.clone(),
+        })
+    }
+
+    pub fn primary(&self) -> &str {
+        &self.primary
+    }
+
+    pub fn health(&self) -> ReplicationHealth {
+        let state = self.state.lock().unwrap();
+        ReplicationHealth {
+            primary: self.primary.clone(),
+            connected: state.connected,
+            last_applied_commit: state.last_applied,
+            lag_ms: state.last_applied.map(|at| atomic_lib::utils::now() - at),
+            last_error: state.last_error.clone(),
+        }
+    }
+
+    /// Follows the primary on a background thread, reconnecting when the connection is lost.
+    pub fn start(&self, store: Db, search_state: SearchState) {
+        let replica = self.clone();
+        std::thread::spawn(move || {
+            let mut backoff = Duration::from_secs(1);
+            loop {
+                let last_applied = replica.state.lock().unwrap().last_applied;
+                let result = match last_applied {
+                    None => replica.bootstrap(&store, &search_state),
+                    Some(since) => replica.follow(&store, since),
+                };
+                let mut state = replica.state.lock().unwrap();
+                if state.connected {
+                    backoff = Duration::from_secs(1);
+                }
+                state.connected = false;
+                if let Err(e) = result {
+                    tracing::warn!("Replication from {} failed: {}", replica.primary, e);
+                    state.last_error = Some(e.message);
+                }
+                drop(state);
+                std::thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        });
+    }
+
+    /// Imports everything from the primary, and rebuilds the indexes.
+    fn bootstrap(&self, store: &Db, search_state: &SearchState) -> AtomicServerResult<()> {
+        tracing::info!("Importing all Resources from {}", self.primary);
+        let response = self.get(
+            store,
+            "/replication/export",
+            atomic_lib::parse::JSON_AD_MIME,
+        )?;
+        let since: i64 = response
+            .header(SINCE_HEADER)
+            .and_then(|since| since.parse().ok())
+            .ok_or(format!("The export has no valid {} header", SINCE_HEADER))?;
+        let body = response
+            .into_string()
+            .map_err(|e| format!("Could not read the export: {}", e))?;
+        let count = store.import(&body, &ParseOpts::default())?;
+        store.build_index(true)?;
+        crate::search::add_all_resources(search_state, store)?;
+        tracing::info!("Imported {} Resources from {}", count, self.primary);
+        self.set_applied(since - BOOTSTRAP_MARGIN_MS);
+        Ok(())
+    }
+
+    /// Applies Commits from the stream until the connection is lost.
+    fn follow(&self, store: &Db, since: i64) -> AtomicServerResult<()> {
+        let path = format!("/replication/stream?since={}", since);
+        let response = self.get(store, &path, "text/event-stream")?;
+        {
+            let mut state = self.state.lock().unwrap();
+            state.connected = true;
+            state.last_error = None;
+        }
+        tracing::info!("Following {} since {}", self.primary, since);
+        for line in BufReader::new(response.into_reader()).lines() {
+            let line = line.map_err(|e| format!("Lost the stream: {}", e))?;
+            if let Some(data) = line.strip_prefix("data: ") {
+                self.apply(store, data)?;
+            }
+        }
+        Err("The primary closed the stream".into())
+    }
+
+    fn apply(&self, store: &Db, commit_json: &str) -> AtomicServerResult<()> {
+        let commit = Commit::from_resource(parse_json_ad_commit_resource(commit_json, store)?)?;
+        let signature = commit.signature.as_ref().ok_or("Commit is not signed")?;
+        let commit_subject = format!("{}/commits/{}", store.get_server_url(), signature);
+        if store.get_resource(&commit_subject).is_ok() {
+            self.set_applied(commit.created_at);
+            return Ok(());
+        }
+
+        let local_last_commit = store
+            .get_resource(&commit.subject)
+            .ok()
+            .and_then(|resource| resource.get(urls::LAST_COMMIT).ok().map(|v| v.to_string()));
+        if local_last_commit != commit.previous_commit {
+            tracing::warn!(
+                "{} has diverged from the primary, fetching it again",
+                commit.subject
+            );
+            self.fetch_resource(store, &commit.subject)?;
+            store.add_resource_opts(&commit.into_resource(store)?, false, true, true)?;
+        } else {
+            let opts = CommitOpts {
+                validate_schema: false,
+                validate_signature: true,
+                // The Commits were checked by the primary, and can be old when catching up
+                validate_timestamp: false,
+                validate_rights: false,
+                validate_previous_commit: true,
+                validate_for_agent: None,
+                update_index: true,
+                validate_relative_urls: false,
+            };
+            commit.apply_opts(store, &opts)?;
+        }
+        self.set_applied(commit.created_at);
+        Ok(())
+    }
+
+    /// Replaces the local Resource with the one on the primary, or removes it if the primary does not have it.
+    fn fetch_resource(&self, store: &Db, subject: &str) -> AtomicServerResult<()> {
+        let path = subject
+            .strip_prefix(store.get_server_url())
+            .ok_or(format!("{} is not a Resource of this server", subject))?;
+        match self.get(store, path, atomic_lib::parse::JSON_AD_MIME) {
+            Ok(response) => {
+                let body = response
+                    .into_string()
+                    .map_err(|e| format!("Could not read {}: {}", subject, e))?;
+                parse_json_ad_resource(&body, store, &ParseOpts::default())?;
+            }
+            Err(e) if matches!(e.error_type, AppErrorType::NotFound) => {
+                if store.get_resource(subject).is_ok() {
+                    store.remove_resource(subject)?;
+                }
+            }
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    /// Sends a GET request to the primary, signed by the server Agent.
+    fn get(&self, store: &Db, path: &str, accept: &str) -> AtomicServerResult<ureq::Response> {
+        let agent = store.get_default_agent()?;
+        // The signature is for the subject, which uses the shared `server_url`
+        let signed_url = format!("{}{}", store.get_server_url(), path);
+        let mut headers = atomic_lib::client::get_authentication_headers(&signed_url, &agent)?;
+        headers.push(("Accept".into(), accept.into()));
+        let url = format!("{}{}", self.primary, path);
+        let response = store.get_outbound().unwrap_or_default().stream(
+            OutboundFeature::Replication,
+            &url,
+            &headers,
+            READ_TIMEOUT,
+        )?;
+        match response.status() {
+            404 => Err(AtomicServerError::new(
+                format!("{} was not found on the primary", url),
+                AppErrorType::NotFound,
+            )),
+            status if status >= 400 => {
+                Err(format!("Request to {} failed with status {}", url, status).into())
+            }
+            _ => Ok(response),
+        }
+    }
+
+    fn set_applied(&self, created_at: i64) {
+        let mut state = self.state.lock().unwrap();
+        if state.last_applied < Some(created_at) {
+            state.last_applied = Some(created_at);
+            let json = serde_json::to_string(&*state).expect("State is serializable");
+            if let Err(e) = std::fs::write(&self.state_path, json) {
+                tracing::error!(
+                    "Could not save the replication state to {:?}: {}",
+                    self.state_path,
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Refuses requests that change data on a replica, and points to the primary instead.
+/// Also refuses them when serving a single file, see [crate::serve_file].
+pub fn reject_writes(appstate: &AppState) -> AtomicServerResult<()> {
+    if let Some(file) = &appstate.config.opts.serve_file {
+        return Err(AtomicServerError::new(
+            format!("This server only serves {:?}, and is read-only", file),
+            AppErrorType::MethodNotAllowed,
+        ));
+    }
+    match &appstate.replica {
+        Some(replica) => Err(AtomicServerError::new(
+            format!(
+                "This server is a read-only replica. Send changes to the primary at {}",
+                replica.primary()
+            ),
+            AppErrorType::MethodNotAllowed,
+        )),
+        None => Ok(()),
+    }
+}
@@ -20,6 +20,81 @@ include!(concat!(env!("OUT_DIR"), "/generated.rs"));
 pub fn config_routes(app: &mut actix_web::web::ServiceConfig) {
     app.service(web::resource("/ws").to(handlers::web_sockets::web_socket_handler))
         .service(web::resource("/download/{path:[^{}]+}").to(handlers::download::handle_download))
+        .service(
+            web::resource("/health")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::health::health),
+        )
+        .service(
+            web::resource("/agent-activity")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::agent_activity::agent_activity),
+        )
+        .service(
+            web::resource("/agent-overview")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::agent_overview::agent_overview),
+        )
+        .service(
+            web::resource("/pins")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::pins::pins),
+        )
+        .service(
+            web::resource("/inbox")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::inbox::inbox),
+        )
+        .service(
+            web::resource("/metrics")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::metrics::metrics),
+        )
+        .service(
+            web::resource("/replication/export")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::replication::export),
+        )
+        .service(
+            web::resource("/replication/stream")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::replication::stream),
+        )
+        .service(
+            web::resource("/openapi.json")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::openapi::openapi_json),
+        )
+        .service(
+            web::resource("/api-docs")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::openapi::api_docs),
+        )
+        .service(
+            web::resource("/schema")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::schema::schema_overview),
+        )
+        .service(
+            web::resource("/activity")
+                .guard(guard::Method(Method::GET))
+                .guard(guard::fn_guard(|guard_ctx| {
+                    content_types::get_accept(guard_ctx.head().headers())
+                        == content_types::ContentType::Html
+                        && guard_ctx.head().uri.query().is_some()
+                }))
+                .to(handlers::activity::activity_page),
+        )
+        .service(
+            web::resource("/table")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::table::table_page),
+        )
+        .service(
+            web::resource("/export-html")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::export_html::export_html),
+        )
         // This `generate` imports the static files from the `app_assets` folder
         .service(
             ResourceFiles::new("/", generate())
@@ -36,16 +111,91 @@ pub fn config_routes(app: &mut actix_web::web::ServiceConfig) {
                 }))
                 .to(handlers::single_page_app::single_page),
         )
+        .service(
+            web::resource("/setup")
+                .guard(guard::Method(Method::POST))
+                .to(handlers::setup::complete_setup),
+        )
         .service(
             web::resource("/upload")
                 .guard(guard::Method(Method::POST))
                 .to(handlers::upload::upload_handler),
         )
+        .service(
+            web::resource("/upload-progress")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::upload::upload_progress),
+        )
         .service(
             web::resource("/commit")
                 .guard(guard::Method(Method::POST))
                 .to(handlers::commit::post_commit),
         )
+        .service(
+            web::resource("/commit-report")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::commit::commit_report),
+        )
+        .service(
+            web::resource("/copy")
+                .guard(guard::Method(Method::POST))
+                .to(handlers::copy::copy),
+        )
+        .service(
+            web::resource("/duplicates")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::duplicates::find_duplicates),
+        )
+        .service(
+            web::resource("/duplicates/merge")
+                .guard(guard::Method(Method::POST))
+                .to(handlers::duplicates::merge_duplicates),
+        )
+        .service(
+            web::resource("/import")
+                .guard(guard::Method(Method::POST))
+                .to(handlers::import::import),
+        )
+        .service(
+            web::resource("/jobs")
+                .guard(guard::Method(Method::POST))
+                .to(handlers::jobs::create_job),
+        )
+        .service(
+            web::resource("/link-report")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::link_report::link_report),
+        )
+        .service(
+            web::resource("/preview")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::preview::preview),
+        )
+        .service(
+            web::resource("/lock")
+                .route(web::post().to(handlers::lock::lock_resource))
+                .route(web::delete().to(handlers::lock::unlock_resource)),
+        )
+        .service(
+            web::resource("/query/table")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::query::query_table_page),
+        )
+        .service(
+            web::resource("/query")
+                .guard(guard::Method(Method::POST))
+                .to(handlers::query::structured_query),
+        )
+        .service(
+            web::resource("/retention")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::retention::retention),
+        )
+        .service(
+            web::resource("/rights")
+                .guard(guard::Method(Method::GET))
+                .to(handlers::rights::rights),
+        )
         .service(
             web::resource("/search")
                 .guard(guard::Method(Method::GET))
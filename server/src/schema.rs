@@ -0,0 +1,167 @@
+//! Builds an overview of the Classes and Properties that are known to the server, served at `/schema`.
+//! The JSON structure is meant to be consumed by clients that generate forms, so keep changes to it backwards compatible.
+
+use std::collections::HashMap;
+
+use atomic_lib::{
+    agents::ForAgent,
+    db::SchemaUsageReport,
+    schema::{Class, Property},
+    storelike::Query,
+    urls, Storelike,
+};
+use serde::Serialize;
+
+use crate::errors::AtomicServerResult;
+
+/// How many instances are linked for every Class.
+const EXAMPLES_PER_CLASS: usize = 3;
+
+#[derive(Serialize, Debug)]
+pub struct SchemaOverview {
+    pub classes: Vec<ClassOverview>,
+    pub properties: Vec<PropertyOverview>,
+    /// How the schema is used by the data, see [atomic_lib::Db::schema_usage]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<SchemaUsageReport>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ClassOverview {
+    pub subject: String,
+    pub shortname: String,
+    pub description: String,
+    pub requires: Vec<PropertySummary>,
+    pub recommends: Vec<PropertySummary>,
+    /// Number of Resources in the store that have this Class
+    pub instance_count: usize,
+    /// A few instances that the requesting Agent can read
+    pub examples: Vec<String>,
+}
+
+/// A Property as it is used in a Class.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertySummary {
+    pub subject: String,
+    pub shortname: String,
+    pub description: String,
+    pub datatype: String,
+    pub classtype: Option<String>,
+    pub allows_only: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PropertyOverview {
+    #[serde(flatten)]
+    pub property: PropertySummary,
+    /// Classes that require or recommend this Property
+    pub used_by_classes: Vec<String>,
+}
+
+impl From<&Property> for PropertySummary {
+    fn from(property: &Property) -> Self {
+        PropertySummary {
+            subject: property.subject.clone(),
+            shortname: property.shortname.clone(),
+            description: property.description.clone(),
+            datatype: property.data_type.to_string(),
+            classtype: property.class_type.clone(),
+            allows_only: property.allows_only.clone(),
+        }
+    }
+}
+
+/// Lists all Classes and Properties in the store, sorted by shortname.
+/// Examples are only included if `for_agent` can read them.
+pub fn build_schema_overview(
+    store: &impl Storelike,
+    for_agent: &ForAgent,
+) -> AtomicServerResult<SchemaOverview> {
+    let mut properties: HashMap<String, Property> = HashMap::new();
+    for subject in instances_of(store, urls::PROPERTY)? {
+        if let Ok(property) = store.get_property(&subject) {
+            properties.insert(subject, property);
+        }
+    }
+
+    let mut used_by: HashMap<String, Vec<String>> = HashMap::new();
+    let mut classes = Vec::new();
+    for subject in instances_of(store, urls::CLASS)? {
+        let Ok(class) = store.get_resource(&subject).and_then(Class::from_resource) else {
+            continue;
+        };
+        for prop in class.requires.iter().chain(class.recommends.iter()) {
+            used_by
+                .entry(prop.clone())
+                .or_default()
+                .push(class.subject.clone());
+        }
+
+        let mut query = Query::new_class(&class.subject);
+        query.include_external = true;
+        query.limit = Some(EXAMPLES_PER_CLASS);
+        query.for_agent = for_agent.clone();
+        let instances = store.query(&query)?;
+
+        classes.push(ClassOverview {
+            requires: summaries(store, &properties, &class.requires),
+            recommends: summaries(store, &properties, &class.recommends),
+            instance_count: instances.count,
+            examples: instances.subjects,
+            subject: class.subject,
+            shortname: class.shortname,
+            description: class.description,
+        });
+    }
+    classes.sort_by(|a, b| a.shortname.cmp(&b.shortname));
+
+    let mut properties: Vec<PropertyOverview> = properties
+        .values()
+        .map(|property| PropertyOverview {
+            property: property.into(),
+            used_by_classes: used_by.remove(&property.subject).unwrap_or_default(),
+        })
+        .collect();
+    properties.sort_by(|a, b| a.property.shortname.cmp(&b.property.shortname));
+
+    Ok(SchemaOverview {
+        classes,
+        properties,
+        usage: None,
+    })
+}
+
+fn instances_of(store: &impl Storelike, class: &str) -> AtomicServerResult<Vec<String>> {
+    let mut query = Query::new_class(class);
+    query.include_external = true;
+    query.include_nested = false;
+    Ok(store.query(&query)?.subjects)
+}
+
+/// Properties that can't be found are still listed by their subject, so the Class stays complete.
+fn summaries(
+    store: &impl Storelike,
+    properties: &HashMap<String, Property>,
+    subjects: &[String],
+) -> Vec<PropertySummary> {
+    subjects
+        .iter()
+        .map(|subject| match properties.get(subject) {
+            Some(property) => property.into(),
+            None => match store.get_property(subject) {
+                Ok(property) => (&property).into(),
+                Err(_) => PropertySummary {
+                    subject: subject.clone(),
+                    shortname: subject.clone(),
+                    description: String::new(),
+                    datatype: String::new(),
+                    classtype: None,
+                    allows_only: None,
+                },
+            },
+        })
+        .collect()
+}
@@ -1,17 +1,31 @@
 //! Full-text search, powered by Tantivy.
 //! A folder for the index is stored in the config.
 //! You can see the Endpoint on `http://localhost/search`
+//! Titles and descriptions are analyzed using the language set in the config, see [AnalyzerSettings].
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
 use atomic_lib::Db;
 use atomic_lib::Resource;
 use atomic_lib::Storelike;
+use serde::{Deserialize, Serialize};
 use tantivy::schema::*;
+use tantivy::tokenizer::{
+    AsciiFoldingFilter, Language, LowerCaser, RemoveLongFilter, SimpleTokenizer, Stemmer,
+    StopWordFilter, TextAnalyzer,
+};
 use tantivy::Index;
 use tantivy::IndexWriter;
 use tantivy::ReloadPolicy;
 
-use crate::config::Config;
+use crate::config::{Config, SearchLanguage};
 use crate::errors::AtomicServerResult;
 
+/// Name under which the analyzer of [AnalyzerSettings] is registered in the index.
+const TOKENIZER: &str = "atomic";
+/// Stores the [AnalyzerSettings] that the index was built with, in the index folder.
+const ANALYZER_FILE: &str = "analyzer.json";
+
 /// The actual Schema used for search.
 /// It mimics a single Atom (or Triple).
 #[derive(Debug)]
@@ -36,13 +50,62 @@ pub struct SearchState {
     pub writer: std::sync::Arc<std::sync::RwLock<tantivy::IndexWriter>>,
     /// The shape of data stored in the index
     pub schema: tantivy::schema::Schema,
+    pub analyzer: AnalyzerSettings,
+    /// Folder of the index
+    path: std::path::PathBuf,
+    /// Set when the index was built with other [AnalyzerSettings], until all Resources are added again
+    stale: Arc<AtomicBool>,
+}
+
+/// How titles and descriptions are split into the terms that are searched for.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzerSettings {
+    pub language: SearchLanguage,
+    pub ascii_folding: bool,
+}
+
+impl AnalyzerSettings {
+    pub fn from_config(config: &Config) -> Self {
+        AnalyzerSettings {
+            language: config.opts.search_language,
+            ascii_folding: config.opts.search_ascii_folding,
+        }
+    }
+
+    /// Splits and lowercases words, removes stop words and stems them for the language, and optionally removes diacritics.
+    pub fn build(&self) -> AtomicServerResult<TextAnalyzer> {
+        let mut builder = TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser)
+            .dynamic();
+        let language = match self.language {
+            SearchLanguage::Raw => None,
+            SearchLanguage::English => Some(Language::English),
+            SearchLanguage::Dutch => Some(Language::Dutch),
+        };
+        if let Some(language) = language {
+            let stop_words =
+                StopWordFilter::new(language).ok_or(format!("No stop words for {:?}", language))?;
+            builder = builder
+                .filter_dynamic(stop_words)
+                .filter_dynamic(Stemmer::new(language));
+        }
+        if self.ascii_folding {
+            builder = builder.filter_dynamic(AsciiFoldingFilter);
+        }
+        Ok(builder.build())
+    }
 }
 
 impl SearchState {
     /// Create a new SearchState for the Server, which includes building the schema and index.
     pub fn new(config: &Config) -> AtomicServerResult<SearchState> {
         let schema = crate::search::build_schema()?;
+        let analyzer = AnalyzerSettings::from_config(config);
+        let stale = clear_if_analyzer_changed(config, &analyzer)?;
         let (writer, index) = crate::search::get_index(config)?;
+        index.tokenizers().register(TOKENIZER, analyzer.build()?);
         let reader = crate::search::get_reader(&index)?;
         let locked = std::sync::RwLock::from(writer);
         let arced = std::sync::Arc::from(locked);
@@ -51,8 +114,48 @@ impl SearchState {
             reader,
             index,
             writer: arced,
+            analyzer,
+            path: config.search_index_path.clone(),
+            stale: Arc::new(AtomicBool::new(stale)),
         })
     }
+
+    /// Whether the index still has to be rebuilt with the current [AnalyzerSettings]. Search results are incomplete until then.
+    pub fn is_stale(&self) -> bool {
+        self.stale.load(Ordering::Relaxed)
+    }
+
+    /// Records that the index is built with the current [AnalyzerSettings].
+    fn mark_fresh(&self) -> AtomicServerResult<()> {
+        let json = serde_json::to_string(&self.analyzer).map_err(|e| e.to_string())?;
+        std::fs::write(self.path.join(ANALYZER_FILE), json)?;
+        self.stale.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Removes the index if it was built with other [AnalyzerSettings] (or by a version that did not record them), since its terms would not match.
+/// Returns whether the index has to be rebuilt.
+fn clear_if_analyzer_changed(
+    config: &Config,
+    analyzer: &AnalyzerSettings,
+) -> AtomicServerResult<bool> {
+    let path = config.search_index_path.join(ANALYZER_FILE);
+    let current: Option<AnalyzerSettings> = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok());
+    if current.as_ref() == Some(analyzer) {
+        return Ok(false);
+    }
+    if config.search_index_path.exists() {
+        tracing::warn!(
+            "The search analyzer changed from {:?} to {:?}, clearing the search index",
+            current,
+            analyzer
+        );
+        std::fs::remove_dir_all(&config.search_index_path)?;
+    }
+    Ok(true)
 }
 
 /// Returns the schema for the search index.
@@ -60,8 +163,15 @@ pub fn build_schema() -> AtomicServerResult<tantivy::schema::Schema> {
     let mut schema_builder = Schema::builder();
     // The STORED flag makes the index store the full values. Can be useful.
     schema_builder.add_text_field("subject", TEXT | STORED);
-    schema_builder.add_text_field("title", TEXT | STORED);
-    schema_builder.add_text_field("description", TEXT | STORED);
+    let analyzed = TextOptions::default()
+        .set_indexing_options(
+            TextFieldIndexing::default()
+                .set_tokenizer(TOKENIZER)
+                .set_index_option(IndexRecordOption::WithFreqsAndPositions),
+        )
+        .set_stored();
+    schema_builder.add_text_field("title", analyzed.clone());
+    schema_builder.add_text_field("description", analyzed);
     schema_builder.add_json_field("propvals", STORED | TEXT);
     schema_builder.add_facet_field("hierarchy", STORED);
     let schema = schema_builder.build();
@@ -112,7 +222,8 @@ pub fn add_all_resources(search_state: &SearchState, store: &Db) -> AtomicServer
 
     let resources = store
         .all_resources(true)
-        .filter(|resource| !resource.get_subject().contains("/commits/"));
+        .filter(|resource| !resource.get_subject().contains("/commits/"))
+        .filter(|resource| !atomic_lib::plugins::trash::is_trashed(resource));
 
     for resource in resources {
         add_resource(search_state, &resource, store).map_err(|e| {
@@ -125,10 +236,35 @@ pub fn add_all_resources(search_state: &SearchState, store: &Db) -> AtomicServer
     }
 
     search_state.writer.write()?.commit()?;
+    search_state.mark_fresh()?;
     tracing::info!("Search index finished!");
     Ok(())
 }
 
+/// Leaves out values that are too large to index, see [Db::set_max_indexed_value_size].
+fn without_large_values(resource: &Resource, max: usize) -> std::borrow::Cow<'_, Resource> {
+    let too_large: Vec<String> = resource
+        .get_propvals()
+        .iter()
+        .filter(|(_, value)| value.text_len() > max)
+        .map(|(prop, _)| prop.clone())
+        .collect();
+    if too_large.is_empty() {
+        return std::borrow::Cow::Borrowed(resource);
+    }
+    let mut resource = resource.clone();
+    for prop in too_large {
+        tracing::warn!(
+            "Not adding {} of {} to the search index, since its value is larger than {} bytes.",
+            prop,
+            resource.get_subject(),
+            max
+        );
+        resource.remove_propval(&prop);
+    }
+    std::borrow::Cow::Owned(resource)
+}
+
 /// Adds a single resource to the search index, but does _not_ commit!
 /// Does not index outgoing links, or resourcesArrays
 /// `appstate.search_index_writer.write()?.commit()?;`
@@ -138,9 +274,25 @@ pub fn add_resource(
     resource: &Resource,
     store: &Db,
 ) -> AtomicServerResult<()> {
+    let doc = build_document(appstate, resource, store)?;
+    appstate.writer.read()?.add_document(doc)?;
+    Ok(())
+}
+
+/// Builds the search document of a Resource, without adding it to the index.
+pub fn build_document(
+    appstate: &SearchState,
+    resource: &Resource,
+    store: &Db,
+) -> AtomicServerResult<Document> {
     let fields = get_schema_fields(appstate)?;
+    // Already capped by `--max-extracted-text`, and often larger than other values, see [crate::text_extraction]
+    let extracted_text = resource
+        .get(atomic_lib::urls::EXTRACTED_TEXT)
+        .map(|text| text.to_string())
+        .ok();
+    let resource = &without_large_values(resource, store.max_indexed_value_size());
     let subject = resource.get_subject();
-    let writer = appstate.writer.read()?;
 
     let mut doc = Document::default();
     doc.add_json_object(
@@ -161,13 +313,14 @@ pub fn add_resource(
     {
         doc.add_text(fields.description, description);
     };
+    if let Some(text) = extracted_text {
+        doc.add_text(fields.description, text);
+    }
 
     let hierarchy = resource_to_facet(resource, store)?;
     doc.add_facet(fields.hierarchy, hierarchy);
 
-    writer.add_document(doc)?;
-
-    Ok(())
+    Ok(doc)
 }
 
 /// Removes a single resource from the search index, but does _not_ commit!
@@ -246,8 +399,91 @@ fn get_resource_title(resource: &Resource) -> String {
 #[cfg(test)]
 mod tests {
     use atomic_lib::{urls, Resource, Storelike};
+    use tantivy::{
+        collector::Count,
+        query::{Query, QueryParser},
+        Document, Index,
+    };
+
+    use super::{build_schema, resource_to_facet, AnalyzerSettings, Fields, TOKENIZER};
+    use crate::config::SearchLanguage;
+
+    fn index_with_title(settings: &AnalyzerSettings, title: &str) -> (Index, Fields) {
+        let schema = build_schema().unwrap();
+        let index = Index::create_in_ram(schema.clone());
+        index
+            .tokenizers()
+            .register(TOKENIZER, settings.build().unwrap());
+        let fields = Fields {
+            subject: schema.get_field("subject").unwrap(),
+            title: schema.get_field("title").unwrap(),
+            description: schema.get_field("description").unwrap(),
+            propvals: schema.get_field("propvals").unwrap(),
+            hierarchy: schema.get_field("hierarchy").unwrap(),
+        };
+        let mut writer = index.writer(15_000_000).unwrap();
+        let mut doc = Document::default();
+        doc.add_text(fields.title, title);
+        writer.add_document(doc).unwrap();
+        writer.commit().unwrap();
+        (index, fields)
+    }
+
+    fn count(index: &Index, query: &dyn Query) -> usize {
+        index
+            .reader()
+            .unwrap()
+            .searcher()
+            .search(query, &Count)
+            .unwrap()
+    }
+
+    /// Matches only exact terms, unlike the fuzzy search of the endpoint.
+    fn count_exact(settings: &AnalyzerSettings, title: &str, query: &str) -> usize {
+        let (index, fields) = index_with_title(settings, title);
+        let query = QueryParser::for_index(&index, vec![fields.title])
+            .parse_query(query)
+            .unwrap();
+        count(&index, &query)
+    }
+
+    #[test]
+    fn analyzer_stems_and_folds() {
+        let raw = AnalyzerSettings {
+            language: SearchLanguage::Raw,
+            ascii_folding: false,
+        };
+        let dutch = AnalyzerSettings {
+            language: SearchLanguage::Dutch,
+            ascii_folding: false,
+        };
+        assert_eq!(count_exact(&dutch, "Openstaande facturen", "factuur"), 1);
+        assert_eq!(count_exact(&raw, "Openstaande facturen", "factuur"), 0);
+
+        let folding = AnalyzerSettings {
+            language: SearchLanguage::Raw,
+            ascii_folding: true,
+        };
+        assert_eq!(count_exact(&folding, "Café de Paris", "cafe"), 1);
+        assert_eq!(count_exact(&raw, "Café de Paris", "cafe"), 0);
+    }
+
+    #[test]
+    fn quoted_text_is_a_phrase() {
+        let dutch = AnalyzerSettings {
+            language: SearchLanguage::Dutch,
+            ascii_folding: false,
+        };
+        let text_query = |title: &str, q: &str| {
+            let (index, fields) = index_with_title(&dutch, title);
+            let query = crate::handlers::search::build_text_query(&fields, q, &index).unwrap();
+            count(&index, &query)
+        };
+        assert_eq!(text_query("De facturen betalen", "\"factuur betalen\""), 1);
+        assert_eq!(text_query("Betalen van facturen", "\"factuur betalen\""), 0);
+        assert_eq!(text_query("Betalen van facturen", "factuur betalen"), 1);
+    }
 
-    use super::resource_to_facet;
     #[test]
     fn facet_contains_subfacet() {
         let store = atomic_lib::Db::init_temp("facet_contains").unwrap();
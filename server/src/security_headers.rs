@@ -0,0 +1,195 @@
+//! Security headers on every response: a Content-Security-Policy for HTML, a sandbox for downloads and `nosniff` for everything.
+//! Every request gets a random nonce, which the templates put on their inline `<script>` and `<style>` tags, see [nonce].
+//! Handlers that need another policy (e.g. the Swagger UI, which loads from a CDN) set the `Content-Security-Policy` header themselves, which is then kept.
+
+use std::future::Future;
+
+use actix_web::{
+    dev::{Service, ServiceRequest, ServiceResponse},
+    http::header::{self, HeaderMap, HeaderName, HeaderValue},
+    web, HttpMessage, HttpRequest,
+};
+
+use crate::{appstate::AppState, errors::AtomicServerResult};
+
+/// The policy for HTML pages, before the nonce is added to `script-src` and `style-src`.
+/// Atomic Data links to other servers, so the browser may connect to them.
+const DEFAULT_DIRECTIVES: &[(&str, &str)] = &[
+    ("default-src", "'self'"),
+    ("script-src", "'self'"),
+    ("style-src", "'self'"),
+    ("img-src", "'self' data:"),
+    ("connect-src", "'self' https: wss:"),
+    ("object-src", "'none'"),
+    ("base-uri", "'self'"),
+    ("form-action", "'self'"),
+    ("frame-ancestors", "'none'"),
+];
+/// Uploaded files can contain anything, including HTML and SVG with scripts.
+/// When they are opened in the browser, they can't run scripts or reach the origin of the server, but the app can still show them in a frame.
+pub const DOWNLOAD_POLICY: &str = "sandbox; default-src 'none'; img-src 'self' data:; media-src 'self'; style-src 'unsafe-inline'; frame-ancestors 'self'";
+/// Error pages have no scripts, and their inline style is part of the template.
+pub const ERROR_PAGE_POLICY: &str =
+    "default-src 'none'; style-src 'unsafe-inline'; img-src 'self' data:; frame-ancestors 'none'";
+/// Exports have no scripts, and can't use a nonce since their output must not change between requests.
+pub const EXPORT_POLICY: &str =
+    "default-src 'none'; style-src 'unsafe-inline'; img-src 'self' data: https:; frame-ancestors 'none'";
+const REFERRER_POLICY: &str = "strict-origin-when-cross-origin";
+const PERMISSIONS_POLICY: &str =
+    "camera=(), microphone=(), geolocation=(), payment=(), usb=(), interest-cohort=()";
+/// Length of the random nonce of a request.
+const NONCE_LENGTH: usize = 22;
+
+/// The random value that allows the inline scripts and styles of one response.
+#[derive(Clone)]
+struct CspNonce(String);
+
+/// The Content-Security-Policy for HTML pages, with the directives from `--csp` replacing the defaults.
+#[derive(Clone, Debug)]
+pub struct SecurityHeaders {
+    directives: Vec<(String, String)>,
+}
+
+impl SecurityHeaders {
+    /// Parses directives like `script-src 'self' https://widgets.example.com`.
+    /// A directive replaces the default with the same name, or is added.
+    pub fn new(overrides: &[String]) -> AtomicServerResult<Self> {
+        let mut directives: Vec<(String, String)> = DEFAULT_DIRECTIVES
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        for directive in overrides.iter().map(|d| d.trim()).filter(|d| !d.is_empty()) {
+            let (name, value) = directive.split_once(' ').unwrap_or((directive, ""));
+            let name = name.to_lowercase();
+            let value = value.trim().to_string();
+            if !name.chars().all(|c| c.is_ascii_lowercase() || c == '-') {
+                return Err(format!("Invalid CSP directive name '{}'", name).into());
+            }
+            if value.contains(|c: char| c == ';' || c == ',' || c.is_control()) {
+                return Err(
+                    format!("Invalid value for CSP directive {}: '{}'", name, value).into(),
+                );
+            }
+            match directives
+                .iter_mut()
+                .find(|(existing, _)| *existing == name)
+            {
+                Some(existing) => existing.1 = value,
+                None => directives.push((name, value)),
+            }
+        }
+        Ok(SecurityHeaders { directives })
+    }
+
+    /// The policy for an HTML page, allowing the inline scripts and styles with the nonce.
+    pub fn html_policy(&self, nonce: &str) -> String {
+        self.directives
+            .iter()
+            .map(|(name, value)| match name.as_str() {
+                "script-src" | "style-src" => format!("{} {} 'nonce-{}'", name, value, nonce),
+                _ if value.is_empty() => name.clone(),
+                _ => format!("{} {}", name, value),
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    /// Whether pages may not be shown in frames at all, so older browsers get `X-Frame-Options` too.
+    fn denies_frames(&self) -> bool {
+        self.directives
+            .iter()
+            .any(|(name, value)| name == "frame-ancestors" && value == "'none'")
+    }
+
+    /// Adds the headers that are missing for the kind of response.
+    fn apply(&self, headers: &mut HeaderMap, nonce: &str, is_download: bool) {
+        let is_html = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.starts_with("text/html"))
+            .unwrap_or(false);
+        let mut insert = |name: HeaderName, value: &str| {
+            if !headers.contains_key(&name) {
+                if let Ok(value) = HeaderValue::from_str(value) {
+                    headers.insert(name, value);
+                }
+            }
+        };
+        insert(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+        insert(header::REFERRER_POLICY, REFERRER_POLICY);
+        if is_download {
+            insert(header::CONTENT_SECURITY_POLICY, DOWNLOAD_POLICY);
+        } else if is_html {
+            insert(header::CONTENT_SECURITY_POLICY, &self.html_policy(nonce));
+            insert(
+                HeaderName::from_static("permissions-policy"),
+                PERMISSIONS_POLICY,
+            );
+            if self.denies_frames() {
+                insert(header::X_FRAME_OPTIONS, "DENY");
+            }
+        }
+    }
+}
+
+/// Middleware that adds the security headers, use with `wrap_fn`.
+pub fn wrap<S, B>(
+    req: ServiceRequest,
+    srv: &S,
+) -> impl Future<Output = Result<ServiceResponse<B>, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error>,
+{
+    let nonce = atomic_lib::utils::random_string(NONCE_LENGTH);
+    req.extensions_mut().insert(CspNonce(nonce.clone()));
+    let is_download = req.path().starts_with("/download/");
+    let security_headers = req
+        .app_data::<web::Data<AppState>>()
+        .map(|appstate| appstate.config.security_headers.clone());
+    let response = srv.call(req);
+    async move {
+        let mut response = response.await?;
+        if let Some(security_headers) = security_headers {
+            security_headers.apply(response.headers_mut(), &nonce, is_download);
+        }
+        Ok(response)
+    }
+}
+
+/// The nonce for the inline scripts and styles of this request. Templates use it as `nonce="{{ cspNonce }}"`.
+pub fn nonce(req: &HttpRequest) -> String {
+    req.extensions()
+        .get::<CspNonce>()
+        .map(|nonce| nonce.0.clone())
+        // Without the middleware there is no policy, so any value works
+        .unwrap_or_else(|| atomic_lib::utils::random_string(NONCE_LENGTH))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn directives_can_be_relaxed() {
+        let defaults = SecurityHeaders::new(&[]).unwrap();
+        let policy = defaults.html_policy("abc");
+        assert!(policy.contains("script-src 'self' 'nonce-abc'"));
+        assert!(policy.contains("img-src 'self' data:"));
+        assert!(policy.contains("frame-ancestors 'none'"));
+        assert!(defaults.denies_frames());
+
+        let relaxed = SecurityHeaders::new(&[
+            "script-src 'self' https://widgets.example.com".into(),
+            "frame-ancestors https://blog.example.com".into(),
+            "upgrade-insecure-requests".into(),
+        ])
+        .unwrap();
+        let policy = relaxed.html_policy("abc");
+        assert!(policy.contains("script-src 'self' https://widgets.example.com 'nonce-abc'"));
+        assert!(policy.ends_with("; upgrade-insecure-requests"));
+        assert!(!relaxed.denies_frames());
+
+        assert!(SecurityHeaders::new(&["script-src *; img-src *".into()]).is_err());
+        assert!(SecurityHeaders::new(&["script_src *".into()]).is_err());
+    }
+}
@@ -0,0 +1,465 @@
+//! Checks the configuration, the directories and the store when the server starts, see [check_environment] and [check_store].
+//! Problems that would otherwise surface as confusing errors while the server is running are reported up front.
+//! Use `atomic-server --check` to run only these checks, e.g. in CI.
+
+use std::path::{Path, PathBuf};
+
+use atomic_lib::{storelike::Query, urls, Db, Storelike};
+use serde::Serialize;
+
+use crate::{config::Config, errors::AtomicServerResult};
+
+/// How many Resources are validated at startup.
+const SAMPLE_SIZE: usize = 1000;
+/// How many invalid subjects are listed in the summary.
+const LISTED_SUBJECTS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    /// The server can run, but something should be looked at
+    Warning,
+    /// The server can't run correctly, so it does not start
+    Fatal,
+}
+
+impl std::fmt::Display for CheckStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let status = match self {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Warning => "warning",
+            CheckStatus::Fatal => "fatal",
+        };
+        write!(f, "{}", status)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    /// What was found, and what to do about it
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct SelfCheckReport {
+    pub checks: Vec<Check>,
+    /// The indexes are outdated, so they have to be rebuilt before the server can use them
+    pub rebuild_indexes: bool,
+}
+
+impl SelfCheckReport {
+    fn add(&mut self, name: &'static str, status: CheckStatus, message: impl Into<String>) {
+        self.checks.push(Check {
+            name,
+            status,
+            message: message.into(),
+        });
+    }
+
+    pub fn fatal(&self) -> Vec<&Check> {
+        self.checks
+            .iter()
+            .filter(|check| check.status == CheckStatus::Fatal)
+            .collect()
+    }
+
+    /// One line per check, aligned in columns.
+    pub fn summary(&self) -> String {
+        let width = self
+            .checks
+            .iter()
+            .map(|check| check.name.len())
+            .max()
+            .unwrap_or(0);
+        self.checks
+            .iter()
+            .map(|check| {
+                format!(
+                    "{:<7}  {:<width$}  {}",
+                    check.status.to_string(),
+                    check.name,
+                    check.message,
+                    width = width
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Logs every check, with a level that matches its status.
+    pub fn log(&self) {
+        for check in &self.checks {
+            match check.status {
+                CheckStatus::Ok => tracing::info!("Self-check {}: {}", check.name, check.message),
+                CheckStatus::Warning => {
+                    tracing::warn!("Self-check {}: {}", check.name, check.message)
+                }
+                CheckStatus::Fatal => {
+                    tracing::error!("Self-check {}: {}", check.name, check.message)
+                }
+            }
+        }
+    }
+
+    /// Fails with the messages of the fatal checks, so startup is aborted.
+    pub fn fail_on_fatal(&self) -> AtomicServerResult<()> {
+        let fatal = self.fatal();
+        if fatal.is_empty() {
+            return Ok(());
+        }
+        let messages: Vec<String> = fatal
+            .iter()
+            .map(|check| format!("{}: {}", check.name, check.message))
+            .collect();
+        Err(format!("The self-check failed.\n{}", messages.join("\n")).into())
+    }
+}
+
+/// Runs the checks that don't need the store. Call this before opening the store.
+pub fn check_environment(config: &Config) -> SelfCheckReport {
+    let mut report = SelfCheckReport::default();
+    check_paths(config, &mut report);
+    check_search_index(config, &mut report);
+    report
+}
+
+/// Runs the checks that need the store, and adds them to the `report`.
+/// `fresh` means the store has just been created, so there is nothing to compare yet.
+pub fn check_store(config: &Config, store: &Db, fresh: bool, report: &mut SelfCheckReport) {
+    if fresh {
+        report.add("store", CheckStatus::Ok, "New store, nothing to check yet");
+        return;
+    }
+    check_server_url(config, store, report);
+    check_index(store, report);
+    check_sample(store, report);
+}
+
+/// Runs the store checks, including the slow ones that are skipped at startup.
+pub fn check_existing_store(config: &Config, store: &Db, report: &mut SelfCheckReport) {
+    check_store(config, store, false, report);
+    check_hierarchy(store, report);
+}
+
+/// Runs all checks and prints the summary, for `atomic-server --check`.
+/// Also looks for loops in the hierarchy, which reads every Resource and is too slow to do at every startup.
+/// Does not create or change any files, except for migrating the store if it is outdated.
+// Only called by the binary
+#[allow(dead_code)]
+pub fn run_only(config: &Config) -> AtomicServerResult<()> {
+    let mut report = check_environment(config);
+    if config.store_path.exists() && report.fatal().is_empty() {
+        match Db::init(&config.store_path, config.server_url.clone()) {
+            Ok(store) => check_existing_store(config, &store, &mut report),
+            Err(e) => report.add(
+                "store",
+                CheckStatus::Fatal,
+                format!("Could not open the store. {}", e),
+            ),
+        }
+    } else if !config.store_path.exists() {
+        report.add(
+            "store",
+            CheckStatus::Ok,
+            format!(
+                "No store at {:?} yet, it will be created",
+                config.store_path
+            ),
+        );
+    }
+    println!("{}", report.summary());
+    report.fail_on_fatal()
+}
+
+/// The directories that the server writes to, and whether it can run without them.
+fn writable_paths(config: &Config) -> Vec<(&'static str, PathBuf, CheckStatus)> {
+    let mut paths = vec![
+        ("store", config.store_path.clone(), CheckStatus::Fatal),
+        ("uploads", config.uploads_path.clone(), CheckStatus::Fatal),
+        (
+            "search index",
+            config.search_index_path.clone(),
+            CheckStatus::Fatal,
+        ),
+        ("config", config.config_dir.clone(), CheckStatus::Warning),
+        (
+            "backups",
+            config.config_dir.join("backups"),
+            CheckStatus::Warning,
+        ),
+    ];
+    if let Some(audit_dir) = &config.opts.audit_dir {
+        let status = if config.opts.audit_strict {
+            CheckStatus::Fatal
+        } else {
+            CheckStatus::Warning
+        };
+        paths.push(("audit log", audit_dir.clone(), status));
+    }
+    paths
+}
+
+fn check_paths(config: &Config, report: &mut SelfCheckReport) {
+    let mut problems = Vec::new();
+    let mut status = CheckStatus::Ok;
+    for (name, path, severity) in writable_paths(config) {
+        if let Err(e) = check_writable(&path) {
+            problems.push(format!(
+                "the {} directory {:?} is not writable ({})",
+                name, path, e
+            ));
+            status = status.max(severity);
+        }
+    }
+    if problems.is_empty() {
+        report.add("paths", CheckStatus::Ok, "All directories are writable");
+    } else {
+        report.add(
+            "paths",
+            status,
+            format!(
+                "{}. Change their permissions, or point `--data-dir` / `--config-dir` somewhere else.",
+                problems.join("; ")
+            ),
+        );
+    }
+}
+
+/// Writes and removes a file in the directory.
+/// Directories that don't exist yet are not created, so instead their closest existing parent is checked.
+fn check_writable(path: &Path) -> std::io::Result<()> {
+    let existing = path
+        .ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(path);
+    if !existing.is_dir() {
+        return Err(std::io::Error::other(format!(
+            "{:?} is not a directory",
+            existing
+        )));
+    }
+    let probe = existing.join(format!(
+        ".atomic-write-check-{}",
+        atomic_lib::utils::random_string(8)
+    ));
+    std::fs::write(&probe, b"")?;
+    std::fs::remove_file(&probe)
+}
+
+/// An index that was built with another schema can't be opened, so it is rebuilt.
+fn check_search_index(config: &Config, report: &mut SelfCheckReport) {
+    if !config.search_index_path.join("meta.json").exists() {
+        report.add("search index", CheckStatus::Ok, "Will be created");
+        return;
+    }
+    let expected = crate::search::build_schema()
+        .ok()
+        .and_then(|schema| serde_json::to_value(schema).ok());
+    let current = tantivy::Index::open_in_dir(&config.search_index_path)
+        .ok()
+        .and_then(|index| serde_json::to_value(index.schema()).ok());
+    if current.is_some() && current == expected {
+        report.add("search index", CheckStatus::Ok, "Schema is up to date");
+    } else {
+        report.rebuild_indexes = true;
+        report.add(
+            "search index",
+            CheckStatus::Warning,
+            "The search index was built by another version, or can't be read. It is rebuilt on startup.",
+        );
+    }
+}
+
+/// The subjects in the store contain the server URL, so they can't be found after it changes.
+fn check_server_url(config: &Config, store: &Db, report: &mut SelfCheckReport) {
+    let server_url = &config.server_url;
+    if store.get_resource(server_url).is_ok() {
+        report.add(
+            "server url",
+            CheckStatus::Ok,
+            format!("The Drive at {} exists", server_url),
+        );
+    } else {
+        let mut query = Query::new_class(urls::DRIVE);
+        query.include_external = true;
+        let drives = store
+            .query(&query)
+            .map(|result| result.subjects)
+            .unwrap_or_default();
+        let status = if config.opts.initialize {
+            CheckStatus::Warning
+        } else {
+            CheckStatus::Fatal
+        };
+        let message = match drives.first() {
+            Some(stored) => format!(
+                "The store has no Drive at the configured server URL {}, but it does contain Drives at {}. \
+                 Start with `--server-url {}` (or set ATOMIC_SERVER_URL) to keep using the stored data, \
+                 or pass `--initialize` to create a new Drive at {}.",
+                server_url,
+                drives.join(", "),
+                stored,
+                server_url
+            ),
+            None => format!(
+                "The store contains no Drive. Pass `--initialize` to create one at {}.",
+                server_url
+            ),
+        };
+        report.add("server url", status, message);
+    }
+
+    match atomic_lib::config::read_config(&config.config_file_path) {
+        Ok(agent_config) if !agent_config.agent.starts_with(server_url.as_str()) => report.add(
+            "agent",
+            CheckStatus::Warning,
+            format!(
+                "The default Agent {} in {:?} is not hosted at {}. Commits it signs can only be verified while {} is available.",
+                agent_config.agent, config.config_file_path, server_url, agent_config.agent
+            ),
+        ),
+        Ok(agent_config) => report.add(
+            "agent",
+            CheckStatus::Ok,
+            format!("The default Agent is {}", agent_config.agent),
+        ),
+        Err(_) => report.add("agent", CheckStatus::Ok, "A new default Agent will be created"),
+    }
+}
+
+/// The Class index is empty while Classes exist, e.g. after a crash during an index rebuild.
+fn check_index(store: &Db, report: &mut SelfCheckReport) {
+    let mut query = Query::new_class(urls::CLASS);
+    query.include_external = true;
+    query.limit = Some(1);
+    let indexed = store.query(&query).map(|result| result.count).unwrap_or(0);
+    if indexed == 0 && store.get_resource(urls::CLASS).is_ok() {
+        report.rebuild_indexes = true;
+        report.add(
+            "index",
+            CheckStatus::Warning,
+            "The index is incomplete. It is rebuilt on startup.",
+        );
+    } else {
+        report.add("index", CheckStatus::Ok, "The index is complete");
+    }
+}
+
+/// Resources whose parents form a loop can't be read or edited by anyone but the server Agent, so they need to be fixed by hand.
+fn check_hierarchy(store: &Db, report: &mut SelfCheckReport) {
+    let cycles = atomic_lib::hierarchy::find_cycles(store);
+    if cycles.is_empty() {
+        report.add("hierarchy", CheckStatus::Ok, "No cycles in the hierarchy");
+        return;
+    }
+    report.add(
+        "hierarchy",
+        CheckStatus::Warning,
+        format!(
+            "{} cycles in the hierarchy, set the parent of one of the Resources in each to fix it: {}",
+            cycles.len(),
+            cycles
+                .iter()
+                .take(LISTED_SUBJECTS)
+                .map(|cycle| format!("{} -> {}", cycle.join(" -> "), cycle[0]))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    );
+}
+
+/// Validates the first Resources of the store, which is quick and catches most systematic problems.
+fn check_sample(store: &Db, report: &mut SelfCheckReport) {
+    let mut checked = 0;
+    let mut invalid: Vec<String> = Vec::new();
+    for resource in store.all_resources(false).take(SAMPLE_SIZE) {
+        checked += 1;
+        if let Err(e) = resource.check_required_props(store) {
+            invalid.push(format!("{} ({})", resource.get_subject(), e));
+        }
+    }
+    if invalid.is_empty() {
+        report.add(
+            "resources",
+            CheckStatus::Ok,
+            format!("{} Resources are valid", checked),
+        );
+    } else {
+        report.add(
+            "resources",
+            CheckStatus::Warning,
+            format!(
+                "{} of {} sampled Resources are invalid, such as {}",
+                invalid.len(),
+                checked,
+                invalid
+                    .iter()
+                    .take(LISTED_SUBJECTS)
+                    .cloned()
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reports_unwritable_and_fresh_paths() {
+        let dir = format!("./.temp/{}/check", atomic_lib::utils::random_string(10));
+        let missing = Path::new(&dir).join("does/not/exist");
+        check_writable(&missing).unwrap();
+        assert!(!missing.exists(), "the check should not create directories");
+
+        let file = Path::new(&dir).join("file");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&file, "not a directory").unwrap();
+        check_writable(&file.join("child")).unwrap_err();
+    }
+
+    #[test]
+    fn summarizes_checks() {
+        let mut report = SelfCheckReport::default();
+        report.add("paths", CheckStatus::Ok, "fine");
+        report.add("server url", CheckStatus::Fatal, "wrong");
+        assert_eq!(report.fatal().len(), 1);
+        assert!(report.summary().contains("fatal    server url  wrong"));
+        let err = report.fail_on_fatal().unwrap_err();
+        assert!(err.message.contains("server url: wrong"));
+    }
+
+    #[test]
+    fn detects_a_changed_server_url() {
+        use clap::Parser;
+        let dir = format!("./.temp/{}", atomic_lib::utils::random_string(10));
+        let opts = crate::config::Opts::parse_from([
+            "atomic-server",
+            "--data-dir",
+            &format!("{}/data", dir),
+            "--config-dir",
+            &format!("{}/config", dir),
+            "--server-url",
+            "http://new.example.com",
+        ]);
+        let config = crate::config::build_config(opts).unwrap();
+        let store = Db::init(&config.store_path, "http://old.example.com".into()).unwrap();
+        let agent = store.create_agent(None).unwrap();
+        store.set_default_agent(agent);
+        store.populate().unwrap();
+
+        let mut report = SelfCheckReport::default();
+        check_store(&config, &store, false, &mut report);
+        let err = report.fail_on_fatal().unwrap_err();
+        assert!(
+            err.message.contains("--server-url http://old.example.com"),
+            "{}",
+            err.message
+        );
+    }
+}
@@ -1,35 +1,23 @@
+use std::time::Duration;
+
 use actix_cors::Cors;
-use actix_web::{middleware, web, HttpServer};
-use atomic_lib::Storelike;
+use actix_web::{http::KeepAlive, middleware, web, HttpServer};
+use atomic_lib::agents::ForAgent;
 
 use crate::errors::AtomicServerResult;
 
 fn rebuild_indexes(appstate: &crate::appstate::AppState) -> AtomicServerResult<()> {
-    let appstate_clone = appstate.clone();
-
-    actix_web::rt::spawn(async move {
-        appstate_clone
-            .store
-            .clear_index()
-            .expect("Failed to clear value index");
-        appstate_clone
-            .store
-            .build_index(true)
-            .expect("Failed to build value index");
-    });
-    tracing::info!("Removing existing search index...");
-    appstate_clone
-        .search_state
-        .writer
-        .write()
-        .expect("Could not get a lock on search writer")
-        .delete_all_documents()?;
-    crate::search::add_all_resources(&appstate_clone.search_state, &appstate.store)?;
+    let job = appstate.job_queue.enqueue(
+        crate::jobs::JobType::RebuildIndexes,
+        serde_json::json!({}),
+        &ForAgent::Sudo,
+    )?;
+    tracing::info!("Rebuilding indexes in job {}", job.get_subject());
     Ok(())
 }
 
 // Increase the maximum payload size (for POSTing a body, for example) to 50MB
-const PAYLOAD_MAX: usize = 50_242_880;
+pub(crate) const PAYLOAD_MAX: usize = 50_242_880;
 
 /// Start the server
 pub async fn serve(config: crate::config::Config) -> AtomicServerResult<()> {
@@ -37,20 +25,32 @@ pub async fn serve(config: crate::config::Config) -> AtomicServerResult<()> {
     let tracing_chrome_flush_guard = crate::trace::init_tracing(&config);
 
     // Setup the database and more
-    let appstate = crate::appstate::init(config.clone())?;
+    let appstate = match crate::appstate::init(config.clone()) {
+        Ok(appstate) => appstate,
+        Err(e) => {
+            if let Some(dir) = &config.ephemeral_dir {
+                crate::serve_file::clean_up(dir);
+            }
+            return Err(e);
+        }
+    };
 
-    // Start async processes
-    if config.opts.rebuild_indexes {
+    // Start async processes. The self-check can request a rebuild, too.
+    if appstate.config.opts.rebuild_indexes {
         rebuild_indexes(&appstate)?;
     }
 
-    let server = HttpServer::new(move || {
+    let connections = appstate.connections.clone();
+    let webhooks = appstate.webhooks.clone();
+    let opts = &config.opts;
+    let mut server = HttpServer::new(move || {
         let cors = Cors::permissive();
 
         actix_web::App::new()
             .app_data(web::PayloadConfig::new(PAYLOAD_MAX))
             .app_data(web::Data::new(appstate.clone()))
             .wrap(cors)
+            .wrap_fn(crate::security_headers::wrap)
             .wrap(tracing_actix_web::TracingLogger::default())
             .wrap(middleware::Compress::default())
             // Here are the actual handlers / endpoints
@@ -64,9 +64,26 @@ pub async fn serve(config: crate::config::Config) -> AtomicServerResult<()> {
                     // register error_handler for JSON extractors.
                     .error_handler(crate::jsonerrors::json_error_handler),
             )
-    });
+    })
+    .max_connections(opts.max_connections)
+    .client_request_timeout(Duration::from_millis(opts.client_request_timeout_ms))
+    .client_disconnect_timeout(Duration::from_millis(opts.client_disconnect_timeout_ms))
+    .keep_alive(match opts.keep_alive {
+        0 => KeepAlive::Disabled,
+        seconds => KeepAlive::Timeout(Duration::from_secs(seconds)),
+    })
+    .on_connect(move |_connection, extensions| connections.on_connect(extensions));
+    if let Some(workers) = opts.workers {
+        server = server.workers(workers);
+    }
 
-    let message = format!("{}\n\nVisit {}\n\n", BANNER, config.server_url);
+    let message = match &config.opts.serve_file {
+        Some(file) => format!(
+            "{}\n\nServing {:?} read-only at {}\n\n",
+            BANNER, file, config.server_url
+        ),
+        None => format!("{}\n\nVisit {}\n\n", BANNER, config.server_url),
+    };
 
     if config.opts.https {
         if cfg!(feature = "https") {
@@ -82,13 +99,12 @@ pub async fn serve(config: crate::config::Config) -> AtomicServerResult<()> {
                     .expect("HTTPS TLS Configuration with Let's Encrypt failed.");
                 let endpoint = format!("{}:{}", config.opts.ip, config.opts.port_https);
                 tracing::info!("Binding HTTPS server to endpoint {}", endpoint);
-                println!("{}", message);
-                server
+                let server = server
                     .bind_rustls(&endpoint, https_config)
-                    .map_err(|e| format!("Cannot bind to endpoint {}: {}", &endpoint, e))?
-                    .shutdown_timeout(TIMEOUT)
-                    .run()
-                    .await?;
+                    .map_err(|e| format!("Cannot bind to endpoint {}: {}", &endpoint, e))?;
+                // Printed once the server listens, since scripts wait for the URL
+                println!("{}", message);
+                server.shutdown_timeout(TIMEOUT).run().await?;
             }
         } else {
             return Err("The HTTPS feature has been disabled for this build. Please compile atomic-server with the HTTP feature. `cargo install atomic-server`".into());
@@ -96,15 +112,22 @@ pub async fn serve(config: crate::config::Config) -> AtomicServerResult<()> {
     } else {
         let endpoint = format!("{}:{}", config.opts.ip, config.opts.port);
         tracing::info!("Binding HTTP server to endpoint {}", endpoint);
+        let server = server
+            .bind(&endpoint)
+            .map_err(|e| format!("Cannot bind to endpoint {}: {}", &endpoint, e))?;
+        // Printed once the server listens, since scripts wait for the URL
         println!("{}", message);
-        server
-            .bind(&format!("{}:{}", config.opts.ip, config.opts.port))
-            .map_err(|e| format!("Cannot bind to endpoint {}: {}", &endpoint, e))?
-            .shutdown_timeout(TIMEOUT)
-            .run()
-            .await?;
+        server.shutdown_timeout(TIMEOUT).run().await?;
     }
     tracing::info!("Cleaning up");
+    // Requests have finished, so no new Commits are applied. Deliver the remaining ones.
+    let grace = Duration::from_secs(config.opts.shutdown_grace_seconds);
+    if !webhooks.shutdown(grace) {
+        tracing::warn!(
+            "Not all Commits were delivered to Webhooks within {:?}, the rest is delivered after the next start",
+            grace
+        );
+    }
 
     // Cleanup, runs when server is stopped
     if let Some(guard) = tracing_chrome_flush_guard {
@@ -118,6 +141,10 @@ pub async fn serve(config: crate::config::Config) -> AtomicServerResult<()> {
         }
     }
 
+    if let Some(dir) = &config.ephemeral_dir {
+        crate::serve_file::clean_up(dir);
+    }
+
     tracing::info!("Server stopped");
     Ok(())
 }
@@ -0,0 +1,187 @@
+//! Serves a single exported file read-only, without a data directory, using `--serve-file`. Useful for demos and reproducing bug reports.
+//! The store, search index and config are kept in a temporary directory (in memory on Linux, in `/dev/shm`), which is removed when the server stops.
+//! Subjects in the file are moved to the temporary server URL, so links between them work locally.
+//! Everything that changes data is refused, see [crate::replication::reject_writes].
+
+use std::path::{Path, PathBuf};
+
+use atomic_lib::{agents::ForAgent, Storelike};
+use serde_json::Value as JsonValue;
+
+use crate::{config::Opts, errors::AtomicServerResult};
+
+/// The default ontology is built into the store, so its subjects are never moved.
+const ONTOLOGY_ORIGIN: &str = "https://atomicdata.dev";
+
+/// Overrides the options that would make the server persist or accept anything, and picks a free port.
+/// Returns the temporary directory for all data.
+pub fn prepare(opts: &mut Opts) -> AtomicServerResult<PathBuf> {
+    let port = std::net::TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|listener| listener.local_addr())
+        .map_err(|e| format!("Could not find a free port: {}", e))?
+        .port();
+    opts.port = port.into();
+    opts.ip = std::net::Ipv4Addr::LOCALHOST.into();
+    opts.domain = "localhost".into();
+    opts.server_url = None;
+    opts.https = false;
+    opts.initialize = true;
+    opts.rebuild_indexes = false;
+    // Visitors of a demo are not signed in
+    opts.public_mode = true;
+    opts.disable_invites = true;
+    opts.audit_dir = None;
+    opts.replica_of = None;
+    opts.cdn_purge_url = None;
+
+    let shm = Path::new("/dev/shm");
+    let parent = if shm.is_dir() {
+        shm.to_path_buf()
+    } else {
+        std::env::temp_dir()
+    };
+    Ok(parent.join(format!(
+        "atomic-serve-file-{}",
+        atomic_lib::utils::random_string(10)
+    )))
+}
+
+/// Reads a JSON-AD or `.ad3` file, moves its subjects to the URL of the store and saves its Resources.
+/// Returns the amount of Resources.
+pub fn load(store: &impl Storelike, path: &Path) -> AtomicServerResult<usize> {
+    let text =
+        std::fs::read_to_string(path).map_err(|e| format!("Could not read {:?}: {}", path, e))?;
+    let mut json = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("ad3") => ad3_to_json_ad(&text)?,
+        _ => serde_json::from_str(&text).map_err(|e| format!("Invalid JSON-AD: {}", e))?,
+    };
+    let base = store.get_server_url().trim_end_matches('/').to_string();
+    for origin in origins(&json) {
+        if origin != base && origin != ONTOLOGY_ORIGIN {
+            tracing::info!("Serving {} at {}", origin, base);
+            rewrite(&mut json, &origin, &base);
+        }
+    }
+    let parse_opts = atomic_lib::parse::ParseOpts {
+        importer: Some(base),
+        for_agent: ForAgent::Sudo,
+        overwrite_outside: true,
+        save: atomic_lib::parse::SaveOpts::Save,
+        signer: None,
+        base: None,
+    };
+    Ok(store.import(&json.to_string(), &parse_opts)?)
+}
+
+/// Removes the temporary directory of [prepare].
+pub fn clean_up(dir: &Path) {
+    match std::fs::remove_dir_all(dir) {
+        Ok(()) => tracing::info!("Removed {:?}", dir),
+        Err(e) => tracing::error!("Could not remove {:?}: {}", dir, e),
+    }
+}
+
+/// Groups the Atoms of AD3 (one `[subject, property, value]` array per line) into JSON-AD objects.
+fn ad3_to_json_ad(text: &str) -> AtomicServerResult<JsonValue> {
+    let mut resources: Vec<serde_json::Map<String, JsonValue>> = Vec::new();
+    let mut positions: std::collections::HashMap<String, usize> = Default::default();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let [subject, property, value]: [String; 3] = serde_json::from_str(line)
+            .map_err(|e| format!("Invalid AD3 on line {}: {}", number + 1, e))?;
+        let position = *positions.entry(subject.clone()).or_insert_with(|| {
+            let mut resource = serde_json::Map::new();
+            resource.insert("@id".into(), JsonValue::String(subject));
+            resources.push(resource);
+            resources.len() - 1
+        });
+        resources[position].insert(property, JsonValue::String(value));
+    }
+    Ok(JsonValue::Array(
+        resources.into_iter().map(JsonValue::Object).collect(),
+    ))
+}
+
+/// The origins of the `@id`s in the file.
+fn origins(json: &JsonValue) -> std::collections::BTreeSet<String> {
+    let objects: Vec<&JsonValue> = match json {
+        JsonValue::Array(items) => items.iter().collect(),
+        object => vec![object],
+    };
+    objects
+        .into_iter()
+        .filter_map(|object| object.get("@id")?.as_str())
+        .filter_map(|subject| atomic_lib::utils::server_url(subject).ok())
+        .map(|origin| origin.trim_end_matches('/').to_string())
+        .collect()
+}
+
+/// Replaces the origin in all keys and strings, including those in AD3 arrays.
+fn rewrite(json: &mut JsonValue, from: &str, to: &str) {
+    match json {
+        JsonValue::String(text) => *text = replace_origin(text, from, to),
+        JsonValue::Array(items) => items.iter_mut().for_each(|item| rewrite(item, from, to)),
+        JsonValue::Object(map) => {
+            *map = std::mem::take(map)
+                .into_iter()
+                .map(|(key, mut value)| {
+                    rewrite(&mut value, from, to);
+                    (replace_origin(&key, from, to), value)
+                })
+                .collect();
+        }
+        _ => {}
+    }
+}
+
+/// Replaces `from` where it is a complete origin, so `https://example.com` does not match `https://example.community` or `https://example.com:8080`.
+fn replace_origin(text: &str, from: &str, to: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(from) {
+        let end = start + from.len();
+        let complete = rest[end..]
+            .chars()
+            .next()
+            .map(|next| !next.is_alphanumeric() && !matches!(next, '.' | '-' | '_' | ':'))
+            .unwrap_or(true);
+        out.push_str(&rest[..start]);
+        out.push_str(if complete { to } else { from });
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn moves_subjects_to_the_local_server() {
+        let ad3 = r#"["https://example.com/a","https://atomicdata.dev/properties/name","A"]
+["https://example.com/a","https://atomicdata.dev/properties/write","[\"https://example.com/agents/x\"]"]
+["https://example.com/b","https://example.com/properties/link","https://example.community/c"]
+"#;
+        let mut json = ad3_to_json_ad(ad3).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 2);
+        let origins = origins(&json);
+        assert_eq!(
+            origins.into_iter().collect::<Vec<_>>(),
+            ["https://example.com"]
+        );
+
+        rewrite(&mut json, "https://example.com", "http://localhost:1234");
+        assert_eq!(json[0]["@id"], "http://localhost:1234/a");
+        assert_eq!(
+            json[0]["https://atomicdata.dev/properties/write"],
+            r#"["http://localhost:1234/agents/x"]"#
+        );
+        assert_eq!(
+            json[1]["http://localhost:1234/properties/link"],
+            "https://example.community/c"
+        );
+    }
+}
@@ -0,0 +1,381 @@
+//! Settings of the server that can be changed at runtime, stored in the `ServerSettings` resource at `{server_url}/settings`.
+//! On startup, the config file and environment create this resource if it does not exist yet.
+//! After that, the resource is leading: Commits to it change the settings of the running server.
+//! The [crate::commit_monitor::CommitMonitor] refreshes the snapshot that handlers read, so they don't have to fetch the resource on every request.
+//! Removing a property from the resource falls back to the value from the config.
+//!
+//! Only Agents with write rights to the root Drive can change the settings.
+//! Settings that affect the security of the server, such as TLS, the bind address and data paths, can only be set in the config file or environment.
+
+use std::sync::{Arc, RwLock};
+
+use atomic_lib::{
+    agents::ForAgent, hierarchy::check_write, urls, Commit, Resource, Storelike, Value,
+};
+
+use crate::{
+    config::Opts,
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+};
+
+const SETTINGS_PATH: &str = "/settings";
+const MAX_SCRIPT_LENGTH: usize = 64 * 1024;
+const MAX_TRASH_RETENTION_DAYS: i64 = 100 * 365;
+
+/// Properties of the ServerSettings that change a setting.
+const SETTINGS_PROPS: &[&str] = &[
+    urls::COMMIT_RATE_LIMIT,
+    urls::MAX_COMMIT_SIZE,
+    urls::MAX_COMMIT_ARRAY_LENGTH,
+    urls::TRASH_RETENTION_DAYS,
+    urls::MAX_UPLOAD_SIZE,
+    urls::INVITES_ENABLED,
+    urls::CUSTOM_SCRIPT,
+    urls::NOTIFY_ON,
+];
+
+/// Properties that every Resource can have, which don't change any setting.
+const RESOURCE_PROPS: &[&str] = &[
+    urls::IS_A,
+    urls::PARENT,
+    urls::NAME,
+    urls::DESCRIPTION,
+    urls::READ,
+    urls::WRITE,
+];
+
+/// Config options that can only be set in the config file or environment, with the flag that sets them.
+/// Properties are matched on the last part of their URL, ignoring case, `-` and `_`.
+const FILE_ONLY: &[(&str, &str)] = &[
+    ("https", "--https"),
+    ("tls", "--https"),
+    ("httpsDns", "--https-dns"),
+    ("email", "--email"),
+    ("ip", "--ip"),
+    ("bindAddress", "--ip"),
+    ("port", "--port"),
+    ("portHttps", "--port-https"),
+    ("domain", "--domain"),
+    ("serverUrl", "--server-url"),
+    ("dataDir", "--data-dir"),
+    ("configDir", "--config-dir"),
+    ("storePath", "--data-dir"),
+    ("uploadsPath", "--data-dir"),
+    ("publicMode", "--public-mode"),
+];
+
+/// A snapshot of the runtime settings.
+/// `None` means there is no limit.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerSettings {
+    pub commit_rate_limit: Option<u64>,
+    pub max_commit_size: Option<u64>,
+    pub max_commit_array_length: Option<u64>,
+    /// If `None`, the trash is never purged automatically.
+    pub trash_retention_days: Option<u64>,
+    pub max_upload_size: Option<u64>,
+    pub invites_enabled: bool,
+    pub custom_script: String,
+    /// Properties that create a Notification for the Agents they are set to
+    pub notify_on: Vec<String>,
+}
+
+impl ServerSettings {
+    pub fn from_opts(opts: &Opts) -> ServerSettings {
+        ServerSettings {
+            commit_rate_limit: opts.commit_rate_limit,
+            max_commit_size: opts.max_commit_size,
+            max_commit_array_length: opts.max_commit_array_length,
+            trash_retention_days: opts.trash_retention_days,
+            max_upload_size: opts.max_upload_size,
+            invites_enabled: !opts.disable_invites,
+            custom_script: opts.script.clone(),
+            notify_on: opts.notify_on.clone(),
+        }
+    }
+
+    /// Reads the settings from the ServerSettings resource.
+    /// Properties that are missing use the value from `defaults`.
+    /// For limits, `0` means no limit, like it does for the overrides on Agents.
+    pub fn from_resource(resource: &Resource, defaults: &ServerSettings) -> ServerSettings {
+        let integer = |prop: &str| match resource.get(prop) {
+            Ok(Value::Integer(n)) if *n >= 0 => Some(*n as u64),
+            _ => None,
+        };
+        let limit = |prop: &str, default: Option<u64>| match integer(prop) {
+            Some(0) => None,
+            Some(n) => Some(n),
+            None => default,
+        };
+        ServerSettings {
+            commit_rate_limit: limit(urls::COMMIT_RATE_LIMIT, defaults.commit_rate_limit),
+            max_commit_size: limit(urls::MAX_COMMIT_SIZE, defaults.max_commit_size),
+            max_commit_array_length: limit(
+                urls::MAX_COMMIT_ARRAY_LENGTH,
+                defaults.max_commit_array_length,
+            ),
+            trash_retention_days: integer(urls::TRASH_RETENTION_DAYS)
+                .or(defaults.trash_retention_days),
+            max_upload_size: limit(urls::MAX_UPLOAD_SIZE, defaults.max_upload_size),
+            invites_enabled: match resource.get(urls::INVITES_ENABLED) {
+                Ok(Value::Boolean(enabled)) => *enabled,
+                _ => defaults.invites_enabled,
+            },
+            custom_script: match resource.get(urls::CUSTOM_SCRIPT) {
+                Ok(Value::String(script)) => script.clone(),
+                _ => defaults.custom_script.clone(),
+            },
+            notify_on: match resource.get(urls::NOTIFY_ON) {
+                Ok(value @ Value::ResourceArray(_)) => value.to_subjects(None).unwrap_or_default(),
+                _ => defaults.notify_on.clone(),
+            },
+        }
+    }
+
+    /// The values that are stored when the ServerSettings resource is created.
+    fn propvals(&self) -> Vec<(&'static str, Value)> {
+        let mut propvals = vec![(urls::INVITES_ENABLED, Value::Boolean(self.invites_enabled))];
+        let integers = [
+            (urls::COMMIT_RATE_LIMIT, self.commit_rate_limit),
+            (urls::MAX_COMMIT_SIZE, self.max_commit_size),
+            (urls::MAX_COMMIT_ARRAY_LENGTH, self.max_commit_array_length),
+            (urls::TRASH_RETENTION_DAYS, self.trash_retention_days),
+            (urls::MAX_UPLOAD_SIZE, self.max_upload_size),
+        ];
+        for (prop, value) in integers {
+            if let Some(value) = value {
+                propvals.push((prop, Value::Integer(value as i64)));
+            }
+        }
+        propvals.push((urls::NOTIFY_ON, self.notify_on.clone().into()));
+        if !self.custom_script.is_empty() {
+            propvals.push((
+                urls::CUSTOM_SCRIPT,
+                Value::String(self.custom_script.clone()),
+            ));
+        }
+        propvals
+    }
+}
+
+/// Holds the current [ServerSettings]. Cheap to clone, clones share the snapshot.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    subject: String,
+    /// The settings from the config file and environment
+    defaults: Arc<ServerSettings>,
+    current: Arc<RwLock<ServerSettings>>,
+}
+
+impl Settings {
+    /// Uses the config until [Settings::seed] has read the ServerSettings resource.
+    pub fn new(opts: &Opts, server_url: &str) -> Settings {
+        let defaults = ServerSettings::from_opts(opts);
+        Settings {
+            subject: format!("{}{}", server_url, SETTINGS_PATH),
+            current: Arc::new(RwLock::new(defaults.clone())),
+            defaults: Arc::new(defaults),
+        }
+    }
+
+    /// Subject of the ServerSettings resource
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    /// Returns a copy of the current settings.
+    pub fn get(&self) -> ServerSettings {
+        match self.current.read() {
+            Ok(current) => current.clone(),
+            Err(_) => (*self.defaults).clone(),
+        }
+    }
+
+    /// Creates the ServerSettings resource from the config if it does not exist yet, and reads it.
+    pub fn seed(&self, store: &impl Storelike) -> AtomicServerResult<()> {
+        let resource = match store.get_resource(&self.subject) {
+            Ok(resource) => resource,
+            Err(_) => {
+                tracing::info!("Creating server settings at {}", self.subject);
+                let mut resource = Resource::new_instance(urls::SERVER_SETTINGS, store)?;
+                resource.set_subject(self.subject.clone());
+                resource.set_propval_string(urls::PARENT.into(), store.get_server_url(), store)?;
+                resource.set_propval_string(urls::NAME.into(), "Server settings", store)?;
+                for (prop, value) in self.defaults.propvals() {
+                    resource.set_propval(prop.into(), value, store)?;
+                }
+                resource.save_locally(store)?;
+                resource
+            }
+        };
+        self.refresh(Some(&resource));
+        Ok(())
+    }
+
+    /// Updates the snapshot after the ServerSettings resource has changed.
+    /// If the resource is gone, the config is used.
+    pub fn refresh(&self, resource: Option<&Resource>) {
+        let settings = match resource {
+            Some(resource) => ServerSettings::from_resource(resource, &self.defaults),
+            None => (*self.defaults).clone(),
+        };
+        match self.current.write() {
+            Ok(mut current) => *current = settings,
+            Err(e) => tracing::error!("Could not update server settings: {}", e),
+        }
+    }
+
+    /// Checks a Commit that was sent by a client. Does nothing if it edits another Resource.
+    /// Only admins can change the settings, and only to valid values.
+    pub fn check_commit(
+        &self,
+        store: &impl Storelike,
+        commit: &Commit,
+        for_agent: &ForAgent,
+    ) -> AtomicServerResult<()> {
+        if commit.subject != self.subject {
+            return Ok(());
+        }
+        let drive = store.get_resource(store.get_server_url())?;
+        check_write(store, &drive, for_agent).map_err(|e| {
+            AtomicServerError::new(
+                format!(
+                    "Only Agents with write rights to the root Drive can change the server settings. {}",
+                    e
+                ),
+                AppErrorType::Unauthorized,
+            )
+        })?;
+        if commit.destroy == Some(true) {
+            return Err("The server settings can't be destroyed. Remove a property to use the value from the config instead.".into());
+        }
+        let changed = [
+            &commit.set,
+            &commit.push,
+            &commit.pull,
+            &commit.patch,
+            &commit.increment,
+        ]
+        .into_iter()
+        .flatten()
+        .flat_map(|changes| changes.keys())
+        .chain(commit.remove.iter().flatten());
+        for prop in changed {
+            check_settings_prop(prop)?;
+        }
+        for (prop, value) in commit.set.iter().flatten() {
+            check_settings_value(prop, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors if the Property is not one of the [SETTINGS_PROPS], with a specific message for file-only settings.
+fn check_settings_prop(prop: &str) -> AtomicServerResult<()> {
+    if SETTINGS_PROPS.contains(&prop) || RESOURCE_PROPS.contains(&prop) {
+        return Ok(());
+    }
+    let normalize = |name: &str| name.replace(['-', '_'], "").to_lowercase();
+    let name = prop.rsplit('/').next().unwrap_or(prop);
+    if let Some((_, flag)) = FILE_ONLY
+        .iter()
+        .find(|(option, _)| normalize(option) == normalize(name))
+    {
+        return Err(format!(
+            "'{}' affects the security of the server, so it can only be set in the config file or environment (`{}`), not in the server settings.",
+            name, flag
+        )
+        .into());
+    }
+    Err(format!(
+        "{} is not a server setting. The settings that can be changed at runtime are: {}",
+        prop,
+        SETTINGS_PROPS.join(", ")
+    )
+    .into())
+}
+
+/// Checks the datatype and range of a new value for a setting.
+fn check_settings_value(prop: &str, value: &Value) -> AtomicServerResult<()> {
+    let error = |expected: &str| -> AtomicServerResult<()> {
+        Err(format!(
+            "Invalid value '{}' for {}, expected {}",
+            value, prop, expected
+        )
+        .into())
+    };
+    match (prop, value) {
+        (urls::INVITES_ENABLED, Value::Boolean(_)) => Ok(()),
+        (urls::INVITES_ENABLED, _) => error("a boolean"),
+        (urls::CUSTOM_SCRIPT, Value::String(script)) if script.len() > MAX_SCRIPT_LENGTH => {
+            error(&format!("at most {} bytes", MAX_SCRIPT_LENGTH))
+        }
+        (urls::CUSTOM_SCRIPT, Value::String(_)) => Ok(()),
+        (urls::CUSTOM_SCRIPT, _) => error("a string"),
+        (urls::NOTIFY_ON, Value::ResourceArray(_)) => Ok(()),
+        (urls::NOTIFY_ON, _) => error("an array of Properties"),
+        (urls::TRASH_RETENTION_DAYS, Value::Integer(days))
+            if (0..=MAX_TRASH_RETENTION_DAYS).contains(days) =>
+        {
+            Ok(())
+        }
+        (urls::TRASH_RETENTION_DAYS, _) => error(&format!(
+            "a number of days between 0 and {}",
+            MAX_TRASH_RETENTION_DAYS
+        )),
+        (prop, Value::Integer(limit)) if SETTINGS_PROPS.contains(&prop) && *limit >= 0 => Ok(()),
+        (prop, _) if SETTINGS_PROPS.contains(&prop) => {
+            error("a positive integer, or 0 for no limit")
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atomic_lib::{commit::CommitBuilder, Db};
+    use clap::Parser;
+
+    #[test]
+    fn settings_resource() {
+        let store = Db::init_temp("settings_resource").unwrap();
+        let opts = Opts::parse_from(["atomic-server", "--max-upload-size", "1000"]);
+        let settings = Settings::new(&opts, store.get_server_url());
+        settings.seed(&store).unwrap();
+        assert_eq!(settings.get().max_upload_size, Some(1000));
+        assert!(settings.get().invites_enabled);
+
+        let agent = store.get_default_agent().unwrap();
+        let resource = store.get_resource(settings.subject()).unwrap();
+        let commit_with = |prop: &str, value: Value| {
+            let mut builder = CommitBuilder::new(settings.subject().into());
+            builder.set(prop.into(), value);
+            builder.sign(&agent, &store, &resource).unwrap()
+        };
+        let admin: ForAgent = agent.subject.clone().into();
+
+        let valid = commit_with(urls::MAX_UPLOAD_SIZE, Value::Integer(0));
+        settings.check_commit(&store, &valid, &admin).unwrap();
+        let negative = commit_with(urls::COMMIT_RATE_LIMIT, Value::Integer(-1));
+        settings
+            .check_commit(&store, &negative, &admin)
+            .unwrap_err();
+        let file_only = commit_with("https://atomicdata.dev/properties/port", Value::Integer(80));
+        let err = settings
+            .check_commit(&store, &file_only, &admin)
+            .unwrap_err();
+        assert!(err.message.contains("--port"), "{}", err.message);
+        let err = settings
+            .check_commit(&store, &valid, &ForAgent::Public)
+            .unwrap_err();
+        assert!(matches!(err.error_type, AppErrorType::Unauthorized));
+
+        // Applying the Commit and refreshing changes the snapshot, `0` removes the limit.
+        let response = valid.apply_unsafe(&store).unwrap();
+        settings.refresh(response.resource_new.as_ref());
+        assert_eq!(settings.get().max_upload_size, None);
+        settings.refresh(None);
+        assert_eq!(settings.get().max_upload_size, Some(1000));
+    }
+}
@@ -0,0 +1,179 @@
+//! First-run setup, which creates the admin Agent of a new server.
+//! As long as no Agent other than the server itself can write to the root Drive, a one-time setup token is generated at startup and printed to the log.
+//! Posting to `/setup?token=...` creates the admin Agent and gives it write rights on the root Drive, using Commits signed by the server.
+//! After that, setup is closed, also after restarting the server.
+
+use std::sync::{Arc, Mutex};
+
+use atomic_lib::{agents::Agent, plugins::invite::add_rights, urls, Storelike, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{AppErrorType, AtomicServerError, AtomicServerResult};
+
+const TOKEN_LENGTH: usize = 32;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SetupRequest {
+    /// Public key of the admin Agent. If not set, a new keypair is generated.
+    pub public_key: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SetupResponse {
+    /// Subject of the admin Agent
+    pub agent: String,
+    /// Only set if the keypair was generated by the server. It is not stored, so it is only shown once.
+    pub private_key: Option<String>,
+}
+
+/// Holds the setup token while setup is open. Cheap to clone, clones share the token.
+#[derive(Clone, Debug, Default)]
+pub struct SetupState {
+    token: Arc<Mutex<Option<String>>>,
+}
+
+impl SetupState {
+    /// Opens setup if the store has no admin yet, and logs the URL containing the token.
+    pub fn init(store: &impl Storelike) -> AtomicServerResult<SetupState> {
+        let state = SetupState::default();
+        if needs_setup(store)? {
+            let token = atomic_lib::utils::random_string(TOKEN_LENGTH);
+            tracing::warn!(
+                "No admin Agent exists yet. Create one by sending a POST request to {}/setup?token={} , optionally with a JSON body containing your `publicKey`. This token can only be used once.",
+                store.get_server_url(),
+                token
+            );
+            *state.token.lock()? = Some(token);
+        }
+        Ok(state)
+    }
+
+    #[cfg(test)]
+    fn is_open(&self) -> bool {
+        self.token.lock().map(|t| t.is_some()).unwrap_or(false)
+    }
+
+    /// Creates the admin Agent and closes setup.
+    pub fn complete(
+        &self,
+        store: &impl Storelike,
+        token: &str,
+        request: SetupRequest,
+    ) -> AtomicServerResult<SetupResponse> {
+        // Holding the lock makes sure the token is used only once, even for concurrent requests.
+        let mut current = self.token.lock()?;
+        let Some(expected) = current.as_ref() else {
+            return Err(AtomicServerError::new(
+                "Setup has already been completed".into(),
+                AppErrorType::Gone,
+            ));
+        };
+        if !constant_time_eq(expected.as_bytes(), token.as_bytes()) {
+            return Err(AtomicServerError::new(
+                "Invalid setup token. Use the token printed in the log of the server.".into(),
+                AppErrorType::Unauthorized,
+            ));
+        }
+        // An admin might have been added in another way, e.g. using the `/setup` Invite.
+        if !needs_setup(store)? {
+            *current = None;
+            return Err(AtomicServerError::new(
+                "Setup has already been completed".into(),
+                AppErrorType::Gone,
+            ));
+        }
+
+        let mut agent = match &request.public_key {
+            Some(public_key) => Agent::new_from_public_key(store, public_key)?,
+            None => Agent::new(None, store)?,
+        };
+        agent.name = request.name.clone();
+        if store.get_resource(&agent.subject).is_err() {
+            agent.to_resource()?.save_locally(store)?;
+        }
+        add_rights(&agent.subject, &agent.subject, true, store)?;
+        add_rights(&agent.subject, store.get_server_url(), true, store)?;
+        close_setup_invite(store)?;
+        *current = None;
+        tracing::info!("Setup completed, admin Agent is {}", agent.subject);
+
+        Ok(SetupResponse {
+            agent: agent.subject,
+            private_key: agent.private_key,
+        })
+    }
+}
+
+/// Whether no Agent, except for the server itself, has write rights on the root Drive.
+pub fn needs_setup(store: &impl Storelike) -> AtomicServerResult<bool> {
+    let drive = store.get_resource(store.get_server_url())?;
+    let server_agent = store.get_default_agent()?.subject;
+    let writers = match drive.get(urls::WRITE) {
+        Ok(value) => value.to_subjects(None)?,
+        Err(_) => Vec::new(),
+    };
+    Ok(!writers
+        .iter()
+        .any(|writer| writer != &server_agent && writer != urls::PUBLIC_AGENT))
+}
+
+/// The `/setup` Invite also gives write rights on the root Drive, so it is used up as well.
+fn close_setup_invite(store: &impl Storelike) -> AtomicServerResult<()> {
+    let Ok(mut invite) = store.get_resource(&format!("{}/setup", store.get_server_url())) else {
+        return Ok(());
+    };
+    invite.set_propval(urls::USAGES_LEFT.into(), Value::Integer(0), store)?;
+    invite.save_locally(store)?;
+    Ok(())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atomic_lib::{hierarchy::check_write, Db};
+
+    #[test]
+    fn setup_creates_admin_once() {
+        let store = Db::init_temp("setup_creates_admin_once").unwrap();
+        let setup = SetupState::init(&store).unwrap();
+        assert!(setup.is_open());
+        let token = setup.token.lock().unwrap().clone().unwrap();
+
+        let err = setup
+            .complete(&store, "wrong", SetupRequest::default())
+            .unwrap_err();
+        assert!(matches!(err.error_type, AppErrorType::Unauthorized));
+        assert!(setup.is_open(), "a wrong token should not close setup");
+
+        let response = setup
+            .complete(&store, &token, SetupRequest::default())
+            .unwrap();
+        let private_key = response.private_key.expect("a keypair should be generated");
+        let admin = Agent::new_from_private_key(None, &store, &private_key);
+        assert_eq!(admin.subject, response.agent);
+        let drive = store.get_resource(store.get_server_url()).unwrap();
+        check_write(&store, &drive, &admin.subject.clone().into()).unwrap();
+        // The rights were given using a Commit
+        let last_commit = store
+            .get_resource(&drive.get(urls::LAST_COMMIT).unwrap().to_string())
+            .unwrap();
+        assert_eq!(
+            last_commit.get(urls::SUBJECT).unwrap().to_string(),
+            store.get_server_url()
+        );
+
+        let err = setup
+            .complete(&store, &token, SetupRequest::default())
+            .unwrap_err();
+        assert!(matches!(err.error_type, AppErrorType::Gone));
+        // Restarting doesn't open setup again
+        assert!(!SetupState::init(&store).unwrap().is_open());
+    }
+}
@@ -0,0 +1,367 @@
+//! Changes outside the store that follow from an applied Commit: the full-text search index and uploaded files.
+//! The [crate::commit_monitor::CommitMonitor] receives Commits after they have been stored, and applies their side effects in phases:
+//!
+//! 1. [stage] builds the search document and finds the uploaded file to remove, without changing anything.
+//! 2. [SideEffects::apply] writes the document to the search index.
+//! 3. [SideEffects::flush] commits the search index, which happens in batches.
+//!    Only after that, the uploaded files of destroyed Resources are removed, since that can't be undone.
+//!
+//! Side effects that fail are not lost: they are collected in [Repairs], which a `repair-side-effects` Job retries.
+
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+
+use atomic_lib::{commit::CommitResponse, plugins::trash::is_trashed, Db, Resource, Storelike};
+use serde::{Deserialize, Serialize};
+use tantivy::Document;
+
+use crate::{errors::AtomicServerResult, search::SearchState};
+
+/// The full-text search index, as used by [SideEffects].
+pub trait SearchIndex {
+    /// Builds the search document of a Resource, without changing the index.
+    fn document(&self, resource: &Resource, store: &Db) -> AtomicServerResult<Document>;
+    /// Removes the document of the subject, and adds the new one if there is one. Not visible until [SearchIndex::commit].
+    fn replace(&self, subject: &str, document: Option<Document>) -> AtomicServerResult<()>;
+    fn commit(&self) -> AtomicServerResult<()>;
+}
+
+impl SearchIndex for SearchState {
+    fn document(&self, resource: &Resource, store: &Db) -> AtomicServerResult<Document> {
+        crate::search::build_document(self, resource, store)
+    }
+
+    fn replace(&self, subject: &str, document: Option<Document>) -> AtomicServerResult<()> {
+        crate::search::remove_resource(self, subject)?;
+        if let Some(document) = document {
+            self.writer.read()?.add_document(document)?;
+        }
+        Ok(())
+    }
+
+    fn commit(&self) -> AtomicServerResult<()> {
+        self.writer.write()?.commit()?;
+        Ok(())
+    }
+}
+
+/// The side effects of a single Commit, ready to be applied.
+pub struct Staged {
+    subject: String,
+    /// `None` if the Resource should no longer be found
+    document: Option<Document>,
+    /// Id of the uploaded file to remove
+    remove_file: Option<String>,
+}
+
+/// Prepares the side effects of a Commit, without applying any of them.
+pub fn stage(
+    commit_response: &CommitResponse,
+    index: &impl SearchIndex,
+    store: &Db,
+) -> AtomicServerResult<Staged> {
+    let subject = commit_response.commit_struct.subject.clone();
+    match &commit_response.resource_new {
+        // Resources in the trash can't be found
+        Some(resource) if !is_trashed(resource) => Ok(Staged {
+            document: Some(index.document(resource, store)?),
+            subject,
+            remove_file: None,
+        }),
+        Some(_) => Ok(Staged {
+            subject,
+            document: None,
+            remove_file: None,
+        }),
+        // Destroyed permanently
+        None => Ok(Staged {
+            subject,
+            document: None,
+            remove_file: commit_response
+                .resource_old
+                .as_ref()
+                .and_then(crate::handlers::upload::file_blob_id),
+        }),
+    }
+}
+
+/// Side effects that failed, and have to be retried using [Repairs::run].
+#[derive(Default, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Repairs {
+    /// Resources whose search document has to be replaced with the current version in the store
+    pub reindex: BTreeSet<String>,
+    /// Ids of uploaded files that have to be removed
+    pub remove_files: BTreeSet<String>,
+}
+
+impl Repairs {
+    pub fn is_empty(&self) -> bool {
+        self.reindex.is_empty() && self.remove_files.is_empty()
+    }
+
+    /// Indexes the Resources as they are in the store, and then removes the files.
+    /// Files that are already gone count as removed.
+    pub fn run(
+        &self,
+        store: &Db,
+        index: &impl SearchIndex,
+        uploads_path: &Path,
+    ) -> AtomicServerResult<()> {
+        for subject in &self.reindex {
+            let document = match store.get_resource(subject) {
+                Ok(resource) if !is_trashed(&resource) => Some(index.document(&resource, store)?),
+                _ => None,
+            };
+            index.replace(subject, document)?;
+        }
+        index.commit()?;
+        for file_id in &self.remove_files {
+            remove_file(uploads_path, file_id)?;
+        }
+        Ok(())
+    }
+}
+
+/// Applies staged side effects, and remembers what still has to happen when the search index is committed.
+pub struct SideEffects {
+    uploads_path: PathBuf,
+    /// Written to the search index since the last commit
+    unflushed: BTreeSet<String>,
+    /// Ids of the uploaded files that are removed after the next commit of the search index
+    pending_files: Vec<String>,
+    repairs: Repairs,
+}
+
+impl SideEffects {
+    pub fn new(uploads_path: PathBuf) -> Self {
+        SideEffects {
+            uploads_path,
+            unflushed: BTreeSet::new(),
+            pending_files: Vec::new(),
+            repairs: Repairs::default(),
+        }
+    }
+
+    pub fn apply(&mut self, staged: Staged, index: &impl SearchIndex) {
+        match index.replace(&staged.subject, staged.document) {
+            Ok(()) => {
+                self.unflushed.insert(staged.subject);
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Could not update {} in the search index, scheduling a repair: {}",
+                    staged.subject,
+                    e
+                );
+                self.repairs.reindex.insert(staged.subject);
+            }
+        }
+        self.pending_files.extend(staged.remove_file);
+    }
+
+    /// For Commits whose side effects could not be staged.
+    pub fn stage_failed(&mut self, subject: &str) {
+        self.repairs.reindex.insert(subject.into());
+    }
+
+    /// Commits the search index, and then removes the files of destroyed Resources.
+    pub fn flush(&mut self, index: &impl SearchIndex) {
+        if let Err(e) = index.commit() {
+            tracing::error!(
+                "Could not commit the search index, scheduling a repair: {}",
+                e
+            );
+            self.repairs.reindex.append(&mut self.unflushed);
+            self.repairs
+                .remove_files
+                .extend(self.pending_files.drain(..));
+            return;
+        }
+        self.unflushed.clear();
+        for file_id in self.pending_files.drain(..) {
+            if let Err(e) = remove_file(&self.uploads_path, &file_id) {
+                tracing::error!("{}, scheduling a repair", e);
+                self.repairs.remove_files.insert(file_id);
+            }
+        }
+    }
+
+    /// Returns the side effects that have to be retried, and forgets them.
+    pub fn take_repairs(&mut self) -> Repairs {
+        std::mem::take(&mut self.repairs)
+    }
+
+    /// Adds repairs that could not be scheduled, so they are tried again later.
+    pub fn restore_repairs(&mut self, mut repairs: Repairs) {
+        self.repairs.reindex.append(&mut repairs.reindex);
+        self.repairs.remove_files.append(&mut repairs.remove_files);
+    }
+}
+
+fn remove_file(uploads_path: &Path, file_id: &str) -> AtomicServerResult<()> {
+    if !crate::handlers::download::is_safe_file_id(file_id) {
+        return Err(format!("Not removing file with unsafe id {}", file_id).into());
+    }
+    let path = uploads_path.join(file_id);
+    match std::fs::remove_file(&path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("Could not remove uploaded file {:?}: {}", path, e).into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::Cell, cell::RefCell, collections::BTreeMap};
+
+    use atomic_lib::{
+        commit::{CommitBuilder, CommitOpts},
+        urls, Value,
+    };
+
+    use super::*;
+
+    /// Keeps documents in memory, and fails when asked to.
+    #[derive(Default)]
+    struct FlakyIndex {
+        fail_writes: Cell<bool>,
+        fail_commits: Cell<bool>,
+        written: RefCell<BTreeMap<String, bool>>,
+        committed: RefCell<BTreeMap<String, bool>>,
+    }
+
+    impl SearchIndex for FlakyIndex {
+        fn document(&self, _resource: &Resource, _store: &Db) -> AtomicServerResult<Document> {
+            Ok(Document::default())
+        }
+
+        fn replace(&self, subject: &str, document: Option<Document>) -> AtomicServerResult<()> {
+            if self.fail_writes.get() {
+                return Err("Injected write failure".into());
+            }
+            self.written
+                .borrow_mut()
+                .insert(subject.into(), document.is_some());
+            Ok(())
+        }
+
+        fn commit(&self) -> AtomicServerResult<()> {
+            if self.fail_commits.get() {
+                return Err("Injected commit failure".into());
+            }
+            *self.committed.borrow_mut() = self.written.borrow().clone();
+            Ok(())
+        }
+    }
+
+    const OPTS: CommitOpts = CommitOpts {
+        validate_schema: false,
+        validate_signature: false,
+        validate_timestamp: false,
+        validate_rights: false,
+        validate_previous_commit: false,
+        validate_for_agent: None,
+        update_index: true,
+        validate_relative_urls: false,
+    };
+
+    fn apply(store: &Db, subject: &str, change: impl FnOnce(&mut CommitBuilder)) -> CommitResponse {
+        let resource = store
+            .get_resource(subject)
+            .unwrap_or_else(|_| Resource::new(subject.into()));
+        let mut builder = CommitBuilder::new(subject.into());
+        change(&mut builder);
+        builder
+            .sign(&store.get_default_agent().unwrap(), store, &resource)
+            .unwrap()
+            .apply_opts(store, &OPTS)
+            .unwrap()
+    }
+
+    #[test]
+    fn index_failures_converge_through_repairs() {
+        let store = Db::init_temp("side_effects_index").unwrap();
+        let uploads = PathBuf::from(".temp/side_effects_index");
+        let subject = format!("{}/indexed", store.get_server_url());
+        let index = FlakyIndex::default();
+        let mut effects = SideEffects::new(uploads.clone());
+
+        let response = apply(&store, &subject, |builder| {
+            builder.set(urls::NAME.into(), Value::String("Indexed".into()))
+        });
+        index.fail_writes.set(true);
+        effects.apply(stage(&response, &index, &store).unwrap(), &index);
+        effects.flush(&index);
+        assert!(index.committed.borrow().is_empty());
+        let repairs = effects.take_repairs();
+        assert!(repairs.reindex.contains(&subject));
+
+        // The repair indexes the Resource as it is in the store
+        index.fail_writes.set(false);
+        repairs.run(&store, &index, &uploads).unwrap();
+        assert_eq!(index.committed.borrow().get(&subject), Some(&true));
+
+        // Writes that were not committed are repaired as well
+        index.fail_commits.set(true);
+        let response = apply(&store, &subject, |builder| {
+            builder.set(urls::NAME.into(), Value::String("Changed".into()))
+        });
+        effects.apply(stage(&response, &index, &store).unwrap(), &index);
+        effects.flush(&index);
+        let repairs = effects.take_repairs();
+        assert!(repairs.reindex.contains(&subject));
+        index.fail_commits.set(false);
+        repairs.run(&store, &index, &uploads).unwrap();
+        assert!(effects.take_repairs().is_empty());
+    }
+
+    #[test]
+    fn files_are_removed_after_the_index_is_committed() {
+        let store = Db::init_temp("side_effects_files").unwrap();
+        let uploads = PathBuf::from(".temp/side_effects_files");
+        let _ = std::fs::remove_dir_all(&uploads);
+        std::fs::create_dir_all(&uploads).unwrap();
+        let subject = format!("{}/file", store.get_server_url());
+        let index = FlakyIndex::default();
+        let mut effects = SideEffects::new(uploads.clone());
+
+        apply(&store, &subject, |builder| {
+            builder.set(urls::INTERNAL_ID.into(), Value::String("blob".into()))
+        });
+        // A folder can't be removed as a file, which makes the removal fail
+        let blob = uploads.join("blob");
+        std::fs::create_dir_all(blob.join("inside")).unwrap();
+
+        let response = apply(&store, &subject, |builder| {
+            builder.destroy(true);
+            builder.purge(true);
+        });
+        let staged = stage(&response, &index, &store).unwrap();
+        index.fail_commits.set(true);
+        effects.apply(staged, &index);
+        assert!(
+            blob.exists(),
+            "Files are only removed after the index commit"
+        );
+        effects.flush(&index);
+        assert!(
+            blob.exists(),
+            "The index commit failed, so the file is kept"
+        );
+        let repairs = effects.take_repairs();
+        assert!(repairs.remove_files.contains("blob"));
+
+        index.fail_commits.set(false);
+        assert!(repairs.run(&store, &index, &uploads).is_err());
+        assert!(blob.exists());
+        assert_eq!(index.committed.borrow().get(&subject), Some(&false));
+
+        std::fs::remove_dir_all(&blob).unwrap();
+        std::fs::write(&blob, "content").unwrap();
+        repairs.run(&store, &index, &uploads).unwrap();
+        assert!(!blob.exists());
+    }
+}
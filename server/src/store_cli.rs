@@ -0,0 +1,360 @@
+//! Subcommands that work on the store files directly, without starting the HTTP server: `export`, `import`, `show`, `rebuild-index`, `check`, `backup` and `verify-backup`.
+//! The store is opened with an exclusive lock (held by sled while the store is open), so these commands refuse to run while a server uses the same store, and the server can't start while they run.
+//! They are meant to be used from deployment scripts: pass `--output json` to get a single JSON object on STDOUT, and check the exit code.
+
+use std::io::Write;
+
+use atomic_lib::{agents::ForAgent, urls, Db, Storelike};
+use serde_json::{json, Value as JsonValue};
+
+use crate::{
+    config::{
+        BackupOpts, Command, Config, ExportFormat, ExportOpts, ImportOpts, OutputFormat,
+        ShowFormat, ShowOpts, VerifyBackupOpts,
+    },
+    content_types::ContentType,
+    errors::{AppErrorType, AtomicServerError, AtomicServerResult},
+    self_check::{self, CheckStatus},
+};
+
+pub const EXIT_OK: i32 = 0;
+/// The command failed, e.g. because a file can't be read or a Resource doesn't exist
+pub const EXIT_ERROR: i32 = 1;
+/// `check` found problems that keep the server from starting, or Resources that are invalid, or `verify-backup` found discrepancies
+pub const EXIT_CHECK_FAILED: i32 = 2;
+/// The store is used by a running server or another command
+pub const EXIT_STORE_LOCKED: i32 = 3;
+
+/// What a command did, printed as `text` or as `json` depending on `--output`.
+struct Outcome {
+    code: i32,
+    text: String,
+    json: JsonValue,
+}
+
+impl Outcome {
+    fn ok(text: String, json: JsonValue) -> Outcome {
+        Outcome {
+            code: EXIT_OK,
+            text,
+            json,
+        }
+    }
+}
+
+/// Runs the store subcommand of the [Config], and prints its result.
+/// Returns the exit code, or `None` if the command is not a store subcommand.
+pub fn run(config: &Config) -> Option<i32> {
+    let (name, outcome) = match config.opts.command.as_ref()? {
+        Command::Export(opts) => ("export", export(config, opts)),
+        Command::Import(opts) => ("import", import(config, opts)),
+        Command::Show(opts) => ("show", show(config, opts)),
+        Command::RebuildIndex => ("rebuild-index", rebuild_index(config)),
+        Command::Check => ("check", check(config)),
+        Command::Backup(opts) => ("backup", backup(config, opts)),
+        Command::VerifyBackup(opts) => ("verify-backup", verify_backup(config, opts)),
+        _ => return None,
+    };
+    let outcome = outcome.unwrap_or_else(|e| Outcome {
+        code: match e.error_type {
+            AppErrorType::Locked => EXIT_STORE_LOCKED,
+            _ => EXIT_ERROR,
+        },
+        json: json!({ "error": e.message }),
+        text: e.message,
+    });
+    print(config, name, outcome.code, outcome.text, outcome.json);
+    Some(outcome.code)
+}
+
+fn print(config: &Config, command: &str, code: i32, text: String, json: JsonValue) {
+    match config.opts.output {
+        OutputFormat::Json => {
+            let mut object = json!({ "command": command, "ok": code == EXIT_OK, "exitCode": code });
+            if let (Some(object), JsonValue::Object(fields)) = (object.as_object_mut(), json) {
+                object.extend(fields);
+            }
+            println!("{}", object);
+        }
+        OutputFormat::Text if code == EXIT_ERROR || code == EXIT_STORE_LOCKED => {
+            use colored::Colorize;
+            eprintln!("{}: {}", "Error".red(), text);
+        }
+        OutputFormat::Text => println!("{}", text),
+    }
+}
+
+/// Opens the store with the options of the server.
+/// A missing store is created and populated like the server does if `create` is true, otherwise it is an error.
+/// Fails with [AppErrorType::Locked] if another process has the store open.
+fn open_store(config: &Config, create: bool) -> AtomicServerResult<Db> {
+    let fresh = !config.store_path.exists();
+    if fresh && !create {
+        return Err(format!(
+            "No store at {:?}. Start the server once to create it, or point `--data-dir` to an existing one.",
+            config.store_path
+        )
+        .into());
+    }
+    let mut store =
+        Db::init(&config.store_path, config.server_url.clone()).map_err(|e| {
+            if e.message.contains("could not acquire lock") {
+                AtomicServerError::new(
+                    format!(
+                        "The store at {:?} is in use, probably by a running atomic-server. Stop it and try again.",
+                        config.store_path
+                    ),
+                    AppErrorType::Locked,
+                )
+            } else {
+                e.into()
+            }
+        })?;
+    crate::appstate::configure_store(config, &mut store);
+    if fresh {
+        atomic_lib::populate::populate_default_store(&store)
+            .map_err(|e| format!("Failed to populate default store. {}", e))?;
+        // Creates the Drive, like a server that starts with a new store
+        crate::appstate::set_default_agent(config, &store)?;
+        atomic_lib::populate::populate_all(&store)?;
+    }
+    Ok(store)
+}
+
+fn export(config: &Config, opts: &ExportOpts) -> AtomicServerResult<Outcome> {
+    let path = match opts.path.clone() {
+        Some(p) => p,
+        None => {
+            let date = chrono::Local::now().to_rfc3339();
+            config
+                .config_dir
+                .join(format!("backups/{}.{}", date, opts.format.extension()))
+        }
+    };
+    let store = open_store(config, false)?;
+    let outstr = match opts.format {
+        ExportFormat::JsonAd => store.export(!opts.only_internal)?,
+        ExportFormat::NQuads => store.export_nquads(!opts.only_internal)?,
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create directory {:?}. {}", dir, e))?;
+    }
+    let mut file = std::fs::File::create(&path)
+        .map_err(|e| format!("Failed to write file to {:?}. {}", path, e))?;
+    write!(file, "{}", outstr)?;
+    Ok(Outcome::ok(
+        format!("Successfully exported data to {}", path.display()),
+        json!({ "path": path, "bytes": outstr.len() }),
+    ))
+}
+
+fn import(config: &Config, opts: &ImportOpts) -> AtomicServerResult<Outcome> {
+    let readstring = std::fs::read_to_string(&opts.file)
+        .map_err(|e| format!("Failed to read {:?}. {}", opts.file, e))?;
+    let store = open_store(config, true)?;
+    crate::appstate::set_default_agent(config, &store)?;
+    let importer_subject = match &opts.parent {
+        Some(parent) => parent.clone(),
+        None => urls::construct_path_import(store.get_server_url()),
+    };
+    let parse_opts = atomic_lib::parse::ParseOpts {
+        importer: Some(importer_subject),
+        for_agent: ForAgent::Sudo,
+        overwrite_outside: true,
+        save: if opts.force {
+            atomic_lib::parse::SaveOpts::Save
+        } else {
+            atomic_lib::parse::SaveOpts::Commit
+        },
+        signer: Some(store.get_default_agent()?),
+        base: opts.base.clone(),
+    };
+    let count = store.import(&readstring, &parse_opts)?;
+    Ok(Outcome::ok(
+        format!(
+            "Successfully imported {} Resources from {:?} to store.",
+            count, opts.file
+        ),
+        json!({ "path": opts.file, "resources": count }),
+    ))
+}
+
+fn show(config: &Config, opts: &ShowOpts) -> AtomicServerResult<Outcome> {
+    let store = open_store(config, false)?;
+    let resource = store.get_resource(&opts.subject)?;
+    let content_type = match opts.format {
+        ShowFormat::JsonAd => ContentType::JsonAd,
+        ShowFormat::Json => ContentType::Json,
+        ShowFormat::JsonLd => ContentType::JsonLd,
+        ShowFormat::Turtle => ContentType::Turtle,
+        ShowFormat::NTriples => ContentType::NTriples,
+        ShowFormat::NQuads => ContentType::NQuads,
+    };
+    let body = content_type.serialize(&resource, &store)?;
+    Ok(Outcome::ok(
+        body.clone(),
+        json!({
+            "subject": opts.subject,
+            "contentType": content_type.to_mime(),
+            "body": body,
+        }),
+    ))
+}
+
+/// Rebuilds both indexes, like the `RebuildIndexes` Job of a running server, see [crate::jobs::rebuild_indexes].
+fn rebuild_index(config: &Config) -> AtomicServerResult<Outcome> {
+    let store = open_store(config, false)?;
+    store.clear_index()?;
+    store.build_index(true)?;
+    // Removes the search index directory before opening it
+    let mut config = config.clone();
+    config.opts.rebuild_indexes = true;
+    let search_state = crate::search::SearchState::new(&config)?;
+    crate::search::add_all_resources(&search_state, &store)?;
+    Ok(Outcome::ok(
+        "Rebuilt the value index and the search index".into(),
+        json!({}),
+    ))
+}
+
+/// Writes a backup like the `backup` Job of a running server, see [crate::backup::create].
+fn backup(config: &Config, opts: &BackupOpts) -> AtomicServerResult<Outcome> {
+    let dir = match opts.path.clone() {
+        Some(p) => p,
+        None => crate::backup::backups_dir(config).join(chrono::Local::now().to_rfc3339()),
+    };
+    let store = open_store(config, false)?;
+    let manifest = crate::backup::create(&store, &config.uploads_path, &dir)?;
+    let text = format!(
+        "Backed up {} Resources and {} files to {}",
+        manifest.resources,
+        manifest.files.len(),
+        dir.display()
+    );
+    if !opts.verify {
+        return Ok(Outcome::ok(
+            text,
+            json!({ "path": dir, "manifest": manifest }),
+        ));
+    }
+    let mut outcome = verification_outcome(crate::backup::verify(
+        Some(&store),
+        &dir,
+        crate::backup::DEFAULT_SAMPLE_SIZE,
+    )?)?;
+    outcome.text = format!("{}\n{}", text, outcome.text);
+    Ok(outcome)
+}
+
+/// Restores a backup in a temporary store and checks it, see [crate::backup::verify].
+/// When a server uses the live store, the backup is checked without comparing it with the live store.
+fn verify_backup(config: &Config, opts: &VerifyBackupOpts) -> AtomicServerResult<Outcome> {
+    let dir = match opts.path.clone() {
+        Some(p) => p,
+        None => crate::backup::latest(&crate::backup::backups_dir(config))?,
+    };
+    let live = match open_store(config, false) {
+        Ok(store) => Some(store),
+        Err(e) if matches!(e.error_type, AppErrorType::Locked) => None,
+        Err(e) => return Err(e),
+    };
+    let mut outcome =
+        verification_outcome(crate::backup::verify(live.as_ref(), &dir, opts.sample)?)?;
+    if live.is_none() {
+        outcome
+            .text
+            .push_str("\nThe live store is in use, so it was not compared with the backup.");
+    }
+    Ok(outcome)
+}
+
+fn verification_outcome(report: crate::backup::BackupVerification) -> AtomicServerResult<Outcome> {
+    let mut text = format!(
+        "Backup {} {}: restored {} of {} Resources and {} of {} Commits, checked {} files, compared {} Resources with the live store ({} changed since the backup, {} removed).",
+        report.backup.display(),
+        if report.passed { "passed" } else { "failed" },
+        report.resources,
+        report.expected_resources,
+        report.commits,
+        report.expected_commits,
+        report.files_checked,
+        report.sampled,
+        report.changed_since_backup,
+        report.missing_in_live,
+    );
+    for discrepancy in &report.discrepancies {
+        text.push_str(&format!("\n- [{}] ", discrepancy.kind));
+        if let Some(subject) = &discrepancy.subject {
+            text.push_str(&format!("{}: ", subject));
+        }
+        text.push_str(&discrepancy.message);
+    }
+    Ok(Outcome {
+        code: if report.passed {
+            EXIT_OK
+        } else {
+            EXIT_CHECK_FAILED
+        },
+        text,
+        json: serde_json::to_value(&report).map_err(|e| e.to_string())?,
+    })
+}
+
+/// Runs the self-check of `--check`, and validates every Resource.
+fn check(config: &Config) -> AtomicServerResult<Outcome> {
+    let mut report = self_check::check_environment(config);
+    let validation = if config.store_path.exists() {
+        let store = open_store(config, false)?;
+        self_check::check_existing_store(config, &store, &mut report);
+        Some(atomic_lib::validate::validate_store(&store, false))
+    } else {
+        None
+    };
+    let failed = !report.fatal().is_empty()
+        || validation
+            .as_ref()
+            .map(|validation| !validation.is_valid())
+            .unwrap_or(false);
+    let warnings = report
+        .checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Warning)
+        .count();
+
+    let mut text = report.summary();
+    let validation_json = match &validation {
+        Some(validation) => {
+            text.push_str(&format!(
+                "\n\nValidated {} Resources with {} Atoms. {}",
+                validation.resource_count, validation.atom_count, validation
+            ));
+            json!({
+                "valid": validation.is_valid(),
+                "resources": validation.resource_count,
+                "atoms": validation.atom_count,
+                "unfetchable": validation.unfetchable.len(),
+                "unfetchableClasses": validation.unfetchable_classes.len(),
+                "unfetchableProperties": validation.unfetchable_props.len(),
+                "invalidValues": validation.invalid_value.len(),
+                "schemaViolations": validation.schema_violations.len(),
+                "datatypeMismatches": validation.datatype_mismatches.len(),
+                "hierarchyCycles": validation.hierarchy_cycles,
+                "replacementWarnings": validation.replacement_warnings.len(),
+            })
+        }
+        None => {
+            text.push_str(&format!("\n\nNo store at {:?}", config.store_path));
+            JsonValue::Null
+        }
+    };
+    Ok(Outcome {
+        code: if failed { EXIT_CHECK_FAILED } else { EXIT_OK },
+        text,
+        json: json!({
+            "checks": report.checks,
+            "warnings": warnings,
+            "validation": validation_json,
+        }),
+    })
+}
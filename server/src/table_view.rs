@@ -0,0 +1,464 @@
+//! Builds a table of the children of a Resource, served as an HTML page at `/table`.
+//! If most children share a Class, the columns are the required and recommended Properties of that Class.
+//! Sorting and paging use the sorted query index, the same way [atomic_lib::collections] do.
+//! The sorting and the selected columns are kept in the query parameters, so a table can be bookmarked.
+
+use std::collections::{HashMap, HashSet};
+
+use atomic_lib::{
+    agents::ForAgent, hierarchy::check_read, schema::Property, storelike::Query, urls, Resource,
+    Storelike, Value,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::errors::AtomicServerResult;
+
+pub const PAGE_SIZE: usize = 50;
+/// The share of the children that must have a Class for its Properties to be used as columns.
+const CLASS_THRESHOLD: f64 = 0.5;
+/// Properties that are not shown as columns when there is no shared Class.
+const HIDDEN_PROPERTIES: [&str; 6] = [
+    urls::IS_A,
+    urls::PARENT,
+    urls::LAST_COMMIT,
+    urls::READ,
+    urls::WRITE,
+    urls::NAME,
+];
+/// How many characters of Strings and Markdown are shown in a cell.
+const MAX_TEXT_LENGTH: usize = 120;
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct TableParams {
+    /// The Resource of which the children are shown
+    pub parent: String,
+    /// The Class whose Properties are used as columns. Defaults to the Class that most children share.
+    pub class: Option<String>,
+    /// Subject or shortname of the Property to sort by
+    pub sort: Option<String>,
+    #[serde(default)]
+    pub desc: bool,
+    /// Comma separated shortnames of the columns to show
+    pub columns: Option<String>,
+    #[serde(default)]
+    pub page: usize,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TableView {
+    pub parent: String,
+    pub parent_title: String,
+    pub class: Option<String>,
+    pub class_shortname: Option<String>,
+    /// The columns that are shown
+    pub columns: Vec<Column>,
+    /// All columns that can be selected, with links that toggle them
+    pub available_columns: Vec<Column>,
+    pub rows: Vec<Row>,
+    pub total_count: usize,
+    pub page: usize,
+    pub prev_page: Option<String>,
+    pub next_page: Option<String>,
+    /// The same children as a JSON-AD Collection
+    pub collection: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Column {
+    pub subject: String,
+    pub shortname: String,
+    pub datatype: String,
+    pub selected: bool,
+    /// `asc` or `desc` if the table is sorted by this column
+    pub sorted: Option<&'static str>,
+    /// Sorts by this column, or reverses the order if it already is
+    pub sort_url: String,
+    /// Shows or hides this column
+    pub toggle_url: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Row {
+    pub subject: String,
+    pub title: String,
+    pub cells: Vec<Cell>,
+}
+
+/// A value formatted for its datatype. The template uses `kind` to pick a format.
+#[derive(Serialize, Debug, Default)]
+pub struct Cell {
+    /// `empty`, `text`, `number`, `timestamp`, `date`, `boolean`, `link` or `count`
+    pub kind: &'static str,
+    pub value: serde_json::Value,
+    pub href: Option<String>,
+}
+
+/// Lists a page of the children of `params.parent` that `for_agent` can read.
+pub fn build_table(
+    store: &impl Storelike,
+    params: &TableParams,
+    for_agent: &ForAgent,
+) -> AtomicServerResult<TableView> {
+    let parent = store.get_resource(&params.parent)?;
+    check_read(store, &parent, for_agent)?;
+
+    let sort_by = match &params.sort {
+        Some(sort) => Some(resolve_property(store, sort)?.subject),
+        None => None,
+    };
+    let mut query = Query::new_prop_val(urls::PARENT, &params.parent);
+    query.sort_by = sort_by.clone();
+    query.sort_desc = params.desc;
+    query.limit = Some(PAGE_SIZE);
+    query.offset = params.page * PAGE_SIZE;
+    query.for_agent = for_agent.clone();
+    let result = store.query(&query)?;
+    let children = result.resources;
+
+    let class = match &params.class {
+        Some(class) => Some(store.get_class(class)?),
+        None => shared_class(&children).and_then(|class| store.get_class(&class).ok()),
+    };
+    let available: Vec<Property> = match &class {
+        Some(class) => class
+            .requires
+            .iter()
+            .chain(class.recommends.iter())
+            .filter_map(|prop| store.get_property(prop).ok())
+            .collect(),
+        None => present_properties(store, &children),
+    };
+    let selected: Vec<String> = match &params.columns {
+        Some(columns) => columns
+            .split(',')
+            .filter_map(|column| {
+                available
+                    .iter()
+                    .find(|p| p.shortname == column || p.subject == column)
+                    .map(|p| p.subject.clone())
+            })
+            .collect(),
+        None => available.iter().map(|p| p.subject.clone()).collect(),
+    };
+
+    let available_columns: Vec<Column> = available
+        .iter()
+        .map(|property| {
+            let is_selected = selected.contains(&property.subject);
+            let sorted = match &sort_by {
+                Some(sort) if sort == &property.subject => {
+                    Some(if params.desc { "desc" } else { "asc" })
+                }
+                _ => None,
+            };
+            let toggled: Vec<&str> = available
+                .iter()
+                .filter(|p| (p.subject == property.subject) != selected.contains(&p.subject))
+                .map(|p| p.shortname.as_str())
+                .collect();
+            Column {
+                subject: property.subject.clone(),
+                shortname: property.shortname.clone(),
+                datatype: property.data_type.to_string(),
+                selected: is_selected,
+                sorted,
+                sort_url: table_url(
+                    store,
+                    &TableParams {
+                        sort: Some(property.shortname.clone()),
+                        desc: sorted == Some("asc"),
+                        page: 0,
+                        ..params.clone()
+                    },
+                ),
+                toggle_url: table_url(
+                    store,
+                    &TableParams {
+                        columns: Some(toggled.join(",")),
+                        ..params.clone()
+                    },
+                ),
+            }
+        })
+        .collect();
+    // Keep the order of the `columns` param
+    let columns: Vec<Column> = selected
+        .iter()
+        .filter_map(|subject| available_columns.iter().find(|c| &c.subject == subject))
+        .cloned()
+        .collect();
+
+    let rows = children
+        .iter()
+        .map(|child| Row {
+            subject: child.get_subject().clone(),
+            title: title_of(child),
+            cells: columns
+                .iter()
+                .map(|column| format_cell(store, child, &column.subject))
+                .collect(),
+        })
+        .collect();
+
+    let page_url = |page: usize| {
+        table_url(
+            store,
+            &TableParams {
+                page,
+                ..params.clone()
+            },
+        )
+    };
+    let mut collection = format!(
+        "{}{}?property={}&value={}",
+        store.get_server_url(),
+        urls::PATH_QUERY,
+        urlencoding::encode(urls::PARENT),
+        urlencoding::encode(&params.parent)
+    );
+    if let Some(sort) = &sort_by {
+        collection.push_str(&format!(
+            "&sort_by={}&sort_desc={}",
+            urlencoding::encode(sort),
+            params.desc
+        ));
+    }
+
+    Ok(TableView {
+        parent_title: title_of(&parent),
+        parent: params.parent.clone(),
+        class_shortname: class.as_ref().map(|c| c.shortname.clone()),
+        class: class.map(|c| c.subject),
+        columns,
+        available_columns,
+        rows,
+        total_count: result.count,
+        page: params.page,
+        prev_page: (params.page > 0).then(|| page_url(params.page - 1)),
+        next_page: ((params.page + 1) * PAGE_SIZE < result.count)
+            .then(|| page_url(params.page + 1)),
+        collection,
+    })
+}
+
+pub(crate) fn resolve_property(
+    store: &impl Storelike,
+    subject_or_shortname: &str,
+) -> AtomicServerResult<Property> {
+    if subject_or_shortname.contains('/') {
+        return Ok(store.get_property(subject_or_shortname)?);
+    }
+    let mut query = Query::new_prop_val(urls::SHORTNAME, subject_or_shortname);
+    query.include_external = true;
+    for subject in store.query(&query)?.subjects {
+        if let Ok(property) = store.get_property(&subject) {
+            return Ok(property);
+        }
+    }
+    Err(format!("Property '{}' not found", subject_or_shortname).into())
+}
+
+/// Returns the Class that most of the Resources have, if any.
+fn shared_class(resources: &[Resource]) -> Option<String> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for resource in resources {
+        if let Ok(classes) = resource.get(urls::IS_A).and_then(|v| v.to_subjects(None)) {
+            for class in classes {
+                *counts.entry(class).or_default() += 1;
+            }
+        }
+    }
+    counts
+        .into_iter()
+        .filter(|(_, count)| *count as f64 > resources.len() as f64 * CLASS_THRESHOLD)
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+        .map(|(class, _)| class)
+}
+
+/// The Properties that are used by the Resources, for children without a shared Class.
+pub(crate) fn present_properties(store: &impl Storelike, resources: &[Resource]) -> Vec<Property> {
+    let mut seen = HashSet::new();
+    let mut properties = Vec::new();
+    for resource in resources {
+        let mut props: Vec<&String> = resource.get_propvals().keys().collect();
+        props.sort();
+        for prop in props {
+            if HIDDEN_PROPERTIES.contains(&prop.as_str()) || !seen.insert(prop.clone()) {
+                continue;
+            }
+            if let Ok(property) = store.get_property(prop) {
+                properties.push(property);
+            }
+        }
+    }
+    properties
+}
+
+pub(crate) fn title_of(resource: &Resource) -> String {
+    [urls::NAME, urls::SHORTNAME, urls::FILENAME]
+        .iter()
+        .find_map(|prop| resource.get(prop).ok())
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| resource.get_subject().clone())
+}
+
+pub(crate) fn format_cell(store: &impl Storelike, resource: &Resource, property: &str) -> Cell {
+    let Ok(value) = resource.get(property) else {
+        return Cell {
+            kind: "empty",
+            ..Default::default()
+        };
+    };
+    match value {
+        Value::Integer(i) => Cell {
+            kind: "number",
+            value: (*i).into(),
+            href: None,
+        },
+        Value::Float(f) => Cell {
+            kind: "number",
+            value: (*f).into(),
+            href: None,
+        },
+        Value::Timestamp(t) => Cell {
+            kind: "timestamp",
+            value: (*t).into(),
+            href: None,
+        },
+        Value::Date(d) => Cell {
+            kind: "date",
+            value: d.clone().into(),
+            href: None,
+        },
+        Value::Boolean(b) => Cell {
+            kind: "boolean",
+            value: (*b).into(),
+            href: None,
+        },
+        Value::AtomicUrl(url) => Cell {
+            kind: "link",
+            value: store
+                .get_resource(url)
+                .map(|linked| title_of(&linked))
+                .unwrap_or_else(|_| url.clone())
+                .into(),
+            href: Some(url.clone()),
+        },
+        Value::ResourceArray(items) => Cell {
+            kind: "count",
+            value: items.len().into(),
+            href: Some(resource.get_subject().clone()),
+        },
+        other => {
+            let text = other.to_string();
+            let mut shortened: String = text.chars().take(MAX_TEXT_LENGTH).collect();
+            if shortened.len() < text.len() {
+                shortened.push('…');
+            }
+            Cell {
+                kind: "text",
+                value: shortened.into(),
+                href: None,
+            }
+        }
+    }
+}
+
+fn table_url(store: &impl Storelike, params: &TableParams) -> String {
+    let mut url = format!(
+        "{}/table?parent={}",
+        store.get_server_url(),
+        urlencoding::encode(&params.parent)
+    );
+    if let Some(class) = &params.class {
+        url.push_str(&format!("&class={}", urlencoding::encode(class)));
+    }
+    if let Some(sort) = &params.sort {
+        url.push_str(&format!("&sort={}", urlencoding::encode(sort)));
+        if params.desc {
+            url.push_str("&desc=true");
+        }
+    }
+    if let Some(columns) = &params.columns {
+        url.push_str(&format!("&columns={}", urlencoding::encode(columns)));
+    }
+    if params.page > 0 {
+        url.push_str(&format!("&page={}", params.page));
+    }
+    url
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atomic_lib::Db;
+
+    #[test]
+    fn builds_table_of_children() {
+        let store = Db::init_temp("builds_table_of_children").unwrap();
+        let mut folder = Resource::new_generate_subject(&store);
+        folder
+            .set_propval_string(urls::NAME.into(), "Tasks", &store)
+            .unwrap();
+        folder
+            .set_propval(
+                urls::PARENT.into(),
+                Value::AtomicUrl(store.get_server_url().into()),
+                &store,
+            )
+            .unwrap();
+        folder.save_locally(&store).unwrap();
+        for (name, description) in [("b", "Second"), ("a", "First"), ("c", "Third")] {
+            let mut child = Resource::new_generate_subject(&store);
+            child.set_class(urls::CLASS);
+            child
+                .set_propval_string(urls::SHORTNAME.into(), name, &store)
+                .unwrap();
+            child
+                .set_propval_string(urls::DESCRIPTION.into(), description, &store)
+                .unwrap();
+            child
+                .set_propval(
+                    urls::PARENT.into(),
+                    Value::AtomicUrl(folder.get_subject().into()),
+                    &store,
+                )
+                .unwrap();
+            child.save_locally(&store).unwrap();
+        }
+
+        let params = TableParams {
+            parent: folder.get_subject().clone(),
+            sort: Some("shortname".into()),
+            columns: Some("description,recommends".into()),
+            ..Default::default()
+        };
+        let table = build_table(&store, &params, &ForAgent::Sudo).unwrap();
+        assert_eq!(table.class.as_deref(), Some(urls::CLASS));
+        assert_eq!(table.total_count, 3);
+        let titles: Vec<&str> = table.rows.iter().map(|r| r.title.as_str()).collect();
+        assert_eq!(titles, ["a", "b", "c"]);
+        let columns: Vec<&str> = table.columns.iter().map(|c| c.shortname.as_str()).collect();
+        assert_eq!(columns, ["description", "recommends"]);
+        assert_eq!(table.rows[1].cells[0].value, "Second");
+        // Missing values are empty cells
+        assert_eq!(table.rows[1].cells[1].kind, "empty");
+
+        let shortname = table
+            .available_columns
+            .iter()
+            .find(|c| c.shortname == "shortname")
+            .unwrap();
+        assert_eq!(shortname.sorted, Some("asc"));
+        assert!(shortname.sort_url.contains("desc=true"));
+        assert!(!shortname.selected);
+        let description = table
+            .available_columns
+            .iter()
+            .find(|c| c.shortname == "description")
+            .unwrap();
+        assert!(description.toggle_url.ends_with("&columns=recommends"));
+    }
+}
@@ -30,18 +30,29 @@ fn build_request_authenticated(path: &str, appstate: &AppState) -> TestRequest {
     prereq.insert_header(("Accept", "application/ad+json"))
 }
 
-#[actix_rt::test]
-async fn server_tests() {
+/// Initializes a fresh store, config and search index in a unique temp directory.
+fn build_test_appstate() -> AppState {
+    build_test_appstate_with(&[])
+}
+
+/// Like [build_test_appstate], with extra CLI options.
+fn build_test_appstate_with(args: &[&str]) -> AppState {
     let unique_string = atomic_lib::utils::random_string(10);
     use clap::Parser;
-    let opts = Opts::parse_from([
-        "atomic-server",
-        "--initialize",
-        "--data-dir",
-        &format!("./.temp/{}/db", unique_string),
-        "--config-dir",
-        &format!("./.temp/{}/config", unique_string),
-    ]);
+    let data_dir = format!("./.temp/{}/db", unique_string);
+    let config_dir = format!("./.temp/{}/config", unique_string);
+    let opts = Opts::parse_from(
+        [
+            "atomic-server",
+            "--initialize",
+            "--data-dir",
+            &data_dir,
+            "--config-dir",
+            &config_dir,
+        ]
+        .iter()
+        .chain(args),
+    );
 
     let mut config = config::build_config(opts)
         .map_err(|e| format!("Initialization failed: {}", e))
@@ -49,7 +60,12 @@ async fn server_tests() {
     // This prevents folder access issues when running concurrent tests
     config.search_index_path = format!("./.temp/{}/search_index", unique_string).into();
 
-    let appstate = crate::appstate::init(config.clone()).expect("failed init appstate");
+    crate::appstate::init(config).expect("failed init appstate")
+}
+
+#[actix_rt::test]
+async fn server_tests() {
+    let appstate = build_test_appstate();
     let data = Data::new(appstate.clone());
     let app = test::init_service(
         App::new()
@@ -140,10 +156,21 @@ async fn server_tests() {
     assert!(resp.status().is_success());
     let body = get_body(resp);
     assert!(
-        body.as_str().starts_with("<htt"),
+        body.as_str().starts_with("@prefix"),
         "response should be turtle"
     );
 
+    // Get N-Triples
+    let req = build_request_authenticated("/properties", &appstate)
+        .insert_header(("Accept", "application/n-triples"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let body = get_body(resp);
+    assert!(
+        body.as_str().starts_with("<htt"),
+        "response should be n-triples"
+    );
+
     // Get Search
     // Does not test the contents of the results - the index isn't built at this point
     let req = build_request_authenticated("/search?q=setup", &appstate);
@@ -157,9 +184,1416 @@ async fn server_tests() {
     );
 }
 
+/// A slow upload streams its bytes without holding up other requests.
+#[actix_rt::test]
+async fn slow_upload_does_not_block_requests() {
+    use actix_web::dev::Payload;
+    use futures::StreamExt;
+    use std::time::{Duration, Instant};
+
+    let appstate = build_test_appstate();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let store = &appstate.store;
+
+    let boundary = "atomicboundary";
+    let chunk_count = 20;
+    let body = futures::stream::iter(0..chunk_count + 2).then(move |i| async move {
+        actix_rt::time::sleep(Duration::from_millis(50)).await;
+        let part = if i == 0 {
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"slow.txt\"\r\nContent-Type: text/plain\r\n\r\n"
+            )
+        } else if i <= chunk_count {
+            "some slowly uploaded text ".repeat(100)
+        } else {
+            format!("\r\n--{boundary}--\r\n")
+        };
+        Ok::<_, actix_web::error::PayloadError>(actix_web::web::Bytes::from(part))
+    });
+
+    let path = format!(
+        "/upload?parent={}",
+        urlencoding::encode(&appstate.config.server_url)
+    );
+    let mut upload_req = build_request_authenticated(&path, &appstate)
+        .method(actix_web::http::Method::POST)
+        .insert_header((
+            "Content-Type",
+            format!("multipart/form-data; boundary={}", boundary),
+        ))
+        .to_request();
+    *upload_req.payload() = Payload::Stream {
+        payload: Box::pin(body),
+    };
+
+    let upload = async {
+        let resp = test::call_service(&app, upload_req).await;
+        (resp, Instant::now())
+    };
+    let get = async {
+        let req = build_request_authenticated("/properties", &appstate);
+        let resp = test::call_service(&app, req.to_request()).await;
+        (resp, Instant::now())
+    };
+    let ((upload_resp, upload_done), (get_resp, get_done)) = futures::join!(upload, get);
+
+    assert!(get_resp.status().is_success());
+    assert!(
+        upload_resp.status().is_success(),
+        "upload failed: {}",
+        get_body(upload_resp)
+    );
+    assert!(
+        get_done < upload_done,
+        "GET should complete while the upload is still streaming"
+    );
+
+    let drive = store.get_resource(&appstate.config.server_url).unwrap();
+    let attachments = drive
+        .get(urls::ATTACHMENTS)
+        .unwrap()
+        .to_subjects(None)
+        .unwrap();
+    assert_eq!(attachments.len(), 1, "file should be attached to the drive");
+}
+
+/// An upload that breaks off removes the files it has written so far.
+#[actix_rt::test]
+async fn aborted_upload_leaves_no_files() {
+    use actix_web::dev::Payload;
+
+    let appstate = build_test_appstate();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+
+    let boundary = "atomicboundary";
+    let parts = vec![
+        Ok(format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"complete.txt\"\r\n\r\ncomplete\r\n"
+        )),
+        Ok(format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"partial.txt\"\r\n\r\n"
+        )),
+        Ok("the connection breaks while sending this file".repeat(100)),
+        Err(actix_web::error::PayloadError::Incomplete(None)),
+    ];
+    let body = futures::stream::iter(
+        parts
+            .into_iter()
+            .map(|part| part.map(actix_web::web::Bytes::from)),
+    );
+
+    let path = format!(
+        "/upload?parent={}",
+        urlencoding::encode(&appstate.config.server_url)
+    );
+    let mut req = build_request_authenticated(&path, &appstate)
+        .method(actix_web::http::Method::POST)
+        .insert_header((
+            "Content-Type",
+            format!("multipart/form-data; boundary={}", boundary),
+        ))
+        .to_request();
+    *req.payload() = Payload::Stream {
+        payload: Box::pin(body),
+    };
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 400);
+
+    let remaining = std::fs::read_dir(&appstate.config.uploads_path)
+        .unwrap()
+        .count();
+    assert_eq!(remaining, 0, "uploaded files should be removed");
+    let drive = appstate
+        .store
+        .get_resource(&appstate.config.server_url)
+        .unwrap();
+    assert!(drive.get(urls::ATTACHMENTS).is_err());
+}
+
+/// Multipart fields that are not files are refused, except for `name`, which names the file before it.
+#[actix_rt::test]
+async fn upload_rejects_fields_that_are_not_files() {
+    let appstate = build_test_appstate();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let path = format!(
+        "/upload?parent={}",
+        urlencoding::encode(&appstate.config.server_url)
+    );
+    let boundary = "atomicboundary";
+    let upload = |parts: &[(&str, Option<&str>, &str)]| {
+        let mut body = String::new();
+        for (name, filename, content) in parts {
+            let filename = filename
+                .map(|f| format!("; filename=\"{}\"", f))
+                .unwrap_or_default();
+            body.push_str(&format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"{filename}\r\n\r\n{content}\r\n"
+            ));
+        }
+        body.push_str(&format!("--{boundary}--\r\n"));
+        build_request_authenticated(&path, &appstate)
+            .method(actix_web::http::Method::POST)
+            .insert_header((
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            ))
+            .set_payload(body)
+            .to_request()
+    };
+
+    let req = upload(&[("data", None, "{\"json\": true}")]);
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 400);
+    let body = get_body(resp);
+    assert!(body.contains("contains no files"), "{}", body);
+    assert!(body.contains("Field 0 ('data')"), "{}", body);
+
+    let req = upload(&[("file", Some("a.txt"), "a"), ("other", None, "b")]);
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 400);
+    assert!(get_body(resp).contains("Field 1 ('other')"));
+
+    let req = upload(&[("file", Some("a.txt"), "a"), ("name", None, "My file")]);
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body = get_body(resp);
+    assert!(body.contains("My file"), "{}", body);
+
+    let req = upload(&[
+        ("file", Some("a.txt"), "a"),
+        (
+            "file",
+            None,
+            r#"{"description": "Meeting notes", "mimetype": "text/markdown"}"#,
+        ),
+    ]);
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body = get_body(resp);
+    assert!(body.contains("Meeting notes"), "{}", body);
+    assert!(body.contains("text/markdown"), "{}", body);
+
+    // Invalid metadata only fails its own file, which gets an Error at its index
+    let req = upload(&[
+        ("first", Some("a.txt"), "a"),
+        ("second", Some("b.txt"), "b"),
+        (
+            "metadata",
+            None,
+            r#"[{"name": "First"}, {"shortname": "not a slug!"}]"#,
+        ),
+    ]);
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body = get_body(resp);
+    let resources: Vec<serde_json::Value> = serde_json::from_str(&body).unwrap();
+    assert_eq!(resources.len(), 2);
+    assert_eq!(resources[0][urls::NAME], "First");
+    assert!(resources[1][urls::DESCRIPTION]
+        .as_str()
+        .unwrap()
+        .contains("File 1 ('b.txt')"));
+
+    let req = upload(&[
+        ("file", Some("a.txt"), "a"),
+        ("metadata", None, r#"[{"color": "red"}]"#),
+    ]);
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 400);
+
+    let req = upload(&[("file", Some("a.txt"), "a"), ("metadata", None, "{}")]);
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 400);
+    assert!(get_body(resp).contains("Field 1 ('metadata')"));
+
+    let req = test::TestRequest::with_uri(&format!("{}&unknown=1", path))
+        .method(actix_web::http::Method::POST)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status().as_u16(), 400);
+}
+
+/// The text of uploaded files is extracted by a Job and made searchable. Files that can't be read don't fail the upload.
+#[actix_rt::test]
+async fn uploaded_text_is_extracted_and_searchable() {
+    use std::time::Duration;
+
+    let appstate = build_test_appstate();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let store = &appstate.store;
+    let boundary = "atomicboundary";
+    let body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"notes.txt\"\r\n\r\nThe   zebrafinch\nsings\r\n\
+         --{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"broken.pdf\"\r\n\r\nnot a pdf\r\n\
+         --{boundary}--\r\n"
+    );
+    let req = build_request_authenticated(
+        &format!(
+            "/upload?parent={}",
+            urlencoding::encode(&appstate.config.server_url)
+        ),
+        &appstate,
+    )
+    .method(actix_web::http::Method::POST)
+    .insert_header((
+        "Content-Type",
+        format!("multipart/form-data; boundary={}", boundary),
+    ))
+    .set_payload(body)
+    .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let files: Vec<serde_json::Value> = serde_json::from_str(&get_body(resp)).unwrap();
+    let text_file = files[0]["@id"].as_str().unwrap().to_string();
+    let pdf_file = files[1]["@id"].as_str().unwrap().to_string();
+
+    let mut extracted = None;
+    for _ in 0..100 {
+        let text = store.get_resource(&text_file).unwrap();
+        let pdf = store.get_resource(&pdf_file).unwrap();
+        if let (Ok(text), Ok(_error)) = (
+            text.get(urls::EXTRACTED_TEXT),
+            pdf.get(urls::EXTRACTION_ERROR),
+        ) {
+            extracted = Some(text.to_string());
+            break;
+        }
+        actix_rt::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(extracted.as_deref(), Some("The zebrafinch sings"));
+
+    // The search index is committed in batches
+    let mut found = false;
+    for _ in 0..100 {
+        let req = build_request_authenticated("/search?q=zebrafinch", &appstate);
+        let resp = test::call_service(&app, req.to_request()).await;
+        if get_body(resp).contains(&text_file) {
+            found = true;
+            break;
+        }
+        actix_rt::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(found, "the file should be found by its contents");
+
+    // Re-extracting unchanged contents is skipped
+    let text = store.get_resource(&text_file).unwrap();
+    let outcome = crate::text_extraction::extract_file(store, &appstate.config, &text).unwrap();
+    assert_eq!(outcome, crate::text_extraction::Extraction::Unchanged);
+}
+
+/// Imports are read while they are uploaded, and can report their progress as Server-Sent Events.
+#[actix_rt::test]
+async fn import_with_progress() {
+    let appstate = build_test_appstate();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let server_url = &appstate.config.server_url;
+    let item = |i: usize, name: serde_json::Value| {
+        let mut object = serde_json::Map::new();
+        object.insert(
+            "@id".into(),
+            format!("{}/imported-{}", server_url, i).into(),
+        );
+        object.insert(urls::NAME.into(), name);
+        serde_json::Value::Object(object)
+    };
+    let body = serde_json::to_string(&[
+        item(0, "First".into()),
+        item(1, serde_json::Value::Null),
+        item(2, "Third".into()),
+    ])
+    .unwrap();
+
+    let path = format!(
+        "/import?parent={}&mode=continue",
+        urlencoding::encode(server_url)
+    );
+    let req = build_request_authenticated(&path, &appstate)
+        .method(actix_web::http::Method::POST)
+        .insert_header(("Accept", "text/event-stream"))
+        .set_payload(body)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let events = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    assert!(events.contains("event: failed"), "{}", events);
+    let summary = events
+        .split("event: summary\ndata: ")
+        .nth(1)
+        .expect("no summary event");
+    let summary: serde_json::Value = serde_json::from_str(summary.trim()).unwrap();
+    assert_eq!(summary["created"], 2);
+    assert_eq!(summary["failed"], 1);
+    assert!(appstate
+        .store
+        .get_resource(&format!("{}/imported-2", server_url))
+        .is_ok());
+}
+
+/// The typed client of `atomic_lib` works against a running server.
+#[actix_rt::test]
+async fn atomic_client() {
+    use atomic_lib::{client::AtomicClient, commit::CommitBuilder, storelike::Query, Value};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let server_url = format!("http://{}", listener.local_addr().unwrap());
+    let appstate = build_test_appstate_with(&["--server-url", &server_url]);
+    let data = Data::new(appstate.clone());
+    let server = actix_web::HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .configure(crate::routes::config_routes)
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_rt::spawn(server);
+
+    let agent = appstate.store.get_default_agent().unwrap();
+    let upload_path = appstate
+        .config
+        .uploads_path
+        .with_file_name("client-upload.txt");
+    // The client blocks, so it runs outside of the server's runtime
+    actix_rt::task::spawn_blocking(move || {
+        let client = AtomicClient::new(&server_url, agent).unwrap();
+        let drive = client.get_resource(&server_url).unwrap();
+        assert_eq!(drive.get_subject(), &server_url);
+
+        let missing = client
+            .get_resource(&format!("{}/does-not-exist", server_url))
+            .unwrap_err();
+        assert!(matches!(
+            missing.error_type,
+            atomic_lib::AtomicErrorType::NotFoundError
+        ));
+
+        let subject = format!("{}/made-by-client", server_url);
+        let mut builder = CommitBuilder::new(subject.clone());
+        builder.set(urls::PARENT.into(), Value::AtomicUrl(server_url.clone()));
+        builder.set(urls::NAME.into(), Value::String("First".into()));
+        let created = client.commit(builder).unwrap();
+        assert!(created.resource_old.is_none());
+        assert_eq!(
+            created
+                .resource_new
+                .unwrap()
+                .get(urls::NAME)
+                .unwrap()
+                .to_string(),
+            "First"
+        );
+
+        let mut subscription = client.subscribe(&subject).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        let mut builder = CommitBuilder::new(subject.clone());
+        builder.set(urls::NAME.into(), Value::String("Second".into()));
+        builder.client_id("optimistic-1".into());
+        let updated = client.commit(builder).unwrap();
+        assert!(updated.resource_old.is_some());
+        assert_eq!(
+            updated
+                .commit_resource
+                .get(urls::CLIENT_ID)
+                .unwrap()
+                .to_string(),
+            "optimistic-1"
+        );
+        // The id passes through the CommitMonitor to the subscribed connection
+        let received = subscription.next().unwrap().unwrap();
+        assert_eq!(received.subject, subject);
+        assert_eq!(received.client_id.as_deref(), Some("optimistic-1"));
+
+        let children = client
+            .query(&Query::new_prop_val(urls::PARENT, &server_url))
+            .unwrap();
+        assert!(children.subjects.contains(&subject));
+
+        std::fs::write(&upload_path, "uploaded by the client").unwrap();
+        let files = client.upload_file(&server_url, &upload_path).unwrap();
+        assert_eq!(files.len(), 1);
+    })
+    .await
+    .unwrap();
+}
+
+#[actix_rt::test]
+async fn openapi_document_is_valid() {
+    let appstate = build_test_appstate();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::with_uri("/openapi.json").insert_header(("Accept", "text/html"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let document: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    assert_valid_openapi(&document);
+
+    let paths = document["paths"].as_object().unwrap();
+    for path in [
+        "/commit", "/upload", "/search", "/query", "/import", "/{path}",
+    ] {
+        assert!(paths.contains_key(path), "missing path {}", path);
+    }
+    // Endpoint parameters are derived from their Properties
+    let query_params = document["paths"]["/query"]["get"]["parameters"]
+        .as_array()
+        .unwrap();
+    let page_size = query_params
+        .iter()
+        .find(|p| p["name"] == "page-size")
+        .expect("page-size param");
+    assert_eq!(page_size["schema"]["type"], "integer");
+    assert!(document["components"]["securitySchemes"]["bearerAuth"].is_object());
+
+    let req = test::TestRequest::with_uri("/api-docs").insert_header(("Accept", "text/html"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    assert!(get_body(resp).contains("/openapi.json"));
+}
+
+#[actix_rt::test]
+async fn schema_overview() {
+    let appstate = build_test_appstate();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::with_uri("/schema").insert_header(("Accept", "application/json"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let overview: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    let classes = overview["classes"].as_array().unwrap();
+    let agent = classes
+        .iter()
+        .find(|c| c["subject"] == urls::AGENT)
+        .expect("Agent class");
+    assert!(agent["instanceCount"].is_u64());
+    let public_key = agent["requires"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|p| p["subject"] == urls::PUBLIC_KEY)
+        .expect("publicKey is required");
+    assert_eq!(public_key["datatype"], urls::STRING);
+    let properties = overview["properties"].as_array().unwrap();
+    let public_key = properties
+        .iter()
+        .find(|p| p["subject"] == urls::PUBLIC_KEY)
+        .unwrap();
+    assert!(public_key["usedByClasses"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!(urls::AGENT)));
+    let agent_usage = overview["usage"]["classes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|c| c["subject"] == urls::AGENT)
+        .expect("Agent class usage");
+    assert!(agent_usage["instanceCount"].as_u64().unwrap() > 0);
+
+    let req = test::TestRequest::with_uri("/schema").insert_header(("Accept", "text/html"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let body = get_body(resp);
+    assert!(body.contains("<h2 id=\"classes\">Classes</h2>"));
+    assert!(body.contains("<h2 id=\"usage\">Usage</h2>"));
+}
+
+/// Checks the parts of the OpenAPI 3.0 schema that the generated document relies on.
+fn assert_valid_openapi(document: &serde_json::Value) {
+    use std::collections::HashSet;
+
+    assert!(document["openapi"].as_str().unwrap().starts_with("3.0."));
+    assert!(document["info"]["title"].is_string());
+    assert!(document["info"]["version"].is_string());
+
+    let mut operation_ids = HashSet::new();
+    for (path, item) in document["paths"].as_object().expect("paths object") {
+        assert!(path.starts_with('/'), "path {} must start with /", path);
+        for (method, operation) in item.as_object().unwrap() {
+            assert!(
+                ["get", "put", "post", "delete", "options", "head", "patch", "trace"]
+                    .contains(&method.as_str()),
+                "unknown method {} in {}",
+                method,
+                path
+            );
+            let responses = operation["responses"].as_object().expect("responses");
+            assert!(
+                !responses.is_empty(),
+                "{} {} has no responses",
+                method,
+                path
+            );
+            if let Some(id) = operation["operationId"].as_str() {
+                assert!(
+                    operation_ids.insert(id.to_string()),
+                    "duplicate operationId {}",
+                    id
+                );
+            }
+            for param in operation["parameters"].as_array().into_iter().flatten() {
+                let location = param["in"].as_str().expect("parameter location");
+                assert!(["query", "header", "path", "cookie"].contains(&location));
+                let name = param["name"].as_str().expect("parameter name");
+                assert!(param["schema"].is_object(), "{} needs a schema", name);
+                if location == "path" {
+                    assert_eq!(param["required"], true);
+                    assert!(path.contains(&format!("{{{}}}", name)));
+                }
+            }
+        }
+    }
+
+    // Every `$ref` should point to something inside the document
+    fn check_refs(root: &serde_json::Value, value: &serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                if let Some(reference) = map.get("$ref") {
+                    let pointer = reference.as_str().unwrap().trim_start_matches('#');
+                    assert!(root.pointer(pointer).is_some(), "unresolved {}", pointer);
+                }
+                map.values().for_each(|v| check_refs(root, v));
+            }
+            serde_json::Value::Array(items) => items.iter().for_each(|v| check_refs(root, v)),
+            _ => {}
+        }
+    }
+    check_refs(document, document);
+
+    let schemes = document["components"]["securitySchemes"].as_object();
+    for requirement in document["security"].as_array().into_iter().flatten() {
+        for name in requirement.as_object().unwrap().keys() {
+            assert!(
+                schemes.unwrap().contains_key(name),
+                "unknown scheme {}",
+                name
+            );
+        }
+    }
+}
+
 /// Gets the body from the response as a String. Why doen't actix provide this?
 fn get_body(resp: ServiceResponse) -> String {
     let boxbody = resp.into_body();
     let bytes = boxbody.try_into_bytes().unwrap();
     String::from_utf8(bytes.as_ref().into()).unwrap()
 }
+
+#[actix_rt::test]
+async fn metrics_require_rights_and_list_slow_operations() {
+    let appstate = build_test_appstate_with(&["--slow-operation-ms", "0"]);
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::with_uri("/metrics");
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_client_error());
+
+    let req = build_request_authenticated("/metrics", &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let metrics: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    assert!(metrics["operations"]["get"]["count"].as_u64().unwrap() > 0);
+    assert_eq!(metrics["slowThresholdMs"], 0.0);
+    assert!(!metrics["slowOperations"].as_array().unwrap().is_empty());
+}
+
+/// Importing from a URL can't be used to reach internal services, and refused requests are counted.
+#[actix_rt::test]
+async fn outbound_requests_to_internal_addresses_are_refused() {
+    let appstate = build_test_appstate();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let path = format!(
+        "/import?parent={}&url={}",
+        urlencoding::encode(&appstate.config.server_url),
+        urlencoding::encode("http://127.0.0.1:9883/admin")
+    );
+    let req = build_request_authenticated(&path, &appstate)
+        .method(actix_web::http::Method::POST)
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), actix_web::http::StatusCode::FORBIDDEN);
+
+    let req = build_request_authenticated("/metrics", &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    let metrics: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    assert_eq!(metrics["outbound"]["blocked"]["import"], 1);
+}
+
+#[actix_rt::test]
+async fn agent_overview_lists_rights_and_commits() {
+    let appstate = build_test_appstate();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let agent = appstate.store.get_default_agent().unwrap().subject;
+
+    let req = test::TestRequest::with_uri("/agent-overview");
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_client_error());
+
+    let req = build_request_authenticated("/agent-overview", &appstate)
+        .insert_header(("Accept", "application/json"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let overview: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    assert_eq!(overview["agent"], agent.as_str());
+    assert_eq!(overview["isSelf"], true);
+    // The root Drive gives the default Agent write rights
+    let rights = overview["rights"]["items"].as_array().unwrap();
+    assert!(rights
+        .iter()
+        .any(|entry| entry["subject"] == appstate.store.get_server_url()));
+    assert!(overview["commits"]["total"].as_u64().is_some());
+
+    let req = build_request_authenticated("/agent-overview", &appstate)
+        .insert_header(("Accept", "text/html"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    // The template escapes the slashes in the subject
+    assert!(get_body(resp).contains(&tera::escape_html(&agent)));
+}
+
+#[actix_rt::test]
+async fn link_preview_honors_rights_and_etags() {
+    use atomic_lib::{Resource, Value};
+
+    let appstate = build_test_appstate();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let store = &appstate.store;
+    let agent = store.get_default_agent().unwrap().subject;
+
+    // Not in the root Drive, so only the default Agent can read it
+    let folder = format!("{}/preview-folder", store.get_server_url());
+    let mut resource = Resource::new(folder.clone());
+    resource.set_class(urls::DRIVE);
+    resource.set_propval_unsafe(urls::NAME.into(), Value::String("Plans".into()));
+    resource.set_propval_unsafe(
+        urls::DESCRIPTION.into(),
+        Value::Markdown("# Plans\n\nThe **next** [release](https://example.com)".into()),
+    );
+    resource.set_propval_unsafe(urls::READ.into(), Value::ResourceArray(vec![agent.into()]));
+    resource.save_locally(store).unwrap();
+    let image = format!("{}/preview-folder/logo", store.get_server_url());
+    let mut file = Resource::new(image.clone());
+    file.set_class(urls::FILE);
+    file.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(folder.clone()));
+    file.set_propval_unsafe(urls::FILENAME.into(), Value::String("logo.png".into()));
+    file.set_propval_unsafe(urls::MIMETYPE.into(), Value::String("image/png".into()));
+    file.set_propval_unsafe(
+        urls::DOWNLOAD_URL.into(),
+        Value::String(format!("{}/download/logo", store.get_server_url())),
+    );
+    file.save_locally(store).unwrap();
+
+    let path = |subject: &str| format!("/preview?subject={}", urlencoding::encode(subject));
+    let req = build_request_authenticated(&path(&folder), &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let etag = resp
+        .headers()
+        .get("ETag")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let preview: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    assert_eq!(preview["name"], "Plans");
+    assert_eq!(preview["description"], "Plans The next release");
+    assert_eq!(preview["class"], "drive");
+    assert_eq!(preview["childCount"], 1);
+
+    let req = build_request_authenticated(&path(&folder), &appstate)
+        .insert_header(("If-None-Match", etag.clone()));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 304);
+
+    let req = build_request_authenticated(&path(&image), &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    let preview: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    assert_eq!(preview["name"], "logo.png");
+    assert_eq!(preview["class"], "file");
+    assert!(preview["thumbnail"]
+        .as_str()
+        .unwrap()
+        .ends_with("/download/logo"));
+
+    // A change invalidates the ETag
+    let mut resource = store.get_resource(&folder).unwrap();
+    resource.set_propval_unsafe(urls::NAME.into(), Value::String("Roadmap".into()));
+    resource.save_locally(store).unwrap();
+    let req = build_request_authenticated(&path(&folder), &appstate)
+        .insert_header(("If-None-Match", etag));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+
+    let req =
+        test::TestRequest::with_uri(&path(&folder)).insert_header(("Accept", "application/json"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+
+    let missing = format!("{}/does-not-exist", store.get_server_url());
+    let req = build_request_authenticated(&path(&missing), &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 404);
+
+    // Previews of other servers are disabled by default
+    let req = build_request_authenticated(&path("https://example.com/"), &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 400);
+}
+
+#[actix_rt::test]
+async fn html_export_is_standalone_and_deterministic() {
+    use atomic_lib::{Resource, Value};
+
+    let appstate = build_test_appstate();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let store = &appstate.store;
+    let server_url = store.get_server_url().to_string();
+    let agent = store.get_default_agent().unwrap().subject;
+
+    let folder = format!("{}/export-folder", server_url);
+    let mut resource = Resource::new(folder.clone());
+    resource.set_class(urls::DRIVE);
+    resource.set_propval_unsafe(urls::NAME.into(), Value::String("Handbook".into()));
+    resource.set_propval_unsafe(
+        urls::DESCRIPTION.into(),
+        Value::Markdown(
+            "Read the [setup](/setup) first. <script>alert(1)</script>\n\n![logo](/download/export-folder/logo)"
+                .into(),
+        ),
+    );
+    resource.set_propval_unsafe(urls::READ.into(), Value::ResourceArray(vec![agent.into()]));
+    resource.save_locally(store).unwrap();
+    for name in ["Onboarding", "Holidays"] {
+        let mut child = Resource::new(format!("{}/{}", folder, name.to_lowercase()));
+        child.set_propval_unsafe(urls::NAME.into(), Value::String(name.into()));
+        child.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(folder.clone()));
+        child.save_locally(store).unwrap();
+    }
+    std::fs::create_dir_all(&appstate.config.uploads_path).unwrap();
+    std::fs::write(
+        appstate.config.uploads_path.join("export-logo.png"),
+        b"not really a png",
+    )
+    .unwrap();
+    let mut file = Resource::new(format!("{}/logo", folder));
+    file.set_class(urls::FILE);
+    file.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(folder.clone()));
+    file.set_propval_unsafe(urls::FILENAME.into(), Value::String("logo.png".into()));
+    file.set_propval_unsafe(urls::MIMETYPE.into(), Value::String("image/png".into()));
+    file.set_propval_unsafe(
+        urls::INTERNAL_ID.into(),
+        Value::String("export-logo.png".into()),
+    );
+    file.set_propval_unsafe(
+        urls::DOWNLOAD_URL.into(),
+        Value::String(format!("{}/download/export-folder/logo", server_url)),
+    );
+    file.save_locally(store).unwrap();
+
+    let path = format!(
+        "/export-html?subject={}&depth=1",
+        urlencoding::encode(&folder)
+    );
+    let req = build_request_authenticated(&path, &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let body = get_body(resp);
+    assert!(body.contains("<title>Handbook</title>"));
+    assert!(body.contains(&format!("href=\"{}/setup\"", server_url)));
+    assert!(body.contains("&lt;script&gt;"), "raw HTML must be escaped");
+    assert!(!body.contains("<script"));
+    // The logo is embedded in the description and in its own section
+    assert!(body.contains("data:image/png;base64,bm90IHJlYWxseSBhIHBuZw=="));
+    // Children are sorted by title, after a table of contents
+    let holidays = body.find("href=\"#section-1\">Holidays").unwrap();
+    let onboarding = body.find("href=\"#section-3\">Onboarding").unwrap();
+    assert!(holidays < onboarding);
+
+    let req = build_request_authenticated(&path, &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(
+        get_body(resp),
+        body,
+        "exports of the same state are identical"
+    );
+
+    let req = test::TestRequest::with_uri(&path);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+}
+
+#[actix_rt::test]
+async fn replication_export_requires_admin() {
+    let appstate = build_test_appstate();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+
+    let req = test::TestRequest::with_uri("/replication/export");
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_client_error());
+    let req = test::TestRequest::with_uri("/replication/stream");
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_client_error());
+
+    let req = build_request_authenticated("/replication/export", &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let since: i64 = resp
+        .headers()
+        .get(crate::replication::SINCE_HEADER)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(since <= atomic_lib::utils::now());
+    assert!(get_body(resp).contains(appstate.store.get_server_url()));
+}
+
+#[actix_rt::test]
+async fn security_headers_per_endpoint_class() {
+    let appstate =
+        build_test_appstate_with(&["--csp", "img-src 'self' https://images.example.com"]);
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .wrap_fn(crate::security_headers::wrap)
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let header = |resp: &ServiceResponse, name: &str| -> Option<String> {
+        resp.headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap().to_string())
+    };
+
+    // HTML pages allow their own inline styles with the nonce
+    let req = test::TestRequest::with_uri("/schema").insert_header(("Accept", "text/html"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let csp = header(&resp, "content-security-policy").unwrap();
+    assert!(csp.contains("frame-ancestors 'none'"), "{}", csp);
+    assert!(csp.contains("img-src 'self' https://images.example.com"));
+    assert_eq!(header(&resp, "x-content-type-options").unwrap(), "nosniff");
+    assert_eq!(header(&resp, "x-frame-options").unwrap(), "DENY");
+    assert!(header(&resp, "referrer-policy").is_some());
+    assert!(header(&resp, "permissions-policy").is_some());
+    let nonce = csp
+        .split("'nonce-")
+        .nth(1)
+        .and_then(|rest| rest.split('\'').next())
+        .unwrap()
+        .to_string();
+    assert!(get_body(resp).contains(&format!("<style nonce=\"{}\">", nonce)));
+
+    // Swagger-UI has its own policy
+    let req = test::TestRequest::with_uri("/api-docs");
+    let resp = test::call_service(&app, req.to_request()).await;
+    let csp = header(&resp, "content-security-policy").unwrap();
+    assert!(csp.contains("https://unpkg.com"), "{}", csp);
+    assert_eq!(header(&resp, "x-content-type-options").unwrap(), "nosniff");
+
+    // JSON does not need a policy
+    let req = test::TestRequest::with_uri("/schema").insert_header(("Accept", "application/json"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(header(&resp, "content-security-policy").is_none());
+    assert_eq!(header(&resp, "x-content-type-options").unwrap(), "nosniff");
+
+    // Uploaded HTML can't run scripts when it is opened
+    let boundary = "atomicboundary";
+    let body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"page.html\"\r\nContent-Type: text/html\r\n\r\n<script>alert(1)</script>\r\n--{boundary}--\r\n"
+    );
+    let path = format!(
+        "/upload?parent={}",
+        urlencoding::encode(&appstate.config.server_url)
+    );
+    let req = build_request_authenticated(&path, &appstate)
+        .method(actix_web::http::Method::POST)
+        .insert_header((
+            "Content-Type",
+            format!("multipart/form-data; boundary={}", boundary),
+        ))
+        .set_payload(body);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let created: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    let download_url = created[0][urls::DOWNLOAD_URL].as_str().unwrap();
+    let download_path = download_url
+        .strip_prefix(&appstate.config.server_url)
+        .unwrap();
+    // Downloads authenticate with the subject of the File, not with the download URL
+    let headers = atomic_lib::client::get_authentication_headers(
+        created[0]["@id"].as_str().unwrap(),
+        &appstate.store.get_default_agent().unwrap(),
+    )
+    .unwrap();
+    let mut req = test::TestRequest::with_uri(download_path);
+    for (k, v) in headers {
+        req = req.insert_header((k, v));
+    }
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let csp = header(&resp, "content-security-policy").unwrap();
+    assert!(csp.starts_with("sandbox"), "{}", csp);
+    assert_eq!(header(&resp, "x-content-type-options").unwrap(), "nosniff");
+}
+
+/// Agents that exceed an `--activity-limits` are suspended until an admin lifts it.
+#[actix_rt::test]
+async fn exceeding_activity_limits_suspends_agents() {
+    use atomic_lib::{agents::Agent, commit::CommitBuilder, Value};
+
+    let appstate = build_test_appstate_with(&[
+        "--activity-limits",
+        "reads-per-hour=2",
+        "--activity-suspend",
+    ]);
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let store = &appstate.store;
+    let mallory = Agent::new(Some("mallory"), store).unwrap();
+    store.add_resource(&mallory.to_resource().unwrap()).unwrap();
+    let path = mallory
+        .subject
+        .strip_prefix(store.get_server_url())
+        .unwrap()
+        .to_string();
+    let signed = || {
+        let url = format!("{}{}", store.get_server_url(), path);
+        let headers = atomic_lib::client::get_authentication_headers(&url, &mallory).unwrap();
+        let mut req = test::TestRequest::with_uri(&path);
+        for (k, v) in headers {
+            req = req.insert_header((k, v));
+        }
+        req.insert_header(("Accept", "application/ad+json"))
+    };
+
+    for _ in 0..3 {
+        let resp = test::call_service(&app, signed().to_request()).await;
+        assert!(resp.status().is_success());
+    }
+    appstate
+        .agent_activity
+        .flush(store, &appstate.config.activity_limits, true);
+    assert!(matches!(
+        store
+            .get_resource(&mallory.subject)
+            .unwrap()
+            .get(urls::SUSPENDED),
+        Ok(Value::Boolean(true))
+    ));
+    let resp = test::call_service(&app, signed().to_request()).await;
+    assert_eq!(resp.status().as_u16(), 401);
+    assert!(get_body(resp).contains("suspended"));
+
+    // Admins can see why, and get a Notification
+    let req = build_request_authenticated(
+        &format!(
+            "/agent-activity?agent={}",
+            urlencoding::encode(&mallory.subject)
+        ),
+        &appstate,
+    );
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let json: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    let report = &json["agents"][0];
+    assert_eq!(report["agent"], mallory.subject.as_str());
+    assert_eq!(report["days"][0]["reads"], 3);
+    assert_eq!(report["exceeded"][0], "reads-per-hour=2");
+    assert_eq!(report["suspended"], true);
+    let admin = store.get_default_agent().unwrap();
+    let inbox = atomic_lib::plugins::notifications::unread(store, &admin.subject, 0, 10).unwrap();
+    assert!(inbox
+        .notifications
+        .iter()
+        .any(|n| n.about == mallory.subject));
+
+    // Removing `suspended` with a Commit lifts the suspension
+    let resource = store.get_resource(&mallory.subject).unwrap();
+    let mut builder = CommitBuilder::new(mallory.subject.clone());
+    builder.remove(urls::SUSPENDED.into());
+    let commit = builder.sign(&admin, store, &resource).unwrap();
+    // Without an `@id`, parsing the body doesn't save the Commit before it is applied
+    let body = serde_json::to_string(
+        &atomic_lib::serialize::propvals_to_json_ad_map(
+            commit.into_resource(store).unwrap().get_propvals(),
+            None,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let req = test::TestRequest::post().uri("/commit").set_payload(body);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success(), "{}", get_body(resp));
+    let resp = test::call_service(&app, signed().to_request()).await;
+    assert!(resp.status().is_success());
+}
+
+/// Cursors don't skip or repeat members when Commits change the Collection between pages.
+#[actix_rt::test]
+async fn collection_cursors_survive_commits() {
+    use atomic_lib::{Resource, Value};
+
+    let appstate = build_test_appstate();
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let store = &appstate.store;
+    let folder = format!("{}/cursor-folder", store.get_server_url());
+    let mut resource = Resource::new(folder.clone());
+    resource.set_class(urls::DRIVE);
+    resource.set_propval_unsafe(urls::NAME.into(), Value::String("Cursors".into()));
+    resource.set_propval_unsafe(
+        urls::PARENT.into(),
+        Value::AtomicUrl(store.get_server_url().into()),
+    );
+    resource.save_locally(store).unwrap();
+    let create = |name: &str| {
+        let mut resource = Resource::new(format!("{}/{}", folder, name));
+        resource.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(folder.clone()));
+        resource.set_propval_unsafe(urls::NAME.into(), Value::String(name.into()));
+        resource.save_locally(store).unwrap();
+        resource.get_subject().clone()
+    };
+    for name in ["a", "b", "c", "d", "e", "f", "g"] {
+        create(name);
+    }
+    let member = |name: &str| format!("{}/{}", folder, name);
+
+    let page = |cursor: &str| {
+        let path = format!(
+            "/query?property={}&value={}&sort-by={}&page-size=3&cursor={}",
+            urlencoding::encode(urls::PARENT),
+            urlencoding::encode(&folder),
+            urlencoding::encode(urls::NAME),
+            cursor
+        );
+        build_request_authenticated(&path, &appstate).to_request()
+    };
+    let read = |body: String| {
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let members: Vec<String> = json[urls::COLLECTION_MEMBERS]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m.as_str().unwrap().to_string())
+            .collect();
+        let next = json[urls::COLLECTION_NEXT_CURSOR]
+            .as_str()
+            .map(String::from);
+        (members, next)
+    };
+
+    let resp = test::call_service(&app, page("")).await;
+    assert!(resp.status().is_success());
+    let (first, next) = read(get_body(resp));
+    assert_eq!(first, vec![member("a"), member("b"), member("c")]);
+
+    // The last member of the page is deleted, and members are added before and after it
+    store
+        .get_resource(&member("c"))
+        .unwrap()
+        .destroy(store)
+        .unwrap();
+    create("bb");
+    create("cc");
+    let resp = test::call_service(&app, page(&next.unwrap())).await;
+    assert!(resp.status().is_success());
+    let (second, next) = read(get_body(resp));
+    assert_eq!(second, vec![member("cc"), member("d"), member("e")]);
+    let next = next.unwrap();
+
+    // Tampered cursors are refused
+    let tampered = format!("x{}", next);
+    let resp = test::call_service(&app, page(&tampered)).await;
+    assert!(!resp.status().is_success());
+
+    // Renaming the last member of the page expires the cursor
+    let mut renamed = store.get_resource(&member("e")).unwrap();
+    renamed
+        .set_propval(urls::NAME.into(), Value::String("z".into()), store)
+        .unwrap();
+    renamed.save_locally(store).unwrap();
+    let resp = test::call_service(&app, page(&next)).await;
+    assert_eq!(resp.status().as_u16(), 410);
+
+    renamed
+        .set_propval(urls::NAME.into(), Value::String("e".into()), store)
+        .unwrap();
+    renamed.save_locally(store).unwrap();
+    let resp = test::call_service(&app, page(&next)).await;
+    let (last, next) = read(get_body(resp));
+    assert_eq!(last, vec![member("f"), member("g")]);
+    assert!(next.is_none());
+}
+
+/// Many slow connections don't keep the server from answering other requests, and are closed by the server.
+#[actix_rt::test]
+async fn slow_connections_do_not_block_other_requests() {
+    use actix_web::dev::{Payload, Service};
+    use futures::StreamExt;
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+        time::Duration,
+    };
+
+    let appstate = build_test_appstate_with(&[
+        "--min-transfer-rate",
+        "1000",
+        "--transfer-grace-seconds",
+        "1",
+    ]);
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let data = Data::new(appstate.clone());
+    let connections = appstate.connections.clone();
+    let server = actix_web::HttpServer::new(move || {
+        App::new()
+            .app_data(data.clone())
+            .configure(crate::routes::config_routes)
+    })
+    .workers(1)
+    .client_request_timeout(Duration::from_millis(500))
+    .client_disconnect_timeout(Duration::from_millis(100))
+    .on_connect(move |_connection, extensions| connections.on_connect(extensions))
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_rt::spawn(server);
+
+    let health = move || -> serde_json::Value {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+            .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        let body = response.split("\r\n\r\n").nth(1).unwrap();
+        serde_json::from_str(body).unwrap()
+    };
+
+    // The clients block, so they run outside of the server's runtime
+    actix_rt::task::spawn_blocking(move || {
+        // Clients that never finish their headers
+        let slow: Vec<TcpStream> = (0..100)
+            .map(|_| {
+                let mut stream = TcpStream::connect(addr).unwrap();
+                stream
+                    .write_all(b"GET /health HTTP/1.1\r\nHost: localhost\r\n")
+                    .unwrap();
+                stream
+            })
+            .collect();
+        let counts = health();
+        assert!(counts["connections"]["open"].as_u64().unwrap() > 1);
+        assert!(counts["connections"]["total"].as_u64().unwrap() >= 101);
+
+        // The request timeout closes them
+        for mut stream in slow {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut rest = Vec::new();
+            stream
+                .read_to_end(&mut rest)
+                .expect("slow connection should be closed by the server");
+        }
+        std::thread::sleep(Duration::from_millis(300));
+        let counts = health();
+        assert!(counts["connections"]["open"].as_u64().unwrap() <= 2);
+    })
+    .await
+    .unwrap();
+
+    // An upload that stops sending is dropped after the grace period
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+    let boundary = "atomicboundary";
+    let head = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"stalled.txt\"\r\nContent-Type: text/plain\r\n\r\nsome text"
+    );
+    let body = futures::stream::once(async move {
+        Ok::<_, actix_web::error::PayloadError>(actix_web::web::Bytes::from(head))
+    })
+    .chain(futures::stream::pending());
+    let path = format!(
+        "/upload?parent={}",
+        urlencoding::encode(&appstate.config.server_url)
+    );
+    let mut upload_req = build_request_authenticated(&path, &appstate)
+        .method(actix_web::http::Method::POST)
+        .insert_header((
+            "Content-Type",
+            format!("multipart/form-data; boundary={}", boundary),
+        ))
+        .to_request();
+    *upload_req.payload() = Payload::Stream {
+        payload: Box::pin(body),
+    };
+    let resp = actix_rt::time::timeout(Duration::from_secs(10), app.call(upload_req))
+        .await
+        .expect("stalled upload should be dropped")
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 400);
+    assert!(get_body(resp).contains("slower than 1000 bytes per second"));
+    let drive = appstate
+        .store
+        .get_resource(&appstate.config.server_url)
+        .unwrap();
+    let attachments = drive
+        .get(urls::ATTACHMENTS)
+        .map(|v| v.to_subjects(None).unwrap())
+        .unwrap_or_default();
+    assert!(attachments.is_empty());
+}
+
+#[actix_rt::test]
+async fn retention_plan_requires_admin() {
+    use atomic_lib::{Resource, Value};
+    let appstate = build_test_appstate();
+    let store = &appstate.store;
+    let app = test::init_service(
+        App::new()
+            .app_data(Data::new(appstate.clone()))
+            .configure(crate::routes::config_routes),
+    )
+    .await;
+
+    let mut logs = Resource::new_generate_subject(store);
+    logs.set_propval(
+        urls::PARENT.into(),
+        Value::AtomicUrl(store.get_server_url().into()),
+        store,
+    )
+    .unwrap();
+    logs.save_locally(store).unwrap();
+    let mut policy = Resource::new_generate_subject(store);
+    policy.set_class(urls::RETENTION_POLICY);
+    policy
+        .set_propval(
+            urls::PARENT.into(),
+            Value::AtomicUrl(logs.get_subject().into()),
+            store,
+        )
+        .unwrap();
+    policy
+        .set_propval(
+            urls::DELETE_INACTIVE_AFTER.into(),
+            Value::Integer(30),
+            store,
+        )
+        .unwrap();
+    policy.save_locally(store).unwrap();
+    let mut log = Resource::new_generate_subject(store);
+    log.set_propval(
+        urls::PARENT.into(),
+        Value::AtomicUrl(logs.get_subject().into()),
+        store,
+    )
+    .unwrap();
+    log.save_locally(store).unwrap();
+
+    let req =
+        test::TestRequest::with_uri("/retention").insert_header(("Accept", "application/ad+json"));
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_client_error());
+
+    let req = build_request_authenticated("/retention", &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let body: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    assert_eq!(body["policies"][0], policy.get_subject().as_str());
+    assert!(body["delete"].as_array().unwrap().is_empty());
+
+    let at = atomic_lib::utils::now() + 31 * 24 * 60 * 60 * 1000;
+    let path = format!("/retention?at={}", at);
+    let req = build_request_authenticated(&path, &appstate);
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert!(resp.status().is_success());
+    let body: serde_json::Value = serde_json::from_str(&get_body(resp)).unwrap();
+    assert_eq!(body["delete"][0], log.get_subject().as_str());
+    // Nothing was removed
+    assert!(store.get_resource(log.get_subject()).is_ok());
+}
@@ -0,0 +1,210 @@
+//! Extracts the text of uploaded files, so search finds them by their contents.
+//! Text is read from PDFs (using a pure-Rust parser), from text files, and from images if an OCR command is configured (see `--ocr-command`).
+//! An `extract-text` Job (see [crate::jobs]) is queued after every upload that contains such files.
+//! The text is stored in [urls::EXTRACTED_TEXT] on the File, which [crate::search::build_document] adds to the indexed description.
+//! Failures are stored in [urls::EXTRACTION_ERROR], and never fail the upload.
+
+use std::{
+    io::Read,
+    path::Path,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use atomic_lib::{commit::CommitBuilder, urls, Db, Resource, Storelike, Value};
+
+use crate::{config::Config, errors::AtomicServerResult};
+
+/// Larger files are not read, since the whole file is kept in memory while extracting.
+pub const MAX_EXTRACTION_FILE_SIZE: u64 = 100 * 1024 * 1024;
+/// OCR commands that take longer are stopped.
+const OCR_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// What [extract_file] did with a File.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Extraction {
+    /// The text was extracted and stored
+    Extracted,
+    /// The contents have not changed since the last extraction
+    Unchanged,
+    /// The error was stored on the File
+    Failed(String),
+    /// The mimetype is not supported, or needs an OCR command that isn't configured
+    Unsupported,
+}
+
+/// Whether text can be extracted from files with this mimetype.
+pub fn is_extractable(mimetype: &str, config: &Config) -> bool {
+    mimetype == "application/pdf"
+        || mimetype.starts_with("text/")
+        || (mimetype.starts_with("image/") && config.opts.ocr_command.is_some())
+}
+
+/// Extracts the text of the File, and stores it (or the error) using a Commit, so the search index and subscribers are updated.
+/// Skipped if the checksum of the contents matches the last successful extraction.
+pub fn extract_file(
+    store: &Db,
+    config: &Config,
+    file: &Resource,
+) -> AtomicServerResult<Extraction> {
+    let mimetype = file
+        .get(urls::MIMETYPE)
+        .map(|m| m.to_string())
+        .unwrap_or_default();
+    if !is_extractable(&mimetype, config) {
+        return Ok(Extraction::Unsupported);
+    }
+    let file_id = file.get(urls::INTERNAL_ID)?.to_string();
+    if !crate::handlers::download::is_safe_file_id(&file_id) {
+        return Err(format!("Invalid internal ID of file: {}", file_id).into());
+    }
+    let path = config.uploads_path.join(&file_id);
+
+    let mut builder = CommitBuilder::new(file.get_subject().into());
+    let outcome = match read_contents(&path) {
+        Ok(bytes) => {
+            let checksum = atomic_lib::patch::checksum_bytes(&bytes);
+            let unchanged = file
+                .get(urls::EXTRACTED_CHECKSUM)
+                .map(|c| c.to_string() == checksum)
+                .unwrap_or(false)
+                && file.get(urls::EXTRACTION_ERROR).is_err();
+            if unchanged {
+                return Ok(Extraction::Unchanged);
+            }
+            match extract(&path, &bytes, &mimetype, config) {
+                Ok(text) => {
+                    let text =
+                        truncate(&normalize_whitespace(&text), config.opts.max_extracted_text);
+                    builder.set(urls::EXTRACTED_TEXT.into(), Value::String(text));
+                    builder.set(urls::EXTRACTED_CHECKSUM.into(), Value::String(checksum));
+                    builder.remove(urls::EXTRACTION_ERROR.into());
+                    Extraction::Extracted
+                }
+                Err(message) => Extraction::Failed(message),
+            }
+        }
+        Err(message) => Extraction::Failed(message),
+    };
+    if let Extraction::Failed(message) = &outcome {
+        tracing::warn!(
+            "Could not extract text from {}: {}",
+            file.get_subject(),
+            message
+        );
+        builder.set(
+            urls::EXTRACTION_ERROR.into(),
+            Value::String(message.clone()),
+        );
+        builder.remove(urls::EXTRACTED_CHECKSUM.into());
+    }
+    let agent = store.get_default_agent()?;
+    builder
+        .sign(&agent, store, file)?
+        .apply_opts(store, &crate::jobs::server_commit_opts())?;
+    Ok(outcome)
+}
+
+fn read_contents(path: &Path) -> Result<Vec<u8>, String> {
+    let size = std::fs::metadata(path)
+        .map_err(|e| format!("The uploaded file can't be read: {}", e))?
+        .len();
+    if size > MAX_EXTRACTION_FILE_SIZE {
+        return Err(format!(
+            "The file is larger than {} bytes",
+            MAX_EXTRACTION_FILE_SIZE
+        ));
+    }
+    std::fs::read(path).map_err(|e| format!("The uploaded file can't be read: {}", e))
+}
+
+fn extract(path: &Path, bytes: &[u8], mimetype: &str, config: &Config) -> Result<String, String> {
+    if mimetype == "application/pdf" {
+        // The parser panics on some malformed PDFs
+        std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(bytes))
+            .map_err(|_| "The PDF could not be parsed".to_string())?
+            .map_err(|e| format!("The PDF could not be parsed: {}", e))
+    } else if mimetype.starts_with("text/") {
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    } else if let Some(command) = &config.opts.ocr_command {
+        run_ocr(command, path)
+    } else {
+        Err(format!("No text can be extracted from {}", mimetype))
+    }
+}
+
+/// Runs the OCR command, with `{file}` replaced by the path, and returns what it printed.
+fn run_ocr(command: &str, path: &Path) -> Result<String, String> {
+    let path = path.to_string_lossy();
+    let mut args = command
+        .split_whitespace()
+        .map(|arg| arg.replace("{file}", &path));
+    let program = args.next().ok_or("The OCR command is empty")?;
+    let mut child = Command::new(&program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Could not run the OCR command `{}`: {}", program, e))?;
+    // Read in another thread, so a full pipe doesn't block the command
+    let mut stdout = child.stdout.take().ok_or("No output of the OCR command")?;
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).map(|_| output)
+    });
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() > OCR_TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "The OCR command took longer than {} seconds",
+                    OCR_TIMEOUT.as_secs()
+                ));
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(50)),
+            Err(e) => return Err(format!("The OCR command failed: {}", e)),
+        }
+    };
+    let output = reader
+        .join()
+        .map_err(|_| "Could not read the output of the OCR command")?
+        .map_err(|e| format!("Could not read the output of the OCR command: {}", e))?;
+    if !status.success() {
+        return Err(format!("The OCR command failed with {}", status));
+    }
+    Ok(String::from_utf8_lossy(&output).into_owned())
+}
+
+/// PDFs contain a lot of layout whitespace, which is useless for search.
+fn normalize_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// Shortens the text to at most `max` bytes, without splitting a character.
+fn truncate(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text[..end].to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn text_is_normalized_and_truncated() {
+        assert_eq!(normalize_whitespace("  a\n\n b\tc "), "a b c");
+        assert_eq!(truncate("héllo", 2), "h");
+        assert_eq!(truncate("héllo", 3), "hé");
+        assert_eq!(truncate("hello", 10), "hello");
+    }
+}
@@ -0,0 +1,164 @@
+//! Progress of uploads to `/upload`, so clients can show how many bytes the server has received.
+//! The client picks a random token, passes it as `/upload?progress={token}`, and polls `/upload-progress?token={token}` while the upload runs.
+//! The token is picked by the client, because the response of an upload (including its headers) is only sent after the whole body has been read.
+//! Progress is only kept in memory. It is removed when the upload finishes or fails, or after [PROGRESS_TIMEOUT] without new bytes.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::errors::{AppErrorType, AtomicServerError, AtomicServerResult};
+
+/// Uploads that receive no bytes for this long are forgotten, even if they never finish.
+pub const PROGRESS_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const MIN_TOKEN_LENGTH: usize = 16;
+const MAX_TOKEN_LENGTH: usize = 128;
+
+/// The progress of one upload, as returned by `/upload-progress`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadProgress {
+    /// Bytes of files that have been written so far
+    pub received: u64,
+    /// The `Content-Length` of the upload, which includes the multipart boundaries and text fields
+    pub total: Option<u64>,
+    /// Files that have been received completely
+    pub files: usize,
+    #[serde(skip)]
+    agent: String,
+    #[serde(skip)]
+    updated: Instant,
+}
+
+/// The uploads that are running, by token.
+#[derive(Clone, Default)]
+pub struct UploadProgressRegistry {
+    uploads: Arc<Mutex<HashMap<String, UploadProgress>>>,
+}
+
+impl UploadProgressRegistry {
+    /// Starts tracking an upload of the Agent. The progress is removed when the returned guard is dropped.
+    /// Refuses tokens that are too short, contain other characters than `A-Z a-z 0-9 - _`, or are already in use.
+    pub fn start(
+        &self,
+        token: &str,
+        agent: &str,
+        total: Option<u64>,
+    ) -> AtomicServerResult<ProgressGuard> {
+        let valid = (MIN_TOKEN_LENGTH..=MAX_TOKEN_LENGTH).contains(&token.len())
+            && token
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(AtomicServerError::new(
+                format!(
+                    "The progress token must be {} to {} characters of A-Z, a-z, 0-9, - and _",
+                    MIN_TOKEN_LENGTH, MAX_TOKEN_LENGTH
+                ),
+                AppErrorType::BadRequest,
+            ));
+        }
+        let mut uploads = self.uploads.lock().unwrap();
+        uploads.retain(|_token, progress| progress.updated.elapsed() < PROGRESS_TIMEOUT);
+        if uploads.contains_key(token) {
+            return Err(AtomicServerError::new(
+                "This progress token is already used by another upload".into(),
+                AppErrorType::BadRequest,
+            ));
+        }
+        uploads.insert(
+            token.into(),
+            UploadProgress {
+                received: 0,
+                total,
+                files: 0,
+                agent: agent.into(),
+                updated: Instant::now(),
+            },
+        );
+        Ok(ProgressGuard {
+            registry: self.clone(),
+            token: token.into(),
+        })
+    }
+
+    /// Returns the progress of a running upload, if it was started by the same Agent.
+    pub fn get(&self, token: &str, agent: &str) -> AtomicServerResult<UploadProgress> {
+        self.uploads
+            .lock()
+            .unwrap()
+            .get(token)
+            .filter(|progress| progress.agent == agent)
+            .cloned()
+            .ok_or_else(|| {
+                AtomicServerError::new(
+                    "No running upload with this progress token. It may have finished already."
+                        .into(),
+                    AppErrorType::NotFound,
+                )
+            })
+    }
+
+    fn update(&self, token: &str, f: impl FnOnce(&mut UploadProgress)) {
+        if let Some(progress) = self.uploads.lock().unwrap().get_mut(token) {
+            f(progress);
+            progress.updated = Instant::now();
+        }
+    }
+}
+
+/// Updates the progress of one upload, and removes it when dropped, so failed uploads are cleaned up as well.
+pub struct ProgressGuard {
+    registry: UploadProgressRegistry,
+    token: String,
+}
+
+impl ProgressGuard {
+    pub fn add_bytes(&self, bytes: u64) {
+        self.registry
+            .update(&self.token, |progress| progress.received += bytes);
+    }
+
+    pub fn file_done(&self) {
+        self.registry
+            .update(&self.token, |progress| progress.files += 1);
+    }
+}
+
+impl Drop for ProgressGuard {
+    fn drop(&mut self) {
+        self.registry.uploads.lock().unwrap().remove(&self.token);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn progress_is_tracked_per_agent_and_removed() {
+        let registry = UploadProgressRegistry::default();
+        let token = "0123456789abcdef";
+        let alice = "https://example.com/agents/alice";
+        assert!(registry.start("short", alice, None).is_err());
+        assert!(registry.start("0123456789abcdef/../", alice, None).is_err());
+
+        let guard = registry.start(token, alice, Some(100)).unwrap();
+        assert!(registry.start(token, alice, None).is_err());
+        guard.add_bytes(40);
+        guard.file_done();
+        let progress = registry.get(token, alice).unwrap();
+        assert_eq!((progress.received, progress.files), (40, 1));
+        assert_eq!(progress.total, Some(100));
+        registry
+            .get(token, "https://example.com/agents/bob")
+            .unwrap_err();
+
+        drop(guard);
+        registry.get(token, alice).unwrap_err();
+    }
+}
@@ -0,0 +1,393 @@
+//! Webhooks POST the Commits of their target Resource to a URL, see [urls::WEBHOOK].
+//! Every applied Commit gets a sequence number in the commit log of the store, see [Db::commits_after].
+//! A Webhook stores the sequence number of the last Commit it received as its cursor, and continues from there after a restart.
+//! This means that Commits that were applied just before the server stopped are delivered when it starts again,
+//! and that a Commit can be delivered more than once. Receivers can skip duplicates using the `sequence` of a delivery.
+//!
+//! Deliveries run on their own thread, in the order of the log, and only include Commits that the author of the Webhook can read.
+//! A Webhook whose URL fails is retried later with a growing delay, without holding up the other Webhooks.
+//! The [crate::commit_monitor::CommitMonitor] wakes the thread for every Commit.
+//! When the server stops, [Webhooks::shutdown] waits a limited time for the remaining deliveries.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use atomic_lib::{
+    agents::ForAgent, outbound::OutboundFeature, storelike::Query, urls, Db, Resource, Storelike,
+    Value,
+};
+
+use crate::{durable_subscriptions::ancestors, errors::AtomicServerResult};
+
+/// How many entries of the commit log are read at once.
+const BATCH: usize = 100;
+/// How often the Webhooks are checked when no Commits come in, e.g. to retry failed ones.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// The delay after the first failure. It doubles with every next failure.
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+const MAX_RETRY: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Default)]
+struct State {
+    /// A Commit was applied since the worker last looked
+    pending: bool,
+    stopping: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+/// Delivers Commits to Webhooks on a background thread. Clones share the same thread.
+/// Does nothing until it is started, e.g. on read-only servers.
+#[derive(Clone, Default)]
+pub struct Webhooks {
+    shared: Arc<Shared>,
+    worker: Arc<Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl Webhooks {
+    /// Starts delivering, beginning with the Commits that were not delivered before the last shutdown.
+    pub fn start(&self, store: Db) {
+        let shared = self.shared.clone();
+        let handle = std::thread::spawn(move || run(store, shared));
+        *self.worker.lock().unwrap() = Some(handle);
+    }
+
+    /// Tells the worker that a Commit was applied.
+    pub fn notify(&self) {
+        self.shared.state.lock().unwrap().pending = true;
+        self.shared.wake.notify_one();
+    }
+
+    /// Delivers the remaining Commits, and stops the worker.
+    /// Returns false if it did not finish within `grace`. Its cursors are stored, so what remains is delivered after the next start.
+    pub fn shutdown(&self, grace: Duration) -> bool {
+        let Some(worker) = self.worker.lock().unwrap().take() else {
+            return true;
+        };
+        self.shared.state.lock().unwrap().stopping = true;
+        self.shared.wake.notify_one();
+        let deadline = Instant::now() + grace;
+        while !worker.is_finished() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        worker.join().is_ok()
+    }
+}
+
+/// When a failing Webhook is tried again.
+struct Retry {
+    failures: u32,
+    at: Instant,
+}
+
+fn run(store: Db, shared: Arc<Shared>) {
+    let mut retries: HashMap<String, Retry> = HashMap::new();
+    loop {
+        let stopping = {
+            let mut state = shared.state.lock().unwrap();
+            state.pending = false;
+            state.stopping
+        };
+        if let Err(e) = deliver_all(&store, &mut retries) {
+            tracing::error!("Could not deliver Commits to Webhooks: {}", e);
+        }
+        if stopping {
+            return;
+        }
+        let state = shared.state.lock().unwrap();
+        if !state.pending && !state.stopping {
+            let _ = shared.wake.wait_timeout(state, POLL_INTERVAL).unwrap();
+        }
+    }
+}
+
+fn deliver_all(store: &Db, retries: &mut HashMap<String, Retry>) -> AtomicServerResult<()> {
+    for resource in store.query(&Query::new_class(urls::WEBHOOK))?.resources {
+        let subject = resource.get_subject().clone();
+        if retries
+            .get(&subject)
+            .map(|retry| retry.at > Instant::now())
+            .unwrap_or(false)
+        {
+            continue;
+        }
+        let webhook = match Webhook::from_resource(store, &resource) {
+            Ok(webhook) => webhook,
+            Err(e) => {
+                tracing::warn!("Skipping invalid Webhook {}: {}", subject, e);
+                continue;
+            }
+        };
+        match webhook.deliver(store) {
+            Ok(()) => {
+                retries.remove(&subject);
+            }
+            Err(e) => {
+                let failures = retries.get(&subject).map(|r| r.failures).unwrap_or(0) + 1;
+                let delay = FIRST_RETRY
+                    .saturating_mul(2u32.saturating_pow(failures - 1))
+                    .min(MAX_RETRY);
+                tracing::warn!(
+                    "Delivery to Webhook {} failed, retrying in {:?}: {}",
+                    subject,
+                    delay,
+                    e
+                );
+                retries.insert(
+                    subject,
+                    Retry {
+                        failures,
+                        at: Instant::now() + delay,
+                    },
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+struct Webhook {
+    subject: String,
+    target: String,
+    url: String,
+    include_children: bool,
+    /// The sequence number of the last delivered Commit
+    cursor: u64,
+    /// Whether the cursor was stored in the Webhook
+    stored_cursor: bool,
+    /// The signer of the last Commit of the Webhook. Only Commits it can read are delivered.
+    author: ForAgent,
+}
+
+impl Webhook {
+    fn from_resource(store: &Db, resource: &Resource) -> AtomicServerResult<Webhook> {
+        let last_commit = resource
+            .get(urls::LAST_COMMIT)
+            .map_err(|_| "it has no Commits, so its author is unknown")?
+            .to_string();
+        let author = store
+            .get_resource(&last_commit)?
+            .get(urls::SIGNER)?
+            .to_string();
+        let stored_cursor = resource.get(urls::DELIVERY_CURSOR).ok();
+        let cursor = match stored_cursor {
+            Some(cursor) => cursor.to_int()? as u64,
+            // A new Webhook receives the Commits that come after its own
+            None => store
+                .commit_sequence_of(&last_commit)?
+                .unwrap_or_else(|| store.last_commit_sequence()),
+        };
+        Ok(Webhook {
+            subject: resource.get_subject().clone(),
+            target: resource.get(urls::SUBSCRIPTION_TARGET)?.to_string(),
+            url: resource.get(urls::WEBHOOK_URL)?.to_string(),
+            include_children: resource
+                .get(urls::INCLUDE_CHILDREN)
+                .and_then(|v| v.to_bool())
+                .unwrap_or(false),
+            cursor,
+            stored_cursor: stored_cursor.is_some(),
+            author: ForAgent::AgentSubject(author),
+        })
+    }
+
+    /// Whether the Commit is sent to this Webhook.
+    /// Destroyed descendants are no longer part of the tree, so only the Commits of the target itself are sent after it is destroyed.
+    fn wants(&self, store: &Db, commit: &Resource) -> bool {
+        let Ok(target) = commit.get(urls::SUBJECT).map(|v| v.to_string()) else {
+            return false;
+        };
+        match store.get_resource(&target) {
+            Ok(resource) => {
+                (target == self.target
+                    || (self.include_children
+                        && ancestors(store, &resource).contains(&self.target)))
+                    && atomic_lib::hierarchy::check_read(store, &resource, &self.author).is_ok()
+            }
+            Err(_) => target == self.target,
+        }
+    }
+
+    /// Sends the Commits after the cursor in order, and stores the cursor after every batch.
+    /// Stops at the first failure, so the failed Commit is sent again first.
+    fn deliver(mut self, store: &Db) -> AtomicServerResult<()> {
+        if !self.stored_cursor {
+            // Later edits of the Webhook change its last Commit, so its first cursor is kept
+            self.save(store, self.cursor, None)?;
+            self.stored_cursor = true;
+        }
+        loop {
+            let batch = store.commits_after(self.cursor, BATCH)?;
+            if batch.is_empty() {
+                return Ok(());
+            }
+            let start = self.cursor;
+            for (sequence, subject) in batch {
+                if let Ok(commit) = store.get_resource(&subject) {
+                    if self.wants(store, &commit) {
+                        if let Err(e) = self.send(store, sequence, &commit) {
+                            self.save(store, start, Some(e.to_string()))?;
+                            return Err(e);
+                        }
+                    }
+                }
+                self.cursor = sequence;
+            }
+            self.save(store, start, None)?;
+        }
+    }
+
+    fn send(&self, store: &Db, sequence: u64, commit: &Resource) -> AtomicServerResult<()> {
+        let commit_json: serde_json::Value =
+            serde_json::from_str(&commit.to_json_ad()?).map_err(|e| e.to_string())?;
+        let body = serde_json::json!({
+            "sequence": sequence,
+            "webhook": self.subject,
+            "commit": commit_json,
+        })
+        .to_string();
+        let headers = vec![("Content-Type".to_string(), "application/json".to_string())];
+        let response = store.get_outbound().unwrap_or_default().request(
+            OutboundFeature::Webhook,
+            "POST",
+            &self.url,
+            &headers,
+            Some(body.as_bytes()),
+        )?;
+        if !(200..300).contains(&response.status()) {
+            return Err(format!("{} responded with status {}", self.url, response.status()).into());
+        }
+        Ok(())
+    }
+
+    /// Stores the cursor and the last error without a Commit, since every delivery would add one.
+    /// Reads the Webhook again, so changes that were made during the delivery are kept.
+    fn save(&self, store: &Db, previous: u64, error: Option<String>) -> AtomicServerResult<()> {
+        let mut resource = store.get_resource(&self.subject)?;
+        if self.cursor != previous || !self.stored_cursor {
+            resource.set_propval_unsafe(
+                urls::DELIVERY_CURSOR.into(),
+                Value::Integer(self.cursor as i64),
+            );
+        }
+        match error {
+            Some(error) => {
+                resource.set_propval_unsafe(urls::WEBHOOK_LAST_ERROR.into(), Value::String(error))
+            }
+            None => {
+                resource.remove_propval(urls::WEBHOOK_LAST_ERROR);
+            }
+        }
+        store.add_resource_opts(&resource, false, true, true)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use atomic_lib::outbound::{OutboundConfig, OutboundHttp};
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+    };
+
+    fn allow_loopback(store: &Db) {
+        store.set_outbound(OutboundHttp::new(OutboundConfig {
+            allow: vec!["127.0.0.1".parse().unwrap()],
+            ..Default::default()
+        }));
+    }
+
+    /// Answers one request with 200, and returns its body.
+    fn receive(listener: &TcpListener) -> serde_json::Value {
+        let (stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        (&stream)
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[test]
+    fn commits_from_before_a_restart_are_delivered() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let hook_url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let path = std::path::PathBuf::from(".temp/db/webhooks_restart");
+        let (webhook, commit) = {
+            let store = Db::init_temp("webhooks_restart").unwrap();
+            let drive = store.get_server_url().to_string();
+            let mut webhook = Resource::new_instance(urls::WEBHOOK, &store).unwrap();
+            for (prop, value) in [
+                (urls::PARENT, Value::AtomicUrl(drive.clone())),
+                (urls::SUBSCRIPTION_TARGET, Value::AtomicUrl(drive.clone())),
+                (urls::INCLUDE_CHILDREN, Value::Boolean(true)),
+                (urls::WEBHOOK_URL, Value::String(hook_url)),
+            ] {
+                webhook.set_propval_unsafe(prop.into(), value);
+            }
+            webhook.save_locally(&store).unwrap();
+
+            // Applied while no Webhooks are delivered, e.g. right before the server stops
+            let mut document = Resource::new(format!("{}/document", drive));
+            document.set_propval_unsafe(urls::PARENT.into(), Value::AtomicUrl(drive));
+            document.set_propval_unsafe(urls::NAME.into(), Value::String("Delivered".into()));
+            let commit = document.save_locally(&store).unwrap();
+            (
+                webhook.get_subject().clone(),
+                commit.commit_resource.get_subject().clone(),
+            )
+        };
+
+        let store = Db::init(&path, "https://localhost".into()).unwrap();
+        allow_loopback(&store);
+        let sequence = store.commit_sequence_of(&commit).unwrap().unwrap();
+        let webhooks = Webhooks::default();
+        webhooks.start(store.clone());
+        let delivery = receive(&listener);
+        assert_eq!(delivery["sequence"], sequence);
+        assert_eq!(delivery["webhook"], webhook.as_str());
+        assert_eq!(delivery["commit"]["@id"], commit.as_str());
+        assert!(webhooks.shutdown(Duration::from_secs(10)));
+
+        let webhook = store.get_resource(&webhook).unwrap();
+        assert_eq!(
+            webhook
+                .get(urls::DELIVERY_CURSOR)
+                .unwrap()
+                .to_int()
+                .unwrap(),
+            sequence as i64
+        );
+        assert!(webhook.get(urls::WEBHOOK_LAST_ERROR).is_err());
+    }
+}
@@ -19,3 +19,118 @@ fn import_file() {
         .assert()
         .success();
 }
+
+#[test]
+fn store_commands_report_json() {
+    let dir = std::env::temp_dir().join(format!("atomic-server-cli-{}", std::process::id()));
+    let data_dir = dir.join("data");
+    let config_dir = dir.join("config");
+    let run = |args: &[&str]| {
+        let mut cmd = assert_cmd::Command::cargo_bin("atomic-server").unwrap();
+        cmd.args(["--data-dir", data_dir.to_str().unwrap()])
+            .args(["--config-dir", config_dir.to_str().unwrap()])
+            .args(["--output", "json"])
+            .args(args);
+        cmd
+    };
+    let output = |args: &[&str]| -> (Option<i32>, serde_json::Value) {
+        let out = run(args).output().unwrap();
+        (
+            out.status.code(),
+            serde_json::from_slice(&out.stdout).unwrap(),
+        )
+    };
+
+    // Without a store, there is nothing to show
+    let (code, result) = output(&["show", "http://localhost:9883"]);
+    assert_eq!(code, Some(1));
+    assert_eq!(result["ok"], false);
+
+    let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    d.push("../lib/test_files/local_id.json");
+    run(&["import", "--path", d.to_str().unwrap()])
+        .assert()
+        .success();
+
+    let (code, result) = output(&["show", "http://localhost:9883", "--format", "turtle"]);
+    assert_eq!(code, Some(0));
+    assert_eq!(result["contentType"], "text/turtle");
+
+    let export = dir.join("export.json");
+    run(&["export", "--path", export.to_str().unwrap()])
+        .assert()
+        .success();
+    assert!(std::fs::read_to_string(&export)
+        .unwrap()
+        .contains("localhost:9883"));
+
+    run(&["rebuild-index"]).assert().success();
+    let (_code, result) = output(&["check"]);
+    assert_eq!(result["command"], "check");
+    assert!(result["validation"]["resources"].as_u64().unwrap() > 0);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn serve_file() {
+    use std::io::BufRead;
+
+    let mut d = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    d.push("../lib/test_files/served_export.json");
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("atomic-server"))
+        .args(["--serve-file", d.to_str().unwrap()])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Keeps reading the output, so the server never blocks on a full pipe
+    let stdout = std::io::BufReader::new(child.stdout.take().unwrap());
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        for line in stdout.lines().map_while(Result::ok) {
+            if let Some((_, url)) = line.split_once(" read-only at ") {
+                let _ = sender.send(url.to_string());
+            }
+        }
+    });
+    let url = receiver
+        .recv_timeout(std::time::Duration::from_secs(60))
+        .expect("The server did not print its URL");
+    assert!(!url.contains("example.com"));
+
+    let agent = ureq::AgentBuilder::new().max_idle_connections(0).build();
+    let subject = format!("{}/hello", url);
+    let get = |accept: &str| {
+        agent
+            .get(&subject)
+            .set("Accept", accept)
+            .call()
+            .unwrap()
+            .into_string()
+            .unwrap()
+    };
+    let json_ad = get("application/ad+json");
+    assert!(json_ad.contains(&subject), "{}", json_ad);
+    // Links in values are moved to the local server, too
+    assert!(json_ad.contains(&format!("]({})", url)), "{}", json_ad);
+    assert!(!json_ad.contains("example.com"), "{}", json_ad);
+    let turtle = get("text/turtle");
+    assert!(turtle.contains("Hello from an export"), "{}", turtle);
+    let html = get("text/html");
+    assert!(html.contains("Hello from an export"), "{}", html);
+
+    let commit = agent.post(&format!("{}/commit", url)).send_string("{}");
+    assert!(matches!(commit, Err(ureq::Error::Status(405, _))));
+
+    // Stops the server like Ctrl+C does, so it removes its temporary directory
+    #[cfg(unix)]
+    {
+        std::process::Command::new("kill")
+            .args(["-INT", &child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(child.wait().unwrap().success());
+    }
+    #[cfg(not(unix))]
+    child.kill().unwrap();
+}